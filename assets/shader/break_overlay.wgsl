@@ -0,0 +1,83 @@
+struct GlobalUniforms {
+    camera_view_matrix: mat4x4f,
+    camera_projection_matrix: mat4x4f,
+    camera_origin: vec3i,
+    ao_strength: f32,
+    ao_curve: f32,
+}
+
+struct Instance {
+    @location(0) block_pos: vec3i,
+    @location(1) stage: u32,
+    @location(2) opacity: f32,
+}
+
+struct Interpolated {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) stage: u32,
+    @location(2) opacity: f32,
+}
+
+// the overlay cube is slightly larger than the block so that it passes the depth test against
+// the block's own faces
+const INFLATE: f32 = 1.002;
+
+@group(0) @binding(0)
+var stage_textures: texture_2d_array<f32>;
+
+@group(0) @binding(1)
+var stage_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> global: GlobalUniforms;
+
+// meant to be drawn with 36 vertices per instance: two triangles for each face of the cube, in
+// the order +x, +y, +z, -x, -y, -z
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, instance: Instance) -> Interpolated {
+    let face = vertex_index / 6u;
+    let axis = face % 3u;
+    let side = select(0.0, 1.0, face < 3u);
+
+    // corners of the two triangles making up the face
+    var corners = array(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 1.0),
+    );
+    let uv = corners[vertex_index % 6u];
+
+    var local: vec3f;
+    switch axis {
+        case 0u: {
+            local = vec3(side, uv.y, uv.x);
+        }
+        case 1u: {
+            local = vec3(uv.x, side, uv.y);
+        }
+        default: {
+            local = vec3(uv.x, uv.y, side);
+        }
+    }
+    local = (local - 0.5) * INFLATE + 0.5;
+
+    // integer subtraction is exact, so the camera-relative position is small and precise
+    let position = vec3f(instance.block_pos - global.camera_origin) + local;
+
+    var out: Interpolated;
+    out.clip_position = global.camera_projection_matrix * global.camera_view_matrix * vec4f(position, 1.0);
+    out.uv = vec2(uv.x, 1.0 - uv.y);
+    out.stage = instance.stage;
+    out.opacity = instance.opacity;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Interpolated) -> @location(0) vec4f {
+    let color = textureSample(stage_textures, stage_sampler, in.uv, in.stage);
+    return vec4(color.rgb, color.a * in.opacity);
+}
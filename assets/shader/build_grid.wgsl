@@ -0,0 +1,72 @@
+struct GlobalUniforms {
+    camera_view_matrix: mat4x4f,
+    camera_projection_matrix: mat4x4f,
+    camera_origin: vec3i,
+    ao_strength: f32,
+    ao_curve: f32,
+}
+
+struct BuildGridUniforms {
+    // premultiplied by alpha
+    color: vec4f,
+    // block whose face the grid is centred on
+    block_pos: vec3i,
+    // in blocks; the grid fades out towards this distance from the centre
+    radius: f32,
+    normal: vec3i,
+    // in physical pixels
+    line_width: f32,
+}
+
+struct Interpolated {
+    @builtin(position) clip_position: vec4f,
+    // position on the plane in blocks, relative to the corner of the block; grid lines are at
+    // integer coordinates
+    @location(0) grid_pos: vec2f,
+}
+
+// lifts the grid off the surface so that it doesn't z-fight with the face it lies on
+const SURFACE_OFFSET: f32 = 0.002;
+
+@group(0) @binding(0)
+var<uniform> grid: BuildGridUniforms;
+
+@group(1) @binding(0)
+var<uniform> global: GlobalUniforms;
+
+// meant to be drawn as a triangle strip with 4 vertices
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> Interpolated {
+    let corner = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+
+    // axes spanning the plane
+    let normal = vec3f(grid.normal);
+    let tangent = abs(normal).yzx;
+    let bitangent = abs(normal).zxy;
+
+    let grid_pos = 0.5 + corner * grid.radius;
+
+    // integer subtraction is exact, so the camera-relative position is small and precise
+    let block_pos = vec3f(grid.block_pos - global.camera_origin);
+    let face_center = block_pos + 0.5 + normal * (0.5 + SURFACE_OFFSET);
+    let position = face_center + (tangent * (grid_pos.x - 0.5) + bitangent * (grid_pos.y - 0.5));
+
+    var out: Interpolated;
+    out.clip_position = global.camera_projection_matrix * global.camera_view_matrix * vec4f(position, 1.0);
+    out.grid_pos = grid_pos;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Interpolated) -> @location(0) vec4f {
+    // distance to the nearest grid line in pixels
+    let pixel_size = fwidth(in.grid_pos);
+    let line_distance = abs(fract(in.grid_pos + 0.5) - 0.5) / pixel_size;
+
+    // anti-aliasing: fraction of the pixel covered by a line
+    let coverage = clamp(0.5 * grid.line_width + 0.5 - min(line_distance.x, line_distance.y), 0.0, 1.0);
+
+    let fade = 1.0 - smoothstep(0.5 * grid.radius, grid.radius, length(in.grid_pos - 0.5));
+
+    return grid.color * (coverage * fade);
+}
@@ -0,0 +1,109 @@
+struct GlobalUniforms {
+    camera_view_matrix: mat4x4f,
+    camera_projection_matrix: mat4x4f,
+    camera_origin: vec3i,
+    ao_strength: f32,
+    ao_curve: f32,
+    debug_chunk_tint: u32,
+    // seconds since startup
+    time: f32,
+    chunk_fade_duration: f32,
+}
+
+struct Instance {
+    @location(0) block_pos: vec3i,
+    @location(1) texture_index: u32,
+    @location(2) offset: vec3f,
+    @location(3) spawn_time: f32,
+    @location(4) velocity: vec3f,
+    @location(5) tint_index: u32,
+    @location(6) uv_offset: vec2f,
+}
+
+struct Interpolated {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) @interpolate(flat) texture_index: u32,
+    @location(2) @interpolate(flat) tint_index: u32,
+    @location(3) opacity: f32,
+}
+
+// must match PARTICLE_LIFETIME in particles.rs
+const LIFETIME: f32 = 1.0;
+
+// must match TINT_COUNT in block.rs
+const TINT_COUNT: u32 = 2u;
+
+// acceleration due to gravity, in blocks per second squared
+const GRAVITY: vec3f = vec3(0.0, -16.0, 0.0);
+
+// width of a particle in blocks
+const SIZE: f32 = 0.15;
+
+// fraction of the texture shown on each particle
+const UV_SIZE: f32 = 0.25;
+
+@group(0) @binding(0)
+var texture_array: texture_2d_array<f32>;
+
+@group(0) @binding(1)
+var texture_array_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> tint_palette: array<vec4f, TINT_COUNT>;
+
+@group(1) @binding(0)
+var<uniform> global: GlobalUniforms;
+
+// meant to be drawn with 6 vertices per instance: two triangles making up a quad facing the camera
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, instance: Instance) -> Interpolated {
+    var corners = array(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 1.0),
+    );
+    let corner = corners[vertex_index % 6u];
+
+    var out: Interpolated;
+
+    let age = global.time - instance.spawn_time;
+    if age < 0.0 || age > LIFETIME {
+        // collapse expired particles to a point so that nothing is drawn
+        out.clip_position = vec4(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+
+    // integer subtraction is exact, so the camera-relative position is small and precise
+    let position = vec3f(instance.block_pos - global.camera_origin)
+        + instance.offset
+        + instance.velocity * age
+        + 0.5 * GRAVITY * age * age;
+
+    // expand the quad in view space so that it faces the camera
+    var view_position = global.camera_view_matrix * vec4f(position, 1.0);
+    view_position += vec4(SIZE * (corner - 0.5), 0.0, 0.0);
+
+    out.clip_position = global.camera_projection_matrix * view_position;
+    out.uv = instance.uv_offset + UV_SIZE * vec2(corner.x, 1.0 - corner.y);
+    out.texture_index = instance.texture_index;
+    out.tint_index = instance.tint_index;
+    out.opacity = 1.0 - age / LIFETIME;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Interpolated) -> @location(0) vec4f {
+    let texture_color = textureSample(texture_array, texture_array_sampler, in.uv, in.texture_index);
+
+    // fragments of cutout blocks keep their holes
+    if texture_color.a < 0.5 {
+        discard;
+    }
+
+    let tint = tint_palette[min(in.tint_index, TINT_COUNT - 1u)].rgb;
+    return vec4(texture_color.rgb * tint, in.opacity);
+}
@@ -0,0 +1,61 @@
+struct ReticleUniforms {
+    // premultiplied by alpha
+    color: vec4<f32>,
+    screen_size: vec2<f32>,
+    // in physical pixels
+    size: f32,
+    thickness: f32,
+    shape: u32,
+}
+
+const SHAPE_CROSS: u32 = 0u;
+const SHAPE_DOT: u32 = 1u;
+const SHAPE_CIRCLE: u32 = 2u;
+
+@group(0) @binding(0)
+var<uniform> reticle: ReticleUniforms;
+
+// meant to be drawn as a triangle strip with 4 vertices.
+// draws a quad around the centre of the screen, large enough for the reticle plus one pixel for
+// anti-aliasing
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+    let half_extent = (0.5 * reticle.size + 1.0) / (0.5 * reticle.screen_size);
+
+    return vec4(corner * half_extent, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    let pos = frag_coord.xy - 0.5 * reticle.screen_size;
+    let half_size = 0.5 * reticle.size;
+    let half_thickness = 0.5 * reticle.thickness;
+
+    // signed distance to the edge of the reticle in pixels
+    var distance: f32;
+    switch reticle.shape {
+        case SHAPE_DOT: {
+            distance = length(pos) - half_size;
+        }
+        case SHAPE_CIRCLE: {
+            distance = abs(length(pos) - half_size + half_thickness) - half_thickness;
+        }
+        default: {
+            distance = min(
+                box_distance(pos, vec2(half_size, half_thickness)),
+                box_distance(pos, vec2(half_thickness, half_size))
+            );
+        }
+    }
+
+    // anti-aliasing: fraction of the pixel covered by the reticle
+    let coverage = clamp(0.5 - distance, 0.0, 1.0);
+
+    return reticle.color * coverage;
+}
+
+fn box_distance(pos: vec2<f32>, half_extent: vec2<f32>) -> f32 {
+    let d = abs(pos) - half_extent;
+    return length(max(d, vec2(0.0))) + min(max(d.x, d.y), 0.0);
+}
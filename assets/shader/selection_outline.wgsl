@@ -0,0 +1,32 @@
+struct GlobalUniforms {
+    camera_view_matrix: mat4x4f,
+    camera_projection_matrix: mat4x4f,
+    camera_origin: vec3i,
+    ao_strength: f32,
+    ao_curve: f32,
+}
+
+struct Vertex {
+    @location(0) block_pos: vec3i,
+    // relative to the minimum corner of the block
+    @location(1) offset: vec3f,
+}
+
+const OUTLINE_COLOR: vec4f = vec4f(0.0, 0.0, 0.0, 0.6);
+
+@group(0) @binding(0)
+var<uniform> global: GlobalUniforms;
+
+// meant to be drawn as a line list, with one pair of vertices for each edge of the cube
+@vertex
+fn vs_main(vertex: Vertex) -> @builtin(position) vec4f {
+    // integer subtraction is exact, so the camera-relative position is small and precise
+    let position = vec3f(vertex.block_pos - global.camera_origin) + vertex.offset;
+
+    return global.camera_projection_matrix * global.camera_view_matrix * vec4f(position, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return OUTLINE_COLOR;
+}
@@ -0,0 +1,93 @@
+// draws the opaque and two-sided layers of the terrain from the sun into the shadow map of one
+// cascade. Only depth is written, but cutout blocks are still alpha tested so that leaves and
+// plants cast shadows of their shape
+
+struct Attributes {
+    @location(0) position: vec3f,
+    @location(1) uv: vec2f,
+    // texture array layer and tint index, packed by pack_texture in vertex.rs
+    @location(2) texture: u32,
+};
+
+struct Interpolated {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) @interpolate(flat) texture_index: u32,
+}
+
+// must match ShadowUniforms in shadow.rs
+struct ShadowUniforms {
+    view_proj_matrices: array<mat4x4f, MAX_CASCADE_COUNT>,
+    cascade_splits: vec4f,
+    texel_sizes: vec4f,
+    cascade_count: u32,
+    cascade_blend: f32,
+}
+
+struct GlobalUniforms {
+    camera_view_matrix: mat4x4f,
+    camera_projection_matrix: mat4x4f,
+    camera_origin: vec3i,
+    ao_strength: f32,
+    ao_curve: f32,
+    debug_chunk_tint: u32,
+    time: f32,
+    chunk_fade_duration: f32,
+    sun_direction: vec3f,
+    sun_ambient: f32,
+    day_fraction: f32,
+    shadows: ShadowUniforms,
+}
+
+struct RenderGroupUniforms {
+    offset: vec3i,
+}
+
+struct CascadeUniforms {
+    // index of the cascade being drawn
+    index: u32,
+}
+
+// fragments with a lower alpha than this are discarded, as in terrain.wgsl
+const ALPHA_CUTOFF: f32 = 0.5;
+
+// must match MAX_CASCADE_COUNT in shadow.rs
+const MAX_CASCADE_COUNT: u32 = 4u;
+
+// must match TEXTURE_INDEX_BITS in vertex.rs
+const TEXTURE_INDEX_BITS: u32 = 24u;
+const TEXTURE_INDEX_MASK: u32 = (1u << TEXTURE_INDEX_BITS) - 1u;
+
+@group(0) @binding(0)
+var texture_array: texture_2d_array<f32>;
+
+@group(0) @binding(1)
+var texture_array_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> global: GlobalUniforms;
+
+@group(2) @binding(0)
+var<uniform> render_group: RenderGroupUniforms;
+
+@group(3) @binding(0)
+var<uniform> cascade: CascadeUniforms;
+
+@vertex
+fn vs_main(in: Attributes) -> Interpolated {
+    var out: Interpolated;
+    let offset = vec3f(render_group.offset - global.camera_origin);
+    let view_proj_matrix = global.shadows.view_proj_matrices[min(cascade.index, MAX_CASCADE_COUNT - 1u)];
+    out.clip_position = view_proj_matrix * vec4f(in.position + offset, 1.0);
+    out.uv = in.uv;
+    out.texture_index = in.texture & TEXTURE_INDEX_MASK;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Interpolated) {
+    let alpha = textureSample(texture_array, texture_array_sampler, in.uv, in.texture_index).a;
+    if alpha < ALPHA_CUTOFF {
+        discard;
+    }
+}
@@ -5,51 +5,342 @@ struct ColorTargets {
 struct Attributes {
     @location(0) position: vec3f,
     @location(1) uv: vec2f,
-    @location(2) texture_index: u32,
-    @location(3) shading: f32,
+    // texture array layer and tint index, packed by pack_texture in vertex.rs
+    @location(2) texture: u32,
+    @location(3) ao: f32,
+    @location(4) normal: vec4f,
+    // light from emitting blocks and skylight, packed by pack_light in vertex.rs
+    @location(5) light: u32,
 };
 
 struct Interpolated {
     @builtin(position) clip_position: vec4f,
     @location(0) uv: vec2f,
     @location(1) texture_index: u32,
+    // sunlight reaching the face, dimmed by the skylight level and the time of day
     @location(2) shading: f32,
+    @location(3) normal: vec3f,
+    @location(4) ao: f32,
+    // position relative to the chunk batch, used for the chunk tint
+    @location(5) batch_position: vec3f,
+    @location(6) @interpolate(flat) batch_offset: vec3i,
+    @location(7) @interpolate(flat) tint_index: u32,
+    // how far the chunk has faded in, from 0 to 1
+    @location(8) fade: f32,
+    // light from emitting blocks, added to the sunlight
+    @location(9) block_light: vec3f,
+    // position relative to the camera origin, used to look up the shadow maps
+    @location(10) relative_position: vec3f,
+}
+
+// must match ShadowUniforms in shadow.rs
+struct ShadowUniforms {
+    // view-projection matrix of the sun for each cascade, relative to the camera origin
+    view_proj_matrices: array<mat4x4f, MAX_CASCADE_COUNT>,
+    // view depth at which each cascade ends
+    cascade_splits: vec4f,
+    // size of a shadow map texel in blocks in each cascade
+    texel_sizes: vec4f,
+    // number of cascades in use, zero if shadows are disabled
+    cascade_count: u32,
+    // fraction of each cascade over which it fades into the next
+    cascade_blend: f32,
 }
 
 struct GlobalUniforms {
     camera_view_matrix: mat4x4f,
     camera_projection_matrix: mat4x4f,
+    // the view matrix is relative to this point, so that positions stay small and precise far
+    // from the world origin
+    camera_origin: vec3i,
+    ao_strength: f32,
+    ao_curve: f32,
+    // nonzero to tint the terrain by chunk
+    debug_chunk_tint: u32,
+    // seconds since startup
+    time: f32,
+    // seconds taken for new chunks to fade in, or zero to disable the fade
+    chunk_fade_duration: f32,
+    // unit vector pointing towards the sun
+    sun_direction: vec3f,
+    // brightness of faces pointing directly away from the sun
+    sun_ambient: f32,
+    // brightness of the skylight, from 0 (night) to 1 (full daylight)
+    day_fraction: f32,
+    shadows: ShadowUniforms,
 }
 
 struct RenderGroupUniforms {
-    offset: vec3f,
+    offset: vec3i,
+    // time at which each chunk in the batch first appeared, indexed by x + 2y + 4z
+    chunk_spawn_times: array<vec4f, 2>,
 }
 
+// fragments with a lower alpha than this are discarded
+const ALPHA_CUTOFF: f32 = 0.5;
+
+// brightness of the skylight at night, relative to full daylight
+const NIGHT_SKY_BRIGHTNESS: f32 = 0.1;
+
+// must match TEXTURE_INDEX_BITS in vertex.rs
+const TEXTURE_INDEX_BITS: u32 = 24u;
+const TEXTURE_INDEX_MASK: u32 = (1u << TEXTURE_INDEX_BITS) - 1u;
+
+// must match MAX_CASCADE_COUNT in shadow.rs
+const MAX_CASCADE_COUNT: u32 = 4u;
+
+// distance that positions are moved along the normal before looking up the shadow maps, in
+// shadow map texels, so that faces don't shadow themselves
+const SHADOW_NORMAL_OFFSET: f32 = 1.5;
+
+// must match TINT_COUNT in block.rs
+const TINT_COUNT: u32 = 2u;
+
+// must match CHUNK_BATCH_SIZE in chunk_batching.rs
+const CHUNK_BATCH_SIZE: u32 = 2u;
+
+// must match CHUNK_SIZE_LOG2 in chunk.rs
+const CHUNK_SIZE_LOG2: u32 = 5u;
+const CHUNK_SIZE: f32 = 32.0;
+// width of the chunk boundary lines in pixels
+const CHUNK_BOUNDARY_WIDTH: f32 = 1.5;
+
 @group(0) @binding(0)
 var texture_array: texture_2d_array<f32>;
 
 @group(0) @binding(1)
 var texture_array_sampler: sampler;
 
+// linear colour of each tint, multiplied into the texture colour
+@group(0) @binding(2)
+var<uniform> tint_palette: array<vec4f, TINT_COUNT>;
+
 @group(1) @binding(0)
 var<uniform> global: GlobalUniforms;
 
 @group(2) @binding(0)
 var<uniform> render_group: RenderGroupUniforms;
 
+// one layer per shadow cascade
+@group(3) @binding(0)
+var shadow_maps: texture_depth_2d_array;
+
+@group(3) @binding(1)
+var shadow_sampler: sampler_comparison;
+
 @vertex
 fn vs_main(in: Attributes) -> Interpolated {
     var out: Interpolated;
-    out.clip_position = global.camera_projection_matrix * global.camera_view_matrix * vec4f(in.position + render_group.offset, 1.0);
+    // integer subtraction is exact, so the camera-relative offset is small and precise
+    let offset = vec3f(render_group.offset - global.camera_origin);
+    out.relative_position = in.position + offset;
+    out.clip_position = global.camera_projection_matrix * global.camera_view_matrix * vec4f(out.relative_position, 1.0);
     out.uv = in.uv;
-    out.texture_index = in.texture_index;
-    out.shading = in.shading;
+    out.texture_index = in.texture & TEXTURE_INDEX_MASK;
+    out.tint_index = in.texture >> TEXTURE_INDEX_BITS;
+    out.shading = sun_shading(in.normal.xyz) * skylight_brightness(in.light);
+    out.normal = in.normal.xyz;
+    out.ao = in.ao;
+    out.batch_position = in.position;
+    out.batch_offset = render_group.offset;
+    out.fade = chunk_fade(in);
+    out.block_light = block_light_color(in.light);
     return out;
 }
 
 @fragment
 fn fs_main(in: Interpolated) -> ColorTargets {
     var out: ColorTargets;
-    out.color = textureSample(texture_array, texture_array_sampler, in.uv, in.texture_index) * in.shading;
+
+    // the terrain is opaque, so chunks fade in by discarding a growing fraction of pixels
+    if in.fade < 1.0 && dither_threshold(in.clip_position.xy) >= in.fade {
+        discard;
+    }
+
+    let texture_color = textureSample(texture_array, texture_array_sampler, in.uv, in.texture_index);
+
+    // alpha test for cutout blocks
+    if texture_color.a < ALPHA_CUTOFF {
+        discard;
+    }
+
+    let tint = tint_palette[min(in.tint_index, TINT_COUNT - 1u)].rgb;
+    let albedo = vec4(texture_color.rgb * tint, texture_color.a);
+
+    let light = in.shading * shadow_factor(in) + in.block_light;
+    out.color = vec4(albedo.rgb * light * ao_factor(in.ao), albedo.a);
+
+    if global.debug_chunk_tint != 0u {
+        out.color = vec4(out.color.rgb * chunk_tint(in), out.color.a);
+    }
+
     return out;
 }
+
+// translucent blocks are blended over what is behind them, so they keep the alpha of their textures
+@fragment
+fn fs_translucent(in: Interpolated) -> ColorTargets {
+    var out: ColorTargets;
+
+    let texture_color = textureSample(texture_array, texture_array_sampler, in.uv, in.texture_index);
+    let tint = tint_palette[min(in.tint_index, TINT_COUNT - 1u)].rgb;
+
+    let light = in.shading * shadow_factor(in) + in.block_light;
+    var color = texture_color.rgb * tint * light * ao_factor(in.ao);
+
+    if global.debug_chunk_tint != 0u {
+        color *= chunk_tint(in);
+    }
+
+    // rather than dithering, new chunks fade in by becoming more opaque
+    out.color = vec4(color, texture_color.a * in.fade);
+
+    return out;
+}
+
+// brightness of a face lit by the sun. The lambert term is wrapped around the back of the block
+// so that faces pointing away from the sun still differ by direction, keeping cube edges visible
+fn sun_shading(normal: vec3f) -> f32 {
+    let wrapped_lambert = 0.5 + 0.5 * dot(normal, global.sun_direction);
+    return mix(global.sun_ambient, 1.0, wrapped_lambert);
+}
+
+// multiplier for the sunlight reaching a face, which takes it down to the brightness of faces
+// pointing away from the sun where the face is in shadow
+fn shadow_factor(in: Interpolated) -> f32 {
+    let normal = normalize(in.normal);
+    let visibility = sun_visibility(in.relative_position, normal);
+    return mix(global.sun_ambient / max(sun_shading(normal), 1e-4), 1.0, visibility);
+}
+
+// fraction of the sunlight reaching a position relative to the camera origin, from 0 in shadow to
+// 1 fully lit, looked up in the cascade covering its view depth. Over the last part of each
+// cascade it fades into the next one, hiding the seam where the shadow resolution changes, and
+// the last cascade fades out to fully lit
+fn sun_visibility(position: vec3f, normal: vec3f) -> f32 {
+    let cascade_count = min(global.shadows.cascade_count, MAX_CASCADE_COUNT);
+    let depth = -(global.camera_view_matrix * vec4(position, 1.0)).z;
+
+    var cascade = 0u;
+    while cascade < cascade_count && depth > global.shadows.cascade_splits[cascade] {
+        cascade += 1u;
+    }
+    if cascade == cascade_count {
+        return 1.0;
+    }
+
+    var cascade_start = 0.0;
+    if cascade > 0u {
+        cascade_start = global.shadows.cascade_splits[cascade - 1u];
+    }
+    let cascade_end = global.shadows.cascade_splits[cascade];
+    let blend_start = mix(cascade_end, cascade_start, global.shadows.cascade_blend);
+
+    let visibility = sample_shadow_cascade(cascade, position, normal);
+    if depth <= blend_start {
+        return visibility;
+    }
+
+    var next_visibility = 1.0;
+    if cascade + 1u < cascade_count {
+        next_visibility = sample_shadow_cascade(cascade + 1u, position, normal);
+    }
+    return mix(visibility, next_visibility, saturate((depth - blend_start) / (cascade_end - blend_start)));
+}
+
+// fraction of the sunlight reaching a position according to the shadow map of one cascade,
+// filtered over the nearest 2x2 texels. Positions outside the shadow map are lit
+fn sample_shadow_cascade(cascade: u32, position: vec3f, normal: vec3f) -> f32 {
+    let offset = normal * global.shadows.texel_sizes[cascade] * SHADOW_NORMAL_OFFSET;
+    let clip = global.shadows.view_proj_matrices[cascade] * vec4(position + offset, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2(0.5 + 0.5 * ndc.x, 0.5 - 0.5 * ndc.y);
+
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    return textureSampleCompareLevel(shadow_maps, shadow_sampler, uv, cascade, ndc.z);
+}
+
+// colour of the light from emitting blocks, unpacked from 4 bits per channel. Light levels drop
+// by one per block, so they are squared to make the falloff look less linear
+fn block_light_color(packed: u32) -> vec3f {
+    let levels = vec3f(vec3(packed, packed >> 4u, packed >> 8u) & vec3(15u)) / 15.0;
+    return levels * levels;
+}
+
+// brightness of the skylight, unpacked from the 4 bits above the light from emitting blocks and
+// squared like it, at the current time of day
+fn skylight_brightness(packed: u32) -> f32 {
+    let level = f32((packed >> 12u) & 15u) / 15.0;
+    return level * level * mix(NIGHT_SKY_BRIGHTNESS, 1.0, clamp(global.day_fraction, 0.0, 1.0));
+}
+
+// map the interpolated ambient occlusion to a brightness multiplier, according to the strength
+// and curve set in the global uniforms
+fn ao_factor(ao: f32) -> f32 {
+    let strength = clamp(global.ao_strength, 0.0, 1.0);
+    return mix(1.0, pow(ao, global.ao_curve), strength);
+}
+
+// how far the chunk containing the vertex has faded in since it first appeared, from 0 to 1
+fn chunk_fade(in: Attributes) -> f32 {
+    if global.chunk_fade_duration <= 0.0 {
+        return 1.0;
+    }
+
+    // step half a block back from the face, as in chunk_tint, to find the chunk of its block
+    let inside = in.position - 0.5 * in.normal.xyz;
+    let chunk_in_batch = clamp(
+        vec3u(max(floor(inside / CHUNK_SIZE), vec3(0.0))),
+        vec3(0u),
+        vec3(CHUNK_BATCH_SIZE - 1u),
+    );
+    let index = chunk_in_batch.x
+        + CHUNK_BATCH_SIZE * chunk_in_batch.y
+        + CHUNK_BATCH_SIZE * CHUNK_BATCH_SIZE * chunk_in_batch.z;
+    let spawn_time = render_group.chunk_spawn_times[index / 4u][index % 4u];
+
+    return saturate((global.time - spawn_time) / global.chunk_fade_duration);
+}
+
+// threshold between 0 and 1 that varies from pixel to pixel, for dithered transparency
+// (interleaved gradient noise)
+fn dither_threshold(pixel: vec2f) -> f32 {
+    return fract(52.9829189 * fract(dot(floor(pixel), vec2(0.06711056, 0.00583715))));
+}
+
+// colour multiplier tinting each chunk by a hash of its position, with antialiased lines along
+// chunk boundaries
+fn chunk_tint(in: Interpolated) -> vec3f {
+    // step half a block back from the face so that faces on a chunk boundary take the colour of
+    // the chunk containing their block
+    let inside = in.batch_position - 0.5 * in.normal;
+    let block_pos = in.batch_offset + vec3i(floor(inside));
+    // arithmetic shift rounds towards negative infinity, so negative chunks are handled correctly
+    let chunk_pos = block_pos >> vec3(CHUNK_SIZE_LOG2);
+
+    // position within the chunk, from 0 to CHUNK_SIZE along each axis
+    let chunk_local = vec3f(block_pos - (chunk_pos << vec3(CHUNK_SIZE_LOG2))) + fract(inside);
+    let boundary_distance = min(chunk_local, CHUNK_SIZE - chunk_local);
+    // the axis along the normal is constant across the face, so it never forms a line
+    let along_face = abs(in.normal) < vec3(0.5);
+    let line_width = CHUNK_BOUNDARY_WIDTH * fwidth(chunk_local);
+    let line = select(
+        vec3(0.0),
+        1.0 - smoothstep(vec3(0.0), line_width, boundary_distance),
+        along_face,
+    );
+
+    let tint = mix(vec3(0.35), vec3(1.0), hash_color(chunk_pos));
+    return tint * (1.0 - 0.8 * max(line.x, max(line.y, line.z)));
+}
+
+// pseudorandom colour for an integer position
+fn hash_color(pos: vec3i) -> vec3f {
+    var h = vec3u(pos) * vec3(1597334673u, 3812015801u, 2798796415u);
+    let n = (h.x ^ h.y ^ h.z) * 1597334673u;
+    h = vec3(n, n * 16807u, n * 48271u);
+    return vec3f(h >> vec3(24u)) / 255.0;
+}
@@ -0,0 +1,64 @@
+struct TextUniforms {
+    // premultiplied by alpha
+    text_color: vec4f,
+    // premultiplied by alpha
+    background_color: vec4f,
+    screen_size: vec2f,
+    // size of each font pixel in physical pixels
+    pixel_scale: f32,
+}
+
+struct Interpolated {
+    @builtin(position) clip_position: vec4f,
+    // position within the character cell in font pixels
+    @location(0) cell_pos: vec2f,
+    @location(1) @interpolate(flat) glyph: u32,
+}
+
+// must match the glyph size in font.rs
+const GLYPH_SIZE: vec2i = vec2(5, 7);
+// glyphs are separated by one pixel horizontally and two pixels vertically
+const CELL_SIZE: vec2f = vec2(6.0, 9.0);
+// distance of the text from the top left corner of the screen in font pixels
+const MARGIN: vec2f = vec2(2.0, 2.0);
+
+@group(0) @binding(0)
+var<uniform> text: TextUniforms;
+
+@group(0) @binding(1)
+var font: texture_2d<f32>;
+
+// meant to be drawn as a triangle strip with 4 vertices per character cell
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) cell: vec2u,
+    @location(1) glyph: u32,
+) -> Interpolated {
+    let corner = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let cell_pos = corner * CELL_SIZE;
+
+    // in physical pixels from the top left corner of the screen
+    let screen_pos = (MARGIN + vec2f(cell) * CELL_SIZE + cell_pos) * text.pixel_scale;
+    let ndc = vec2(2.0, -2.0) * screen_pos / text.screen_size + vec2(-1.0, 1.0);
+
+    var out: Interpolated;
+    out.clip_position = vec4(ndc, 0.0, 1.0);
+    out.cell_pos = cell_pos;
+    out.glyph = glyph;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Interpolated) -> @location(0) vec4f {
+    // the glyph sits one pixel below the top of the cell, so that lines don't touch
+    let texel = vec2i(floor(in.cell_pos)) - vec2(0, 1);
+
+    var coverage = 0.0;
+    if all(texel >= vec2(0)) && all(texel < GLYPH_SIZE) {
+        let atlas_texel = vec2(i32(in.glyph) * GLYPH_SIZE.x + texel.x, texel.y);
+        coverage = textureLoad(font, atlas_texel, 0).r;
+    }
+
+    return mix(text.background_color, text.text_color, coverage);
+}
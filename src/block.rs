@@ -1,8 +1,18 @@
+use std::sync::LazyLock;
+
 use glam::IVec3;
 
-use self::model::{BlockFace, BlockModel};
+use self::{
+    behaviour::{BlockBehaviour, NoBehaviour, PlantBehaviour},
+    model::BlockModel,
+    texture::BLOCK_TEXTURES,
+};
+use crate::terrain::lighting::EmittedLight;
 
+pub mod behaviour;
 pub mod model;
+pub mod model_file;
+pub mod texture;
 
 /// Numeric identifier for a `Block`
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -11,8 +21,55 @@ pub struct BlockId(pub u16);
 /// Represents a kind of block in the world
 #[derive(Clone, Debug)]
 pub struct Block {
+    /// Unique name of the block, used to identify it in saved chunks so that saves stay valid
+    /// when blocks are added or reordered
+    pub name: &'static str,
     pub model: BlockModel,
     pub emission: IVec3,
+    /// If true, the greedy mesher never merges this block's faces with those of its neighbours, so
+    /// that each block keeps its own quads (e.g. for per-block texture variation)
+    pub never_merge: bool,
+    /// Time taken to break the block in seconds, or infinity if it can't be broken
+    pub hardness: f32,
+    /// How the block reacts to being placed or broken and to changes next to it
+    pub behaviour: &'static dyn BlockBehaviour,
+}
+
+impl Block {
+    /// True if the block occupies its cell with anything at all, i.e. it isn't empty like air.
+    /// Blocks that aren't solid can be replaced freely, e.g. by structures
+    pub fn is_solid(&self) -> bool {
+        !matches!(self.model, BlockModel::Empty)
+    }
+
+    /// True if the block hides the faces of its neighbours and blocks light. Transparent and
+    /// cutout blocks such as leaves are solid but not opaque
+    pub fn is_opaque(&self) -> bool {
+        self.model.is_opaque()
+    }
+
+    /// True if entities are stopped by the block, which is then tested against its bounding box.
+    /// Passable blocks such as plants are solid without being collidable, but can still be hit
+    /// by rays
+    pub fn is_collidable(&self) -> bool {
+        self.model.bounding_box().is_some() && !self.model.is_passable()
+    }
+
+    /// True if the block gives off light of its own
+    pub fn emits_light(&self) -> bool {
+        self.emission != IVec3::ZERO
+    }
+
+    /// Light given off by the block, which spreads to the blocks around it
+    pub fn emitted_light(&self) -> EmittedLight {
+        let [r, g, b] = self.emission.to_array().map(|component| component as u16);
+        EmittedLight::from_rgb(r, g, b)
+    }
+
+    /// True if the player can break the block, i.e. its hardness is finite
+    pub fn is_breakable(&self) -> bool {
+        self.hardness.is_finite()
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -23,60 +80,257 @@ pub const BLOCK_DIRT: BlockId = BlockId(1);
 pub const BLOCK_GRASS: BlockId = BlockId(2);
 pub const BLOCK_WOOD: BlockId = BlockId(3);
 pub const BLOCK_LAMP_ORANGE: BlockId = BlockId(4);
-pub const BLOCK_COUNT: usize = 5;
+pub const BLOCK_LEAVES: BlockId = BlockId(5);
+pub const BLOCK_COAL_ORE: BlockId = BlockId(6);
+pub const BLOCK_FENCE_POST: BlockId = BlockId(7);
+pub const BLOCK_BEDROCK: BlockId = BlockId(8);
+pub const BLOCK_GLASS: BlockId = BlockId(9);
+pub const BLOCK_WOOD_SLAB: BlockId = BlockId(10);
+pub const BLOCK_WOOD_STAIRS: BlockId = BlockId(11);
+pub const BLOCK_TALL_GRASS: BlockId = BlockId(12);
+pub const BLOCK_COUNT: usize = 13;
+
+/// Tints multiplied into the texture colour of block faces, so that grayscale textures such as
+/// the top of grass can be coloured. Indexes into `TINT_PALETTE`
+pub const TINT_NONE: usize = 0;
+pub const TINT_GRASS: usize = 1;
+pub const TINT_COUNT: usize = 2;
+
+/// Linear RGB colour of each tint, padded to 16 bytes for the uniform buffer.
+/// NB: this could later vary by biome or position
+pub const TINT_PALETTE: [[f32; 4]; TINT_COUNT] = [
+    // None
+    [1.0, 1.0, 1.0, 1.0],
+    // Grass
+    [0.22, 0.45, 0.07, 1.0],
+];
 
-pub const BLOCKS: [Block; BLOCK_COUNT] = [
+/// Every block, indexed by `BlockId`. Built on first use, as some models are loaded from
+/// `model_file::BLOCK_MODEL_DIRECTORY`
+pub static BLOCKS: LazyLock<[Block; BLOCK_COUNT]> = LazyLock::new(|| [
     // Air
     Block {
+        name: "air",
         model: BlockModel::Empty,
         emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 0.0,
+        behaviour: &NoBehaviour,
     },
     // Dirt
     Block {
+        name: "dirt",
         model: BlockModel::FullBlock([
-            BlockFace { texture_index: 0 },
-            BlockFace { texture_index: 0 },
-            BlockFace { texture_index: 0 },
-            BlockFace { texture_index: 0 },
-            BlockFace { texture_index: 0 },
-            BlockFace { texture_index: 0 },
+            BLOCK_TEXTURES.face("dirt"),
+            BLOCK_TEXTURES.face("dirt"),
+            BLOCK_TEXTURES.face("dirt"),
+            BLOCK_TEXTURES.face("dirt"),
+            BLOCK_TEXTURES.face("dirt"),
+            BLOCK_TEXTURES.face("dirt"),
         ]),
         emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 0.5,
+        behaviour: &NoBehaviour,
     },
     // Grass
     Block {
+        name: "grass",
         model: BlockModel::FullBlock([
-            BlockFace { texture_index: 1 },
-            BlockFace { texture_index: 2 },
-            BlockFace { texture_index: 1 },
-            BlockFace { texture_index: 1 },
-            BlockFace { texture_index: 0 },
-            BlockFace { texture_index: 1 },
+            BLOCK_TEXTURES.face("grass_side"),
+            BLOCK_TEXTURES
+                .face("grass_top")
+                .with_tint(TINT_GRASS)
+                .with_random_uv_rotation(),
+            BLOCK_TEXTURES.face("grass_side"),
+            BLOCK_TEXTURES.face("grass_side"),
+            BLOCK_TEXTURES.face("dirt"),
+            BLOCK_TEXTURES.face("grass_side"),
         ]),
         emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 0.6,
+        behaviour: &NoBehaviour,
     },
     // Wood
     Block {
+        name: "wood",
         model: BlockModel::FullBlock([
-            BlockFace { texture_index: 3 },
-            BlockFace { texture_index: 3 },
-            BlockFace { texture_index: 3 },
-            BlockFace { texture_index: 3 },
-            BlockFace { texture_index: 3 },
-            BlockFace { texture_index: 3 },
+            BLOCK_TEXTURES.face("wood"),
+            BLOCK_TEXTURES.face("wood"),
+            BLOCK_TEXTURES.face("wood"),
+            BLOCK_TEXTURES.face("wood"),
+            BLOCK_TEXTURES.face("wood"),
+            BLOCK_TEXTURES.face("wood"),
         ]),
         emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 2.0,
+        behaviour: &NoBehaviour,
     },
     // Orange lamp
     Block {
+        name: "lamp_orange",
         model: BlockModel::FullBlock([
-            BlockFace { texture_index: 4 },
-            BlockFace { texture_index: 4 },
-            BlockFace { texture_index: 4 },
-            BlockFace { texture_index: 4 },
-            BlockFace { texture_index: 4 },
-            BlockFace { texture_index: 4 },
+            BLOCK_TEXTURES.face("lamp_orange"),
+            BLOCK_TEXTURES.face("lamp_orange"),
+            BLOCK_TEXTURES.face("lamp_orange"),
+            BLOCK_TEXTURES.face("lamp_orange"),
+            BLOCK_TEXTURES.face("lamp_orange"),
+            BLOCK_TEXTURES.face("lamp_orange"),
         ]),
         emission: IVec3::new(15, 10, 5),
+        never_merge: false,
+        hardness: 0.3,
+        behaviour: &NoBehaviour,
     },
-];
+    // Leaves
+    Block {
+        name: "leaves",
+        model: BlockModel::Cutout {
+            faces: [
+                BLOCK_TEXTURES.face("leaves"),
+                BLOCK_TEXTURES.face("leaves"),
+                BLOCK_TEXTURES.face("leaves"),
+                BLOCK_TEXTURES.face("leaves"),
+                BLOCK_TEXTURES.face("leaves"),
+                BLOCK_TEXTURES.face("leaves"),
+            ],
+            cull_self: true,
+        },
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 0.2,
+        behaviour: &NoBehaviour,
+    },
+    // Coal ore
+    Block {
+        name: "coal_ore",
+        model: BlockModel::FullBlock([
+            BLOCK_TEXTURES.face("coal_ore"),
+            BLOCK_TEXTURES.face("coal_ore"),
+            BLOCK_TEXTURES.face("coal_ore"),
+            BLOCK_TEXTURES.face("coal_ore"),
+            BLOCK_TEXTURES.face("coal_ore"),
+            BLOCK_TEXTURES.face("coal_ore"),
+        ]),
+        emission: IVec3::ZERO,
+        never_merge: true,
+        hardness: 3.0,
+        behaviour: &NoBehaviour,
+    },
+    // Fence post
+    Block {
+        name: "fence_post",
+        model: micro_voxel_model("fence_post"),
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 2.0,
+        behaviour: &NoBehaviour,
+    },
+    // Bedrock
+    Block {
+        name: "bedrock",
+        model: BlockModel::FullBlock([
+            BLOCK_TEXTURES.face("bedrock"),
+            BLOCK_TEXTURES.face("bedrock"),
+            BLOCK_TEXTURES.face("bedrock"),
+            BLOCK_TEXTURES.face("bedrock"),
+            BLOCK_TEXTURES.face("bedrock"),
+            BLOCK_TEXTURES.face("bedrock"),
+        ]),
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: f32::INFINITY,
+        behaviour: &NoBehaviour,
+    },
+    // Glass
+    Block {
+        name: "glass",
+        model: BlockModel::Translucent([
+            BLOCK_TEXTURES.face("glass"),
+            BLOCK_TEXTURES.face("glass"),
+            BLOCK_TEXTURES.face("glass"),
+            BLOCK_TEXTURES.face("glass"),
+            BLOCK_TEXTURES.face("glass"),
+            BLOCK_TEXTURES.face("glass"),
+        ]),
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 0.3,
+        behaviour: &NoBehaviour,
+    },
+    // Wood slab
+    Block {
+        name: "wood_slab",
+        model: micro_voxel_model("wood_slab"),
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 2.0,
+        behaviour: &NoBehaviour,
+    },
+    // Wood stairs
+    Block {
+        name: "wood_stairs",
+        model: micro_voxel_model("wood_stairs"),
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 2.0,
+        behaviour: &NoBehaviour,
+    },
+    // Tall grass
+    Block {
+        name: "tall_grass",
+        model: BlockModel::Cross(BLOCK_TEXTURES.face("tall_grass").with_tint(TINT_GRASS)),
+        emission: IVec3::ZERO,
+        never_merge: false,
+        hardness: 0.0,
+        behaviour: &PlantBehaviour,
+    },
+]);
+
+/// `MicroVoxels` model loaded from the model file with the given name.
+/// Panics if the file can't be loaded, as the blocks can't be built without it
+fn micro_voxel_model(name: &str) -> BlockModel {
+    match model_file::load_micro_voxel_model(name) {
+        Ok(boxes) => BlockModel::MicroVoxels(boxes),
+        Err(e) => panic!("couldn't load block model: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Which of `is_solid`, `is_opaque` and `is_collidable` hold for a block
+    fn predicates(block_id: BlockId) -> [bool; 3] {
+        let block = &BLOCKS[block_id.0 as usize];
+        [block.is_solid(), block.is_opaque(), block.is_collidable()]
+    }
+
+    #[test]
+    fn block_predicates() {
+        // air is none of them
+        assert_eq!(predicates(BLOCK_AIR), [false, false, false]);
+
+        // full blocks are all of them
+        assert_eq!(predicates(BLOCK_DIRT), [true, true, true]);
+        assert_eq!(predicates(BLOCK_COAL_ORE), [true, true, true]);
+
+        // see-through blocks don't hide their neighbours or block light, but still stop rays
+        assert_eq!(predicates(BLOCK_LEAVES), [true, false, true]);
+        assert_eq!(predicates(BLOCK_FENCE_POST), [true, false, true]);
+        assert_eq!(predicates(BLOCK_GLASS), [true, false, true]);
+        assert_eq!(predicates(BLOCK_WOOD_SLAB), [true, false, true]);
+
+        // plants can be walked through
+        assert_eq!(predicates(BLOCK_TALL_GRASS), [true, false, false]);
+    }
+
+    #[test]
+    fn only_bedrock_is_unbreakable() {
+        for (index, block) in BLOCKS.iter().enumerate() {
+            assert_eq!(block.is_breakable(), BlockId(index as u16) != BLOCK_BEDROCK);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Callbacks that let blocks react to being placed or broken and to changes next to them, for
+//! interactive blocks such as doors, logic and growth
+
+use std::collections::VecDeque;
+
+use crate::{
+    block::{BlockId, BLOCKS, BLOCK_AIR},
+    terrain::position_types::GlobalBlockPosition,
+    util::face::FaceIndex,
+};
+
+/// Maximum number of block changes made by one edit and the chain of callbacks it sets off, so
+/// that behaviours which keep triggering each other can't hang the game
+pub const MAX_CHAINED_EDITS: usize = 4096;
+
+/// How a kind of block reacts to edits. Every callback does nothing by default.
+/// NB: callbacks run synchronously on the main thread, inside the `Terrain::set_block` call that
+/// caused them (usually from `update`). They can only read blocks and queue further edits through
+/// `BlockEventContext`, rather than borrowing the terrain, so that they could later run off the
+/// main thread
+pub trait BlockBehaviour: std::fmt::Debug + Send + Sync {
+    /// Called after this block is placed at `pos`
+    fn on_place(&self, _cx: &mut BlockEventContext, _pos: GlobalBlockPosition) {}
+
+    /// Called after this block is replaced at `pos`. The new block is already in place
+    fn on_break(&self, _cx: &mut BlockEventContext, _pos: GlobalBlockPosition) {}
+
+    /// Called on the block at `pos` after the block at `neighbour_pos`, one of its 6 neighbours,
+    /// changed
+    fn on_neighbour_change(
+        &self,
+        _cx: &mut BlockEventContext,
+        _pos: GlobalBlockPosition,
+        _neighbour_pos: GlobalBlockPosition,
+    ) {
+    }
+}
+
+/// Behaviour of blocks that don't react to anything
+#[derive(Debug)]
+pub struct NoBehaviour;
+
+impl BlockBehaviour for NoBehaviour {}
+
+/// Behaviour of plants, which are removed when the block below them stops supporting them, i.e.
+/// is no longer a block that entities can stand on
+#[derive(Debug)]
+pub struct PlantBehaviour;
+
+impl BlockBehaviour for PlantBehaviour {
+    fn on_neighbour_change(
+        &self,
+        cx: &mut BlockEventContext,
+        pos: GlobalBlockPosition,
+        neighbour_pos: GlobalBlockPosition,
+    ) {
+        if neighbour_pos != pos.neighbour(FaceIndex::NEG_Y) {
+            return;
+        }
+
+        let supported = cx
+            .get_block(neighbour_pos)
+            .is_some_and(|block_id| BLOCKS[block_id.0 as usize].is_collidable());
+
+        if !supported {
+            cx.set_block(pos, BLOCK_AIR);
+        }
+    }
+}
+
+/// Read and write access to blocks, implemented by the terrain
+pub trait BlockAccess {
+    /// Returns the block at the given position, or None if it is not loaded
+    fn get_block(&self, pos: GlobalBlockPosition) -> Option<BlockId>;
+
+    /// Sets the block at the given position without running any callbacks. Returns false if the
+    /// position is not loaded or the block can't be set there
+    fn set_block(&mut self, pos: GlobalBlockPosition, new_id: BlockId) -> bool;
+}
+
+/// Passed to the callbacks of `BlockBehaviour` to read blocks and queue edits. Queued edits are
+/// applied once the callback returns, in the order they were queued
+pub struct BlockEventContext<'a> {
+    world: &'a dyn BlockAccess,
+    edits: Vec<(GlobalBlockPosition, BlockId)>,
+}
+
+impl BlockEventContext<'_> {
+    /// Returns the block at the given position, or None if it is not loaded
+    pub fn get_block(&self, pos: GlobalBlockPosition) -> Option<BlockId> {
+        self.world.get_block(pos)
+    }
+
+    /// Queue the block at the given position to be set, running its callbacks in turn
+    pub fn set_block(&mut self, pos: GlobalBlockPosition, new_id: BlockId) {
+        self.edits.push((pos, new_id));
+    }
+}
+
+/// Set a block, then run the callbacks of the blocks involved: `on_break` for the old block,
+/// `on_place` for the new block and `on_neighbour_change` for each of the 6 neighbours. Edits
+/// queued by the callbacks are applied in the same way, breadth first, up to `MAX_CHAINED_EDITS`.
+/// Setting a block to the ID it already has runs no callbacks.
+/// `behaviour` gives the behaviour of each block. Returns false if `pos` is not loaded or the
+/// block can't be set there
+pub fn set_block_with_callbacks<'b>(
+    world: &mut dyn BlockAccess,
+    behaviour: &dyn Fn(BlockId) -> &'b dyn BlockBehaviour,
+    pos: GlobalBlockPosition,
+    new_id: BlockId,
+) -> bool {
+    let Some(old_id) = world.get_block(pos) else {
+        return false;
+    };
+    if !world.set_block(pos, new_id) {
+        return false;
+    }
+
+    let mut pending = VecDeque::from([(pos, old_id, new_id)]);
+    let mut edit_count = 1;
+
+    while let Some((pos, old_id, new_id)) = pending.pop_front() {
+        if old_id == new_id {
+            continue;
+        }
+
+        let mut cx = BlockEventContext {
+            world: &*world,
+            edits: Vec::new(),
+        };
+
+        behaviour(old_id).on_break(&mut cx, pos);
+        behaviour(new_id).on_place(&mut cx, pos);
+
+        for face_index in 0..6 {
+            let neighbour_pos = pos.neighbour(FaceIndex(face_index));
+
+            if let Some(neighbour_id) = cx.get_block(neighbour_pos) {
+                behaviour(neighbour_id).on_neighbour_change(&mut cx, neighbour_pos, pos);
+            }
+        }
+
+        for (pos, new_id) in cx.edits {
+            if edit_count >= MAX_CHAINED_EDITS {
+                log::warn!("block callbacks made over {MAX_CHAINED_EDITS} edits, stopping");
+                return true;
+            }
+
+            let Some(old_id) = world.get_block(pos) else {
+                continue;
+            };
+            if world.set_block(pos, new_id) {
+                pending.push_back((pos, old_id, new_id));
+                edit_count += 1;
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use rustc_hash::FxHashMap;
+
+    use super::*;
+
+    const AIR: BlockId = BlockId(0);
+    const SPREADER: BlockId = BlockId(1);
+    const STONE: BlockId = BlockId(2);
+
+    /// Blocks in a loaded region of 4x4x4 blocks from the origin
+    struct TestWorld(FxHashMap<GlobalBlockPosition, BlockId>);
+
+    impl BlockAccess for TestWorld {
+        fn get_block(&self, pos: GlobalBlockPosition) -> Option<BlockId> {
+            self.0.get(&pos).copied()
+        }
+
+        fn set_block(&mut self, pos: GlobalBlockPosition, new_id: BlockId) -> bool {
+            self.0
+                .get_mut(&pos)
+                .map(|block_id| *block_id = new_id)
+                .is_some()
+        }
+    }
+
+    /// Places stone on its +X side when placed
+    #[derive(Debug)]
+    struct Spreader;
+
+    impl BlockBehaviour for Spreader {
+        fn on_place(&self, cx: &mut BlockEventContext, pos: GlobalBlockPosition) {
+            cx.set_block(pos.neighbour(FaceIndex::POS_X), STONE);
+        }
+    }
+
+    /// Records each neighbour change it is told about
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<(GlobalBlockPosition, GlobalBlockPosition)>>);
+
+    impl BlockBehaviour for Recorder {
+        fn on_neighbour_change(
+            &self,
+            _cx: &mut BlockEventContext,
+            pos: GlobalBlockPosition,
+            neighbour_pos: GlobalBlockPosition,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((pos, neighbour_pos));
+        }
+    }
+
+    #[test]
+    fn placing_a_block_notifies_each_neighbour_once() {
+        let mut world = TestWorld(
+            itertools::iproduct!(0..4, 0..4, 0..4)
+                .map(|(x, y, z)| (GlobalBlockPosition::new(x, y, z), AIR))
+                .collect(),
+        );
+        let recorder = Recorder::default();
+        let behaviour = |block_id: BlockId| -> &dyn BlockBehaviour {
+            match block_id {
+                SPREADER => &Spreader,
+                _ => &recorder,
+            }
+        };
+
+        let pos = GlobalBlockPosition::new(1, 1, 1);
+        let stone_pos = GlobalBlockPosition::new(2, 1, 1);
+        assert!(set_block_with_callbacks(&mut world, &behaviour, pos, SPREADER));
+        assert_eq!(world.get_block(stone_pos), Some(STONE));
+
+        let mut expected = (0..6)
+            .map(|face_index| (pos.neighbour(FaceIndex(face_index)), pos))
+            .chain(
+                // the spreader itself doesn't record changes
+                (0..6)
+                    .map(|face_index| (stone_pos.neighbour(FaceIndex(face_index)), stone_pos))
+                    .filter(|&(neighbour_pos, _)| neighbour_pos != pos),
+            )
+            .collect::<Vec<_>>();
+        let mut recorded = recorder.0.into_inner().unwrap();
+        expected.sort_by_key(|&(a, b)| (a.as_ivec3().to_array(), b.as_ivec3().to_array()));
+        recorded.sort_by_key(|&(a, b)| (a.as_ivec3().to_array(), b.as_ivec3().to_array()));
+        assert_eq!(recorded, expected);
+
+        // setting the same block again changes nothing, so no callbacks run
+        let recorder = Recorder::default();
+        let behaviour = |_| -> &dyn BlockBehaviour { &recorder };
+        assert!(set_block_with_callbacks(&mut world, &behaviour, stone_pos, STONE));
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        // unloaded positions can't be set
+        let outside_pos = GlobalBlockPosition::new(-1, 0, 0);
+        assert!(!set_block_with_callbacks(&mut world, &behaviour, outside_pos, STONE));
+    }
+}
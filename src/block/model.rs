@@ -1,29 +1,270 @@
-use crate::util::face::FaceIndex;
+use glam::{UVec2, UVec3, Vec2, Vec3};
+
+use super::TINT_NONE;
+use crate::{render::util::texture::AlphaClass, util::face::FaceIndex};
 
 #[derive(Clone, Debug)]
 pub enum BlockModel {
     Empty,
     FullBlock([BlockFace; 6]),
+    /// Full block whose textures have fully transparent holes, which are discarded in the shader
+    /// (e.g. leaves). Faces of neighbouring blocks are not hidden by it, except for faces of the
+    /// same block if `cull_self` is set
+    Cutout {
+        faces: [BlockFace; 6],
+        cull_self: bool,
+    },
+    /// Full block whose textures are partially transparent (e.g. glass or water), drawn with
+    /// alpha blending after the rest of the terrain. Faces of neighbouring blocks are not hidden
+    /// by it, but faces between two blocks of the same kind are
+    Translucent([BlockFace; 6]),
+    /// Block made of boxes on a grid of `MICRO_VOXEL_RESOLUTION`³ micro-voxels, for decorations
+    /// that don't fill the block (e.g. fence posts). Faces of neighbouring blocks are not hidden
+    /// by it, and its mesh is built once and copied into chunk meshes at each block's position.
+    /// Models can be loaded from JSON files with `model_file::load_micro_voxel_model`
+    MicroVoxels(&'static [MicroVoxelBox]),
+    /// Two quads crossing diagonally through the block, each visible from both sides, for plants
+    /// (e.g. tall grass). The texture is alpha tested like a cutout block. Faces of neighbouring
+    /// blocks are not hidden by it, its quads are never merged, and entities pass through it
+    Cross(BlockFace),
+}
+
+/// Number of micro-voxels along each edge of a block with a `MicroVoxels` model
+pub const MICRO_VOXEL_RESOLUTION: u32 = 8;
+
+/// Box of micro-voxels in a `MicroVoxels` model
+#[derive(Clone, Copy, Debug)]
+pub struct MicroVoxelBox {
+    /// Minimum corner of the box, in micro-voxels
+    pub min: UVec3,
+    /// Maximum corner of the box, in micro-voxels. Each component must be greater than that of
+    /// `min` and at most `MICRO_VOXEL_RESOLUTION`
+    pub max: UVec3,
+    pub faces: [BlockFace; 6],
+    /// Region of the texture drawn on each face, or None to start the texture at the corner of
+    /// the face
+    pub uvs: [Option<MicroVoxelUv>; 6],
+}
+
+/// Region of the texture drawn on a face of a `MicroVoxelBox`, in micro-voxels from the top left
+/// corner of the texture. The region is mapped onto the face before the face's `uv_rotation`
+/// is applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MicroVoxelUv {
+    pub min: UVec2,
+    pub max: UVec2,
+}
+
+impl MicroVoxelUv {
+    /// Minimum and maximum corners of the region in texture coordinates, where the texture
+    /// spans 0 to 1
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let scale = (MICRO_VOXEL_RESOLUTION as f32).recip();
+        (self.min.as_vec2() * scale, self.max.as_vec2() * scale)
+    }
+}
+
+impl MicroVoxelBox {
+    /// Minimum and maximum corners of the box relative to the block's position, in blocks
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let scale = (MICRO_VOXEL_RESOLUTION as f32).recip();
+        (self.min.as_vec3() * scale, self.max.as_vec3() * scale)
+    }
 }
 
 impl BlockModel {
+    /// Model for a full-size block with the given faces, chosen from the alpha classes of the
+    /// texture array layers so that blocks only need to specify a model to override it. Faces
+    /// with partially transparent pixels give a `Translucent` model, faces with fully transparent
+    /// holes give a `Cutout` model, otherwise the model is `FullBlock`
+    pub fn from_texture_alpha(faces: [BlockFace; 6], alpha_classes: &[AlphaClass]) -> Self {
+        let has_alpha_class = |alpha_class| {
+            faces.iter().any(|face| {
+                alpha_classes
+                    .get(face.texture_index)
+                    .is_some_and(|&face_alpha_class| face_alpha_class == alpha_class)
+            })
+        };
+
+        if has_alpha_class(AlphaClass::Translucent) {
+            BlockModel::Translucent(faces)
+        } else if has_alpha_class(AlphaClass::Cutout) {
+            BlockModel::Cutout {
+                faces,
+                cull_self: true,
+            }
+        } else {
+            BlockModel::FullBlock(faces)
+        }
+    }
+
     pub fn face(&self, face_index: FaceIndex) -> Option<BlockFace> {
         match self {
-            BlockModel::Empty => None,
-            BlockModel::FullBlock(faces) => Some(faces[face_index.as_usize()]),
+            BlockModel::Empty | BlockModel::MicroVoxels(_) | BlockModel::Cross(_) => None,
+            BlockModel::FullBlock(faces)
+            | BlockModel::Cutout { faces, .. }
+            | BlockModel::Translucent(faces) => Some(faces[face_index.as_usize()]),
         }
     }
 
     pub fn is_opaque(&self) -> bool {
         match self {
-            BlockModel::Empty => false,
+            BlockModel::Empty
+            | BlockModel::Cutout { .. }
+            | BlockModel::Translucent(_)
+            | BlockModel::MicroVoxels(_)
+            | BlockModel::Cross(_) => false,
             BlockModel::FullBlock(_) => true,
         }
     }
+
+    pub fn is_cutout(&self) -> bool {
+        matches!(self, BlockModel::Cutout { .. })
+    }
+
+    /// True if the block is drawn with alpha blending in the translucent terrain pass
+    pub fn is_translucent(&self) -> bool {
+        matches!(self, BlockModel::Translucent(_))
+    }
+
+    /// True if the block is drawn from both sides, without back-face culling
+    pub fn is_two_sided(&self) -> bool {
+        matches!(self, BlockModel::Cross(_))
+    }
+
+    /// True if entities pass through the block even though rays can hit it
+    pub fn is_passable(&self) -> bool {
+        matches!(self, BlockModel::Cross(_))
+    }
+
+    /// True if this block hides the faces of adjacent blocks that touch its face with the given
+    /// index
+    pub fn hides_adjacent_faces(&self, face_index: FaceIndex) -> bool {
+        match self {
+            BlockModel::Empty
+            | BlockModel::Cutout { .. }
+            | BlockModel::Translucent(_)
+            | BlockModel::MicroVoxels(_)
+            | BlockModel::Cross(_) => false,
+            BlockModel::FullBlock(_) => self.face(face_index).is_some(),
+        }
+    }
+
+    /// True if faces of this block touching another block of the same kind should be hidden
+    pub fn culls_self(&self) -> bool {
+        match self {
+            BlockModel::Empty | BlockModel::MicroVoxels(_) | BlockModel::Cross(_) => false,
+            BlockModel::FullBlock(_) | BlockModel::Translucent(_) => true,
+            BlockModel::Cutout { cull_self, .. } => *cull_self,
+        }
+    }
+
+    /// Returns the minimum and maximum corners of the box enclosing the solid part of the block,
+    /// relative to the block's position, or None if the block has no solid part.
+    /// Used for ray intersection so that rays can pass through the empty part of sub-cube models
+    pub fn bounding_box(&self) -> Option<(Vec3, Vec3)> {
+        match self {
+            BlockModel::Empty => None,
+            BlockModel::FullBlock(_)
+            | BlockModel::Cutout { .. }
+            | BlockModel::Translucent(_)
+            | BlockModel::Cross(_) => Some((Vec3::ZERO, Vec3::ONE)),
+            BlockModel::MicroVoxels(boxes) => boxes
+                .iter()
+                .map(MicroVoxelBox::bounds)
+                .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b))),
+        }
+    }
 }
 
 /// represents one axis-aligned face of a block model
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct BlockFace {
+    /// Layer of the terrain texture array, usually looked up by name in `BLOCK_TEXTURES`. When the
+    /// textures are loaded from an atlas, this is the index of the tile, counting left to right
+    /// and then top to bottom
     pub texture_index: usize,
+    /// Index of the tint multiplied into the texture colour in `TINT_PALETTE`
+    pub tint_index: usize,
+    /// Rotation of the texture on the face
+    pub uv_rotation: UvRotation,
+    /// If true, `uv_rotation` is replaced by a pseudo-random rotation picked from the position of
+    /// each block, to break up the tiling of textures that look the same in any orientation
+    pub random_uv_rotation: bool,
+}
+
+impl BlockFace {
+    /// Untinted face with the given texture
+    pub const fn new(texture_index: usize) -> Self {
+        Self {
+            texture_index,
+            tint_index: TINT_NONE,
+            uv_rotation: UvRotation::None,
+            random_uv_rotation: false,
+        }
+    }
+
+    /// The same face with the given tint
+    pub const fn with_tint(self, tint_index: usize) -> Self {
+        Self { tint_index, ..self }
+    }
+
+    /// The same face with its texture rotated
+    pub const fn with_uv_rotation(self, uv_rotation: UvRotation) -> Self {
+        Self {
+            uv_rotation,
+            ..self
+        }
+    }
+
+    /// The same face with its texture rotated pseudo-randomly at each block, see `at`
+    pub const fn with_random_uv_rotation(self) -> Self {
+        Self {
+            random_uv_rotation: true,
+            ..self
+        }
+    }
+
+    /// The face as it appears on the block at the given position in its chunk, with the rotation
+    /// of faces with `random_uv_rotation` picked from the position. The position is local to the
+    /// chunk so that chunks with the same blocks still have the same mesh
+    pub fn at(self, pos_in_chunk: UVec3) -> Self {
+        if !self.random_uv_rotation {
+            return self;
+        }
+
+        let hash = pos_in_chunk.x.wrapping_mul(73856093)
+            ^ pos_in_chunk.y.wrapping_mul(19349663)
+            ^ pos_in_chunk.z.wrapping_mul(83492791);
+        let quarter_turns = hash.wrapping_mul(0x9e37_79b9) >> 30;
+
+        self.with_uv_rotation(UvRotation::from_quarter_turns(quarter_turns as usize))
+    }
+}
+
+/// Clockwise rotation of a face's texture, as seen from outside the block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UvRotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl UvRotation {
+    /// Number of clockwise quarter turns
+    pub const fn quarter_turns(self) -> usize {
+        self as usize
+    }
+
+    /// Rotation by the given number of clockwise quarter turns, modulo a full turn
+    pub const fn from_quarter_turns(quarter_turns: usize) -> Self {
+        match quarter_turns & 3 {
+            0 => Self::None,
+            1 => Self::Clockwise90,
+            2 => Self::Clockwise180,
+            _ => Self::Clockwise270,
+        }
+    }
 }
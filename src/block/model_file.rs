@@ -0,0 +1,295 @@
+//! Loading of `MicroVoxels` block models from JSON files, so that blocks that don't fill their
+//! cell (e.g. slabs and stairs) can be described without code.
+//!
+//! A model file lists the boxes of the model. Each box gives its corners in micro-voxels and the
+//! texture of each of its faces, named by `BLOCK_TEXTURES` or given by its layer (i.e. its tile
+//! index in the texture atlas), optionally with the region of the texture to draw and a clockwise
+//! rotation in degrees:
+//!
+//! ```json
+//! {
+//!     "boxes": [
+//!         {
+//!             "min": [0, 0, 0],
+//!             "max": [8, 4, 8],
+//!             "faces": {
+//!                 "pos_x": { "texture": "wood", "uv": [0, 4, 8, 8] },
+//!                 "pos_y": { "texture": 3, "rotation": 90 },
+//!                 ...
+//!             }
+//!         }
+//!     ]
+//! }
+//! ```
+
+use std::path::PathBuf;
+
+use glam::{UVec2, UVec3};
+use serde::Deserialize;
+
+use super::{
+    model::{BlockFace, MicroVoxelBox, MicroVoxelUv, UvRotation, MICRO_VOXEL_RESOLUTION},
+    texture::BLOCK_TEXTURES,
+};
+
+/// Directory the block models are loaded from
+pub const BLOCK_MODEL_DIRECTORY: &str = "assets/model";
+
+/// Path of the model file with the given name, `<BLOCK_MODEL_DIRECTORY>/<name>.json`
+fn model_path(name: &str) -> PathBuf {
+    PathBuf::from(BLOCK_MODEL_DIRECTORY).join(format!("{name}.json"))
+}
+
+/// Load the boxes of a `MicroVoxels` model from the file with the given name in
+/// `BLOCK_MODEL_DIRECTORY`. The boxes are leaked, as models live for the rest of the program
+pub fn load_micro_voxel_model(name: &str) -> Result<&'static [MicroVoxelBox], ModelFileError> {
+    let path = model_path(name);
+    let json = std::fs::read_to_string(&path).map_err(|e| ModelFileError::Io(path.clone(), e))?;
+
+    parse_micro_voxel_model(&json)
+        .map(|boxes| &*boxes.leak())
+        .map_err(|e| ModelFileError::Invalid(path, e))
+}
+
+/// Parse the boxes of a `MicroVoxels` model from the contents of a model file
+pub fn parse_micro_voxel_model(json: &str) -> Result<Vec<MicroVoxelBox>, InvalidModelError> {
+    let model: ModelFile = serde_json::from_str(json)?;
+
+    model
+        .boxes
+        .into_iter()
+        .map(BoxElement::into_micro_voxel_box)
+        .collect()
+}
+
+/// errors returned by `load_micro_voxel_model`
+#[derive(Debug, thiserror::Error)]
+pub enum ModelFileError {
+    #[error("couldn't read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid model {0}: {1}")]
+    Invalid(PathBuf, InvalidModelError),
+}
+
+/// errors returned by `parse_micro_voxel_model`
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidModelError {
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("box from {0} to {1} is empty or doesn't fit in the block")]
+    BoxOutOfRange(UVec3, UVec3),
+    #[error("no block texture named {0:?}")]
+    UnknownTexture(String),
+    #[error("no block texture at layer {0}")]
+    UnknownTextureLayer(usize),
+    #[error("texture region from {0} to {1} is empty or doesn't fit in the texture")]
+    UvOutOfRange(UVec2, UVec2),
+    #[error("rotation of {0} degrees is not a multiple of 90")]
+    InvalidRotation(u32),
+}
+
+/// Contents of a model file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelFile {
+    boxes: Vec<BoxElement>,
+}
+
+/// Box of micro-voxels in a model file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxElement {
+    min: [u32; 3],
+    max: [u32; 3],
+    faces: BoxFaces,
+}
+
+/// Faces of a box in a model file, named after the `FaceIndex` constants
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxFaces {
+    pos_x: FaceElement,
+    pos_y: FaceElement,
+    pos_z: FaceElement,
+    neg_x: FaceElement,
+    neg_y: FaceElement,
+    neg_z: FaceElement,
+}
+
+/// Face of a box in a model file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FaceElement {
+    texture: TextureElement,
+    /// Minimum and maximum corners of the region of the texture to draw, as `[u, v, u, v]` in
+    /// micro-voxels
+    #[serde(default)]
+    uv: Option<[u32; 4]>,
+    /// Clockwise rotation of the texture in degrees
+    #[serde(default)]
+    rotation: u32,
+}
+
+/// Texture of a face in a model file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureElement {
+    /// Name of the texture in `BLOCK_TEXTURES`
+    Name(String),
+    /// Layer of the texture in the terrain texture array, i.e. its tile index in the atlas
+    Layer(usize),
+}
+
+impl BoxElement {
+    fn into_micro_voxel_box(self) -> Result<MicroVoxelBox, InvalidModelError> {
+        let min = UVec3::from(self.min);
+        let max = UVec3::from(self.max);
+        if !min.cmplt(max).all() || max.cmpgt(UVec3::splat(MICRO_VOXEL_RESOLUTION)).any() {
+            return Err(InvalidModelError::BoxOutOfRange(min, max));
+        }
+
+        let BoxFaces {
+            pos_x,
+            pos_y,
+            pos_z,
+            neg_x,
+            neg_y,
+            neg_z,
+        } = self.faces;
+
+        // in `FaceIndex` order
+        let faces = [pos_x, pos_y, pos_z, neg_x, neg_y, neg_z]
+            .map(FaceElement::into_face_and_uv);
+        let mut result = MicroVoxelBox {
+            min,
+            max,
+            faces: [BlockFace::new(0); 6],
+            uvs: [None; 6],
+        };
+        for (face_index, face) in faces.into_iter().enumerate() {
+            (result.faces[face_index], result.uvs[face_index]) = face?;
+        }
+
+        Ok(result)
+    }
+}
+
+impl FaceElement {
+    fn into_face_and_uv(self) -> Result<(BlockFace, Option<MicroVoxelUv>), InvalidModelError> {
+        let face = match self.texture {
+            TextureElement::Name(name) => BLOCK_TEXTURES
+                .find_layer(&name)
+                .map(BlockFace::new)
+                .ok_or(InvalidModelError::UnknownTexture(name))?,
+            TextureElement::Layer(layer) => BLOCK_TEXTURES
+                .face_at(layer)
+                .ok_or(InvalidModelError::UnknownTextureLayer(layer))?,
+        };
+
+        let uv_rotation = match self.rotation {
+            0 => UvRotation::None,
+            90 => UvRotation::Clockwise90,
+            180 => UvRotation::Clockwise180,
+            270 => UvRotation::Clockwise270,
+            rotation => return Err(InvalidModelError::InvalidRotation(rotation)),
+        };
+
+        let uv = self
+            .uv
+            .map(|[min_u, min_v, max_u, max_v]| {
+                let uv = MicroVoxelUv {
+                    min: UVec2::new(min_u, min_v),
+                    max: UVec2::new(max_u, max_v),
+                };
+                let in_range = uv.min.cmplt(uv.max).all()
+                    && uv.max.cmple(UVec2::splat(MICRO_VOXEL_RESOLUTION)).all();
+
+                if in_range {
+                    Ok(uv)
+                } else {
+                    Err(InvalidModelError::UvOutOfRange(uv.min, uv.max))
+                }
+            })
+            .transpose()?;
+
+        Ok((face.with_uv_rotation(uv_rotation), uv))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Model file with a single box and the given face for each of its faces
+    fn single_box_model(min: [u32; 3], max: [u32; 3], face: &str) -> String {
+        let faces = ["pos_x", "pos_y", "pos_z", "neg_x", "neg_y", "neg_z"]
+            .map(|name| format!("\"{name}\": {face}"))
+            .join(", ");
+
+        format!(r#"{{ "boxes": [{{ "min": {min:?}, "max": {max:?}, "faces": {{ {faces} }} }}] }}"#)
+    }
+
+    #[test]
+    fn parses_boxes_with_textures_uvs_and_rotations() {
+        let json = single_box_model(
+            [0, 0, 0],
+            [8, 4, 8],
+            r#"{ "texture": "wood", "uv": [0, 4, 8, 8], "rotation": 90 }"#,
+        );
+        let boxes = parse_micro_voxel_model(&json).unwrap();
+
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(boxes[0].min, UVec3::ZERO);
+        assert_eq!(boxes[0].max, UVec3::new(8, 4, 8));
+        assert_eq!(
+            boxes[0].faces[0],
+            BLOCK_TEXTURES
+                .face("wood")
+                .with_uv_rotation(UvRotation::Clockwise90)
+        );
+        assert_eq!(
+            boxes[0].uvs[0],
+            Some(MicroVoxelUv {
+                min: UVec2::new(0, 4),
+                max: UVec2::new(8, 8),
+            })
+        );
+
+        // the region and rotation are optional
+        let json = single_box_model([0, 0, 0], [8, 8, 8], r#"{ "texture": "dirt" }"#);
+        let boxes = parse_micro_voxel_model(&json).unwrap();
+        assert_eq!(boxes[0].faces, [BLOCK_TEXTURES.face("dirt"); 6]);
+        assert_eq!(boxes[0].uvs, [None; 6]);
+
+        // textures can also be given by their layer, i.e. their tile index in the atlas
+        let json = single_box_model([0, 0, 0], [8, 8, 8], r#"{ "texture": 3 }"#);
+        let boxes = parse_micro_voxel_model(&json).unwrap();
+        assert_eq!(boxes[0].faces, [BLOCK_TEXTURES.face("wood"); 6]);
+    }
+
+    #[test]
+    fn rejects_invalid_models() {
+        let is_invalid = |min, max, face| {
+            parse_micro_voxel_model(&single_box_model(min, max, face)).is_err()
+        };
+        let wood = r#"{ "texture": "wood" }"#;
+
+        assert!(is_invalid([0, 0, 0], [8, 9, 8], wood));
+        assert!(is_invalid([0, 4, 0], [8, 4, 8], wood));
+        assert!(is_invalid([0, 0, 0], [8, 8, 8], r#"{ "texture": "stone" }"#));
+        assert!(is_invalid([0, 0, 0], [8, 8, 8], r#"{ "texture": 100 }"#));
+        assert!(is_invalid([0, 0, 0], [8, 8, 8], r#"{ "texture": "wood", "rotation": 45 }"#));
+        assert!(is_invalid([0, 0, 0], [8, 8, 8], r#"{ "texture": "wood", "uv": [0, 0, 9, 8] }"#));
+        assert!(is_invalid([0, 0, 0], [8, 8, 8], r#"{ "texture": "wood", "tint": 1 }"#));
+        assert!(parse_micro_voxel_model("{ \"boxes\": [{}] }").is_err());
+    }
+
+    #[test]
+    fn missing_model_files_are_reported_with_their_path() {
+        let error = load_micro_voxel_model("no_such_model").unwrap_err();
+        let ModelFileError::Io(path, _) = error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(path, model_path("no_such_model"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Names of the textures used by block faces, and the layers they occupy in the terrain texture
+//! array
+
+use std::path::PathBuf;
+
+use glam::UVec2;
+
+use super::model::BlockFace;
+
+/// Directory the block textures are loaded from
+pub const BLOCK_TEXTURE_DIRECTORY: &str = "assets/image/block";
+
+/// Atlas image that the block textures are loaded from instead of `BLOCK_TEXTURE_DIRECTORY`, if
+/// it exists. Its tiles are indexed left to right and then top to bottom, and tile n is the
+/// texture of layer n
+pub const BLOCK_TEXTURE_ATLAS_PATH: &str = "assets/image/block_atlas.png";
+
+/// Size in pixels of each tile of `BLOCK_TEXTURE_ATLAS_PATH`
+pub const BLOCK_TEXTURE_TILE_SIZE: UVec2 = UVec2::splat(16);
+
+/// Every block texture, in the order of their layers in the terrain texture array
+pub const BLOCK_TEXTURES: BlockTextures = BlockTextures {
+    names: &[
+        "dirt",
+        "grass_side",
+        "grass_top",
+        "wood",
+        "lamp_orange",
+        "leaves",
+        "coal_ore",
+        "bedrock",
+        "glass",
+        "tall_grass",
+    ],
+};
+
+/// Maps texture names to layers of the terrain texture array. Each texture is loaded from
+/// `<BLOCK_TEXTURE_DIRECTORY>/<name>.png`
+#[derive(Clone, Copy, Debug)]
+pub struct BlockTextures {
+    names: &'static [&'static str],
+}
+
+impl BlockTextures {
+    /// Layer of the texture with the given name.
+    /// Panics if there is no such texture, so a typo in `BLOCKS` fails as soon as it is built
+    pub const fn layer(&self, name: &str) -> usize {
+        match self.find_layer(name) {
+            Some(layer) => layer,
+            None => panic!("no block texture with this name"),
+        }
+    }
+
+    /// Layer of the texture with the given name, or None if there is no such texture
+    pub const fn find_layer(&self, name: &str) -> Option<usize> {
+        let mut layer = 0;
+
+        while layer < self.names.len() {
+            if str_eq(self.names[layer], name) {
+                return Some(layer);
+            }
+            layer += 1;
+        }
+
+        None
+    }
+
+    /// Untinted face with the texture at the given layer, which is its tile index when the
+    /// textures are loaded from an atlas, or None if there is no such layer
+    pub const fn face_at(&self, layer: usize) -> Option<BlockFace> {
+        if layer < self.names.len() {
+            Some(BlockFace::new(layer))
+        } else {
+            None
+        }
+    }
+
+    /// Untinted face with the texture with the given name
+    pub const fn face(&self, name: &str) -> BlockFace {
+        BlockFace::new(self.layer(name))
+    }
+
+    /// Paths of the texture images in layer order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.names
+            .iter()
+            .map(|name| PathBuf::from(BLOCK_TEXTURE_DIRECTORY).join(format!("{name}.png")))
+            .collect()
+    }
+}
+
+/// String equality usable in const fns
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{block::BLOCKS, util::face::FaceIndex};
+
+    #[test]
+    fn texture_names_are_unique_and_have_images() {
+        assert!(BLOCK_TEXTURES.names.iter().all_unique());
+
+        for path in BLOCK_TEXTURES.paths() {
+            assert!(path.is_file(), "missing block texture {}", path.display());
+        }
+    }
+
+    #[test]
+    fn layers_follow_the_order_of_the_names() {
+        assert_eq!(BLOCK_TEXTURES.layer("dirt"), 0);
+        assert_eq!(BLOCK_TEXTURES.layer("tall_grass"), BLOCK_TEXTURES.names.len() - 1);
+        assert_eq!(BLOCK_TEXTURES.face("grass_top").texture_index, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_texture_names_panic() {
+        BLOCK_TEXTURES.layer("grass");
+    }
+
+    #[test]
+    fn block_faces_use_loaded_layers() {
+        for block in BLOCKS.iter() {
+            for face in (0..6).filter_map(|i| block.model.face(FaceIndex(i))) {
+                assert!(face.texture_index < BLOCK_TEXTURES.names.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::terrain::position_types::GlobalBlockPosition;
+
+/// Number of destruction stage textures shown while a block is being broken
+pub const DESTROY_STAGE_COUNT: u32 = 10;
+
+/// Time taken for the destruction overlay of an abandoned target to fade out, in seconds
+pub const BREAK_FADE_OUT_SECONDS: f32 = 0.25;
+
+/// Destruction overlay to draw over a block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BreakOverlay {
+    pub pos: GlobalBlockPosition,
+    /// Index of the destruction stage texture, from 0 to `DESTROY_STAGE_COUNT - 1`
+    pub stage: u32,
+    pub opacity: f32,
+}
+
+/// Block that is being broken, or was being broken and is fading out
+#[derive(Clone, Copy, Debug)]
+struct BreakTarget {
+    pos: GlobalBlockPosition,
+    /// Time taken to break the block in seconds, or infinity if it can't be broken
+    hardness: f32,
+    /// Time spent breaking the block so far in seconds
+    elapsed: f32,
+}
+
+impl BreakTarget {
+    fn progress(&self) -> f32 {
+        if self.hardness > 0.0 {
+            (self.elapsed / self.hardness).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
+    fn stage(&self) -> u32 {
+        ((self.progress() * DESTROY_STAGE_COUNT as f32) as u32).min(DESTROY_STAGE_COUNT - 1)
+    }
+}
+
+/// Tracks how far the player has got breaking the block they are looking at. Blocks take their
+/// hardness in seconds to break, and progress is lost when the target changes
+#[derive(Clone, Debug, Default)]
+pub struct BlockBreaking {
+    target: Option<BreakTarget>,
+    /// Previous target and the opacity of its overlay, which fades out after the target changes
+    fading: Option<(BreakTarget, f32)>,
+}
+
+impl BlockBreaking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called once per frame with the position and hardness of the block being broken, or None if
+    /// the player is not breaking a block. Returns the position of the block once it is broken
+    pub fn update(
+        &mut self,
+        target: Option<(GlobalBlockPosition, f32)>,
+        delta_seconds: f32,
+    ) -> Option<GlobalBlockPosition> {
+        // fade out the overlay of the previous target
+        if let Some((_, opacity)) = &mut self.fading {
+            *opacity -= delta_seconds / BREAK_FADE_OUT_SECONDS;
+            if *opacity <= 0.0 {
+                self.fading = None;
+            }
+        }
+
+        let target_pos = target.map(|(pos, _)| pos);
+        if self.target.map(|target| target.pos) != target_pos {
+            if let Some(previous) = self.target.take() {
+                self.fading = Some((previous, 1.0));
+            }
+
+            self.target = target.map(|(pos, hardness)| BreakTarget {
+                pos,
+                hardness,
+                elapsed: 0.0,
+            });
+        }
+
+        let target = self.target.as_mut()?;
+        target.elapsed += delta_seconds;
+
+        if target.progress() >= 1.0 {
+            let pos = target.pos;
+            self.target = None;
+            Some(pos)
+        } else {
+            None
+        }
+    }
+
+    /// Destruction overlays to draw this frame: one for the target block and one for the
+    /// previous target while it fades out
+    pub fn overlays(&self) -> impl Iterator<Item = BreakOverlay> + '_ {
+        let current = self.target.map(|target| (target, 1.0));
+
+        self.fading
+            .into_iter()
+            .chain(current)
+            .filter(|(target, _)| target.elapsed > 0.0)
+            .map(|(target, opacity)| BreakOverlay {
+                pos: target.pos,
+                stage: target.stage(),
+                opacity,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BLOCKS, BLOCK_BEDROCK};
+
+    #[test]
+    fn stages_advance_with_break_time() {
+        let pos = GlobalBlockPosition::new(1, 2, 3);
+        let mut breaking = BlockBreaking::new();
+
+        assert_eq!(breaking.update(Some((pos, 2.0)), 0.5), None);
+        assert_eq!(breaking.target.unwrap().pos, pos);
+        assert_eq!(breaking.target.unwrap().progress(), 0.25);
+        assert_eq!(breaking.overlays().next().unwrap().stage, 2);
+
+        assert_eq!(breaking.update(Some((pos, 2.0)), 1.25), None);
+        assert_eq!(breaking.overlays().next().unwrap().stage, 8);
+
+        assert_eq!(breaking.update(Some((pos, 2.0)), 0.25), Some(pos));
+        assert!(breaking.target.is_none());
+        assert_eq!(breaking.overlays().count(), 0);
+    }
+
+    #[test]
+    fn changing_target_resets_progress_and_fades_out() {
+        let first = GlobalBlockPosition::new(0, 0, 0);
+        let second = GlobalBlockPosition::new(0, 1, 0);
+        let mut breaking = BlockBreaking::new();
+
+        breaking.update(Some((first, 1.0)), 0.5);
+        breaking.update(Some((second, 1.0)), 0.1);
+        assert_eq!(breaking.target.unwrap().pos, second);
+        assert!((breaking.target.unwrap().progress() - 0.1).abs() < 1e-6);
+
+        let overlays: Vec<_> = breaking.overlays().collect();
+        assert_eq!(overlays.len(), 2);
+        assert_eq!(overlays[0].pos, first);
+        assert_eq!(overlays[0].stage, 5);
+        assert_eq!(overlays[0].opacity, 1.0);
+
+        // releasing the button fades out the second target too
+        breaking.update(None, BREAK_FADE_OUT_SECONDS * 0.5);
+        let overlays: Vec<_> = breaking.overlays().collect();
+        assert_eq!(overlays.len(), 1);
+        assert_eq!(overlays[0].pos, second);
+
+        breaking.update(None, BREAK_FADE_OUT_SECONDS);
+        assert_eq!(breaking.overlays().count(), 0);
+    }
+
+    #[test]
+    fn bedrock_is_never_broken() {
+        let pos = GlobalBlockPosition::new(0, -128, 0);
+        let hardness = BLOCKS[BLOCK_BEDROCK.0 as usize].hardness;
+        let mut breaking = BlockBreaking::new();
+
+        for _ in 0..100 {
+            assert_eq!(breaking.update(Some((pos, hardness)), 10.0), None);
+        }
+        assert_eq!(breaking.target.unwrap().pos, pos);
+        assert_eq!(breaking.target.unwrap().progress(), 0.0);
+    }
+}
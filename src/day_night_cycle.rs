@@ -0,0 +1,185 @@
+use std::f32::consts::TAU;
+
+use glam::Vec3;
+
+use crate::{
+    render::{render_engine::RenderEngine, render_pass::Pass},
+    time::Time,
+};
+
+/// Default length of a full day and night, in seconds
+pub const DEFAULT_DAY_LENGTH: f32 = 600.0;
+
+/// Time of day when the game starts, in the morning
+const INITIAL_TIME_OF_DAY: f32 = 0.3;
+
+/// Tilt of the sun's path across the sky towards +z, so that the sides of blocks facing along
+/// the z axis are shaded differently even at noon
+const SUN_PATH_TILT: f32 = 0.5;
+
+/// Height of the sun above the horizon, as the y component of its direction before tilting, over
+/// which day turns into night
+const TWILIGHT_HEIGHTS: (f32, f32) = (-0.1, 0.2);
+
+const NIGHT_SKY_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.01,
+    g: 0.012,
+    b: 0.03,
+    a: 1.0,
+};
+
+/// Colour the sky is tinted towards while the sun is near the horizon
+const SUNSET_SKY_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.9,
+    g: 0.45,
+    b: 0.25,
+    a: 1.0,
+};
+
+/// Advances the time of day and works out the sun direction, skylight brightness and sky colour
+/// for it
+#[derive(Clone, Debug)]
+pub struct DayNightCycle {
+    /// From 0 to 1, where 0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+    time_of_day: f32,
+    /// Length of a full day and night, in seconds
+    day_length: f32,
+}
+
+impl DayNightCycle {
+    pub fn new(day_length: f32) -> Self {
+        Self {
+            time_of_day: INITIAL_TIME_OF_DAY,
+            day_length: day_length.max(1.0),
+        }
+    }
+
+    /// Advance the time of day by the duration of the previous frame, so that it stands still
+    /// while the simulation is paused
+    pub fn update(&mut self, time: &Time) {
+        self.advance(time.delta_seconds());
+    }
+
+    /// Advance the time of day by the given number of seconds
+    pub fn advance(&mut self, seconds: f32) {
+        self.set_time_of_day(self.time_of_day + seconds / self.day_length);
+    }
+
+    /// From 0 to 1, where 0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    /// Set the time of day, wrapping it into 0..1
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.0);
+    }
+
+    /// Unit vector pointing towards the sun. At night the moon takes its place, following the
+    /// same path mirrored above the horizon, so that the terrain is never lit from below
+    pub fn sun_direction(&self) -> Vec3 {
+        let (height, east) = self.sun_angle().sin_cos();
+
+        Vec3::new(east, height.abs(), SUN_PATH_TILT).normalize()
+    }
+
+    /// Brightness of the skylight, from 0 at night to 1 during the day
+    pub fn day_fraction(&self) -> f32 {
+        let (night, day) = TWILIGHT_HEIGHTS;
+        let t = ((self.sun_height() - night) / (day - night)).clamp(0.0, 1.0);
+
+        // smoothstep, so that the light doesn't visibly start and stop changing
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Colour of the sky, fading from the day colour through a sunset tint to the night colour
+    pub fn sky_color(&self) -> wgpu::Color {
+        let sky_color = mix_colors(NIGHT_SKY_COLOR, Pass::DEFAULT_SKY_COLOR, self.day_fraction());
+
+        // the tint is strongest with the sun on the horizon
+        let (night, day) = TWILIGHT_HEIGHTS;
+        let sunset = 1.0 - (self.sun_height().abs() / (day - night)).min(1.0);
+
+        mix_colors(sky_color, SUNSET_SKY_COLOR, 0.5 * sunset * sunset)
+    }
+
+    /// Push the sun direction, skylight brightness and sky colour for the current time of day to
+    /// the render engine
+    pub fn apply(&self, render_engine: &mut RenderEngine) {
+        render_engine.set_sun_direction(self.sun_direction());
+        render_engine.set_day_fraction(self.day_fraction());
+        render_engine.set_sky_color(self.sky_color());
+    }
+
+    /// Angle of the sun around its path, zero at sunrise
+    fn sun_angle(&self) -> f32 {
+        (self.time_of_day - 0.25) * TAU
+    }
+
+    /// Height of the sun above the horizon, from -1 at midnight to 1 at noon
+    fn sun_height(&self) -> f32 {
+        self.sun_angle().sin()
+    }
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        Self::new(DEFAULT_DAY_LENGTH)
+    }
+}
+
+/// Linearly interpolate between two colours
+fn mix_colors(a: wgpu::Color, b: wgpu::Color, t: f32) -> wgpu::Color {
+    let t = t as f64;
+    let mix = |a: f64, b: f64| a + (b - a) * t;
+
+    wgpu::Color {
+        r: mix(a.r, b.r),
+        g: mix(a.g, b.g),
+        b: mix(a.b, b.b),
+        a: mix(a.a, b.a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_of_day_wraps_around_each_day() {
+        let mut cycle = DayNightCycle::new(100.0);
+        cycle.set_time_of_day(0.5);
+
+        cycle.advance(25.0);
+        assert!((cycle.time_of_day() - 0.75).abs() < 1e-5);
+        cycle.advance(50.0);
+        assert!((cycle.time_of_day() - 0.25).abs() < 1e-5);
+
+        cycle.set_time_of_day(-0.25);
+        assert!((cycle.time_of_day() - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn noon_is_bright_and_midnight_is_dark() {
+        let mut cycle = DayNightCycle::default();
+
+        cycle.set_time_of_day(0.5);
+        assert_eq!(cycle.day_fraction(), 1.0);
+        assert_eq!(cycle.sky_color(), Pass::DEFAULT_SKY_COLOR);
+        let noon_sun = cycle.sun_direction();
+        assert!(noon_sun.y > noon_sun.x.abs());
+
+        cycle.set_time_of_day(0.0);
+        assert_eq!(cycle.day_fraction(), 0.0);
+        assert_eq!(cycle.sky_color(), NIGHT_SKY_COLOR);
+
+        // the sun rises in the east and sets in the west, and the terrain is always lit from above
+        for (time_of_day, east) in [(0.3, true), (0.7, false), (0.9, false), (0.1, true)] {
+            cycle.set_time_of_day(time_of_day);
+            let sun_direction = cycle.sun_direction();
+            assert_eq!(sun_direction.x > 0.0, east, "{time_of_day}");
+            assert!(sun_direction.y > 0.0);
+            assert!(sun_direction.is_normalized());
+        }
+    }
+}
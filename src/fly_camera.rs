@@ -1,82 +1,494 @@
-use glam::{EulerRot, Quat, Vec3};
-use winit::keyboard::KeyCode;
+use std::f32::consts::FRAC_PI_2;
 
-use crate::{input::Input, time::Time, util::transform::Transform};
+use glam::{IVec3, Quat, Vec2, Vec3, Vec3Swizzles};
+
+use crate::{
+    input::{Action, Axis, Input},
+    time::Time,
+    util::transform::Transform,
+};
 
 pub const DEFAULT_SPEED: f32 = 10.0;
 pub const DEFAULT_SENSITIVITY: f32 = 0.01;
+/// Factor the movement speed is multiplied by while sprinting
+pub const DEFAULT_SPRINT_MULTIPLIER: f32 = 2.0;
+/// Fastest that the camera turns with the right stick of a gamepad, in radians per second
+pub const GAMEPAD_LOOK_SPEED: f32 = 3.0;
+
+/// Half of the width of the player's bounding box
+pub const PLAYER_HALF_WIDTH: f32 = 0.3;
+/// Height of the camera above the bottom of the player's bounding box
+pub const PLAYER_EYE_HEIGHT: f32 = 1.62;
+/// Height of the player's bounding box
+pub const PLAYER_HEIGHT: f32 = 1.8;
+
+/// Horizontal speed in walk mode, in blocks per second
+pub const WALK_SPEED: f32 = 4.3;
+/// Downwards acceleration in walk mode, in blocks per second squared
+pub const GRAVITY: f32 = 28.0;
+/// Fastest that the player can fall, in blocks per second
+pub const TERMINAL_VELOCITY: f32 = 60.0;
+/// Upwards velocity at the start of a jump, in blocks per second. Enough to jump onto a block
+pub const JUMP_SPEED: f32 = 9.0;
+/// Highest ledge that the player walks onto without jumping
+pub const STEP_HEIGHT: f32 = 1.0;
+/// Longest time step simulated in walk mode, so that a long frame can't launch the player
+const MAX_WALK_TIME_STEP: f32 = 0.1;
+/// Gap kept between the player and the blocks they collide with
+const COLLISION_EPSILON: f32 = 1e-4;
+/// Steepest that the camera can look up or down, in radians. Kept just short of straight up or
+/// down, where the camera would flip over
+const MAX_PITCH: f32 = FRAC_PI_2 - 1e-3;
+
+/// Minimum and maximum corners of an axis-aligned box
+pub type Aabb = (Vec3, Vec3);
 
 #[derive(Clone, Debug)]
 pub struct FlyCamera {
     pub position: Vec3,
+    /// Position before the last fixed update step, which the rendered position is interpolated
+    /// from
+    pub previous_position: Vec3,
     pub yaw: f32,
     pub pitch: f32,
+    /// Speed in no-clip mode, in blocks per second
     pub speed: f32,
+    /// Radians turned per pixel of mouse movement
     pub sensitivity: f32,
-    pub key_forward: KeyCode,
-    pub key_backward: KeyCode,
-    pub key_right: KeyCode,
-    pub key_left: KeyCode,
-    pub key_up: KeyCode,
-    pub key_down: KeyCode,
+    /// Whether moving the mouse or right stick up looks down
+    pub invert_y: bool,
+    /// Factor the movement speed is multiplied by while the sprint action is held
+    pub sprint_multiplier: f32,
+    /// Whether the camera flies through blocks. Otherwise it walks, falling under gravity and
+    /// colliding with blocks
+    pub no_clip: bool,
+    /// Velocity in walk mode, in blocks per second. Only the vertical component carries over
+    /// between frames
+    pub velocity: Vec3,
+    /// Whether the player is standing on a block in walk mode
+    pub on_ground: bool,
 }
 
 impl FlyCamera {
     pub fn get_transform(&self) -> Transform {
+        let mut transform = self.heading_transform();
+        transform.rotate_local(Quat::from_rotation_x(self.pitch));
+        transform
+    }
+
+    /// Transform at the camera's position facing its yaw, ignoring the pitch, whose axes give the
+    /// horizontal directions of movement
+    fn heading_transform(&self) -> Transform {
         Transform {
             translation: self.position,
-            scale: Vec3::ONE,
-            rotation: Quat::from_euler(EulerRot::ZYX, 0.0, self.yaw, self.pitch),
+            rotation: Quat::from_rotation_y(self.yaw),
+            ..Transform::IDENTITY
         }
     }
 
-    pub fn update(&mut self, input: &Input, time: &Time) {
-        // movement
-        let input_forward = axis_input(input, self.key_forward, self.key_backward);
-        let input_right = axis_input(input, self.key_right, self.key_left);
-        let input_up = axis_input(input, self.key_up, self.key_down);
+    /// Turn to face `target`, keeping the pitch within `MAX_PITCH` of level. No-op if `target` is
+    /// at the camera's position or straight above or below it
+    pub fn look_at(&mut self, target: Vec3) {
+        let mut transform = self.get_transform();
+        transform.look_at(target, Vec3::Y);
 
-        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let right = transform.right();
+        self.yaw = (-right.z).atan2(right.x);
+        self.pitch = transform
+            .forward()
+            .y
+            .atan2(transform.up().y)
+            .clamp(-MAX_PITCH, MAX_PITCH);
+    }
 
-        let dir_forward = Vec3::new(-sin_yaw, 0.0, -cos_yaw);
-        let dir_right = Vec3::new(cos_yaw, 0.0, -sin_yaw);
-        const DIR_UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    /// Transform with the position interpolated between the last two fixed update steps, where
+    /// `alpha` is `Time::fixed_alpha`
+    pub fn interpolated_transform(&self, alpha: f32) -> Transform {
+        let current = self.get_transform();
 
-        let speed = self.speed * time.delta_seconds();
+        // the rotation is updated every frame, so only the position is interpolated
+        let previous = Transform {
+            translation: self.previous_position,
+            ..current
+        };
+
+        previous.interpolate(&current, alpha)
+    }
+
+    /// Returns the minimum and maximum corners of the player's bounding box, or None in no-clip
+    /// mode
+    pub fn collision_box(&self) -> Option<Aabb> {
+        (!self.no_clip).then_some(self.player_box())
+    }
+
+    /// Switch between flying through blocks and walking
+    pub fn set_no_clip(&mut self, no_clip: bool) {
+        self.no_clip = no_clip;
+        self.velocity = Vec3::ZERO;
+        self.on_ground = false;
+    }
 
-        self.position += dir_forward * input_forward * speed;
-        self.position += dir_right * input_right * speed;
-        self.position += DIR_UP * input_up * speed;
+    /// Move and rotate the camera according to the input. The camera moves once for each fixed
+    /// update step this frame, so that walking doesn't depend on the frame rate, and rotates
+    /// every frame.
+    /// `block_box` returns the bounding box of the block at the given position relative to the
+    /// block, or None if the block can be walked through. It is only used in walk mode
+    pub fn update(
+        &mut self,
+        input: &Input,
+        time: &Time,
+        block_box: impl Fn(IVec3) -> Option<Aabb>,
+    ) {
+        time.for_each_fixed_step(|dt| self.fixed_update(input, dt, &block_box));
 
         // rotation
-        let rotate_amount = input.mouse_delta_f32();
-        self.yaw -= self.sensitivity * rotate_amount.x;
-        self.pitch -= self.sensitivity * rotate_amount.y;
+        let invert_y = if self.invert_y { -1.0 } else { 1.0 };
+
+        // the mouse moving down the screen gives a positive y delta
+        let mouse_delta = self.sensitivity * input.mouse_delta_f32();
+        self.turn(mouse_delta.x, -invert_y * mouse_delta.y);
+
+        let look_stick = Vec2::new(input.axis(Axis::RightStickX), input.axis(Axis::RightStickY));
+        let look_amount = GAMEPAD_LOOK_SPEED * time.delta_seconds() * look_stick;
+        self.turn(look_amount.x, invert_y * look_amount.y);
+    }
+
+    /// Turn the camera right and up by the given angles in radians, keeping the pitch within
+    /// `MAX_PITCH` of level
+    fn turn(&mut self, right: f32, up: f32) {
+        self.yaw -= right;
+        self.pitch = (self.pitch + up).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Move the camera according to the input by one fixed update step of `dt` seconds
+    fn fixed_update(
+        &mut self,
+        input: &Input,
+        dt: f32,
+        block_box: &impl Fn(IVec3) -> Option<Aabb>,
+    ) {
+        self.previous_position = self.position;
+
+        let Vec2 {
+            x: input_right,
+            y: input_forward,
+        } = movement_input(input);
+        let input_up = axis_input(input, Action::MoveUp, Action::MoveDown);
+
+        let speed_multiplier = if input.is_action_pressed(Action::Sprint) {
+            self.sprint_multiplier
+        } else {
+            1.0
+        };
+
+        // move in the horizontal plane regardless of pitch
+        let mut heading = self.heading_transform();
+
+        if self.no_clip {
+            let speed = self.speed * speed_multiplier * dt;
+
+            let local_movement = Vec3::new(input_right, input_up, -input_forward);
+            heading.translate_local(local_movement * speed);
+            self.position = heading.translation;
+        } else {
+            let walk_direction = (heading.right() * input_right
+                + heading.forward() * input_forward)
+                .clamp_length_max(1.0)
+                * speed_multiplier;
+            let jump = input.is_action_pressed(Action::MoveUp);
+
+            self.walk(walk_direction, jump, dt, block_box);
+        }
+    }
+
+    /// Advance walk mode by `dt` seconds, walking in the given horizontal direction at
+    /// `WALK_SPEED` times its length
+    fn walk(
+        &mut self,
+        walk_direction: Vec3,
+        jump: bool,
+        dt: f32,
+        block_box: &impl Fn(IVec3) -> Option<Aabb>,
+    ) {
+        let dt = dt.min(MAX_WALK_TIME_STEP);
+
+        if jump && self.on_ground {
+            self.velocity.y = JUMP_SPEED;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+        self.velocity.x = walk_direction.x * WALK_SPEED;
+        self.velocity.z = walk_direction.z * WALK_SPEED;
+
+        let delta = self.velocity * dt;
+        let player_box = self.player_box();
+        let mut moved = sweep(player_box, delta, block_box);
+
+        // if a wall stopped the player on the ground, try stepping up onto it
+        let blocked = moved.x != delta.x || moved.z != delta.z;
+        if blocked && self.on_ground {
+            let up = sweep(player_box, Vec3::Y * STEP_HEIGHT, block_box);
+            let raised_box = translate(player_box, up);
+            let across = sweep(raised_box, Vec3::new(delta.x, 0.0, delta.z), block_box);
+            let down = sweep(
+                translate(raised_box, across),
+                Vec3::new(0.0, delta.y.min(0.0) - up.y, 0.0),
+                block_box,
+            );
+            let stepped = up + across + down;
+
+            if stepped.xz().length_squared() > moved.xz().length_squared() {
+                moved = stepped;
+            }
+        }
+
+        // stop falling or rising when a block is hit
+        self.on_ground = delta.y < 0.0 && moved.y > delta.y;
+        if moved.y != delta.y {
+            self.velocity.y = 0.0;
+        }
+
+        self.position += moved;
+    }
+
+    /// Minimum and maximum corners of the player's bounding box
+    fn player_box(&self) -> Aabb {
+        let min = self.position - Vec3::new(PLAYER_HALF_WIDTH, PLAYER_EYE_HEIGHT, PLAYER_HALF_WIDTH);
+        let max = min + Vec3::new(2.0 * PLAYER_HALF_WIDTH, PLAYER_HEIGHT, 2.0 * PLAYER_HALF_WIDTH);
+
+        (min, max)
+    }
+}
+
+/// Move the box by `delta`, stopping along each axis where it would enter a block. The axes are
+/// resolved one at a time, vertical first, so that the box slides along walls and corners rather
+/// than sticking to them. Every block that the box sweeps through is checked, so that it can't
+/// pass through thin walls however far it moves.
+/// Returns the movement that was possible
+pub fn sweep(aabb: Aabb, delta: Vec3, block_box: &impl Fn(IVec3) -> Option<Aabb>) -> Vec3 {
+    let mut moved = Vec3::ZERO;
+
+    for axis in [1, 0, 2] {
+        let axis_delta = clip_axis(translate(aabb, moved), axis, delta[axis], block_box);
+        moved[axis] = axis_delta;
+    }
+
+    moved
+}
+
+/// Returns how far the box can move along the axis towards `delta` before entering a block.
+/// Blocks that the box already overlaps are ignored, so that it can move out of them
+fn clip_axis(
+    (min, max): Aabb,
+    axis: usize,
+    mut delta: f32,
+    block_box: &impl Fn(IVec3) -> Option<Aabb>,
+) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
 
-        self.pitch = self
-            .pitch
-            .clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+    // the blocks that the box passes through
+    let mut region_min = min;
+    let mut region_max = max;
+    region_min[axis] += delta.min(0.0);
+    region_max[axis] += delta.max(0.0);
+
+    let block_min = region_min.floor().as_ivec3();
+    let block_max = region_max.ceil().as_ivec3() - IVec3::ONE;
+
+    for (x, y, z) in itertools::iproduct!(
+        block_min.x..=block_max.x,
+        block_min.y..=block_max.y,
+        block_min.z..=block_max.z,
+    ) {
+        let block_pos = IVec3::new(x, y, z);
+        let Some((local_min, local_max)) = block_box(block_pos) else {
+            continue;
+        };
+        let other_min = block_pos.as_vec3() + local_min;
+        let other_max = block_pos.as_vec3() + local_max;
+
+        // only blocks level with the box along the other two axes are in the way
+        let overlaps_other_axes = (0..3).filter(|&other| other != axis).all(|other| {
+            min[other] < other_max[other] - COLLISION_EPSILON
+                && max[other] > other_min[other] + COLLISION_EPSILON
+        });
+        if !overlaps_other_axes {
+            continue;
+        }
+
+        if delta > 0.0 && max[axis] <= other_min[axis] + COLLISION_EPSILON {
+            delta = delta.min(other_min[axis] - max[axis] - COLLISION_EPSILON).max(0.0);
+        } else if delta < 0.0 && min[axis] >= other_max[axis] - COLLISION_EPSILON {
+            delta = delta.max(other_max[axis] - min[axis] + COLLISION_EPSILON).min(0.0);
+        }
     }
+
+    delta
+}
+
+fn translate((min, max): Aabb, offset: Vec3) -> Aabb {
+    (min + offset, max + offset)
 }
 
 impl Default for FlyCamera {
     fn default() -> Self {
         Self {
             position: Vec3::ZERO,
+            previous_position: Vec3::ZERO,
             yaw: 0.0,
             pitch: 0.0,
             speed: DEFAULT_SPEED,
             sensitivity: DEFAULT_SENSITIVITY,
-            key_forward: KeyCode::KeyW,
-            key_backward: KeyCode::KeyS,
-            key_right: KeyCode::KeyD,
-            key_left: KeyCode::KeyA,
-            key_up: KeyCode::Space,
-            key_down: KeyCode::ShiftLeft,
+            invert_y: false,
+            sprint_multiplier: DEFAULT_SPRINT_MULTIPLIER,
+            no_clip: true,
+            velocity: Vec3::ZERO,
+            on_ground: false,
         }
     }
 }
 
-fn axis_input(input: &Input, key_pos: KeyCode, key_neg: KeyCode) -> f32 {
-    (input.is_key_down(key_pos) as i32 - input.is_key_down(key_neg) as i32) as f32
+/// Horizontal movement, with x to the right and y forwards, read from the left stick of a gamepad
+/// if it is pushed and from the keyboard otherwise
+fn movement_input(input: &Input) -> Vec2 {
+    let stick = Vec2::new(input.axis(Axis::LeftStickX), input.axis(Axis::LeftStickY));
+    if stick != Vec2::ZERO {
+        return stick;
+    }
+
+    Vec2::new(
+        axis_input(input, Action::MoveRight, Action::MoveLeft),
+        axis_input(input, Action::MoveForward, Action::MoveBackward),
+    )
+}
+
+fn axis_input(input: &Input, action_pos: Action, action_neg: Action) -> f32 {
+    (input.is_action_pressed(action_pos) as i32 - input.is_action_pressed(action_neg) as i32) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Full blocks below y = 0 and wherever `wall` is true, air elsewhere
+    fn world(wall: impl Fn(IVec3) -> bool) -> impl Fn(IVec3) -> Option<Aabb> {
+        move |pos| (pos.y < 0 || wall(pos)).then_some((Vec3::ZERO, Vec3::ONE))
+    }
+
+    /// A walking player standing on the ground at the given horizontal position
+    fn walker(x: f32, z: f32) -> FlyCamera {
+        let mut camera = FlyCamera {
+            position: Vec3::new(x, PLAYER_EYE_HEIGHT, z),
+            ..Default::default()
+        };
+        camera.set_no_clip(false);
+        camera
+    }
+
+    #[test]
+    fn player_falls_and_lands_on_the_ground() {
+        let blocks = world(|_| false);
+        let mut camera = walker(0.5, 0.5);
+        camera.position.y += 3.0;
+
+        for _ in 0..60 {
+            camera.walk(Vec3::ZERO, false, 1.0 / 60.0, &blocks);
+        }
+
+        assert!(camera.on_ground);
+        assert!((camera.player_box().0.y - 0.0).abs() < 1e-3);
+        assert_eq!(camera.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn fast_movement_does_not_pass_through_thin_walls() {
+        // a one block thick wall at x = 5
+        let blocks = world(|pos| pos.x == 5);
+        let (min, max) = walker(0.5, 0.5).player_box();
+
+        let moved = sweep((min, max), Vec3::new(100.0, 0.0, 0.0), &blocks);
+
+        assert!((max.x + moved.x - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn diagonal_movement_slides_along_walls_and_corners() {
+        // walls along x = 2 and z = 2, meeting in a corner
+        let blocks = world(|pos| pos.x == 2 || pos.z == 2);
+        let (min, max) = walker(0.5, 0.5).player_box();
+
+        // each axis stops at its own wall, rather than the whole move being cancelled
+        let moved = sweep((min, max), Vec3::new(3.0, -0.5, 0.5), &blocks);
+        assert!((max.x + moved.x - 2.0).abs() < 1e-3);
+        assert_eq!(moved.y, 0.0);
+        assert_eq!(moved.z, 0.5);
+
+        let moved = sweep((min, max), Vec3::new(3.0, 0.0, 3.0), &blocks);
+        assert!((max.x + moved.x - 2.0).abs() < 1e-3);
+        assert!((max.z + moved.z - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn player_steps_up_single_blocks_but_not_walls() {
+        // a step up at x = 1, and a two block wall on top of it at x = 3
+        let blocks = world(|pos| (pos.x >= 1 && pos.y == 0) || (pos.x == 3 && pos.y <= 2));
+        let mut camera = walker(0.5, 0.5);
+
+        for _ in 0..120 {
+            camera.walk(Vec3::X, false, 1.0 / 60.0, &blocks);
+        }
+
+        // on top of the step, against the wall
+        let (min, max) = camera.player_box();
+        assert!(camera.on_ground);
+        assert!((min.y - 1.0).abs() < 1e-3);
+        assert!((max.x - 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn player_jumps_only_from_the_ground() {
+        let blocks = world(|_| false);
+        let mut camera = walker(0.5, 0.5);
+
+        // settle onto the ground, then jump
+        camera.walk(Vec3::ZERO, false, 1.0 / 60.0, &blocks);
+        camera.walk(Vec3::ZERO, true, 1.0 / 60.0, &blocks);
+        assert!(!camera.on_ground);
+        assert!(camera.velocity.y > 0.0);
+
+        // holding jump in the air doesn't jump again
+        let velocity = camera.velocity.y;
+        camera.walk(Vec3::ZERO, true, 1.0 / 60.0, &blocks);
+        assert!(camera.velocity.y < velocity);
+    }
+
+    #[test]
+    fn look_at_turns_the_camera_towards_the_target() {
+        let mut camera = FlyCamera {
+            position: Vec3::new(1.0, 2.0, 3.0),
+            ..Default::default()
+        };
+
+        camera.look_at(Vec3::new(4.0, -2.0, 3.0));
+        let forward = camera.get_transform().forward();
+        assert!(forward.abs_diff_eq(Vec3::new(0.6, -0.8, 0.0), 1e-5), "{forward}");
+
+        // straight up is clamped short of flipping over
+        camera.look_at(camera.position + Vec3::new(0.0, 100.0, -1e-3));
+        assert_eq!(camera.pitch, MAX_PITCH);
+    }
+
+    #[test]
+    fn pitch_stops_short_of_straight_up_and_down() {
+        let mut camera = FlyCamera::default();
+
+        camera.turn(0.0, 1000.0);
+        assert!(camera.pitch < FRAC_PI_2);
+        assert_eq!(camera.pitch, MAX_PITCH);
+
+        camera.turn(0.5, -1000.0);
+        assert!(camera.pitch > -FRAC_PI_2);
+        assert_eq!(camera.yaw, -0.5);
+    }
 }
@@ -0,0 +1,160 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// Environment variable naming a CSV file to record frame metrics to from startup
+pub const FRAME_RECORDING_PATH_VAR: &str = "VOXELS_RECORD_FRAMES";
+
+/// File that frame metrics are recorded to when recording is started with a key
+pub const DEFAULT_FRAME_RECORDING_PATH: &str = "frame_metrics.csv";
+
+/// Number of frames between flushes of the recording to the file
+const FLUSH_INTERVAL_FRAMES: u64 = 120;
+
+/// Columns of the recording, in order
+const HEADER: &str = "frame,frame_ms,render_cpu_ms,terrain_gpu_ms,chunks_loaded,chunks_pending,\
+                      chunks_visible,batches_drawn,triangles_drawn,meshes_built";
+
+/// Metrics gathered over one frame from the stats of the terrain, renderer and tasks
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameMetrics {
+    /// Time between the start of the previous frame and this one
+    pub frame_time: Duration,
+    /// Time spent on the CPU recording and submitting the frame's render passes
+    pub render_time: Duration,
+    /// Latest GPU time of the terrain pass, which lags a few frames behind. None if the device
+    /// doesn't support timestamp queries, in which case the column is left empty
+    pub terrain_gpu_time: Option<Duration>,
+    pub chunks_loaded: usize,
+    /// Chunks that are generating or meshing
+    pub chunks_pending: usize,
+    pub chunks_visible: usize,
+    pub batches_drawn: usize,
+    pub triangles_drawn: usize,
+    /// Total number of chunk meshes built since startup. The recording holds the number built
+    /// during each frame
+    pub meshes_built_total: usize,
+}
+
+/// Writes the metrics of each frame to a CSV file with a header row, for analysing stutter
+/// offline, e.g. in a spreadsheet. Rows are buffered and flushed every `FLUSH_INTERVAL_FRAMES`
+/// frames and when recording stops
+pub struct FrameRecorder<W: Write> {
+    writer: BufWriter<W>,
+    /// Index of the next frame recorded, starting from 0 when recording starts
+    frame_index: u64,
+    /// Value of `FrameMetrics::meshes_built_total` in the previous frame
+    last_meshes_built_total: Option<usize>,
+}
+
+impl FrameRecorder<File> {
+    /// Start recording to the file at the given path, replacing it if it exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+}
+
+impl<W: Write> FrameRecorder<W> {
+    /// Start recording to `writer`, writing the header row
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut writer = BufWriter::new(writer);
+        writeln!(writer, "{HEADER}")?;
+
+        Ok(Self {
+            writer,
+            frame_index: 0,
+            last_meshes_built_total: None,
+        })
+    }
+
+    /// Append a row for the next frame
+    pub fn record(&mut self, metrics: &FrameMetrics) -> io::Result<()> {
+        let meshes_built = self
+            .last_meshes_built_total
+            .map_or(0, |last| metrics.meshes_built_total.saturating_sub(last));
+        self.last_meshes_built_total = Some(metrics.meshes_built_total);
+
+        let terrain_gpu_ms = metrics
+            .terrain_gpu_time
+            .map_or(String::new(), |time| format!("{:.3}", time.as_secs_f64() * 1000.0));
+
+        writeln!(
+            self.writer,
+            "{},{:.3},{:.3},{},{},{},{},{},{},{}",
+            self.frame_index,
+            metrics.frame_time.as_secs_f64() * 1000.0,
+            metrics.render_time.as_secs_f64() * 1000.0,
+            terrain_gpu_ms,
+            metrics.chunks_loaded,
+            metrics.chunks_pending,
+            metrics.chunks_visible,
+            metrics.batches_drawn,
+            metrics.triangles_drawn,
+            meshes_built,
+        )?;
+
+        self.frame_index += 1;
+        if self.frame_index.is_multiple_of(FLUSH_INTERVAL_FRAMES) {
+            self.writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of frames recorded so far
+    pub fn frame_count(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Stop recording, flushing any buffered rows, and return the writer
+    pub fn finish(self) -> io::Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_follow_the_header_with_increasing_frame_indices() {
+        let mut recorder = FrameRecorder::new(Vec::new()).unwrap();
+
+        for (meshes_built_total, frame_ms, terrain_gpu_time) in [
+            (10, 16, None),
+            (12, 17, Some(Duration::from_micros(2500))),
+            (12, 33, Some(Duration::from_millis(3))),
+        ] {
+            recorder
+                .record(&FrameMetrics {
+                    frame_time: Duration::from_millis(frame_ms),
+                    terrain_gpu_time,
+                    chunks_loaded: 100,
+                    meshes_built_total,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        assert_eq!(recorder.frame_count(), 3);
+
+        let csv = String::from_utf8(recorder.finish().unwrap()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines, [
+            HEADER,
+            "0,16.000,0.000,,100,0,0,0,0,0",
+            "1,17.000,0.000,2.500,100,0,0,0,0,2",
+            "2,33.000,0.000,3.000,100,0,0,0,0,0",
+        ]);
+
+        // every row has a value for each column
+        let column_count = HEADER.split(',').count();
+        assert!(lines
+            .iter()
+            .all(|line| line.split(',').count() == column_count));
+    }
+}
@@ -1,27 +1,231 @@
 use glam::{DVec2, Vec2};
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
 use winit::{
     event::{DeviceEvent, ElementState, KeyEvent, MouseButton, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
 
+/// Fraction of the range of a stick or trigger around its rest position that is ignored, so that
+/// a stick that doesn't quite return to the centre doesn't move the camera
+pub const GAMEPAD_DEADZONE: f32 = 0.15;
+
+/// Logical action performed by the player, which is triggered by whichever key or mouse button
+/// it is bound to in `InputBindings`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    /// Fly up, or jump while walking
+    MoveUp,
+    MoveDown,
+    /// Move faster while held
+    Sprint,
+    /// Switch between flying and walking
+    ToggleFly,
+    /// Release the grabbed cursor
+    ReleaseCursor,
+    /// Hand control of the camera to the mouse and keyboard, or take it back and free the cursor
+    ToggleCameraControl,
+    BreakBlock,
+    PlaceBlock1,
+    PlaceBlock2,
+    PlaceBlock3,
+    PlaceBlock4,
+    PlaceBlock5,
+    PlaceBlock6,
+    PlaceBlock7,
+    PlaceBlock8,
+    PlaceBlock9,
+    PlaceBlock10,
+    PlaceBlock11,
+    PlaceBlock12,
+}
+
+/// Physical key or mouse button that an action is bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    MouseButton(MouseButton),
+}
+
+/// Analog input on a gamepad. Sticks range from -1 to 1, with positive values right and up, and
+/// triggers range from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(not(feature = "gamepad"), allow(unused))]
+pub enum Axis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl Axis {
+    /// X and Y axes of the stick this axis belongs to, or None for triggers
+    fn stick_axes(self) -> Option<(Self, Self)> {
+        match self {
+            Self::LeftStickX | Self::LeftStickY => Some((Self::LeftStickX, Self::LeftStickY)),
+            Self::RightStickX | Self::RightStickY => Some((Self::RightStickX, Self::RightStickY)),
+            Self::LeftTrigger | Self::RightTrigger => None,
+        }
+    }
+}
+
+/// Button on a gamepad, named by its position as the labels differ between controllers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(not(feature = "gamepad"), allow(unused))]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    /// Pressed once the trigger is pulled past a threshold
+    LeftTrigger,
+    /// Pressed once the trigger is pulled past a threshold
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Maps each action to the key or mouse button that triggers it, and optionally a gamepad button
+/// that also triggers it, so that gameplay code never refers to physical keys directly. Actions
+/// without a binding are never triggered
+#[derive(Clone, Debug)]
+pub struct InputBindings {
+    bindings: FxHashMap<Action, Binding>,
+    gamepad_bindings: FxHashMap<Action, GamepadButton>,
+}
+
+impl InputBindings {
+    /// Key or mouse button bound to the action, if any
+    pub fn binding(&self, action: Action) -> Option<Binding> {
+        self.bindings.get(&action).copied()
+    }
+
+    /// Bind the action to the given key or mouse button, replacing its previous binding. Several
+    /// actions may share a binding
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.bindings.insert(action, binding);
+    }
+
+    /// Gamepad button bound to the action, if any
+    pub fn gamepad_binding(&self, action: Action) -> Option<GamepadButton> {
+        self.gamepad_bindings.get(&action).copied()
+    }
+
+    /// Rebind the actions listed in a JSON object mapping action names to keys or mouse buttons,
+    /// named as in winit, e.g. `{ "PlaceBlock1": { "Key": "KeyQ" }, "BreakBlock": { "MouseButton":
+    /// "Right" } }`. Actions that aren't listed keep their bindings. Nothing is rebound if the
+    /// JSON is invalid
+    pub fn rebind_from_json(&mut self, json: &str) -> Result<(), serde_json::Error> {
+        let bindings: FxHashMap<Action, Binding> = serde_json::from_str(json)?;
+
+        for (action, binding) in bindings {
+            self.bind(action, binding);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Action::*;
+
+        let bindings = [
+            (MoveForward, Binding::Key(KeyCode::KeyW)),
+            (MoveBackward, Binding::Key(KeyCode::KeyS)),
+            (MoveLeft, Binding::Key(KeyCode::KeyA)),
+            (MoveRight, Binding::Key(KeyCode::KeyD)),
+            (MoveUp, Binding::Key(KeyCode::Space)),
+            (MoveDown, Binding::Key(KeyCode::ShiftLeft)),
+            (Sprint, Binding::Key(KeyCode::ControlLeft)),
+            (ToggleFly, Binding::Key(KeyCode::KeyF)),
+            (ReleaseCursor, Binding::Key(KeyCode::Escape)),
+            (ToggleCameraControl, Binding::Key(KeyCode::KeyC)),
+            (BreakBlock, Binding::MouseButton(MouseButton::Left)),
+            (PlaceBlock1, Binding::Key(KeyCode::Digit1)),
+            (PlaceBlock2, Binding::Key(KeyCode::Digit2)),
+            (PlaceBlock3, Binding::Key(KeyCode::Digit3)),
+            (PlaceBlock4, Binding::Key(KeyCode::Digit4)),
+            (PlaceBlock5, Binding::Key(KeyCode::Digit5)),
+            (PlaceBlock6, Binding::Key(KeyCode::Digit6)),
+            (PlaceBlock7, Binding::Key(KeyCode::Digit7)),
+            (PlaceBlock8, Binding::Key(KeyCode::Digit8)),
+            (PlaceBlock9, Binding::Key(KeyCode::Digit9)),
+            (PlaceBlock10, Binding::Key(KeyCode::Digit0)),
+            (PlaceBlock11, Binding::Key(KeyCode::Minus)),
+            (PlaceBlock12, Binding::Key(KeyCode::Equal)),
+        ];
+
+        // movement and looking around are read from the sticks instead
+        let gamepad_bindings = [
+            (MoveUp, GamepadButton::South),
+            (MoveDown, GamepadButton::East),
+            (Sprint, GamepadButton::LeftStick),
+            (ToggleFly, GamepadButton::Select),
+            (ReleaseCursor, GamepadButton::Start),
+            (BreakBlock, GamepadButton::RightTrigger),
+            (PlaceBlock1, GamepadButton::LeftTrigger),
+            (PlaceBlock2, GamepadButton::West),
+            (PlaceBlock3, GamepadButton::North),
+            (PlaceBlock4, GamepadButton::RightBumper),
+            (PlaceBlock5, GamepadButton::LeftBumper),
+        ];
+
+        Self {
+            bindings: bindings.into_iter().collect(),
+            gamepad_bindings: gamepad_bindings.into_iter().collect(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Input {
+    bindings: InputBindings,
     keys_held: FxHashSet<KeyCode>,
     keys_held_last_frame: FxHashSet<KeyCode>,
     mouse_buttons_held: FxHashSet<MouseButton>,
     mouse_buttons_held_last_frame: FxHashSet<MouseButton>,
     mouse_delta: DVec2,
+    /// Latest value of each gamepad axis, before the deadzone is applied. Axes only report
+    /// changes, so these are kept between frames
+    gamepad_axes: FxHashMap<Axis, f32>,
+    gamepad_buttons_held: FxHashSet<GamepadButton>,
+    gamepad_buttons_held_last_frame: FxHashSet<GamepadButton>,
+    /// None if gamepads aren't supported on this platform
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
 }
 
 impl Input {
     pub fn new() -> Self {
         Self {
+            bindings: InputBindings::default(),
             keys_held: FxHashSet::default(),
             keys_held_last_frame: FxHashSet::default(),
             mouse_buttons_held: FxHashSet::default(),
             mouse_buttons_held_last_frame: FxHashSet::default(),
             mouse_delta: DVec2::ZERO,
+            gamepad_axes: FxHashMap::default(),
+            gamepad_buttons_held: FxHashSet::default(),
+            gamepad_buttons_held_last_frame: FxHashSet::default(),
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new()
+                .inspect_err(|e| log::warn!("gamepads are unavailable: {e}"))
+                .ok(),
         }
     }
 
@@ -29,9 +233,84 @@ impl Input {
     pub fn reset(&mut self) {
         self.keys_held_last_frame = self.keys_held.clone();
         self.mouse_buttons_held_last_frame = self.mouse_buttons_held.clone();
+        self.gamepad_buttons_held_last_frame = self.gamepad_buttons_held.clone();
         self.mouse_delta = DVec2::ZERO;
     }
 
+    /// Process the events of any connected gamepads. Called at the start of each frame, before
+    /// the input is read. Does nothing unless the `gamepad` feature is enabled
+    pub fn poll_gamepads(&mut self) {
+        #[cfg(feature = "gamepad")]
+        {
+            let Some(gilrs) = self.gilrs.as_mut() else {
+                return;
+            };
+
+            let mut events = Vec::new();
+            while let Some(gilrs::Event { event, .. }) = gilrs.next_event() {
+                events.push(event);
+            }
+            let gamepad_connected = gilrs.gamepads().next().is_some();
+
+            for event in events {
+                self.handle_gamepad_event(event);
+            }
+            self.set_gamepad_connected(gamepad_connected);
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn handle_gamepad_event(&mut self, event: gilrs::EventType) {
+        use gilrs::EventType;
+
+        match event {
+            EventType::ButtonPressed(button, _) => {
+                if let Some(button) = gamepad_button_from_gilrs(button) {
+                    self.gamepad_buttons_held.insert(button);
+                }
+            }
+            EventType::ButtonReleased(button, _) => {
+                if let Some(button) = gamepad_button_from_gilrs(button) {
+                    self.gamepad_buttons_held.remove(&button);
+                }
+            }
+            // analog triggers are reported as buttons with a value
+            EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => {
+                self.set_gamepad_axis(Axis::LeftTrigger, value);
+            }
+            EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => {
+                self.set_gamepad_axis(Axis::RightTrigger, value);
+            }
+            EventType::AxisChanged(axis, value, _) => {
+                let axis = match axis {
+                    gilrs::Axis::LeftStickX => Axis::LeftStickX,
+                    gilrs::Axis::LeftStickY => Axis::LeftStickY,
+                    gilrs::Axis::RightStickX => Axis::RightStickX,
+                    gilrs::Axis::RightStickY => Axis::RightStickY,
+                    _ => return,
+                };
+                self.set_gamepad_axis(axis, value);
+            }
+            _ => (),
+        }
+    }
+
+    /// Record the latest value of a gamepad axis
+    #[cfg_attr(not(feature = "gamepad"), allow(unused))]
+    fn set_gamepad_axis(&mut self, axis: Axis, value: f32) {
+        self.gamepad_axes.insert(axis, value);
+    }
+
+    /// Forget the state of the last gamepad when it is disconnected, so that a stick or button
+    /// held at the time doesn't stay held
+    #[cfg_attr(not(feature = "gamepad"), allow(unused))]
+    fn set_gamepad_connected(&mut self, connected: bool) {
+        if !connected {
+            self.gamepad_axes.clear();
+            self.gamepad_buttons_held.clear();
+        }
+    }
+
     /// Returns true if the event was "consumed"
     pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
         match event {
@@ -119,6 +398,78 @@ impl Input {
                 .contains(&button)
     }
 
+    /// Value of a gamepad axis with the deadzone removed, and the remaining range stretched so
+    /// that the value still reaches 1 at the edge. The deadzone of a stick is circular, so that
+    /// small diagonal movements aren't snapped to either axis. 0 if no gamepad is connected
+    pub fn axis(&self, axis: Axis) -> f32 {
+        let raw_value = |axis| {
+            self.gamepad_axes
+                .get(&axis)
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        match axis.stick_axes() {
+            Some((x_axis, y_axis)) => {
+                let magnitude = Vec2::new(raw_value(x_axis), raw_value(y_axis)).length();
+                if magnitude <= GAMEPAD_DEADZONE {
+                    return 0.0;
+                }
+
+                raw_value(axis) / magnitude * apply_deadzone(magnitude.min(1.0))
+            }
+            None => apply_deadzone(raw_value(axis)),
+        }
+    }
+
+    pub fn is_gamepad_button_down(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons_held
+            .contains(&button)
+    }
+
+    pub fn is_button_just_pressed(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons_held
+            .contains(&button)
+            && !self
+                .gamepad_buttons_held_last_frame
+                .contains(&button)
+    }
+
+    /// Remap actions at runtime. Takes effect immediately
+    pub fn bindings_mut(&mut self) -> &mut InputBindings {
+        &mut self.bindings
+    }
+
+    /// Whether the key, mouse button or gamepad button bound to the action is held
+    pub fn is_action_pressed(&self, action: Action) -> bool {
+        let gamepad_pressed = self
+            .bindings
+            .gamepad_binding(action)
+            .is_some_and(|button| self.is_gamepad_button_down(button));
+
+        gamepad_pressed
+            || match self.bindings.binding(action) {
+                Some(Binding::Key(key_code)) => self.is_key_down(key_code),
+                Some(Binding::MouseButton(button)) => self.is_mouse_button_down(button),
+                None => false,
+            }
+    }
+
+    /// Whether the key, mouse button or gamepad button bound to the action was pressed this frame
+    pub fn is_action_just_pressed(&self, action: Action) -> bool {
+        let gamepad_pressed = self
+            .bindings
+            .gamepad_binding(action)
+            .is_some_and(|button| self.is_button_just_pressed(button));
+
+        gamepad_pressed
+            || match self.bindings.binding(action) {
+                Some(Binding::Key(key_code)) => self.is_key_just_pressed(key_code),
+                Some(Binding::MouseButton(button)) => self.is_mouse_button_just_pressed(button),
+                None => false,
+            }
+    }
+
     pub fn mouse_delta(&self) -> DVec2 {
         self.mouse_delta
     }
@@ -127,3 +478,149 @@ impl Input {
         self.mouse_delta.as_vec2()
     }
 }
+
+/// Remove the deadzone from the magnitude of an axis, from 0 to 1, keeping its sign
+fn apply_deadzone(value: f32) -> f32 {
+    let magnitude = ((value.abs() - GAMEPAD_DEADZONE) / (1.0 - GAMEPAD_DEADZONE)).clamp(0.0, 1.0);
+    magnitude.copysign(value)
+}
+
+#[cfg(feature = "gamepad")]
+fn gamepad_button_from_gilrs(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::LeftThumb => GamepadButton::LeftStick,
+        Button::RightThumb => GamepadButton::RightStick,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_an_action_changes_the_key_that_triggers_it() {
+        let mut input = Input::new();
+
+        input.keys_held.insert(KeyCode::Digit1);
+        assert!(input.is_action_just_pressed(Action::PlaceBlock1));
+        assert!(input.is_action_pressed(Action::PlaceBlock1));
+
+        input
+            .bindings_mut()
+            .bind(Action::PlaceBlock1, Binding::Key(KeyCode::KeyQ));
+        assert!(!input.is_action_pressed(Action::PlaceBlock1));
+
+        input.keys_held.insert(KeyCode::KeyQ);
+        assert!(input.is_action_just_pressed(Action::PlaceBlock1));
+
+        // held since the last frame, so no longer just pressed
+        input.reset();
+        assert!(input.is_action_pressed(Action::PlaceBlock1));
+        assert!(!input.is_action_just_pressed(Action::PlaceBlock1));
+
+        // actions can be bound to mouse buttons, or to nothing
+        input
+            .bindings_mut()
+            .bind(Action::PlaceBlock1, Binding::MouseButton(MouseButton::Right));
+        input.mouse_buttons_held.insert(MouseButton::Right);
+        assert!(input.is_action_pressed(Action::PlaceBlock1));
+    }
+
+    #[test]
+    fn bindings_are_read_from_json() {
+        let mut bindings = InputBindings::default();
+        bindings
+            .rebind_from_json(
+                r#"{ "PlaceBlock1": { "Key": "KeyQ" }, "BreakBlock": { "MouseButton": "Right" } }"#,
+            )
+            .unwrap();
+
+        assert_eq!(bindings.binding(Action::PlaceBlock1), Some(Binding::Key(KeyCode::KeyQ)));
+        assert_eq!(
+            bindings.binding(Action::BreakBlock),
+            Some(Binding::MouseButton(MouseButton::Right))
+        );
+        // actions that aren't listed keep their default bindings
+        assert_eq!(bindings.binding(Action::PlaceBlock2), Some(Binding::Key(KeyCode::Digit2)));
+
+        // unknown actions or keys are rejected without changing any binding
+        assert!(bindings
+            .rebind_from_json(r#"{ "PlaceBlock2": { "Key": "KeyQ" }, "Fly": { "Key": "KeyF" } }"#)
+            .is_err());
+        assert!(bindings
+            .rebind_from_json(r#"{ "PlaceBlock2": { "Key": "Q" } }"#)
+            .is_err());
+        assert_eq!(bindings.binding(Action::PlaceBlock2), Some(Binding::Key(KeyCode::Digit2)));
+    }
+
+    #[test]
+    fn gamepad_axes_ignore_the_deadzone() {
+        let mut input = Input::new();
+        input.set_gamepad_connected(true);
+
+        // a stick resting slightly off centre reads as centred
+        input.set_gamepad_axis(Axis::LeftStickX, 0.1);
+        input.set_gamepad_axis(Axis::LeftStickY, -0.05);
+        assert_eq!(input.axis(Axis::LeftStickX), 0.0);
+        assert_eq!(input.axis(Axis::LeftStickY), 0.0);
+
+        // the range outside the deadzone is stretched to reach 1 at the edge
+        input.set_gamepad_axis(Axis::LeftStickX, -1.0);
+        input.set_gamepad_axis(Axis::LeftStickY, 0.0);
+        assert_eq!(input.axis(Axis::LeftStickX), -1.0);
+
+        // the deadzone is circular, so a small movement along one axis counts on a diagonal
+        input.set_gamepad_axis(Axis::LeftStickX, 0.12);
+        input.set_gamepad_axis(Axis::LeftStickY, 0.12);
+        assert!(input.axis(Axis::LeftStickX) > 0.0);
+        assert!((input.axis(Axis::LeftStickX) - input.axis(Axis::LeftStickY)).abs() < 1e-6);
+
+        input.set_gamepad_axis(Axis::RightTrigger, 0.5 + 0.5 * GAMEPAD_DEADZONE);
+        assert!((input.axis(Axis::RightTrigger) - 0.5).abs() < 1e-6);
+
+        // axes only report changes, so they keep their values from frame to frame
+        input.reset();
+        assert!(input.axis(Axis::RightTrigger) > 0.0);
+
+        // until the gamepad is disconnected
+        input.set_gamepad_connected(false);
+        assert_eq!(input.axis(Axis::RightTrigger), 0.0);
+        assert_eq!(input.axis(Axis::LeftStickX), 0.0);
+    }
+
+    #[test]
+    fn gamepad_buttons_are_just_pressed_for_one_frame() {
+        let mut input = Input::new();
+        input.set_gamepad_connected(true);
+
+        input.gamepad_buttons_held.insert(GamepadButton::West);
+        assert!(input.is_button_just_pressed(GamepadButton::West));
+        assert!(input.is_action_just_pressed(Action::PlaceBlock2));
+
+        input.reset();
+        assert!(!input.is_button_just_pressed(GamepadButton::West));
+        assert!(input.is_action_pressed(Action::PlaceBlock2));
+
+        // a button held when the gamepad is disconnected is released
+        input.gamepad_buttons_held.insert(GamepadButton::South);
+        input.set_gamepad_connected(false);
+        assert!(!input.is_gamepad_button_down(GamepadButton::South));
+    }
+}
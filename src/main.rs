@@ -1,28 +1,65 @@
-use std::sync::Arc;
+use std::{
+    fs::File,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use block::{BLOCK_AIR, BLOCK_DIRT, BLOCK_GRASS, BLOCK_LAMP_ORANGE};
+use block::{
+    BLOCKS, BLOCK_AIR, BLOCK_COAL_ORE, BLOCK_DIRT, BLOCK_FENCE_POST, BLOCK_GLASS, BLOCK_GRASS,
+    BLOCK_LAMP_ORANGE, BLOCK_LEAVES, BLOCK_TALL_GRASS, BLOCK_WOOD_SLAB, BLOCK_WOOD_STAIRS,
+};
+use block_breaking::BlockBreaking;
+use day_night_cycle::DayNightCycle;
 use fly_camera::FlyCamera;
+use frame_recorder::{
+    FrameMetrics, FrameRecorder, DEFAULT_FRAME_RECORDING_PATH, FRAME_RECORDING_PATH_VAR,
+};
 use generational_arena::Index;
-use input::Input;
-use render::{render_context::RenderContext, render_engine::RenderEngine};
-use tasks::Tasks;
-use terrain::{chunk::CHUNK_SIZE, load_area::LoadArea, position_types::ChunkPosition, Terrain};
-use time::{TargetFrameRate, Time};
+use glam::{IVec3, Vec3};
+use input::{Action, Input};
+use render::{
+    build_grid::Plane,
+    render_context::RenderContext,
+    render_engine::RenderEngine,
+    render_pass::Pass,
+    reticle::{ReticleColor, ReticleShape},
+    shadow::{self, ShadowMaps},
+    terrain::{
+        lod::DEFAULT_LOD_DISTANCE,
+        mesh_upload_queue::DEFAULT_MESH_UPLOAD_BUDGET,
+        meshing::{MeshingStrategy, NormalMode},
+    },
+};
+use tasks::{worker_scaling::WorkerScaling, TaskStage, Tasks};
+use terrain::{
+    chunk::CHUNK_SIZE,
+    generator::GenerationParams,
+    load_area::LoadArea,
+    persistence::ChunkStore,
+    position_types::{ChunkPosition, GlobalBlockPosition},
+    structure::{PlacementMode, Structure},
+    RaymarchOptions, Terrain, TerrainHit, WorldBounds,
+};
+use time::{TargetFrameRate, Time, DEFAULT_FIXED_DELTA};
 use util::size::Size3;
 use winit::{
     application::ApplicationHandler,
-    dpi::{LogicalPosition, PhysicalSize},
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
     error::EventLoopError,
-    event::{DeviceEvent, DeviceId, MouseButton, WindowEvent},
+    event::{DeviceEvent, DeviceId, ElementState, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::KeyCode,
-    window::{Window, WindowId},
+    window::{CursorGrabMode, Fullscreen, Window, WindowId},
 };
 
-use crate::{block::BLOCK_WOOD, terrain::position_types::GlobalBlockPosition};
+use crate::block::BLOCK_WOOD;
 
 mod block;
+mod block_breaking;
+mod day_night_cycle;
 mod fly_camera;
+mod frame_recorder;
 mod input;
 mod render;
 mod tasks;
@@ -32,9 +69,29 @@ mod util;
 
 const WINDOW_TITLE: &'static str = "\"minecraft\"";
 
-/// Number of threads to use for task processing
+/// Size of the window when it is first created, in logical pixels
+const INITIAL_WINDOW_SIZE: LogicalSize<u32> = LogicalSize::new(1280, 720);
+
+/// Number of threads to use for task processing when the available parallelism is unknown
 const TASKS_WORKER_THREAD_COUNT: usize = 4;
 
+/// Frame time above which task workers are deactivated to leave time for the main thread
+const TASKS_FRAME_BUDGET: Duration = Duration::from_micros(16_667);
+
+/// Number of task worker threads reserved for chunk generation, so that it progresses even when
+/// there are many meshing tasks
+const GENERATION_RESERVED_WORKER_COUNT: usize = 1;
+
+/// Number of task worker threads reserved for chunk meshing, so that chunks which are already
+/// loaded get meshed even when there are many generation tasks
+const MESHING_RESERVED_WORKER_COUNT: usize = 1;
+
+/// Priority value for tasks recomputing the light after edits and chunk loads
+const LIGHT_PROPAGATION_PRIORITY: i32 = 0;
+
+/// Priority value for tasks writing edited chunks to disk
+const CHUNK_SAVING_PRIORITY: i32 = 0;
+
 /// Priority value for chunk mesh generation tasks when an outdated mesh already exists
 const CHUNK_MESH_UPDATE_PRIORITY: i32 = 0;
 
@@ -47,34 +104,108 @@ const CHUNK_LOADING_PRIORITY: i32 = 2;
 /// Priority value for chunk mesh generation tasks when an up-to-date mesh already exists
 const CHUNK_MESH_OPTIMIZATION_PRIORITY: i32 = 3;
 
+/// Camera speed in blocks per second above which new chunk meshes are throttled
+const MESH_THROTTLE_SPEED_THRESHOLD: f32 = 20.0;
+
+/// Maximum number of distant chunk meshes to request per frame while the camera is moving quickly
+const MESH_THROTTLE_JOB_BUDGET: usize = 8;
+
+/// Distance from the camera in chunks within which chunk meshes are never throttled
+const MESH_THROTTLE_PROMPT_RADIUS: f32 = 3.0;
+
+/// Horizontal radius of the area around the camera in which chunks are loaded, in chunks
+const LOAD_AREA_HORIZONTAL_RADIUS: usize = 20;
+
+/// Height of the area around the camera in which chunks are loaded, in chunks
+const LOAD_AREA_VERTICAL_RANGE: usize = 16;
+
+/// Time taken for newly meshed chunks to fade in when the fade is enabled, in seconds
+const CHUNK_FADE_DURATION: f32 = 0.5;
+
+/// How many times faster than normal the time of day advances while fast-forwarding
+const TIME_FAST_FORWARD_RATE: f32 = 60.0;
+
+/// Directory in which edited chunks are saved, relative to the working directory
+const SAVE_DIRECTORY: &str = "saves/world";
+
+/// File overriding the default input bindings if it exists, relative to the working directory.
+/// See `InputBindings::rebind_from_json` for the format
+const INPUT_BINDINGS_PATH: &str = "bindings.json";
+
+/// Width of the cube of blocks around the targeted block copied by the copy key
+const COPIED_REGION_SIZE: usize = 5;
+
+/// World bounds switched to by the world bounds key, shallow enough to reach both ends quickly
+const SHALLOW_WORLD_BOUNDS: WorldBounds = WorldBounds {
+    min_y: -16,
+    max_y: 47,
+};
+
+/// Fixed update step switched to by the fixed delta key, slow enough to make interpolation
+/// between steps visible
+const SLOW_FIXED_DELTA: Duration = Duration::from_millis(100);
+
+/// Environment variable that, when set, executes tasks on a single worker in a reproducible order,
+/// flushed once per frame, so that chunk loading happens in the same sequence on every run
+const DETERMINISTIC_TASKS_VAR: &str = "VOXELS_DETERMINISTIC_TASKS";
+
 struct State {
     window: Arc<Window>,
     render_context: RenderContext,
     time: Time,
     input: Input,
     tasks: Tasks,
+    worker_scaling: WorkerScaling,
     terrain: Terrain,
     load_area_index: Index,
     render_engine: RenderEngine,
+    day_night_cycle: DayNightCycle,
     fly_camera: FlyCamera,
     fly_camera_active: bool,
+    cursor_grab: CursorGrab,
+    block_breaking: BlockBreaking,
+    build_grid_enabled: bool,
+    /// Block targeted by the camera in the last update, shown in the debug HUD
+    target: Option<TerrainHit>,
+    /// Region copied from the terrain, stamped instead of a tree while set
+    copied_structure: Option<Structure>,
+    debug_hud_visible: bool,
+    /// Records the metrics of each frame to a CSV file while enabled
+    frame_recorder: Option<FrameRecorder<File>>,
     close_requested: bool,
 }
 
 impl State {
     fn new(window: Arc<Window>) -> Self {
         let render_context = RenderContext::new(window.clone());
-        let input = Input::new();
-        let time = Time::new(TargetFrameRate::UnlimitedOrVsync);
-        let tasks = Tasks::new(TASKS_WORKER_THREAD_COUNT);
-        let mut terrain = Terrain::new();
+        let mut input = Input::new();
+        Self::load_input_bindings(&mut input, INPUT_BINDINGS_PATH);
+        let time = Time::new(TargetFrameRate::Unlimited);
+        let reserved_worker_count =
+            GENERATION_RESERVED_WORKER_COUNT + MESHING_RESERVED_WORKER_COUNT;
+        let worker_thread_count = std::thread::available_parallelism()
+            .map_or(TASKS_WORKER_THREAD_COUNT, NonZeroUsize::get)
+            .max(reserved_worker_count);
+        let tasks = if std::env::var_os(DETERMINISTIC_TASKS_VAR).is_some() {
+            Tasks::new_deterministic()
+        } else {
+            Tasks::new(worker_thread_count, &[
+                (TaskStage::Generation, GENERATION_RESERVED_WORKER_COUNT),
+                (TaskStage::Meshing, MESHING_RESERVED_WORKER_COUNT),
+            ])
+        };
+        let worker_scaling =
+            WorkerScaling::new(TASKS_FRAME_BUDGET, reserved_worker_count, worker_thread_count);
+        let mut terrain = Terrain::new(GenerationParams::default());
+        terrain.set_chunk_store(Some(ChunkStore::new(SAVE_DIRECTORY)));
         let fly_camera = FlyCamera::default();
 
         let load_area_index = terrain
             .load_areas_mut()
-            .insert(LoadArea::new(
+            .insert(LoadArea::with_radius_and_height(
                 ChunkPosition::ZERO,
-                Size3::new(40, 16, 40),
+                LOAD_AREA_HORIZONTAL_RADIUS,
+                LOAD_AREA_VERTICAL_RANGE,
                 terrain::load_area::AreaShape::Cylindrical,
             ));
         let render_engine = RenderEngine::new(
@@ -91,16 +222,80 @@ impl State {
             input,
             time,
             tasks,
+            worker_scaling,
             terrain,
             load_area_index,
             render_engine,
+            day_night_cycle: DayNightCycle::default(),
             fly_camera,
             fly_camera_active: true,
+            cursor_grab: CursorGrab::Released,
+            block_breaking: BlockBreaking::new(),
+            build_grid_enabled: false,
+            target: None,
+            copied_structure: None,
+            debug_hud_visible: false,
+            frame_recorder: std::env::var_os(FRAME_RECORDING_PATH_VAR)
+                .and_then(Self::start_frame_recording),
             close_requested: false,
         }
     }
 
+    /// Rebind actions from the JSON file at the given path, if it exists. The default bindings are
+    /// kept if the file can't be read or is invalid, which is logged
+    fn load_input_bindings(input: &mut Input, path: &str) {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                log::error!("couldn't read input bindings from {path}: {e}");
+                return;
+            }
+        };
+
+        match input.bindings_mut().rebind_from_json(&json) {
+            Ok(()) => log::info!("loaded input bindings from {path}"),
+            Err(e) => log::error!("invalid input bindings in {path}: {e}"),
+        }
+    }
+
+    /// Start recording frame metrics to the CSV file at the given path, or log why it couldn't be
+    /// created
+    fn start_frame_recording(path: impl AsRef<std::path::Path>) -> Option<FrameRecorder<File>> {
+        let path = path.as_ref();
+
+        match FrameRecorder::create(path) {
+            Ok(recorder) => {
+                log::info!("recording frame metrics to {}", path.display());
+                Some(recorder)
+            }
+            Err(e) => {
+                log::error!("couldn't record frame metrics to {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    /// Flush and close the frame metrics recording, if any
+    fn stop_frame_recording(&mut self) {
+        let Some(recorder) = self.frame_recorder.take() else {
+            return;
+        };
+
+        let frame_count = recorder.frame_count();
+        match recorder.finish() {
+            Ok(_) => log::info!("recorded {frame_count} frames of metrics"),
+            Err(e) => log::error!("couldn't finish recording frame metrics: {e}"),
+        }
+    }
+
     fn frame(&mut self) {
+        // NB: not every platform sends `Resized` when entering or leaving fullscreen
+        let window_size = self.window.inner_size();
+        if window_size != self.render_context.window_size {
+            self.resized(window_size);
+        }
+
         self.time.begin_frame();
         self.update();
         self.render();
@@ -114,124 +309,911 @@ impl State {
             .resized(&self.render_context);
     }
 
+    /// Switch between windowed and borderless fullscreen on the monitor the window is currently
+    /// on. The surface and render targets are resized when the `Resized` event arrives
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(self.window.current_monitor())),
+        };
+        self.window.set_fullscreen(fullscreen);
+    }
+
+    /// Grab and hide the cursor for mouse look, or release and show it. Where the cursor can't
+    /// be grabbed, it is instead moved back to the center of the window every frame
+    fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        self.cursor_grab = if grabbed {
+            let grab_result = self
+                .window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| {
+                    self.window
+                        .set_cursor_grab(CursorGrabMode::Confined)
+                });
+
+            match grab_result {
+                Ok(()) => CursorGrab::Grabbed,
+                Err(e) => {
+                    log::warn!("couldn't grab cursor, falling back to warping it: {e}");
+                    CursorGrab::Warping
+                }
+            }
+        } else {
+            if let Err(e) = self
+                .window
+                .set_cursor_grab(CursorGrabMode::None)
+            {
+                log::warn!("couldn't release cursor: {e}");
+            }
+            CursorGrab::Released
+        };
+
+        self.window
+            .set_cursor_visible(!grabbed);
+    }
+
+    /// Give the mouse and keyboard control of the camera, grabbing the cursor, or take it away
+    /// and release the cursor
+    fn set_fly_camera_active(&mut self, active: bool) {
+        self.fly_camera_active = active;
+        self.set_cursor_grabbed(active);
+    }
+
+    /// Called after the player places or breaks a block. Chunks being edited switch to culled
+    /// meshing, which is quicker to rebuild than greedy meshing, until they are unloaded
+    fn block_edited(&mut self, global_block_pos: &GlobalBlockPosition) {
+        let (_, chunk_pos) = global_block_pos.get_local_and_chunk_pos();
+
+        self.render_engine
+            .set_meshing_strategy(chunk_pos, Some(MeshingStrategy::Culled));
+    }
+
     fn update(&mut self) {
         self.terrain.clear_events();
+        self.input.poll_gamepads();
+
+        // release the cursor (click in the window to grab it again)
+        if self
+            .input
+            .is_action_just_pressed(Action::ReleaseCursor)
+        {
+            self.set_cursor_grabbed(false);
+        }
 
-        // capture cursor
-        if self.window.has_focus() {
+        // keep the cursor in the window where it can't be grabbed
+        if self.cursor_grab == CursorGrab::Warping && self.window.has_focus() {
             let window_size = self.window.inner_size();
-            self.window
-                .set_cursor_position(LogicalPosition::new(
-                    window_size.width / 2,
-                    window_size.height / 2,
-                ))
-                .unwrap();
+            let center = PhysicalPosition::new(window_size.width / 2, window_size.height / 2);
+            if let Err(e) = self.window.set_cursor_position(center) {
+                // mouse look still works from raw mouse motion, so stop trying
+                log::warn!("couldn't move cursor, it may leave the window: {e}");
+                self.cursor_grab = CursorGrab::Grabbed;
+            }
         }
 
+        // stop the mouse and keyboard controlling the camera, freeing the cursor
+        if self
+            .input
+            .is_action_just_pressed(Action::ToggleCameraControl)
+        {
+            self.set_fly_camera_active(!self.fly_camera_active);
+            log::info!(
+                "camera control {}",
+                if self.fly_camera_active { "enabled" } else { "disabled" }
+            );
+        }
+
+        // pause the simulation, or step a single frame while paused (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyP)
+        {
+            self.time
+                .set_paused(!self.time.is_paused());
+        }
         if self
             .input
-            .is_key_just_pressed(KeyCode::KeyC)
+            .is_key_just_pressed(KeyCode::Period)
         {
-            self.fly_camera.position.x *= 2.0;
-            log::info!("{}", self.fly_camera.position.x);
+            self.time.step();
         }
 
-        // display framerate in window title
+        // advance the time of day, much faster while fast-forwarding (TEMP)
+        self.day_night_cycle.update(&self.time);
+        if self
+            .input
+            .is_key_down(KeyCode::KeyH)
+        {
+            self.day_night_cycle
+                .advance(self.time.delta_seconds() * (TIME_FAST_FORWARD_RATE - 1.0));
+        }
+        if self
+            .input
+            .is_key_just_released(KeyCode::KeyH)
+        {
+            log::info!("time of day: {:.3}", self.day_night_cycle.time_of_day());
+        }
+        self.day_night_cycle
+            .apply(&mut self.render_engine);
+
+        // toggle ambient occlusion (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyO)
+        {
+            let ao_strength = if self.render_engine.ao_strength() > 0.0 {
+                0.0
+            } else {
+                RenderEngine::DEFAULT_AO_STRENGTH
+            };
+            self.render_engine
+                .set_ao_strength(ao_strength);
+        }
+
+        // cycle ambient occlusion curves, from gentle to harsh (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyY)
+        {
+            const AO_CURVES: [f32; 4] = [RenderEngine::DEFAULT_AO_CURVE, 1.5, 3.0, 0.5];
+
+            let next_index = AO_CURVES
+                .iter()
+                .position(|&curve| curve == self.render_engine.ao_curve())
+                .map_or(0, |index| (index + 1) % AO_CURVES.len());
+            log::info!("ambient occlusion curve: {}", AO_CURVES[next_index]);
+            self.render_engine
+                .set_ao_curve(AO_CURVES[next_index]);
+        }
+
+        // cycle the number of shadow cascades, including none (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::Semicolon)
+        {
+            let cascade_count =
+                (self.render_engine.shadow_cascade_count() + 1) % (shadow::MAX_CASCADE_COUNT + 1);
+            log::info!("shadow cascades: {}", cascade_count);
+            self.render_engine
+                .set_shadow_cascade_count(cascade_count);
+        }
+
+        // cycle how the view depth is split between shadow cascades (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::Quote)
+        {
+            const SPLIT_LAMBDAS: [f32; 3] = [ShadowMaps::DEFAULT_SPLIT_LAMBDA, 0.95, 0.5];
+
+            let next_index = SPLIT_LAMBDAS
+                .iter()
+                .position(|&lambda| lambda == self.render_engine.shadow_split_lambda())
+                .map_or(0, |index| (index + 1) % SPLIT_LAMBDAS.len());
+            log::info!("shadow split lambda: {}", SPLIT_LAMBDAS[next_index]);
+            self.render_engine
+                .set_shadow_split_lambda(SPLIT_LAMBDAS[next_index]);
+        }
+
+        // toggle chunk boundary tint (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyB)
+        {
+            let enabled = !self.render_engine.debug_chunk_tint();
+            self.render_engine
+                .set_debug_chunk_tint(enabled);
+        }
+
+        // toggle build grid (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyG)
+        {
+            self.build_grid_enabled = !self.build_grid_enabled;
+        }
+
+        // cycle reticle shapes and colours (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyR)
+        {
+            let mut reticle = self.render_engine.reticle();
+            reticle.shape = match reticle.shape {
+                ReticleShape::Cross => ReticleShape::Dot,
+                ReticleShape::Dot => ReticleShape::Circle,
+                ReticleShape::Circle => ReticleShape::Cross,
+            };
+            self.render_engine.set_reticle(reticle);
+        }
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyT)
+        {
+            let mut reticle = self.render_engine.reticle();
+            reticle.color = match reticle.color {
+                ReticleColor::Invert => ReticleColor::Fixed([1.0, 1.0, 1.0, 0.8]),
+                ReticleColor::Fixed(_) => ReticleColor::Invert,
+            };
+            self.render_engine.set_reticle(reticle);
+        }
+
+        // cycle present modes (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyV)
+        {
+            let present_mode = match self.render_context.present_mode() {
+                wgpu::PresentMode::Fifo => wgpu::PresentMode::Mailbox,
+                wgpu::PresentMode::Mailbox => wgpu::PresentMode::Immediate,
+                wgpu::PresentMode::Immediate => wgpu::PresentMode::AutoNoVsync,
+                _ => wgpu::PresentMode::Fifo,
+            };
+            let present_mode = self
+                .render_context
+                .set_present_mode(present_mode);
+            log::info!("present mode: {present_mode:?}");
+        }
+
+        // toggle uploading every finished chunk mesh at once, to compare the frame times with and
+        // without the upload budget (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyX)
+        {
+            let budget = if self.render_engine.mesh_upload_budget() == usize::MAX {
+                DEFAULT_MESH_UPLOAD_BUDGET
+            } else {
+                usize::MAX
+            };
+            log::info!("chunk mesh upload budget: {budget}");
+            self.render_engine
+                .set_mesh_upload_budget(budget);
+        }
+
+        // toggle deferring chunk meshes while travelling quickly, to compare the stutter with and
+        // without the mesh throttle (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyZ)
+        {
+            let mesh_throttle = self.render_engine.mesh_throttle_mut();
+            mesh_throttle.speed_threshold = if mesh_throttle.speed_threshold == f32::INFINITY {
+                MESH_THROTTLE_SPEED_THRESHOLD
+            } else {
+                f32::INFINITY
+            };
+            log::info!("mesh throttle speed threshold: {}", mesh_throttle.speed_threshold);
+        }
+
+        // toggle fading in new chunks (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyK)
+        {
+            let fade_duration = if self.render_engine.chunk_fade_duration() == 0.0 {
+                CHUNK_FADE_DURATION
+            } else {
+                0.0
+            };
+            self.render_engine
+                .set_chunk_fade_duration(fade_duration);
+        }
+
+        // regenerate the terrain with the next seed (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyN)
+        {
+            let params = GenerationParams {
+                seed: self.terrain.generation_params().seed + 1,
+                ..*self.terrain.generation_params()
+            };
+            log::info!("regenerating terrain with seed {}", params.seed);
+            self.terrain.set_generation_params(params);
+            self.terrain.regenerate(&mut self.tasks);
+        }
+
+        // toggle between the default world bounds and a shallow world, to check that edits and
+        // generation respect them (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyE)
+        {
+            let world_bounds = if *self.terrain.world_bounds() == WorldBounds::default() {
+                SHALLOW_WORLD_BOUNDS
+            } else {
+                WorldBounds::default()
+            };
+            log::info!(
+                "regenerating terrain with world bounds {}..={}",
+                world_bounds.min_y,
+                world_bounds.max_y
+            );
+            self.terrain.set_world_bounds(world_bounds);
+            self.terrain.regenerate(&mut self.tasks);
+        }
+
+        // toggle a slow fixed update rate, to check that the camera is interpolated smoothly
+        // between fixed steps (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F12)
+        {
+            let fixed_delta = if self.time.fixed_delta() == DEFAULT_FIXED_DELTA {
+                SLOW_FIXED_DELTA
+            } else {
+                DEFAULT_FIXED_DELTA
+            };
+            log::info!("fixed delta: {fixed_delta:?}");
+            self.time.set_fixed_delta(fixed_delta);
+        }
+
+        // rebuild the meshes of all loaded chunks (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F5)
+        {
+            log::info!("remeshing {} chunks", self.terrain.chunks().len());
+            self.terrain.remesh_all();
+        }
+
+        // cycle terrain face culling between back faces, none and front faces, to find faces with
+        // the wrong winding (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyL)
+        {
+            let cull_mode = match self.render_engine.cull_mode() {
+                Some(wgpu::Face::Back) => None,
+                None => Some(wgpu::Face::Front),
+                Some(wgpu::Face::Front) => Some(wgpu::Face::Back),
+            };
+            log::info!("terrain cull mode: {cull_mode:?}");
+            self.render_engine.set_cull_mode(cull_mode);
+        }
+
+        // toggle wireframe terrain (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F6)
+        {
+            let wireframe = self
+                .render_engine
+                .set_wireframe(&self.render_context, !self.render_engine.wireframe());
+            log::info!("wireframe terrain: {wireframe}");
+        }
+
+        // toggle drawing the terrain, leaving the sky and overlays (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F2)
+        {
+            let enabled = !self
+                .render_engine
+                .is_pass_enabled(Pass::Terrain);
+            self.render_engine
+                .set_pass_enabled(Pass::Terrain, enabled);
+            self.render_engine
+                .set_pass_enabled(Pass::TranslucentTerrain, enabled);
+            log::info!("terrain drawn: {enabled}");
+        }
+
+        // switch chunk meshing between greedy and culled meshing (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F7)
+        {
+            let strategy = match self.render_engine.default_meshing_strategy() {
+                MeshingStrategy::Greedy => MeshingStrategy::Culled,
+                MeshingStrategy::Culled => MeshingStrategy::Greedy,
+            };
+            log::info!("chunk meshing strategy: {strategy:?}");
+            self.render_engine
+                .set_default_meshing_strategy(strategy);
+        }
+
+        // switch chunk meshes between flat and smooth normals (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F9)
+        {
+            let normal_mode = match self.render_engine.normal_mode() {
+                NormalMode::Flat => NormalMode::Smooth,
+                NormalMode::Smooth => NormalMode::Flat,
+            };
+            log::info!("chunk normal mode: {normal_mode:?}");
+            self.render_engine
+                .set_normal_mode(normal_mode);
+        }
+
+        // toggle meshing distant chunks at a lower resolution (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F8)
+        {
+            let lod_distance = match self.render_engine.lod_distance() {
+                Some(_) => None,
+                None => Some(DEFAULT_LOD_DISTANCE),
+            };
+            log::info!("chunk LOD distance: {lod_distance:?}");
+            self.render_engine
+                .set_lod_distance(lod_distance);
+        }
+
+        // log chunk mesh build times (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyM)
+        {
+            let mesh_time_stats = self.render_engine.mesh_time_stats();
+            log::info!(
+                "mesh times: {:?}, correlation with block count: {:?}",
+                mesh_time_stats.summary(),
+                mesh_time_stats.block_count_correlation()
+            );
+        }
+
+        // log pending task counts (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyJ)
+        {
+            log::info!(
+                "pending tasks: {} generation, {} meshing",
+                self.tasks
+                    .pending_task_count(TaskStage::Generation),
+                self.tasks
+                    .pending_task_count(TaskStage::Meshing)
+            );
+        }
+
+        // remove or add a task worker thread (TEMP)
+        let worker_count_change = [(KeyCode::BracketLeft, -1), (KeyCode::BracketRight, 1)]
+            .into_iter()
+            .filter(|&(key, _)| self.input.is_key_just_pressed(key))
+            .map(|(_, change)| change)
+            .sum::<isize>();
+        if worker_count_change != 0 {
+            let worker_count = self
+                .tasks
+                .total_worker_count()
+                .saturating_add_signed(worker_count_change)
+                .max(GENERATION_RESERVED_WORKER_COUNT + MESHING_RESERVED_WORKER_COUNT);
+            self.tasks.set_worker_count(worker_count);
+            self.worker_scaling.max_workers = worker_count;
+            log::info!("task worker threads: {worker_count}");
+        }
+
+        // activate more task workers while there is a backlog and time to spare in the frame, and
+        // fewer when idle to save power. Paused frames have no duration, so they are skipped
+        if self.time.is_advancing() {
+            let pending_task_count = self
+                .tasks
+                .pending_task_count(TaskStage::Generation)
+                + self
+                    .tasks
+                    .pending_task_count(TaskStage::Meshing);
+            let worker_limit = self
+                .worker_scaling
+                .update(self.time.delta(), pending_task_count);
+            self.tasks.set_worker_limit(worker_limit);
+        }
+
+        // toggle fullscreen
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F11)
+        {
+            self.toggle_fullscreen();
+        }
+
+        // start or stop recording frame metrics
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F4)
+        {
+            if self.frame_recorder.is_some() {
+                self.stop_frame_recording();
+            } else {
+                self.frame_recorder = Self::start_frame_recording(DEFAULT_FRAME_RECORDING_PATH);
+            }
+        }
+
+        // toggle debug HUD
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::F3)
+        {
+            self.debug_hud_visible = !self.debug_hud_visible;
+        }
+
+        // display framerate and adapter in window title
+        let adapter_info = self.render_context.adapter_info();
         self.window.set_title(&format!(
-            "{} ({} fps)",
+            "{} ({} fps, {} on {:?})",
             WINDOW_TITLE,
-            self.time.get_frames_last_second()
+            self.time.get_frames_last_second(),
+            adapter_info.name,
+            adapter_info.backend
         ));
 
+        // switch between flying and walking (TEMP)
+        if self
+            .input
+            .is_action_just_pressed(Action::ToggleFly)
+        {
+            let no_clip = !self.fly_camera.no_clip;
+            self.fly_camera.set_no_clip(no_clip);
+            log::info!("{}", if no_clip { "flying" } else { "walking" });
+        }
+
+        // face the sun, or the moon at night (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyU)
+        {
+            let sun_position = self.fly_camera.position + self.day_night_cycle.sun_direction();
+            self.fly_camera.look_at(sun_position);
+        }
+
         // update flycam
         if self.fly_camera_active {
+            let terrain = &self.terrain;
+            let load_area_index = self.load_area_index;
+
             self.fly_camera
-                .update(&self.input, &self.time);
+                .update(&self.input, &self.time, |block_pos| {
+                    let global_block_pos = GlobalBlockPosition::from(block_pos);
+
+                    // blocks that aren't loaded yet are solid, so that the player can't fall
+                    // out of the world before it loads
+                    let Some(block_id) = terrain.get_block(load_area_index, &global_block_pos)
+                    else {
+                        return Some((Vec3::ZERO, Vec3::ONE));
+                    };
+                    let block = &BLOCKS[block_id.0 as usize];
+
+                    block
+                        .is_collidable()
+                        .then(|| block.model.bounding_box())
+                        .flatten()
+                });
         }
         self.render_engine
             .camera_mut()
-            .transform = self.fly_camera.get_transform();
+            .transform = self
+            .fly_camera
+            .interpolated_transform(self.time.fixed_alpha());
 
         // block breaking and placing (TEMP)
         let destroy = self
             .input
-            .is_mouse_button_just_pressed(MouseButton::Left);
+            .is_action_pressed(Action::BreakBlock);
         let place_dirt = self
             .input
-            .is_key_just_pressed(KeyCode::Digit1);
+            .is_action_just_pressed(Action::PlaceBlock1);
         let place_grass = self
             .input
-            .is_key_just_pressed(KeyCode::Digit2);
+            .is_action_just_pressed(Action::PlaceBlock2);
         let place_wood = self
             .input
-            .is_key_just_pressed(KeyCode::Digit3);
+            .is_action_just_pressed(Action::PlaceBlock3);
         let place_lamp = self
             .input
-            .is_key_just_pressed(KeyCode::Digit4);
-        if destroy || place_dirt || place_grass || place_wood || place_lamp {
-            let look_dir = self.render_engine.camera().look_dir(); // bad coupling
+            .is_action_just_pressed(Action::PlaceBlock4);
+        let place_leaves = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock5);
+        let place_coal_ore = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock6);
+        let place_tree = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock7);
+        let place_fence_post = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock8);
+        let place_glass = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock9);
+        let place_wood_slab = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock10);
+        let place_wood_stairs = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock11);
+        let place_tall_grass = self
+            .input
+            .is_action_just_pressed(Action::PlaceBlock12);
+        let edit_requested = (destroy
+            || place_dirt
+            || place_grass
+            || place_wood
+            || place_lamp
+            || place_leaves
+            || place_coal_ore
+            || place_tree
+            || place_fence_post
+            || place_glass
+            || place_wood_slab
+            || place_wood_stairs
+            || place_tall_grass)
+            && self.time.is_advancing();
 
-            let hit = self.terrain.raymarch(
-                self.load_area_index,
-                self.fly_camera.position,
-                look_dir,
-                50.0,
+        // the targeted block is needed to outline it, to edit blocks and to position the build
+        // grid
+        let look_dir = self.render_engine.camera().look_dir(); // bad coupling
+        let hit = self.terrain.raymarch(
+            self.load_area_index,
+            self.fly_camera.position,
+            look_dir,
+            50.0,
+            RaymarchOptions::default(),
+        );
+
+        self.target = hit;
+        self.render_engine
+            .set_selection(hit.as_ref().map(|hit| hit.hit_pos));
+
+        let hit_face = hit.as_ref().and_then(|hit| hit.face);
+
+        // show the build grid on the targeted face
+        let build_grid = hit
+            .as_ref()
+            .zip(hit_face)
+            .filter(|_| self.build_grid_enabled)
+            .map(|(hit, hit_face)| Plane::from_block_face(hit.hit_pos, hit_face));
+        self.render_engine.set_build_grid(build_grid);
+
+        // copy the region around the targeted block to stamp instead of trees, or go back to
+        // trees if nothing is targeted (TEMP)
+        if self
+            .input
+            .is_key_just_pressed(KeyCode::KeyI)
+        {
+            self.copied_structure = hit.as_ref().and_then(|hit| {
+                let size = Size3::splat(COPIED_REGION_SIZE);
+                let min = hit.hit_pos - IVec3::splat(COPIED_REGION_SIZE as i32 / 2);
+
+                Structure::from_region(&self.terrain, self.load_area_index, min, size)
+            });
+            log::info!(
+                "{}",
+                if self.copied_structure.is_some() {
+                    "copied region"
+                } else {
+                    "placing trees"
+                }
             );
+        }
 
+        let mut break_target = None;
+        if edit_requested {
             if let Some(hit) = hit {
                 if destroy {
-                    self.terrain
-                        .set_block(self.load_area_index, &hit.hit_pos, BLOCK_AIR);
-                }
-                if place_dirt {
-                    if let Some(hit_normal) = hit.hit_normal {
-                        self.terrain.set_block(
-                            self.load_area_index,
-                            &(hit.hit_pos + GlobalBlockPosition::from(hit_normal)),
-                            BLOCK_DIRT,
-                        );
-                    }
+                    break_target = self
+                        .terrain
+                        .get_block(self.load_area_index, &hit.hit_pos)
+                        .map(|block_id| &BLOCKS[block_id.0 as usize])
+                        .filter(|block| block.is_breakable())
+                        .map(|block| (hit.hit_pos, block.hardness));
                 }
-                if place_grass {
-                    if let Some(hit_normal) = hit.hit_normal {
-                        self.terrain.set_block(
-                            self.load_area_index,
-                            &(hit.hit_pos + GlobalBlockPosition::from(hit_normal)),
-                            BLOCK_GRASS,
-                        );
+
+                let block_to_place = [
+                    (place_dirt, BLOCK_DIRT),
+                    (place_grass, BLOCK_GRASS),
+                    (place_wood, BLOCK_WOOD),
+                    (place_lamp, BLOCK_LAMP_ORANGE),
+                    (place_leaves, BLOCK_LEAVES),
+                    (place_coal_ore, BLOCK_COAL_ORE),
+                    (place_fence_post, BLOCK_FENCE_POST),
+                    (place_glass, BLOCK_GLASS),
+                    (place_wood_slab, BLOCK_WOOD_SLAB),
+                    (place_wood_stairs, BLOCK_WOOD_STAIRS),
+                    (place_tall_grass, BLOCK_TALL_GRASS),
+                ]
+                .into_iter()
+                .find_map(|(place, block_id)| place.then_some(block_id));
+
+                if let (Some(block_id), Some(placement_pos)) = (block_to_place, hit.placement_pos())
+                {
+                    let placed = self.terrain.place_block(
+                        self.load_area_index,
+                        &placement_pos,
+                        block_id,
+                        self.fly_camera.collision_box(),
+                    );
+
+                    if placed {
+                        self.block_edited(&placement_pos);
+                    } else {
+                        log::info!("can't place a block there");
                     }
                 }
-                if place_wood {
-                    if let Some(hit_normal) = hit.hit_normal {
-                        self.terrain.set_block(
-                            self.load_area_index,
-                            &(hit.hit_pos + GlobalBlockPosition::from(hit_normal)),
-                            BLOCK_WOOD,
-                        );
-                    }
+
+                if let (true, Some(placement_pos)) = (place_tree, hit.placement_pos()) {
+                    // holding sprint stamps the whole tree, replacing whatever is in the way
+                    let mode = if self.input.is_action_pressed(Action::Sprint) {
+                        PlacementMode::Overwrite
+                    } else {
+                        PlacementMode::KeepExisting
+                    };
+
+                    let structure = self
+                        .copied_structure
+                        .clone()
+                        .unwrap_or_else(Structure::tree);
+
+                    self.terrain.place_structure(
+                        self.load_area_index,
+                        &placement_pos,
+                        &structure,
+                        mode,
+                    );
                 }
-                if place_lamp {
-                    if let Some(hit_normal) = hit.hit_normal {
-                        self.terrain.set_block(
-                            self.load_area_index,
-                            &(hit.hit_pos + GlobalBlockPosition::from(hit_normal)),
-                            BLOCK_LAMP_ORANGE,
-                        );
-                    }
+            }
+        }
+
+        // holding the button breaks the targeted block once its hardness has elapsed
+        if self.time.is_advancing() {
+            let broken = self
+                .block_breaking
+                .update(break_target, self.time.delta_seconds());
+
+            if let Some(broken_pos) = broken {
+                if let Some(broken_id) = self
+                    .terrain
+                    .get_block(self.load_area_index, &broken_pos)
+                {
+                    self.render_engine
+                        .particle_system_mut()
+                        .emit_block_break(broken_pos, broken_id);
                 }
+
+                self.terrain
+                    .set_block(self.load_area_index, &broken_pos, BLOCK_AIR);
+                self.block_edited(&broken_pos);
             }
         }
+        self.render_engine
+            .set_break_overlays(self.block_breaking.overlays());
+
+        // the world is frozen while paused, but mouse look stays live to inspect the scene
+        if self.time.is_advancing() {
+            self.terrain.load_areas_mut()[self.load_area_index]
+                .set_center(self.fly_camera.position / (CHUNK_SIZE as f32));
+
+            self.terrain
+                .update(&mut self.tasks, self.fly_camera.position);
 
-        self.terrain.load_areas_mut()[self.load_area_index]
-            .set_center(self.fly_camera.position / (CHUNK_SIZE as f32));
+            // deterministic tasks are held until flushed, so execute this frame's tasks now
+            if self.tasks.is_deterministic() {
+                self.tasks.block_until_finished();
+            }
+        }
 
-        self.terrain
-            .update(&mut self.tasks, self.fly_camera.position);
+        // the draw stats are from the previous frame, as this frame hasn't been rendered yet
+        let debug_hud_text = if self.debug_hud_visible {
+            self.debug_hud_text()
+        } else {
+            String::new()
+        };
+        self.render_engine
+            .set_overlay_text(&debug_hud_text);
 
         self.input.reset();
     }
 
+    /// Text of the debug HUD shown with F3
+    fn debug_hud_text(&self) -> String {
+        let draw_stats = self.render_engine.terrain_draw_stats();
+        let camera_pos = self.fly_camera.position;
+        let frame_time_stats = self
+            .time
+            .frame_time_percentiles()
+            .unwrap_or_default();
+        let terrain_gpu_time = self
+            .render_engine
+            .last_gpu_frame_time()
+            .map_or("n/a".to_string(), |time| {
+                format!("{:.2} ms", time.as_secs_f64() * 1000.0)
+            });
+        let camera_chunk_pos = ChunkPosition::containing(camera_pos);
+        let camera_chunk_state = self
+            .terrain
+            .chunk_state(self.load_area_index, &camera_chunk_pos);
+        let camera_chunk_solid_blocks = self
+            .terrain
+            .get_chunk(self.load_area_index, &camera_chunk_pos)
+            .map_or("n/a".to_string(), |chunk| {
+                let summary = chunk.summary();
+                if summary.is_full() {
+                    "all".to_string()
+                } else {
+                    summary.solid_count().to_string()
+                }
+            });
+        let load_area = &self.terrain.load_areas()[self.load_area_index];
+        let target = self.target.map_or("none".to_string(), |hit| {
+            format!(
+                "{} {} {}, {:.1} blocks away",
+                hit.hit_pos.x(),
+                hit.hit_pos.y(),
+                hit.hit_pos.z(),
+                hit.distance
+            )
+        });
+        let peak_rss = util::memory::peak_rss().map_or("n/a".to_string(), |bytes| {
+            format!("{} MiB", bytes / (1024 * 1024))
+        });
+
+        format!(
+            "{} fps ({:.2} ms)\n\
+             frame times: {:.2} min, {:.2} avg, {:.2} p99, {:.2} max ms\n\
+             fixed steps: {} this frame, {:.2} ms each\n\
+             terrain gpu time: {}\n\
+             chunks: {} loaded, {} pending, {} visible, {} batches drawn\n\
+             load area: {} chunk radius, {} chunks tall\n\
+             culled: {} by frustum, {} by occlusion\n\
+             triangles: {}\n\
+             pending tasks: {} generation, {} meshing, {} uploads\n\
+             workers: {} active, {} allowed of {}\n\
+             xyz: {:.1} {:.1} {:.1}\n\
+             chunk: {} {} {} ({:?}, {} solid blocks)\n\
+             target: {}\n\
+             skylight: {}\n\
+             shadows: {} cascades, split lambda {:.2}\n\
+             peak memory: {}",
+            self.time.get_frames_last_second(),
+            self.time.delta_seconds_f64() * 1000.0,
+            frame_time_stats.min * 1000.0,
+            frame_time_stats.average * 1000.0,
+            frame_time_stats.p99 * 1000.0,
+            frame_time_stats.max * 1000.0,
+            self.time.fixed_step_count(),
+            self.time.fixed_delta_seconds() * 1000.0,
+            terrain_gpu_time,
+            self.terrain.chunks().len(),
+            self.terrain.pending_chunk_count(),
+            draw_stats.chunks_visible,
+            draw_stats.batches_drawn,
+            load_area.horizontal_radius(),
+            load_area.vertical_range(),
+            draw_stats.chunks_culled_by_frustum,
+            draw_stats.chunks_culled_by_occlusion,
+            draw_stats.triangles_drawn,
+            self.tasks
+                .pending_task_count(TaskStage::Generation),
+            self.tasks
+                .pending_task_count(TaskStage::Meshing),
+            self.render_engine.pending_mesh_upload_count(),
+            self.tasks.active_worker_count(),
+            self.tasks.worker_limit(),
+            self.tasks.total_worker_count(),
+            camera_pos.x,
+            camera_pos.y,
+            camera_pos.z,
+            camera_chunk_pos.x(),
+            camera_chunk_pos.y(),
+            camera_chunk_pos.z(),
+            camera_chunk_state,
+            camera_chunk_solid_blocks,
+            target,
+            self.terrain
+                .get_skylight(&camera_pos.floor().as_ivec3().into())
+                .level(),
+            self.render_engine.shadow_cascade_count(),
+            self.render_engine.shadow_split_lambda(),
+            peak_rss,
+        )
+    }
+
     fn render(&mut self) {
+        // nothing to render to while the window is minimized
+        if self.render_context.is_zero_area() {
+            return;
+        }
+
         let Some(surface_texture) = self
             .render_context
             .get_surface_texture()
@@ -244,6 +1226,7 @@ impl State {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        let render_start = Instant::now();
         self.render_engine.render(
             &self.render_context,
             &output_view,
@@ -252,11 +1235,62 @@ impl State {
             &self.terrain,
             self.load_area_index,
         );
+        let render_time = render_start.elapsed();
 
         surface_texture.present();
+
+        self.record_frame_metrics(render_time);
+    }
+
+    /// Append this frame's metrics to the recording, if recording. Recording stops if the file
+    /// can't be written
+    fn record_frame_metrics(&mut self, render_time: Duration) {
+        let Some(recorder) = &mut self.frame_recorder else {
+            return;
+        };
+
+        let draw_stats = self.render_engine.terrain_draw_stats();
+        let metrics = FrameMetrics {
+            frame_time: self.time.delta(),
+            render_time,
+            terrain_gpu_time: self.render_engine.last_gpu_frame_time(),
+            chunks_loaded: self.terrain.chunks().len(),
+            chunks_pending: self.terrain.pending_chunk_count(),
+            chunks_visible: draw_stats.chunks_visible,
+            batches_drawn: draw_stats.batches_drawn,
+            triangles_drawn: draw_stats.triangles_drawn,
+            meshes_built_total: self
+                .render_engine
+                .mesh_time_stats()
+                .total_recorded(),
+        };
+
+        if let Err(e) = recorder.record(&metrics) {
+            log::error!("couldn't record frame metrics, stopping: {e}");
+            self.frame_recorder = None;
+        }
     }
 }
 
+impl Drop for State {
+    fn drop(&mut self) {
+        // finish the pending tasks, e.g. chunk saves, while the rest of the state is still alive
+        self.tasks.shutdown();
+    }
+}
+
+/// How the cursor is captured for mouse look. Mouse look always uses raw mouse motion, so this
+/// only decides whether the cursor is kept in the window and hidden
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CursorGrab {
+    /// The cursor is free and mouse motion doesn't turn the camera
+    Released,
+    /// The cursor is grabbed by the platform
+    Grabbed,
+    /// The platform can't grab the cursor, so it is moved to the center of the window each frame
+    Warping,
+}
+
 struct WinitApplicationHandler {
     state: Option<State>,
 }
@@ -270,14 +1304,18 @@ impl WinitApplicationHandler {
 impl ApplicationHandler<()> for WinitApplicationHandler {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.state.is_none() {
-            let window_attributes = Window::default_attributes().with_title(WINDOW_TITLE);
+            let window_attributes = Window::default_attributes()
+                .with_title(WINDOW_TITLE)
+                .with_inner_size(INITIAL_WINDOW_SIZE);
             let window = Arc::new(
                 event_loop
                     .create_window(window_attributes)
                     .expect("failed to create window"),
             );
 
-            self.state = Some(State::new(window));
+            let mut state = State::new(window);
+            state.set_cursor_grabbed(state.fly_camera_active);
+            self.state = Some(state);
         }
     }
 
@@ -287,6 +1325,20 @@ impl ApplicationHandler<()> for WinitApplicationHandler {
         match event {
             WindowEvent::CloseRequested => state.close_requested = true,
             WindowEvent::Resized(new_size) => state.resized(new_size),
+            WindowEvent::Focused(false) => state.set_cursor_grabbed(false),
+            // the click that grabs the cursor isn't passed on, so that it doesn't break a block.
+            // The cursor stays free while the camera isn't under the player's control
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                ..
+            } if state.cursor_grab == CursorGrab::Released && state.fly_camera_active => {
+                state.set_cursor_grabbed(true)
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                state
+                    .render_context
+                    .scale_factor_changed(scale_factor)
+            }
             _ => {
                 state.input.handle_window_event(&event);
             }
@@ -295,13 +1347,19 @@ impl ApplicationHandler<()> for WinitApplicationHandler {
 
     fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
         let state = self.state.as_mut().unwrap();
-        state.input.handle_device_event(&event);
+
+        // raw mouse motion turns the camera, so ignore it while the cursor is released
+        if state.cursor_grab != CursorGrab::Released {
+            state.input.handle_device_event(&event);
+        }
     }
 
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         match self.state.as_mut() {
             Some(state) => {
                 if state.close_requested {
+                    state.stop_frame_recording();
+                    state.terrain.save(&mut state.tasks);
                     event_loop.exit();
                 }
                 state.frame();
@@ -1,6 +1,15 @@
+pub mod break_overlay;
+pub mod build_grid;
 pub mod camera;
 pub mod frustum_culling;
+pub mod gpu_timer;
+pub mod particles;
 pub mod render_context;
 pub mod render_engine;
+pub mod render_pass;
+pub mod reticle;
+pub mod selection_outline;
+pub mod shadow;
 pub mod terrain;
+pub mod text;
 pub mod util;
@@ -0,0 +1,195 @@
+use super::{
+    render_context::RenderContext,
+    render_engine::RenderEngine,
+    render_pass::PassTargets,
+    util::{
+        bind_group_builder::BindGroupBuilder,
+        mesh::Vertex,
+        pipeline_builder::RenderPipelineBuilder,
+        shader_source::{shader_source, ReloadablePipeline},
+        texture::{ArrayTexture, TextureConfig, TextureHolder},
+    },
+};
+use crate::block_breaking::{BreakOverlay, DESTROY_STAGE_COUNT};
+
+/// Responsible for drawing destruction stage textures over blocks that are being broken
+#[derive(Debug)]
+pub struct BreakOverlayRenderer {
+    overlays: Vec<BreakOverlay>,
+    instance_buffer: wgpu::Buffer,
+    texture_bind_group: wgpu::BindGroup,
+    /// Rebuilt when the shader is modified
+    pipeline: ReloadablePipeline,
+}
+
+impl BreakOverlayRenderer {
+    /// Maximum number of overlays drawn in a frame: the block being broken and the previous
+    /// target while it fades out
+    const MAX_OVERLAYS: usize = 2;
+
+    pub fn new(
+        cx: &RenderContext,
+        common_uniforms_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        // TODO load textures using proper asset system rather than doing it here
+        let stage_paths: Vec<_> = (0..DESTROY_STAGE_COUNT)
+            .map(|stage| format!("assets/image/destroy/destroy_stage_{stage}.png"))
+            .collect();
+
+        let stage_textures = ArrayTexture::from_files(
+            &cx.device,
+            &cx.queue,
+            &stage_paths,
+            image::ImageFormat::Png,
+            &TextureConfig::default(),
+        )
+        .expect("failed to load destroy stage textures")
+        .with_view_and_sampler(&cx.device, wgpu::SamplerDescriptor::default());
+
+        let (texture_bind_group, texture_bind_group_layout) = BindGroupBuilder::new()
+            .with_label("Break Overlay Texture Bind Group")
+            .with_texture_view(
+                stage_textures.view(),
+                wgpu::TextureViewDimension::D2Array,
+                wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::ShaderStages::FRAGMENT,
+            )
+            .with_sampler(
+                stage_textures.sampler(),
+                wgpu::SamplerBindingType::Filtering,
+                wgpu::ShaderStages::FRAGMENT,
+            )
+            .build(&cx.device);
+
+        let instance_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Break Overlay Instance Buffer"),
+                size: (Self::MAX_OVERLAYS * std::mem::size_of::<BreakOverlayInstance>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let shader = shader_source!("break_overlay.wgsl");
+        let module = shader.create_module(&cx.device);
+
+        let (pipeline, pipeline_layout) = Self::pipeline_builder(cx, &module)
+            .with_bind_group_layout(&texture_bind_group_layout)
+            .with_bind_group_layout(common_uniforms_bind_group_layout)
+            .build(&cx.device);
+
+        Self {
+            overlays: Vec::new(),
+            instance_buffer,
+            texture_bind_group,
+            pipeline: ReloadablePipeline::new(shader, pipeline_layout, pipeline),
+        }
+    }
+
+    fn pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+    ) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .with_label("Break Overlay Pipeline")
+            .with_vertex::<BreakOverlayInstance>()
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_color_target(
+                cx.surface_config.format,
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+                wgpu::ColorWrites::COLOR,
+            )
+            .with_depth(RenderEngine::DEPTH_FORMAT, RenderEngine::DEPTH_COMPARE)
+            .with_depth_write(false)
+            .with_cull_mode(None)
+    }
+
+    /// Called once per frame after the terrain has been drawn, so that the overlays are depth
+    /// tested against it
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        cx: &RenderContext,
+    ) {
+        self.pipeline.reload_if_changed(&cx.device, |module, layout| {
+            Self::pipeline_builder(cx, module)
+                .with_layout(layout)
+                .build_with_existing_layout(&cx.device)
+        });
+
+        if self.overlays.is_empty() {
+            targets.clear(render_encoder);
+            return;
+        }
+
+        let instances: Vec<_> = self
+            .overlays
+            .iter()
+            .map(BreakOverlayInstance::from)
+            .collect();
+
+        cx.queue.write_buffer(
+            &self.instance_buffer,
+            0 as wgpu::BufferAddress,
+            bytemuck::cast_slice(&instances),
+        );
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(self.pipeline.get());
+        render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(1, common_uniforms_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..36, 0..instances.len() as u32);
+    }
+
+    /// Set the overlays to draw, at most `MAX_OVERLAYS` of which are used. Takes effect from the
+    /// next frame
+    pub fn set_overlays(&mut self, overlays: impl IntoIterator<Item = BreakOverlay>) {
+        self.overlays.clear();
+        self.overlays.extend(
+            overlays
+                .into_iter()
+                .take(Self::MAX_OVERLAYS),
+        );
+    }
+}
+
+/// Per-instance data for a destruction overlay
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BreakOverlayInstance {
+    block_pos: [i32; 3],
+    stage: u32,
+    opacity: f32,
+}
+
+impl From<&BreakOverlay> for BreakOverlayInstance {
+    fn from(overlay: &BreakOverlay) -> Self {
+        Self {
+            block_pos: overlay.pos.as_ivec3().to_array(),
+            stage: overlay.stage,
+            opacity: overlay.opacity.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Vertex for BreakOverlayInstance {
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+            0 => Sint32x3,
+            1 => Uint32,
+            2 => Float32,
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
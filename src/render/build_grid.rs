@@ -0,0 +1,202 @@
+use glam::IVec3;
+
+use super::{
+    render_context::RenderContext,
+    render_engine::RenderEngine,
+    render_pass::PassTargets,
+    util::{
+        bind_group_builder::BindGroupBuilder,
+        pipeline_builder::RenderPipelineBuilder,
+        shader_source::{shader_source, ReloadablePipeline},
+    },
+};
+use crate::{
+    terrain::position_types::GlobalBlockPosition,
+    util::face::{FaceIndex, FACE_NORMALS},
+};
+
+/// Axis-aligned plane lying on one face of a block, on which the build grid is drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Plane {
+    /// Block whose face the plane lies on. The grid is centred on this face
+    pub block_pos: GlobalBlockPosition,
+    /// Unit normal of the face, along one of the axes
+    pub normal: IVec3,
+}
+
+impl Plane {
+    pub fn from_block_face(block_pos: GlobalBlockPosition, face: FaceIndex) -> Self {
+        Self {
+            block_pos,
+            normal: FACE_NORMALS[face.as_usize()],
+        }
+    }
+}
+
+/// Responsible for drawing a faint grid of block boundaries on the plane of the targeted face, to
+/// help align structures while building
+#[derive(Debug)]
+pub struct BuildGridRenderer {
+    plane: Option<Plane>,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    /// Rebuilt when the shader is modified
+    pipeline: ReloadablePipeline,
+}
+
+impl BuildGridRenderer {
+    /// Distance from the centre of the targeted face in blocks at which the grid has faded out
+    const RADIUS: f32 = 8.0;
+    /// Width of the grid lines in logical pixels
+    const LINE_WIDTH: f32 = 1.0;
+    /// Linear RGBA colour of the grid lines
+    const COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+
+    pub fn new(
+        cx: &RenderContext,
+        common_uniforms_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Build Grid Uniform Buffer"),
+                size: std::mem::size_of::<BuildGridUniforms>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let (uniform_bind_group, uniform_bind_group_layout) = BindGroupBuilder::new()
+            .with_label("Build Grid Uniforms Bind Group")
+            .with_uniform_buffer(
+                &uniform_buffer,
+                wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            )
+            .build(&cx.device);
+
+        let shader = shader_source!("build_grid.wgsl");
+        let module = shader.create_module(&cx.device);
+
+        let (pipeline, pipeline_layout) = Self::pipeline_builder(cx, &module)
+            .with_bind_group_layout(&uniform_bind_group_layout)
+            .with_bind_group_layout(common_uniforms_bind_group_layout)
+            .build(&cx.device);
+
+        Self {
+            plane: None,
+            uniform_buffer,
+            uniform_bind_group,
+            pipeline: ReloadablePipeline::new(shader, pipeline_layout, pipeline),
+        }
+    }
+
+    fn pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+    ) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .with_label("Build Grid Pipeline")
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_color_target(
+                cx.surface_config.format,
+                Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                wgpu::ColorWrites::COLOR,
+            )
+            .with_depth(RenderEngine::DEPTH_FORMAT, RenderEngine::DEPTH_COMPARE)
+            .with_depth_write(false)
+            .with_topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .with_cull_mode(None)
+    }
+
+    /// Called once per frame after the terrain has been drawn, so that the grid is depth tested
+    /// against it
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        cx: &RenderContext,
+    ) {
+        self.pipeline.reload_if_changed(&cx.device, |module, layout| {
+            Self::pipeline_builder(cx, module)
+                .with_layout(layout)
+                .build_with_existing_layout(&cx.device)
+        });
+
+        let Some(plane) = self.plane else {
+            targets.clear(render_encoder);
+            return;
+        };
+
+        let uniforms = BuildGridUniforms::new(&plane, cx.scale_factor);
+
+        cx.queue.write_buffer(
+            &self.uniform_buffer,
+            0 as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[uniforms]),
+        );
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(self.pipeline.get());
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, common_uniforms_bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+
+    /// Set the plane to draw the grid on, or None to hide the grid. Takes effect from the next
+    /// frame
+    pub fn set_plane(&mut self, plane: Option<Plane>) {
+        self.plane = plane;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BuildGridUniforms {
+    color: [f32; 4],
+    block_pos: [i32; 3],
+    radius: f32,
+    normal: [i32; 3],
+    line_width: f32,
+}
+
+impl BuildGridUniforms {
+    fn new(plane: &Plane, scale_factor: f64) -> Self {
+        let [r, g, b, a] = BuildGridRenderer::COLOR;
+
+        Self {
+            color: [r * a, g * a, b * a, a],
+            block_pos: plane.block_pos.as_ivec3().to_array(),
+            radius: BuildGridRenderer::RADIUS,
+            normal: plane.normal.to_array(),
+            line_width: BuildGridRenderer::LINE_WIDTH * scale_factor as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_lies_on_the_block_face() {
+        let block_pos = GlobalBlockPosition::new(3, -5, 7);
+
+        let top = Plane::from_block_face(block_pos, FaceIndex::POS_Y);
+        assert_eq!(top.normal, IVec3::Y);
+
+        let bottom = Plane::from_block_face(block_pos, FaceIndex::NEG_Y);
+        assert_eq!(bottom.normal, IVec3::NEG_Y);
+
+        let side = Plane::from_block_face(block_pos, FaceIndex::POS_X);
+
+        let uniforms = BuildGridUniforms::new(&side, 2.0);
+        assert_eq!(uniforms.block_pos, [3, -5, 7]);
+        assert_eq!(uniforms.normal, [1, 0, 0]);
+        assert_eq!(uniforms.line_width, 2.0 * BuildGridRenderer::LINE_WIDTH);
+
+        // the uniform struct must match the 16-byte aligned layout in build_grid.wgsl
+        assert_eq!(std::mem::size_of::<BuildGridUniforms>(), 48);
+    }
+}
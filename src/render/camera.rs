@@ -1,7 +1,10 @@
-use glam::{Mat4, Vec3};
+use glam::{IVec3, Mat4, Vec3};
 use winit::dpi::PhysicalSize;
 
-use crate::util::transform::Transform;
+use crate::{
+    terrain::{chunk::CHUNK_SIZE_I32, position_types::ChunkPosition},
+    util::transform::Transform,
+};
 
 #[derive(Clone, Copy, Debug)]
 pub enum Projection {
@@ -40,8 +43,19 @@ impl Projection {
             } => Mat4::perspective_rh(fov_y_radians, aspect_ratio, z_near, z_far),
         }
     }
+
+    /// Distances from the camera to the near and far planes
+    pub fn depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Orthographic { near, far, .. } => (near, far),
+            Projection::Perspective { z_near, z_far, .. } => (z_near, z_far),
+        }
+    }
 }
 
+/// NB: the world is right-handed with +Y up. With an identity rotation the camera looks along -Z,
+/// with +X to the right of the screen, and the projection maps depth to wgpu's 0..1 range. Front
+/// faces wind anticlockwise on screen, and back faces are culled
 #[derive(Clone, Copy, Debug)]
 pub struct Camera {
     pub transform: Transform,
@@ -60,11 +74,33 @@ impl Camera {
         self.transform.as_matrix().inverse()
     }
 
+    /// Origin for camera-relative rendering: the corner of the chunk containing the camera.
+    /// Vertex positions are made relative to this before they reach the GPU, so that they stay
+    /// small and precise even far from the world origin
+    pub fn render_origin(&self) -> IVec3 {
+        ChunkPosition::containing(self.pos()).as_ivec3() * CHUNK_SIZE_I32
+    }
+
+    /// View matrix for positions given relative to `origin` rather than the world origin
+    pub fn view_matrix_relative_to(&self, origin: IVec3) -> Mat4 {
+        Transform {
+            translation: self.transform.translation - origin.as_vec3(),
+            ..self.transform
+        }
+        .as_matrix()
+        .inverse()
+    }
+
     pub fn projection_matrix(&self) -> Mat4 {
         self.projection.as_matrix()
     }
 
     pub fn resized(&mut self, new_size: PhysicalSize<u32>) {
+        // keep the old aspect ratio if the window has zero area, e.g. when minimized
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+
         if let Projection::Perspective { aspect_ratio, .. } = &mut self.projection {
             *aspect_ratio = new_size.width as f32 / new_size.height as f32;
         }
@@ -77,8 +113,125 @@ impl Camera {
 
     /// Returns the direction the camera is looking in
     pub fn look_dir(&self) -> Vec3 {
-        self.transform
-            .rotation
-            .mul_vec3(Vec3::NEG_Z)
+        self.transform.forward()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+
+    fn aspect_ratio(camera: &Camera) -> f32 {
+        match camera.projection {
+            Projection::Perspective { aspect_ratio, .. } => aspect_ratio,
+            Projection::Orthographic { .. } => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn resize_to_zero_keeps_aspect_ratio() {
+        let mut camera = Camera::new(Transform::IDENTITY, Projection::Perspective {
+            aspect_ratio: 1.0,
+            fov_y_radians: 1.0,
+            z_near: 0.01,
+            z_far: 1000.0,
+        });
+
+        camera.resized(PhysicalSize::new(1600, 900));
+        assert_eq!(aspect_ratio(&camera), 1600.0 / 900.0);
+
+        for size in [
+            PhysicalSize::new(0, 0),
+            PhysicalSize::new(1600, 0),
+            PhysicalSize::new(0, 900),
+        ] {
+            camera.resized(size);
+            assert_eq!(aspect_ratio(&camera), 1600.0 / 900.0);
+            assert!(camera
+                .projection_matrix()
+                .is_finite());
+        }
+
+        camera.resized(PhysicalSize::new(800, 800));
+        assert_eq!(aspect_ratio(&camera), 1.0);
+    }
+
+    /// Project a world position to physical pixels from the top left corner of a screen of the
+    /// given size, as the terrain shader and rasterizer would
+    fn world_to_screen(camera: &Camera, pos: Vec3, screen_size: Vec2) -> Vec2 {
+        let ndc = (camera.projection_matrix() * camera.view_matrix()).project_point3(pos);
+        (Vec2::new(ndc.x, -ndc.y) + 1.0) * 0.5 * screen_size
+    }
+
+    #[test]
+    fn coordinate_convention_is_right_handed_y_up() {
+        let screen_size = Vec2::new(1600.0, 900.0);
+        let mut camera = Camera::new(Transform::IDENTITY, Projection::Perspective {
+            aspect_ratio: screen_size.x / screen_size.y,
+            fov_y_radians: std::f32::consts::FRAC_PI_2,
+            z_near: 0.01,
+            z_far: 1000.0,
+        });
+        camera.transform.translation = Vec3::new(0.5, 0.5, 5.0);
+        assert_eq!(camera.look_dir(), Vec3::NEG_Z);
+
+        // centre of the block at `pos`, seen from 4.5 blocks away along +Z
+        let block_center_on_screen = |pos: IVec3| {
+            world_to_screen(&camera, pos.as_vec3() + 0.5, screen_size)
+        };
+
+        // the block in front of the camera is in the middle of the screen
+        let center = block_center_on_screen(IVec3::ZERO);
+        assert!(center.abs_diff_eq(screen_size * 0.5, 1e-3));
+
+        // +X is to the right: tan(45) * 4.5 blocks span half the screen height
+        let right = block_center_on_screen(IVec3::new(3, 0, 0));
+        assert!(right.abs_diff_eq(Vec2::new(800.0 + 450.0 * 3.0 / 4.5, 450.0), 1e-3));
+
+        // +Y is up, towards the top of the screen
+        let up = block_center_on_screen(IVec3::new(0, 3, 0));
+        assert!(up.abs_diff_eq(Vec2::new(800.0, 450.0 - 450.0 * 3.0 / 4.5), 1e-3));
+
+        // +Z is towards the camera, so a block at +Z is nearer and drawn in front
+        let depth = |pos: IVec3| {
+            (camera.projection_matrix() * camera.view_matrix())
+                .project_point3(pos.as_vec3() + 0.5)
+                .z
+        };
+        assert!(depth(IVec3::new(0, 0, 2)) < depth(IVec3::ZERO));
+        assert!((0.0..1.0).contains(&depth(IVec3::ZERO)));
+    }
+
+    #[test]
+    fn camera_relative_positions_stay_small_far_from_origin() {
+        let mut camera = Camera::new(Transform::IDENTITY, Projection::Perspective {
+            aspect_ratio: 1.0,
+            fov_y_radians: 1.0,
+            z_near: 0.01,
+            z_far: 1000.0,
+        });
+        camera.transform.translation = Vec3::new(1_000_000.25, 70.5, -2_000_000.5);
+
+        let origin = camera.render_origin();
+        let view_matrix = camera.view_matrix_relative_to(origin);
+
+        // the camera is within a chunk of the origin
+        assert!(view_matrix
+            .w_axis
+            .truncate()
+            .abs()
+            .max_element()
+            <= CHUNK_SIZE_I32 as f32);
+
+        // a block next to the camera, offset as in the terrain shader
+        let block_pos = IVec3::new(1_000_003, 68, -2_000_002);
+        let relative_pos = (block_pos - origin).as_vec3();
+        assert!(relative_pos.abs().max_element() <= 2.0 * CHUNK_SIZE_I32 as f32);
+
+        // its position in view space is exact, which it wouldn't be using world space positions
+        let view_pos = view_matrix.transform_point3(relative_pos);
+        assert_eq!(view_pos, Vec3::new(2.75, -2.5, -1.5));
     }
 }
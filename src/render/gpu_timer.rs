@@ -0,0 +1,249 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+use super::{render_context::RenderContext, render_pass::PassTargets};
+
+/// Number of buffers the timestamps are copied to for reading back. Reading back takes a few
+/// frames, and frames are skipped while every buffer is still waiting to be read
+const READBACK_BUFFER_COUNT: usize = 3;
+
+/// Size in bytes of the two timestamps written at the start and end of the timed pass
+const TIMESTAMPS_SIZE: wgpu::BufferAddress = 2 * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+
+/// Measures how long the GPU takes to execute one render pass, using timestamp queries written at
+/// the start and end of the pass. The timestamps are read back asynchronously, so the measured
+/// duration lags a few frames behind. Does nothing if the device doesn't support
+/// `TIMESTAMP_QUERY`
+#[derive(Debug)]
+pub struct GpuTimer {
+    /// None if timestamp queries are unsupported
+    queries: Option<TimestampQueries>,
+    last_duration: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct TimestampQueries {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffers: Vec<ReadbackBuffer>,
+    /// Index of the readback buffer the timestamps are copied to this frame, or None if every
+    /// buffer is still waiting to be read
+    current: Option<usize>,
+    /// Index of the readback buffer this frame's timestamps have been copied to, once the timed
+    /// pass has been recorded
+    resolved: Option<usize>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    /// Receives the index of each readback buffer once it is mapped, and whether mapping it
+    /// succeeded
+    mapped_receiver: Receiver<(usize, bool)>,
+    mapped_sender: Sender<(usize, bool)>,
+}
+
+#[derive(Debug)]
+struct ReadbackBuffer {
+    buffer: wgpu::Buffer,
+    /// Whether the buffer holds timestamps that haven't been read yet
+    in_use: bool,
+}
+
+impl GpuTimer {
+    pub fn new(cx: &RenderContext) -> Self {
+        let queries = cx
+            .supported_features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| TimestampQueries::new(cx));
+
+        if queries.is_none() {
+            log::info!("timestamp queries are unsupported, GPU pass times won't be measured");
+        }
+
+        Self {
+            queries,
+            last_duration: None,
+        }
+    }
+
+    /// Read back the timestamps of any earlier frames that the GPU has finished, and pick a
+    /// buffer to copy this frame's timestamps to. Called at the start of each frame
+    pub fn begin_frame(&mut self, cx: &RenderContext) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+
+        if let Some(duration) = queries.read_finished(cx) {
+            self.last_duration = Some(duration);
+        }
+
+        queries.current = queries
+            .readback_buffers
+            .iter()
+            .position(|readback| !readback.in_use);
+        queries.resolved = None;
+    }
+
+    /// Make the pass drawn to `targets` write timestamps at its start and end, unless no
+    /// timestamps can be recorded this frame
+    pub fn time_pass<'a>(&'a self, targets: PassTargets<'a>) -> PassTargets<'a> {
+        match &self.queries {
+            Some(queries) if queries.current.is_some() => {
+                targets.with_timestamps(&queries.query_set, 0)
+            }
+            _ => targets,
+        }
+    }
+
+    /// Copy this frame's timestamps to a readback buffer. Called after the timed pass has been
+    /// recorded, and only if it was recorded, as the timestamps are otherwise never written
+    pub fn resolve(&mut self, render_encoder: &mut wgpu::CommandEncoder) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        let Some(current) = queries.current.take() else {
+            return;
+        };
+        queries.resolved = Some(current);
+
+        render_encoder.resolve_query_set(&queries.query_set, 0..2, &queries.resolve_buffer, 0);
+        render_encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &queries.readback_buffers[current].buffer,
+            0,
+            TIMESTAMPS_SIZE,
+        );
+    }
+
+    /// Start reading back this frame's timestamps, if they were resolved. Called after the frame
+    /// has been submitted
+    pub fn submitted(&mut self) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        let Some(current) = queries.resolved.take() else {
+            return;
+        };
+
+        let readback = &mut queries.readback_buffers[current];
+        readback.in_use = true;
+
+        let mapped_sender = queries.mapped_sender.clone();
+        readback
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                // the timer may have been dropped, in which case nobody is waiting
+                let _ = mapped_sender.send((current, result.is_ok()));
+            });
+    }
+
+    /// GPU time taken by the timed pass in the most recent frame whose timestamps have been read
+    /// back, or None if nothing has been measured yet or timestamp queries are unsupported
+    pub fn last_duration(&self) -> Option<Duration> {
+        self.last_duration
+    }
+}
+
+impl TimestampQueries {
+    fn new(cx: &RenderContext) -> Self {
+        let query_set = cx
+            .device
+            .create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("GPU Timer Query Set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            });
+
+        let resolve_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("GPU Timer Resolve Buffer"),
+                size: TIMESTAMPS_SIZE,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+
+        let readback_buffers = (0..READBACK_BUFFER_COUNT)
+            .map(|_| ReadbackBuffer {
+                buffer: cx
+                    .device
+                    .create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("GPU Timer Readback Buffer"),
+                        size: TIMESTAMPS_SIZE,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                in_use: false,
+            })
+            .collect();
+
+        let (mapped_sender, mapped_receiver) = mpsc::channel();
+
+        Self {
+            query_set,
+            resolve_buffer,
+            readback_buffers,
+            current: None,
+            resolved: None,
+            timestamp_period: cx.queue.get_timestamp_period(),
+            mapped_receiver,
+            mapped_sender,
+        }
+    }
+
+    /// Read the timestamps from every readback buffer that has been mapped since the last call,
+    /// freeing the buffers for reuse. Returns the duration from the most recent of them
+    fn read_finished(&mut self, cx: &RenderContext) -> Option<Duration> {
+        // run the callbacks of any buffers that have finished mapping, without waiting
+        cx.device.poll(wgpu::Maintain::Poll);
+
+        let mut last_duration = None;
+
+        for (index, mapped) in self.mapped_receiver.try_iter() {
+            let readback = &mut self.readback_buffers[index];
+
+            if mapped {
+                let timestamps: [u64; 2] = {
+                    let view = readback.buffer.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned(&view)
+                };
+                readback.buffer.unmap();
+
+                last_duration = Some(timestamps_to_duration(timestamps, self.timestamp_period));
+            }
+
+            readback.in_use = false;
+        }
+
+        last_duration
+    }
+}
+
+/// Duration between a pair of timestamps, given the number of nanoseconds per tick. Zero if the
+/// end is before the start, which happens if the pass was never begun
+fn timestamps_to_duration([start, end]: [u64; 2], timestamp_period: f32) -> Duration {
+    let ticks = end.saturating_sub(start);
+    Duration::from_nanos((ticks as f64 * timestamp_period as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_convert_to_durations_with_the_period() {
+        assert_eq!(
+            timestamps_to_duration([1_000, 3_000], 1.0),
+            Duration::from_micros(2)
+        );
+        assert_eq!(
+            timestamps_to_duration([1_000, 3_000], 83.333),
+            Duration::from_nanos(166_666)
+        );
+
+        // timestamps that were never written are zero
+        assert_eq!(timestamps_to_duration([5_000, 0], 1.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,328 @@
+use glam::Vec3;
+
+use super::{
+    render_context::RenderContext,
+    render_engine::RenderEngine,
+    render_pass::PassTargets,
+    util::{
+        mesh::Vertex,
+        pipeline_builder::RenderPipelineBuilder,
+        shader_source::{shader_source, ReloadablePipeline},
+    },
+};
+use crate::{
+    block::{
+        model::{BlockFace, BlockModel},
+        BlockId, BLOCKS,
+    },
+    terrain::position_types::GlobalBlockPosition,
+};
+
+/// Maximum number of particles alive at once. When more are emitted, the oldest are replaced
+pub const MAX_PARTICLES: usize = 1024;
+
+/// Seconds that a particle lives for. Must match `LIFETIME` in particles.wgsl
+pub const PARTICLE_LIFETIME: f32 = 1.0;
+
+/// Number of particles emitted when a block is broken
+pub const PARTICLES_PER_BREAK: usize = 24;
+
+/// Short-lived particles such as the fragments of broken blocks, drawn as camera-facing quads.
+/// Particles are simulated entirely in the shader from their spawn time, so each is only written
+/// to the GPU once
+#[derive(Debug)]
+pub struct ParticleSystem {
+    ring: ParticleRing,
+    /// Particles emitted since the last frame, whose spawn times are set when they are uploaded
+    pending: Vec<ParticleInstance>,
+    /// Time that the most recent particle was spawned, to skip drawing once all have expired
+    last_spawn_time: Option<f32>,
+    /// State of the generator used to scatter particles
+    rng_state: u32,
+    instance_buffer: wgpu::Buffer,
+    /// Rebuilt when the shader is modified
+    pipeline: ReloadablePipeline,
+}
+
+impl ParticleSystem {
+    /// `texture_bind_group_layout` is the layout of the terrain texture array bind group, which
+    /// particles are textured with
+    pub fn new(
+        cx: &RenderContext,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        common_uniforms_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let instance_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Particle Instance Buffer"),
+                size: (MAX_PARTICLES * std::mem::size_of::<ParticleInstance>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let shader = shader_source!("particles.wgsl");
+        let module = shader.create_module(&cx.device);
+
+        let (pipeline, pipeline_layout) = Self::pipeline_builder(cx, &module)
+            .with_bind_group_layout(texture_bind_group_layout)
+            .with_bind_group_layout(common_uniforms_bind_group_layout)
+            .build(&cx.device);
+
+        Self {
+            ring: ParticleRing::new(MAX_PARTICLES),
+            pending: Vec::new(),
+            last_spawn_time: None,
+            rng_state: 0x9e37_79b9,
+            instance_buffer,
+            pipeline: ReloadablePipeline::new(shader, pipeline_layout, pipeline),
+        }
+    }
+
+    fn pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+    ) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .with_label("Particle Pipeline")
+            .with_vertex::<ParticleInstance>()
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_color_target(
+                cx.surface_config.format,
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+                wgpu::ColorWrites::COLOR,
+            )
+            .with_depth(RenderEngine::DEPTH_FORMAT, RenderEngine::DEPTH_COMPARE)
+            .with_depth_write(false)
+            .with_cull_mode(None)
+    }
+
+    /// Called once per frame after the terrain has been drawn, so that particles are depth tested
+    /// against it. `now` is the time in the common uniforms, which the shader animates particles
+    /// with
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        texture_bind_group: &wgpu::BindGroup,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        cx: &RenderContext,
+        now: f32,
+    ) {
+        self.pipeline.reload_if_changed(&cx.device, |module, layout| {
+            Self::pipeline_builder(cx, module)
+                .with_layout(layout)
+                .build_with_existing_layout(&cx.device)
+        });
+
+        self.upload_pending(cx, now);
+
+        let all_expired = self
+            .last_spawn_time
+            .is_none_or(|spawn_time| now - spawn_time > PARTICLE_LIFETIME);
+
+        if all_expired {
+            self.ring.clear();
+            self.last_spawn_time = None;
+            targets.clear(render_encoder);
+            return;
+        }
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(self.pipeline.get());
+        render_pass.set_bind_group(0, texture_bind_group, &[]);
+        render_pass.set_bind_group(1, common_uniforms_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.ring.len() as u32);
+    }
+
+    /// Stamp particles emitted since the last frame with the current time and write them over the
+    /// oldest slots of the instance buffer
+    fn upload_pending(&mut self, cx: &RenderContext, now: f32) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let stride = std::mem::size_of::<ParticleInstance>();
+
+        for mut particle in self.pending.drain(..) {
+            particle.spawn_time = now;
+            let slot = self.ring.push(particle);
+
+            cx.queue.write_buffer(
+                &self.instance_buffer,
+                (slot * stride) as wgpu::BufferAddress,
+                bytemuck::bytes_of(&particle),
+            );
+        }
+
+        self.last_spawn_time = Some(now);
+    }
+
+    /// Emit a burst of fragments of the given block, textured like it, from the block at `pos`.
+    /// Blocks with an empty model emit nothing. The particles appear from the next frame
+    pub fn emit_block_break(&mut self, pos: GlobalBlockPosition, block_id: BlockId) {
+        let Some(face) = particle_face(&BLOCKS[block_id.0 as usize].model) else {
+            return;
+        };
+
+        for _ in 0..PARTICLES_PER_BREAK {
+            let offset = Vec3::new(self.next_random(), self.next_random(), self.next_random());
+
+            // fly outwards from the centre of the block, and slightly upwards
+            let velocity = (offset - 0.5) * 4.0 + Vec3::Y * (1.0 + 2.0 * self.next_random());
+
+            // each particle shows a random quarter of the texture
+            let uv_offset = [
+                (self.next_random() * 3.0).floor() * 0.25,
+                (self.next_random() * 3.0).floor() * 0.25,
+            ];
+
+            self.pending.push(ParticleInstance {
+                block_pos: pos.as_ivec3().to_array(),
+                texture_index: face.texture_index as u32,
+                offset: offset.to_array(),
+                spawn_time: 0.0,
+                velocity: velocity.to_array(),
+                tint_index: face.tint_index as u32,
+                uv_offset,
+            });
+        }
+    }
+
+    /// Pseudo-random number in [0, 1), from a xorshift generator
+    fn next_random(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+        (self.rng_state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Face whose texture the fragments of a broken block use, or None if the block has no faces
+fn particle_face(model: &BlockModel) -> Option<BlockFace> {
+    match model {
+        BlockModel::Empty => None,
+        BlockModel::FullBlock(faces)
+        | BlockModel::Cutout { faces, .. }
+        | BlockModel::Translucent(faces) => Some(faces[0]),
+        BlockModel::Cross(face) => Some(*face),
+        BlockModel::MicroVoxels(boxes) => boxes
+            .first()
+            .map(|micro_voxel_box| micro_voxel_box.faces[0]),
+    }
+}
+
+/// Fixed-capacity ring of particles. Once full, each new particle replaces the oldest
+#[derive(Debug)]
+struct ParticleRing {
+    particles: Vec<ParticleInstance>,
+    capacity: usize,
+    /// Slot that the next particle is written to
+    next: usize,
+}
+
+impl ParticleRing {
+    fn new(capacity: usize) -> Self {
+        Self {
+            particles: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    /// Add a particle, returning the slot it was written to
+    fn push(&mut self, particle: ParticleInstance) -> usize {
+        let slot = self.next;
+
+        if slot < self.particles.len() {
+            self.particles[slot] = particle;
+        } else {
+            self.particles.push(particle);
+        }
+
+        self.next = (self.next + 1) % self.capacity;
+        slot
+    }
+
+    fn clear(&mut self) {
+        self.particles.clear();
+        self.next = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.particles.len()
+    }
+}
+
+/// Per-instance data for a particle
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleInstance {
+    /// Block that the particle was emitted from
+    block_pos: [i32; 3],
+    texture_index: u32,
+    /// Starting position within the block
+    offset: [f32; 3],
+    spawn_time: f32,
+    /// Starting velocity in blocks per second
+    velocity: [f32; 3],
+    tint_index: u32,
+    /// Corner of the part of the texture shown on the particle
+    uv_offset: [f32; 2],
+}
+
+impl Vertex for ParticleInstance {
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+            0 => Sint32x3,
+            1 => Uint32,
+            2 => Float32x3,
+            3 => Float32,
+            4 => Float32x3,
+            5 => Uint32,
+            6 => Float32x2,
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_replaces_the_oldest_particles_once_full() {
+        let mut ring = ParticleRing::new(3);
+        let particle = |spawn_time| ParticleInstance {
+            spawn_time,
+            ..Default::default()
+        };
+
+        let slots: Vec<_> = (0..5)
+            .map(|i| ring.push(particle(i as f32)))
+            .collect();
+        assert_eq!(slots, [0, 1, 2, 0, 1]);
+        assert_eq!(ring.len(), 3);
+
+        // the two oldest particles were replaced by the two newest
+        let spawn_times: Vec<_> = ring
+            .particles
+            .iter()
+            .map(|particle| particle.spawn_time)
+            .collect();
+        assert_eq!(spawn_times, [3.0, 4.0, 2.0]);
+
+        ring.clear();
+        assert_eq!(ring.len(), 0);
+        assert_eq!(ring.push(particle(5.0)), 0);
+    }
+}
@@ -6,35 +6,113 @@ use winit::{dpi::PhysicalSize, window::Window};
 #[derive(Debug)]
 pub struct RenderContext {
     pub window_size: PhysicalSize<u32>,
+    /// Ratio of physical pixels to logical pixels for the window
+    pub scale_factor: f64,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface: wgpu::Surface<'static>,
     pub surface_config: wgpu::SurfaceConfiguration,
+    /// Information about the adapter (GPU and backend) in use
+    adapter_info: wgpu::AdapterInfo,
+    /// Present modes supported by the surface on this adapter
+    supported_present_modes: Vec<wgpu::PresentMode>,
 }
 
 impl RenderContext {
+    /// Features that are enabled if the adapter supports them. Code relying on one of these must
+    /// check `supported_features` first
+    pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE
+        .union(wgpu::Features::TIMESTAMP_QUERY)
+        .union(wgpu::Features::INDIRECT_FIRST_INSTANCE)
+        .union(wgpu::Features::MULTI_DRAW_INDIRECT)
+        .union(wgpu::Features::TEXTURE_COMPRESSION_BC);
+
     pub fn new(window: Arc<Window>) -> Self {
         let window_size = window.inner_size();
-
-        let (device, queue, surface, surface_config) = init_wgpu(window);
+        let scale_factor = window.scale_factor();
+
+        let (device, queue, surface, surface_config, adapter_info, supported_present_modes) =
+            init_wgpu(window);
+
+        log::info!(
+            "using {} ({:?}, {:?} backend, driver {} {})",
+            adapter_info.name,
+            adapter_info.device_type,
+            adapter_info.backend,
+            adapter_info.driver,
+            adapter_info.driver_info
+        );
+        log::info!("enabled optional features: {:?}", device.features());
+        log::info!("supported present modes: {:?}", supported_present_modes);
 
         Self {
             window_size,
+            scale_factor,
             device,
             queue,
             surface,
             surface_config,
+            adapter_info,
+            supported_present_modes,
         }
     }
 
+    /// Information about the adapter in use, including its name and backend
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Features enabled on the device: the subset of `OPTIONAL_FEATURES` supported by the adapter
+    pub fn supported_features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    /// Present mode the surface is configured with
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.surface_config.present_mode
+    }
+
+    /// Reconfigure the surface to present with the given mode, e.g. `Immediate` for the lowest
+    /// latency or `Mailbox` for low latency without tearing. If the mode isn't supported, falls
+    /// back to `Fifo`, which is always supported. Returns the mode that was actually selected
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let selected = select_present_mode(present_mode, &self.supported_present_modes);
+
+        if selected != present_mode {
+            log::warn!("present mode {present_mode:?} is not supported, using {selected:?} instead");
+        }
+
+        self.surface_config.present_mode = selected;
+        self.surface
+            .configure(&self.device, &self.surface_config);
+
+        selected
+    }
+
     pub fn resized(&mut self, new_size: PhysicalSize<u32>) {
         self.window_size = new_size;
-        self.surface_config.width = new_size.width;
-        self.surface_config.height = new_size.height;
+
+        // the window is minimized; keep the old surface configuration until it is restored
+        if self.is_zero_area() {
+            return;
+        }
+
+        self.surface_config.width = new_size.width.max(1);
+        self.surface_config.height = new_size.height.max(1);
         self.surface
             .configure(&self.device, &self.surface_config);
     }
 
+    pub fn scale_factor_changed(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// True if the window has zero area, e.g. when minimized. Nothing should be rendered while
+    /// this is the case
+    pub fn is_zero_area(&self) -> bool {
+        self.window_size.width == 0 || self.window_size.height == 0
+    }
+
     pub fn get_surface_texture(&mut self) -> Option<wgpu::SurfaceTexture> {
         match self.surface.get_current_texture() {
             Ok(tex) => Some(tex),
@@ -56,7 +134,26 @@ impl RenderContext {
     }
 }
 
-/// Create the core wgpu resources: device, queue, surface and surface configuration
+/// Returns the requested present mode if it is supported, or otherwise `Fifo`, which every surface
+/// supports. The `Auto` modes are always supported as wgpu resolves them itself
+fn select_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    let is_auto = matches!(
+        requested,
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+    );
+
+    if is_auto || supported.contains(&requested) {
+        requested
+    } else {
+        wgpu::PresentMode::Fifo
+    }
+}
+
+/// Create the core wgpu resources: device, queue, surface and surface configuration, along with
+/// information about the adapter and the present modes supported by the surface
 fn init_wgpu(
     window: Arc<Window>,
 ) -> (
@@ -64,6 +161,8 @@ fn init_wgpu(
     wgpu::Queue,
     wgpu::Surface<'static>,
     wgpu::SurfaceConfiguration,
+    wgpu::AdapterInfo,
+    Vec<wgpu::PresentMode>,
 ) {
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
         backends: wgpu::Backends::PRIMARY,
@@ -86,7 +185,7 @@ fn init_wgpu(
     let (device, queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::POLYGON_MODE_LINE,
+                required_features: adapter.features() & RenderContext::OPTIONAL_FEATURES,
                 required_limits: wgpu::Limits::default(),
                 label: None,
             },
@@ -111,8 +210,8 @@ fn init_wgpu(
     let surface_config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
-        width: window.inner_size().width,
-        height: window.inner_size().height,
+        width: window.inner_size().width.max(1),
+        height: window.inner_size().height.max(1),
         present_mode: wgpu::PresentMode::AutoNoVsync,
         alpha_mode: wgpu::CompositeAlphaMode::Auto,
         view_formats: vec![],
@@ -122,5 +221,35 @@ fn init_wgpu(
     surface.configure(&device, &surface_config);
     surface.configure(&device, &surface_config);
 
-    (device, queue, surface, surface_config)
+    (
+        device,
+        queue,
+        surface,
+        surface_config,
+        adapter.get_info(),
+        surface_caps.present_modes,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_present_modes_fall_back_to_fifo() {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox];
+
+        assert_eq!(
+            select_present_mode(wgpu::PresentMode::Mailbox, &supported),
+            wgpu::PresentMode::Mailbox
+        );
+        assert_eq!(
+            select_present_mode(wgpu::PresentMode::Immediate, &supported),
+            wgpu::PresentMode::Fifo
+        );
+        assert_eq!(
+            select_present_mode(wgpu::PresentMode::AutoNoVsync, &[wgpu::PresentMode::Fifo]),
+            wgpu::PresentMode::AutoNoVsync
+        );
+    }
 }
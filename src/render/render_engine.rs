@@ -1,18 +1,42 @@
+use std::time::Duration;
+
 use generational_arena::Index;
+use glam::Vec3;
+use winit::dpi::PhysicalSize;
 
 use super::{
+    break_overlay::BreakOverlayRenderer,
+    build_grid::{BuildGridRenderer, Plane},
     camera::{Camera, Projection},
     frustum_culling::{FrustumCullingRegions},
+    gpu_timer::GpuTimer,
+    particles::ParticleSystem,
     render_context::RenderContext,
-    terrain::{TerrainCullMode, TerrainRenderer},
+    render_pass::{plan_passes, Pass},
+    reticle::{ReticleRenderer, ReticleStyle},
+    selection_outline::SelectionOutlineRenderer,
+    shadow::{ShadowMaps, ShadowUniforms},
+    terrain::{
+        mesh_throttle::MeshThrottle,
+        mesh_time_stats::MeshTimeStats,
+        meshing::{MeshingStrategy, NormalMode},
+        TerrainCullMode, TerrainDrawStats, TerrainRenderer,
+    },
+    text::TextRenderer,
     util::{
         bind_group_builder::BindGroupBuilder,
         texture::{DepthTexture, TextureHolder, WithViewAndSampler},
     },
 };
 use crate::{
+    block_breaking::BreakOverlay,
     tasks::Tasks,
-    terrain::{load_area::LoadArea, Terrain},
+    terrain::{
+        chunk::{Chunk, CHUNK_SIZE},
+        load_area::LoadArea,
+        position_types::{ChunkPosition, GlobalBlockPosition},
+        Terrain,
+    },
     time::Time,
     util::{size::Size3, transform::Transform, DEGREE},
 };
@@ -22,20 +46,47 @@ pub struct RenderEngine {
     common_uniforms: CommonUniforms,
     common_uniforms_buffer: wgpu::Buffer,
     common_uniforms_bind_group: wgpu::BindGroup,
+    shadow_maps: ShadowMaps,
     terrain_renderer: TerrainRenderer,
+    build_grid_renderer: BuildGridRenderer,
+    selection_outline_renderer: SelectionOutlineRenderer,
+    particle_system: ParticleSystem,
+    break_overlay_renderer: BreakOverlayRenderer,
+    reticle_renderer: ReticleRenderer,
+    text_renderer: TextRenderer,
     camera: Camera,
     frustum_culling_regions: FrustumCullingRegions,
+    /// Whether each pass is drawn, indexed by `Pass::as_usize`
+    enabled_passes: [bool; Pass::ALL.len()],
+    /// Colour the sky pass clears the output to
+    sky_color: wgpu::Color,
+    /// Measures the GPU time of the terrain pass
+    gpu_timer: GpuTimer,
 }
 
 impl RenderEngine {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
     pub const DEPTH_COMPARE: wgpu::CompareFunction = wgpu::CompareFunction::Less;
     pub const FRUSTUM_CULLING_REGION_SIZE_CHUNKS: usize = 8;
+    pub const DEFAULT_AO_STRENGTH: f32 = 1.0;
+    pub const DEFAULT_AO_CURVE: f32 = 0.75;
+    /// Direction towards the sun, chosen so that each of the six faces of a block has a different
+    /// brightness. Need not be normalized
+    pub const DEFAULT_SUN_DIRECTION: Vec3 = Vec3::new(0.3, 1.0, 0.6);
+    pub const DEFAULT_SUN_AMBIENT: f32 = 0.4;
+    /// Full daylight
+    pub const DEFAULT_DAY_FRACTION: f32 = 1.0;
 
     pub fn new(cx: &RenderContext, load_area: &LoadArea) -> Self {
+        // the window may have zero area at startup, so size textures for at least one pixel
+        let initial_size = PhysicalSize::new(
+            cx.window_size.width.max(1),
+            cx.window_size.height.max(1),
+        );
+
         let depth_texture = DepthTexture::new(
             &cx.device,
-            cx.window_size,
+            initial_size,
             Self::DEPTH_FORMAT,
             Self::DEPTH_COMPARE,
             Some("Depth Texture"),
@@ -49,7 +100,14 @@ impl RenderEngine {
             },
         );
 
-        let common_uniforms = CommonUniforms::default();
+        let common_uniforms = CommonUniforms {
+            ao_strength: Self::DEFAULT_AO_STRENGTH,
+            ao_curve: Self::DEFAULT_AO_CURVE,
+            sun_direction: Self::DEFAULT_SUN_DIRECTION.normalize().to_array(),
+            sun_ambient: Self::DEFAULT_SUN_AMBIENT,
+            day_fraction: Self::DEFAULT_DAY_FRACTION,
+            ..Default::default()
+        };
 
         let common_uniforms_buffer = cx
             .device
@@ -65,17 +123,39 @@ impl RenderEngine {
                 .with_uniform_buffer(&common_uniforms_buffer, wgpu::ShaderStages::all())
                 .build(&cx.device);
 
+        let shadow_maps = ShadowMaps::new(cx);
+
         let terrain_renderer = TerrainRenderer::new(
             cx,
             &common_uniforms_bind_group_layout,
+            &shadow_maps,
             load_area,
             TerrainCullMode::VisibilitySearch,
         );
 
+        let build_grid_renderer =
+            BuildGridRenderer::new(cx, &common_uniforms_bind_group_layout);
+
+        let selection_outline_renderer =
+            SelectionOutlineRenderer::new(cx, &common_uniforms_bind_group_layout);
+
+        let particle_system = ParticleSystem::new(
+            cx,
+            terrain_renderer.texture_bind_group_layout(),
+            &common_uniforms_bind_group_layout,
+        );
+
+        let break_overlay_renderer =
+            BreakOverlayRenderer::new(cx, &common_uniforms_bind_group_layout);
+
+        let reticle_renderer = ReticleRenderer::new(cx, ReticleStyle::default());
+
+        let text_renderer = TextRenderer::new(cx);
+
         let camera = Camera::new(
             Transform::IDENTITY,
             Projection::Perspective {
-                aspect_ratio: cx.window_size.width as f32 / cx.window_size.height as f32,
+                aspect_ratio: initial_size.width as f32 / initial_size.height as f32,
                 fov_y_radians: 80.0 * DEGREE,
                 z_near: 0.01,
                 z_far: 1000.0,
@@ -92,9 +172,19 @@ impl RenderEngine {
             common_uniforms,
             common_uniforms_buffer,
             common_uniforms_bind_group,
+            shadow_maps,
             terrain_renderer,
+            build_grid_renderer,
+            selection_outline_renderer,
+            particle_system,
+            break_overlay_renderer,
+            reticle_renderer,
+            text_renderer,
             camera,
             frustum_culling_regions,
+            enabled_passes: [true; Pass::ALL.len()],
+            sky_color: Pass::DEFAULT_SKY_COLOR,
+            gpu_timer: GpuTimer::new(cx),
         }
     }
 
@@ -111,19 +201,40 @@ impl RenderEngine {
         let proj_matrix = self.camera.projection_matrix();
         let view_proj_matrix = proj_matrix * view_matrix;
 
+        // terrain is rendered relative to the camera's chunk, so that vertex positions stay small
+        let render_origin = self.camera.render_origin();
+        let relative_view_matrix = self
+            .camera
+            .view_matrix_relative_to(render_origin);
+
         // update frustum culling regions
         self.frustum_culling_regions
             .update(&view_proj_matrix, self.camera.pos());
 
         // update common uniforms
-        self.common_uniforms.camera_view_matrix = view_matrix.to_cols_array();
+        self.common_uniforms.camera_view_matrix = relative_view_matrix.to_cols_array();
+        self.common_uniforms.camera_origin = render_origin.to_array();
         self.common_uniforms.camera_proj_matrix = proj_matrix.to_cols_array();
+        self.common_uniforms.time = time.elapsed_seconds();
+
+        // the shadow cascades cover the load area around the camera
+        let shadow_distance = terrain.load_areas()[load_area_index].horizontal_radius() as f32
+            * CHUNK_SIZE as f32;
+        self.common_uniforms.shadows = self.shadow_maps.uniforms(
+            &self.camera,
+            render_origin,
+            Vec3::from(self.common_uniforms.sun_direction),
+            shadow_distance,
+        );
 
+        // wgpu orders buffer writes before any later submission, and never overwrites the buffer
+        // while an earlier submission is still reading it, so one buffer is enough
         cx.queue.write_buffer(
             &self.common_uniforms_buffer,
             0 as wgpu::BufferAddress,
             bytemuck::cast_slice(&[self.common_uniforms]),
         );
+        let common_uniforms_bind_group = &self.common_uniforms_bind_group;
 
         let mut render_encoder =
             cx.device
@@ -131,11 +242,10 @@ impl RenderEngine {
                     label: Some("Render Encoder"),
                 });
 
-        self.terrain_renderer.render(
-            &mut render_encoder,
-            output_view,
-            &self.depth_texture.view(),
-            &self.common_uniforms_bind_group,
+        // the terrain is updated even when its pass is disabled, so that chunk meshes stay in
+        // sync with the terrain
+        let render_queue = self.visible_chunks(terrain, load_area_index, self.camera.pos());
+        self.terrain_renderer.update(
             cx,
             time,
             tasks,
@@ -143,15 +253,105 @@ impl RenderEngine {
             load_area_index,
             &self.frustum_culling_regions,
             self.camera.pos(),
+            &render_queue,
         );
 
+        // shadows are cast onto the terrain, so they are only rendered when it is drawn
+        if self.is_pass_enabled(Pass::Terrain)
+            || self.is_pass_enabled(Pass::TranslucentTerrain)
+        {
+            self.terrain_renderer.render_shadows(
+                &mut render_encoder,
+                &self.shadow_maps,
+                &self.common_uniforms.shadows,
+                common_uniforms_bind_group,
+                render_origin,
+            );
+        }
+
+        self.gpu_timer.begin_frame(cx);
+        let mut terrain_drawn = false;
+
+        for plan in plan_passes(|pass| self.is_pass_enabled(pass), self.sky_color) {
+            let targets = plan.targets(output_view, self.depth_texture.view());
+
+            match plan.pass {
+                Pass::Sky => targets.clear(&mut render_encoder),
+                Pass::Terrain => {
+                    self.terrain_renderer.render(
+                        &mut render_encoder,
+                        &self.gpu_timer.time_pass(targets),
+                        common_uniforms_bind_group,
+                        self.shadow_maps.bind_group(),
+                        time,
+                    );
+                    terrain_drawn = true;
+                }
+                Pass::TranslucentTerrain => self.terrain_renderer.render_translucent(
+                    &mut render_encoder,
+                    &targets,
+                    common_uniforms_bind_group,
+                    self.shadow_maps.bind_group(),
+                ),
+                Pass::BuildGrid => self.build_grid_renderer.render(
+                    &mut render_encoder,
+                    &targets,
+                    common_uniforms_bind_group,
+                    cx,
+                ),
+                Pass::SelectionOutline => self.selection_outline_renderer.render(
+                    &mut render_encoder,
+                    &targets,
+                    common_uniforms_bind_group,
+                    cx,
+                ),
+                Pass::Particles => self.particle_system.render(
+                    &mut render_encoder,
+                    &targets,
+                    self.terrain_renderer.texture_bind_group(),
+                    common_uniforms_bind_group,
+                    cx,
+                    self.common_uniforms.time,
+                ),
+                Pass::BreakOverlay => self.break_overlay_renderer.render(
+                    &mut render_encoder,
+                    &targets,
+                    common_uniforms_bind_group,
+                    cx,
+                ),
+                Pass::Reticle => self
+                    .reticle_renderer
+                    .render(&mut render_encoder, &targets, cx),
+                Pass::Text => self
+                    .text_renderer
+                    .render(&mut render_encoder, &targets, cx),
+            }
+        }
+
+        if terrain_drawn {
+            self.gpu_timer.resolve(&mut render_encoder);
+        }
+
         let command_buffer = render_encoder.finish();
 
         cx.queue
             .submit(std::iter::once(command_buffer));
+        self.gpu_timer.submitted();
+    }
+
+    /// GPU time taken by the terrain pass in the most recent frame that has been measured. The
+    /// measurement lags a few frames behind, as it is read back from the GPU asynchronously.
+    /// None if nothing has been measured yet or the device doesn't support timestamp queries
+    pub fn last_gpu_frame_time(&self) -> Option<Duration> {
+        self.gpu_timer.last_duration()
     }
 
     pub fn resized(&mut self, cx: &RenderContext) {
+        // textures can't have zero size, so wait until the window is restored
+        if cx.is_zero_area() {
+            return;
+        }
+
         // recreate depth texture
         let new_depth_texture = self
             .depth_texture
@@ -168,6 +368,273 @@ impl RenderEngine {
         self.camera.resized(cx.window_size);
     }
 
+    /// Set how strongly ambient occlusion darkens the terrain, from 0 (disabled) to 1 (full
+    /// strength). Takes effect immediately without remeshing
+    pub fn set_ao_strength(&mut self, strength: f32) {
+        self.common_uniforms.ao_strength = strength.clamp(0.0, 1.0);
+    }
+
+    /// How strongly ambient occlusion darkens the terrain
+    pub fn ao_strength(&self) -> f32 {
+        self.common_uniforms.ao_strength
+    }
+
+    /// Set the exponent applied to the ambient occlusion term. Values below 1 give a gentler
+    /// falloff, values above 1 make occluded corners darker
+    pub fn set_ao_curve(&mut self, curve: f32) {
+        self.common_uniforms.ao_curve = curve.clamp(0.1, 4.0);
+    }
+
+    /// Exponent applied to the ambient occlusion term
+    pub fn ao_curve(&self) -> f32 {
+        self.common_uniforms.ao_curve
+    }
+
+    /// Set the direction towards the sun, which shades terrain faces by how directly they face
+    /// it. Takes effect immediately without remeshing. Ignored if the direction is zero
+    pub fn set_sun_direction(&mut self, direction: Vec3) {
+        if let Some(direction) = direction.try_normalize() {
+            self.common_uniforms.sun_direction = direction.to_array();
+        }
+    }
+
+    /// Set how bright the skylight is, from 0 (night) to 1 (full daylight). Light from emitting
+    /// blocks is unaffected. Takes effect immediately without remeshing
+    pub fn set_day_fraction(&mut self, day_fraction: f32) {
+        self.common_uniforms.day_fraction = day_fraction.clamp(0.0, 1.0);
+    }
+
+    /// Number of shadow cascades the view is split into. Zero means sun shadows are disabled
+    pub fn shadow_cascade_count(&self) -> usize {
+        self.shadow_maps.cascade_count()
+    }
+
+    /// Set the number of shadow cascades, up to `shadow::MAX_CASCADE_COUNT`, or disable sun
+    /// shadows with zero. More cascades keep shadows near the camera sharp over a larger view
+    /// distance, at the cost of rendering the terrain from the sun once more per cascade
+    pub fn set_shadow_cascade_count(&mut self, cascade_count: usize) {
+        self.shadow_maps
+            .set_cascade_count(cascade_count);
+    }
+
+    /// How the view depth is split between shadow cascades, see `set_shadow_split_lambda`
+    pub fn shadow_split_lambda(&self) -> f32 {
+        self.shadow_maps.split_lambda()
+    }
+
+    /// Set how the view depth is split between shadow cascades, from 0 for cascades of equal
+    /// depth to 1 for logarithmic splits, which give the nearest cascade the most resolution
+    pub fn set_shadow_split_lambda(&mut self, split_lambda: f32) {
+        self.shadow_maps
+            .set_split_lambda(split_lambda);
+    }
+
+    /// Set the colour the sky pass clears the output to
+    pub fn set_sky_color(&mut self, color: wgpu::Color) {
+        self.sky_color = color;
+    }
+
+    /// Set the time taken for newly meshed chunks to fade in, in seconds, so that chunks don't pop
+    /// in as the world streams in. Zero, the default, disables the fade so that every chunk is
+    /// drawn exactly as meshed
+    pub fn set_chunk_fade_duration(&mut self, seconds: f32) {
+        self.common_uniforms.chunk_fade_duration = seconds.max(0.0);
+    }
+
+    /// Time taken for newly meshed chunks to fade in, in seconds
+    pub fn chunk_fade_duration(&self) -> f32 {
+        self.common_uniforms.chunk_fade_duration
+    }
+
+    /// Tint each chunk of terrain a different colour and outline chunk boundaries, for debugging
+    /// chunk loading and meshing. Takes effect immediately without remeshing
+    pub fn set_debug_chunk_tint(&mut self, enabled: bool) {
+        self.common_uniforms.debug_chunk_tint = enabled as u32;
+    }
+
+    /// Whether the terrain is tinted by chunk
+    pub fn debug_chunk_tint(&self) -> bool {
+        self.common_uniforms.debug_chunk_tint != 0
+    }
+
+    /// Change which faces of terrain triangles are culled. Back faces are culled normally; None
+    /// draws both sides, so that faces with the wrong winding become visible
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) {
+        self.terrain_renderer
+            .set_face_cull_mode(cull_mode);
+    }
+
+    /// Which faces of terrain triangles are culled
+    pub fn cull_mode(&self) -> Option<wgpu::Face> {
+        self.terrain_renderer.face_cull_mode()
+    }
+
+    /// Whether the terrain is drawn as a wireframe
+    pub fn wireframe(&self) -> bool {
+        self.terrain_renderer.wireframe()
+    }
+
+    /// Draw the terrain as a wireframe or as filled triangles. Falls back to filled triangles if
+    /// the device doesn't support wireframes. Returns whether the terrain is now a wireframe
+    pub fn set_wireframe(&mut self, cx: &RenderContext, wireframe: bool) -> bool {
+        self.terrain_renderer
+            .set_wireframe(cx, wireframe)
+    }
+
+    /// Strategy used to mesh chunks without an override
+    pub fn default_meshing_strategy(&self) -> MeshingStrategy {
+        self.terrain_renderer
+            .default_meshing_strategy()
+    }
+
+    /// Set the strategy used to mesh chunks without an override. Chunks whose meshes were built
+    /// with another strategy are remeshed as they are drawn
+    pub fn set_default_meshing_strategy(&mut self, strategy: MeshingStrategy) {
+        self.terrain_renderer
+            .set_default_meshing_strategy(strategy);
+    }
+
+    /// How the vertex normals of chunk meshes are computed
+    pub fn normal_mode(&self) -> NormalMode {
+        self.terrain_renderer.normal_mode()
+    }
+
+    /// Set how the vertex normals of chunk meshes are computed. Chunks are remeshed as they are
+    /// drawn
+    pub fn set_normal_mode(&mut self, normal_mode: NormalMode) {
+        self.terrain_renderer
+            .set_normal_mode(normal_mode);
+    }
+
+    /// Distance from the center of the load area, in chunks, beyond which chunks are meshed at a
+    /// lower resolution, or None if every chunk is meshed at full resolution
+    pub fn lod_distance(&self) -> Option<f32> {
+        self.terrain_renderer.lod_distance()
+    }
+
+    /// Set the distance from the center of the load area, in chunks, beyond which chunks are
+    /// meshed at half resolution, and beyond twice which they are meshed at quarter resolution,
+    /// or None to mesh every chunk at full resolution. Chunks whose meshes were built at another
+    /// resolution are remeshed as they are drawn
+    pub fn set_lod_distance(&mut self, lod_distance: Option<f32>) {
+        self.terrain_renderer
+            .set_lod_distance(lod_distance);
+    }
+
+    /// Maximum number of finished chunk meshes uploaded per frame
+    pub fn mesh_upload_budget(&self) -> usize {
+        self.terrain_renderer.mesh_upload_budget()
+    }
+
+    /// Set the maximum number of finished chunk meshes uploaded per frame. When many chunks finish
+    /// meshing at once, the rest wait for later frames rather than causing a spike. At least one
+    /// mesh is uploaded per frame
+    pub fn set_mesh_upload_budget(&mut self, budget: usize) {
+        self.terrain_renderer
+            .set_mesh_upload_budget(budget);
+    }
+
+    /// Number of finished chunk meshes waiting to be uploaded
+    pub fn pending_mesh_upload_count(&self) -> usize {
+        self.terrain_renderer.pending_mesh_upload_count()
+    }
+
+    /// Choose the strategy used to mesh one chunk, e.g. culled meshing for a chunk that is being
+    /// edited, or go back to the default with None
+    pub fn set_meshing_strategy(
+        &mut self,
+        chunk_pos: ChunkPosition,
+        strategy: Option<MeshingStrategy>,
+    ) {
+        self.terrain_renderer
+            .set_meshing_strategy(chunk_pos, strategy);
+    }
+
+    /// Set the destruction overlays to draw over blocks that are being broken
+    pub fn set_break_overlays(&mut self, overlays: impl IntoIterator<Item = BreakOverlay>) {
+        self.break_overlay_renderer
+            .set_overlays(overlays);
+    }
+
+    /// Particle system used for effects such as the fragments of broken blocks
+    pub fn particle_system_mut(&mut self) -> &mut ParticleSystem {
+        &mut self.particle_system
+    }
+
+    /// Show a grid of block boundaries on the given plane, typically that of the targeted face,
+    /// or hide it with None
+    pub fn set_build_grid(&mut self, plane: Option<Plane>) {
+        self.build_grid_renderer.set_plane(plane);
+    }
+
+    /// Draw a wireframe box around the given block, typically the targeted one, or hide it with
+    /// None
+    pub fn set_selection(&mut self, block_pos: Option<GlobalBlockPosition>) {
+        self.selection_outline_renderer
+            .set_selection(block_pos);
+    }
+
+    /// Change the appearance of the reticle drawn at the centre of the screen
+    pub fn set_reticle(&mut self, style: ReticleStyle) {
+        self.reticle_renderer.set_style(style);
+    }
+
+    /// Appearance of the reticle drawn at the centre of the screen
+    pub fn reticle(&self) -> ReticleStyle {
+        self.reticle_renderer.style()
+    }
+
+    /// Enable or disable one of the passes making up a frame. Disabled passes are skipped, and the
+    /// remaining passes still share the depth texture correctly
+    pub fn set_pass_enabled(&mut self, pass: Pass, enabled: bool) {
+        self.enabled_passes[pass.as_usize()] = enabled;
+    }
+
+    /// Whether the given pass is drawn
+    pub fn is_pass_enabled(&self, pass: Pass) -> bool {
+        self.enabled_passes[pass.as_usize()]
+    }
+
+    /// Set the text drawn in the top left corner of the screen, e.g. the debug HUD. An empty
+    /// string hides it
+    pub fn set_overlay_text(&mut self, text: &str) {
+        self.text_renderer.set_text(text);
+    }
+
+    /// Chunks that can be seen from `camera_pos` with the terrain renderer's cull mode, e.g. those
+    /// within the view frustum that the visibility search reaches from the camera's chunk without
+    /// passing through solid regions. The terrain is drawn in this order, front to back for the
+    /// visibility search
+    pub fn visible_chunks<'a>(
+        &self,
+        terrain: &'a Terrain,
+        load_area_index: Index,
+        camera_pos: Vec3,
+    ) -> Vec<&'a Chunk> {
+        self.terrain_renderer.visible_chunks(
+            terrain,
+            load_area_index,
+            &self.frustum_culling_regions,
+            camera_pos,
+        )
+    }
+
+    /// Number of chunks, batches and triangles of terrain drawn in the last frame
+    pub fn terrain_draw_stats(&self) -> TerrainDrawStats {
+        self.terrain_renderer.draw_stats()
+    }
+
+    /// Time taken to build recent chunk meshes
+    pub fn mesh_time_stats(&self) -> &MeshTimeStats {
+        self.terrain_renderer.mesh_time_stats()
+    }
+
+    /// Mesh throttle used to defer new chunk meshes while the camera is moving quickly, exposed
+    /// so that its speed threshold and per-frame job budget can be tuned
+    pub fn mesh_throttle_mut(&mut self) -> &mut MeshThrottle {
+        self.terrain_renderer.mesh_throttle_mut()
+    }
+
     /// Returns a shared reference to the camera used to render the world
     pub fn camera(&self) -> &Camera {
         &self.camera
@@ -184,4 +651,60 @@ impl RenderEngine {
 pub struct CommonUniforms {
     pub camera_view_matrix: [f32; 16],
     pub camera_proj_matrix: [f32; 16],
+    /// Origin that the view matrix is relative to, subtracted from chunk batch translations in
+    /// the shader
+    pub camera_origin: [i32; 3],
+    pub ao_strength: f32,
+    pub ao_curve: f32,
+    /// Nonzero to tint the terrain by chunk, for debugging chunk boundaries
+    pub debug_chunk_tint: u32,
+    /// Time since startup in seconds, compared with the spawn times of chunks to fade them in
+    pub time: f32,
+    /// Time taken for new chunks to fade in, in seconds. Zero disables the fade
+    pub chunk_fade_duration: f32,
+    /// Unit vector pointing towards the sun. NB: aligned to 16 bytes, as a `vec3f` in WGSL
+    pub sun_direction: [f32; 3],
+    /// Brightness of terrain faces pointing directly away from the sun
+    pub sun_ambient: f32,
+    /// Brightness of the skylight, from 0 (night) to 1 (full daylight)
+    pub day_fraction: f32,
+    /// Aligns `shadows` to 16 bytes, as WGSL requires for structs in uniform buffers
+    pub _padding: [f32; 3],
+    pub shadows: ShadowUniforms,
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::util::face::FACE_NORMALS;
+
+    #[test]
+    fn sun_direction_is_aligned_for_wgsl() {
+        // a vec3f in a WGSL uniform struct starts on a 16 byte boundary
+        assert_eq!(std::mem::offset_of!(CommonUniforms, sun_direction) % 16, 0);
+        assert_eq!(std::mem::size_of::<CommonUniforms>() % 16, 0);
+    }
+
+    #[test]
+    fn shadow_uniforms_are_aligned_for_wgsl() {
+        // a struct in a WGSL uniform struct starts on a 16 byte boundary, as do its matrices
+        assert_eq!(std::mem::offset_of!(CommonUniforms, shadows) % 16, 0);
+        assert_eq!(std::mem::offset_of!(ShadowUniforms, cascade_splits) % 16, 0);
+        assert_eq!(std::mem::size_of::<ShadowUniforms>() % 16, 0);
+    }
+
+    #[test]
+    fn default_sun_direction_shades_every_face_differently() {
+        // the shading increases with the dot product, so distinct dot products mean distinct
+        // brightness
+        let sun_direction = RenderEngine::DEFAULT_SUN_DIRECTION.normalize();
+        let dots = FACE_NORMALS
+            .iter()
+            .map(|normal| (normal.as_vec3().dot(sun_direction) * 1000.0).round() as i32)
+            .collect_vec();
+
+        assert!(dots.iter().all_unique());
+    }
 }
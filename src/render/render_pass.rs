@@ -0,0 +1,338 @@
+/// Passes making up a frame, in the order they are drawn. Each can be enabled or disabled
+/// independently, and they share the output and depth textures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Clears the output to the sky colour
+    Sky,
+    Terrain,
+    /// Translucent faces of the terrain, blended over everything opaque
+    TranslucentTerrain,
+    BuildGrid,
+    /// Wireframe box around the targeted block
+    SelectionOutline,
+    Particles,
+    BreakOverlay,
+    Reticle,
+    Text,
+}
+
+impl Pass {
+    /// Every pass, in the order they are drawn
+    pub const ALL: [Self; 9] = [
+        Self::Sky,
+        Self::Terrain,
+        Self::TranslucentTerrain,
+        Self::BuildGrid,
+        Self::SelectionOutline,
+        Self::Particles,
+        Self::BreakOverlay,
+        Self::Reticle,
+        Self::Text,
+    ];
+
+    /// Colour the sky pass clears the output to unless another is set with
+    /// `RenderEngine::set_sky_color`
+    pub const DEFAULT_SKY_COLOR: wgpu::Color = wgpu::Color {
+        r: 0.25,
+        g: 0.45,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    pub fn as_usize(self) -> usize {
+        self as usize
+    }
+
+    /// How the pass uses the shared depth texture. This must match the depth state of the
+    /// pipelines used in the pass
+    pub fn depth_usage(self) -> DepthUsage {
+        match self {
+            Self::Terrain => DepthUsage::Write,
+            Self::TranslucentTerrain
+            | Self::BuildGrid
+            | Self::SelectionOutline
+            | Self::Particles
+            | Self::BreakOverlay => DepthUsage::Test,
+            Self::Sky | Self::Reticle | Self::Text => DepthUsage::None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Sky => "Sky Render Pass",
+            Self::Terrain => "Terrain Render Pass",
+            Self::TranslucentTerrain => "Translucent Terrain Render Pass",
+            Self::BuildGrid => "Build Grid Render Pass",
+            Self::SelectionOutline => "Selection Outline Render Pass",
+            Self::Particles => "Particle Render Pass",
+            Self::BreakOverlay => "Break Overlay Render Pass",
+            Self::Reticle => "Reticle Render Pass",
+            Self::Text => "Text Render Pass",
+        }
+    }
+}
+
+/// How a pass uses the shared depth texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthUsage {
+    /// The pass has no depth attachment, e.g. screen-space overlays
+    None,
+    /// The pass tests against the depth of earlier passes without writing depth, e.g. translucent
+    /// geometry
+    Test,
+    /// The pass tests and writes depth, e.g. opaque geometry
+    Write,
+}
+
+/// Load and store operations for the attachments of one pass in a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PassPlan {
+    pub pass: Pass,
+    pub color_load: wgpu::LoadOp<wgpu::Color>,
+    /// None if the pass has no depth attachment
+    pub depth_ops: Option<wgpu::Operations<f32>>,
+}
+
+impl PassPlan {
+    /// Attachments for this pass, drawing to the given output and depth views
+    pub fn targets<'a>(
+        &self,
+        output_view: &'a wgpu::TextureView,
+        depth_view: &'a wgpu::TextureView,
+    ) -> PassTargets<'a> {
+        PassTargets {
+            label: self.pass.label(),
+            output_view,
+            color_load: self.color_load,
+            depth: self
+                .depth_ops
+                .map(|depth_ops| (depth_view, depth_ops)),
+            timestamps: None,
+        }
+    }
+}
+
+/// Work out the load and store operations for each enabled pass, so that the output and depth
+/// textures are cleared by the first pass to use them and depth is only kept while a later pass
+/// still needs it. The sky pass clears the output to `sky_color`
+pub fn plan_passes(is_enabled: impl Fn(Pass) -> bool, sky_color: wgpu::Color) -> Vec<PassPlan> {
+    let enabled: Vec<Pass> = Pass::ALL
+        .into_iter()
+        .filter(|pass| is_enabled(*pass))
+        .collect();
+
+    let mut depth_cleared = false;
+
+    enabled
+        .iter()
+        .enumerate()
+        .map(|(index, &pass)| {
+            let color_load = match (index, pass) {
+                (_, Pass::Sky) => wgpu::LoadOp::Clear(sky_color),
+                (0, _) => wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                _ => wgpu::LoadOp::Load,
+            };
+
+            let depth_ops = (pass.depth_usage() != DepthUsage::None).then(|| {
+                let load = if depth_cleared {
+                    wgpu::LoadOp::Load
+                } else {
+                    wgpu::LoadOp::Clear(1.0)
+                };
+                depth_cleared = true;
+
+                let depth_needed_later = enabled[index + 1..]
+                    .iter()
+                    .any(|pass| pass.depth_usage() != DepthUsage::None);
+                let store = if depth_needed_later {
+                    wgpu::StoreOp::Store
+                } else {
+                    wgpu::StoreOp::Discard
+                };
+
+                wgpu::Operations { load, store }
+            });
+
+            PassPlan {
+                pass,
+                color_load,
+                depth_ops,
+            }
+        })
+        .collect()
+}
+
+/// Output and depth attachments of a pass, passed to the renderer drawing it
+#[derive(Clone, Copy, Debug)]
+pub struct PassTargets<'a> {
+    pub label: &'static str,
+    pub output_view: &'a wgpu::TextureView,
+    pub color_load: wgpu::LoadOp<wgpu::Color>,
+    /// Depth view and operations, or None if the pass has no depth attachment
+    pub depth: Option<(&'a wgpu::TextureView, wgpu::Operations<f32>)>,
+    /// Query set and index to write a timestamp to at the start of the pass, followed by one at
+    /// the next index at the end of the pass, or None to not write timestamps
+    pub timestamps: Option<(&'a wgpu::QuerySet, u32)>,
+}
+
+impl<'a> PassTargets<'a> {
+    /// The same targets, with the pass writing timestamps to `query_set` at its start and end, at
+    /// `first_index` and the index after it. Needs `TIMESTAMP_QUERY`
+    pub fn with_timestamps(self, query_set: &'a wgpu::QuerySet, first_index: u32) -> Self {
+        Self {
+            timestamps: Some((query_set, first_index)),
+            ..self
+        }
+    }
+
+    /// Begin a render pass drawing to these targets
+    pub fn begin_render_pass<'encoder>(
+        &self,
+        render_encoder: &'encoder mut wgpu::CommandEncoder,
+    ) -> wgpu::RenderPass<'encoder>
+    where
+        'a: 'encoder,
+    {
+        render_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(self.label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: self.output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: self.color_load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: self
+                .depth
+                .map(|(view, depth_ops)| wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(depth_ops),
+                    stencil_ops: None,
+                }),
+            occlusion_query_set: None,
+            timestamp_writes: self
+                .timestamps
+                .map(|(query_set, first_index)| wgpu::RenderPassTimestampWrites {
+                    query_set,
+                    beginning_of_pass_write_index: Some(first_index),
+                    end_of_pass_write_index: Some(first_index + 1),
+                }),
+        })
+    }
+
+    /// Perform any clears these targets need without drawing anything. Called by passes that
+    /// have nothing to draw this frame, so that later passes don't load uncleared attachments
+    pub fn clear(&self, render_encoder: &mut wgpu::CommandEncoder) {
+        let depth_load = self
+            .depth
+            .map(|(_, depth_ops)| depth_ops.load);
+
+        let needs_clear = matches!(self.color_load, wgpu::LoadOp::Clear(_))
+            || matches!(depth_load, Some(wgpu::LoadOp::Clear(_)));
+
+        if needs_clear {
+            self.begin_render_pass(render_encoder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan_for(plans: &[PassPlan], pass: Pass) -> PassPlan {
+        *plans
+            .iter()
+            .find(|plan| plan.pass == pass)
+            .unwrap()
+    }
+
+    #[test]
+    fn terrain_clears_depth_and_last_depth_pass_discards_it() {
+        let plans = plan_passes(|_| true, Pass::DEFAULT_SKY_COLOR);
+        assert_eq!(plans.len(), Pass::ALL.len());
+
+        let sky = plan_for(&plans, Pass::Sky);
+        assert_eq!(sky.color_load, wgpu::LoadOp::Clear(Pass::DEFAULT_SKY_COLOR));
+        assert_eq!(sky.depth_ops, None);
+
+        let terrain = plan_for(&plans, Pass::Terrain);
+        assert_eq!(terrain.color_load, wgpu::LoadOp::Load);
+        assert_eq!(
+            terrain.depth_ops,
+            Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Store,
+            })
+        );
+
+        // translucent terrain is depth tested against the opaque terrain
+        let translucent_terrain = plan_for(&plans, Pass::TranslucentTerrain);
+        assert_eq!(
+            translucent_terrain.depth_ops,
+            Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            })
+        );
+
+        let break_overlay = plan_for(&plans, Pass::BreakOverlay);
+        assert_eq!(
+            break_overlay.depth_ops,
+            Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Discard,
+            })
+        );
+
+        assert_eq!(plan_for(&plans, Pass::Text).depth_ops, None);
+    }
+
+    #[test]
+    fn disabling_terrain_keeps_sky_and_overlays_valid() {
+        // without the opaque terrain, the translucent terrain is the first pass to use depth
+        let plans = plan_passes(|pass| pass != Pass::Terrain, Pass::DEFAULT_SKY_COLOR);
+        assert_eq!(
+            plan_for(&plans, Pass::TranslucentTerrain)
+                .depth_ops
+                .map(|ops| ops.load),
+            Some(wgpu::LoadOp::Clear(1.0))
+        );
+
+        let night_sky = wgpu::Color {
+            r: 0.01,
+            g: 0.01,
+            b: 0.03,
+            a: 1.0,
+        };
+        let plans = plan_passes(
+            |pass| !matches!(pass, Pass::Terrain | Pass::TranslucentTerrain),
+            night_sky,
+        );
+
+        assert!(plans.iter().all(|plan| plan.pass != Pass::Terrain));
+        assert_eq!(plans[0].pass, Pass::Sky);
+        assert_eq!(plans[0].color_load, wgpu::LoadOp::Clear(night_sky));
+
+        // the build grid is now the first pass to use depth, so it must clear it rather than load
+        // the previous frame's depth
+        let build_grid = plan_for(&plans, Pass::BuildGrid);
+        assert_eq!(
+            build_grid.depth_ops.map(|ops| ops.load),
+            Some(wgpu::LoadOp::Clear(1.0))
+        );
+
+        // overlays ignore depth entirely, so they never see an uncleared depth texture
+        for pass in [Pass::Reticle, Pass::Text] {
+            let plan = plan_for(&plans, pass);
+            assert_eq!(plan.color_load, wgpu::LoadOp::Load);
+            assert_eq!(plan.depth_ops, None);
+        }
+
+        // without the sky, the first remaining pass clears the output instead
+        let plans = plan_passes(|pass| pass == Pass::Reticle, night_sky);
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].color_load, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+    }
+}
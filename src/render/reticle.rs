@@ -0,0 +1,248 @@
+use winit::dpi::PhysicalSize;
+
+use super::{
+    render_context::RenderContext,
+    render_pass::PassTargets,
+    util::{
+        bind_group_builder::BindGroupBuilder,
+        pipeline_builder::RenderPipelineBuilder,
+        shader_source::{shader_source, ReloadablePipeline},
+    },
+};
+
+/// Shape of the reticle drawn at the centre of the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReticleShape {
+    Cross,
+    Dot,
+    Circle,
+}
+
+/// How the reticle is coloured
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReticleColor {
+    /// Invert the colour behind the reticle, so that it is visible over bright and dark terrain
+    Invert,
+    /// Fixed linear RGBA colour
+    Fixed([f32; 4]),
+}
+
+/// Appearance of the reticle drawn at the centre of the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReticleStyle {
+    pub shape: ReticleShape,
+    /// Width of the reticle in logical pixels
+    pub size: f32,
+    /// Width of the lines making up the cross and circle in logical pixels
+    pub thickness: f32,
+    pub color: ReticleColor,
+}
+
+impl ReticleStyle {
+    /// Uniforms for drawing the reticle on a surface of the given size. The size and thickness are
+    /// scaled by `scale_factor` so that the reticle looks the same on high-DPI displays
+    fn uniforms(&self, surface_size: PhysicalSize<u32>, scale_factor: f64) -> ReticleUniforms {
+        let color = match self.color {
+            ReticleColor::Invert => [1.0; 4],
+            ReticleColor::Fixed([r, g, b, a]) => [r * a, g * a, b * a, a],
+        };
+
+        let shape = match self.shape {
+            ReticleShape::Cross => 0,
+            ReticleShape::Dot => 1,
+            ReticleShape::Circle => 2,
+        };
+
+        ReticleUniforms {
+            color,
+            screen_size: [surface_size.width as f32, surface_size.height as f32],
+            size: self.size * scale_factor as f32,
+            thickness: self.thickness * scale_factor as f32,
+            shape,
+            padding: [0; 3],
+        }
+    }
+}
+
+impl Default for ReticleStyle {
+    fn default() -> Self {
+        Self {
+            shape: ReticleShape::Cross,
+            size: 16.0,
+            thickness: 2.0,
+            color: ReticleColor::Invert,
+        }
+    }
+}
+
+/// Responsible for drawing the reticle over the rendered scene
+#[derive(Debug)]
+pub struct ReticleRenderer {
+    style: ReticleStyle,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    /// Pipelines for `ReticleColor::Invert` and `ReticleColor::Fixed`, sharing one layout and
+    /// rebuilt when the shader is modified
+    pipelines: ReloadablePipeline<[wgpu::RenderPipeline; 2]>,
+}
+
+impl ReticleRenderer {
+    /// Blend state that inverts the destination where the shader outputs white
+    const INVERT_BLEND: wgpu::BlendState = wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::OneMinusDst,
+            dst_factor: wgpu::BlendFactor::OneMinusSrc,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent::OVER,
+    };
+
+    pub fn new(cx: &RenderContext, style: ReticleStyle) -> Self {
+        let uniform_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Reticle Uniform Buffer"),
+                size: std::mem::size_of::<ReticleUniforms>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let (uniform_bind_group, uniform_bind_group_layout) = BindGroupBuilder::new()
+            .with_label("Reticle Uniforms Bind Group")
+            .with_uniform_buffer(
+                &uniform_buffer,
+                wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            )
+            .build(&cx.device);
+
+        let shader = shader_source!("reticle.wgsl");
+        let module = shader.create_module(&cx.device);
+
+        let (invert_pipeline, pipeline_layout) =
+            Self::pipeline_builder(cx, &module, Self::INVERT_BLEND)
+                .with_bind_group_layout(&uniform_bind_group_layout)
+                .build(&cx.device);
+
+        let fixed_pipeline =
+            Self::pipeline_builder(cx, &module, wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+                .with_layout(&pipeline_layout)
+                .build_with_existing_layout(&cx.device);
+
+        Self {
+            style,
+            uniform_buffer,
+            uniform_bind_group,
+            pipelines: ReloadablePipeline::new(
+                shader,
+                pipeline_layout,
+                [invert_pipeline, fixed_pipeline],
+            ),
+        }
+    }
+
+    fn pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+        blend: wgpu::BlendState,
+    ) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .with_label("Reticle Pipeline")
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_color_target(
+                cx.surface_config.format,
+                Some(blend),
+                wgpu::ColorWrites::COLOR,
+            )
+            .with_topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .with_cull_mode(None)
+    }
+
+    /// Called once per frame to draw the reticle over the output
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        cx: &RenderContext,
+    ) {
+        self.pipelines.reload_if_changed(&cx.device, |module, layout| {
+            [
+                Self::INVERT_BLEND,
+                wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            ]
+            .map(|blend| {
+                Self::pipeline_builder(cx, module, blend)
+                    .with_layout(layout)
+                    .build_with_existing_layout(&cx.device)
+            })
+        });
+
+        let uniforms = self
+            .style
+            .uniforms(cx.window_size, cx.scale_factor);
+
+        cx.queue.write_buffer(
+            &self.uniform_buffer,
+            0 as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[uniforms]),
+        );
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        let [invert_pipeline, fixed_pipeline] = self.pipelines.get();
+        let pipeline = match self.style.color {
+            ReticleColor::Invert => invert_pipeline,
+            ReticleColor::Fixed(_) => fixed_pipeline,
+        };
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+
+    /// Current appearance of the reticle
+    pub fn style(&self) -> ReticleStyle {
+        self.style
+    }
+
+    /// Change the appearance of the reticle. Takes effect from the next frame
+    pub fn set_style(&mut self, style: ReticleStyle) {
+        self.style = style;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ReticleUniforms {
+    color: [f32; 4],
+    screen_size: [f32; 2],
+    size: f32,
+    thickness: f32,
+    shape: u32,
+    padding: [u32; 3],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniforms_scale_with_dpi() {
+        let style = ReticleStyle {
+            shape: ReticleShape::Circle,
+            size: 10.0,
+            thickness: 1.5,
+            color: ReticleColor::Fixed([1.0, 0.5, 0.0, 0.5]),
+        };
+
+        let uniforms = style.uniforms(PhysicalSize::new(3840, 2160), 2.0);
+        assert_eq!(uniforms.screen_size, [3840.0, 2160.0]);
+        assert_eq!(uniforms.size, 20.0);
+        assert_eq!(uniforms.thickness, 3.0);
+        assert_eq!(uniforms.shape, 2);
+        assert_eq!(uniforms.color, [0.5, 0.25, 0.0, 0.5]);
+
+        // the uniform struct must match the 16-byte aligned layout in reticle.wgsl
+        assert_eq!(std::mem::size_of::<ReticleUniforms>(), 48);
+    }
+}
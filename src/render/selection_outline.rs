@@ -0,0 +1,202 @@
+use super::{
+    render_context::RenderContext,
+    render_engine::RenderEngine,
+    render_pass::PassTargets,
+    util::{
+        mesh::Vertex,
+        pipeline_builder::RenderPipelineBuilder,
+        shader_source::{shader_source, ReloadablePipeline},
+    },
+};
+use crate::terrain::position_types::GlobalBlockPosition;
+
+/// Number of vertices in the outline: two for each of the 12 edges of the cube
+const OUTLINE_VERTEX_COUNT: usize = 24;
+
+/// Distance the outline is pushed out from the faces of the block, in blocks, so that it passes
+/// the depth test against the block's own faces
+const OUTLINE_INFLATE: f32 = 0.002;
+
+/// Responsible for drawing a wireframe box around the block targeted by the player
+#[derive(Debug)]
+pub struct SelectionOutlineRenderer {
+    selection: Option<GlobalBlockPosition>,
+    /// Block whose outline is currently in the vertex buffer
+    uploaded_selection: Option<GlobalBlockPosition>,
+    vertex_buffer: wgpu::Buffer,
+    /// Rebuilt when the shader is modified
+    pipeline: ReloadablePipeline,
+}
+
+impl SelectionOutlineRenderer {
+    pub fn new(
+        cx: &RenderContext,
+        common_uniforms_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let vertex_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Selection Outline Vertex Buffer"),
+                size: (OUTLINE_VERTEX_COUNT * std::mem::size_of::<OutlineVertex>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let shader = shader_source!("selection_outline.wgsl");
+        let module = shader.create_module(&cx.device);
+
+        let (pipeline, pipeline_layout) = Self::pipeline_builder(cx, &module)
+            .with_bind_group_layout(common_uniforms_bind_group_layout)
+            .build(&cx.device);
+
+        Self {
+            selection: None,
+            uploaded_selection: None,
+            vertex_buffer,
+            pipeline: ReloadablePipeline::new(shader, pipeline_layout, pipeline),
+        }
+    }
+
+    fn pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+    ) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .with_label("Selection Outline Pipeline")
+            .with_vertex::<OutlineVertex>()
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_color_target(
+                cx.surface_config.format,
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+                wgpu::ColorWrites::COLOR,
+            )
+            .with_depth(RenderEngine::DEPTH_FORMAT, RenderEngine::DEPTH_COMPARE)
+            .with_depth_write(false)
+            .with_topology(wgpu::PrimitiveTopology::LineList)
+            .with_cull_mode(None)
+    }
+
+    /// Called once per frame after the terrain has been drawn, so that the outline is depth
+    /// tested against it
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        cx: &RenderContext,
+    ) {
+        self.pipeline.reload_if_changed(&cx.device, |module, layout| {
+            Self::pipeline_builder(cx, module)
+                .with_layout(layout)
+                .build_with_existing_layout(&cx.device)
+        });
+
+        let Some(selection) = self.selection else {
+            targets.clear(render_encoder);
+            return;
+        };
+
+        // the edges only change when a different block is selected
+        if self.uploaded_selection != Some(selection) {
+            cx.queue.write_buffer(
+                &self.vertex_buffer,
+                0 as wgpu::BufferAddress,
+                bytemuck::cast_slice(&outline_vertices(selection)),
+            );
+            self.uploaded_selection = Some(selection);
+        }
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(self.pipeline.get());
+        render_pass.set_bind_group(0, common_uniforms_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..OUTLINE_VERTEX_COUNT as u32, 0..1);
+    }
+
+    /// Set the block to outline, or None to hide the outline. Takes effect from the next frame
+    pub fn set_selection(&mut self, selection: Option<GlobalBlockPosition>) {
+        self.selection = selection;
+    }
+}
+
+/// Endpoint of one edge of the outline
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineVertex {
+    /// Kept separate from the offset so that the shader can subtract the camera origin exactly
+    block_pos: [i32; 3],
+    /// Position relative to the minimum corner of the block
+    offset: [f32; 3],
+}
+
+impl Vertex for OutlineVertex {
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+            0 => Sint32x3,
+            1 => Float32x3,
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Vertices of the 12 edges of the unit cube at the given block, as pairs of endpoints for a line
+/// list. The cube is inflated slightly so that the lines aren't hidden by the block's faces
+fn outline_vertices(block_pos: GlobalBlockPosition) -> [OutlineVertex; OUTLINE_VERTEX_COUNT] {
+    let (min, max) = (-OUTLINE_INFLATE, 1.0 + OUTLINE_INFLATE);
+    let block_pos = block_pos.as_ivec3().to_array();
+
+    let mut vertices = [OutlineVertex::default(); OUTLINE_VERTEX_COUNT];
+    let mut endpoints = vertices.iter_mut();
+
+    // four edges along each axis, one through each corner of the face at the minimum of the axis
+    for (axis, u, v) in itertools::iproduct!(0..3, [min, max], [min, max]) {
+        for along in [min, max] {
+            let mut offset = [0.0; 3];
+            offset[axis] = along;
+            offset[(axis + 1) % 3] = u;
+            offset[(axis + 2) % 3] = v;
+
+            *endpoints.next().unwrap() = OutlineVertex { block_pos, offset };
+        }
+    }
+
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_covers_each_edge_of_the_cube_once() {
+        let vertices = outline_vertices(GlobalBlockPosition::new(3, -5, 7));
+        assert!(vertices.iter().all(|vertex| vertex.block_pos == [3, -5, 7]));
+
+        let mut edges: Vec<([f32; 3], [f32; 3])> = vertices
+            .chunks_exact(2)
+            .map(|edge| (edge[0].offset, edge[1].offset))
+            .collect();
+
+        for (start, end) in &edges {
+            // each edge runs the length of the cube along exactly one axis
+            let differences: Vec<f32> = (0..3).map(|i| (end[i] - start[i]).abs()).collect();
+            assert_eq!(differences.iter().filter(|&&d| d == 0.0).count(), 2);
+            assert!(differences.iter().all(|&d| d == 0.0 || d > 1.0));
+
+            // and lies on the corners of the cube
+            assert!(start.iter().chain(end).all(|&x| x.abs() < 0.01 || (x - 1.0).abs() < 0.01));
+        }
+
+        edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        edges.dedup();
+        assert_eq!(edges.len(), 12);
+    }
+}
@@ -0,0 +1,523 @@
+use glam::{IVec3, Mat4, Vec3};
+use itertools::Itertools;
+
+use super::{
+    camera::Camera,
+    render_context::RenderContext,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+/// Maximum number of shadow cascades, and the number of layers in the shadow map texture
+pub const MAX_CASCADE_COUNT: usize = 4;
+
+/// Width and height of the shadow map of each cascade in texels
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+pub const SHADOW_MAP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Distance beyond the bounds of each cascade, towards the sun, from which terrain still casts
+/// shadows into it
+const CASTER_DISTANCE: f32 = 128.0;
+
+/// Cascaded shadow maps for sunlight. The view frustum is split by view depth into up to
+/// `MAX_CASCADE_COUNT` cascades, each rendered from the sun into its own layer of a depth texture,
+/// so that shadows near the camera get most of the resolution while the further cascades still
+/// cover the rest of the load area. The terrain shader picks a cascade by the view depth of each
+/// fragment
+#[derive(Debug)]
+pub struct ShadowMaps {
+    /// View of each layer of the shadow map texture, rendered to by the shadow pass of its cascade
+    layer_views: Vec<wgpu::TextureView>,
+    /// Bind group holding the index of each cascade, used by the shadow pass
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    /// Layout of `cascade_bind_groups`
+    cascade_bind_group_layout: wgpu::BindGroupLayout,
+    /// Bind group for sampling the shadow maps, used by the terrain shader
+    bind_group: wgpu::BindGroup,
+    /// Layout of `bind_group`
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Number of cascades in use, from 0 (shadows disabled) to `MAX_CASCADE_COUNT`
+    cascade_count: usize,
+    /// Interpolates the split depths between uniform (0) and logarithmic (1) spacing
+    split_lambda: f32,
+    /// Fraction of each cascade over which it fades into the next
+    cascade_blend: f32,
+}
+
+impl ShadowMaps {
+    pub const DEFAULT_CASCADE_COUNT: usize = 3;
+    pub const DEFAULT_SPLIT_LAMBDA: f32 = 0.75;
+    pub const DEFAULT_CASCADE_BLEND: f32 = 0.1;
+
+    pub fn new(cx: &RenderContext) -> Self {
+        let texture = cx
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Shadow Map Texture"),
+                size: wgpu::Extent3d {
+                    width: SHADOW_MAP_SIZE,
+                    height: SHADOW_MAP_SIZE,
+                    depth_or_array_layers: MAX_CASCADE_COUNT as u32,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SHADOW_MAP_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+
+        let layer_views = (0..MAX_CASCADE_COUNT as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer View"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect_vec();
+
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        // linear filtering of the comparisons gives a cheap 2x2 PCF
+        let sampler = cx
+            .device
+            .create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Shadow Map Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                ..Default::default()
+            });
+
+        let (bind_group, bind_group_layout) = BindGroupBuilder::new()
+            .with_label("Shadow Map Bind Group")
+            .with_texture_view(
+                &array_view,
+                wgpu::TextureViewDimension::D2Array,
+                wgpu::TextureSampleType::Depth,
+                wgpu::ShaderStages::FRAGMENT,
+            )
+            .with_sampler(
+                &sampler,
+                wgpu::SamplerBindingType::Comparison,
+                wgpu::ShaderStages::FRAGMENT,
+            )
+            .build(&cx.device);
+
+        // the index is padded to 16 bytes, the minimum size of a uniform buffer binding
+        let cascade_buffers = (0..MAX_CASCADE_COUNT as u32)
+            .map(|cascade| {
+                let buffer = cx
+                    .device
+                    .create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Shadow Cascade Uniform Buffer"),
+                        size: std::mem::size_of::<[u32; 4]>() as wgpu::BufferAddress,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    });
+                cx.queue.write_buffer(
+                    &buffer,
+                    0 as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&[cascade, 0, 0, 0]),
+                );
+                buffer
+            })
+            .collect_vec();
+
+        let (cascade_bind_groups, cascade_bind_group_layouts): (Vec<_>, Vec<_>) = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                BindGroupBuilder::new()
+                    .with_label("Shadow Cascade Bind Group")
+                    .with_uniform_buffer(buffer, wgpu::ShaderStages::VERTEX)
+                    .build(&cx.device)
+            })
+            .unzip();
+        let cascade_bind_group_layout = cascade_bind_group_layouts
+            .into_iter()
+            .next()
+            .expect("there should be at least one cascade");
+
+        Self {
+            layer_views,
+            cascade_bind_groups,
+            cascade_bind_group_layout,
+            bind_group,
+            bind_group_layout,
+            cascade_count: Self::DEFAULT_CASCADE_COUNT,
+            split_lambda: Self::DEFAULT_SPLIT_LAMBDA,
+            cascade_blend: Self::DEFAULT_CASCADE_BLEND,
+        }
+    }
+
+    /// Uniforms for sampling the shadow maps and rendering each cascade this frame, with the
+    /// cascades covering view depths up to `distance`. Matrices are relative to `render_origin`,
+    /// like the camera's view matrix in the common uniforms
+    pub fn uniforms(
+        &self,
+        camera: &Camera,
+        render_origin: IVec3,
+        sun_direction: Vec3,
+        distance: f32,
+    ) -> ShadowUniforms {
+        let (near, far) = camera.projection.depth_range();
+        let far = far.min(distance).max(near);
+        let splits = cascade_splits(near, far, self.cascade_count, self.split_lambda);
+
+        let view_matrix = camera.view_matrix_relative_to(render_origin);
+        let proj_matrix = camera.projection_matrix();
+
+        let mut uniforms = ShadowUniforms {
+            cascade_splits: splits,
+            cascade_count: self.cascade_count as u32,
+            cascade_blend: self.cascade_blend,
+            ..Default::default()
+        };
+
+        for cascade in 0..self.cascade_count {
+            let cascade_near = if cascade == 0 {
+                near
+            } else {
+                splits[cascade - 1]
+            };
+            let (matrix, texel_size) = cascade_view_proj_matrix(
+                view_matrix,
+                proj_matrix,
+                sun_direction,
+                cascade_near,
+                splits[cascade],
+            );
+
+            uniforms.view_proj_matrices[cascade] = matrix.to_cols_array();
+            uniforms.texel_sizes[cascade] = texel_size;
+        }
+
+        uniforms
+    }
+
+    /// View of the shadow map of the given cascade and the bind group holding its index, for
+    /// rendering the cascade
+    pub fn cascade_target(&self, cascade: usize) -> (&wgpu::TextureView, &wgpu::BindGroup) {
+        (&self.layer_views[cascade], &self.cascade_bind_groups[cascade])
+    }
+
+    /// Layout of the bind group holding the index of the cascade being rendered
+    pub fn cascade_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.cascade_bind_group_layout
+    }
+
+    /// Bind group for sampling the shadow maps
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Layout of `bind_group`
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// Number of cascades in use. Zero means shadows are disabled
+    pub fn cascade_count(&self) -> usize {
+        self.cascade_count
+    }
+
+    /// Set the number of cascades, clamped to `MAX_CASCADE_COUNT`, or disable shadows with zero
+    pub fn set_cascade_count(&mut self, cascade_count: usize) {
+        self.cascade_count = cascade_count.min(MAX_CASCADE_COUNT);
+    }
+
+    /// Interpolation between uniform and logarithmic split depths
+    pub fn split_lambda(&self) -> f32 {
+        self.split_lambda
+    }
+
+    /// Set how the view depth is split between cascades, from 0 for cascades of equal depth to 1
+    /// for logarithmic splits, which give the nearest cascade the most resolution
+    pub fn set_split_lambda(&mut self, split_lambda: f32) {
+        self.split_lambda = split_lambda.clamp(0.0, 1.0);
+    }
+}
+
+/// Uniforms describing the shadow cascades, part of the common uniforms.
+/// NB: the layout must match `ShadowUniforms` in terrain.wgsl and shadow.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniforms {
+    /// View-projection matrix of the sun for each cascade, relative to the render origin
+    pub view_proj_matrices: [[f32; 16]; MAX_CASCADE_COUNT],
+    /// View depth at which each cascade ends
+    pub cascade_splits: [f32; MAX_CASCADE_COUNT],
+    /// Size of a shadow map texel in blocks in each cascade
+    pub texel_sizes: [f32; MAX_CASCADE_COUNT],
+    /// Number of cascades in use, zero if shadows are disabled
+    pub cascade_count: u32,
+    /// Fraction of each cascade over which it fades into the next
+    pub cascade_blend: f32,
+    /// Pads the struct to a multiple of 16 bytes, as WGSL requires for uniform buffers
+    pub _padding: [f32; 2],
+}
+
+impl ShadowUniforms {
+    /// Whether any part of the box between `min` and `max`, relative to the render origin, falls
+    /// within the shadow map of the given cascade, so that it can cast shadows into it
+    pub fn is_box_within_cascade(&self, cascade: usize, min: Vec3, max: Vec3) -> bool {
+        let matrix = Mat4::from_cols_array(&self.view_proj_matrices[cascade]);
+        let corners = (0..8)
+            .map(|i| {
+                let corner = Vec3::select(
+                    glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                    max,
+                    min,
+                );
+                matrix.project_point3(corner)
+            })
+            .collect_vec();
+
+        // the box is outside if all of its corners are beyond the same side of the clip volume.
+        // Anything nearer to the sun than the near plane is too far from the cascade to matter
+        let all = |outside: fn(Vec3) -> bool| corners.iter().all(|&corner| outside(corner));
+        !(all(|c| c.x < -1.0)
+            || all(|c| c.x > 1.0)
+            || all(|c| c.y < -1.0)
+            || all(|c| c.y > 1.0)
+            || all(|c| c.z < 0.0)
+            || all(|c| c.z > 1.0))
+    }
+}
+
+/// View depth at which each of the first `cascade_count` cascades between `near` and `far` ends,
+/// using the practical split scheme: `lambda` interpolates between splits of equal depth (0) and
+/// logarithmic splits (1). The remaining entries are `far`
+pub fn cascade_splits(
+    near: f32,
+    far: f32,
+    cascade_count: usize,
+    lambda: f32,
+) -> [f32; MAX_CASCADE_COUNT] {
+    let mut splits = [far; MAX_CASCADE_COUNT];
+
+    for (index, split) in splits
+        .iter_mut()
+        .enumerate()
+        .take(cascade_count)
+    {
+        let fraction = (index + 1) as f32 / cascade_count as f32;
+        let uniform = near + (far - near) * fraction;
+        let logarithmic = near * (far / near).powf(fraction);
+        *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+    }
+
+    // the last split is exactly `far`, whatever the rounding
+    if cascade_count > 0 {
+        splits[cascade_count - 1] = far;
+    }
+
+    splits
+}
+
+/// View-projection matrix of the sun for the cascade covering view depths `near..far`, and the
+/// size of a shadow map texel in blocks. The cascade is a box around the bounding sphere of that
+/// slice of the view frustum, so that it doesn't change size as the camera turns, and it moves
+/// in whole texels so that the edges of shadows don't shimmer as the camera moves.
+/// `sun_direction` is a unit vector pointing towards the sun
+pub fn cascade_view_proj_matrix(
+    view_matrix: Mat4,
+    proj_matrix: Mat4,
+    sun_direction: Vec3,
+    near: f32,
+    far: f32,
+) -> (Mat4, f32) {
+    let inverse_proj_matrix = proj_matrix.inverse();
+    let inverse_view_matrix = view_matrix.inverse();
+
+    // corners of the slice, found along the edges of the view frustum in view space, where the
+    // camera looks along -Z
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+        .into_iter()
+        .flat_map(|(x, y)| {
+            let frustum_near = inverse_proj_matrix.project_point3(Vec3::new(x, y, 0.0));
+            let frustum_far = inverse_proj_matrix.project_point3(Vec3::new(x, y, 1.0));
+
+            [near, far].map(|depth| {
+                let t = (depth + frustum_near.z) / (frustum_near.z - frustum_far.z);
+                inverse_view_matrix.transform_point3(frustum_near.lerp(frustum_far, t))
+            })
+        })
+        .collect_vec();
+
+    let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+    // rounded up, so that the radius doesn't flicker with rounding errors as the camera turns
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max)
+        .ceil()
+        .max(1.0);
+    let texel_size = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+
+    // snap the centre to the texel grid of the shadow map
+    let up = if sun_direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let light_rotation = Mat4::look_to_rh(Vec3::ZERO, -sun_direction, up);
+    let light_center = light_rotation.transform_point3(center);
+    let snapped_center = Vec3::new(
+        (light_center.x / texel_size).round() * texel_size,
+        (light_center.y / texel_size).round() * texel_size,
+        light_center.z,
+    );
+    let center = light_rotation
+        .inverse()
+        .transform_point3(snapped_center);
+
+    let eye = center + sun_direction * (radius + CASTER_DISTANCE);
+    let light_view_matrix = Mat4::look_to_rh(eye, -sun_direction, up);
+    let light_proj_matrix = Mat4::orthographic_rh(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + CASTER_DISTANCE,
+    );
+
+    (light_proj_matrix * light_view_matrix, texel_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render::camera::Projection,
+        util::{transform::Transform, DEGREE},
+    };
+
+    #[test]
+    fn splits_interpolate_between_uniform_and_logarithmic() {
+        let uniform = cascade_splits(1.0, 256.0, 4, 0.0);
+        assert_eq!(uniform, [64.75, 128.5, 192.25, 256.0]);
+
+        let logarithmic = cascade_splits(1.0, 256.0, 4, 1.0);
+        for (split, expected) in logarithmic.iter().zip([4.0, 16.0, 64.0, 256.0]) {
+            assert!((split - expected).abs() < 1e-3, "{split} != {expected}");
+        }
+
+        // the practical scheme falls in between, and always ends at the far plane
+        let practical = cascade_splits(0.01, 256.0, 3, 0.75);
+        assert!(practical[0] < practical[1] && practical[1] < practical[2]);
+        assert_eq!(practical[2], 256.0);
+        assert_eq!(practical[3], 256.0);
+
+        assert_eq!(cascade_splits(0.01, 256.0, 0, 0.75), [256.0; MAX_CASCADE_COUNT]);
+    }
+
+    #[test]
+    fn cascade_contains_its_slice_of_the_view_frustum() {
+        let mut camera = Camera::new(
+            Transform::IDENTITY,
+            Projection::Perspective {
+                aspect_ratio: 16.0 / 9.0,
+                fov_y_radians: 80.0 * DEGREE,
+                z_near: 0.01,
+                z_far: 1000.0,
+            },
+        );
+        camera.transform.translation = Vec3::new(10.0, 70.0, -5.0);
+        let sun_direction = Vec3::new(0.3, 1.0, 0.6).normalize();
+
+        let (matrix, texel_size) = cascade_view_proj_matrix(
+            camera.view_matrix(),
+            camera.projection_matrix(),
+            sun_direction,
+            20.0,
+            60.0,
+        );
+        assert!(texel_size > 0.0);
+
+        // points along the view direction within the slice land inside the shadow map
+        for depth in [20.0, 40.0, 60.0] {
+            let point = camera.pos() + camera.look_dir() * depth;
+            let clip = matrix.project_point3(point);
+            assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{clip}");
+            assert!((0.0..=1.0).contains(&clip.z), "{clip}");
+        }
+
+        // points nearer the sun are nearer in the shadow map, so that they cast shadows
+        let point = camera.pos() + camera.look_dir() * 40.0;
+        let towards_sun = point + sun_direction * 10.0;
+        assert!(matrix.project_point3(towards_sun).z < matrix.project_point3(point).z);
+    }
+
+    #[test]
+    fn cascade_moves_in_whole_texels() {
+        let view_proj = |translation: Vec3| {
+            let camera = Camera::new(
+                Transform {
+                    translation,
+                    ..Transform::IDENTITY
+                },
+                Projection::Perspective {
+                    aspect_ratio: 1.0,
+                    fov_y_radians: 90.0 * DEGREE,
+                    z_near: 0.01,
+                    z_far: 1000.0,
+                },
+            );
+            cascade_view_proj_matrix(
+                camera.view_matrix(),
+                camera.projection_matrix(),
+                Vec3::new(0.3, 1.0, 0.6).normalize(),
+                0.01,
+                30.0,
+            )
+            .0
+        };
+
+        // a fixed point keeps its position relative to the texel grid as the camera moves
+        let point = Vec3::new(3.0, 1.0, -10.0);
+        let texel_pos = |matrix: Mat4| {
+            matrix
+                .project_point3(point)
+                .truncate()
+                * 0.5
+                * SHADOW_MAP_SIZE as f32
+        };
+        let moved_texels =
+            texel_pos(view_proj(Vec3::new(0.37, 0.0, -0.81))) - texel_pos(view_proj(Vec3::ZERO));
+        assert!(
+            moved_texels.abs_diff_eq(moved_texels.round(), 1e-2),
+            "{moved_texels}"
+        );
+    }
+
+    #[test]
+    fn boxes_outside_the_cascade_are_culled() {
+        let (matrix, _) = cascade_view_proj_matrix(
+            Mat4::IDENTITY,
+            Mat4::perspective_rh(90.0 * DEGREE, 1.0, 0.01, 1000.0),
+            Vec3::Y,
+            0.01,
+            30.0,
+        );
+        let mut uniforms = ShadowUniforms::default();
+        uniforms.view_proj_matrices[0] = matrix.to_cols_array();
+
+        // a box in front of the camera, and one high above it that shades it from the sun
+        let within = |min: Vec3, size: Vec3| uniforms.is_box_within_cascade(0, min, min + size);
+        assert!(within(Vec3::new(-1.0, -1.0, -11.0), Vec3::splat(2.0)));
+        assert!(within(Vec3::new(-1.0, 60.0, -11.0), Vec3::splat(2.0)));
+
+        // a box far behind the camera
+        assert!(!within(Vec3::new(-1.0, -1.0, 200.0), Vec3::splat(2.0)));
+    }
+}
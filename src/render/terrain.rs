@@ -1,27 +1,43 @@
-use std::time::Instant;
+use std::{path::Path, sync::Arc, time::Instant};
 
 use generational_arena::Index;
-use glam::Vec3;
+use glam::{IVec3, Vec3};
 use itertools::Itertools;
 
 use self::{
-    chunk_batching::ChunkBatches, vertex::TerrainVertex, visibility_search::visibility_search,
+    chunk_batching::{ChunkBatches, CHUNK_BATCH_TOTAL_SIZE},
+    lod::LodLevel,
+    mesh_cache::SharedChunkMesh,
+    meshing::{MeshLayer, MeshingStrategy, NormalMode},
+    mesh_throttle::MeshThrottle,
+    mesh_time_stats::{MeshTimeSample, MeshTimeStats},
+    vertex::TerrainVertex,
+    visibility_search::visibility_search,
 };
 use super::{
     frustum_culling::FrustumCullingRegions,
     render_context::RenderContext,
     render_engine::RenderEngine,
+    render_pass::PassTargets,
+    shadow::{ShadowMaps, ShadowUniforms, SHADOW_MAP_FORMAT},
     util::{
         bind_group_builder::BindGroupBuilder,
         mip_generator::MipGenerator,
         pipeline_builder::RenderPipelineBuilder,
-        texture::{ArrayTexture, TextureConfig, TextureHolder},
+        shader_source::{shader_source, ReloadablePipeline},
+        texture::{AlphaClass, ArrayTexture, TextureConfig, TextureHolder},
     },
 };
 use crate::{
+    block::{
+        model::BlockModel,
+        texture::{BLOCK_TEXTURES, BLOCK_TEXTURE_ATLAS_PATH, BLOCK_TEXTURE_TILE_SIZE},
+        BLOCKS,
+        TINT_PALETTE,
+    },
     tasks::{TaskId, Tasks},
     terrain::{
-        chunk::Chunk,
+        chunk::{Chunk, CHUNK_SIZE, CHUNK_SIZE_U32},
         event::TerrainEvent,
         load_area::LoadArea,
         position_types::{ChunkPosition, LocalBlockPosition},
@@ -29,12 +45,85 @@ use crate::{
     },
     time::Time,
     CHUNK_MESH_GENERATION_PRIORITY, CHUNK_MESH_OPTIMIZATION_PRIORITY, CHUNK_MESH_UPDATE_PRIORITY,
+    MESH_THROTTLE_JOB_BUDGET, MESH_THROTTLE_PROMPT_RADIUS, MESH_THROTTLE_SPEED_THRESHOLD,
+};
+#[cfg(test)]
+use self::meshing::MeshingOptions;
+#[cfg(test)]
+use crate::terrain::{
+    chunk::{border::ChunkBorder, side::ChunkSide, storage::ChunkBlockStorage},
+    lighting::ChunkLightSnapshot,
 };
 
 mod chunk_batching;
-mod meshing;
-mod vertex;
-mod visibility_search;
+#[cfg(feature = "export")]
+mod export;
+pub mod lod;
+pub mod mesh_cache;
+pub mod mesh_throttle;
+pub mod mesh_time_stats;
+pub mod mesh_upload_queue;
+pub mod meshing;
+pub mod vertex;
+pub mod visibility_search;
+
+/// Face culling modes that a terrain pipeline is built for, the first being the default
+const FACE_CULL_MODES: [Option<wgpu::Face>; 3] =
+    [Some(wgpu::Face::Back), None, Some(wgpu::Face::Front)];
+
+/// Number of visible chunks closest to the camera whose translucent faces are sorted back to
+/// front, see `meshing::generate_sorted_indices`
+const SORTED_TRANSLUCENT_CHUNK_COUNT: usize = 4;
+
+/// Indices drawing the translucent faces of one chunk back to front as seen from a camera
+/// position
+#[derive(Debug)]
+struct SortedIndexBuffer {
+    chunk_pos: ChunkPosition,
+    /// Translucent vertices of the chunk that the indices were sorted for
+    vertices: Arc<[TerrainVertex]>,
+    /// Camera position that the indices were sorted for, in chunk-local coordinates
+    camera_pos: Vec3,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+impl SortedIndexBuffer {
+    /// Sort the quads of `vertices` for the given camera position, writing the indices to
+    /// `index_buffer` if it is large enough, or to a new buffer otherwise
+    fn new(
+        cx: &RenderContext,
+        chunk_pos: ChunkPosition,
+        vertices: Arc<[TerrainVertex]>,
+        camera_pos: Vec3,
+        index_buffer: Option<wgpu::Buffer>,
+    ) -> Self {
+        let indices = meshing::generate_sorted_indices(&vertices, camera_pos);
+        let size = std::mem::size_of_val(indices.as_slice()) as wgpu::BufferAddress;
+
+        let index_buffer = index_buffer
+            .filter(|index_buffer| index_buffer.size() >= size)
+            .unwrap_or_else(|| {
+                cx.device
+                    .create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Sorted Translucent Index Buffer"),
+                        size,
+                        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    })
+            });
+        cx.queue
+            .write_buffer(&index_buffer, 0, bytemuck::cast_slice(&indices));
+
+        Self {
+            chunk_pos,
+            vertices,
+            camera_pos,
+            index_buffer,
+            index_count: indices.len() as u32,
+        }
+    }
+}
 
 /// Responsible for rendering the voxel terrain
 #[derive(Debug)]
@@ -46,10 +135,31 @@ pub struct TerrainRenderer {
     frame_last_drawn: Vec<usize>,
     /// Cull mode to use
     cull_mode: TerrainCullMode,
-    /// Render pipeline for drawing chunk batches
-    terrain_pipeline: wgpu::RenderPipeline,
+    /// Render pipelines for drawing chunk batches, rebuilt when the shader is modified
+    terrain_pipelines: ReloadablePipeline<TerrainPipelines>,
+    /// Render pipeline for drawing chunk batches into the shadow maps, rebuilt when the shader is
+    /// modified
+    shadow_pipeline: ReloadablePipeline,
+    /// Which faces of triangles are culled, one of `FACE_CULL_MODES`
+    face_cull_mode: Option<wgpu::Face>,
+    /// Whether the terrain pipelines draw triangle edges instead of filled triangles
+    wireframe: bool,
     /// Bind group for the texture array
     texture_bind_group: wgpu::BindGroup,
+    /// Layout of the texture array bind group, shared with other renderers that sample the
+    /// terrain textures
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    /// Defers new chunk meshes while the camera is moving quickly
+    mesh_throttle: MeshThrottle,
+    /// Camera position in the previous frame, used to compute the camera speed
+    last_camera_pos: Option<Vec3>,
+    /// Positions of the chunks that survived culling this frame, in the order they are drawn
+    render_queue: Vec<ChunkPosition>,
+    /// Index buffers drawing the translucent faces of the chunks closest to the camera back to
+    /// front
+    sorted_index_buffers: Vec<SortedIndexBuffer>,
+    /// Number of chunks, batches and triangles drawn in the last frame
+    draw_stats: TerrainDrawStats,
 }
 
 impl TerrainRenderer {
@@ -58,26 +168,34 @@ impl TerrainRenderer {
     pub fn new(
         cx: &RenderContext,
         common_uniforms_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_maps: &ShadowMaps,
         load_area: &LoadArea,
         cull_mode: TerrainCullMode,
     ) -> Self {
         // TODO load texture and shader using proper asset system rather than doing it here
-        let texture_array = ArrayTexture::from_files(
-            &cx.device,
-            &cx.queue,
-            &[
-                "assets/image/block/dirt.png",
-                "assets/image/block/grass_side.png",
-                "assets/image/block/grass_top.png",
-                "assets/image/block/wood.png",
-                "assets/image/block/lamp_orange.png",
-            ],
-            image::ImageFormat::Png,
-            &TextureConfig {
-                mip_level_count: Self::MIP_LEVEL_COUNT,
-                ..Default::default()
-            },
-        )
+        let texture_config = TextureConfig {
+            label: Some("terrain textures"),
+            mip_level_count: Self::MIP_LEVEL_COUNT,
+            classify_alpha: true,
+            ..Default::default()
+        };
+        let texture_array = if Path::new(BLOCK_TEXTURE_ATLAS_PATH).exists() {
+            ArrayTexture::from_atlas_file(
+                &cx.device,
+                &cx.queue,
+                BLOCK_TEXTURE_ATLAS_PATH,
+                BLOCK_TEXTURE_TILE_SIZE,
+                &texture_config,
+            )
+        } else {
+            ArrayTexture::from_files(
+                &cx.device,
+                &cx.queue,
+                &BLOCK_TEXTURES.paths(),
+                image::ImageFormat::Png,
+                &texture_config,
+            )
+        }
         .expect("failed to load terrain textures")
         .with_view_and_sampler(
             &cx.device,
@@ -88,6 +206,8 @@ impl TerrainRenderer {
             },
         );
 
+        log_block_model_overrides(texture_array.alpha_classes());
+
         // generate mipmaps
         let mut mip_encoder = cx
             .device
@@ -104,6 +224,21 @@ impl TerrainRenderer {
         cx.queue
             .submit(std::iter::once(mip_encoder.finish()));
 
+        let tint_palette_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Tint Palette Buffer"),
+                size: std::mem::size_of_val(&TINT_PALETTE) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        cx.queue.write_buffer(
+            &tint_palette_buffer,
+            0 as wgpu::BufferAddress,
+            bytemuck::cast_slice(&TINT_PALETTE),
+        );
+
         let (texture_bind_group, texture_bind_group_layout) = BindGroupBuilder::new()
             .with_label("Texture Array Bind Group")
             .with_texture_view(
@@ -117,6 +252,7 @@ impl TerrainRenderer {
                 wgpu::SamplerBindingType::Filtering,
                 wgpu::ShaderStages::FRAGMENT,
             )
+            .with_uniform_buffer(&tint_palette_buffer, wgpu::ShaderStages::FRAGMENT)
             .build(&cx.device);
 
         let batch_bind_group_layout =
@@ -135,26 +271,35 @@ impl TerrainRenderer {
                     }],
                 });
 
-        let terrain_shader = cx
-            .device
-            .create_shader_module(wgpu::include_wgsl!("../../assets/shader/terrain.wgsl"));
+        let terrain_shader = shader_source!("terrain.wgsl");
+        let terrain_module = terrain_shader.create_module(&cx.device);
 
-        let (terrain_pipeline, _) = RenderPipelineBuilder::new()
-            .with_label("Terrain Pipeline")
-            .with_bind_group_layout(&texture_bind_group_layout)
-            .with_bind_group_layout(&common_uniforms_bind_group_layout)
-            .with_bind_group_layout(&batch_bind_group_layout)
-            .with_vertex::<TerrainVertex>()
-            .with_vertex_shader(&terrain_shader, "vs_main")
-            .with_fragment_shader(&terrain_shader, "fs_main")
-            .with_color_target(
-                cx.surface_config.format,
-                Some(wgpu::BlendState::REPLACE),
-                wgpu::ColorWrites::all(),
-            )
-            .with_depth(RenderEngine::DEPTH_FORMAT, RenderEngine::DEPTH_COMPARE)
-            //.with_polygon_mode(wgpu::PolygonMode::Line)
-            .build(&cx.device);
+        let terrain_pipeline_layout =
+            cx.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Terrain Pipeline Layout"),
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        common_uniforms_bind_group_layout,
+                        &batch_bind_group_layout,
+                        shadow_maps.bind_group_layout(),
+                    ],
+                    push_constant_ranges: &[],
+                });
+
+        let terrain_pipelines =
+            TerrainPipelines::new(cx, &terrain_module, &terrain_pipeline_layout, false);
+
+        let shadow_shader = shader_source!("shadow.wgsl");
+        let shadow_module = shadow_shader.create_module(&cx.device);
+
+        let (shadow_pipeline, shadow_pipeline_layout) =
+            Self::shadow_pipeline_builder(&shadow_module)
+                .with_bind_group_layout(&texture_bind_group_layout)
+                .with_bind_group_layout(common_uniforms_bind_group_layout)
+                .with_bind_group_layout(&batch_bind_group_layout)
+                .with_bind_group_layout(shadow_maps.cascade_bind_group_layout())
+                .build(&cx.device);
 
         let chunk_batches = ChunkBatches::new(cx, load_area, batch_bind_group_layout);
 
@@ -164,18 +309,131 @@ impl TerrainRenderer {
             chunk_batches,
             frame_last_drawn,
             cull_mode,
-            terrain_pipeline,
+            terrain_pipelines: ReloadablePipeline::new(
+                terrain_shader,
+                terrain_pipeline_layout,
+                terrain_pipelines,
+            ),
+            shadow_pipeline: ReloadablePipeline::new(
+                shadow_shader,
+                shadow_pipeline_layout,
+                shadow_pipeline,
+            ),
+            face_cull_mode: FACE_CULL_MODES[0],
+            wireframe: false,
             texture_bind_group,
+            texture_bind_group_layout,
+            mesh_throttle: MeshThrottle::new(
+                MESH_THROTTLE_SPEED_THRESHOLD,
+                MESH_THROTTLE_JOB_BUDGET,
+                MESH_THROTTLE_PROMPT_RADIUS,
+            ),
+            last_camera_pos: None,
+            render_queue: Vec::new(),
+            sorted_index_buffers: Vec::new(),
+            draw_stats: TerrainDrawStats::default(),
         }
     }
 
-    /// Called once per frame to render the terrain
-    pub fn render(
+    /// Builder for the pipeline drawing the given layer of the chunk meshes. Translucent faces
+    /// are blended over what is behind them, and are depth tested without writing depth.
+    /// Two-sided quads are never culled, whatever `face_cull_mode` is
+    fn terrain_pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+        face_cull_mode: Option<wgpu::Face>,
+        wireframe: bool,
+        layer: MeshLayer,
+    ) -> RenderPipelineBuilder<'a> {
+        let polygon_mode = if wireframe {
+            wgpu::PolygonMode::Line
+        } else {
+            wgpu::PolygonMode::Fill
+        };
+
+        let (label, fragment_entry_point, blend) = match layer {
+            MeshLayer::Opaque => ("Terrain Pipeline", "fs_main", wgpu::BlendState::REPLACE),
+            MeshLayer::Translucent => (
+                "Translucent Terrain Pipeline",
+                "fs_translucent",
+                wgpu::BlendState::ALPHA_BLENDING,
+            ),
+            MeshLayer::TwoSided => (
+                "Two-Sided Terrain Pipeline",
+                "fs_main",
+                wgpu::BlendState::REPLACE,
+            ),
+        };
+        let face_cull_mode = face_cull_mode.filter(|_| layer != MeshLayer::TwoSided);
+
+        RenderPipelineBuilder::new()
+            .with_label(label)
+            .with_vertex::<TerrainVertex>()
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, fragment_entry_point)
+            .with_color_target(cx.surface_config.format, Some(blend), wgpu::ColorWrites::all())
+            .with_depth(RenderEngine::DEPTH_FORMAT, RenderEngine::DEPTH_COMPARE)
+            .with_depth_write(layer != MeshLayer::Translucent)
+            .with_front_face(meshing::FRONT_FACE)
+            .with_cull_mode(face_cull_mode)
+            .with_polygon_mode(polygon_mode)
+    }
+
+    /// Builder for the pipeline drawing the opaque and two-sided layers of the chunk meshes into
+    /// the shadow maps. Both sides of every face are drawn, so that light doesn't leak through
+    /// thin walls, and the depth is biased by the slope of the face so that faces don't shadow
+    /// themselves
+    fn shadow_pipeline_builder(shader: &wgpu::ShaderModule) -> RenderPipelineBuilder<'_> {
+        RenderPipelineBuilder::new()
+            .with_label("Terrain Shadow Pipeline")
+            .with_vertex::<TerrainVertex>()
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_depth(SHADOW_MAP_FORMAT, wgpu::CompareFunction::LessEqual)
+            .with_depth_bias(wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            })
+            .with_cull_mode(None)
+    }
+
+    /// Chunks to draw with the current cull mode, in front to back order for the visibility
+    /// search and in arena order otherwise
+    pub fn visible_chunks<'a>(
+        &self,
+        terrain: &'a Terrain,
+        load_area_index: Index,
+        frustum_culling_regions: &FrustumCullingRegions,
+        camera_pos: Vec3,
+    ) -> Vec<&'a Chunk> {
+        match self.cull_mode {
+            TerrainCullMode::CullNone => terrain
+                .chunks()
+                .iter()
+                .map(|(_, chunk)| chunk)
+                .collect_vec(),
+            TerrainCullMode::Frustum => terrain
+                .chunks()
+                .iter()
+                .map(|(_, chunk)| chunk)
+                .filter(|chunk| frustum_culling_regions.is_chunk_within_frustum(&chunk.position()))
+                .collect_vec(),
+            TerrainCullMode::VisibilitySearch => visibility_search(
+                terrain,
+                load_area_index,
+                frustum_culling_regions,
+                camera_pos,
+            ),
+        }
+    }
+
+    /// Called once per frame before rendering, to process terrain events and request mesh updates
+    /// for the chunks that will be drawn, given by `render_queue` in the order returned by
+    /// `visible_chunks`. This runs even when the terrain pass is disabled, so that chunk batches
+    /// stay in sync with the terrain
+    pub fn update(
         &mut self,
-        render_encoder: &mut wgpu::CommandEncoder,
-        output_view: &wgpu::TextureView,
-        depth_view: &wgpu::TextureView,
-        common_uniforms_bind_group: &wgpu::BindGroup,
         cx: &RenderContext,
         time: &Time,
         tasks: &mut Tasks,
@@ -183,45 +441,80 @@ impl TerrainRenderer {
         load_area_index: Index,
         frustum_culling_regions: &FrustumCullingRegions,
         camera_pos: Vec3,
+        render_queue: &[&Chunk],
     ) {
+        let wireframe = self.wireframe;
+        self.terrain_pipelines
+            .reload_if_changed(&cx.device, |module, layout| {
+                TerrainPipelines::new(cx, module, layout, wireframe)
+            });
+        self.shadow_pipeline
+            .reload_if_changed(&cx.device, |module, layout| {
+                Self::shadow_pipeline_builder(module)
+                    .with_layout(layout)
+                    .build_with_existing_layout(&cx.device)
+            });
+
         // process terrain events
         for event in terrain.events() {
             match event {
                 TerrainEvent::ChunkLoaded(chunk_pos) => self.chunk_loaded(*chunk_pos),
                 TerrainEvent::ChunkUnloaded(chunk_pos) => self.chunk_unloaded(*chunk_pos),
                 TerrainEvent::BlockModified(chunk_pos, local_block_pos) => {
-                    self.chunk_modified(chunk_pos, local_block_pos)
+                    self.chunk_modified(chunk_pos, Some(local_block_pos))
                 }
+                TerrainEvent::ChunkModified(chunk_pos) => self.chunk_modified(chunk_pos, None),
+                TerrainEvent::ChunkLightChanged(chunk_pos) => self.mark_chunk_outdated(chunk_pos),
+                TerrainEvent::ChunkRemeshRequested(chunk_pos) => self.chunk_remesh_requested(
+                    chunk_pos,
+                    tasks,
+                    terrain,
+                    load_area_index,
+                    camera_pos,
+                ),
             }
         }
 
         // update chunk batches
         self.chunk_batches
-            .update(cx, terrain, load_area_index);
+            .update(cx, terrain, load_area_index, time.elapsed_seconds());
 
-        // get the list of chunks to be rendered in order
-        let render_queue = match self.cull_mode {
-            TerrainCullMode::CullNone => terrain
+        // chunks hidden by the visibility search are those within the frustum that it didn't reach
+        let chunks_within_frustum = match self.cull_mode {
+            TerrainCullMode::CullNone => terrain.chunks().len(),
+            TerrainCullMode::Frustum => render_queue.len(),
+            TerrainCullMode::VisibilitySearch => terrain
                 .chunks()
                 .iter()
-                .map(|(_, chunk)| chunk)
-                .collect_vec(),
-            TerrainCullMode::Frustum => terrain
-                .chunks()
-                .iter()
-                .map(|(_, chunk)| chunk)
-                .filter(|chunk| frustum_culling_regions.is_chunk_within_frustum(&chunk.position()))
-                .collect_vec(),
-            TerrainCullMode::VisibilitySearch => visibility_search(
-                terrain,
-                load_area_index,
-                frustum_culling_regions,
-                camera_pos,
-            ),
+                .filter(|(_, chunk)| {
+                    frustum_culling_regions.is_chunk_within_frustum(&chunk.position())
+                })
+                .count(),
+        };
+
+        self.draw_stats = TerrainDrawStats {
+            chunks_visible: render_queue.len(),
+            chunks_culled_by_frustum: terrain.chunks().len() - chunks_within_frustum,
+            chunks_culled_by_occlusion: chunks_within_frustum.saturating_sub(render_queue.len()),
+            ..Default::default()
         };
 
+        // update the mesh throttle with the camera speed
+        let camera_speed = self
+            .last_camera_pos
+            .map_or(0.0, |last_camera_pos| {
+                last_camera_pos.distance(camera_pos) / time.delta_seconds().max(f32::EPSILON)
+            });
+        self.last_camera_pos = Some(camera_pos);
+        self.mesh_throttle
+            .begin_frame(camera_speed);
+
         // request mesh updates for visible chunks
-        for chunk in &render_queue {
+        for chunk in render_queue {
+            if self.is_mesh_request_throttled(chunk, camera_pos, frustum_culling_regions) {
+                continue;
+            }
+
             self.request_mesh_updates_for_chunk(
                 cx,
                 chunk,
@@ -232,37 +525,89 @@ impl TerrainRenderer {
             );
         }
 
-        // begin render pass
-        let mut render_pass = render_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Terrain Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: output_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.25,
-                        g: 0.45,
-                        b: 1.0,
-                        a: 1.0,
-                    }),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: depth_view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: wgpu::StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
-            occlusion_query_set: None,
-            timestamp_writes: None,
-        });
+        self.render_queue.clear();
+        self.render_queue
+            .extend(render_queue.iter().map(|chunk| chunk.position()));
+
+        self.update_sorted_index_buffers(cx, camera_pos);
+    }
+
+    /// Sort the translucent faces of the visible chunks closest to the camera, reusing the
+    /// indices of chunks whose mesh and relative camera position haven't changed
+    fn update_sorted_index_buffers(&mut self, cx: &RenderContext, camera_pos: Vec3) {
+        let nearest_chunks = self
+            .render_queue
+            .iter()
+            .filter_map(|chunk_pos| {
+                let (batch_pos, chunk_pos_in_batch) =
+                    ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(chunk_pos);
+                let vertices = &self
+                    .chunk_batches
+                    .get_batch(&batch_pos)?
+                    .get_chunk_mesh_data(&chunk_pos_in_batch)?
+                    .translucent_vertices;
+                if vertices.is_empty() {
+                    return None;
+                }
+
+                let chunk_center = (chunk_pos.as_vec3() + 0.5) * CHUNK_SIZE as f32;
+                Some((chunk_center.distance_squared(camera_pos), *chunk_pos, vertices.clone()))
+            })
+            .k_smallest_by(SORTED_TRANSLUCENT_CHUNK_COUNT, |(a, ..), (b, ..)| a.total_cmp(b))
+            .collect_vec();
+
+        let mut old_buffers = std::mem::take(&mut self.sorted_index_buffers);
+
+        for (_, chunk_pos, vertices) in nearest_chunks {
+            // the vertices are in chunk-local coordinates
+            let local_camera_pos = camera_pos - (chunk_pos.as_vec3() * CHUNK_SIZE as f32);
+
+            let old_buffer = old_buffers
+                .iter()
+                .position(|buffer| buffer.chunk_pos == chunk_pos)
+                .map(|index| old_buffers.swap_remove(index));
+
+            let sorted_index_buffer = match old_buffer {
+                Some(buffer)
+                    if Arc::ptr_eq(&buffer.vertices, &vertices)
+                        && buffer.camera_pos == local_camera_pos =>
+                {
+                    buffer
+                }
+                old_buffer => SortedIndexBuffer::new(
+                    cx,
+                    chunk_pos,
+                    vertices,
+                    local_camera_pos,
+                    old_buffer
+                        .or_else(|| old_buffers.pop())
+                        .map(|buffer| buffer.index_buffer),
+                ),
+            };
+            self.sorted_index_buffers
+                .push(sorted_index_buffer);
+        }
+    }
 
-        render_pass.set_pipeline(&self.terrain_pipeline);
+    /// Called once per frame after `update` to draw the chunks that survived culling
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        shadow_bind_group: &wgpu::BindGroup,
+        time: &Time,
+    ) {
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(
+            self.terrain_pipelines
+                .get()
+                .get(MeshLayer::Opaque, self.face_cull_mode),
+        );
         render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-        render_pass.set_bind_group(1, &common_uniforms_bind_group, &[]);
+        render_pass.set_bind_group(1, common_uniforms_bind_group, &[]);
+        render_pass.set_bind_group(3, shadow_bind_group, &[]);
         render_pass.set_index_buffer(
             self.chunk_batches
                 .shared_index_buffer()
@@ -270,9 +615,9 @@ impl TerrainRenderer {
             wgpu::IndexFormat::Uint32,
         );
 
-        for chunk in &render_queue {
-            let (batch_pos, _) =
-                ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(&chunk.position());
+        let mut two_sided_batches = Vec::new();
+        for chunk_pos in &self.render_queue {
+            let (batch_pos, _) = ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(chunk_pos);
             let batch_index = self
                 .chunk_batches
                 .get_batch_index(&batch_pos);
@@ -284,19 +629,357 @@ impl TerrainRenderer {
             let Some(batch) = self.chunk_batches.get_batch(&batch_pos) else {
                 continue;
             };
-            if batch.vertex_count() == 0 {
-                continue;
+
+            self.frame_last_drawn[batch_index] = time.frame_index();
+
+            let mut drawn = false;
+            for draw in batch.opaque_draws() {
+                let index_range = draw.index_range();
+
+                render_pass.set_bind_group(2, draw.uniform_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+                render_pass.draw_indexed(index_range.clone(), 0, 0..1);
+
+                self.draw_stats.triangles_drawn += index_range.len() / 3;
+                drawn = true;
             }
-            let Some(vertex_buffer) = batch.vertex_buffer() else {
-                continue;
+            if batch.two_sided_draws().next().is_some() {
+                two_sided_batches.push(batch);
+                drawn = true;
+            }
+            if drawn {
+                self.draw_stats.batches_drawn += 1;
+            }
+        }
+
+        // plants are drawn once the rest of the batches are, so that the pipeline only changes
+        // once per frame
+        if two_sided_batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(
+            self.terrain_pipelines
+                .get()
+                .get(MeshLayer::TwoSided, self.face_cull_mode),
+        );
+        for draw in two_sided_batches
+            .iter()
+            .flat_map(|batch| batch.two_sided_draws())
+        {
+            let index_range = draw.index_range();
+
+            render_pass.set_bind_group(2, draw.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+            render_pass.draw_indexed(index_range.clone(), 0, 0..1);
+
+            self.draw_stats.triangles_drawn += index_range.len() / 3;
+        }
+    }
+
+    /// Called once per frame after `render` and any other opaque geometry to draw the translucent
+    /// faces of the chunks that survived culling. The chunks are drawn one at a time from back to
+    /// front, so that nearer translucent chunks blend over further ones. The faces within each of
+    /// the `SORTED_TRANSLUCENT_CHUNK_COUNT` nearest chunks are drawn back to front too.
+    /// NB: faces within further chunks are not sorted, so overlapping translucent faces in one of
+    /// them can still blend in the wrong order
+    pub fn render_translucent(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        shadow_bind_group: &wgpu::BindGroup,
+    ) {
+        let camera_pos = self.last_camera_pos.unwrap_or_default();
+
+        // find the visible chunks with translucent faces, furthest first
+        let mut translucent_chunks = self
+            .render_queue
+            .iter()
+            .filter_map(|chunk_pos| {
+                let (batch_pos, chunk_pos_in_batch) =
+                    ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(chunk_pos);
+                let batch = self.chunk_batches.get_batch(&batch_pos)?;
+                let draw = batch.translucent_draw(&chunk_pos_in_batch)?;
+
+                let chunk_center = (chunk_pos.as_vec3() + 0.5) * CHUNK_SIZE as f32;
+                Some((chunk_center.distance_squared(camera_pos), chunk_pos, draw))
+            })
+            .collect_vec();
+        translucent_chunks.sort_by(|(a, ..), (b, ..)| b.total_cmp(a));
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(
+            self.terrain_pipelines
+                .get()
+                .get(MeshLayer::Translucent, self.face_cull_mode),
+        );
+        render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(1, common_uniforms_bind_group, &[]);
+        render_pass.set_bind_group(3, shadow_bind_group, &[]);
+        render_pass.set_index_buffer(
+            self.chunk_batches
+                .shared_index_buffer()
+                .slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+
+        let shared_index_buffer = self.chunk_batches.shared_index_buffer();
+        let mut sorted_index_buffer_bound = false;
+
+        for (_, chunk_pos, draw) in translucent_chunks {
+            render_pass.set_bind_group(2, draw.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+
+            let sorted_index_buffer = self
+                .sorted_index_buffers
+                .iter()
+                .find(|buffer| buffer.chunk_pos == *chunk_pos)
+                .filter(|buffer| buffer.vertices.len() == draw.vertex_range.len());
+
+            let index_count = if let Some(sorted_index_buffer) = sorted_index_buffer {
+                // the sorted indices count from the chunk's first vertex
+                render_pass.set_index_buffer(
+                    sorted_index_buffer.index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                sorted_index_buffer_bound = true;
+
+                let index_count = sorted_index_buffer.index_count;
+                render_pass.draw_indexed(0..index_count, draw.vertex_range.start as i32, 0..1);
+                index_count as usize
+            } else {
+                if sorted_index_buffer_bound {
+                    render_pass
+                        .set_index_buffer(shared_index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    sorted_index_buffer_bound = false;
+                }
+
+                let index_range = draw.index_range();
+                render_pass.draw_indexed(index_range.clone(), 0, 0..1);
+                index_range.len()
             };
 
-            self.frame_last_drawn[batch_index] = time.frame_index();
+            self.draw_stats.triangles_drawn += index_count / 3;
+        }
+    }
+
+    /// Called once per frame after `update` and before `render` to draw the opaque and two-sided
+    /// layers of the chunk batches into the shadow map of each cascade, culling batches outside
+    /// it. Chunks outside the view frustum still cast shadows into it, but only once they have
+    /// been meshed, i.e. after they have been in view
+    pub fn render_shadows(
+        &self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        shadow_maps: &ShadowMaps,
+        shadow_uniforms: &ShadowUniforms,
+        common_uniforms_bind_group: &wgpu::BindGroup,
+        render_origin: IVec3,
+    ) {
+        for cascade in 0..shadow_maps.cascade_count() {
+            let (view, cascade_bind_group) = shadow_maps.cascade_target(cascade);
+
+            let mut render_pass = render_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Terrain Shadow Render Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(self.shadow_pipeline.get());
+            render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+            render_pass.set_bind_group(1, common_uniforms_bind_group, &[]);
+            render_pass.set_bind_group(3, cascade_bind_group, &[]);
+            render_pass.set_index_buffer(
+                self.chunk_batches
+                    .shared_index_buffer()
+                    .slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+
+            for batch in self.chunk_batches.batches() {
+                let batch_min =
+                    (batch.position() * CHUNK_BATCH_TOTAL_SIZE as i32 - render_origin).as_vec3();
+                let batch_max = batch_min + CHUNK_BATCH_TOTAL_SIZE as f32;
+                if !shadow_uniforms.is_box_within_cascade(cascade, batch_min, batch_max) {
+                    continue;
+                }
+
+                for draw in batch
+                    .opaque_draws()
+                    .chain(batch.two_sided_draws())
+                {
+                    render_pass.set_bind_group(2, draw.uniform_bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+                    render_pass.draw_indexed(draw.index_range(), 0, 0..1);
+                }
+            }
+        }
+    }
+
+    /// Number of chunks, batches and triangles drawn in the last frame
+    pub fn draw_stats(&self) -> TerrainDrawStats {
+        self.draw_stats
+    }
+
+    /// Time taken to build recent chunk meshes on the worker threads
+    pub fn mesh_time_stats(&self) -> &MeshTimeStats {
+        self.chunk_batches.mesh_time_stats()
+    }
+
+    /// Which faces of triangles are culled. Back faces are culled normally
+    pub fn face_cull_mode(&self) -> Option<wgpu::Face> {
+        self.face_cull_mode
+    }
+
+    /// Change which faces of triangles are culled, for debugging the winding of block models:
+    /// with None, faces with the wrong winding are drawn instead of disappearing, and culling
+    /// front faces shows only those faces. Takes effect immediately, as a pipeline is built for
+    /// each mode up front
+    pub fn set_face_cull_mode(&mut self, face_cull_mode: Option<wgpu::Face>) {
+        self.face_cull_mode = face_cull_mode;
+    }
+
+    /// Whether the terrain is drawn as a wireframe
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    /// Switch between drawing the terrain as filled triangles and as a wireframe, rebuilding the
+    /// terrain pipelines. Wireframes need `POLYGON_MODE_LINE`, so if the device doesn't support it
+    /// the terrain stays filled. Returns whether the terrain is now drawn as a wireframe
+    pub fn set_wireframe(&mut self, cx: &RenderContext, wireframe: bool) -> bool {
+        if wireframe
+            && !cx
+                .supported_features()
+                .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            log::warn!("wireframe terrain is not supported by this device");
+            return self.wireframe;
+        }
+
+        if wireframe != self.wireframe {
+            let rebuilt = self
+                .terrain_pipelines
+                .rebuild(&cx.device, |module, layout| {
+                    TerrainPipelines::new(cx, module, layout, wireframe)
+                });
+            if rebuilt {
+                self.wireframe = wireframe;
+            }
+        }
+
+        self.wireframe
+    }
+
+    /// Strategy used to mesh chunks without an override
+    pub fn default_meshing_strategy(&self) -> MeshingStrategy {
+        self.chunk_batches
+            .default_meshing_strategy()
+    }
+
+    /// See `ChunkBatches::set_default_meshing_strategy`
+    pub fn set_default_meshing_strategy(&mut self, strategy: MeshingStrategy) {
+        self.chunk_batches
+            .set_default_meshing_strategy(strategy);
+    }
+
+    /// See `ChunkBatches::normal_mode`
+    pub fn normal_mode(&self) -> NormalMode {
+        self.chunk_batches.normal_mode()
+    }
+
+    /// See `ChunkBatches::set_normal_mode`
+    pub fn set_normal_mode(&mut self, normal_mode: NormalMode) {
+        self.chunk_batches
+            .set_normal_mode(normal_mode);
+    }
+
+    /// See `ChunkBatches::lod_distance`
+    pub fn lod_distance(&self) -> Option<f32> {
+        self.chunk_batches.lod_distance()
+    }
+
+    /// See `ChunkBatches::set_lod_distance`
+    pub fn set_lod_distance(&mut self, lod_distance: Option<f32>) {
+        self.chunk_batches
+            .set_lod_distance(lod_distance);
+    }
+
+    /// See `ChunkBatches::mesh_upload_budget`
+    pub fn mesh_upload_budget(&self) -> usize {
+        self.chunk_batches.mesh_upload_budget()
+    }
+
+    /// See `ChunkBatches::set_mesh_upload_budget`
+    pub fn set_mesh_upload_budget(&mut self, budget: usize) {
+        self.chunk_batches
+            .set_mesh_upload_budget(budget);
+    }
+
+    /// See `ChunkBatches::pending_mesh_upload_count`
+    pub fn pending_mesh_upload_count(&self) -> usize {
+        self.chunk_batches.pending_mesh_upload_count()
+    }
+
+    /// See `ChunkBatches::set_meshing_strategy`
+    pub fn set_meshing_strategy(
+        &mut self,
+        chunk_pos: ChunkPosition,
+        strategy: Option<MeshingStrategy>,
+    ) {
+        self.chunk_batches
+            .set_meshing_strategy(chunk_pos, strategy);
+    }
+
+    /// Bind group for the terrain texture array, its sampler and the tint palette
+    pub fn texture_bind_group(&self) -> &wgpu::BindGroup {
+        &self.texture_bind_group
+    }
+
+    /// Layout of `texture_bind_group`
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_bind_group_layout
+    }
+
+    /// Mesh throttle used to defer new chunk meshes while the camera is moving quickly
+    pub fn mesh_throttle_mut(&mut self) -> &mut MeshThrottle {
+        &mut self.mesh_throttle
+    }
 
-            render_pass.set_bind_group(2, batch.uniform_bind_group(), &[]);
-            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_pass.draw_indexed(0..(batch.index_count() as u32), 0, 0..1);
+    /// Returns true if a new or suboptimal mesh for the given chunk should be deferred to a later
+    /// frame because the camera is moving quickly
+    fn is_mesh_request_throttled(
+        &mut self,
+        chunk: &Chunk,
+        camera_pos: Vec3,
+        frustum_culling_regions: &FrustumCullingRegions,
+    ) -> bool {
+        // modified chunks are always remeshed promptly, as the player is looking at them
+        let mesh_status = self
+            .chunk_batches
+            .get_chunk_mesh_status(&chunk.position());
+        if !mesh_status.is_missing() && !mesh_status.is_suboptimal() {
+            return false;
         }
+
+        let distance_to_camera = (chunk.position() - ChunkPosition::containing(camera_pos))
+            .as_ivec3()
+            .as_vec3()
+            .length();
+        let within_frustum = frustum_culling_regions.is_chunk_within_frustum(&chunk.position());
+
+        !self
+            .mesh_throttle
+            .allow(distance_to_camera, within_frustum)
     }
 
     /// Request any necessary mesh updates for the given chunk
@@ -309,14 +992,15 @@ impl TerrainRenderer {
         load_area_index: Index,
         camera_pos: Vec3,
     ) {
-        let (batch_pos, chunk_pos_in_batch) =
-            ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(&chunk.position());
+        let (batch_pos, _) = ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(&chunk.position());
 
-        let batch = self
-            .chunk_batches
-            .get_or_repurpose_batch(cx, tasks, &batch_pos);
+        self.chunk_batches
+            .get_or_repurpose_batch(cx, tasks, terrain, &batch_pos);
 
-        let remeshing_priority = match batch.get_chunk_mesh_status(&chunk_pos_in_batch) {
+        let remeshing_priority = match self
+            .chunk_batches
+            .get_chunk_mesh_status(&chunk.position())
+        {
             ChunkMeshStatus::Good | ChunkMeshStatus::Generating(_) => None,
             ChunkMeshStatus::Missing => Some(CHUNK_MESH_GENERATION_PRIORITY),
             ChunkMeshStatus::Outdated => Some(CHUNK_MESH_UPDATE_PRIORITY),
@@ -362,8 +1046,11 @@ impl TerrainRenderer {
     }
 
     /// Called when a chunk has been unloaded to remove its mesh from the batch containing
-    /// it
+    /// it and forget the meshing strategy chosen for it
     fn chunk_unloaded(&mut self, chunk_pos: ChunkPosition) {
+        self.chunk_batches
+            .set_meshing_strategy(chunk_pos, None);
+
         let (batch_pos, chunk_pos_in_batch) =
             ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(&chunk_pos);
 
@@ -375,8 +1062,38 @@ impl TerrainRenderer {
         }
     }
 
-    /// Called when a block in a chunk has been modified
-    fn chunk_modified(&mut self, chunk_pos: &ChunkPosition, _block_pos: &LocalBlockPosition) {
+    /// Called when a block in a chunk has been modified. Blocks on the edge of the chunk also
+    /// affect the meshes of the neighbouring chunks that they touch, through their visible faces
+    /// and ambient occlusion, so those are outdated too
+    fn chunk_modified(
+        &mut self,
+        chunk_pos: &ChunkPosition,
+        block_pos: Option<&LocalBlockPosition>,
+    ) {
+        // offsets of the chunks touching the modified block along one axis. If the block isn't
+        // known, all of the surrounding chunks may be affected
+        let axis_offsets = |coord: Option<u32>| match coord {
+            None => -1..=1,
+            Some(0) => -1..=0,
+            Some(coord) if coord == CHUNK_SIZE_U32 - 1 => 0..=1,
+            Some(_) => 0..=0,
+        };
+        let coord = |axis: usize| block_pos.map(|block_pos| block_pos.as_uvec3()[axis]);
+
+        let touched_chunks = itertools::iproduct!(
+            axis_offsets(coord(0)),
+            axis_offsets(coord(1)),
+            axis_offsets(coord(2))
+        )
+        .map(|(x, y, z)| *chunk_pos + ChunkPosition::new(x, y, z));
+
+        for chunk_pos in touched_chunks {
+            self.mark_chunk_outdated(&chunk_pos);
+        }
+    }
+
+    /// Mark the mesh of a chunk as outdated, so that it is rebuilt the next time it is drawn
+    fn mark_chunk_outdated(&mut self, chunk_pos: &ChunkPosition) {
         let (batch_pos, chunk_pos_in_batch) =
             ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(chunk_pos);
 
@@ -387,6 +1104,70 @@ impl TerrainRenderer {
             batch.mark_outdated(&chunk_pos_in_batch);
         }
     }
+
+    /// Called when the mesh of a chunk should be rebuilt although its blocks haven't changed.
+    /// The new mesh is queued straight away at optimization priority, whether or not the chunk is
+    /// visible, and replaces the old one once it is ready. Chunks without a mesh are skipped, as
+    /// they are meshed when they are first drawn
+    fn chunk_remesh_requested(
+        &mut self,
+        chunk_pos: &ChunkPosition,
+        tasks: &mut Tasks,
+        terrain: &Terrain,
+        load_area_index: Index,
+        camera_pos: Vec3,
+    ) {
+        let (batch_pos, chunk_pos_in_batch) =
+            ChunkBatches::get_batch_pos_and_chunk_pos_in_batch(chunk_pos);
+
+        let has_mesh = self
+            .chunk_batches
+            .get_batch(&batch_pos)
+            .is_some_and(|batch| {
+                !batch
+                    .get_chunk_mesh_status(&chunk_pos_in_batch)
+                    .is_missing()
+            });
+        let Some(chunk) = terrain.get_chunk(load_area_index, chunk_pos) else {
+            return;
+        };
+
+        if has_mesh {
+            self.chunk_batches
+                .queue_chunk_for_meshing(
+                    chunk,
+                    tasks,
+                    terrain,
+                    load_area_index,
+                    camera_pos,
+                    CHUNK_MESH_OPTIMIZATION_PRIORITY,
+                );
+        }
+    }
+}
+
+/// Log the blocks whose model in the registry differs from the one suggested by the alpha
+/// channels of their textures, which are the blocks overriding it
+fn log_block_model_overrides(alpha_classes: &[AlphaClass]) {
+    for (block_index, block) in BLOCKS.iter().enumerate() {
+        let (BlockModel::FullBlock(faces)
+        | BlockModel::Cutout { faces, .. }
+        | BlockModel::Translucent(faces)) = block.model
+        else {
+            continue;
+        };
+
+        let suggested_model = BlockModel::from_texture_alpha(faces, alpha_classes);
+        if suggested_model.is_cutout() != block.model.is_cutout()
+            || suggested_model.is_translucent() != block.model.is_translucent()
+        {
+            log::info!(
+                "block {} overrides the model suggested by its textures ({:?})",
+                block_index,
+                suggested_model
+            );
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, derive_more::IsVariant)]
@@ -398,10 +1179,141 @@ enum ChunkMeshStatus {
     Outdated,
 }
 
+/// Mesh of one chunk built on the CPU, before it is combined into its batch's vertex buffer
 #[derive(Debug)]
-struct ChunkMeshData {
-    pub vertices: Vec<TerrainVertex>,
+pub struct ChunkMeshData {
+    /// Vertices in chunk-local coordinates. The batch moves them into place when it combines them.
+    /// Identical chunks may share their vertices through the `ChunkMeshCache`
+    pub vertices: Arc<[TerrainVertex]>,
+    /// Vertices of the faces in the translucent layer, drawn after the rest of the terrain, in
+    /// the same coordinates as `vertices`
+    pub translucent_vertices: Arc<[TerrainVertex]>,
+    /// Vertices of the quads in the two-sided layer, drawn without back-face culling, in the same
+    /// coordinates as `vertices`
+    pub two_sided_vertices: Arc<[TerrainVertex]>,
+    /// The mesh in the `ChunkMeshCache` that the vertices belong to, or None if the mesh isn't
+    /// cached
+    pub shared: Option<Arc<SharedChunkMesh>>,
     pub queued_instant: Instant,
+    /// Time taken to build the mesh, or None if meshing was skipped
+    pub mesh_time: Option<MeshTimeSample>,
+    /// Strategy the mesh was built with, or None for chunks known to have an empty mesh, which is
+    /// the same with every strategy
+    pub strategy: Option<MeshingStrategy>,
+    /// Resolution the mesh was built at
+    pub lod: LodLevel,
+    /// Sides of the chunk that the mesh was built with seams on, see `MeshingOptions::lod_seams`
+    pub lod_seams: u8,
+    /// Resolution of the neighbours that the mesh was built against, see
+    /// `MeshingOptions::neighbour_lods`
+    pub neighbour_lods: [LodLevel; 6],
+    /// How the vertex normals of the mesh were computed
+    pub normal_mode: NormalMode,
+}
+
+impl ChunkMeshData {
+    /// True if no layer of the mesh has any faces
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+            && self.translucent_vertices.is_empty()
+            && self.two_sided_vertices.is_empty()
+    }
+}
+
+/// Mesh a chunk on the calling thread, the same way as the meshing tasks but without touching the
+/// GPU. The vertices are in chunk-local coordinates
+#[cfg(test)]
+pub fn mesh_chunk_now(
+    blocks: &ChunkBlockStorage,
+    surrounding_sides: &[Option<ChunkSide>],
+    border: &ChunkBorder,
+    light: Option<&ChunkLightSnapshot>,
+    options: MeshingOptions,
+) -> ChunkMeshData {
+    let mesher = match options.strategy {
+        MeshingStrategy::Culled => meshing::mesh_culled,
+        MeshingStrategy::Greedy => meshing::mesh_greedy,
+    };
+
+    chunk_batching::build_chunk_mesh(
+        mesher,
+        &blocks.as_block_array(),
+        surrounding_sides,
+        border,
+        light,
+        options,
+        Instant::now(),
+    )
+}
+
+/// Render pipelines for drawing each layer of the chunk meshes, one for each of
+/// `FACE_CULL_MODES`, so that face culling can be switched without rebuilding a pipeline. The
+/// two-sided layer is never culled, so it only needs one
+#[derive(Debug)]
+struct TerrainPipelines {
+    opaque: [wgpu::RenderPipeline; FACE_CULL_MODES.len()],
+    translucent: [wgpu::RenderPipeline; FACE_CULL_MODES.len()],
+    two_sided: wgpu::RenderPipeline,
+}
+
+impl TerrainPipelines {
+    fn new(
+        cx: &RenderContext,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        wireframe: bool,
+    ) -> Self {
+        let build = |layer, face_cull_mode| {
+            TerrainRenderer::terrain_pipeline_builder(
+                cx,
+                shader,
+                face_cull_mode,
+                wireframe,
+                layer,
+            )
+            .with_layout(layout)
+            .build_with_existing_layout(&cx.device)
+        };
+        let build_layer =
+            |layer| FACE_CULL_MODES.map(|face_cull_mode| build(layer, face_cull_mode));
+
+        Self {
+            opaque: build_layer(MeshLayer::Opaque),
+            translucent: build_layer(MeshLayer::Translucent),
+            two_sided: build(MeshLayer::TwoSided, None),
+        }
+    }
+
+    /// Pipeline drawing the given layer with the given face cull mode, which must be one of
+    /// `FACE_CULL_MODES`
+    fn get(&self, layer: MeshLayer, face_cull_mode: Option<wgpu::Face>) -> &wgpu::RenderPipeline {
+        let pipeline_index = FACE_CULL_MODES
+            .iter()
+            .position(|&mode| mode == face_cull_mode)
+            .expect("the face cull mode should be one of `FACE_CULL_MODES`");
+
+        match layer {
+            MeshLayer::Opaque => &self.opaque[pipeline_index],
+            MeshLayer::Translucent => &self.translucent[pipeline_index],
+            MeshLayer::TwoSided => &self.two_sided,
+        }
+    }
+}
+
+/// Number of chunks, batches and triangles drawn in a frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TerrainDrawStats {
+    /// Chunks that survived culling. Each is drawn as part of its batch
+    pub chunks_visible: usize,
+    /// Loaded chunks outside the view frustum
+    pub chunks_culled_by_frustum: usize,
+    /// Chunks within the view frustum that the visibility search found to be hidden behind solid
+    /// chunks. Always zero unless the cull mode is `VisibilitySearch`
+    pub chunks_culled_by_occlusion: usize,
+    /// Chunk batches drawn, i.e. draw calls in the opaque pass
+    pub batches_drawn: usize,
+    /// Triangles drawn in the opaque and translucent passes
+    pub triangles_drawn: usize,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -1,29 +1,41 @@
 use std::{
-    sync::mpsc::{self, Receiver, Sender},
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
     time::Instant,
 };
 
 use generational_arena::Index;
 use glam::{IVec3, UVec3, Vec3};
 use itertools::Itertools;
+use rustc_hash::FxHashMap;
 use wgpu::util::DeviceExt;
 
 use super::{
-    meshing::{self, ChunkMeshInput},
+    lod::{self, LodLevel, DEFAULT_LOD_DISTANCE},
+    mesh_cache::{ChunkMeshCache, MeshCacheKey},
+    mesh_time_stats::{MeshTimeSample, MeshTimeStats},
+    mesh_upload_queue::MeshUploadQueue,
+    meshing::{self, ChunkMeshInput, MeshLayer, MeshingOptions, MeshingStrategy, NormalMode},
     vertex::TerrainVertex,
     ChunkMeshData, ChunkMeshStatus,
 };
 use crate::{
-    block::BLOCK_AIR,
+    block::{model::BlockModel, BlockId, BLOCKS},
     render::render_context::RenderContext,
-    tasks::{TaskId, TaskPriority, Tasks},
+    tasks::{TaskId, TaskPriority, TaskStage, Tasks},
     terrain::{
-        chunk::{side::ChunkSide, storage::ChunkBlockStorage, Chunk, CHUNK_SIZE, CHUNK_SIZE_I32},
+        chunk::{
+            border::ChunkBorder, side::ChunkSide, storage::ChunkBlockStorage, Chunk, CHUNK_SIZE,
+        },
+        lighting::ChunkLightSnapshot,
         load_area::LoadArea,
         position_types::ChunkPosition,
-        Terrain,
+        MeshProgress, Terrain,
     },
-    util::{measure_time::measure_time, size::Size3},
+    util::{face::FACE_NORMALS, measure_time::measure_time, size::Size3},
 };
 
 /// Size of one chunk batch on each axis, in chunks
@@ -59,14 +71,36 @@ pub struct ChunkBatch {
     vertex_buffer: Option<wgpu::Buffer>,
     /// Number of vertices in `vertex_buffer`
     vertex_count: usize,
+    /// Combined vertex buffer for the translucent layers of the chunks in this batch
+    translucent_vertex_buffer: Option<wgpu::Buffer>,
+    /// Range of vertices of each chunk in `translucent_vertex_buffer`, so that translucent chunks
+    /// can be drawn one at a time in order of distance
+    translucent_vertex_ranges: [Range<usize>; CHUNK_BATCH_SIZE_CUBED],
+    /// Combined vertex buffer for the two-sided layers of the chunks in this batch
+    two_sided_vertex_buffer: Option<wgpu::Buffer>,
+    /// Number of vertices in `two_sided_vertex_buffer`
+    two_sided_vertex_count: usize,
     /// Mesh data for each chunk in the batch
     chunk_mesh_data: [Option<ChunkMeshData>; CHUNK_BATCH_SIZE_CUBED],
     /// Mesh status for each chunk in the batch
     chunk_mesh_status: [ChunkMeshStatus; CHUNK_BATCH_SIZE_CUBED],
+    /// Time at which each chunk in the batch first appeared, in seconds since startup, used to
+    /// fade in new chunks
+    chunk_spawn_times: [f32; CHUNK_BATCH_SIZE_CUBED],
+    /// Bitmask of the chunks in the batch that appeared since the last call to
+    /// `ChunkBatches::update`, which sets their spawn times
+    newly_spawned_chunks: u8,
+    /// Bitmask of the chunks in the batch whose mesh was also used by another chunk when the
+    /// vertex buffers were last updated. These are drawn from the vertex buffers of their
+    /// `SharedChunkMesh` instead of being copied into the batch's own
+    shared_mesh_chunks: u8,
     /// Uniform buffer for batch-specific uniforms
     uniform_buffer: wgpu::Buffer,
     /// Bind group for the uniform buffer
     uniform_bind_group: wgpu::BindGroup,
+    /// Uniform buffer and bind group for each chunk drawn from a shared mesh, which is in
+    /// chunk-local coordinates rather than relative to the batch. Created when first needed
+    chunk_uniforms: [Option<(wgpu::Buffer, wgpu::BindGroup)>; CHUNK_BATCH_SIZE_CUBED],
 }
 
 impl ChunkBatch {
@@ -75,30 +109,10 @@ impl ChunkBatch {
         cx: &RenderContext,
         uniform_bind_group_layout: &wgpu::BindGroupLayout,
     ) -> Self {
-        let batch_translation = pos.as_vec3() * (CHUNK_BATCH_TOTAL_SIZE as f32);
-        let batch_translation = batch_translation.to_array();
-
-        let uniforms = ChunkBatchUniforms {
-            translation: batch_translation,
-            pad: 0.0,
-        };
-
-        let uniform_buffer = cx
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Chunk Batch Uniform Buffer"),
-                contents: bytemuck::cast_slice(&[uniforms]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-
-        let uniform_bind_group = cx.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Chunk Batch Uniforms Bind Group"),
-            layout: uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
+        let chunk_spawn_times = [0.0; CHUNK_BATCH_SIZE_CUBED];
+        let uniforms = ChunkBatchUniforms::new(pos, &chunk_spawn_times);
+        let (uniform_buffer, uniform_bind_group) =
+            Self::create_uniforms(&cx.device, uniform_bind_group_layout, uniforms);
 
         let chunk_mesh_data = array_init::array_init(|_| None);
         let chunk_mesh_status = array_init::array_init(|_| ChunkMeshStatus::Missing);
@@ -108,32 +122,90 @@ impl ChunkBatch {
             position: pos,
             vertex_buffer: None,
             vertex_count: 0,
+            translucent_vertex_buffer: None,
+            translucent_vertex_ranges: array_init::array_init(|_| 0..0),
+            two_sided_vertex_buffer: None,
+            two_sided_vertex_count: 0,
             chunk_mesh_data,
             chunk_mesh_status,
+            chunk_spawn_times,
+            newly_spawned_chunks: 0,
+            shared_mesh_chunks: 0,
             uniform_buffer,
             uniform_bind_group,
+            chunk_uniforms: array_init::array_init(|_| None),
         }
     }
 
+    /// Create a uniform buffer holding `uniforms` and a bind group for it
+    fn create_uniforms(
+        device: &wgpu::Device,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        uniforms: ChunkBatchUniforms,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Batch Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniforms]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Chunk Batch Uniforms Bind Group"),
+            layout: uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        (uniform_buffer, uniform_bind_group)
+    }
+
     /// Reset this chunk batch so that it can be reused
     pub fn reset(&mut self, cx: &RenderContext, pos: IVec3) {
         self.vertex_buffer_needs_updating = false;
         self.position = pos;
         self.vertex_count = 0;
+        self.translucent_vertex_ranges = array_init::array_init(|_| 0..0);
+        self.two_sided_vertex_count = 0;
         self.chunk_mesh_data = array_init::array_init(|_| None);
         self.chunk_mesh_status = array_init::array_init(|_| ChunkMeshStatus::Missing);
+        self.chunk_spawn_times = [0.0; CHUNK_BATCH_SIZE_CUBED];
+        self.newly_spawned_chunks = 0;
+        self.shared_mesh_chunks = 0;
 
-        // update the uniform buffer
-        let batch_translation = pos.as_vec3() * (CHUNK_BATCH_TOTAL_SIZE as f32);
-        let batch_translation = batch_translation.to_array();
+        self.write_uniforms(&cx.queue);
+    }
 
-        let uniforms = ChunkBatchUniforms {
-            translation: batch_translation,
-            pad: 0.0,
-        };
+    /// Write the translation and chunk spawn times of the batch to its uniform buffer, and those
+    /// of each chunk to the chunk's uniform buffer, if it has one
+    fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let uniforms = ChunkBatchUniforms::new(self.position, &self.chunk_spawn_times);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        for (index, chunk_uniforms) in self.chunk_uniforms.iter().enumerate() {
+            if let Some((uniform_buffer, _)) = chunk_uniforms {
+                let uniforms = ChunkBatchUniforms::for_chunk(
+                    self.position,
+                    Self::get_chunk_for_index(index),
+                    self.chunk_spawn_times[index],
+                );
+                queue.write_buffer(uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+            }
+        }
+    }
 
-        cx.queue
-            .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    /// Set the spawn time of the chunks that appeared since the last call to `now`, the time since
+    /// startup in seconds
+    fn set_spawn_times(&mut self, queue: &wgpu::Queue, now: f32) {
+        for (index, spawn_time) in self.chunk_spawn_times.iter_mut().enumerate() {
+            if self.newly_spawned_chunks & (1 << index) != 0 {
+                *spawn_time = now;
+            }
+        }
+        self.newly_spawned_chunks = 0;
+
+        self.write_uniforms(queue);
     }
 
     /// Update the stored vertices for the given chunk.
@@ -156,6 +228,14 @@ impl ChunkBatch {
             }
         }
 
+        // the chunk appears when it first gets a visible mesh, but not when it is remeshed
+        let was_visible = self.chunk_mesh_data[index]
+            .as_ref()
+            .is_some_and(|existing_mesh_data| !existing_mesh_data.is_empty());
+        if !was_visible && !mesh_data.is_empty() {
+            self.newly_spawned_chunks |= 1 << index;
+        }
+
         self.chunk_mesh_data[index] = Some(mesh_data);
         self.chunk_mesh_status[index] = ChunkMeshStatus::Good;
         self.vertex_buffer_needs_updating = true;
@@ -192,44 +272,120 @@ impl ChunkBatch {
         }
     }
 
-    /// Update the vertex_buffer for this batch
-    pub fn update_vertex_buffer(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    /// Update the vertex buffers for this batch
+    pub fn update_vertex_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) {
         self.vertex_buffer_needs_updating = false;
 
-        // calculate the total number of vertices for the combined mesh
-        self.vertex_count = self
-            .chunk_mesh_data
-            .iter()
-            .filter_map(|mesh_data_opt| mesh_data_opt.as_ref())
-            .map(|mesh_data| mesh_data.vertices.len())
+        // chunks whose mesh is used by another chunk are drawn from the shared mesh, so that its
+        // vertices are only on the GPU once
+        self.shared_mesh_chunks = 0;
+        for (index, mesh_data) in self.chunk_mesh_data.iter().enumerate() {
+            let Some(shared) = mesh_data
+                .as_ref()
+                .and_then(|mesh_data| mesh_data.shared.as_ref())
+                .filter(|shared| Arc::strong_count(shared) > 1)
+            else {
+                continue;
+            };
+
+            shared.upload(device);
+            self.shared_mesh_chunks |= 1 << index;
+            self.chunk_uniforms[index].get_or_insert_with(|| {
+                Self::create_uniforms(device, uniform_bind_group_layout, Default::default())
+            });
+        }
+        self.write_uniforms(queue);
+
+        let (vertices, _) = self.combine_chunk_vertices(|mesh_data| &mesh_data.vertices);
+        self.vertex_count = vertices.len();
+        Self::write_vertex_buffer(device, queue, &mut self.vertex_buffer, &vertices);
+
+        let (translucent_vertices, translucent_vertex_ranges) =
+            self.combine_chunk_vertices(|mesh_data| &mesh_data.translucent_vertices);
+        self.translucent_vertex_ranges = translucent_vertex_ranges;
+        Self::write_vertex_buffer(
+            device,
+            queue,
+            &mut self.translucent_vertex_buffer,
+            &translucent_vertices,
+        );
+
+        let (two_sided_vertices, _) =
+            self.combine_chunk_vertices(|mesh_data| &mesh_data.two_sided_vertices);
+        self.two_sided_vertex_count = two_sided_vertices.len();
+        Self::write_vertex_buffer(
+            device,
+            queue,
+            &mut self.two_sided_vertex_buffer,
+            &two_sided_vertices,
+        );
+    }
+
+    /// Concatenate one layer of each chunk's mesh, moving the vertices from chunk-local
+    /// coordinates to coordinates relative to the batch. The offsets are small integers, so this
+    /// is exact. Also returns the range of vertices of each chunk
+    fn combine_chunk_vertices(
+        &self,
+        layer: impl Fn(&ChunkMeshData) -> &Arc<[TerrainVertex]>,
+    ) -> (Vec<TerrainVertex>, [Range<usize>; CHUNK_BATCH_SIZE_CUBED]) {
+        // chunks drawn from their shared mesh are left out
+        let combined_mesh_data = |index: usize| {
+            self.chunk_mesh_data[index]
+                .as_ref()
+                .filter(|_| self.shared_mesh_chunks & (1 << index) == 0)
+        };
+
+        let vertex_count = (0..CHUNK_BATCH_SIZE_CUBED)
+            .filter_map(combined_mesh_data)
+            .map(|mesh_data| layer(mesh_data).len())
             .sum();
 
-        if self.vertex_count == 0 {
-            self.vertex_buffer = None;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        let ranges = array_init::array_init(|index| {
+            let start = vertices.len();
+
+            if let Some(mesh_data) = combined_mesh_data(index) {
+                let offset = (Self::get_chunk_for_index(index) * CHUNK_SIZE as u32).as_vec3();
+                vertices.extend(layer(mesh_data).iter().map(|vertex| TerrainVertex {
+                    position: (Vec3::from(vertex.position) + offset).to_array(),
+                    ..*vertex
+                }));
+            }
+
+            start..vertices.len()
+        });
+
+        (vertices, ranges)
+    }
+
+    /// Write `vertices` to `vertex_buffer`, reusing the existing buffer if it is large enough
+    fn write_vertex_buffer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_buffer: &mut Option<wgpu::Buffer>,
+        vertices: &[TerrainVertex],
+    ) {
+        if vertices.is_empty() {
+            *vertex_buffer = None;
             return;
         }
 
-        // concatenate each chunk's vertices
-        let mut vertices = Vec::with_capacity(self.vertex_count);
-        self.chunk_mesh_data
-            .iter()
-            .filter_map(|mesh_data_opt| mesh_data_opt.as_ref())
-            .for_each(|mesh_data| vertices.extend_from_slice(&mesh_data.vertices));
-
         // see if we can reuse the existing vertex buffer
-        if let Some(old_vertex_buffer) = self.vertex_buffer.as_ref().filter(|old_vertex_buffer| {
-            self.vertex_count * std::mem::size_of::<TerrainVertex>()
-                <= old_vertex_buffer.size() as usize
+        if let Some(old_vertex_buffer) = vertex_buffer.as_ref().filter(|old_vertex_buffer| {
+            std::mem::size_of_val(vertices) <= old_vertex_buffer.size() as usize
         }) {
-            queue.write_buffer(&old_vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            queue.write_buffer(old_vertex_buffer, 0, bytemuck::cast_slice(vertices));
         } else {
-            self.vertex_buffer = Some(device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: bytemuck::cast_slice(&vertices),
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                },
-            ));
+            *vertex_buffer = Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }));
         }
     }
 
@@ -239,24 +395,125 @@ impl ChunkBatch {
         self.chunk_mesh_status[index]
     }
 
-    /// Returns the vertex buffer for this batch, if it has one
-    pub fn vertex_buffer(&self) -> Option<&wgpu::Buffer> {
-        self.vertex_buffer.as_ref()
+    /// Returns the current mesh of the given chunk, or None if the chunk has no mesh
+    pub fn get_chunk_mesh_data(&self, chunk_pos_in_batch: &UVec3) -> Option<&ChunkMeshData> {
+        let index = Self::get_index_for_chunk(chunk_pos_in_batch);
+        self.chunk_mesh_data[index].as_ref()
+    }
+
+    /// Position of this batch in the grid of chunk batches
+    pub fn position(&self) -> IVec3 {
+        self.position
     }
 
-    /// Returns the bind group for this batch's uniforms
-    pub fn uniform_bind_group(&self) -> &wgpu::BindGroup {
-        &self.uniform_bind_group
+    /// Returns the draws of the opaque layer of the batch: one for the chunks in the batch's own
+    /// vertex buffer, and one for each chunk drawn from its shared mesh
+    pub fn opaque_draws(&self) -> impl Iterator<Item = MeshDraw<'_>> {
+        self.combined_layer_draws(MeshLayer::Opaque, &self.vertex_buffer, self.vertex_count)
     }
 
-    /// Returns the number of vertices in this batch
-    pub fn vertex_count(&self) -> usize {
-        self.vertex_count
+    /// Returns the draws of the two-sided layer of the batch, in the same way as `opaque_draws`
+    pub fn two_sided_draws(&self) -> impl Iterator<Item = MeshDraw<'_>> {
+        self.combined_layer_draws(
+            MeshLayer::TwoSided,
+            &self.two_sided_vertex_buffer,
+            self.two_sided_vertex_count,
+        )
     }
 
-    /// Returns the number of indices required to draw this batch
-    pub fn index_count(&self) -> usize {
-        self.vertex_count * 3 / 2
+    /// Returns the draw of `vertex_count` vertices from a combined vertex buffer of the batch,
+    /// followed by the draws of the same layer of the chunks drawn from their shared mesh
+    fn combined_layer_draws<'a>(
+        &'a self,
+        layer: MeshLayer,
+        vertex_buffer: &'a Option<wgpu::Buffer>,
+        vertex_count: usize,
+    ) -> impl Iterator<Item = MeshDraw<'a>> {
+        let combined = vertex_buffer
+            .as_ref()
+            .filter(|_| vertex_count > 0)
+            .map(|vertex_buffer| MeshDraw {
+                vertex_buffer,
+                vertex_range: 0..vertex_count,
+                uniform_bind_group: &self.uniform_bind_group,
+            });
+
+        combined.into_iter().chain(
+            (0..CHUNK_BATCH_SIZE_CUBED).filter_map(move |index| self.shared_mesh_draw(index, layer)),
+        )
+    }
+
+    /// Returns the draw of the translucent layer of the given chunk, or None if it has no
+    /// translucent faces
+    pub fn translucent_draw(&self, chunk_pos_in_batch: &UVec3) -> Option<MeshDraw<'_>> {
+        let index = Self::get_index_for_chunk(chunk_pos_in_batch);
+        if self.shared_mesh_chunks & (1 << index) != 0 {
+            return self.shared_mesh_draw(index, MeshLayer::Translucent);
+        }
+
+        let vertex_range = self.translucent_vertex_ranges[index].clone();
+        self.translucent_vertex_buffer
+            .as_ref()
+            .filter(|_| !vertex_range.is_empty())
+            .map(|vertex_buffer| MeshDraw {
+                vertex_buffer,
+                vertex_range,
+                uniform_bind_group: &self.uniform_bind_group,
+            })
+    }
+
+    /// Returns the draw of one layer of the chunk with the given index from its shared mesh, or
+    /// None if the chunk isn't drawn from a shared mesh or the layer is empty
+    fn shared_mesh_draw(&self, index: usize, layer: MeshLayer) -> Option<MeshDraw<'_>> {
+        if self.shared_mesh_chunks & (1 << index) == 0 {
+            return None;
+        }
+
+        let shared = self.chunk_mesh_data[index].as_ref()?.shared.as_ref()?;
+        let (_, uniform_bind_group) = self.chunk_uniforms[index].as_ref()?;
+
+        Some(MeshDraw {
+            vertex_buffer: shared.vertex_buffer(layer)?,
+            vertex_range: 0..shared.vertices(layer).len(),
+            uniform_bind_group,
+        })
+    }
+
+    /// Returns the largest number of vertices drawn from one vertex buffer by the batch, which
+    /// the shared index buffer must cover
+    fn highest_vertex_count(&self) -> usize {
+        let shared_vertex_counts = (0..CHUNK_BATCH_SIZE_CUBED).flat_map(|index| {
+            [MeshLayer::Opaque, MeshLayer::Translucent, MeshLayer::TwoSided]
+                .map(|layer| self.shared_mesh_draw(index, layer))
+                .into_iter()
+                .flatten()
+                .map(|draw| draw.vertex_range.end)
+        });
+
+        shared_vertex_counts
+            .chain([
+                self.vertex_count,
+                self.translucent_vertex_ranges[CHUNK_BATCH_SIZE_CUBED - 1].end,
+                self.two_sided_vertex_count,
+            ])
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Iterator over the positions in the batch of the chunks whose meshes are built or being
+    /// built
+    fn chunks_with_meshes(&self) -> impl Iterator<Item = UVec3> + '_ {
+        let size = CHUNK_BATCH_SIZE as u32;
+
+        itertools::iproduct!(0..size, 0..size, 0..size)
+            .map(|(z, y, x)| UVec3::new(x, y, z))
+            .filter(|chunk_pos_in_batch| {
+                let index = Self::get_index_for_chunk(chunk_pos_in_batch);
+                matches!(
+                    self.chunk_mesh_status[index],
+                    ChunkMeshStatus::Good | ChunkMeshStatus::Generating(_)
+                )
+            })
     }
 
     /// Returns the index in `self.vertices_for_chunk` for the chunk with the given position in the
@@ -266,13 +523,70 @@ impl ChunkBatch {
             + CHUNK_BATCH_SIZE * pos.y as usize
             + pos.x as usize
     }
+
+    /// Inverse of `get_index_for_chunk`
+    fn get_chunk_for_index(index: usize) -> UVec3 {
+        UVec3::new(
+            (index % CHUNK_BATCH_SIZE) as u32,
+            (index / CHUNK_BATCH_SIZE % CHUNK_BATCH_SIZE) as u32,
+            (index / CHUNK_BATCH_SIZE_SQUARED) as u32,
+        )
+    }
 }
 
+// the spawn times are packed into vec4s, and `ChunkBatch::newly_spawned_chunks` is a u8 bitmask
+const _: () = assert!(CHUNK_BATCH_SIZE_CUBED.is_multiple_of(4) && CHUNK_BATCH_SIZE_CUBED <= 8);
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 struct ChunkBatchUniforms {
-    translation: [f32; 3],
-    pad: f32,
+    /// Translation of the batch in the world. This is an integer so that the camera-relative
+    /// translation computed in the shader is exact
+    translation: [i32; 3],
+    pad: i32,
+    /// Time at which each chunk in the batch first appeared, indexed by
+    /// `ChunkBatch::get_index_for_chunk` and packed into vec4s for the uniform layout
+    chunk_spawn_times: [[f32; 4]; CHUNK_BATCH_SIZE_CUBED / 4],
+}
+
+impl ChunkBatchUniforms {
+    fn new(batch_pos: IVec3, chunk_spawn_times: &[f32; CHUNK_BATCH_SIZE_CUBED]) -> Self {
+        Self {
+            translation: (batch_pos * CHUNK_BATCH_TOTAL_SIZE as i32).to_array(),
+            pad: 0,
+            chunk_spawn_times: bytemuck::cast(*chunk_spawn_times),
+        }
+    }
+
+    /// Uniforms for drawing one chunk of a batch from its shared mesh. The mesh is in
+    /// chunk-local coordinates, so the shader finds every vertex in the first chunk of the batch
+    fn for_chunk(batch_pos: IVec3, chunk_pos_in_batch: UVec3, spawn_time: f32) -> Self {
+        let mut chunk_spawn_times = [0.0; CHUNK_BATCH_SIZE_CUBED];
+        chunk_spawn_times[0] = spawn_time;
+
+        let mut uniforms = Self::new(batch_pos, &chunk_spawn_times);
+        uniforms.translation = (IVec3::from(uniforms.translation)
+            + chunk_pos_in_batch.as_ivec3() * CHUNK_SIZE as i32)
+            .to_array();
+        uniforms
+    }
+}
+
+/// Vertices of one layer of a chunk batch or chunk, drawn with the shared index buffer, and the
+/// uniforms to draw them with
+#[derive(Debug)]
+pub struct MeshDraw<'a> {
+    pub vertex_buffer: &'a wgpu::Buffer,
+    pub vertex_range: Range<usize>,
+    pub uniform_bind_group: &'a wgpu::BindGroup,
+}
+
+impl MeshDraw<'_> {
+    /// Range of the shared index buffer that draws the quads of `vertex_range`. The index buffer
+    /// repeats the same pattern for every quad, so the range follows from the vertex range
+    pub fn index_range(&self) -> Range<u32> {
+        (self.vertex_range.start * 3 / 2) as u32..(self.vertex_range.end * 3 / 2) as u32
+    }
 }
 
 /// Responsible for managing chunk batches, including issuing mesh generation tasks
@@ -286,10 +600,28 @@ pub struct ChunkBatches {
     finished_mesh_tx: Sender<(ChunkPosition, ChunkMeshData)>,
     /// Receiver for finished chunk meshes
     finished_mesh_rx: Receiver<(ChunkPosition, ChunkMeshData)>,
+    /// Finished chunk meshes waiting to be uploaded, a limited number per frame
+    pending_uploads: MeshUploadQueue<(ChunkPosition, ChunkMeshData)>,
     /// Bind group layout for uniforms specific to each chunk batch
     uniform_bind_group_layout: wgpu::BindGroupLayout,
     /// Shared index buffer for rendering chunk batches
     shared_index_buffer: SharedIndexBuffer,
+    /// Time taken to build recent chunk meshes
+    mesh_time_stats: MeshTimeStats,
+    /// Meshes shared between identical chunks, shared with the meshing threads
+    mesh_cache: Arc<Mutex<ChunkMeshCache>>,
+    /// Strategy used to mesh chunks without an override
+    default_meshing_strategy: MeshingStrategy,
+    /// Strategies chosen for individual chunks, overriding the default
+    meshing_strategy_overrides: FxHashMap<ChunkPosition, MeshingStrategy>,
+    /// Distance from `lod_center`, in chunks, beyond which chunks are meshed at a lower
+    /// resolution, or None to mesh every chunk at full resolution
+    lod_distance: Option<f32>,
+    /// Center of the load area in chunks as of the last `update`, which the distance of chunks
+    /// for choosing their resolution is measured from
+    lod_center: Vec3,
+    /// How the vertex normals of chunk meshes are computed
+    normal_mode: NormalMode,
 }
 
 impl ChunkBatches {
@@ -321,8 +653,16 @@ impl ChunkBatches {
             batch_grid_size,
             finished_mesh_tx,
             finished_mesh_rx,
+            pending_uploads: MeshUploadQueue::default(),
             uniform_bind_group_layout,
             shared_index_buffer,
+            mesh_time_stats: MeshTimeStats::default(),
+            mesh_cache: Arc::default(),
+            default_meshing_strategy: MeshingStrategy::default(),
+            meshing_strategy_overrides: FxHashMap::default(),
+            lod_distance: Some(DEFAULT_LOD_DISTANCE),
+            lod_center: load_area.center(),
+            normal_mode: NormalMode::default(),
         }
     }
 
@@ -345,24 +685,54 @@ impl ChunkBatches {
         self.batch_grid_size.flatten(grid_pos)
     }
 
-    /// Called each frame before rendering terrain to update the chunk batches
-    pub fn update(&mut self, cx: &RenderContext, terrain: &Terrain, load_area_index: Index) {
-        // check for newly finished meshes
-        while let Ok(received) = self.finished_mesh_rx.try_recv() {
-            let load_area = terrain
-                .load_areas()
-                .get(load_area_index)
-                .expect("load area should exist");
-
-            self.finished_mesh_received(load_area, received.0, received.1);
+    /// Called each frame before rendering terrain to update the chunk batches. `now` is the time
+    /// since startup in seconds, recorded as the spawn time of chunks that appeared this frame
+    pub fn update(
+        &mut self,
+        cx: &RenderContext,
+        terrain: &Terrain,
+        load_area_index: Index,
+        now: f32,
+    ) {
+        // chunks crossing a resolution threshold as the load area follows the camera become
+        // suboptimal, so that they are remeshed when they are next drawn
+        self.lod_center = terrain
+            .load_areas()
+            .get(load_area_index)
+            .expect("load area should exist")
+            .center();
+
+        // queue newly finished meshes, then upload as many as the budget allows. Chunks stay
+        // marked as generating while their meshes are queued, so they aren't requested again
+        self.pending_uploads
+            .extend(self.finished_mesh_rx.try_iter());
+
+        let load_area = terrain
+            .load_areas()
+            .get(load_area_index)
+            .expect("load area should exist");
+
+        let uploads = self.pending_uploads.take_frame().collect_vec();
+        for (chunk_pos, mesh_data) in uploads {
+            self.finished_mesh_received(terrain, load_area, chunk_pos, mesh_data);
         }
 
+        // forget the meshes that were replaced or unloaded
+        self.mesh_cache.lock().unwrap().evict_unused();
+
         // update the vertex buffers of any batches requiring it
         let mut highest_vertex_count = self.shared_index_buffer.vertex_count;
         for batch in &mut self.batches {
+            if batch.newly_spawned_chunks != 0 {
+                batch.set_spawn_times(&cx.queue, now);
+            }
             if batch.vertex_buffer_needs_updating {
-                batch.update_vertex_buffer(&cx.device, &cx.queue);
-                highest_vertex_count = highest_vertex_count.max(batch.vertex_count());
+                batch.update_vertex_buffer(
+                    &cx.device,
+                    &cx.queue,
+                    &self.uniform_bind_group_layout,
+                );
+                highest_vertex_count = highest_vertex_count.max(batch.highest_vertex_count());
             }
         }
 
@@ -372,6 +742,153 @@ impl ChunkBatches {
         }
     }
 
+    /// Returns the mesh status of the given chunk, or `Missing` if no batch is assigned to it.
+    /// Meshes built with a different strategy, resolution or seams from the ones now chosen for
+    /// the chunk are suboptimal, so that they are rebuilt the next time the chunk is drawn
+    pub fn get_chunk_mesh_status(&self, chunk_pos: &ChunkPosition) -> ChunkMeshStatus {
+        let (batch_pos, chunk_pos_in_batch) = Self::get_batch_pos_and_chunk_pos_in_batch(chunk_pos);
+
+        let Some(batch) = self.get_batch(&batch_pos) else {
+            return ChunkMeshStatus::Missing;
+        };
+
+        match batch.get_chunk_mesh_status(&chunk_pos_in_batch) {
+            ChunkMeshStatus::Good
+                if batch
+                    .get_chunk_mesh_data(&chunk_pos_in_batch)
+                    .is_some_and(|mesh_data| !self.mesh_is_up_to_date(chunk_pos, mesh_data)) =>
+            {
+                ChunkMeshStatus::Suboptimal
+            }
+            status => status,
+        }
+    }
+
+    /// True if the mesh of the given chunk was built with the options now chosen for it. Empty
+    /// meshes are the same with every strategy and resolution, but not with every set of seams
+    fn mesh_is_up_to_date(&self, chunk_pos: &ChunkPosition, mesh_data: &ChunkMeshData) -> bool {
+        let strategy_matches = mesh_data.strategy.is_none_or(|strategy| {
+            strategy == self.meshing_strategy(chunk_pos)
+                && mesh_data.lod == self.lod_level(chunk_pos)
+                && mesh_data.normal_mode == self.normal_mode
+        });
+
+        strategy_matches
+            && mesh_data.lod_seams == self.lod_seams(chunk_pos)
+            && mesh_data.neighbour_lods == self.neighbour_lods(chunk_pos)
+    }
+
+    /// Returns the strategy used to mesh the given chunk
+    pub fn meshing_strategy(&self, chunk_pos: &ChunkPosition) -> MeshingStrategy {
+        self.meshing_strategy_overrides
+            .get(chunk_pos)
+            .copied()
+            .unwrap_or(self.default_meshing_strategy)
+    }
+
+    /// Returns the resolution that the given chunk is meshed at, chosen by its distance from the
+    /// center of the load area
+    pub fn lod_level(&self, chunk_pos: &ChunkPosition) -> LodLevel {
+        let distance = (chunk_pos.as_vec3() + 0.5).distance(self.lod_center);
+
+        LodLevel::for_distance(distance, self.lod_distance)
+    }
+
+    /// Returns the sides of the given chunk that lie on a seam between levels of detail, one bit
+    /// per `FaceIndex`. Chunks cull their faces against the full resolution blocks of their
+    /// neighbours, which no longer match a neighbour's mesh once either chunk is downsampled, so
+    /// the faces along these sides are always kept to stop holes opening up in the terrain
+    pub fn lod_seams(&self, chunk_pos: &ChunkPosition) -> u8 {
+        let downsampled = |chunk_pos| self.lod_level(&chunk_pos) != LodLevel::Full;
+        let chunk_is_downsampled = downsampled(*chunk_pos);
+
+        FACE_NORMALS
+            .iter()
+            .enumerate()
+            .filter(|&(_, &normal)| chunk_is_downsampled || downsampled(*chunk_pos + normal))
+            .fold(0, |seams, (face_index, _)| seams | 1 << face_index)
+    }
+
+    /// Returns the resolution of the neighbouring chunk on each side of the given chunk, indexed
+    /// by `FaceIndex`, which the vertices along the sides are clamped to when it is coarser
+    pub fn neighbour_lods(&self, chunk_pos: &ChunkPosition) -> [LodLevel; 6] {
+        FACE_NORMALS.map(|normal| self.lod_level(&(*chunk_pos + normal)))
+    }
+
+    /// Distance from the center of the load area, in chunks, beyond which chunks are meshed at a
+    /// lower resolution, or None if every chunk is meshed at full resolution
+    pub fn lod_distance(&self) -> Option<f32> {
+        self.lod_distance
+    }
+
+    /// Set the distance from the center of the load area, in chunks, beyond which chunks are
+    /// meshed at half resolution, and beyond twice which they are meshed at quarter resolution.
+    /// None meshes every chunk at full resolution. Meshes are rebuilt as they are drawn
+    pub fn set_lod_distance(&mut self, lod_distance: Option<f32>) {
+        self.lod_distance = lod_distance;
+    }
+
+    /// Maximum number of finished chunk meshes uploaded per frame
+    pub fn mesh_upload_budget(&self) -> usize {
+        self.pending_uploads.budget()
+    }
+
+    /// Set the maximum number of finished chunk meshes uploaded per frame. Meshes beyond the
+    /// budget wait in a queue for later frames. At least one mesh is uploaded per frame
+    pub fn set_mesh_upload_budget(&mut self, budget: usize) {
+        self.pending_uploads.set_budget(budget);
+    }
+
+    /// Number of finished chunk meshes waiting to be uploaded
+    pub fn pending_mesh_upload_count(&self) -> usize {
+        self.pending_uploads.len()
+    }
+
+    /// Strategy used to mesh chunks without an override
+    pub fn default_meshing_strategy(&self) -> MeshingStrategy {
+        self.default_meshing_strategy
+    }
+
+    /// Set the strategy used to mesh chunks without an override. Their meshes are rebuilt as they
+    /// are drawn, unless they were already built with the new strategy
+    pub fn set_default_meshing_strategy(&mut self, strategy: MeshingStrategy) {
+        self.default_meshing_strategy = strategy;
+    }
+
+    /// How the vertex normals of chunk meshes are computed
+    pub fn normal_mode(&self) -> NormalMode {
+        self.normal_mode
+    }
+
+    /// Set how the vertex normals of chunk meshes are computed. Meshes are rebuilt as they are
+    /// drawn
+    pub fn set_normal_mode(&mut self, normal_mode: NormalMode) {
+        self.normal_mode = normal_mode;
+    }
+
+    /// Choose the strategy used to mesh one chunk, or go back to the default with None. The mesh
+    /// is rebuilt when the chunk is next drawn, unless it was already built with that strategy.
+    /// The choice is forgotten when the chunk is unloaded
+    pub fn set_meshing_strategy(
+        &mut self,
+        chunk_pos: ChunkPosition,
+        strategy: Option<MeshingStrategy>,
+    ) {
+        match strategy {
+            Some(strategy) => self
+                .meshing_strategy_overrides
+                .insert(chunk_pos, strategy),
+            None => self
+                .meshing_strategy_overrides
+                .remove(&chunk_pos),
+        };
+    }
+
+    /// Every chunk batch, whatever position it is assigned to
+    pub fn batches(&self) -> impl Iterator<Item = &ChunkBatch> {
+        self.batches.iter()
+    }
+
     /// Returns a shared reference to the batch at the given position, or None if there is no batch
     /// assigned to this position
     pub fn get_batch(&self, batch_pos: &IVec3) -> Option<&ChunkBatch> {
@@ -407,6 +924,7 @@ impl ChunkBatches {
         &mut self,
         cx: &RenderContext,
         tasks: &mut Tasks,
+        terrain: &Terrain,
         batch_pos: &IVec3,
     ) -> &mut ChunkBatch {
         let index = self.get_batch_index(batch_pos);
@@ -423,6 +941,14 @@ impl ChunkBatches {
                 }
             }
 
+            // the meshes of the chunks previously in this batch are lost
+            for chunk_pos_in_batch in batch.chunks_with_meshes() {
+                let chunk_pos = ChunkPosition::from(
+                    batch.position * CHUNK_BATCH_SIZE as i32 + chunk_pos_in_batch.as_ivec3(),
+                );
+                terrain.report_mesh_progress(chunk_pos, MeshProgress::Discarded);
+            }
+
             batch.reset(cx, *batch_pos);
         }
 
@@ -442,21 +968,15 @@ impl ChunkBatches {
         priority: i32,
     ) {
         let queued_instant = Instant::now();
-
-        // skip meshing air chunks
-        if let ChunkBlockStorage::Uniform(block_id) = chunk.get_block_storage() {
-            if *block_id == BLOCK_AIR {
-                let _ = self.finished_mesh_tx.send((
-                    chunk.position(),
-                    ChunkMeshData {
-                        vertices: Vec::new(),
-                        queued_instant,
-                    },
-                ));
-            }
-        }
-
         let finished_mesh_tx = self.finished_mesh_tx.clone();
+        let mesh_cache = Arc::clone(&self.mesh_cache);
+        let options = MeshingOptions {
+            strategy: self.meshing_strategy(&chunk.position()),
+            lod: self.lod_level(&chunk.position()),
+            lod_seams: self.lod_seams(&chunk.position()),
+            neighbour_lods: self.neighbour_lods(&chunk.position()),
+            normal_mode: self.normal_mode,
+        };
 
         let (batch_pos, chunk_pos_in_batch) =
             Self::get_batch_pos_and_chunk_pos_in_batch(&chunk.position());
@@ -477,34 +997,72 @@ impl ChunkBatches {
         let surrounding_sides =
             ChunkSide::get_surrounding_sides(chunk_pos, terrain, load_area_index);
 
+        // chunks whose mesh would be empty get an empty mesh straight away, without a task.
+        // The batch skips empty meshes when building its vertex buffer
+        if mesh_is_empty(&blocks, &surrounding_sides, options.lod_seams) {
+            let empty_mesh_data = ChunkMeshData {
+                vertices: Arc::new([]),
+                translucent_vertices: Arc::new([]),
+                two_sided_vertices: Arc::new([]),
+                shared: None,
+                queued_instant,
+                mesh_time: None,
+                strategy: None,
+                lod: LodLevel::Full,
+                lod_seams: options.lod_seams,
+                neighbour_lods: options.neighbour_lods,
+                normal_mode: options.normal_mode,
+            };
+            if batch.set_mesh_data_for_chunk(chunk_pos_in_batch, empty_mesh_data) {
+                terrain.report_mesh_progress(chunk_pos, MeshProgress::Finished);
+            }
+            return;
+        }
+
+        let border = ChunkBorder::get_border(chunk_pos, terrain, load_area_index);
+        let light = ChunkLightSnapshot::take(chunk_pos, terrain, load_area_index);
+
         // assign a higher priority to chunks closer to the camera
         let priority_within_class = (chunk_pos.as_vec3() - camera_pos).length_squared() as i32;
 
         let task_id = tasks.submit(
+            TaskStage::Meshing,
             TaskPriority {
                 class_priority: priority,
                 priority_within_class,
+                tie_breaker: chunk_pos.as_ivec3().to_array(),
             },
             move || {
-                // move `blocks` and `surrounding sides` to the new thread
-                let (blocks, surrounding_sides) = (blocks, surrounding_sides);
                 let blocks = blocks.as_block_array();
-
-                let translation = chunk_pos
-                    .as_ivec3()
-                    .rem_euclid(IVec3::splat(CHUNK_BATCH_SIZE as i32))
-                    * CHUNK_SIZE_I32;
-
-                let vertices = meshing::mesh_greedy(ChunkMeshInput {
-                    blocks: &blocks,
-                    translation: translation.as_vec3(), // eventually this will be an IVec3
-                    surrounding_sides: &surrounding_sides,
-                });
-
-                if let Err(e) = finished_mesh_tx.send((chunk_pos, ChunkMeshData {
-                    vertices,
-                    queued_instant,
-                })) {
+                let mesher = match options.strategy {
+                    MeshingStrategy::Culled => meshing::mesh_culled,
+                    MeshingStrategy::Greedy => meshing::mesh_greedy_parallel,
+                };
+
+                let build = || {
+                    build_chunk_mesh(
+                        mesher,
+                        &blocks,
+                        &surrounding_sides,
+                        &border,
+                        light.as_ref(),
+                        options,
+                        queued_instant,
+                    )
+                };
+
+                // identical chunks share one mesh, so look for it in the cache before meshing
+                let key = MeshCacheKey::new(
+                    &blocks,
+                    &surrounding_sides,
+                    &border,
+                    light.as_ref(),
+                    options,
+                );
+                let mesh_data =
+                    ChunkMeshCache::get_or_build(&mesh_cache, key, queued_instant, build);
+
+                if let Err(e) = finished_mesh_tx.send((chunk_pos, mesh_data)) {
                     log::trace!(
                         "sending chunk vertices from meshing thread to main thread returned error: {}",
                         e
@@ -513,6 +1071,7 @@ impl ChunkBatches {
             });
 
         batch.mark_generating(&chunk_pos_in_batch, task_id);
+        terrain.report_mesh_progress(chunk_pos, MeshProgress::Queued);
     }
 
     /// Size of the grid of chunk batches
@@ -520,6 +1079,11 @@ impl ChunkBatches {
         self.batch_grid_size
     }
 
+    /// Time taken to build recent chunk meshes
+    pub fn mesh_time_stats(&self) -> &MeshTimeStats {
+        &self.mesh_time_stats
+    }
+
     /// Index buffer used to draw all chunk batches
     pub fn shared_index_buffer(&self) -> &wgpu::Buffer {
         &self.shared_index_buffer.index_buffer
@@ -528,10 +1092,15 @@ impl ChunkBatches {
     /// Called whenever a finished chunk mesh arrives
     fn finished_mesh_received(
         &mut self,
+        terrain: &Terrain,
         loaded_area: &LoadArea,
         chunk_pos: ChunkPosition,
         mesh_data: ChunkMeshData,
     ) {
+        if let Some(mesh_time) = mesh_data.mesh_time {
+            self.mesh_time_stats.record(mesh_time);
+        }
+
         // make sure that the chunk is still loaded
         if !loaded_area.is_loaded(&chunk_pos) {
             return;
@@ -544,7 +1113,9 @@ impl ChunkBatches {
             return;
         };
 
-        batch.set_mesh_data_for_chunk(chunk_pos_in_batch, mesh_data);
+        if batch.set_mesh_data_for_chunk(chunk_pos_in_batch, mesh_data) {
+            terrain.report_mesh_progress(chunk_pos, MeshProgress::Finished);
+        }
     }
 
     fn compute_batch_grid_size(load_area: &LoadArea) -> Size3 {
@@ -552,6 +1123,136 @@ impl ChunkBatches {
     }
 }
 
+/// Generates the vertices of a chunk mesh
+pub type Mesher = fn(ChunkMeshInput) -> Vec<TerrainVertex>;
+
+/// Mesh a chunk with `mesher` from a snapshot of its blocks and the sides and border of the
+/// surrounding chunks, timing how long it takes. The blocks are first downsampled to the
+/// resolution in `options`. The mesher runs once for each `MeshLayer`.
+/// `light` is None if the chunk and its neighbours are dark.
+/// The vertices are in chunk-local coordinates
+pub fn build_chunk_mesh(
+    mesher: Mesher,
+    blocks: &[BlockId],
+    surrounding_sides: &[Option<ChunkSide>],
+    border: &ChunkBorder,
+    light: Option<&ChunkLightSnapshot>,
+    options: MeshingOptions,
+    queued_instant: Instant,
+) -> ChunkMeshData {
+    let mesh_start = Instant::now();
+
+    let downsampled;
+    let blocks = if options.lod == LodLevel::Full {
+        blocks
+    } else {
+        downsampled = lod::downsample(blocks, options.lod);
+        &downsampled
+    };
+
+    // faces along LOD seams are never culled against the neighbour
+    let surrounding_sides = surrounding_sides
+        .iter()
+        .enumerate()
+        .map(|(face_index, side)| {
+            side.clone()
+                .filter(|_| !is_lod_seam(options.lod_seams, face_index))
+        })
+        .collect_vec();
+
+    let mesh_layer = |layer| {
+        mesher(ChunkMeshInput {
+            blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|pos| border.get(pos),
+            light: &|pos| light.map_or(ChunkLightSnapshot::UNLIT, |light| light.get(pos)),
+            layer,
+            options,
+        })
+    };
+
+    let mut vertices = mesh_layer(MeshLayer::Opaque);
+    // most chunks have no translucent blocks, so skip meshing the translucent layer for them
+    let mut translucent_vertices = if blocks
+        .iter()
+        .any(|&block_id| BLOCKS[block_id.0 as usize].model.is_translucent())
+    {
+        mesh_layer(MeshLayer::Translucent)
+    } else {
+        Vec::new()
+    };
+    // and the same for the two-sided layer, which only has plants
+    let two_sided_vertices = if blocks
+        .iter()
+        .any(|&block_id| BLOCKS[block_id.0 as usize].model.is_two_sided())
+    {
+        mesh_layer(MeshLayer::TwoSided)
+    } else {
+        Vec::new()
+    };
+
+    // close the cracks along the sides shared with coarser chunks. Plants in the two-sided layer
+    // don't line up with anything across the side, so they keep their shape
+    for vertices in [&mut vertices, &mut translucent_vertices] {
+        lod::clamp_to_coarser_neighbours(vertices, options.lod, &options.neighbour_lods);
+    }
+
+    let mesh_time = MeshTimeSample {
+        duration: mesh_start.elapsed(),
+        solid_block_count: blocks
+            .iter()
+            .filter(|&&block_id| BLOCKS[block_id.0 as usize].is_solid())
+            .count(),
+    };
+
+    ChunkMeshData {
+        vertices: vertices.into(),
+        translucent_vertices: translucent_vertices.into(),
+        two_sided_vertices: two_sided_vertices.into(),
+        shared: None,
+        queued_instant,
+        mesh_time: Some(mesh_time),
+        strategy: Some(options.strategy),
+        lod: options.lod,
+        lod_seams: options.lod_seams,
+        neighbour_lods: options.neighbour_lods,
+        normal_mode: options.normal_mode,
+    }
+}
+
+/// True if the side of a chunk with the given `FaceIndex` is one of `lod_seams`
+fn is_lod_seam(lod_seams: u8, face_index: usize) -> bool {
+    lod_seams & (1 << face_index) != 0
+}
+
+/// True if the mesh of a chunk with the given blocks and surrounding sides is certain to be
+/// empty, so that meshing can be skipped. This is the case for chunks made entirely of blocks
+/// without a mesh (e.g. air), and chunks made entirely of opaque blocks whose every side is
+/// covered by a loaded neighbour and not on one of `lod_seams`
+fn mesh_is_empty(
+    blocks: &ChunkBlockStorage,
+    surrounding_sides: &[Option<ChunkSide>],
+    lod_seams: u8,
+) -> bool {
+    let Some(block_id) = blocks.uniform_block() else {
+        return false;
+    };
+
+    match BLOCKS[block_id.0 as usize].model {
+        BlockModel::Empty => true,
+        BlockModel::FullBlock(_) => surrounding_sides.iter().enumerate().all(|(face_index, side)| {
+            !is_lod_seam(lod_seams, face_index)
+                && side
+                    .as_ref()
+                    .is_some_and(|side| side.faces.iter().all(|&visible| !visible))
+        }),
+        BlockModel::Cutout { .. }
+        | BlockModel::Translucent(_)
+        | BlockModel::MicroVoxels(_)
+        | BlockModel::Cross(_) => false,
+    }
+}
+
 /// As the indices for drawing chunk batches follow the same pattern for all batches, one index
 /// buffer is shared between all batches
 #[derive(Debug)]
@@ -578,3 +1279,263 @@ impl SharedIndexBuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use super::*;
+    use crate::{
+        block::{BLOCK_AIR, BLOCK_DIRT, BLOCK_FENCE_POST, BLOCK_LEAVES, BLOCK_WOOD},
+        terrain::{
+            chunk::{CHUNK_SIZE_CUBED, CHUNK_SIZE_SQUARED},
+            generator::GenerationParams,
+            load_area::AreaShape,
+            position_types::{GlobalBlockPosition, LocalBlockPosition},
+        },
+        util::face::FaceIndex,
+    };
+
+    fn side(visible: bool) -> Option<ChunkSide> {
+        Some(ChunkSide {
+            faces: Arc::new([visible; CHUNK_SIZE_SQUARED]),
+        })
+    }
+
+    #[test]
+    fn empty_meshes_are_detected_without_meshing() {
+        let air = ChunkBlockStorage::new(vec![BLOCK_AIR; CHUNK_SIZE_CUBED]);
+        let dirt = ChunkBlockStorage::new(vec![BLOCK_DIRT; CHUNK_SIZE_CUBED]);
+        let leaves = ChunkBlockStorage::new(vec![BLOCK_LEAVES; CHUNK_SIZE_CUBED]);
+        let enclosed = vec![side(false); 6];
+        let unloaded = vec![None; 6];
+
+        // all-air chunks never have a mesh, so no task is submitted and no buffer created
+        assert!(mesh_is_empty(&air, &unloaded, 0));
+        assert!(mesh_is_empty(&air, &enclosed, 0));
+
+        // solid chunks only if every neighbour is loaded and covers them
+        assert!(mesh_is_empty(&dirt, &enclosed, 0));
+        assert!(!mesh_is_empty(&dirt, &unloaded, 0));
+        let mut partly_exposed = enclosed.clone();
+        partly_exposed[3] = side(true);
+        assert!(!mesh_is_empty(&dirt, &partly_exposed, 0));
+        // or if one of the sides is on an LOD seam
+        assert!(!mesh_is_empty(&dirt, &enclosed, 1 << 4));
+
+        // cutout blocks show their faces inside the chunk
+        assert!(!mesh_is_empty(&leaves, &enclosed, 0));
+
+        // chunks with more than one kind of block are always meshed
+        let mut mixed = air.clone();
+        mixed.set_block(LocalBlockPosition::new(1, 2, 3), BLOCK_DIRT);
+        assert!(!mesh_is_empty(&mixed, &enclosed, 0));
+
+        // the palettes still show a single kind of block after an edit converts the storage to
+        // layers
+        let mut edited = air.clone();
+        edited.set_block(LocalBlockPosition::new(1, 2, 3), BLOCK_AIR);
+        assert!(mesh_is_empty(&edited, &unloaded, 0));
+    }
+
+    #[test]
+    fn faces_along_lod_seams_are_kept() {
+        let blocks = vec![BLOCK_DIRT; CHUNK_SIZE_CUBED];
+        let enclosed = vec![side(false); 6];
+        let mesh = |lod_seams| {
+            let options = MeshingOptions {
+                strategy: MeshingStrategy::Culled,
+                lod: LodLevel::Half,
+                lod_seams,
+                ..Default::default()
+            };
+            let border = ChunkBorder::air();
+            build_chunk_mesh(
+                meshing::mesh_culled,
+                &blocks,
+                &enclosed,
+                &border,
+                None,
+                options,
+                Instant::now(),
+            )
+        };
+
+        // the neighbours cover every side, so no faces are drawn between the chunks
+        assert!(mesh(0).is_empty());
+
+        // but a neighbour on a seam may not cover the chunk once either of them is downsampled
+        assert_eq!(mesh(1 << 4).vertices.len(), 4 * CHUNK_SIZE_SQUARED);
+        assert_eq!(mesh(1 << 4 | 1 << 1).vertices.len(), 2 * 4 * CHUNK_SIZE_SQUARED);
+    }
+
+    #[test]
+    fn vertices_on_a_seam_align_with_the_coarser_neighbour() {
+        // a full resolution chunk next to a half resolution chunk on its +x side
+        let mesh = |surface_height: &dyn Fn(u32) -> u32, lod, neighbour_lods| {
+            let blocks = (0..CHUNK_SIZE_CUBED)
+                .map(|index| {
+                    let pos = LocalBlockPosition::from_array_index(index);
+                    if pos.y() < surface_height(pos.z()) {
+                        BLOCK_DIRT
+                    } else {
+                        BLOCK_AIR
+                    }
+                })
+                .collect_vec();
+            let options = MeshingOptions {
+                lod,
+                lod_seams: 1 << FaceIndex::POS_X.as_usize() | 1 << FaceIndex::NEG_X.as_usize(),
+                neighbour_lods,
+                ..Default::default()
+            };
+
+            build_chunk_mesh(
+                meshing::mesh_greedy_parallel,
+                &blocks,
+                &vec![None; 6],
+                &ChunkBorder::air(),
+                None,
+                options,
+                Instant::now(),
+            )
+        };
+        // positions across the side shared by the chunks, as (y, z), of the vertices on it
+        let seam_vertices = |mesh_data: &ChunkMeshData, x| {
+            mesh_data
+                .vertices
+                .iter()
+                .filter(|vertex| vertex.position[0] == x)
+                .map(|vertex| (vertex.position[1] as u32, vertex.position[2] as u32))
+                .collect::<BTreeSet<_>>()
+        };
+
+        let mut full_neighbour_lods = [LodLevel::Full; 6];
+        full_neighbour_lods[FaceIndex::POS_X.as_usize()] = LodLevel::Half;
+        let mut half_neighbour_lods = [LodLevel::Half; 6];
+        half_neighbour_lods[FaceIndex::NEG_X.as_usize()] = LodLevel::Full;
+
+        // the surface at an odd height is one block lower in the full resolution chunk, until its
+        // vertices on the seam are moved up to meet the half resolution chunk
+        let flat = |_| 11;
+        let full = mesh(&flat, LodLevel::Full, full_neighbour_lods);
+        let half = mesh(&flat, LodLevel::Half, half_neighbour_lods);
+        let unclamped = mesh(&flat, LodLevel::Full, [LodLevel::Full; 6]);
+        assert!(seam_vertices(&unclamped, CHUNK_SIZE as f32).contains(&(11, 0)));
+        assert_eq!(seam_vertices(&full, CHUNK_SIZE as f32), seam_vertices(&half, 0.0));
+
+        // a sloped surface has vertices at every height, which all end up on the coarser grid
+        let sloped = |z| 8 + z / 3;
+        let full = mesh(&sloped, LodLevel::Full, full_neighbour_lods);
+        let seam = seam_vertices(&full, CHUNK_SIZE as f32);
+        assert!(!seam.is_empty());
+        assert!(seam.iter().all(|&(y, z)| y % 2 == 0 && z % 2 == 0));
+
+        // vertices away from the seam are left alone
+        assert!(full
+            .vertices
+            .iter()
+            .any(|vertex| vertex.position[0] < CHUNK_SIZE as f32 && vertex.position[1] == 9.0));
+    }
+
+    #[test]
+    fn chunk_spawn_times_are_packed_in_index_order() {
+        let spawn_times = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let uniforms = ChunkBatchUniforms::new(IVec3::new(1, 0, -1), &spawn_times);
+
+        assert_eq!(uniforms.translation, [64, 0, -64]);
+        assert_eq!(uniforms.chunk_spawn_times, [[0.0, 1.0, 2.0, 3.0], [4.0, 5.0, 6.0, 7.0]]);
+
+        // the uniform struct must match the 16-byte aligned layout in terrain.wgsl
+        assert_eq!(std::mem::size_of::<ChunkBatchUniforms>(), 48);
+    }
+
+    #[test]
+    fn chunk_uniforms_place_a_shared_mesh_at_its_chunk() {
+        let uniforms =
+            ChunkBatchUniforms::for_chunk(IVec3::new(1, 0, -1), UVec3::new(1, 1, 0), 3.0);
+
+        assert_eq!(uniforms.translation, [96, 32, -64]);
+        assert_eq!(uniforms.chunk_spawn_times, [[3.0, 0.0, 0.0, 0.0], [0.0; 4]]);
+    }
+
+    #[test]
+    fn chunk_indices_round_trip() {
+        for index in 0..CHUNK_BATCH_SIZE_CUBED {
+            let chunk_pos_in_batch = ChunkBatch::get_chunk_for_index(index);
+            assert!(chunk_pos_in_batch.max_element() < CHUNK_BATCH_SIZE as u32);
+            assert_eq!(ChunkBatch::get_index_for_chunk(&chunk_pos_in_batch), index);
+        }
+    }
+
+    #[test]
+    fn synchronous_meshes_match_the_meshing_tasks() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::new(0, -1, 0),
+                Size3::splat(3),
+                AreaShape::Cuboid,
+            ));
+        let mut tasks = Tasks::new_deterministic();
+        terrain.update(&mut tasks, Vec3::ZERO);
+        tasks.block_until_finished();
+        terrain.update(&mut tasks, Vec3::ZERO);
+
+        // not at the origin of its batch, so the meshes are translated within the batch
+        let chunk_pos = ChunkPosition::new(1, 0, 1);
+
+        // make sure the chunk has each kind of model, including against its neighbours
+        for (pos, block_id) in [
+            (GlobalBlockPosition::new(40, 10, 40), BLOCK_WOOD),
+            (GlobalBlockPosition::new(41, 10, 40), BLOCK_LEAVES),
+            (GlobalBlockPosition::new(42, 10, 40), BLOCK_FENCE_POST),
+            (GlobalBlockPosition::new(32, 31, 63), BLOCK_DIRT),
+        ] {
+            terrain.set_block(load_area_index, &pos, block_id);
+        }
+
+        let options = MeshingOptions::default();
+        let sync_mesh = terrain
+            .mesh_chunk_now(load_area_index, &chunk_pos, options)
+            .expect("chunk should be loaded");
+        assert!(!sync_mesh.vertices.is_empty());
+
+        // mesh the same snapshot in a task, as `queue_chunk_for_meshing` does
+        let blocks = terrain
+            .get_chunk(load_area_index, &chunk_pos)
+            .unwrap()
+            .get_block_storage()
+            .clone();
+        let surrounding_sides =
+            ChunkSide::get_surrounding_sides(chunk_pos, &terrain, load_area_index);
+        let border = ChunkBorder::get_border(chunk_pos, &terrain, load_area_index);
+        let light = ChunkLightSnapshot::take(chunk_pos, &terrain, load_area_index);
+        let (tx, rx) = mpsc::channel();
+        tasks.submit(TaskStage::Meshing, TaskPriority::default(), move || {
+            let mesh_data = build_chunk_mesh(
+                meshing::mesh_greedy_parallel,
+                &blocks.as_block_array(),
+                &surrounding_sides,
+                &border,
+                light.as_ref(),
+                options,
+                Instant::now(),
+            );
+            tx.send(mesh_data).unwrap();
+        });
+        tasks.block_until_finished();
+        let async_mesh = rx.recv().unwrap();
+
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&sync_mesh.vertices),
+            bytemuck::cast_slice::<_, u8>(&async_mesh.vertices)
+        );
+
+        // unloaded chunks have no mesh
+        assert!(terrain
+            .mesh_chunk_now(load_area_index, &ChunkPosition::new(5, 0, 0), options)
+            .is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Export of chunk meshes for inspection in external tools
+
+use std::io::{self, Write};
+
+use itertools::Itertools;
+
+use super::{
+    vertex::{unpack_normal, unpack_texture, TerrainVertex},
+    ChunkMeshData,
+};
+
+impl ChunkMeshData {
+    /// Write the mesh to `writer` in Wavefront OBJ format.
+    /// See `write_obj`
+    pub fn to_obj(&self, writer: impl Write) -> io::Result<()> {
+        write_obj(&self.vertices, writer)
+    }
+}
+
+/// Write a chunk mesh to `writer` in Wavefront OBJ format.
+/// Each quad becomes a single OBJ face with the same winding as the rendered triangles, and
+/// faces are grouped by texture index, with a material named `texture_<index>` for each group
+pub fn write_obj(vertices: &[TerrainVertex], mut writer: impl Write) -> io::Result<()> {
+    for vertex in vertices {
+        let [x, y, z] = vertex.position;
+        writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+
+    for vertex in vertices {
+        let [u, v] = vertex.uv;
+        writeln!(writer, "vt {} {}", u, v)?;
+    }
+
+    for vertex in vertices {
+        let normal = unpack_normal(vertex.normal);
+        writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+
+    // OBJ indices start at 1
+    let quads_by_texture = (0..vertices.len() / 4)
+        .map(|quad_index| (
+                unpack_texture(vertices[4 * quad_index].texture).0,
+                4 * quad_index + 1,
+            ))
+        .into_group_map();
+
+    for (texture_index, quads) in quads_by_texture
+        .into_iter()
+        .sorted_by_key(|(texture_index, _)| *texture_index)
+    {
+        writeln!(writer, "g texture_{}", texture_index)?;
+        writeln!(writer, "usemtl texture_{}", texture_index)?;
+
+        for first in quads {
+            writeln!(
+                writer,
+                "f {}",
+                (first..first + 4)
+                    .map(|i| format!("{i}/{i}/{i}"))
+                    .join(" ")
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec3, Vec3};
+
+    use super::*;
+    use crate::{
+        block::{BLOCK_AIR, BLOCK_GRASS},
+        render::terrain::meshing::{mesh_greedy, ChunkMeshInput, MeshLayer, MeshingOptions},
+        terrain::{
+            chunk::CHUNK_SIZE_CUBED, lighting::ChunkLightSnapshot,
+            position_types::LocalBlockPosition,
+        },
+    };
+
+    #[test]
+    fn single_cube_to_obj() {
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        blocks[LocalBlockPosition::from(UVec3::new(1, 1, 1)).get_array_index()] = BLOCK_GRASS;
+
+        let surrounding_sides = vec![None; 6];
+        let vertices = mesh_greedy(ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer: MeshLayer::Opaque,
+            options: MeshingOptions::default(),
+        });
+
+        let mut obj = Vec::new();
+        write_obj(&vertices, &mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+
+        let count_lines = |prefix: &str| {
+            obj.lines()
+                .filter(|line| line.starts_with(prefix))
+                .count()
+        };
+        assert_eq!(count_lines("v "), 24);
+        assert_eq!(count_lines("vt "), 24);
+        assert_eq!(count_lines("vn "), 24);
+        assert_eq!(count_lines("f "), 6);
+
+        // grass has a side texture, a top texture and a bottom texture
+        assert_eq!(count_lines("usemtl "), 3);
+
+        // every vertex is referenced by exactly one face
+        let referenced = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .flat_map(|face| face.split(' '))
+            .map(|corner| {
+                corner
+                    .split('/')
+                    .next()
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap()
+            })
+            .sorted()
+            .collect_vec();
+        assert_eq!(referenced, (1..=24).collect_vec());
+    }
+}
@@ -0,0 +1,189 @@
+use glam::Vec3;
+
+use super::vertex::TerrainVertex;
+use crate::{
+    block::{BlockId, BLOCKS, BLOCK_AIR},
+    terrain::{
+        chunk::{CHUNK_SIZE, CHUNK_SIZE_CUBED},
+        position_types::LocalBlockPosition,
+    },
+    util::face::FACE_NORMALS,
+};
+
+/// Default distance from the center of the load area, in chunks, beyond which chunks are meshed
+/// at half resolution. Chunks beyond twice this distance are meshed at quarter resolution
+pub const DEFAULT_LOD_DISTANCE: f32 = 8.0;
+
+/// Resolution that a chunk is meshed at. Distant chunks are downsampled before meshing, so that
+/// their meshes have fewer, larger faces
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LodLevel {
+    #[default]
+    Full,
+    /// Each 2x2x2 cell of blocks is meshed as one block
+    Half,
+    /// Each 4x4x4 cell of blocks is meshed as one block
+    Quarter,
+}
+
+impl LodLevel {
+    /// Level for a chunk at the given distance from the center of the load area, in chunks, with
+    /// chunks beyond `lod_distance` at half resolution and beyond twice it at quarter resolution.
+    /// Every chunk is at full resolution if `lod_distance` is None
+    pub fn for_distance(distance: f32, lod_distance: Option<f32>) -> Self {
+        match lod_distance {
+            Some(lod_distance) if distance > 2.0 * lod_distance => Self::Quarter,
+            Some(lod_distance) if distance > lod_distance => Self::Half,
+            _ => Self::Full,
+        }
+    }
+
+    /// Width of the cells of blocks that are meshed as one block, in blocks
+    pub fn cell_size(self) -> usize {
+        match self {
+            Self::Full => 1,
+            Self::Half => 2,
+            Self::Quarter => 4,
+        }
+    }
+}
+
+/// Downsample an array of blocks in a chunk, in chunk storage order, by replacing every
+/// block in each cell of `level.cell_size()` blocks with the most common block in the cell.
+/// Ties go to solid blocks, so that thin walls and floors don't disappear, and then to the block
+/// whose lowest occurrence is highest, so that the surface of the terrain keeps its top layer.
+/// The result has the same size and order as `blocks`
+pub fn downsample(blocks: &[BlockId], level: LodLevel) -> Vec<BlockId> {
+    debug_assert_eq!(blocks.len(), CHUNK_SIZE_CUBED);
+
+    let cell_size = level.cell_size();
+    if cell_size == 1 {
+        return blocks.to_vec();
+    }
+
+    let index = |x: usize, y: usize, z: usize| {
+        LocalBlockPosition::new(x as u32, y as u32, z as u32).get_array_index()
+    };
+    // from the bottom of the cell to the top
+    let cell_offsets = || itertools::iproduct!(0..cell_size, 0..cell_size, 0..cell_size);
+
+    let mut downsampled = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+    let mut counts: Vec<(BlockId, usize)> = Vec::with_capacity(cell_size.pow(3));
+
+    for (cell_z, cell_y, cell_x) in itertools::iproduct!(
+        (0..CHUNK_SIZE).step_by(cell_size),
+        (0..CHUNK_SIZE).step_by(cell_size),
+        (0..CHUNK_SIZE).step_by(cell_size)
+    ) {
+        // count the blocks in the cell, in the order they first appear
+        counts.clear();
+        for (y, z, x) in cell_offsets() {
+            let block_id = blocks[index(cell_x + x, cell_y + y, cell_z + z)];
+            match counts.iter_mut().find(|(id, _)| *id == block_id) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((block_id, 1)),
+            }
+        }
+
+        // the last of the tied blocks is the one whose lowest occurrence is highest
+        let (winner, _) = counts
+            .iter()
+            .copied()
+            .max_by_key(|&(block_id, count)| (count, BLOCKS[block_id.0 as usize].is_solid()))
+            .expect("cells are not empty");
+
+        for (y, z, x) in cell_offsets() {
+            downsampled[index(cell_x + x, cell_y + y, cell_z + z)] = winner;
+        }
+    }
+
+    downsampled
+}
+
+/// Move the vertices of a chunk mesh at resolution `lod` that lie on a side of the chunk shared
+/// with a coarser neighbour onto the neighbour's grid, so that the edges of the chunk's faces meet
+/// those of the neighbour's faces instead of leaving cracks between them. `neighbour_lods` is the
+/// resolution of the neighbour on each side, indexed by `FaceIndex`.
+/// Each coordinate along the side is rounded to the nearest multiple of the neighbour's cell size,
+/// with ties rounded up like the ties between solid blocks and air in `downsample`
+pub fn clamp_to_coarser_neighbours(
+    vertices: &mut [TerrainVertex],
+    lod: LodLevel,
+    neighbour_lods: &[LodLevel; 6],
+) {
+    for (normal, neighbour_lod) in FACE_NORMALS.iter().zip(neighbour_lods) {
+        if neighbour_lod.cell_size() <= lod.cell_size() {
+            continue;
+        }
+
+        let normal = normal.as_vec3();
+        let cell_size = neighbour_lod.cell_size() as f32;
+        // the side is at 0 along its axis for negative normals and at `CHUNK_SIZE` for positive
+        let side_distance = normal.max_element() * CHUNK_SIZE as f32;
+
+        for vertex in vertices.iter_mut() {
+            let position = Vec3::from(vertex.position);
+            if position.dot(normal) != side_distance {
+                continue;
+            }
+
+            // the coordinate across the side is already on the grid
+            let snapped = (position / cell_size + 0.5).floor() * cell_size;
+            let across = normal.abs();
+            vertex.position = (position * across + snapped * (Vec3::ONE - across)).to_array();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BLOCK_DIRT, BLOCK_GRASS};
+
+    #[test]
+    fn downsampling_takes_the_majority_of_each_cell() {
+        let index = |x: usize, y: usize, z: usize| {
+            LocalBlockPosition::new(x as u32, y as u32, z as u32).get_array_index()
+        };
+
+        // dirt below y = 3, with a grass layer at y = 3 and a single grass block at the origin, and
+        // a floating dirt floor at y = 8
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        for (z, y, x) in itertools::iproduct!(0..CHUNK_SIZE, 0..4, 0..CHUNK_SIZE) {
+            blocks[index(x, y, z)] = if y == 3 { BLOCK_GRASS } else { BLOCK_DIRT };
+        }
+        blocks[index(0, 0, 0)] = BLOCK_GRASS;
+        for (z, x) in itertools::iproduct!(0..CHUNK_SIZE, 0..CHUNK_SIZE) {
+            blocks[index(x, 8, z)] = BLOCK_DIRT;
+        }
+
+        let half = downsample(&blocks, LodLevel::Half);
+        // the lone grass block is outvoted by the dirt around it
+        assert_eq!(half[index(0, 0, 0)], BLOCK_DIRT);
+        assert_eq!(half[index(1, 1, 1)], BLOCK_DIRT);
+        // dirt and grass are tied in the cells from y = 2 to 3, and the grass on top wins
+        assert_eq!(half[index(5, 2, 7)], BLOCK_GRASS);
+        assert_eq!(half[index(4, 3, 6)], BLOCK_GRASS);
+        assert_eq!(half[index(4, 4, 6)], BLOCK_AIR);
+        // the floor is tied with the air above it, and the solid floor wins
+        assert_eq!(half[index(9, 8, 3)], BLOCK_DIRT);
+        assert_eq!(half[index(9, 9, 3)], BLOCK_DIRT);
+
+        let quarter = downsample(&blocks, LodLevel::Quarter);
+        // each cell from y = 0 to 3 has 47 dirt blocks and 17 grass blocks at the origin, 48 and 16
+        // elsewhere
+        assert!(quarter[..index(0, 4, 0)].iter().all(|&id| id == BLOCK_DIRT));
+        assert!(quarter[index(0, 4, 0)..index(0, 8, 0)].iter().all(|&id| id == BLOCK_AIR));
+
+        // a full resolution "downsample" leaves the blocks as they were
+        assert_eq!(downsample(&blocks, LodLevel::Full), blocks);
+    }
+
+    #[test]
+    fn lod_level_increases_with_distance() {
+        assert_eq!(LodLevel::for_distance(3.0, Some(4.0)), LodLevel::Full);
+        assert_eq!(LodLevel::for_distance(5.0, Some(4.0)), LodLevel::Half);
+        assert_eq!(LodLevel::for_distance(9.0, Some(4.0)), LodLevel::Quarter);
+        assert_eq!(LodLevel::for_distance(100.0, None), LodLevel::Full);
+    }
+}
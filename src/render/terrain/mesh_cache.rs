@@ -0,0 +1,328 @@
+use std::{
+    sync::{Arc, Mutex, OnceLock, Weak},
+    time::Instant,
+};
+
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use wgpu::util::DeviceExt;
+
+use super::{
+    meshing::{MeshLayer, MeshingOptions},
+    vertex::TerrainVertex,
+    ChunkMeshData,
+};
+use crate::{
+    block::BlockId,
+    terrain::{
+        chunk::{
+            border::ChunkBorder, compression::CompressedBlocks, side::ChunkSide,
+            CHUNK_SIZE_SQUARED,
+        },
+        lighting::{ChunkLightSnapshot, EmittedLight, Skylight},
+    },
+};
+
+/// Number of `u64`s needed to store one bit for each tile of a chunk side
+const PACKED_SIDE_LEN: usize = CHUNK_SIZE_SQUARED / 64;
+
+/// Light and skylight of a run of consecutive blocks, and the length of the run
+type LightRun = ((EmittedLight, Skylight), u32);
+
+/// Everything that the mesh of a chunk depends on. Since meshes are in chunk-local coordinates,
+/// chunks with equal keys have identical meshes wherever they are
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshCacheKey {
+    blocks: CompressedBlocks,
+    /// Sides of the surrounding chunks, with one bit per tile
+    surrounding_sides: Vec<Option<[u64; PACKED_SIDE_LEN]>>,
+    /// Blocks of the surrounding chunks touching the chunk, which affect its ambient occlusion
+    border: CompressedBlocks,
+    /// Light in front of each face, run-length encoded as pairs of light and run length, or None
+    /// if the chunk and its neighbours are unlit. Light varies smoothly, and underground or in
+    /// the open it is the same almost everywhere, so this is short for most chunks
+    light: Option<Box<[LightRun]>>,
+    options: MeshingOptions,
+}
+
+impl MeshCacheKey {
+    /// `blocks` is the array of blocks in the chunk, ordered by y, then z, then x
+    pub fn new(
+        blocks: &[BlockId],
+        surrounding_sides: &[Option<ChunkSide>],
+        border: &ChunkBorder,
+        light: Option<&ChunkLightSnapshot>,
+        options: MeshingOptions,
+    ) -> Self {
+        let pack_side = |side: &ChunkSide| {
+            let mut packed = [0; PACKED_SIDE_LEN];
+            for (index, &visible) in side.faces.iter().enumerate() {
+                packed[index / 64] |= (visible as u64) << (index % 64);
+            }
+            packed
+        };
+
+        Self {
+            blocks: CompressedBlocks::compress(blocks),
+            surrounding_sides: surrounding_sides
+                .iter()
+                .map(|side| side.as_ref().map(pack_side))
+                .collect(),
+            border: CompressedBlocks::compress(&border.blocks().collect_vec()),
+            light: light.map(|light| {
+                light
+                    .levels()
+                    .iter()
+                    .dedup_with_count()
+                    .map(|(length, &level)| (level, length as u32))
+                    .collect()
+            }),
+            options,
+        }
+    }
+}
+
+/// Shares the meshes of identical chunks, e.g. for flat fields or solid rock, so that they are
+/// only built, kept in memory and uploaded to the GPU once.
+/// The cache only holds weak references, so a mesh lives as long as some chunk batch refers to it.
+/// Entries whose meshes have been dropped are removed by `evict_unused`
+#[derive(Debug, Default)]
+pub struct ChunkMeshCache {
+    meshes: FxHashMap<MeshCacheKey, Weak<SharedChunkMesh>>,
+}
+
+/// Vertices of the opaque, translucent and two-sided layers of a chunk mesh
+pub type MeshVertices = (Arc<[TerrainVertex]>, Arc<[TerrainVertex]>, Arc<[TerrainVertex]>);
+
+/// Mesh in the cache, shared by every chunk with the same key. Chunk batches draw the chunks
+/// using a mesh that is shared with another chunk from its own vertex buffers, rather than
+/// copying it into their combined buffers
+#[derive(Debug)]
+pub struct SharedChunkMesh {
+    pub vertices: Arc<[TerrainVertex]>,
+    pub translucent_vertices: Arc<[TerrainVertex]>,
+    pub two_sided_vertices: Arc<[TerrainVertex]>,
+    /// Vertex buffers of each `MeshLayer`, or None for an empty layer, created when the mesh is
+    /// first drawn
+    vertex_buffers: OnceLock<[Option<wgpu::Buffer>; 3]>,
+}
+
+impl SharedChunkMesh {
+    /// Upload the vertices of each layer to their own vertex buffers, unless they already have
+    /// been
+    pub fn upload(&self, device: &wgpu::Device) {
+        self.vertex_buffers.get_or_init(|| {
+            let layers = [
+                &self.vertices,
+                &self.translucent_vertices,
+                &self.two_sided_vertices,
+            ];
+            layers.map(|vertices| {
+                (!vertices.is_empty()).then(|| {
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Shared Chunk Mesh Vertex Buffer"),
+                        contents: bytemuck::cast_slice(vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    })
+                })
+            })
+        });
+    }
+
+    /// Returns the vertex buffer of the given layer, or None if the layer is empty or the mesh
+    /// hasn't been uploaded
+    pub fn vertex_buffer(&self, layer: MeshLayer) -> Option<&wgpu::Buffer> {
+        self.vertex_buffers.get()?[layer as usize].as_ref()
+    }
+
+    /// Returns the vertices of the given layer
+    pub fn vertices(&self, layer: MeshLayer) -> &[TerrainVertex] {
+        match layer {
+            MeshLayer::Opaque => &self.vertices,
+            MeshLayer::Translucent => &self.translucent_vertices,
+            MeshLayer::TwoSided => &self.two_sided_vertices,
+        }
+    }
+}
+
+impl ChunkMeshCache {
+    /// Returns the cached mesh for chunks with the given key, if any chunk still uses it
+    pub fn get(&self, key: &MeshCacheKey) -> Option<Arc<SharedChunkMesh>> {
+        self.meshes.get(key)?.upgrade()
+    }
+
+    /// Add a mesh to the cache, returning the mesh to use for the chunk. If an identical mesh was
+    /// cached in the meantime (e.g. by another meshing thread), that one is returned instead so
+    /// that both chunks share it
+    pub fn insert(
+        &mut self,
+        key: MeshCacheKey,
+        (vertices, translucent_vertices, two_sided_vertices): MeshVertices,
+    ) -> Arc<SharedChunkMesh> {
+        if let Some(cached) = self.get(&key) {
+            return cached;
+        }
+
+        let mesh = Arc::new(SharedChunkMesh {
+            vertices,
+            translucent_vertices,
+            two_sided_vertices,
+            vertex_buffers: OnceLock::new(),
+        });
+        self.meshes.insert(key, Arc::downgrade(&mesh));
+        mesh
+    }
+
+    /// Find the mesh data for a chunk in the cache, or build it with `build` and add it to the
+    /// cache. The lock is not held while building, so that meshing threads don't wait on each
+    /// other
+    pub fn get_or_build(
+        cache: &Mutex<Self>,
+        key: MeshCacheKey,
+        queued_instant: Instant,
+        build: impl FnOnce() -> ChunkMeshData,
+    ) -> ChunkMeshData {
+        if let Some(shared) = cache.lock().unwrap().get(&key) {
+            return ChunkMeshData {
+                vertices: Arc::clone(&shared.vertices),
+                translucent_vertices: Arc::clone(&shared.translucent_vertices),
+                two_sided_vertices: Arc::clone(&shared.two_sided_vertices),
+                shared: Some(shared),
+                queued_instant,
+                mesh_time: None,
+                strategy: Some(key.options.strategy),
+                lod: key.options.lod,
+                lod_seams: key.options.lod_seams,
+                neighbour_lods: key.options.neighbour_lods,
+                normal_mode: key.options.normal_mode,
+            };
+        }
+
+        let mut mesh_data = build();
+
+        // empty meshes are cheap to create and never drawn, so caching them would only waste
+        // memory
+        if !mesh_data.is_empty() {
+            let shared = cache.lock().unwrap().insert(
+                key,
+                (
+                    mesh_data.vertices,
+                    mesh_data.translucent_vertices,
+                    mesh_data.two_sided_vertices,
+                ),
+            );
+            mesh_data.vertices = Arc::clone(&shared.vertices);
+            mesh_data.translucent_vertices = Arc::clone(&shared.translucent_vertices);
+            mesh_data.two_sided_vertices = Arc::clone(&shared.two_sided_vertices);
+            mesh_data.shared = Some(shared);
+        }
+        mesh_data
+    }
+
+    /// Remove the entries whose meshes are no longer used by any chunk. Returns the number of
+    /// entries removed
+    pub fn evict_unused(&mut self) -> usize {
+        let len_before = self.meshes.len();
+        self.meshes
+            .retain(|_, cached| cached.strong_count() > 0);
+        len_before - self.meshes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        block::{BLOCK_AIR, BLOCK_DIRT, BLOCK_GRASS},
+        terrain::chunk::{CHUNK_SIZE_CUBED, CHUNK_SIZE_SQUARED},
+    };
+
+    /// Flat field of grass on dirt, like generated terrain far from any hills
+    fn flat_field() -> Vec<BlockId> {
+        (0..CHUNK_SIZE_CUBED)
+            .map(|index| match index / CHUNK_SIZE_SQUARED {
+                0..10 => BLOCK_DIRT,
+                10 => BLOCK_GRASS,
+                _ => BLOCK_AIR,
+            })
+            .collect()
+    }
+
+    fn vertices(count: usize) -> MeshVertices {
+        (
+            vec![bytemuck::Zeroable::zeroed(); count].into(),
+            Arc::new([]),
+            Arc::new([]),
+        )
+    }
+
+    #[test]
+    fn identical_chunks_share_one_cached_mesh() {
+        let mut cache = ChunkMeshCache::default();
+        let blocks = flat_field();
+        let surrounding_sides = vec![None; 6];
+        let border = ChunkBorder::air();
+        let options = MeshingOptions::default();
+        let key = || MeshCacheKey::new(&blocks, &surrounding_sides, &border, None, options);
+
+        let first = cache.insert(key(), vertices(4));
+        let second = cache.get(&key()).expect("mesh should be cached");
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // a mesh built for the same key in the meantime is replaced by the cached one
+        assert!(Arc::ptr_eq(&cache.insert(key(), vertices(4)), &first));
+        assert_eq!(cache.meshes.len(), 1);
+
+        // chunks with different blocks or neighbours get their own mesh
+        let mut other_blocks = blocks.clone();
+        other_blocks[0] = BLOCK_GRASS;
+        let other_key =
+            MeshCacheKey::new(&other_blocks, &surrounding_sides, &border, None, options);
+        assert!(cache.get(&other_key).is_none());
+
+        let side = ChunkSide {
+            faces: Arc::new([true; CHUNK_SIZE_SQUARED]),
+        };
+        let mut other_sides = surrounding_sides.clone();
+        other_sides[2] = Some(side);
+        let other_key = MeshCacheKey::new(&blocks, &other_sides, &border, None, options);
+        assert!(cache.get(&other_key).is_none());
+
+        let floor = ChunkBorder::from_fn(|pos| if pos.y < 0 { BLOCK_DIRT } else { BLOCK_AIR });
+        let other_key = MeshCacheKey::new(&blocks, &surrounding_sides, &floor, None, options);
+        assert!(cache.get(&other_key).is_none());
+
+        // and so do chunks that are lit differently, but identically lit chunks share a mesh
+        let dark = ChunkLightSnapshot::uniform((EmittedLight::DARK, Skylight::DARK));
+        let lit_key =
+            || MeshCacheKey::new(&blocks, &surrounding_sides, &border, Some(&dark), options);
+        assert!(cache.get(&lit_key()).is_none());
+        let lit = cache.insert(lit_key(), vertices(4));
+        assert!(Arc::ptr_eq(&cache.get(&lit_key()).unwrap(), &lit));
+    }
+
+    #[test]
+    fn meshes_are_evicted_once_unused() {
+        let mut cache = ChunkMeshCache::default();
+        let surrounding_sides = vec![None; 6];
+        let border = ChunkBorder::air();
+        let key = MeshCacheKey::new(
+            &flat_field(),
+            &surrounding_sides,
+            &border,
+            None,
+            MeshingOptions::default(),
+        );
+        let first = cache.insert(key.clone(), vertices(4));
+        let second = cache.get(&key).unwrap();
+        drop(first);
+        assert_eq!(cache.evict_unused(), 0);
+
+        drop(second);
+        assert!(cache.get(&key).is_none());
+        assert_eq!(cache.evict_unused(), 1);
+        assert_eq!(cache.meshes.len(), 0);
+    }
+}
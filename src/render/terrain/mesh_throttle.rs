@@ -0,0 +1,82 @@
+/// Decides which new chunk meshes to defer while the camera is travelling quickly, as meshing
+/// every newly loaded chunk immediately causes stutter.
+/// Deferred chunks are simply requested again on later frames, so they catch up once the camera
+/// slows down
+#[derive(Clone, Debug)]
+pub struct MeshThrottle {
+    /// Camera speed in blocks per second above which mesh jobs are throttled
+    pub speed_threshold: f32,
+    /// Maximum number of throttled mesh jobs to issue per frame while the camera is moving quickly
+    pub job_budget: usize,
+    /// Chunks within this distance of the camera, in chunks, are always meshed promptly
+    pub prompt_radius: f32,
+    /// Whether the camera is moving faster than `speed_threshold` this frame
+    throttling: bool,
+    /// Number of throttled mesh jobs issued so far this frame
+    jobs_this_frame: usize,
+}
+
+impl MeshThrottle {
+    pub fn new(speed_threshold: f32, job_budget: usize, prompt_radius: f32) -> Self {
+        Self {
+            speed_threshold,
+            job_budget,
+            prompt_radius,
+            throttling: false,
+            jobs_this_frame: 0,
+        }
+    }
+
+    /// Called at the start of each frame with the current speed of the camera in blocks per
+    /// second
+    pub fn begin_frame(&mut self, camera_speed: f32) {
+        self.throttling = camera_speed > self.speed_threshold;
+        self.jobs_this_frame = 0;
+    }
+
+    /// Returns true if a mesh job for a chunk at the given distance from the camera, in chunks,
+    /// should be issued this frame. Chunks near the camera are always allowed; while the camera
+    /// is moving quickly, chunks outside the frustum are deferred and the rest share the per-frame
+    /// budget
+    pub fn allow(&mut self, distance_to_camera: f32, within_frustum: bool) -> bool {
+        if !self.throttling || distance_to_camera <= self.prompt_radius {
+            return true;
+        }
+
+        if !within_frustum || self.jobs_this_frame >= self.job_budget {
+            return false;
+        }
+
+        self.jobs_this_frame += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_only_while_moving_quickly() {
+        let mut throttle = MeshThrottle::new(20.0, 2, 2.0);
+
+        // slow: everything is allowed
+        throttle.begin_frame(10.0);
+        assert!((0..10).all(|_| throttle.allow(10.0, false)));
+
+        // fast: distant chunks share the budget, outside the frustum they are deferred
+        throttle.begin_frame(50.0);
+        assert!(!throttle.allow(10.0, false));
+        assert!(throttle.allow(10.0, true));
+        assert!(throttle.allow(10.0, true));
+        assert!(!throttle.allow(10.0, true));
+
+        // nearby chunks are never deferred, even once the budget is spent
+        assert!(throttle.allow(1.0, false));
+        assert!(throttle.allow(2.0, true));
+
+        // the budget is refilled each frame
+        throttle.begin_frame(50.0);
+        assert!(throttle.allow(10.0, true));
+    }
+}
@@ -0,0 +1,175 @@
+use std::{collections::VecDeque, time::Duration};
+
+use itertools::Itertools;
+
+/// Records how long chunk meshes took to build, to help find meshing hotspots
+#[derive(Clone, Debug)]
+pub struct MeshTimeStats {
+    /// Most recent samples, oldest first
+    samples: VecDeque<MeshTimeSample>,
+    /// Maximum number of samples to keep
+    capacity: usize,
+    /// Number of samples recorded in total, including those discarded
+    total_recorded: usize,
+}
+
+impl MeshTimeStats {
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            total_recorded: 0,
+        }
+    }
+
+    /// Record the time taken to mesh one chunk, discarding the oldest sample if full
+    pub fn record(&mut self, sample: MeshTimeSample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.total_recorded += 1;
+    }
+
+    /// Number of chunk meshes recorded since the stats were created, including those whose
+    /// samples have since been discarded
+    pub fn total_recorded(&self) -> usize {
+        self.total_recorded
+    }
+
+    /// Compute the min, median, max and 99th percentile of the recorded mesh times, or None if
+    /// nothing has been recorded
+    pub fn summary(&self) -> Option<MeshTimeSummary> {
+        let sorted = self
+            .samples
+            .iter()
+            .map(|sample| sample.duration)
+            .sorted()
+            .collect_vec();
+
+        // nearest-rank percentile
+        let percentile = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+
+        (!sorted.is_empty()).then(|| MeshTimeSummary {
+            count: sorted.len(),
+            min: sorted[0],
+            median: percentile(0.5),
+            max: sorted[sorted.len() - 1],
+            p99: percentile(0.99),
+        })
+    }
+
+    /// Pearson correlation between the number of solid blocks in a chunk and the time taken to
+    /// mesh it, or None if there are too few samples or no variation
+    pub fn block_count_correlation(&self) -> Option<f64> {
+        let n = self.samples.len() as f64;
+        if self.samples.len() < 2 {
+            return None;
+        }
+
+        let xs = self
+            .samples
+            .iter()
+            .map(|sample| sample.solid_block_count as f64);
+        let ys = self
+            .samples
+            .iter()
+            .map(|sample| sample.duration.as_secs_f64());
+
+        let mean_x = xs.clone().sum::<f64>() / n;
+        let mean_y = ys.clone().sum::<f64>() / n;
+
+        let (covariance, variance_x, variance_y) = xs.zip(ys).fold(
+            (0.0, 0.0, 0.0),
+            |(covariance, variance_x, variance_y), (x, y)| {
+                let (dx, dy) = (x - mean_x, y - mean_y);
+                (covariance + dx * dy, variance_x + dx * dx, variance_y + dy * dy)
+            },
+        );
+
+        let denominator = (variance_x * variance_y).sqrt();
+        (denominator > 0.0).then(|| covariance / denominator)
+    }
+}
+
+impl Default for MeshTimeStats {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+/// Time taken to mesh a single chunk
+#[derive(Clone, Copy, Debug)]
+pub struct MeshTimeSample {
+    /// Time spent in the mesher on the worker thread
+    pub duration: Duration,
+    /// Number of non-air blocks in the chunk, as a measure of its complexity
+    pub solid_block_count: usize,
+}
+
+/// Summary of the recorded mesh times
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshTimeSummary {
+    pub count: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+    pub p99: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(millis: u64, solid_block_count: usize) -> MeshTimeSample {
+        MeshTimeSample {
+            duration: Duration::from_millis(millis),
+            solid_block_count,
+        }
+    }
+
+    #[test]
+    fn summary_of_samples() {
+        let mut stats = MeshTimeStats::default();
+        assert!(stats.summary().is_none());
+
+        for millis in (1..=100).rev() {
+            stats.record(sample(millis, 0));
+        }
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.median, Duration::from_millis(50));
+        assert_eq!(summary.max, Duration::from_millis(100));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+    }
+
+    #[test]
+    fn oldest_samples_are_discarded() {
+        let mut stats = MeshTimeStats::new(2);
+        stats.record(sample(10, 0));
+        stats.record(sample(1, 0));
+        stats.record(sample(2, 0));
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.max, Duration::from_millis(2));
+    }
+
+    #[test]
+    fn correlation_with_block_count() {
+        let mut stats = MeshTimeStats::default();
+        assert!(stats.block_count_correlation().is_none());
+
+        for i in 1..10 {
+            stats.record(sample(i, 100 * i as usize));
+        }
+        assert!((stats.block_count_correlation().unwrap() - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+/// Default maximum number of finished chunk meshes uploaded per frame
+pub const DEFAULT_MESH_UPLOAD_BUDGET: usize = 8;
+
+/// Holds finished chunk meshes until they are uploaded, so that only a limited number are uploaded
+/// each frame. Uploading every mesh as soon as it is finished causes a spike when many chunks
+/// finish at once, e.g. after teleporting or when the world first loads.
+/// Meshes are uploaded in the order they were finished
+#[derive(Debug)]
+pub struct MeshUploadQueue<T> {
+    queue: VecDeque<T>,
+    /// Maximum number of meshes taken from the queue per frame
+    budget: usize,
+}
+
+impl<T> MeshUploadQueue<T> {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            budget: budget.max(1),
+        }
+    }
+
+    /// Add finished meshes to the back of the queue
+    pub fn extend(&mut self, meshes: impl IntoIterator<Item = T>) {
+        self.queue.extend(meshes);
+    }
+
+    /// Take the meshes to upload this frame from the front of the queue, at most `budget` of them
+    pub fn take_frame(&mut self) -> impl Iterator<Item = T> + '_ {
+        let count = self.budget.min(self.queue.len());
+        self.queue.drain(..count)
+    }
+
+    /// Maximum number of meshes taken from the queue per frame
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Set the maximum number of meshes taken from the queue per frame. At least one mesh is
+    /// always taken, so that the queue is eventually emptied
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget.max(1);
+    }
+
+    /// Number of meshes waiting to be uploaded
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+impl<T> Default for MeshUploadQueue<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_MESH_UPLOAD_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flood_of_meshes_is_spread_over_frames() {
+        let mut queue = MeshUploadQueue::new(8);
+        queue.extend(0..500);
+
+        let mut uploaded = Vec::new();
+        let mut frames = 0;
+        while queue.len() > 0 {
+            let frame: Vec<i32> = queue.take_frame().collect();
+            assert!(!frame.is_empty() && frame.len() <= 8);
+
+            uploaded.extend(frame);
+            frames += 1;
+        }
+
+        // every mesh is uploaded once, in the order it was finished
+        assert_eq!(frames, 63);
+        assert_eq!(uploaded, (0..500).collect::<Vec<_>>());
+
+        // a budget of zero would never empty the queue
+        queue.set_budget(0);
+        queue.extend([1, 2]);
+        assert_eq!(queue.budget(), 1);
+        assert_eq!(queue.take_frame().count(), 1);
+    }
+}
@@ -1,24 +1,131 @@
-use glam::{UVec2, UVec3, Vec2, Vec3};
+use std::{hash::Hash, sync::LazyLock};
+
+use glam::{IVec3, UVec2, UVec3, Vec2, Vec3};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
 
 use self::face_dir::*;
-use super::vertex::TerrainVertex;
+use super::{
+    lod::LodLevel,
+    vertex::{pack_light, pack_normal, pack_texture, TerrainVertex},
+};
 use crate::{
-    block::{model::BlockFace, BlockId, BLOCKS},
+    block::{
+        model::{BlockFace, BlockModel, MicroVoxelBox, UvRotation},
+        BlockId, BLOCKS,
+    },
     terrain::{
-        chunk::{side::ChunkSide, CHUNK_SIZE_SQUARED, CHUNK_SIZE_U32},
+        chunk::{side::ChunkSide, CHUNK_SIZE, CHUNK_SIZE_SQUARED, CHUNK_SIZE_U32},
+        lighting::{EmittedLight, Skylight},
         position_types::LocalBlockPosition,
     },
+    util::face::FaceIndex,
 };
 
-/// Data about a chunk needed to generate its mesh
+/// Winding order of front-facing triangles in the meshes generated here: anticlockwise when the
+/// face is viewed from outside the block. Pipelines drawing these meshes with back-face culling
+/// must use this
+pub const FRONT_FACE: wgpu::FrontFace = wgpu::FrontFace::Ccw;
+
+/// Data about a chunk needed to generate its mesh.
+/// Meshes are generated in chunk-local coordinates, from 0 to `CHUNK_SIZE` on each axis, so they
+/// don't depend on where the chunk is in the world
 #[derive(Clone, Copy)]
 pub struct ChunkMeshInput<'a> {
-    /// Array of blocks in the chunk, ordered by z, then y, then x
+    /// Array of blocks in the chunk, ordered by y, then z, then x
     pub blocks: &'a [BlockId],
-    /// Translation to encode in the mesh
-    pub translation: Vec3,
     /// Sides of the surrounding chunks
     pub surrounding_sides: &'a [Option<ChunkSide>],
+    /// Returns the block at a position relative to the origin of the chunk that is just outside
+    /// it, so that ambient occlusion continues across chunk boundaries. Blocks in chunks that
+    /// aren't loaded should be air
+    pub neighbour_block: &'a (dyn Fn(IVec3) -> BlockId + Sync),
+    /// Returns the light from emitting blocks and the skylight of the block at a position relative
+    /// to the origin of the chunk, either in the chunk or just outside it
+    pub light: &'a (dyn Fn(IVec3) -> (EmittedLight, Skylight) + Sync),
+    /// Which faces to include. The mesher runs once for each layer of the chunk mesh
+    pub layer: MeshLayer,
+    /// Options controlling the generated mesh
+    pub options: MeshingOptions,
+}
+
+/// Part of a chunk mesh drawn in its own render pass
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MeshLayer {
+    /// Faces drawn without blending in the main terrain pass, including those of cutout blocks
+    #[default]
+    Opaque,
+    /// Faces of translucent blocks, drawn with alpha blending after the rest of the terrain
+    Translucent,
+    /// Quads of cross-shaped plants, alpha tested like cutout blocks but drawn without back-face
+    /// culling, so that each quad is visible from both sides
+    TwoSided,
+}
+
+impl MeshLayer {
+    /// Layer containing the faces of blocks with the given model
+    pub fn of(model: &BlockModel) -> Self {
+        if model.is_translucent() {
+            Self::Translucent
+        } else if model.is_two_sided() {
+            Self::TwoSided
+        } else {
+            Self::Opaque
+        }
+    }
+
+    /// Returns the face of a block with the given model in the given direction at the given
+    /// position in the chunk, if the block has one and it belongs to this layer
+    fn face(
+        self,
+        model: &BlockModel,
+        face_index: FaceIndex,
+        pos_in_chunk: UVec3,
+    ) -> Option<BlockFace> {
+        model
+            .face(face_index)
+            .filter(|_| Self::of(model) == self)
+            .map(|face| face.at(pos_in_chunk))
+    }
+}
+
+/// Options controlling how chunk meshes are generated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MeshingOptions {
+    /// Which mesher builds the mesh
+    pub strategy: MeshingStrategy,
+    /// How vertex normals are computed
+    pub normal_mode: NormalMode,
+    /// Resolution the chunk is downsampled to before meshing, applied by `build_chunk_mesh`
+    pub lod: LodLevel,
+    /// Sides of the chunk on a seam between levels of detail, one bit per `FaceIndex`. Faces on
+    /// these sides are not culled against the neighbour, see `ChunkBatches::lod_seams`
+    pub lod_seams: u8,
+    /// Resolution of the neighbouring chunk on each side, indexed by `FaceIndex`. Vertices on the
+    /// sides shared with coarser neighbours are moved onto their grid by `build_chunk_mesh`
+    pub neighbour_lods: [LodLevel; 6],
+}
+
+/// Which mesher is used to build a chunk mesh
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MeshingStrategy {
+    /// `mesh_culled`: quick to build but slower to draw, e.g. for chunks that are being edited
+    Culled,
+    /// `mesh_greedy_parallel`: slower to build but quicker to draw, e.g. for distant chunks
+    #[default]
+    Greedy,
+}
+
+/// How vertex normals are computed for each face
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NormalMode {
+    /// Every vertex of a face uses the face normal, for the blocky look
+    #[default]
+    Flat,
+    /// Vertices on exposed edges are bent towards the neighbouring faces, so that lighting is
+    /// continuous around the edges of a surface.
+    /// Neighbouring chunks are not considered, so edges on the chunk boundary are left flat
+    Smooth,
 }
 
 /// Creates the vertices for a chunk mesh where faces inside the volume are skipped but no
@@ -26,7 +133,6 @@ pub struct ChunkMeshInput<'a> {
 /// The mesh should be rendered an index buffer that repeats the pattern 0, 1, 2, 2, 3, 0.
 /// Compared to `mesh_greedy`, meshing is much faster but the resulting meshes
 /// are more complex and therefore slower to render
-#[allow(unused)]
 pub fn mesh_culled(input: ChunkMeshInput) -> Vec<TerrainVertex> {
     let mut vertices = Vec::new();
 
@@ -36,6 +142,7 @@ pub fn mesh_culled(input: ChunkMeshInput) -> Vec<TerrainVertex> {
     add_visible_faces::<NegX>(&mut vertices, input);
     add_visible_faces::<NegY>(&mut vertices, input);
     add_visible_faces::<NegZ>(&mut vertices, input);
+    add_precomputed_model_blocks(&mut vertices, input);
 
     vertices
 }
@@ -43,29 +150,269 @@ pub fn mesh_culled(input: ChunkMeshInput) -> Vec<TerrainVertex> {
 /// Creates a chunk mesh where faces inside the volume are skipped and
 /// compatible faces are merged greedily.
 /// Compared to `culled`, meshing is much slower but the resulting meshes
-/// are simpler and therefore faster to render
-#[allow(unused)]
+/// are simpler and therefore faster to render.
+/// Faces are merged according to `DefaultMergePolicy`.
+/// The meshing tasks use `mesh_greedy_parallel`, which is checked against this in tests
+#[cfg(test)]
 pub fn mesh_greedy(input: ChunkMeshInput) -> Vec<TerrainVertex> {
     let mut vertices = Vec::new();
 
-    add_greedy_merged_faces::<PosX>(&mut vertices, input);
-    add_greedy_merged_faces::<PosY>(&mut vertices, input);
-    add_greedy_merged_faces::<PosZ>(&mut vertices, input);
-    add_greedy_merged_faces::<NegX>(&mut vertices, input);
-    add_greedy_merged_faces::<NegY>(&mut vertices, input);
-    add_greedy_merged_faces::<NegZ>(&mut vertices, input);
+    for add_faces in greedy_meshing_passes::<DefaultMergePolicy>() {
+        add_faces(&mut vertices, input, &DefaultMergePolicy);
+    }
+    add_precomputed_model_blocks(&mut vertices, input);
 
     vertices
 }
 
-/// Decides whether the two faces can be merged
-fn can_merge_faces<Dir>(first: Option<BlockFace>, second: Option<BlockFace>) -> bool
+/// Same as `mesh_greedy`, but the six face directions are meshed in parallel and the results
+/// concatenated. The output is identical to that of `mesh_greedy`.
+/// Since meshes are drawn with a shared index buffer, the vertices of each direction can simply
+/// be appended without needing to offset any indices
+pub fn mesh_greedy_parallel(input: ChunkMeshInput) -> Vec<TerrainVertex> {
+    mesh_greedy_with_policy(input, &DefaultMergePolicy)
+}
+
+/// Same as `mesh_greedy_parallel`, but `merge_policy` decides which faces can be merged
+pub fn mesh_greedy_with_policy<P>(input: ChunkMeshInput, merge_policy: &P) -> Vec<TerrainVertex>
+where
+    P: MergePolicy,
+{
+    let mut vertices = greedy_meshing_passes::<P>()
+        .par_iter()
+        .map(|add_faces| {
+            let mut vertices = Vec::new();
+            add_faces(&mut vertices, input, merge_policy);
+            vertices
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    add_precomputed_model_blocks(&mut vertices, input);
+
+    vertices
+}
+
+/// Adds the greedily merged faces for one face direction to the mesh
+type GreedyMeshingPass<P> = fn(&mut Vec<TerrainVertex>, ChunkMeshInput, &P);
+
+/// Greedy meshing pass for each face direction, in the order the faces appear in the mesh
+fn greedy_meshing_passes<P>() -> [GreedyMeshingPass<P>; 6]
+where
+    P: MergePolicy,
+{
+    [
+        add_greedy_merged_faces::<PosX, P>,
+        add_greedy_merged_faces::<PosY, P>,
+        add_greedy_merged_faces::<PosZ, P>,
+        add_greedy_merged_faces::<NegX, P>,
+        add_greedy_merged_faces::<NegY, P>,
+        add_greedy_merged_faces::<NegZ, P>,
+    ]
+}
+
+/// A visible face considered for merging by the greedy mesher
+#[derive(Clone, Copy, Debug)]
+pub struct MergeFace<'a> {
+    /// ID of the block the face belongs to
+    pub block_id: BlockId,
+    /// Model of the block the face belongs to
+    pub model: &'a BlockModel,
+    /// The face itself
+    pub face: BlockFace,
+}
+
+/// Decides which faces the greedy mesher may merge into a single quad.
+/// The policy gives each face a key, and faces in the same layer with equal keys may be merged,
+/// so compatibility is always symmetric and transitive. Unless the policy merges across light,
+/// faces must also have the same light to be merged.
+/// The merged quad takes its texture, light and normals from one of its faces, so for the mesh to
+/// look correct, every face with a given key must look right when drawn with the texture and any
+/// other per-face data of any other face with that key.
+/// The key is computed before the light of the face, as interpolating the light is much more
+/// expensive. Hidden faces and faces of `never_merge` blocks are never passed to the policy
+pub trait MergePolicy: Sync {
+    /// Identifies a group of faces that can be merged with each other
+    type Key: Copy + Eq + Hash;
+
+    /// Key of the given face, or None if the face must have a quad of its own
+    fn merge_key(&self, face: &MergeFace) -> Option<Self::Key>;
+
+    /// Whether faces with different light can be merged, the merged quad taking the light of one
+    /// of them
+    fn merges_across_light(&self) -> bool {
+        false
+    }
+}
+
+/// Merges faces with the same texture, tint, UV rotation and light, never merging cutout faces
+/// with solid faces, or translucent faces of different blocks
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultMergePolicy;
+
+impl MergePolicy for DefaultMergePolicy {
+    /// The face, whether it is a cutout, and the block for translucent faces
+    type Key = (BlockFace, bool, Option<BlockId>);
+
+    fn merge_key(&self, face: &MergeFace) -> Option<Self::Key> {
+        Some((
+            face.face,
+            // cutout faces are never merged with solid faces
+            face.model.is_cutout(),
+            // translucent faces are only merged with those of the same block, so that blocks
+            // with the same texture but different blending still look right
+            face.model
+                .is_translucent()
+                .then_some(face.block_id),
+        ))
+    }
+}
+
+/// Mesh of the `MicroVoxels` or `Cross` model of each block relative to the block's position,
+/// indexed by `BlockId`. Empty for blocks with other models, whose faces are meshed one by one
+static PRECOMPUTED_MODEL_MESHES: LazyLock<Vec<Vec<TerrainVertex>>> = LazyLock::new(|| {
+    BLOCKS
+        .iter()
+        .map(|block| match block.model {
+            BlockModel::MicroVoxels(boxes) => mesh_micro_voxel_boxes(boxes),
+            BlockModel::Cross(face) => mesh_cross(face),
+            _ => Vec::new(),
+        })
+        .collect()
+});
+
+/// Creates the mesh of a `MicroVoxels` model relative to the block's position. Every face of
+/// every box is included, with full light and flat normals
+fn mesh_micro_voxel_boxes(boxes: &[MicroVoxelBox]) -> Vec<TerrainVertex> {
+    let mut vertices = Vec::new();
+
+    for micro_voxel_box in boxes {
+        add_box_face::<PosX>(&mut vertices, micro_voxel_box);
+        add_box_face::<PosY>(&mut vertices, micro_voxel_box);
+        add_box_face::<PosZ>(&mut vertices, micro_voxel_box);
+        add_box_face::<NegX>(&mut vertices, micro_voxel_box);
+        add_box_face::<NegY>(&mut vertices, micro_voxel_box);
+        add_box_face::<NegZ>(&mut vertices, micro_voxel_box);
+    }
+
+    vertices
+}
+
+/// Add the face of a micro-voxel box with the given direction to the mesh
+fn add_box_face<Dir>(vertices: &mut Vec<TerrainVertex>, micro_voxel_box: &MicroVoxelBox)
 where
     Dir: FaceDir,
 {
-    let faces_match = first == second;
+    let (min, max) = micro_voxel_box.bounds();
+    let extent = max - min;
 
-    faces_match
+    // size of the face along the two axes parallel to it, in the order `FaceDir::vertices` uses
+    let size = Vec2::new(
+        extent.dot(Dir::rotate_vec3(Vec3::X).abs()),
+        extent.dot(Dir::rotate_vec3(Vec3::Y).abs()),
+    );
+
+    // `FaceDir::vertices` places faces on the sides of a unit cube, so move positive faces back
+    // onto the far side of the box
+    let origin = if Dir::NEGATIVE {
+        min
+    } else {
+        min - Dir::NORMAL.as_vec3() * (1.0 - extent)
+    };
+
+    add_face::<Dir>(
+        vertices,
+        origin,
+        size,
+        micro_voxel_box.faces[Dir::FACE_INDEX.as_usize()],
+        FaceLightData::UNOCCLUDED,
+        [Dir::NORMAL.as_vec3(); 4],
+    );
+
+    // stretch the texture coordinates, which run from zero to the size of the face (in either
+    // order once rotated), over the requested region of the texture
+    if let Some(uv) = micro_voxel_box.uvs[Dir::FACE_INDEX.as_usize()] {
+        let (uv_min, uv_max) = uv.bounds();
+        let face_vertices = vertices.len() - 4..;
+        let uv_size = vertices[face_vertices.clone()]
+            .iter()
+            .fold(Vec2::ZERO, |uv_size, vertex| uv_size.max(Vec2::from(vertex.uv)));
+
+        for vertex in &mut vertices[face_vertices] {
+            let uv = uv_min + Vec2::from(vertex.uv) / uv_size * (uv_max - uv_min);
+            vertex.uv = uv.to_array();
+        }
+    }
+}
+
+/// Creates the mesh of a `Cross` model relative to the block's position: one quad along each
+/// diagonal of the block. The quads are drawn in the `TwoSided` layer, so they aren't culled from
+/// behind. The normals point up, so that both sides are lit like the ground the plant stands on
+fn mesh_cross(face: BlockFace) -> Vec<TerrainVertex> {
+    let diagonals = [
+        (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0)),
+        (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    ];
+
+    let mut vertices = Vec::new();
+
+    for (start, end) in diagonals {
+        // counterclockwise from the bottom left, as seen from the front
+        let corners = [start, end, end + Vec3::Y, start + Vec3::Y];
+        let uvs = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+
+        vertices.extend(corners.into_iter().zip(uvs).map(|(corner, uv)| {
+            TerrainVertex {
+                position: corner.to_array(),
+                uv,
+                texture: pack_texture(face.texture_index, face.tint_index),
+                ao: 1.0,
+                normal: pack_normal(Vec3::Y),
+                light: 0,
+            }
+        }));
+    }
+
+    vertices
+}
+
+/// Add the precomputed meshes of blocks with `MicroVoxels` or `Cross` models in the layer being
+/// meshed to the mesh, translated to the position of each block and lit by the light of the
+/// block's own cell. `MicroVoxels` models are in the opaque layer and `Cross` models in the
+/// two-sided layer
+fn add_precomputed_model_blocks(vertices: &mut Vec<TerrainVertex>, input: ChunkMeshInput) {
+    for (block_index, &block_id) in input.blocks.iter().enumerate() {
+        let mesh = &PRECOMPUTED_MODEL_MESHES[block_id.0 as usize];
+        if mesh.is_empty() || MeshLayer::of(&BLOCKS[block_id.0 as usize].model) != input.layer {
+            continue;
+        }
+
+        let pos = LocalBlockPosition::from_array_index(block_index).as_ivec3();
+        let (block_light, skylight) = (input.light)(pos);
+        let light = pack_light(block_light, skylight);
+
+        vertices.extend(mesh.iter().map(|vertex| TerrainVertex {
+            position: (Vec3::from(vertex.position) + pos.as_vec3()).to_array(),
+            light,
+            ..*vertex
+        }));
+    }
+}
+
+/// True if the face of the block at `pos` is hidden by a block of the same kind in front of it.
+/// Faces hidden by opaque blocks are already handled by tracking visibility between layers, so
+/// this only needs to handle cutout and translucent blocks which cull themselves.
+/// Blocks in neighbouring chunks are not considered
+fn is_hidden_by_same_block<Dir>(pos: UVec3, block_id: BlockId, blocks: &[BlockId]) -> bool
+where
+    Dir: FaceDir,
+{
+    let model = &BLOCKS[block_id.0 as usize].model;
+
+    (model.is_cutout() || model.is_translucent())
+        && model.culls_self()
+        && LocalBlockPosition::from(pos)
+            .try_add(Dir::NORMAL)
+            .is_some_and(|neighbour_pos| blocks[neighbour_pos.get_array_index()] == block_id)
 }
 
 /// Add a single axis-aligned face to the mesh
@@ -74,13 +421,28 @@ fn add_face<Dir>(
     vertices: &mut Vec<TerrainVertex>,
     origin: Vec3,
     size: Vec2,
-    texture_index: usize,
+    face: BlockFace,
     light_data: FaceLightData,
+    normals: [Vec3; 4],
 ) where
     Dir: FaceDir,
 {
     let vertex_offsets = Dir::vertices(size);
-    let uvs = [[0.0, size.y], [size.x, size.y], [size.x, 0.0], [0.0, 0.0]];
+
+    // rotating the texture by a quarter turn moves each corner of the texture to the next vertex
+    // and swaps the width and height of the texture coordinates for merged quads
+    let quarter_turns = face.uv_rotation.quarter_turns();
+    let uv_size = match face.uv_rotation {
+        UvRotation::None | UvRotation::Clockwise180 => size,
+        UvRotation::Clockwise90 | UvRotation::Clockwise270 => Vec2::new(size.y, size.x),
+    };
+    let uv_corners = [
+        [0.0, uv_size.y],
+        [uv_size.x, uv_size.y],
+        [uv_size.x, 0.0],
+        [0.0, 0.0],
+    ];
+    let uvs: [[f32; 2]; 4] = std::array::from_fn(|i| uv_corners[(i + quarter_turns) & 3]);
 
     // improve the anisotropy in how the lighting is interpolated along the quad when divided into
     // two triangles by flipping the orientation of the triangles based on the brightness of the
@@ -101,12 +463,61 @@ fn add_face<Dir>(
             .map(|i| TerrainVertex {
                 position: (origin + vertex_offsets[i]).to_array(),
                 uv: uvs[i],
-                texture_index: texture_index as u32,
-                shading: Dir::SHADING * light_data.0[Dir::LIGHT_INDICES[i]],
+                texture: pack_texture(face.texture_index, face.tint_index),
+                ao: light_data.ao[Dir::LIGHT_INDICES[i]],
+                normal: pack_normal(normals[i]),
+                light: pack_light(light_data.block_light, light_data.skylight),
             }),
     );
 }
 
+/// Compute the normal for each vertex of a face, in the same order as `FaceDir::vertices`
+/// `origin` is the position of the block with the smallest coordinates that the face covers
+fn compute_vertex_normals<Dir>(origin: UVec3, size: Vec2, input: &ChunkMeshInput) -> [Vec3; 4]
+where
+    Dir: FaceDir,
+{
+    let normal = Dir::NORMAL.as_vec3();
+
+    match input.options.normal_mode {
+        NormalMode::Flat => [normal; 4],
+        NormalMode::Smooth => {
+            let axes = [Dir::TANGENT.abs(), Dir::BITANGENT.abs()];
+
+            Dir::vertices(size).map(|vertex_offset| {
+                // find the block at this corner of the face and the outwards direction along
+                // each axis parallel to the face
+                let mut corner_block = origin.as_ivec3();
+                let mut outwards = [IVec3::ZERO; 2];
+                for (axis_index, axis) in axes.into_iter().enumerate() {
+                    let offset_along_axis = vertex_offset.dot(axis.as_vec3()) as i32;
+                    if offset_along_axis == 0 {
+                        outwards[axis_index] = -axis;
+                    } else {
+                        outwards[axis_index] = axis;
+                        corner_block += axis * (offset_along_axis - 1);
+                    }
+                }
+
+                // bend the normal towards each exposed edge meeting at this corner
+                let corner_block = LocalBlockPosition::from(corner_block.as_uvec3());
+                outwards
+                    .into_iter()
+                    .filter(|&outwards| {
+                        corner_block
+                            .try_add(outwards)
+                            .is_some_and(|neighbour_pos| {
+                                let neighbour_id = input.blocks[neighbour_pos.get_array_index()];
+                                !BLOCKS[neighbour_id.0 as usize].is_opaque()
+                            })
+                    })
+                    .fold(normal, |normal, outwards| normal + outwards.as_vec3())
+                    .normalize()
+            })
+        }
+    }
+}
+
 /// Add all visible faces for the given face direction
 fn add_visible_faces<Dir>(vertices: &mut Vec<TerrainVertex>, input: ChunkMeshInput)
 where
@@ -135,36 +546,60 @@ where
                 let block_id = input.blocks[uvec3_to_chunk_index(pos_in_chunk)];
                 let block_model = &BLOCKS[block_id.0 as usize].model;
 
-                let face = block_model.face(Dir::FACE_INDEX);
+                let face = input
+                    .layer
+                    .face(block_model, Dir::FACE_INDEX, pos_in_chunk);
                 if let Some(face) = face {
-                    if visible {
+                    if visible
+                        && !is_hidden_by_same_block::<Dir>(pos_in_chunk, block_id, input.blocks)
+                    {
                         let light_data = interpolate_light_for_face::<Dir>(
                             LocalBlockPosition::from(pos_in_chunk),
-                            input.blocks,
+                            input,
                         );
 
                         add_face::<Dir>(
                             vertices,
-                            pos_in_chunk.as_vec3() + input.translation,
+                            pos_in_chunk.as_vec3(),
                             Vec2::ONE,
-                            face.texture_index,
+                            face,
                             light_data,
+                            compute_vertex_normals::<Dir>(pos_in_chunk, Vec2::ONE, &input),
                         );
                     }
                 }
 
-                visible = block_model
-                    .face(Dir::OPPOSITE_FACE_INDEX)
-                    .is_none();
+                visible = !block_model.hides_adjacent_faces(Dir::OPPOSITE_FACE_INDEX);
             }
         }
     }
 }
 
-/// Greedily merge visible faces with the given direction and add them to the mesh
-fn add_greedy_merged_faces<Dir>(vertices: &mut Vec<TerrainVertex>, input: ChunkMeshInput)
-where
+/// Faces in one layer that the merge policy allows to be merged with each other. The bucket
+/// stores which cells of the layer contain such a face as one bit mask per row, so that merging
+/// only needs to consider geometry
+struct MergeBucket {
+    /// Face and light of the first face added to the bucket, used for every quad covering it
+    face: BlockFace,
+    light_data: FaceLightData,
+    /// One row per V coordinate, with bit U set if the cell has a face in this bucket
+    rows: [u32; CHUNK_SIZE],
+}
+
+/// Light of a face compared by its bits, so that it can be hashed
+type LightKey = ([u32; 4], EmittedLight, Skylight);
+
+/// Greedily merge visible faces with the given direction and add them to the mesh.
+/// Instead of comparing pairs of faces, the faces of each layer are first sorted into buckets of
+/// mutually compatible faces by their `MergePolicy` key and light, then each bucket is covered
+/// with rectangles using bit operations
+fn add_greedy_merged_faces<Dir, P>(
+    vertices: &mut Vec<TerrainVertex>,
+    input: ChunkMeshInput,
+    merge_policy: &P,
+) where
     Dir: FaceDir,
+    P: MergePolicy,
 {
     // references:
     // - https://eddieabbondanz.io/post/voxel/greedy-mesh/
@@ -174,6 +609,9 @@ where
     //   U is the direction of the first texture coordinate
     //   V is the direction of the second texture coordinate
 
+    // each row of a bucket is a u32 with one bit per block
+    const _: () = assert!(CHUNK_SIZE <= 32);
+
     // this will track whether each face in the next layer is visible
     // a face is visible if the block in the previous layer had no face in
     // the opposite direction
@@ -184,299 +622,215 @@ where
             [true; CHUNK_SIZE_SQUARED]
         };
 
-    // iterate over each layer of faces we will create
+    let merges_across_light = merge_policy.merges_across_light();
+
+    let mut buckets: Vec<MergeBucket> = Vec::new();
+    let mut bucket_indices: FxHashMap<(P::Key, Option<LightKey>), usize> = FxHashMap::default();
+    // origin and size in the layer, face and light of each quad in the layer
+    let mut quads: Vec<(UVec2, UVec2, BlockFace, FaceLightData)> = Vec::new();
+
+    // iterate over each layer of faces we will create, moving backwards through the chunk with
+    // respect to the face direction
     for layer_index in 0..CHUNK_SIZE_U32 {
-        // position of this layer, moving backwards through the chunk with respect to the face
-        // direction
         let layer_pos = if Dir::NEGATIVE {
             layer_index
         } else {
             (CHUNK_SIZE_U32 - 1) - layer_index
         };
 
-        // this will track which faces have already been merged with another
-        // already merged faces can safely be ignored
-        let mut already_merged = [false; CHUNK_SIZE_SQUARED];
+        buckets.clear();
+        bucket_indices.clear();
 
-        // this is used to cache the interpolated light data for faces, to avoid computing it twice
-        // for the same face
-        let mut interpolated_light_cache = [None; CHUNK_SIZE_SQUARED];
+        // sort the visible faces of the layer into buckets.
+        // NB: stepping along U is strided in the block array for X and Y faces, but reading from
+        // a copy in layer order was slower, as the whole array stays in the cache anyway
+        for v in 0..CHUNK_SIZE_U32 {
+            for u in 0..CHUNK_SIZE_U32 {
+                let index_in_layer = (v * CHUNK_SIZE_U32 + u) as usize;
+                let pos = Dir::rotate_uvec3(UVec3::new(u, v, layer_pos));
 
-        // iterate over each block in the layer
-        for original_v in 0..CHUNK_SIZE_U32 {
-            for original_u in 0..CHUNK_SIZE_U32 {
-                // index of this block in the current layer
-                let original_index = (original_v * CHUNK_SIZE_U32 + original_u) as usize;
-
-                // skip if already merged
-                if already_merged[original_index] {
-                    continue;
-                }
-
-                // position of this block in the chunk
-                let original_pos = Dir::rotate_uvec3(UVec3::new(original_u, original_v, layer_pos));
-
-                let original_id = input.blocks[uvec3_to_chunk_index(original_pos) as usize];
-                let original_model = &BLOCKS[original_id.0 as usize].model;
-                let original_face = original_model.face(Dir::FACE_INDEX);
-                let original_visible = visible[original_index];
-
-                let original_light_data =
-                    if let Some(cached_light_data) = interpolated_light_cache[original_index] {
-                        cached_light_data
-                    } else {
-                        interpolate_light_for_face::<Dir>(
-                            LocalBlockPosition::from(original_pos),
-                            input.blocks,
-                        )
-                        // no need to insert it into the cache because this face will never be
-                        // considered as a merge candidate
-                    };
+                let block_id = input.blocks[uvec3_to_chunk_index(pos)];
+                let block = &BLOCKS[block_id.0 as usize];
+                let is_visible = visible[index_in_layer]
+                    && !is_hidden_by_same_block::<Dir>(pos, block_id, input.blocks);
 
                 // update `visible` for the next layer
-                visible[original_index] = original_model
-                    .face(Dir::OPPOSITE_FACE_INDEX)
-                    .is_none();
-
-                // skip if there is no face or the face is invisible
-                if original_face.is_none() || !original_visible {
+                visible[index_in_layer] = !block
+                    .model
+                    .hides_adjacent_faces(Dir::OPPOSITE_FACE_INDEX);
+
+                let Some(face) = input
+                    .layer
+                    .face(&block.model, Dir::FACE_INDEX, pos)
+                    .filter(|_| is_visible)
+                else {
                     continue;
-                }
-                let original_face = original_face.unwrap();
-
-                // march to see how many faces can be merged in the U direction
-                let mut face_size = UVec2::ONE;
-                for merge_candidate_u in (original_u + 1)..CHUNK_SIZE_U32 {
-                    let (can_merge, next_visible) = consider_merge_candidate::<Dir>(
-                        input.blocks,
-                        &visible,
-                        &mut interpolated_light_cache,
-                        layer_pos,
-                        original_face,
-                        original_light_data,
-                        merge_candidate_u,
-                        original_v,
-                    );
-
-                    // stop counting when we can't merge any more faces
-                    if !can_merge {
-                        break;
-                    }
-
-                    let merged_index_in_layer =
-                        (CHUNK_SIZE_U32 * original_v + merge_candidate_u) as usize;
+                };
 
-                    // grow the face
-                    face_size.x += 1;
-
-                    // mark that this face is already merged
-                    already_merged[merged_index_in_layer] = true;
-
-                    // update `visible` for the same block in the next layer
-                    // (this would not otherwise occur)
-                    visible[merged_index_in_layer] = next_visible;
-                }
-
-                // march to see how many faces can be merged in the V direction
-                'v: for merge_candidate_v in (original_v + 1)..CHUNK_SIZE_U32 {
-                    // bit flags for whether the block adjacent to a block being considered for
-                    // merging will be visible
-                    // this avoids having to check the model again once it has been decided
-                    // the layers can be merged
-                    let mut visibility_flags: u64 = 0;
-
-                    // see if we can merge the next layer down by checking all blocks on this
-                    // layer in the U direction
-                    for merge_candidate_u in original_u..(original_u + face_size.x) {
-                        let (can_merge, next_visible) = consider_merge_candidate::<Dir>(
-                            input.blocks,
-                            &visible,
-                            &mut interpolated_light_cache,
-                            layer_pos,
-                            original_face,
-                            original_light_data,
-                            merge_candidate_u,
-                            merge_candidate_v,
-                        );
-
-                        // stop counting when we can't merge any more faces
-                        if !can_merge {
-                            break 'v;
-                        }
-
-                        // update visibility flags
-                        visibility_flags |= (next_visible as u64) << merge_candidate_u;
-                    }
-
-                    // merge layers
-                    face_size.y += 1;
-
-                    // mark all faces in the layer as merged
-                    for merged_x in original_u..(original_u + face_size.x) {
-                        let merged_index_in_layer =
-                            (merge_candidate_v * CHUNK_SIZE_U32 + merged_x) as usize;
+                // blocks flagged `never_merge` keep their own quads regardless of the policy
+                let merge_key = if block.never_merge {
+                    None
+                } else {
+                    merge_policy.merge_key(&MergeFace {
+                        block_id,
+                        model: &block.model,
+                        face,
+                    })
+                };
+
+                let interpolate_light =
+                    || interpolate_light_for_face::<Dir>(LocalBlockPosition::from(pos), input);
+
+                let Some(merge_key) = merge_key else {
+                    quads.push((UVec2::new(u, v), UVec2::ONE, face, interpolate_light()));
+                    continue;
+                };
 
-                        already_merged[merged_index_in_layer] = true;
+                // the light is only needed for the key if faces with different light aren't
+                // merged, and otherwise only for the first face of each bucket
+                let (light_data, light_key) = if merges_across_light {
+                    (None, None)
+                } else {
+                    let light_data = interpolate_light();
+                    let light_key = (
+                        light_data.ao.map(f32::to_bits),
+                        light_data.block_light,
+                        light_data.skylight,
+                    );
+                    (Some(light_data), Some(light_key))
+                };
+
+                let bucket_index = *bucket_indices
+                    .entry((merge_key, light_key))
+                    .or_insert_with(|| {
+                        buckets.push(MergeBucket {
+                            face,
+                            light_data: light_data.unwrap_or_else(interpolate_light),
+                            rows: [0; CHUNK_SIZE],
+                        });
+                        buckets.len() - 1
+                    });
+
+                buckets[bucket_index].rows[v as usize] |= 1 << u;
+            }
+        }
 
-                        // update `visible` for the same block in the next layer
-                        // visibility flags already computed
-                        // (this would not otherwise occur)
-                        visible[merged_index_in_layer] = (visibility_flags & (1 << merged_x)) != 0;
+        // cover each bucket with rectangles, growing each in the U direction and then the V
+        // direction
+        for bucket in &mut buckets {
+            for v in 0..CHUNK_SIZE {
+                while bucket.rows[v] != 0 {
+                    let u = bucket.rows[v].trailing_zeros();
+                    let width = (bucket.rows[v] >> u).trailing_ones();
+                    let run = (u32::MAX >> (32 - width)) << u;
+
+                    let mut height = 1;
+                    while v + height < CHUNK_SIZE && bucket.rows[v + height] & run == run {
+                        bucket.rows[v + height] &= !run;
+                        height += 1;
                     }
+                    bucket.rows[v] &= !run;
+
+                    quads.push((
+                        UVec2::new(u, v as u32),
+                        UVec2::new(width, height as u32),
+                        bucket.face,
+                        bucket.light_data,
+                    ));
                 }
-
-                // create the merged face
-                add_face::<Dir>(
-                    vertices,
-                    original_pos.as_vec3() + input.translation,
-                    face_size.as_vec2(),
-                    original_face.texture_index,
-                    original_light_data,
-                );
             }
         }
-    }
-}
-
-/// Evaluate whether the original face can be merged with the face with coordinates
-/// `merge_candidate_u` and `merge_candidate_v` in the layer with position `layer_pos`
-/// returns two booleans: whether the face can be merged, and whether the block with the
-/// same U and V coordinates in the following layer is visible
-fn consider_merge_candidate<Dir>(
-    blocks: &[BlockId],
-    visible: &[bool; CHUNK_SIZE_SQUARED],
-    interpolated_light_cache: &mut [Option<FaceLightData>; CHUNK_SIZE_SQUARED],
-    layer_pos: u32,
-    original_face: BlockFace,
-    original_light_data: FaceLightData,
-    merge_candidate_u: u32,
-    merge_candidate_v: u32,
-) -> (bool, bool)
-where
-    Dir: FaceDir,
-{
-    let merge_candidate_pos = UVec3::new(merge_candidate_u, merge_candidate_v, layer_pos);
-    let merge_candidate_pos = Dir::rotate_uvec3(merge_candidate_pos);
-
-    let merge_candidate_index = (CHUNK_SIZE_U32 * merge_candidate_v + merge_candidate_u) as usize;
-
-    let merge_candidate_id = blocks[uvec3_to_chunk_index(merge_candidate_pos) as usize];
-    let merge_candidate_model = &BLOCKS[merge_candidate_id.0 as usize].model;
-    let merge_candidate_face = merge_candidate_model.face(Dir::FACE_INDEX);
-    let merge_candidate_visible = visible[merge_candidate_index];
 
-    let next_visible = merge_candidate_model
-        .face(Dir::OPPOSITE_FACE_INDEX)
-        .is_none();
+        // add the quads in a fixed order, so that the mesh doesn't depend on the order of the
+        // buckets
+        quads.sort_unstable_by_key(|(origin, ..)| (origin.y, origin.x));
 
-    let can_merge = can_merge_faces::<Dir>(Some(original_face), merge_candidate_face)
-        && merge_candidate_visible;
+        for (origin, size, face, light_data) in quads.drain(..) {
+            let origin = Dir::rotate_uvec3(origin.extend(layer_pos));
 
-    if !can_merge {
-        return (false, next_visible);
-    }
-
-    let merge_candidate_light_data =
-        if let Some(cached_light_data) = interpolated_light_cache[merge_candidate_index] {
-            cached_light_data
-        } else {
-            let interpolated = interpolate_light_for_face::<Dir>(
-                LocalBlockPosition::from(merge_candidate_pos),
-                blocks,
+            add_face::<Dir>(
+                vertices,
+                origin.as_vec3(),
+                size.as_vec2(),
+                face,
+                light_data,
+                compute_vertex_normals::<Dir>(origin, size.as_vec2(), &input),
             );
-            interpolated_light_cache[merge_candidate_index] = Some(interpolated);
-            interpolated
-        };
-    let light_matches = merge_candidate_light_data == original_light_data;
-
-    (light_matches, next_visible)
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct FaceLightData([f32; 4]);
+struct FaceLightData {
+    /// Ambient occlusion at each corner of the face, from 0 (fully occluded) to 1
+    ao: [f32; 4],
+    /// Light from emitting blocks in the cell in front of the face
+    block_light: EmittedLight,
+    /// Skylight in the cell in front of the face
+    skylight: Skylight,
+}
 
-/// future: Interpolate the light values for each vertex of the given face
-/// now: just interpolate whether each block is not air, for AO
-/// once there is floodfill lighting, this will interpolate that instead
+impl FaceLightData {
+    /// No ambient occlusion, no light from emitting blocks and full skylight
+    const UNOCCLUDED: Self = Self {
+        ao: [1.0; 4],
+        block_light: EmittedLight::DARK,
+        skylight: Skylight::FULL,
+    };
+}
+
+/// Interpolate whether the blocks around each vertex of the given face are air or passable (e.g.
+/// plants) for ambient occlusion, and read the light of the block in front of the face, which
+/// lights the whole face
 fn interpolate_light_for_face<Dir>(
     block_pos: LocalBlockPosition,
-    blocks: &[BlockId],
+    input: ChunkMeshInput,
 ) -> FaceLightData
 where
     Dir: FaceDir,
 {
-    fn sample_block_at(block_pos: Option<LocalBlockPosition>, blocks: &[BlockId]) -> f32 {
-        if let Some(block_pos) = block_pos {
-            let is_air = blocks[block_pos.get_array_index()] == BlockId(0);
-            if is_air {
-                0.25
-            } else {
-                0.0
-            }
-        } else {
+    let sample_block_at = |offset: IVec3| {
+        let pos = block_pos.as_uvec3().as_ivec3() + offset;
+
+        // blocks outside the chunk are read from its neighbours
+        let block_id = match block_pos.try_add(offset) {
+            Some(pos_in_chunk) => input.blocks[pos_in_chunk.get_array_index()],
+            None => (input.neighbour_block)(pos),
+        };
+
+        let block = &BLOCKS[block_id.0 as usize];
+        if !block.is_solid() || block.model.is_passable() {
             0.25
+        } else {
+            0.0
         }
-    }
-
-    // read the 9x9 neighbourhood of blocks in front of the face
-    #[rustfmt::skip]
-    let samples = [
-        [
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL - Dir::TANGENT - Dir::BITANGENT),
-                blocks,
-            ),
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL - Dir::TANGENT),
-                blocks
-            ),
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL - Dir::TANGENT + Dir::BITANGENT),
-                blocks,
-            ),
-        ],
-        [
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL - Dir::BITANGENT),
-                blocks,
-            ),
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL),
-                blocks
-            ),
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL + Dir::BITANGENT),
-                blocks,
-            ),
+    };
+
+    // read the 3x3 neighbourhood of blocks in front of the face
+    let samples = [-1, 0, 1].map(|tangent| {
+        [-1, 0, 1].map(|bitangent| {
+            sample_block_at(Dir::NORMAL + tangent * Dir::TANGENT + bitangent * Dir::BITANGENT)
+        })
+    });
+
+    let (block_light, skylight) = (input.light)(block_pos.as_ivec3() + Dir::NORMAL);
+
+    FaceLightData {
+        ao: [
+            samples[0][0] + samples[0][1] + samples[1][0] + samples[1][1],
+            samples[0][1] + samples[0][2] + samples[1][1] + samples[1][2],
+            samples[1][0] + samples[1][1] + samples[2][0] + samples[2][1],
+            samples[1][1] + samples[1][2] + samples[2][1] + samples[2][2],
         ],
-        [
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL + Dir::TANGENT - Dir::BITANGENT),
-                blocks,
-            ),
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL + Dir::TANGENT),
-                blocks
-            ),
-            sample_block_at(
-                block_pos.try_add(Dir::NORMAL + Dir::TANGENT + Dir::BITANGENT),
-                blocks,
-            ),
-        ],
-    ];
-
-    FaceLightData([
-        samples[0][0] + samples[0][1] + samples[1][0] + samples[1][1],
-        samples[0][1] + samples[0][2] + samples[1][1] + samples[1][2],
-        samples[1][0] + samples[1][1] + samples[2][0] + samples[2][1],
-        samples[1][1] + samples[1][2] + samples[2][1] + samples[2][2],
-    ])
+        block_light,
+        skylight,
+    }
 }
 
 /// Decide whether to generate a flipped quad based on the light data, in order to improve the
 /// anisotropy artifact caused by the division of the quad into two triangles
 fn should_flip_quad(light_data: &FaceLightData) -> bool {
-    light_data.0[0] + light_data.0[3] <= light_data.0[1] + light_data.0[2]
+    light_data.ao[0] + light_data.ao[3] <= light_data.ao[1] + light_data.ao[2]
 }
 
 /// Generate indices for the meshes returned by `mesh_culled` and `mesh_greedy`
@@ -499,6 +853,40 @@ pub fn generate_indices(vertex_count: usize) -> Vec<u32> {
     indices
 }
 
+/// Generate indices for drawing the quads in `vertices` back-to-front as seen from `camera_pos`,
+/// given in the same space as the vertex positions, so that translucent faces within one mesh
+/// blend in the right order.
+/// Only the indices are reordered, so the vertex buffer can be left untouched. The sort is
+/// approximate: quads are ordered by the distance to their centres, not per fragment, so large or
+/// intersecting quads can still blend incorrectly. Because it has to be redone whenever the camera
+/// moves, it should be limited to the few translucent meshes closest to the camera
+pub fn generate_sorted_indices(vertices: &[TerrainVertex], camera_pos: Vec3) -> Vec<u32> {
+    const INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+    let quad_distances = vertices
+        .chunks_exact(4)
+        .map(|quad| {
+            let center = quad
+                .iter()
+                .map(|vertex| Vec3::from(vertex.position))
+                .sum::<Vec3>()
+                * 0.25;
+
+            center.distance_squared(camera_pos)
+        })
+        .collect::<Vec<_>>();
+
+    let mut quad_order = (0..quad_distances.len() as u32).collect::<Vec<_>>();
+    quad_order.sort_by(|&a, &b| {
+        quad_distances[b as usize].total_cmp(&quad_distances[a as usize])
+    });
+
+    quad_order
+        .into_iter()
+        .flat_map(|quad_index| INDICES.map(|index| index + quad_index * 4))
+        .collect()
+}
+
 fn uvec3_to_chunk_index(pos: UVec3) -> usize {
     ((CHUNK_SIZE_U32 * CHUNK_SIZE_U32) * pos.y + CHUNK_SIZE_U32 * pos.z + pos.x) as usize
 }
@@ -528,9 +916,6 @@ mod face_dir {
         /// Whether this face direction points away from its axis
         const NEGATIVE: bool;
 
-        /// Hardcoded directional shading for this face
-        const SHADING: f32;
-
         /// Which of the interpolated light values corresponds to each vertex
         const LIGHT_INDICES: [usize; 4];
 
@@ -567,7 +952,6 @@ mod face_dir {
         const FACE_INDEX: FaceIndex = FaceIndex::POS_X;
         const OPPOSITE_FACE_INDEX: FaceIndex = FaceIndex::NEG_X;
         const NEGATIVE: bool = false;
-        const SHADING: f32 = 0.7;
         const LIGHT_INDICES: [usize; 4] = [2, 0, 1, 3];
 
         fn vertices(size: Vec2) -> [Vec3; 4] {
@@ -598,7 +982,6 @@ mod face_dir {
         const FACE_INDEX: FaceIndex = FaceIndex::POS_Y;
         const OPPOSITE_FACE_INDEX: FaceIndex = FaceIndex::NEG_Y;
         const NEGATIVE: bool = false;
-        const SHADING: f32 = 1.0;
         const LIGHT_INDICES: [usize; 4] = [0, 2, 3, 1];
 
         fn vertices(size: Vec2) -> [Vec3; 4] {
@@ -629,7 +1012,6 @@ mod face_dir {
         const FACE_INDEX: FaceIndex = FaceIndex::POS_Z;
         const OPPOSITE_FACE_INDEX: FaceIndex = FaceIndex::NEG_Z;
         const NEGATIVE: bool = false;
-        const SHADING: f32 = 0.8;
         const LIGHT_INDICES: [usize; 4] = [0, 2, 3, 1];
 
         fn vertices(size: Vec2) -> [Vec3; 4] {
@@ -660,7 +1042,6 @@ mod face_dir {
         const FACE_INDEX: FaceIndex = FaceIndex::NEG_X;
         const OPPOSITE_FACE_INDEX: FaceIndex = FaceIndex::POS_X;
         const NEGATIVE: bool = true;
-        const SHADING: f32 = 0.7;
         const LIGHT_INDICES: [usize; 4] = [3, 1, 0, 2];
 
         fn vertices(size: Vec2) -> [Vec3; 4] {
@@ -691,7 +1072,6 @@ mod face_dir {
         const FACE_INDEX: FaceIndex = FaceIndex::NEG_Y;
         const OPPOSITE_FACE_INDEX: FaceIndex = FaceIndex::POS_Y;
         const NEGATIVE: bool = true;
-        const SHADING: f32 = 0.5;
         const LIGHT_INDICES: [usize; 4] = [2, 0, 1, 3];
 
         fn vertices(size: Vec2) -> [Vec3; 4] {
@@ -722,7 +1102,6 @@ mod face_dir {
         const FACE_INDEX: FaceIndex = FaceIndex::NEG_Z;
         const OPPOSITE_FACE_INDEX: FaceIndex = FaceIndex::POS_Z;
         const NEGATIVE: bool = true;
-        const SHADING: f32 = 0.6;
         const LIGHT_INDICES: [usize; 4] = [1, 3, 2, 0];
 
         fn vertices(size: Vec2) -> [Vec3; 4] {
@@ -743,3 +1122,1180 @@ mod face_dir {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use glam::{IVec3, Mat4, UVec3, Vec2, Vec3, Vec3Swizzles};
+
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{
+        block::{
+            model_file::load_micro_voxel_model, texture::BLOCK_TEXTURES, BLOCK_AIR,
+            BLOCK_COAL_ORE, BLOCK_DIRT, BLOCK_FENCE_POST, BLOCK_GLASS, BLOCK_GRASS, BLOCK_LEAVES,
+            BLOCK_TALL_GRASS, BLOCK_WOOD_SLAB, BLOCK_WOOD_STAIRS, TINT_COUNT, TINT_GRASS,
+            TINT_NONE, TINT_PALETTE,
+        },
+        render::terrain::vertex::{unpack_normal, unpack_texture},
+        terrain::{chunk::CHUNK_SIZE_CUBED, lighting::ChunkLightSnapshot},
+    };
+
+    /// Build a block array for a chunk by evaluating `f` at each position in the chunk
+    fn blocks_from_fn(f: impl Fn(UVec3) -> BlockId) -> Vec<BlockId> {
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+
+        for y in 0..CHUNK_SIZE_U32 {
+            for z in 0..CHUNK_SIZE_U32 {
+                for x in 0..CHUNK_SIZE_U32 {
+                    let pos = UVec3::new(x, y, z);
+                    blocks[uvec3_to_chunk_index(pos)] = f(pos);
+                }
+            }
+        }
+
+        blocks
+    }
+
+    /// Build a block array where every block within `min..max` is dirt and all others are air
+    fn blocks_in_box(min: UVec3, max: UVec3) -> Vec<BlockId> {
+        blocks_from_fn(|pos| {
+            if pos.cmpge(min).all() && pos.cmplt(max).all() {
+                BLOCK_DIRT
+            } else {
+                BLOCK_AIR
+            }
+        })
+    }
+
+    /// Mesh the opaque layer of the blocks with both meshers, with no neighbouring chunks
+    /// Returns the culled and greedy vertices respectively
+    fn mesh_both(blocks: &[BlockId]) -> (Vec<TerrainVertex>, Vec<TerrainVertex>) {
+        mesh_both_in_layer(blocks, MeshLayer::Opaque)
+    }
+
+    /// Mesh the given layer of the blocks with both meshers, with no neighbouring chunks
+    /// Returns the culled and greedy vertices respectively
+    fn mesh_both_in_layer(
+        blocks: &[BlockId],
+        layer: MeshLayer,
+    ) -> (Vec<TerrainVertex>, Vec<TerrainVertex>) {
+        let surrounding_sides = vec![None; 6];
+        let input = ChunkMeshInput {
+            blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer,
+            options: MeshingOptions::default(),
+        };
+
+        (mesh_culled(input), mesh_greedy(input))
+    }
+
+    fn quad_count(vertices: &[TerrainVertex]) -> usize {
+        assert_eq!(vertices.len() % 4, 0, "meshes should consist only of quads");
+        vertices.len() / 4
+    }
+
+    fn assert_greedy_not_worse(culled: &[TerrainVertex], greedy: &[TerrainVertex]) {
+        assert!(quad_count(greedy) <= quad_count(culled));
+        assert_eq!(
+            generate_indices(greedy.len()).len(),
+            quad_count(greedy) * 6
+        );
+        assert_eq!(
+            generate_indices(culled.len()).len(),
+            quad_count(culled) * 6
+        );
+    }
+
+    /// Signed area of the triangle after projecting it to normalized device coordinates, positive
+    /// if its vertices appear anticlockwise on screen. This is what the rasterizer uses to decide
+    /// which side of a triangle is visible
+    fn screen_space_signed_area(view_proj: Mat4, triangle: [Vec3; 3]) -> f32 {
+        let [a, b, c] = triangle.map(|pos| view_proj.project_point3(pos).truncate());
+        (b - a).perp_dot(c - a)
+    }
+
+    fn assert_face_dir_winding<Dir: FaceDir>() {
+        for size in [Vec2::ONE, Vec2::new(3.0, 2.0)] {
+            let vertices = Dir::vertices(size);
+
+            // check the triangles for both orientations of the quad used by `add_face`
+            for flipped in [false, true] {
+                let quad = [0, 1, 2, 3].map(|i| vertices[if flipped { (i + 1) & 3 } else { i }]);
+
+                for [a, b, c] in [[0, 1, 2], [2, 3, 0]] {
+                    let winding_normal = (quad[b] - quad[a]).cross(quad[c] - quad[a]);
+                    assert!(
+                        winding_normal.dot(Dir::NORMAL.as_vec3()) > 0.0,
+                        "face {:?} is wound clockwise when viewed from outside",
+                        Dir::FACE_INDEX
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn face_dir_vertices_wind_anticlockwise_from_outside() {
+        assert_eq!(FRONT_FACE, wgpu::FrontFace::Ccw);
+
+        assert_face_dir_winding::<PosX>();
+        assert_face_dir_winding::<PosY>();
+        assert_face_dir_winding::<PosZ>();
+        assert_face_dir_winding::<NegX>();
+        assert_face_dir_winding::<NegY>();
+        assert_face_dir_winding::<NegZ>();
+    }
+
+    #[test]
+    fn only_faces_towards_camera_survive_back_face_culling() {
+        let min = UVec3::new(3, 4, 5);
+        let blocks = blocks_in_box(min, min + UVec3::ONE);
+        let (culled, greedy) = mesh_both(&blocks);
+        let cube_center = min.as_vec3() + 0.5;
+
+        let projection = Mat4::perspective_rh(1.5, 1.0, 0.01, 100.0);
+
+        // look at the cube from just outside each face in turn, as the terrain pipeline would
+        for normal in crate::util::face::FACE_NORMALS.map(|normal| normal.as_vec3()) {
+            let up = if normal.y == 0.0 { Vec3::Y } else { Vec3::Z };
+            let eye = cube_center + normal * 4.0;
+            let view_proj = projection * Mat4::look_at_rh(eye, cube_center, up);
+
+            for vertices in [&culled, &greedy] {
+                let positions = vertices
+                    .iter()
+                    .map(|vertex| Vec3::from(vertex.position))
+                    .collect::<Vec<_>>();
+                let indices = generate_indices(positions.len());
+
+                for triangle in indices.chunks_exact(3) {
+                    let triangle = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+                    let centroid = (triangle[0] + triangle[1] + triangle[2]) / 3.0;
+                    let facing_camera = (centroid - cube_center).dot(normal) > 0.25;
+
+                    let area = screen_space_signed_area(view_proj, triangle);
+                    if facing_camera {
+                        assert!(area > 0.0, "visible face {normal} would be culled");
+                    } else {
+                        assert!(area < 0.0, "hidden face would be drawn when viewed from {normal}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_cube() {
+        let blocks = blocks_in_box(UVec3::new(3, 4, 5), UVec3::new(4, 5, 6));
+        let (culled, greedy) = mesh_both(&blocks);
+
+        assert_eq!(quad_count(&culled), 6);
+        assert_eq!(quad_count(&greedy), 6);
+        assert_greedy_not_worse(&culled, &greedy);
+
+        // every vertex should lie on one of the 8 corners of the cube
+        for vertices in [&culled, &greedy] {
+            let corners: HashSet<IVec3> = vertices
+                .iter()
+                .map(|vertex| Vec3::from(vertex.position).as_ivec3())
+                .collect();
+            let expected: HashSet<IVec3> = itertools::iproduct!(3..=4, 4..=5, 5..=6)
+                .map(|(x, y, z)| IVec3::new(x, y, z))
+                .collect();
+            assert_eq!(corners, expected);
+        }
+    }
+
+    #[test]
+    fn slab_2x2() {
+        let blocks = blocks_in_box(UVec3::new(1, 1, 1), UVec3::new(3, 2, 3));
+        let (culled, greedy) = mesh_both(&blocks);
+
+        // top and bottom: 4 faces each; sides: 2 faces each
+        assert_eq!(quad_count(&culled), 16);
+        // one merged quad per side
+        assert_eq!(quad_count(&greedy), 6);
+        assert_greedy_not_worse(&culled, &greedy);
+
+        // the merged top face should span the whole slab
+        let top_face = greedy
+            .chunks_exact(4)
+            .find(|quad| quad.iter().all(|vertex| vertex.position[1] == 2.0))
+            .expect("there should be a top face");
+        let min = top_face
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position))
+            .reduce(Vec3::min)
+            .unwrap();
+        let max = top_face
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position))
+            .reduce(Vec3::max)
+            .unwrap();
+        assert_eq!(min, Vec3::new(1.0, 2.0, 1.0));
+        assert_eq!(max, Vec3::new(3.0, 2.0, 3.0));
+    }
+
+    /// Total area of the quads in the mesh, in block faces
+    fn quad_area(vertices: &[TerrainVertex]) -> f32 {
+        vertices
+            .chunks_exact(4)
+            .map(|quad| {
+                // the UVs of each quad span its size in blocks
+                let max_uv = quad
+                    .iter()
+                    .fold(Vec2::ZERO, |max_uv, vertex| max_uv.max(Vec2::from(vertex.uv)));
+                max_uv.x * max_uv.y
+            })
+            .sum()
+    }
+
+    #[test]
+    fn greedy_merging_covers_same_area_as_culled_meshing() {
+        let surrounding_sides = vec![None; 6];
+        let palette = [BLOCK_AIR, BLOCK_DIRT, BLOCK_GRASS, BLOCK_LEAVES, BLOCK_COAL_ORE];
+
+        // xorshift, so that the random chunks are the same every run
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next_random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for seed in 0..8 {
+            // chunks range from mostly air to mostly solid
+            let air_fraction = seed as u64 % 4;
+            let blocks: Vec<BlockId> = (0..CHUNK_SIZE_CUBED)
+                .map(|_| {
+                    let random = next_random();
+                    if random % 4 < air_fraction {
+                        BLOCK_AIR
+                    } else {
+                        palette[(random >> 8) as usize % palette.len()]
+                    }
+                })
+                .collect();
+
+            let input = ChunkMeshInput {
+                blocks: &blocks,
+                surrounding_sides: &surrounding_sides,
+                neighbour_block: &|_| BLOCK_AIR,
+                light: &|_| ChunkLightSnapshot::UNLIT,
+                layer: MeshLayer::Opaque,
+                options: MeshingOptions::default(),
+            };
+
+            let culled = mesh_culled(input);
+            let greedy = mesh_greedy(input);
+
+            // every visible face is covered exactly once
+            assert_eq!(quad_area(&greedy), quad_count(&culled) as f32);
+            assert!(quad_count(&greedy) < quad_count(&culled));
+        }
+    }
+
+    /// Split each quad of the mesh into the unit block faces it covers, returned as the face
+    /// normal and the position of the block the face belongs to, sorted so that meshes covering
+    /// the same faces compare equal. Faces covered by more than one quad appear more than once
+    fn unit_faces(vertices: &[TerrainVertex]) -> Vec<(IVec3, IVec3)> {
+        vertices
+            .chunks_exact(4)
+            .flat_map(|quad| {
+                let normal = unpack_normal(quad[0].normal).round().as_ivec3();
+                let min = quad
+                    .iter()
+                    .fold(Vec3::MAX, |min, vertex| min.min(Vec3::from(vertex.position)));
+                let max = quad
+                    .iter()
+                    .fold(Vec3::MIN, |max, vertex| max.max(Vec3::from(vertex.position)));
+
+                // the quad lies on the face of the block on the negative side of the plane for
+                // positive normals, and on the positive side for negative normals
+                let min = min.round().as_ivec3() - normal.max(IVec3::ZERO);
+                let max = max.round().as_ivec3() - normal.max(IVec3::ZERO) + normal.abs();
+                let max = max.max(min + IVec3::ONE);
+
+                itertools::iproduct!(min.x..max.x, min.y..max.y, min.z..max.z)
+                    .map(move |(x, y, z)| (normal, IVec3::new(x, y, z)))
+            })
+            .sorted_by_key(|(normal, pos)| (normal.to_array(), pos.to_array()))
+            .collect()
+    }
+
+    /// Layers of opaque and cutout blocks with holes of different shapes, so that a face which is
+    /// merged in one layer decides whether the face behind it is visible in the next
+    fn layered_patterns() -> Vec<Vec<BlockId>> {
+        let patterns: [fn(UVec3) -> BlockId; 4] = [
+            // opaque layers with holes, every third layer
+            |pos| {
+                if pos.y % 3 == 0 && (pos.x * 7 + pos.z * 3) % 5 != 0 {
+                    BLOCK_DIRT
+                } else {
+                    BLOCK_AIR
+                }
+            },
+            // opaque layers alternating with layers of cutout blocks in a coarse checkerboard
+            |pos| match pos.y % 2 {
+                0 if (pos.x / 3 + pos.z / 2) % 3 != 0 => BLOCK_DIRT,
+                1 if (pos.x / 2 + pos.z / 2) % 2 == 0 => BLOCK_LEAVES,
+                _ => BLOCK_AIR,
+            },
+            // a stepped pyramid, so that merged rectangles shrink from layer to layer
+            |pos| {
+                let (dx, dz) = (pos.x.abs_diff(16), pos.z.abs_diff(11));
+                if dx.max(dz) + pos.y / 2 < 14 {
+                    BLOCK_DIRT
+                } else {
+                    BLOCK_AIR
+                }
+            },
+            // rows of differently textured blocks, which merge along U but not V
+            |pos| match (pos.y % 4, pos.z % 3) {
+                (0, 0) => BLOCK_DIRT,
+                (0, 1) => BLOCK_COAL_ORE,
+                (0, 2) | (1, 0) => BLOCK_GRASS,
+                _ => BLOCK_AIR,
+            },
+        ];
+
+        // each pattern is layered along every axis, to cover all face directions
+        patterns
+            .into_iter()
+            .flat_map(|pattern| {
+                [
+                    blocks_from_fn(pattern),
+                    blocks_from_fn(move |pos| pattern(pos.yzx())),
+                    blocks_from_fn(move |pos| pattern(pos.zxy())),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn greedy_meshes_cover_the_same_faces_as_culled_meshes() {
+        let surrounding_sides = vec![None; 6];
+
+        for blocks in layered_patterns() {
+            let input = ChunkMeshInput {
+                blocks: &blocks,
+                surrounding_sides: &surrounding_sides,
+                neighbour_block: &|_| BLOCK_AIR,
+                light: &|_| ChunkLightSnapshot::UNLIT,
+                layer: MeshLayer::Opaque,
+                options: MeshingOptions::default(),
+            };
+
+            // the culled mesher decides the visibility of each face on its own, so it is the
+            // reference
+            let expected = unit_faces(&mesh_culled(input));
+            assert!(!expected.is_empty());
+
+            let covered = unit_faces(&mesh_greedy(input));
+            assert!(
+                covered.iter().tuple_windows().all(|(a, b)| a != b),
+                "greedy quads overlap"
+            );
+            assert_eq!(covered, expected);
+        }
+    }
+
+    #[test]
+    fn checkerboard() {
+        let blocks = blocks_from_fn(|pos| {
+            if pos.cmplt(UVec3::splat(4)).all() && (pos.x + pos.y + pos.z) % 2 == 0 {
+                BLOCK_DIRT
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        // no two solid blocks share a face, so every face of every block is visible and no faces
+        // can be merged
+        assert_eq!(quad_count(&culled), 32 * 6);
+        assert_eq!(quad_count(&greedy), 32 * 6);
+        assert_greedy_not_worse(&culled, &greedy);
+    }
+
+    #[test]
+    fn solid_chunk() {
+        let blocks = vec![BLOCK_DIRT; CHUNK_SIZE_CUBED];
+        let (culled, greedy) = mesh_both(&blocks);
+
+        // only the faces on the chunk boundary are visible
+        assert_eq!(quad_count(&culled), 6 * CHUNK_SIZE_SQUARED);
+        // each side of the chunk is merged into one face
+        assert_eq!(quad_count(&greedy), 6);
+        assert_greedy_not_worse(&culled, &greedy);
+    }
+
+    #[test]
+    fn solid_chunk_with_solid_neighbours() {
+        let blocks = vec![BLOCK_DIRT; CHUNK_SIZE_CUBED];
+        let solid_side = ChunkSide {
+            faces: [false; CHUNK_SIZE_SQUARED].into(),
+        };
+        let surrounding_sides = vec![Some(solid_side); 6];
+        let input = ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer: MeshLayer::Opaque,
+            options: MeshingOptions::default(),
+        };
+
+        assert!(mesh_culled(input).is_empty());
+        assert!(mesh_greedy(input).is_empty());
+    }
+
+    #[test]
+    fn hollow_box() {
+        let blocks = blocks_from_fn(|pos| {
+            let outer = pos.cmpge(UVec3::splat(1)).all() && pos.cmplt(UVec3::splat(5)).all();
+            let inner = pos.cmpge(UVec3::splat(2)).all() && pos.cmplt(UVec3::splat(4)).all();
+            if outer && !inner {
+                BLOCK_DIRT
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        // 4x4 faces on each outer side and 2x2 faces on each inner side
+        assert_eq!(quad_count(&culled), 6 * 16 + 6 * 4);
+        // outer sides are merged; inner faces each have a different ambient occlusion so they
+        // cannot be merged
+        assert_eq!(quad_count(&greedy), 6 + 6 * 4);
+        assert_greedy_not_worse(&culled, &greedy);
+    }
+
+    #[test]
+    fn vertex_positions_are_chunk_local() {
+        let blocks = blocks_in_box(UVec3::ZERO, UVec3::ONE);
+        let (culled, greedy) = mesh_both(&blocks);
+
+        for vertices in [culled, greedy] {
+            for vertex in vertices {
+                let position = Vec3::from(vertex.position);
+                assert!(position.cmpge(Vec3::ZERO).all() && position.cmple(Vec3::ONE).all());
+            }
+        }
+    }
+
+    #[test]
+    fn ambient_occlusion_continues_across_chunk_boundaries() {
+        // a single block in the corner of the chunk, with walls on the neighbouring chunks on the
+        // -X and -Z sides
+        let blocks = blocks_in_box(UVec3::ZERO, UVec3::ONE);
+        let surrounding_sides = vec![None; 6];
+        let walls = |pos: IVec3| {
+            if pos.x < 0 || pos.z < 0 {
+                BLOCK_DIRT
+            } else {
+                BLOCK_AIR
+            }
+        };
+
+        // ambient occlusion at each corner of the top face of the block
+        let top_face_ao = |vertices: Vec<TerrainVertex>| -> FxHashMap<[i32; 2], f32> {
+            vertices
+                .iter()
+                .filter(|vertex| vertex.position[1] == 1.0 && unpack_normal(vertex.normal).y > 0.5)
+                .map(|vertex| {
+                    let position = Vec3::from(vertex.position).as_ivec3();
+                    ([position.x, position.z], vertex.ao)
+                })
+                .collect()
+        };
+
+        let no_walls = |_| BLOCK_AIR;
+        let cases: [(&(dyn Fn(IVec3) -> BlockId + Sync), bool); 2] =
+            [(&walls, true), (&no_walls, false)];
+
+        for (neighbour_block, with_walls) in cases {
+            let input = ChunkMeshInput {
+                blocks: &blocks,
+                surrounding_sides: &surrounding_sides,
+                neighbour_block,
+                light: &|_| ChunkLightSnapshot::UNLIT,
+                layer: MeshLayer::Opaque,
+                options: MeshingOptions::default(),
+            };
+
+            for vertices in [mesh_culled(input), mesh_greedy(input)] {
+                let ao = top_face_ao(vertices);
+                assert_eq!(ao.len(), 4);
+
+                if with_walls {
+                    // darkest in the corner between the walls, unoccluded away from them
+                    assert_eq!(ao[&[0, 0]], 0.25);
+                    assert_eq!(ao[&[1, 0]], 0.5);
+                    assert_eq!(ao[&[0, 1]], 0.5);
+                    assert_eq!(ao[&[1, 1]], 1.0);
+                } else {
+                    assert!(ao.values().all(|&ao| ao == 1.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_normals_decode_to_face_direction() {
+        let blocks = blocks_in_box(UVec3::new(3, 4, 5), UVec3::new(4, 5, 6));
+        let (culled, greedy) = mesh_both(&blocks);
+        let cube_center = Vec3::new(3.5, 4.5, 5.5);
+
+        for vertices in [&culled, &greedy] {
+            for quad in vertices.chunks_exact(4) {
+                // the face direction is the direction from the center of the cube to the center
+                // of the face
+                let face_center = quad
+                    .iter()
+                    .map(|vertex| Vec3::from(vertex.position))
+                    .sum::<Vec3>()
+                    / 4.0;
+                let face_dir = ((face_center - cube_center) * 2.0).round();
+
+                for vertex in quad {
+                    assert_eq!(unpack_normal(vertex.normal), face_dir);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pack_normal_round_trip() {
+        for normal in [
+            Vec3::X,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::ONE.normalize(),
+            Vec3::new(-1.0, 1.0, 0.0).normalize(),
+        ] {
+            let unpacked = unpack_normal(pack_normal(normal));
+            assert!(unpacked.abs_diff_eq(normal, 1.0 / 127.0));
+        }
+    }
+
+    #[test]
+    fn smooth_normals_point_out_of_corners() {
+        let blocks = blocks_in_box(UVec3::new(3, 4, 5), UVec3::new(4, 5, 6));
+        let surrounding_sides = vec![None; 6];
+        let input = ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer: MeshLayer::Opaque,
+            options: MeshingOptions {
+                normal_mode: NormalMode::Smooth,
+                ..Default::default()
+            },
+        };
+        let cube_center = Vec3::new(3.5, 4.5, 5.5);
+
+        for vertices in [mesh_culled(input), mesh_greedy(input)] {
+            for vertex in vertices {
+                // every edge of an isolated cube is exposed, so the normals should be the average
+                // of the three faces meeting at each corner
+                let expected = (Vec3::from(vertex.position) - cube_center).normalize();
+                assert!(unpack_normal(vertex.normal).abs_diff_eq(expected, 1.0 / 127.0));
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_normals_are_flat_inside_surfaces() {
+        let blocks = blocks_in_box(UVec3::new(1, 1, 1), UVec3::new(4, 2, 4));
+        let surrounding_sides = vec![None; 6];
+        let input = ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer: MeshLayer::Opaque,
+            options: MeshingOptions {
+                normal_mode: NormalMode::Smooth,
+                ..Default::default()
+            },
+        };
+
+        // the vertices in the middle of the top of the slab should point straight up
+        let vertices = mesh_culled(input);
+        let inner_vertices = vertices
+            .iter()
+            .filter(|vertex| {
+                let position = Vec3::from(vertex.position);
+                position.y == 2.0 && position.x > 1.0 && position.x < 4.0
+                    && position.z > 1.0
+                    && position.z < 4.0
+            })
+            .collect_vec();
+        assert!(!inner_vertices.is_empty());
+        for vertex in inner_vertices {
+            assert_eq!(unpack_normal(vertex.normal), Vec3::Y);
+        }
+    }
+
+    #[test]
+    fn cutout_faces_are_not_culled_by_air() {
+        let blocks = blocks_from_fn(|pos| {
+            if pos == UVec3::new(1, 1, 1) {
+                BLOCK_LEAVES
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        assert_eq!(quad_count(&culled), 6);
+        assert_eq!(quad_count(&greedy), 6);
+    }
+
+    #[test]
+    fn cutout_faces_cull_against_same_block() {
+        // two touching leaves blocks along x
+        let blocks = blocks_from_fn(|pos| {
+            if pos.y == 1 && pos.z == 1 && (1..3).contains(&pos.x) {
+                BLOCK_LEAVES
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        // the touching faces are hidden, and the remaining faces merge into a 2x1x1 box
+        assert_eq!(quad_count(&culled), 10);
+        assert_eq!(quad_count(&greedy), 6);
+    }
+
+    #[test]
+    fn cutout_faces_do_not_hide_or_merge_with_solid_faces() {
+        // leaves at x = 1, dirt at x = 2
+        let blocks = blocks_from_fn(|pos| match (pos.x, pos.y, pos.z) {
+            (1, 1, 1) => BLOCK_LEAVES,
+            (2, 1, 1) => BLOCK_DIRT,
+            _ => BLOCK_AIR,
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        // the dirt face behind the leaves is still visible, but the leaves face touching the
+        // opaque dirt is hidden
+        assert_eq!(quad_count(&culled), 11);
+        assert_eq!(quad_count(&greedy), 11);
+
+        let dirt_face_behind_leaves = culled
+            .chunks(4)
+            .any(|quad| {
+                quad.iter().all(|vertex| {
+                    vertex.position[0] == 2.0 && vertex.normal == pack_normal(Vec3::NEG_X)
+                })
+            });
+        assert!(dirt_face_behind_leaves);
+    }
+
+    #[test]
+    fn translucent_faces_are_meshed_in_their_own_layer() {
+        // two touching glass blocks at x = 1..3, dirt at x = 3
+        let blocks = blocks_from_fn(|pos| match (pos.x, pos.y, pos.z) {
+            (1 | 2, 1, 1) => BLOCK_GLASS,
+            (3, 1, 1) => BLOCK_DIRT,
+            _ => BLOCK_AIR,
+        });
+        let glass_texture_index = BLOCKS[BLOCK_GLASS.0 as usize]
+            .model
+            .face(FaceIndex::POS_X)
+            .unwrap()
+            .texture_index as u32;
+        let surrounding_sides = vec![None; 6];
+        let input = |layer| ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer,
+            options: MeshingOptions::default(),
+        };
+
+        let meshers: [fn(ChunkMeshInput) -> Vec<TerrainVertex>; 3] =
+            [mesh_culled, mesh_greedy, mesh_greedy_parallel];
+
+        for (mesher, translucent_quads) in meshers.into_iter().zip([9, 5, 5]) {
+            // the dirt face behind the glass is still drawn with the opaque geometry
+            let opaque = mesher(input(MeshLayer::Opaque));
+            assert_eq!(quad_count(&opaque), 6);
+            assert!(opaque
+                .iter()
+                .all(|vertex| unpack_texture(vertex.texture).0 != glass_texture_index));
+
+            // the glass faces between the two glass blocks and against the dirt are hidden
+            let translucent = mesher(input(MeshLayer::Translucent));
+            assert_eq!(quad_count(&translucent), translucent_quads);
+            assert!(translucent
+                .iter()
+                .all(|vertex| unpack_texture(vertex.texture).0 == glass_texture_index));
+        }
+    }
+
+    #[test]
+    fn tint_index_is_only_emitted_for_tintable_faces() {
+        // a grass block on top of a dirt block
+        let blocks = blocks_from_fn(|pos| match (pos.x, pos.y, pos.z) {
+            (1, 1, 1) => BLOCK_DIRT,
+            (1, 2, 1) => BLOCK_GRASS,
+            _ => BLOCK_AIR,
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        for vertices in [&culled, &greedy] {
+            let mut tinted_quads = 0;
+
+            for quad in vertices.chunks_exact(4) {
+                let is_grass_top = quad.iter().all(|vertex| vertex.position[1] == 3.0);
+                let expected_tint = if is_grass_top { TINT_GRASS } else { TINT_NONE };
+
+                for vertex in quad {
+                    assert_eq!(unpack_texture(vertex.texture).1, expected_tint as u32);
+                }
+
+                tinted_quads += usize::from(is_grass_top);
+            }
+
+            assert_eq!(tinted_quads, 1);
+        }
+
+        // the shader indexes a palette of `TINT_COUNT` entries
+        assert_eq!(TINT_PALETTE.len(), TINT_COUNT);
+        assert_eq!(TINT_PALETTE[TINT_NONE], [1.0; 4]);
+    }
+
+    #[test]
+    fn uv_rotation_moves_texture_corners() {
+        let size = Vec2::new(2.0, 1.0);
+        let expected_uvs = [
+            (UvRotation::None, [[0.0, 1.0], [2.0, 1.0], [2.0, 0.0], [0.0, 0.0]]),
+            (UvRotation::Clockwise90, [[1.0, 2.0], [1.0, 0.0], [0.0, 0.0], [0.0, 2.0]]),
+            (UvRotation::Clockwise180, [[2.0, 0.0], [0.0, 0.0], [0.0, 1.0], [2.0, 1.0]]),
+            (UvRotation::Clockwise270, [[0.0, 0.0], [0.0, 2.0], [1.0, 2.0], [1.0, 0.0]]),
+        ];
+
+        for (uv_rotation, expected_uvs) in expected_uvs {
+            let mut vertices = Vec::new();
+            add_face::<PosY>(
+                &mut vertices,
+                Vec3::ZERO,
+                size,
+                BlockFace::new(0).with_uv_rotation(uv_rotation),
+                // light for which the quad is not flipped, so vertices stay in `FaceDir` order
+                FaceLightData {
+                    ao: [1.0, 0.0, 0.0, 1.0],
+                    block_light: EmittedLight::DARK,
+                    skylight: Skylight::FULL,
+                },
+                [Vec3::Y; 4],
+            );
+
+            let uvs = vertices.iter().map(|vertex| vertex.uv).collect_vec();
+            assert_eq!(uvs, expected_uvs, "{uv_rotation:?}");
+        }
+    }
+
+    #[test]
+    fn differently_rotated_faces_are_not_merged() {
+        let model = &BLOCKS[BLOCK_DIRT.0 as usize].model;
+        let merge_face = |uv_rotation| MergeFace {
+            block_id: BLOCK_DIRT,
+            model,
+            face: BlockFace::new(0).with_uv_rotation(uv_rotation),
+        };
+
+        let merge_key = |uv_rotation| DefaultMergePolicy.merge_key(&merge_face(uv_rotation));
+        assert_eq!(merge_key(UvRotation::None), merge_key(UvRotation::None));
+        assert_ne!(merge_key(UvRotation::None), merge_key(UvRotation::Clockwise90));
+    }
+
+    #[test]
+    fn random_uv_rotation_depends_on_the_position() {
+        let face = BlockFace::new(0).with_random_uv_rotation();
+        let rotations = itertools::iproduct!(0..4, 0..4, 0..4)
+            .map(|(x, y, z)| face.at(UVec3::new(x, y, z)).uv_rotation)
+            .collect_vec();
+
+        // every rotation is used, and the same position always gets the same rotation
+        assert_eq!(rotations.iter().unique().count(), 4);
+        assert_eq!(face.at(UVec3::ONE), face.at(UVec3::ONE));
+
+        // faces without the flag keep their rotation
+        let face = BlockFace::new(0).with_uv_rotation(UvRotation::Clockwise90);
+        assert_eq!(face.at(UVec3::new(1, 2, 3)), face);
+    }
+
+    #[test]
+    fn micro_voxel_blocks_stay_within_their_cell() {
+        let block_pos = UVec3::new(3, 4, 5);
+        let blocks = blocks_from_fn(|pos| {
+            if pos == block_pos {
+                BLOCK_FENCE_POST
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let (culled, greedy) = mesh_both(&blocks);
+
+        for vertices in [&culled, &greedy] {
+            // a fence post is a single box
+            assert_eq!(quad_count(vertices), 6);
+
+            let positions = vertices
+                .iter()
+                .map(|vertex| Vec3::from(vertex.position))
+                .collect_vec();
+            let min = positions
+                .iter()
+                .copied()
+                .reduce(Vec3::min)
+                .unwrap();
+            let max = positions
+                .iter()
+                .copied()
+                .reduce(Vec3::max)
+                .unwrap();
+
+            let cell_min = block_pos.as_vec3();
+            assert!(min.cmpge(cell_min).all() && max.cmple(cell_min + Vec3::ONE).all());
+
+            // the mesh matches the model's bounding box, which is narrower than the cell
+            let (model_min, model_max) = BLOCKS[BLOCK_FENCE_POST.0 as usize]
+                .model
+                .bounding_box()
+                .unwrap();
+            assert_eq!((min, max), (cell_min + model_min, cell_min + model_max));
+            assert!(model_max.x - model_min.x < 1.0);
+
+            // every face points out of the box
+            let center = (min + max) / 2.0;
+            for quad in vertices.chunks_exact(4) {
+                let face_center = quad
+                    .iter()
+                    .map(|vertex| Vec3::from(vertex.position))
+                    .sum::<Vec3>()
+                    / 4.0;
+                let normal = unpack_normal(quad[0].normal);
+                assert!((face_center - center).dot(normal) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn partial_blocks_have_a_quad_per_box_face_and_do_not_hide_neighbours() {
+        let single_block = |block_id| {
+            blocks_from_fn(|pos| if pos == UVec3::ONE { block_id } else { BLOCK_AIR })
+        };
+
+        // one quad for each face of each box in the model file, whichever mesher is used
+        for (block_id, model_name, box_count) in [
+            (BLOCK_WOOD_SLAB, "wood_slab", 1),
+            (BLOCK_WOOD_STAIRS, "wood_stairs", 2),
+        ] {
+            let boxes = load_micro_voxel_model(model_name).unwrap();
+            assert_eq!(boxes.len(), box_count);
+            assert_eq!(mesh_micro_voxel_boxes(boxes).len(), 4 * 6 * box_count);
+
+            let (culled, greedy) = mesh_both(&single_block(block_id));
+            assert_eq!(culled.len(), 4 * 6 * box_count);
+            assert_eq!(greedy.len(), 4 * 6 * box_count);
+        }
+
+        // the sides of the slab show the bottom half of the texture, as set in the model file
+        let slab = load_micro_voxel_model("wood_slab").unwrap();
+        let side = &mesh_micro_voxel_boxes(slab)[..4];
+        let v_range = side
+            .iter()
+            .map(|vertex| vertex.uv[1])
+            .minmax()
+            .into_option();
+        assert_eq!(v_range, Some((0.5, 1.0)));
+
+        // a slab and stairs on a dirt floor, with a dirt block against the back of the stairs.
+        // The floor under the partial blocks and the side of the dirt block facing the stairs
+        // are still drawn, so only the floor under the dirt block is hidden
+        let blocks = blocks_from_fn(|pos| match pos.to_array() {
+            [_, 0, _] => BLOCK_DIRT,
+            [1, 1, 1] => BLOCK_WOOD_SLAB,
+            [3, 1, 1] => BLOCK_WOOD_STAIRS,
+            [3, 1, 2] => BLOCK_DIRT,
+            _ => BLOCK_AIR,
+        });
+        let floor = blocks_from_fn(|pos| if pos.y == 0 { BLOCK_DIRT } else { BLOCK_AIR });
+        let (culled, _) = mesh_both(&blocks);
+        let (floor_culled, _) = mesh_both(&floor);
+        assert_eq!(quad_count(&culled), quad_count(&floor_culled) + 6 + 12 + 5 - 1);
+    }
+
+    #[test]
+    fn cross_blocks_are_never_merged_or_culled() {
+        let single = blocks_from_fn(|pos| {
+            if pos == UVec3::ONE {
+                BLOCK_TALL_GRASS
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let (culled, greedy) = mesh_both_in_layer(&single, MeshLayer::TwoSided);
+
+        // two crossing quads, drawn from both sides by the two-sided pipeline
+        assert_eq!(culled.len(), 8);
+        assert_eq!(greedy.len(), 8);
+        let (opaque_culled, opaque_greedy) = mesh_both(&single);
+        assert!(opaque_culled.is_empty() && opaque_greedy.is_empty());
+        assert!(culled
+            .iter()
+            .all(|vertex| {
+                unpack_texture(vertex.texture).0 == BLOCK_TEXTURES.layer("tall_grass") as u32
+            }));
+
+        // a row of plants on a dirt floor: the plants aren't merged, and don't hide or shade the
+        // floor
+        let row = blocks_from_fn(|pos| match pos.to_array() {
+            [_, 0, _] => BLOCK_DIRT,
+            [0..=3, 1, 0] => BLOCK_TALL_GRASS,
+            _ => BLOCK_AIR,
+        });
+        let floor = blocks_from_fn(|pos| if pos.y == 0 { BLOCK_DIRT } else { BLOCK_AIR });
+        let (row_culled, row_greedy) = mesh_both(&row);
+        let (floor_culled, floor_greedy) = mesh_both(&floor);
+        assert_eq!(row_culled.len(), floor_culled.len());
+        assert_eq!(row_greedy.len(), floor_greedy.len());
+
+        let (row_culled, row_greedy) = mesh_both_in_layer(&row, MeshLayer::TwoSided);
+        assert_eq!(row_culled.len(), 4 * 8);
+        assert_eq!(row_greedy.len(), 4 * 8);
+    }
+
+    #[test]
+    fn custom_merge_policy() {
+        // a 4x1x4 floor with a single block on top of one corner, so that ambient occlusion
+        // varies across the top of the floor
+        let blocks = blocks_from_fn(|pos| {
+            let in_floor = pos.y == 0 && pos.x < 4 && pos.z < 4;
+            let on_floor = pos == UVec3::new(0, 1, 0);
+            if in_floor || on_floor {
+                BLOCK_DIRT
+            } else {
+                BLOCK_AIR
+            }
+        });
+        let surrounding_sides = vec![None; 6];
+        let input = ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer: MeshLayer::Opaque,
+            options: MeshingOptions::default(),
+        };
+
+        let default = mesh_greedy(input);
+
+        // never merging gives the same faces as the culled mesher
+        struct NeverMerge;
+        impl MergePolicy for NeverMerge {
+            type Key = ();
+
+            fn merge_key(&self, _face: &MergeFace) -> Option<()> {
+                None
+            }
+        }
+        let unmerged = mesh_greedy_with_policy(input, &NeverMerge);
+        assert_eq!(quad_count(&unmerged), quad_count(&mesh_culled(input)));
+
+        // ignoring light merges across the ambient occlusion boundary around the block on top
+        struct IgnoreLight;
+        impl MergePolicy for IgnoreLight {
+            type Key = BlockFace;
+
+            fn merge_key(&self, face: &MergeFace) -> Option<BlockFace> {
+                Some(face.face)
+            }
+
+            fn merges_across_light(&self) -> bool {
+                true
+            }
+        }
+        let merged_ignoring_light = mesh_greedy_with_policy(input, &IgnoreLight);
+        assert!(quad_count(&merged_ignoring_light) < quad_count(&default));
+
+        // the top of the floor is an L shape, which needs two quads
+        let top_quads = |vertices: &[TerrainVertex]| {
+            vertices
+                .chunks(4)
+                .filter(|quad| quad.iter().all(|vertex| vertex.position[1] == 1.0))
+                .count()
+        };
+        assert_eq!(top_quads(&merged_ignoring_light), 2);
+        assert!(top_quads(&default) > 2);
+    }
+
+    #[test]
+    fn never_merge_blocks_are_not_merged() {
+        // a row of 4 blocks along x
+        let row_of = |block_id: BlockId| {
+            blocks_from_fn(move |pos| {
+                if pos.x < 4 && pos.y == 0 && pos.z == 0 {
+                    block_id
+                } else {
+                    BLOCK_AIR
+                }
+            })
+        };
+        let surrounding_sides = vec![None; 6];
+        let mesh_row = |blocks: &[BlockId]| {
+            mesh_greedy(ChunkMeshInput {
+                blocks,
+                surrounding_sides: &surrounding_sides,
+                neighbour_block: &|_| BLOCK_AIR,
+                light: &|_| ChunkLightSnapshot::UNLIT,
+                layer: MeshLayer::Opaque,
+                options: MeshingOptions::default(),
+            })
+        };
+
+        // the top of a row of dirt is merged into a single strip
+        let top_quads = |vertices: &[TerrainVertex]| {
+            vertices
+                .chunks(4)
+                .filter(|quad| quad.iter().all(|vertex| vertex.position[1] == 1.0))
+                .count()
+        };
+        assert_eq!(top_quads(&mesh_row(&row_of(BLOCK_DIRT))), 1);
+
+        // coal ore is never merged, so each block has its own quad
+        let ore_blocks = row_of(BLOCK_COAL_ORE);
+        assert!(BLOCKS[BLOCK_COAL_ORE.0 as usize].never_merge);
+        assert_eq!(top_quads(&mesh_row(&ore_blocks)), 4);
+        assert_eq!(
+            quad_count(&mesh_row(&ore_blocks)),
+            quad_count(&mesh_culled(ChunkMeshInput {
+                blocks: &ore_blocks,
+                surrounding_sides: &surrounding_sides,
+                neighbour_block: &|_| BLOCK_AIR,
+                light: &|_| ChunkLightSnapshot::UNLIT,
+                layer: MeshLayer::Opaque,
+                options: MeshingOptions::default(),
+            }))
+        );
+    }
+
+    /// Build a block array with a deterministic pseudo-random mix of air, dirt and grass, to
+    /// stress the mesher with many small faces
+    fn noisy_blocks() -> Vec<BlockId> {
+        blocks_from_fn(|pos| {
+            let hash = (pos.x.wrapping_mul(73856093)
+                ^ pos.y.wrapping_mul(19349663)
+                ^ pos.z.wrapping_mul(83492791))
+                % 5;
+
+            match hash {
+                0 | 1 => BLOCK_AIR,
+                2 | 3 => BLOCK_DIRT,
+                _ => BLOCK_GRASS,
+            }
+        })
+    }
+
+    #[test]
+    fn parallel_greedy_meshing_matches_sequential() {
+        for blocks in [
+            noisy_blocks(),
+            blocks_in_box(UVec3::new(1, 1, 1), UVec3::new(31, 31, 31)),
+            vec![BLOCK_AIR; CHUNK_SIZE_CUBED],
+        ] {
+            let surrounding_sides = vec![None; 6];
+            let input = ChunkMeshInput {
+                blocks: &blocks,
+                surrounding_sides: &surrounding_sides,
+                neighbour_block: &|_| BLOCK_AIR,
+                light: &|_| ChunkLightSnapshot::UNLIT,
+                layer: MeshLayer::Opaque,
+                options: MeshingOptions::default(),
+            };
+
+            let sequential = mesh_greedy(input);
+            let parallel = mesh_greedy_parallel(input);
+
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&sequential),
+                bytemuck::cast_slice::<_, u8>(&parallel)
+            );
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_greedy_meshing`
+    #[test]
+    #[ignore]
+    fn bench_greedy_meshing() {
+        const ITERATIONS: u32 = 100;
+
+        let blocks = noisy_blocks();
+        let surrounding_sides = vec![None; 6];
+        let input = ChunkMeshInput {
+            blocks: &blocks,
+            surrounding_sides: &surrounding_sides,
+            neighbour_block: &|_| BLOCK_AIR,
+            light: &|_| ChunkLightSnapshot::UNLIT,
+            layer: MeshLayer::Opaque,
+            options: MeshingOptions::default(),
+        };
+
+        let start = std::time::Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(mesh_greedy(input));
+        }
+        let sequential = start.elapsed() / ITERATIONS;
+
+        let start = std::time::Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(mesh_greedy_parallel(input));
+        }
+        let parallel = start.elapsed() / ITERATIONS;
+
+        println!("sequential: {:?}, parallel: {:?}", sequential, parallel);
+    }
+
+    #[test]
+    fn sorted_indices_flip_with_camera_side() {
+        // two quads facing the camera, one at z = 0 and one at z = 2
+        let quad = |z: f32| {
+            [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]].map(|[x, y]| TerrainVertex {
+                position: [x, y, z],
+                ..bytemuck::Zeroable::zeroed()
+            })
+        };
+        let vertices = [quad(0.0), quad(2.0)].concat();
+
+        let first_quad_drawn = |camera_pos: Vec3| {
+            let indices = generate_sorted_indices(&vertices, camera_pos);
+            assert_eq!(indices.len(), 12);
+            indices[0] / 4
+        };
+
+        // the furthest quad is drawn first
+        assert_eq!(first_quad_drawn(Vec3::new(0.5, 0.5, -5.0)), 1);
+        assert_eq!(first_quad_drawn(Vec3::new(0.5, 0.5, 7.0)), 0);
+
+        // each quad keeps its winding
+        assert_eq!(
+            generate_sorted_indices(&vertices, Vec3::new(0.5, 0.5, -5.0)),
+            [4, 5, 6, 6, 7, 4, 0, 1, 2, 2, 3, 0]
+        );
+    }
+}
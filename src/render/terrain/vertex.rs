@@ -1,18 +1,37 @@
-use crate::render::util::mesh::Vertex;
+use glam::Vec3;
+
+use crate::{
+    render::util::mesh::Vertex,
+    terrain::lighting::{EmittedLight, Skylight},
+};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct TerrainVertex {
     pub position: [f32; 3],
     pub uv: [f32; 2],
-    pub texture_index: u32,
-    pub shading: f32,
+    /// Layer of the texture array and index into the tint palette of the tint multiplied into the
+    /// texture colour, packed with `pack_texture`
+    pub texture: u32,
+    /// Ambient occlusion, from 0 (fully occluded) to 1 (unoccluded). The strength of the effect
+    /// is applied in the shader, so that it can be changed without remeshing
+    pub ao: f32,
+    /// Vertex normal packed with `pack_normal`, also used for the sunlight shading
+    pub normal: u32,
+    /// Light from emitting blocks and skylight, packed with `pack_light`
+    pub light: u32,
 }
 
 impl Vertex for TerrainVertex {
     fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
-        const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
-            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Uint32, 3 => Float32];
+        const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x2,
+            2 => Uint32,
+            3 => Float32,
+            4 => Snorm8x4,
+            5 => Uint32,
+        ];
 
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
@@ -21,3 +40,55 @@ impl Vertex for TerrainVertex {
         }
     }
 }
+
+/// Number of bits of a packed texture holding the texture array layer. The tint index is stored
+/// in the bits above
+pub const TEXTURE_INDEX_BITS: u32 = 24;
+
+/// Pack the texture array layer and tint index of a vertex into 32 bits: the layer in the low
+/// `TEXTURE_INDEX_BITS` bits and the tint index in the rest
+pub fn pack_texture(texture_index: usize, tint_index: usize) -> u32 {
+    debug_assert!(texture_index < 1 << TEXTURE_INDEX_BITS);
+    debug_assert!(tint_index < 1 << (32 - TEXTURE_INDEX_BITS));
+
+    texture_index as u32 | (tint_index as u32) << TEXTURE_INDEX_BITS
+}
+
+/// Unpack the texture array layer and tint index packed with `pack_texture`
+#[cfg(any(test, feature = "export"))]
+pub fn unpack_texture(packed: u32) -> (u32, u32) {
+    (packed & ((1 << TEXTURE_INDEX_BITS) - 1), packed >> TEXTURE_INDEX_BITS)
+}
+
+/// Pack the light from emitting blocks and the skylight of a vertex into 32 bits: the red, green
+/// and blue light in 4 bits each like `EmittedLight`, followed by the skylight in 4 bits
+pub fn pack_light(block_light: EmittedLight, skylight: Skylight) -> u32 {
+    block_light.as_u16() as u32 | (skylight.level() as u32) << 12
+}
+
+/// Pack a unit normal into 32 bits, storing each component as a signed normalized byte
+/// The layout matches `wgpu::VertexFormat::Snorm8x4`, so the shader receives the unpacked normal
+/// in the xyz components of a `vec4f`
+pub fn pack_normal(normal: Vec3) -> u32 {
+    let pack_component = |component: f32| ((component.clamp(-1.0, 1.0) * 127.0).round() as i8) as u8;
+
+    u32::from_le_bytes([
+        pack_component(normal.x),
+        pack_component(normal.y),
+        pack_component(normal.z),
+        0,
+    ])
+}
+
+/// Unpack a normal packed with `pack_normal`, matching the conversion done by the GPU
+#[cfg(any(test, feature = "export"))]
+pub fn unpack_normal(packed: u32) -> Vec3 {
+    let unpack_component = |byte: u8| ((byte as i8) as f32 / 127.0).max(-1.0);
+    let bytes = packed.to_le_bytes();
+
+    Vec3::new(
+        unpack_component(bytes[0]),
+        unpack_component(bytes[1]),
+        unpack_component(bytes[2]),
+    )
+}
@@ -5,7 +5,7 @@ use itertools::Itertools;
 use crate::{
     render::frustum_culling::FrustumCullingRegions,
     terrain::{
-        chunk::{Chunk, CHUNK_SIZE},
+        chunk::Chunk,
         load_area::LoadArea,
         position_types::ChunkPosition,
         Terrain,
@@ -46,11 +46,7 @@ pub fn visibility_search<'terrain>(
     let mut seen = vec![false; load_area.size().product()];
 
     // start at the camera position
-    let camera_chunk_pos = ChunkPosition::from(
-        (camera_pos / (CHUNK_SIZE as f32))
-            .floor()
-            .as_ivec3(),
-    );
+    let camera_chunk_pos = ChunkPosition::containing(camera_pos);
     let Some(camera_chunk) = terrain.get_chunk(load_area_index, &camera_chunk_pos) else {
         return Vec::new();
     };
@@ -86,12 +82,7 @@ pub fn visibility_search<'terrain>(
                     }
                 })
                 // dir -> (dir, chunk_pos)
-                .map(|dir| {
-                    (
-                        dir,
-                        step.chunk.position() + ChunkPosition::from(FACE_NORMALS[dir]),
-                    )
-                })
+                .map(|dir| (dir, step.chunk.position().neighbour(FaceIndex(dir))))
                 // make sure the chunk position is within the load area
                 .filter(|(_, chunk_pos)| load_area.is_within_bounds(chunk_pos))
                 // don't visit the same chunk twice
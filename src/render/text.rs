@@ -0,0 +1,306 @@
+use winit::dpi::PhysicalSize;
+
+use self::font::{GLYPHS, GLYPH_HEIGHT, GLYPH_WIDTH};
+use super::{
+    render_context::RenderContext,
+    render_pass::PassTargets,
+    util::{
+        bind_group_builder::BindGroupBuilder,
+        mesh::Vertex,
+        pipeline_builder::RenderPipelineBuilder,
+        shader_source::{shader_source, ReloadablePipeline},
+    },
+};
+
+pub mod font;
+
+/// Responsible for drawing text in the top left corner of the screen using the embedded bitmap
+/// font, e.g. for the debug HUD
+#[derive(Debug)]
+pub struct TextRenderer {
+    /// One instance per character cell, rebuilt when the text changes
+    glyphs: Vec<GlyphInstance>,
+    /// Set when `glyphs` has changed since it was last uploaded
+    glyphs_changed: bool,
+    instance_buffer: wgpu::Buffer,
+    /// Number of glyph instances that fit in the instance buffer
+    instance_capacity: usize,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Rebuilt when the shader is modified
+    pipeline: ReloadablePipeline,
+}
+
+impl TextRenderer {
+    /// Size of each font pixel in logical pixels
+    const PIXEL_SCALE: f32 = 2.0;
+    /// Linear RGBA colour of the text
+    const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+    /// Linear RGBA colour of the background behind each character, so that the text stays legible
+    /// over bright terrain
+    const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+    /// Initial number of glyph instances that fit in the instance buffer
+    const INITIAL_INSTANCE_CAPACITY: usize = 256;
+
+    pub fn new(cx: &RenderContext) -> Self {
+        let atlas_size = wgpu::Extent3d {
+            width: GLYPH_WIDTH * GLYPHS.len() as u32,
+            height: GLYPH_HEIGHT,
+            depth_or_array_layers: 1,
+        };
+
+        let font_texture = cx
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Font Texture"),
+                size: atlas_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+
+        cx.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &font_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &font::atlas_pixels(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(atlas_size.width),
+                rows_per_image: Some(atlas_size.height),
+            },
+            atlas_size,
+        );
+
+        let font_view = font_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniform_buffer = cx
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Text Uniform Buffer"),
+                size: std::mem::size_of::<TextUniforms>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let (bind_group, bind_group_layout) = BindGroupBuilder::new()
+            .with_label("Text Bind Group")
+            .with_uniform_buffer(
+                &uniform_buffer,
+                wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            )
+            .with_texture_view(
+                &font_view,
+                wgpu::TextureViewDimension::D2,
+                wgpu::TextureSampleType::Float { filterable: false },
+                wgpu::ShaderStages::FRAGMENT,
+            )
+            .build(&cx.device);
+
+        let instance_buffer = Self::create_instance_buffer(cx, Self::INITIAL_INSTANCE_CAPACITY);
+
+        let shader = shader_source!("text.wgsl");
+        let module = shader.create_module(&cx.device);
+
+        let (pipeline, pipeline_layout) = Self::pipeline_builder(cx, &module)
+            .with_bind_group_layout(&bind_group_layout)
+            .build(&cx.device);
+
+        Self {
+            glyphs: Vec::new(),
+            glyphs_changed: false,
+            instance_buffer,
+            instance_capacity: Self::INITIAL_INSTANCE_CAPACITY,
+            uniform_buffer,
+            bind_group,
+            pipeline: ReloadablePipeline::new(shader, pipeline_layout, pipeline),
+        }
+    }
+
+    fn create_instance_buffer(cx: &RenderContext, capacity: usize) -> wgpu::Buffer {
+        cx.device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Text Instance Buffer"),
+                size: (capacity * std::mem::size_of::<GlyphInstance>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+    }
+
+    fn pipeline_builder<'a>(
+        cx: &RenderContext,
+        shader: &'a wgpu::ShaderModule,
+    ) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .with_label("Text Pipeline")
+            .with_vertex::<GlyphInstance>()
+            .with_vertex_shader(shader, "vs_main")
+            .with_fragment_shader(shader, "fs_main")
+            .with_color_target(
+                cx.surface_config.format,
+                Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                wgpu::ColorWrites::COLOR,
+            )
+            .with_topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .with_cull_mode(None)
+    }
+
+    /// Called once per frame to draw the text over the output
+    pub fn render(
+        &mut self,
+        render_encoder: &mut wgpu::CommandEncoder,
+        targets: &PassTargets,
+        cx: &RenderContext,
+    ) {
+        self.pipeline.reload_if_changed(&cx.device, |module, layout| {
+            Self::pipeline_builder(cx, module)
+                .with_layout(layout)
+                .build_with_existing_layout(&cx.device)
+        });
+
+        if self.glyphs.is_empty() {
+            targets.clear(render_encoder);
+            return;
+        }
+
+        if self.glyphs_changed {
+            // grow the instance buffer if the text no longer fits
+            if self.glyphs.len() > self.instance_capacity {
+                self.instance_capacity = self.glyphs.len().next_power_of_two();
+                self.instance_buffer = Self::create_instance_buffer(cx, self.instance_capacity);
+            }
+
+            cx.queue.write_buffer(
+                &self.instance_buffer,
+                0 as wgpu::BufferAddress,
+                bytemuck::cast_slice(&self.glyphs),
+            );
+            self.glyphs_changed = false;
+        }
+
+        let uniforms = TextUniforms::new(cx.window_size, cx.scale_factor);
+
+        cx.queue.write_buffer(
+            &self.uniform_buffer,
+            0 as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[uniforms]),
+        );
+
+        let mut render_pass = targets.begin_render_pass(render_encoder);
+
+        render_pass.set_pipeline(self.pipeline.get());
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..4, 0..self.glyphs.len() as u32);
+    }
+
+    /// Set the text to draw, with lines separated by '\n'. An empty string hides the text. Takes
+    /// effect from the next frame
+    pub fn set_text(&mut self, text: &str) {
+        let glyphs = layout_text(text);
+
+        if glyphs != self.glyphs {
+            self.glyphs = glyphs;
+            self.glyphs_changed = true;
+        }
+    }
+}
+
+/// Lay out the given text in a grid of character cells, one row per line
+fn layout_text(text: &str) -> Vec<GlyphInstance> {
+    text.lines()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.chars()
+                .enumerate()
+                .map(move |(column, c)| GlyphInstance {
+                    cell: [column as u32, row as u32],
+                    glyph: font::glyph_index(c),
+                })
+        })
+        .collect()
+}
+
+/// Per-instance data for one character cell
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlyphInstance {
+    /// Column and row of the cell
+    cell: [u32; 2],
+    /// Index of the glyph in the font atlas
+    glyph: u32,
+}
+
+impl Vertex for GlyphInstance {
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+            0 => Uint32x2,
+            1 => Uint32,
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextUniforms {
+    text_color: [f32; 4],
+    background_color: [f32; 4],
+    screen_size: [f32; 2],
+    pixel_scale: f32,
+    padding: f32,
+}
+
+impl TextUniforms {
+    fn new(surface_size: PhysicalSize<u32>, scale_factor: f64) -> Self {
+        let premultiply = |[r, g, b, a]: [f32; 4]| [r * a, g * a, b * a, a];
+
+        Self {
+            text_color: premultiply(TextRenderer::TEXT_COLOR),
+            background_color: premultiply(TextRenderer::BACKGROUND_COLOR),
+            screen_size: [surface_size.width as f32, surface_size.height as f32],
+            pixel_scale: (TextRenderer::PIXEL_SCALE * scale_factor as f32).round().max(1.0),
+            padding: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_laid_out_in_cells() {
+        let glyphs = layout_text("Ab\n?~");
+
+        let cells: Vec<_> = glyphs.iter().map(|glyph| glyph.cell).collect();
+        assert_eq!(cells, [[0, 0], [1, 0], [0, 1], [1, 1]]);
+
+        // lowercase letters share the uppercase glyphs, and unknown characters become '?'
+        assert_eq!(glyphs[0].glyph, font::glyph_index('A'));
+        assert_eq!(glyphs[1].glyph, font::glyph_index('B'));
+        assert_eq!(glyphs[2].glyph, '?' as u32 - ' ' as u32);
+        assert_eq!(glyphs[3].glyph, glyphs[2].glyph);
+
+        // the top row of '-' is empty and its middle row is full
+        let pixels = font::atlas_pixels();
+        let atlas_width = (GLYPH_WIDTH as usize) * GLYPHS.len();
+        let x = font::glyph_index('-') as usize * GLYPH_WIDTH as usize;
+        assert_eq!(&pixels[x..x + 5], [0; 5]);
+        assert_eq!(&pixels[3 * atlas_width + x..3 * atlas_width + x + 5], [255; 5]);
+
+        // the uniform struct must match the 16-byte aligned layout in text.wgsl
+        assert_eq!(std::mem::size_of::<TextUniforms>(), 48);
+    }
+}
@@ -2,4 +2,5 @@ pub mod bind_group_builder;
 pub mod mesh;
 pub mod mip_generator;
 pub mod pipeline_builder;
+pub mod shader_source;
 pub mod texture;
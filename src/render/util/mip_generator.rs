@@ -1,5 +1,7 @@
 // based on https://github.com/gfx-rs/wgpu/blob/trunk/examples/src/mipmap/mod.rs
 
+use super::shader_source::shader_source;
+
 /// Generates mips for 2D textures and texture arrays
 pub struct MipGenerator {
     pipeline: wgpu::RenderPipeline,
@@ -10,8 +12,7 @@ pub struct MipGenerator {
 impl MipGenerator {
     pub fn new(device: &wgpu::Device, texture_format: wgpu::TextureFormat) -> Self {
         // TODO get shader from proper asset system
-        let shader =
-            device.create_shader_module(wgpu::include_wgsl!("../../../assets/shader/blit.wgsl"));
+        let shader = shader_source!("blit.wgsl").create_module(device);
 
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("blit"),
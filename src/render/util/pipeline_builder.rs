@@ -3,6 +3,7 @@ use super::mesh::Vertex;
 /// Helper struct to create render pipelines and their layouts using the builder pattern
 pub struct RenderPipelineBuilder<'a> {
     label: Option<&'static str>,
+    layout: Option<&'a wgpu::PipelineLayout>,
     bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
     vertex_buffer_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
     vertex_module: Option<&'a wgpu::ShaderModule>,
@@ -13,6 +14,8 @@ pub struct RenderPipelineBuilder<'a> {
     fragment_compilation_options: wgpu::PipelineCompilationOptions<'a>,
     targets: Vec<Option<wgpu::ColorTargetState>>,
     depth: Option<(wgpu::TextureFormat, wgpu::CompareFunction)>,
+    depth_write_enabled: bool,
+    depth_bias: wgpu::DepthBiasState,
     topology: wgpu::PrimitiveTopology,
     front_face: wgpu::FrontFace,
     cull_mode: Option<wgpu::Face>,
@@ -23,6 +26,7 @@ impl<'a> RenderPipelineBuilder<'a> {
     pub fn new() -> Self {
         Self {
             label: None,
+            layout: None,
             bind_group_layouts: Vec::new(),
             vertex_buffer_layouts: Vec::new(),
             vertex_module: None,
@@ -33,6 +37,8 @@ impl<'a> RenderPipelineBuilder<'a> {
             fragment_compilation_options: wgpu::PipelineCompilationOptions::default(),
             targets: Vec::new(),
             depth: None,
+            depth_write_enabled: true,
+            depth_bias: wgpu::DepthBiasState::default(),
             topology: wgpu::PrimitiveTopology::TriangleList,
             front_face: wgpu::FrontFace::Ccw,
             cull_mode: Some(wgpu::Face::Back),
@@ -47,9 +53,29 @@ impl<'a> RenderPipelineBuilder<'a> {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let pipeline = self.build_with_layout(device, &pipeline_layout);
+
+        (pipeline, pipeline_layout)
+    }
+
+    /// Build the pipeline using the layout given by `with_layout`, ignoring any bind group
+    /// layouts. Useful for rebuilding a pipeline that shares the layout of an existing one
+    pub fn build_with_existing_layout(self, device: &wgpu::Device) -> wgpu::RenderPipeline {
+        let pipeline_layout = self
+            .layout
+            .expect("missing pipeline layout");
+
+        self.build_with_layout(device, pipeline_layout)
+    }
+
+    fn build_with_layout(
+        &self,
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: self.label,
-            layout: Some(&pipeline_layout),
+            layout: Some(pipeline_layout),
             vertex: wgpu::VertexState {
                 module: self
                     .vertex_module
@@ -58,7 +84,7 @@ impl<'a> RenderPipelineBuilder<'a> {
                     .vertex_entry_point
                     .expect("missing vertex entry point"),
                 buffers: &self.vertex_buffer_layouts,
-                compilation_options: self.vertex_compilation_options,
+                compilation_options: self.vertex_compilation_options.clone(),
             },
             fragment: self
                 .fragment_module
@@ -67,7 +93,7 @@ impl<'a> RenderPipelineBuilder<'a> {
                     entry_point: self
                         .fragment_entry_point
                         .expect("missing fragment entry point"),
-                    compilation_options: self.fragment_compilation_options,
+                    compilation_options: self.fragment_compilation_options.clone(),
                     targets: &self.targets,
                 }),
             primitive: wgpu::PrimitiveState {
@@ -84,9 +110,9 @@ impl<'a> RenderPipelineBuilder<'a> {
                 .map(|(format, depth_compare)| wgpu::DepthStencilState {
                     format,
                     depth_compare,
-                    depth_write_enabled: true,
+                    depth_write_enabled: self.depth_write_enabled,
                     stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
+                    bias: self.depth_bias,
                 }),
             multisample: wgpu::MultisampleState {
                 count: 1,
@@ -94,9 +120,7 @@ impl<'a> RenderPipelineBuilder<'a> {
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-        });
-
-        (pipeline, pipeline_layout)
+        })
     }
 
     pub fn with_label(mut self, label: &'static str) -> Self {
@@ -104,6 +128,11 @@ impl<'a> RenderPipelineBuilder<'a> {
         self
     }
 
+    pub fn with_layout(mut self, layout: &'a wgpu::PipelineLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
     pub fn with_bind_group_layout(mut self, bind_group_layout: &'a wgpu::BindGroupLayout) -> Self {
         self.bind_group_layouts
             .push(bind_group_layout);
@@ -186,6 +215,20 @@ impl<'a> RenderPipelineBuilder<'a> {
         self
     }
 
+    /// Disable for translucent geometry, which is depth tested without occluding what is drawn
+    /// after it
+    pub fn with_depth_write(mut self, depth_write_enabled: bool) -> Self {
+        self.depth_write_enabled = depth_write_enabled;
+        self
+    }
+
+    /// Offset the depth written by the pipeline, e.g. to keep shadow maps from shadowing the
+    /// surfaces they were rendered from
+    pub fn with_depth_bias(mut self, depth_bias: wgpu::DepthBiasState) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
     pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
         self.topology = topology;
         self
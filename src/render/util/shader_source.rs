@@ -0,0 +1,294 @@
+use std::{borrow::Cow, io};
+#[cfg(feature = "hot-reload")]
+use std::time::{Duration, Instant, SystemTime};
+
+use pollster::FutureExt;
+
+/// Create a `ShaderSource` for a WGSL file in `assets/shader/`
+macro_rules! shader_source {
+    ($file_name:literal) => {
+        $crate::render::util::shader_source::ShaderSource::new(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shader/", $file_name),
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shader/", $file_name)),
+        )
+    };
+}
+
+pub(crate) use shader_source;
+
+/// The source code of a WGSL shader.
+/// In release builds, shaders are embedded in the binary so that it can be run from anywhere.
+/// In debug builds, shaders are read from disk instead, and when the `hot-reload` feature is
+/// enabled `poll_changed` can be used to find out when the file has been modified, so that the
+/// pipelines using it can be rebuilt.
+/// New passes should load their shaders through this too, usually by keeping their pipelines in
+/// a `ReloadablePipeline`
+#[derive(Debug)]
+pub struct ShaderSource {
+    /// Path to the shader file
+    path: &'static str,
+    /// Source code embedded in the binary at compile time
+    embedded: &'static str,
+    /// Modification time of the file when it was last read
+    #[cfg(feature = "hot-reload")]
+    last_modified: Option<SystemTime>,
+    /// Instant when the file's modification time was last checked
+    #[cfg(feature = "hot-reload")]
+    last_polled: Instant,
+}
+
+impl ShaderSource {
+    /// Whether shaders are read from disk rather than using the embedded source
+    const READ_FROM_DISK: bool = cfg!(any(debug_assertions, feature = "hot-reload"));
+
+    /// Minimum duration between checks for modifications to the shader file
+    #[cfg(feature = "hot-reload")]
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Use `shader_source!` rather than calling this directly
+    pub fn new(path: &'static str, embedded: &'static str) -> Self {
+        Self {
+            path,
+            embedded,
+            #[cfg(feature = "hot-reload")]
+            last_modified: Self::modified_time(path),
+            #[cfg(feature = "hot-reload")]
+            last_polled: Instant::now(),
+        }
+    }
+
+    /// Path to the shader file
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// Returns the current source code of the shader.
+    /// Panics if the shader file can't be read, see `try_source`
+    pub fn source(&self) -> Cow<'static, str> {
+        self.try_source().unwrap_or_else(|e| {
+            panic!(
+                "failed to read shader from `{}`: {} (shaders are read from disk in debug builds, \
+                 build with --release to embed them in the binary instead)",
+                self.path, e
+            )
+        })
+    }
+
+    /// Returns the current source code of the shader, or the error from reading the shader file,
+    /// e.g. if it was deleted while the program was running
+    pub fn try_source(&self) -> io::Result<Cow<'static, str>> {
+        if !Self::READ_FROM_DISK {
+            return Ok(Cow::Borrowed(self.embedded));
+        }
+
+        std::fs::read_to_string(self.path).map(Cow::Owned)
+    }
+
+    /// Create a shader module from the current source code.
+    /// Panics if the shader can't be read or fails to compile
+    pub fn create_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        self.create_module_from_source(device, self.source())
+    }
+
+    /// Create a shader module from `source`, which was read from this shader
+    fn create_module_from_source(
+        &self,
+        device: &wgpu::Device,
+        source: Cow<'static, str>,
+    ) -> wgpu::ShaderModule {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(self.path),
+            source: wgpu::ShaderSource::Wgsl(source),
+        })
+    }
+
+    /// Returns true if the shader file has been modified since it was last checked.
+    /// A file that can't be found (e.g. while an editor is replacing it) doesn't count as a
+    /// modification, so its pipelines are only rebuilt once it is back.
+    /// Always returns false unless the `hot-reload` feature is enabled
+    pub fn poll_changed(&mut self) -> bool {
+        #[cfg(feature = "hot-reload")]
+        {
+            if self.last_polled.elapsed() < Self::POLL_INTERVAL {
+                return false;
+            }
+            self.last_polled = Instant::now();
+
+            let modified = Self::modified_time(self.path);
+            if modified.is_some() && modified != self.last_modified {
+                self.last_modified = modified;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn modified_time(path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+/// Pipelines built from one shader with one layout, which can be rebuilt when the shader is
+/// modified on disk. `P` is usually a single `wgpu::RenderPipeline`, but can be any collection of
+/// pipelines built from the same shader
+#[derive(Debug)]
+pub struct ReloadablePipeline<P = wgpu::RenderPipeline> {
+    shader: ShaderSource,
+    layout: wgpu::PipelineLayout,
+    pipeline: P,
+}
+
+impl<P> ReloadablePipeline<P> {
+    /// `pipeline` must have been built from `shader` with `layout`
+    pub fn new(shader: ShaderSource, layout: wgpu::PipelineLayout, pipeline: P) -> Self {
+        Self {
+            shader,
+            layout,
+            pipeline,
+        }
+    }
+
+    pub fn get(&self) -> &P {
+        &self.pipeline
+    }
+
+    /// Rebuild the pipelines with `create` if the shader has been modified on disk. Called once
+    /// per frame by the renderer using them
+    pub fn reload_if_changed(
+        &mut self,
+        device: &wgpu::Device,
+        create: impl FnOnce(&wgpu::ShaderModule, &wgpu::PipelineLayout) -> P,
+    ) {
+        if self.shader.poll_changed() {
+            log::info!("reloading {}", self.shader.path());
+            self.rebuild(device, create);
+        }
+    }
+
+    /// Rebuild the pipelines with `create`, e.g. after a setting they depend on has changed.
+    /// If the shader can't be read or fails to compile, the errors are logged, the old pipelines
+    /// are kept and false is returned
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        create: impl FnOnce(&wgpu::ShaderModule, &wgpu::PipelineLayout) -> P,
+    ) -> bool {
+        let Self {
+            shader,
+            layout,
+            pipeline,
+        } = self;
+
+        Self::rebuild_from_source(shader, pipeline, |source| {
+            try_create(device, || {
+                let module = shader.create_module_from_source(device, source);
+                create(&module, layout)
+            })
+        })
+    }
+
+    /// Replace `pipeline` with the one built by `build` from the current source of `shader`.
+    /// If the source can't be read, `build` isn't called and the error is logged. Returns false
+    /// if the old pipeline was kept
+    fn rebuild_from_source(
+        shader: &ShaderSource,
+        pipeline: &mut P,
+        build: impl FnOnce(Cow<'static, str>) -> Option<P>,
+    ) -> bool {
+        let source = match shader.try_source() {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!(
+                    "failed to read shader from `{}`: {}, keeping the old pipeline",
+                    shader.path(),
+                    e
+                );
+                return false;
+            }
+        };
+
+        build(source)
+            .map(|new_pipeline| *pipeline = new_pipeline)
+            .is_some()
+    }
+}
+
+/// Run `create` and return its result, or None if it raised any validation errors (for example
+/// due to a shader failing to compile). The errors are logged rather than causing a panic
+pub fn try_create<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Option<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = create();
+
+    if let Some(error) = device.pop_error_scope().block_on() {
+        log::error!("{}", error);
+        None
+    } else {
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_shader_from_manifest_dir() {
+        let shader = shader_source!("terrain.wgsl");
+
+        assert!(shader
+            .path()
+            .starts_with(env!("CARGO_MANIFEST_DIR")));
+        assert_eq!(shader.source(), shader.embedded);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "failed to read shader from `assets/shader/missing.wgsl`")]
+    fn missing_shader_panics_with_path() {
+        ShaderSource::new("assets/shader/missing.wgsl", "").source();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn missing_shader_during_reload_keeps_the_old_pipeline() {
+        let missing = ShaderSource::new("assets/shader/missing.wgsl", "");
+        assert!(missing.try_source().is_err());
+
+        let mut pipeline = "old";
+        let rebuilt = ReloadablePipeline::rebuild_from_source(&missing, &mut pipeline, |_| {
+            panic!("the pipeline shouldn't be built without a shader")
+        });
+        assert!(!rebuilt);
+        assert_eq!(pipeline, "old");
+
+        // a shader that can be read replaces the pipeline
+        let shader = shader_source!("terrain.wgsl");
+        let rebuilt = ReloadablePipeline::rebuild_from_source(&shader, &mut pipeline, |source| {
+            assert_eq!(source, shader.embedded);
+            Some("new")
+        });
+        assert!(rebuilt);
+        assert_eq!(pipeline, "new");
+    }
+
+    #[test]
+    #[cfg(feature = "hot-reload")]
+    fn deleting_the_shader_is_not_a_change() {
+        let path = std::env::temp_dir().join(format!(
+            "voxels-test-{}-deleted.wgsl",
+            std::process::id()
+        ));
+        std::fs::write(&path, "").unwrap();
+        let path: &'static str = path.to_str().unwrap().to_owned().leak();
+
+        let mut shader = ShaderSource::new(path, "");
+        std::fs::remove_file(path).unwrap();
+        shader.last_polled -= ShaderSource::POLL_INTERVAL;
+        assert!(!shader.poll_changed());
+        assert!(shader.last_modified.is_some());
+    }
+}
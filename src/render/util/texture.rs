@@ -13,6 +13,8 @@ pub struct TextureConfig {
     pub label: wgpu::Label<'static>,
     pub usage: wgpu::TextureUsages,
     pub mip_level_count: u32,
+    /// If true, array textures classify the alpha channel of each layer when they are created
+    pub classify_alpha: bool,
 }
 
 impl Default for TextureConfig {
@@ -23,6 +25,7 @@ impl Default for TextureConfig {
                 | wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::RENDER_ATTACHMENT,
             mip_level_count: 1,
+            classify_alpha: false,
         }
     }
 }
@@ -143,6 +146,8 @@ pub struct ArrayTexture {
     texture: wgpu::Texture,
     individual_image_size: UVec2,
     layer_count: u32,
+    /// Alpha class of each layer, empty unless `TextureConfig::classify_alpha` was set
+    alpha_classes: Vec<AlphaClass>,
 }
 
 impl ArrayTexture {
@@ -213,10 +218,29 @@ impl ArrayTexture {
             );
         }
 
+        let alpha_classes = if config.classify_alpha {
+            images
+                .iter()
+                .map(AlphaClass::classify)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        for (layer_index, alpha_class) in alpha_classes.iter().enumerate() {
+            log::info!(
+                "{} layer {}: {:?}",
+                config.label.unwrap_or("array texture"),
+                layer_index,
+                alpha_class
+            );
+        }
+
         Ok(Self {
             texture,
             individual_image_size: UVec2::new(dim.0, dim.1),
             layer_count,
+            alpha_classes,
         })
     }
 
@@ -242,6 +266,91 @@ impl ArrayTexture {
 
         Self::from_images(device, queue, &images, config)
     }
+
+    /// Create an array texture from a single atlas image made up of equally sized tiles. Each
+    /// tile becomes one layer, in the order given by `split_atlas`
+    pub fn from_atlas_file(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        tile_size: UVec2,
+        config: &TextureConfig,
+    ) -> Result<Self, ArrayTextureError> {
+        let file = File::open(path).map_err(ArrayTextureError::IoError)?;
+        let reader = BufReader::new(file);
+        let atlas = image::load(reader, IMAGE_FORMAT).map_err(ArrayTextureError::ImageError)?;
+
+        let tiles = split_atlas(&atlas, tile_size)?;
+
+        Self::from_images(device, queue, &tiles, config)
+    }
+
+    /// Alpha class of each layer, or an empty slice unless `TextureConfig::classify_alpha` was
+    /// set when the texture was created
+    pub fn alpha_classes(&self) -> &[AlphaClass] {
+        &self.alpha_classes
+    }
+}
+
+/// How a texture uses its alpha channel, which determines how blocks using it must be rendered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaClass {
+    /// Every pixel is fully opaque
+    Opaque,
+    /// Every pixel is either fully opaque or fully transparent, so transparent pixels can be
+    /// discarded
+    Cutout,
+    /// Some pixels are partially transparent and must be blended
+    Translucent,
+}
+
+impl AlphaClass {
+    /// Classify an image by inspecting its alpha channel
+    pub fn classify(image: &image::DynamicImage) -> Self {
+        let image = image.to_rgba8();
+        let mut alpha_values = image
+            .pixels()
+            .map(|pixel| pixel.0[3]);
+
+        if alpha_values
+            .clone()
+            .all(|alpha| alpha == u8::MAX)
+        {
+            Self::Opaque
+        } else if alpha_values.all(|alpha| alpha == 0 || alpha == u8::MAX) {
+            Self::Cutout
+        } else {
+            Self::Translucent
+        }
+    }
+}
+
+/// Split an atlas image into equally sized tiles, indexed left to right and then top to bottom.
+/// Returns an error if the atlas dimensions are not a multiple of the tile size
+pub fn split_atlas(
+    atlas: &image::DynamicImage,
+    tile_size: UVec2,
+) -> Result<Vec<image::DynamicImage>, ArrayTextureError> {
+    let atlas_size = UVec2::from(atlas.dimensions());
+
+    if tile_size.cmpeq(UVec2::ZERO).any() || atlas_size % tile_size != UVec2::ZERO {
+        return Err(ArrayTextureError::AtlasNotMultipleOfTileSize {
+            atlas_size,
+            tile_size,
+        });
+    }
+
+    let tile_count = atlas_size / tile_size;
+
+    let tiles = (0..tile_count.y)
+        .flat_map(|tile_y| (0..tile_count.x).map(move |tile_x| UVec2::new(tile_x, tile_y)))
+        .map(|tile| {
+            let corner = tile * tile_size;
+            atlas.crop_imm(corner.x, corner.y, tile_size.x, tile_size.y)
+        })
+        .collect();
+
+    Ok(tiles)
 }
 
 impl TextureHolder for ArrayTexture {
@@ -439,4 +548,60 @@ pub enum ArrayTextureError {
     ImageError(image::ImageError),
     #[error("image sizes don't match!")]
     DifferentlySizedImages,
+    #[error("atlas size {atlas_size} is not a multiple of the tile size {tile_size}")]
+    AtlasNotMultipleOfTileSize { atlas_size: UVec2, tile_size: UVec2 },
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn split_atlas_into_tiles() {
+        // 2x2 atlas of 2x2 tiles, where each pixel encodes its tile and position in the tile
+        let atlas = RgbaImage::from_fn(4, 4, |x, y| {
+            let tile_index = (y / 2) * 2 + x / 2;
+            Rgba([tile_index as u8, (x % 2) as u8, (y % 2) as u8, 255])
+        });
+        let atlas = image::DynamicImage::ImageRgba8(atlas);
+
+        let tiles = split_atlas(&atlas, UVec2::splat(2)).unwrap();
+        assert_eq!(tiles.len(), 4);
+
+        // tiles are ordered left to right, then top to bottom
+        let bottom_left = &tiles[2];
+        assert_eq!(bottom_left.dimensions(), (2, 2));
+        assert_eq!(bottom_left.get_pixel(0, 0), Rgba([2, 0, 0, 255]));
+        assert_eq!(bottom_left.get_pixel(1, 0), Rgba([2, 1, 0, 255]));
+        assert_eq!(bottom_left.get_pixel(0, 1), Rgba([2, 0, 1, 255]));
+        assert_eq!(bottom_left.get_pixel(1, 1), Rgba([2, 1, 1, 255]));
+
+        assert!(matches!(
+            split_atlas(&atlas, UVec2::new(3, 2)),
+            Err(ArrayTextureError::AtlasNotMultipleOfTileSize { .. })
+        ));
+    }
+
+    #[test]
+    fn classify_alpha() {
+        let image_with_alpha = |alpha: fn(u32, u32) -> u8| {
+            image::DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, y| {
+                Rgba([100, 150, 200, alpha(x, y)])
+            }))
+        };
+
+        let solid = image_with_alpha(|_, _| 255);
+        let holed = image_with_alpha(|x, y| if (x + y) % 2 == 0 { 255 } else { 0 });
+        let semi_transparent = image_with_alpha(|x, _| if x == 0 { 128 } else { 255 });
+
+        assert_eq!(AlphaClass::classify(&solid), AlphaClass::Opaque);
+        assert_eq!(AlphaClass::classify(&holed), AlphaClass::Cutout);
+        assert_eq!(AlphaClass::classify(&semi_transparent), AlphaClass::Translucent);
+
+        // images without an alpha channel are opaque
+        let rgb = image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 4));
+        assert_eq!(AlphaClass::classify(&rgb), AlphaClass::Opaque);
+    }
 }
@@ -1,52 +1,135 @@
-use std::sync::{Arc, Condvar, Mutex};
+use std::{
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+pub mod worker_scaling;
 
 /// Manages a pool of threads used to execute arbitrary tasks in parallel.
 /// This is similar to `rayon`'s `ThreadPool`, but provides the following additional functionality:
 /// - Tasks are assigned priorities and executed in priority order, rather than FIFO
 /// - Tasks that are queued but have not yet started executing can be cancelled
-/// NB: When the `Tasks` is dropped, any pending tasks will be cancelled but any currently
-/// executing tasks will finish normally
+/// - Tasks belong to a pipeline stage, and worker threads can be reserved for each stage so that a
+///   flood of tasks in one stage can't starve another
+/// - The number of worker threads allowed to execute tasks can be changed at runtime, see
+///   `set_worker_limit`, and so can the number of worker threads, see `set_worker_count`
+///
+/// NB: When the `Tasks` is shut down or dropped, every pending task is still executed before the
+/// worker threads are joined, so no task is silently dropped. Cancel tasks that are no longer
+/// needed first
 pub struct Tasks {
     /// Objects shared between the `Tasks` and its worker threads
     shared: Arc<TasksShared>,
+    /// Join handles of the worker threads, indexed by worker index and joined on shutdown
+    worker_threads: Vec<JoinHandle<()>>,
     /// Number of worker threads in the pool
     thread_count: usize,
     /// Total number of tasks submitted so far, used to assign Task IDs
     total_tasks_submitted: usize,
+    /// If true, tasks are only executed during `block_until_finished`
+    deterministic: bool,
 }
 
 impl Tasks {
-    /// Create a new `Tasks` thread pool with the given number of threads
-    pub fn new(thread_count: usize) -> Self {
+    /// Create a new `Tasks` thread pool with the given number of threads.
+    /// `reserved_workers` gives the number of those threads that prefer tasks from each stage;
+    /// they only execute tasks from other stages when their own stage has none pending. The
+    /// remaining threads execute tasks from any stage in priority order.
+    /// With more than one thread, the order in which tasks execute depends on thread scheduling
+    /// and is inherently nondeterministic. Use `new_deterministic` when a reproducible order is
+    /// needed, e.g. in tests
+    pub fn new(thread_count: usize, reserved_workers: &[(TaskStage, usize)]) -> Self {
+        debug_assert!(
+            reserved_workers
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<usize>()
+                <= thread_count,
+            "more workers reserved than there are threads"
+        );
+
         let shared = Arc::new(TasksShared {
             mutex: Mutex::new(TasksMutex {
                 pending_tasks: Vec::new(),
                 active_worker_threads: 0,
+                worker_limit: thread_count,
+                worker_count: thread_count,
+                held: false,
                 terminate: false,
             }),
             pending_task_cond: Condvar::new(),
             finished_task_cond: Condvar::new(),
         });
 
-        // start worker threads.
-        // note that we do not need to keep the join handles to the worker threads; they are
-        // completely detached
-        for _ in 0..thread_count {
-            // make a clone of the Arc for the worker thread
-            let shared = shared.clone();
+        // start worker threads
+        let preferred_stages = reserved_workers
+            .iter()
+            .flat_map(|&(stage, count)| std::iter::repeat_n(Some(stage), count))
+            .chain(std::iter::repeat(None))
+            .take(thread_count);
 
-            std::thread::spawn(move || Self::worker(shared));
-        }
+        let worker_threads = preferred_stages
+            .enumerate()
+            .map(|(worker_index, preferred_stage)| {
+                // make a clone of the Arc for the worker thread
+                let shared = shared.clone();
+
+                std::thread::spawn(move || Self::worker(shared, worker_index, preferred_stage))
+            })
+            .collect();
 
         Self {
             shared,
+            worker_threads,
             thread_count,
             total_tasks_submitted: 0,
+            deterministic: false,
+        }
+    }
+
+    /// Create a `Tasks` with a single worker thread that executes tasks in a reproducible order:
+    /// strictly by priority, ignoring stages, with ties broken by `TaskPriority::tie_breaker`.
+    /// Submitted tasks are held until `block_until_finished` is called, so the order doesn't
+    /// depend on when the worker wakes up relative to the submitting thread
+    pub fn new_deterministic() -> Self {
+        let mut tasks = Self::new(1, &[]);
+        tasks.deterministic = true;
+        tasks.set_held(true);
+        tasks
+    }
+
+    /// Returns true if this `Tasks` was created with `new_deterministic`, in which case tasks
+    /// only execute during `block_until_finished`
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Set whether the worker threads are prevented from starting new tasks
+    fn set_held(&self, held: bool) {
+        let mut lock = self
+            .shared
+            .mutex
+            .lock()
+            .expect("`Tasks` mutex poisoned");
+        lock.held = held;
+
+        if !held {
+            self.shared
+                .pending_task_cond
+                .notify_all();
         }
     }
 
     /// Submit a new task to the thread pool
-    pub fn submit<TaskFn>(&mut self, priority: TaskPriority, task_fn: TaskFn) -> TaskId
+    pub fn submit<TaskFn>(
+        &mut self,
+        stage: TaskStage,
+        priority: TaskPriority,
+        task_fn: TaskFn,
+    ) -> TaskId
     where
         TaskFn: FnOnce() + Send + Sync + 'static,
     {
@@ -63,6 +146,7 @@ impl Tasks {
             task_id,
             PendingTask {
                 task_fn: Box::new(task_fn),
+                stage,
                 priority,
             },
         ));
@@ -75,9 +159,35 @@ impl Tasks {
         task_id
     }
 
+    /// Submit a new task to the thread pool whose return value can be received from the returned
+    /// handle
+    pub fn submit_with_result<T, TaskFn>(
+        &mut self,
+        stage: TaskStage,
+        priority: TaskPriority,
+        task_fn: TaskFn,
+    ) -> ResultHandle<T>
+    where
+        T: Send + 'static,
+        TaskFn: FnOnce() -> T + Send + Sync + 'static,
+    {
+        let (result_tx, result_rx) = mpsc::channel();
+
+        let task_id = self.submit(stage, priority, move || {
+            // the handle may have been dropped, in which case nobody wants the result
+            let _ = result_tx.send(task_fn());
+        });
+
+        ResultHandle { task_id, result_rx }
+    }
+
     /// Block the calling thread until all tasks have finished
     /// Returns the TaskId of the new task in the thread pool
     pub fn block_until_finished(&self) {
+        if self.deterministic {
+            self.set_held(false);
+        }
+
         loop {
             let lock = self
                 .shared
@@ -99,6 +209,10 @@ impl Tasks {
                 log::error!("error blocking until finished: {}", e);
             }
         }
+
+        if self.deterministic {
+            self.set_held(true);
+        }
     }
 
     /// Attempt to cancel a submitted task if it is still pending execution
@@ -119,13 +233,118 @@ impl Tasks {
             .is_some()
     }
 
-    /// Returns the original number of workers in the pool
-    pub fn total_worker_count(&mut self) -> usize {
+    /// Execute every pending task, then stop and join the worker threads. Tasks submitted
+    /// afterwards never run. Called automatically when the `Tasks` is dropped
+    pub fn shutdown(&mut self) {
+        if self.worker_threads.is_empty() {
+            return;
+        }
+
+        self.block_until_finished();
+
+        // set the `terminate` flag to true, so that worker threads exit instead of waiting for
+        // another task
+        let mut lock = self
+            .shared
+            .mutex
+            .lock()
+            .expect("`Tasks` mutex poisoned");
+        lock.terminate = true;
+        drop(lock);
+
+        // wake the sleeping workers so that they see the flag
+        self.shared
+            .pending_task_cond
+            .notify_all();
+
+        Self::join_workers(self.worker_threads.drain(..));
+    }
+
+    /// Returns the number of worker threads in the pool
+    pub fn total_worker_count(&self) -> usize {
         self.thread_count
     }
 
+    /// Grow or shrink the pool to the given number of worker threads, at least one. New workers
+    /// don't prefer any stage and are allowed to start tasks straight away. When shrinking, the
+    /// workers with the highest indices finish their current task and are joined, so reserved
+    /// workers are the last to go. A deterministic `Tasks` keeps its single worker
+    pub fn set_worker_count(&mut self, worker_count: usize) {
+        if self.deterministic {
+            return;
+        }
+
+        let worker_count = worker_count.max(1);
+        let old_worker_count = self.thread_count;
+
+        let mut lock = self
+            .shared
+            .mutex
+            .lock()
+            .expect("`Tasks` mutex poisoned");
+        lock.worker_count = worker_count;
+
+        if worker_count > old_worker_count {
+            lock.worker_limit += worker_count - old_worker_count;
+            drop(lock);
+
+            self.worker_threads
+                .extend((old_worker_count..worker_count).map(|worker_index| {
+                    let shared = self.shared.clone();
+                    std::thread::spawn(move || Self::worker(shared, worker_index, None))
+                }));
+        } else {
+            lock.worker_limit = lock.worker_limit.min(worker_count);
+            drop(lock);
+
+            // wake the surplus workers so that they see they have been retired
+            self.shared
+                .pending_task_cond
+                .notify_all();
+
+            Self::join_workers(self.worker_threads.drain(worker_count..));
+        }
+
+        self.thread_count = worker_count;
+    }
+
+    /// Set the number of worker threads allowed to start new tasks, clamped to between one and
+    /// the total number of workers. Reserved workers are the last to be disallowed, so a limit of
+    /// at least the number of reserved workers keeps every stage progressing.
+    /// Workers over the limit finish their current task and then sleep until the limit is raised
+    pub fn set_worker_limit(&mut self, worker_limit: usize) {
+        let mut lock = self
+            .shared
+            .mutex
+            .lock()
+            .expect("`Tasks` mutex poisoned");
+        let worker_limit = worker_limit.clamp(1, self.thread_count);
+
+        // called every frame, so avoid waking the parked workers unless they may now start tasks.
+        // Workers over a lowered limit park themselves when they next look for a task
+        let raised = worker_limit > lock.worker_limit;
+        lock.worker_limit = worker_limit;
+
+        if raised {
+            self.shared
+                .pending_task_cond
+                .notify_all();
+        }
+    }
+
+    /// Returns the number of worker threads allowed to start new tasks
+    pub fn worker_limit(&self) -> usize {
+        let lock = self
+            .shared
+            .mutex
+            .lock()
+            .expect("`Tasks` mutex poisoned");
+
+        lock.worker_limit
+    }
+
     /// Returns the current number of active workers in the pool
-    pub fn active_worker_count(&mut self) -> usize {
+    pub fn active_worker_count(&self) -> usize {
         let lock = self
             .shared
             .mutex
@@ -135,8 +354,31 @@ impl Tasks {
         lock.active_worker_threads
     }
 
+    /// Returns the number of tasks in the given stage waiting to be executed
+    pub fn pending_task_count(&self, stage: TaskStage) -> usize {
+        let lock = self
+            .shared
+            .mutex
+            .lock()
+            .expect("`Tasks` mutex poisoned");
+
+        lock.pending_tasks
+            .iter()
+            .filter(|(_, task)| task.stage == stage)
+            .count()
+    }
+
+    /// Join the given worker threads, which must have been told to exit
+    fn join_workers(worker_threads: impl Iterator<Item = JoinHandle<()>>) {
+        for worker_thread in worker_threads {
+            if worker_thread.join().is_err() {
+                log::error!("task worker thread panicked");
+            }
+        }
+    }
+
     /// Function run on the worker threads
-    fn worker(shared: Arc<TasksShared>) {
+    fn worker(shared: Arc<TasksShared>, worker_index: usize, preferred_stage: Option<TaskStage>) {
         loop {
             let mut lock = shared
                 .mutex
@@ -147,22 +389,30 @@ impl Tasks {
             lock = shared
                 .pending_task_cond
                 .wait_while(lock, |info| {
-                    info.pending_tasks.is_empty() && !info.terminate
+                    let over_limit = worker_index >= info.worker_limit;
+                    let retired = worker_index >= info.worker_count;
+                    (info.pending_tasks.is_empty() || info.held || over_limit)
+                        && !info.terminate
+                        && !retired
                 })
                 .expect("`Tasks` mutex poisoned");
 
             // check if the thread should terminate
-            if lock.terminate {
+            if lock.terminate || worker_index >= lock.worker_count {
                 break;
             }
 
-            // get the task with the lowest priority value and remove it from the list
+            // get the task with the lowest priority value, preferring tasks from this worker's
+            // stage, and remove it from the list
             let next_task_index = lock
                 .pending_tasks
                 .iter()
                 .enumerate()
                 .map(|(index, (_task_id, task))| (index, task))
-                .min_by(|(_, task_a), (_, task_b)| task_a.priority.cmp(&task_b.priority))
+                .min_by_key(|(_, task)| {
+                    let other_stage = preferred_stage.is_some_and(|stage| stage != task.stage);
+                    (other_stage, task.priority)
+                })
                 .expect("tasks should not be empty")
                 .0;
             let next_task = lock
@@ -194,14 +444,7 @@ impl Tasks {
 
 impl Drop for Tasks {
     fn drop(&mut self) {
-        // set the `terminate` flag to true, so that worker threads will terminate after their
-        // current task
-        let mut lock = self
-            .shared
-            .mutex
-            .lock()
-            .expect("`Tasks` mutex poisoned");
-        lock.terminate = true;
+        self.shutdown();
     }
 }
 
@@ -211,6 +454,60 @@ impl Drop for Tasks {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TaskId(usize);
 
+/// Handle to the return value of a task submitted with `Tasks::submit_with_result`
+#[derive(Debug)]
+pub struct ResultHandle<T> {
+    /// ID of the task, e.g. for cancelling it
+    task_id: TaskId,
+    /// Receiver for the return value, whose sender is dropped without sending if the task is
+    /// cancelled or panics
+    result_rx: Receiver<T>,
+}
+
+impl<T> ResultHandle<T> {
+    /// ID of the task producing the result
+    pub fn task_id(&self) -> TaskId {
+        self.task_id
+    }
+
+    /// Returns the result of the task if it has finished, without blocking.
+    /// NB: The result can only be received once; later calls return `Cancelled`
+    pub fn try_recv(&self) -> TaskResult<T> {
+        match self.result_rx.try_recv() {
+            Ok(result) => TaskResult::Ready(result),
+            Err(TryRecvError::Empty) => TaskResult::Pending,
+            Err(TryRecvError::Disconnected) => TaskResult::Cancelled,
+        }
+    }
+
+    /// Block the calling thread until the task has finished and return its result, or None if it
+    /// was cancelled or panicked.
+    /// NB: this never returns for a task held by a deterministic `Tasks`
+    pub fn recv(&self) -> Option<T> {
+        self.result_rx.recv().ok()
+    }
+}
+
+/// State of the result of a task submitted with `Tasks::submit_with_result`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskResult<T> {
+    /// The task is pending or executing
+    Pending,
+    /// The task has finished and returned this value
+    Ready(T),
+    /// The task was cancelled or panicked, so there will be no result
+    Cancelled,
+}
+
+/// Pipeline stage of a task submitted to `Tasks`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TaskStage {
+    /// Generating chunks that have entered a load area
+    Generation,
+    /// Building meshes for chunks that are already loaded
+    Meshing,
+}
+
 /// Priority of a task submitted to `Tasks`
 /// As is tradition, smaller priority values represent higher priorities
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -220,6 +517,9 @@ pub struct TaskPriority {
     pub class_priority: i32,
     /// Additional priority allowing tasks to be ordered within classes
     pub priority_within_class: i32,
+    /// Orders tasks whose priorities are otherwise equal, so that the order doesn't depend on the
+    /// order they were submitted in. Chunk tasks use the position of their chunk
+    pub tie_breaker: [i32; 3],
 }
 
 /// Struct shared between `Tasks` and the worker threads
@@ -236,14 +536,271 @@ struct TasksShared {
 struct TasksMutex {
     /// Vec of tasks waiting to be executed
     pending_tasks: Vec<(TaskId, PendingTask)>,
-    /// Flag for the worker threads to terminate themselves after the `Tasks` is dropped
+    /// Flag preventing the worker threads from starting new tasks, used by deterministic mode
+    held: bool,
+    /// Flag for the worker threads to terminate themselves after the `Tasks` is shut down
     terminate: bool,
     /// Number of worker threads that are currently executing a task
     active_worker_threads: usize,
+    /// Number of worker threads allowed to start new tasks. Workers with an index at or above
+    /// this sleep until it is raised
+    worker_limit: usize,
+    /// Number of worker threads in the pool. Workers with an index at or above this exit
+    worker_count: usize,
 }
 
 /// Represents a task that has been submitted to `Tasks` and is waiting to be executed
 struct PendingTask {
     task_fn: Box<dyn FnOnce() + Send + Sync>,
+    stage: TaskStage,
     priority: TaskPriority,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn meshing_progresses_while_generation_is_backlogged() {
+        let mut tasks = Tasks::new(2, &[(TaskStage::Generation, 1), (TaskStage::Meshing, 1)]);
+
+        // a large backlog of generation tasks, all more urgent than the meshing task
+        for _ in 0..200 {
+            tasks.submit(TaskStage::Generation, TaskPriority::default(), || {
+                std::thread::sleep(Duration::from_millis(10));
+            });
+        }
+
+        let (meshed_tx, meshed_rx) = mpsc::channel();
+        tasks.submit(
+            TaskStage::Meshing,
+            TaskPriority {
+                class_priority: 10,
+                ..Default::default()
+            },
+            move || meshed_tx.send(()).unwrap(),
+        );
+
+        meshed_rx
+            .recv_timeout(Duration::from_secs(1))
+            .expect("meshing task should not be starved by generation tasks");
+        assert!(tasks.pending_task_count(TaskStage::Generation) > 0);
+        assert_eq!(tasks.pending_task_count(TaskStage::Meshing), 0);
+    }
+
+    #[test]
+    fn worker_limit_bounds_concurrent_tasks() {
+        let mut tasks = Tasks::new(3, &[]);
+        tasks.set_worker_limit(0);
+        assert_eq!(tasks.worker_limit(), 1);
+        tasks.set_worker_limit(10);
+        assert_eq!(tasks.worker_limit(), 3);
+
+        let run_tasks = |tasks: &mut Tasks| {
+            let running = Arc::new(AtomicUsize::new(0));
+            let max_running = Arc::new(AtomicUsize::new(0));
+
+            for _ in 0..6 {
+                let running = running.clone();
+                let max_running = max_running.clone();
+                tasks.submit(TaskStage::Generation, TaskPriority::default(), move || {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+
+            tasks.block_until_finished();
+            max_running.load(Ordering::SeqCst)
+        };
+
+        tasks.set_worker_limit(1);
+        assert_eq!(run_tasks(&mut tasks), 1);
+
+        // raising the limit wakes the sleeping workers
+        tasks.set_worker_limit(3);
+        assert!(run_tasks(&mut tasks) > 1);
+    }
+
+    #[test]
+    fn deterministic_tasks_run_in_priority_then_tie_breaker_order() {
+        let priorities = [
+            (1, 5, [0, 0, 1]),
+            (0, 3, [2, 0, 0]),
+            (1, 5, [0, 0, 0]),
+            (0, 3, [1, 9, 9]),
+            (0, 7, [0, 0, 0]),
+            (1, 5, [-1, 4, 0]),
+        ]
+        .map(|(class_priority, priority_within_class, tie_breaker)| TaskPriority {
+            class_priority,
+            priority_within_class,
+            tie_breaker,
+        });
+
+        let run = || {
+            let mut tasks = Tasks::new_deterministic();
+            let order = Arc::new(Mutex::new(Vec::new()));
+
+            // stages are ignored in deterministic mode
+            for (index, priority) in priorities.into_iter().enumerate() {
+                let stage = if index % 2 == 0 {
+                    TaskStage::Generation
+                } else {
+                    TaskStage::Meshing
+                };
+                let order = order.clone();
+                tasks.submit(stage, priority, move || order.lock().unwrap().push(index));
+            }
+
+            // nothing runs until the tasks are flushed
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(order.lock().unwrap().is_empty());
+
+            tasks.block_until_finished();
+            let order = order.lock().unwrap().clone();
+            order
+        };
+
+        let order = run();
+        assert_eq!(order, vec![3, 1, 4, 5, 2, 0]);
+        assert_eq!(run(), order);
+    }
+
+    #[test]
+    fn cancelled_tasks_never_run() {
+        let mut tasks = Tasks::new_deterministic();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+
+        let task_ids = [0, 1].map(|index| {
+            let ran = ran.clone();
+            tasks.submit(TaskStage::Meshing, TaskPriority::default(), move || {
+                ran.lock().unwrap().push(index)
+            })
+        });
+
+        assert!(tasks.cancel_if_pending(task_ids[0]));
+        assert_eq!(tasks.pending_task_count(TaskStage::Meshing), 1);
+
+        tasks.block_until_finished();
+        assert_eq!(*ran.lock().unwrap(), vec![1]);
+
+        // tasks that have run can no longer be cancelled
+        assert!(!tasks.cancel_if_pending(task_ids[1]));
+    }
+
+    #[test]
+    fn shutdown_runs_pending_tasks_and_joins_workers() {
+        let mut tasks = Tasks::new(3, &[(TaskStage::Meshing, 1)]);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        for index in 0..50 {
+            let ran = ran.clone();
+            let stage = if index % 2 == 0 {
+                TaskStage::Generation
+            } else {
+                TaskStage::Meshing
+            };
+            tasks.submit(stage, TaskPriority::default(), move || {
+                std::thread::sleep(Duration::from_millis(1));
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        tasks.shutdown();
+
+        // each worker thread holds a clone of the shared state until it exits
+        assert_eq!(ran.load(Ordering::SeqCst), 50);
+        assert!(tasks.worker_threads.is_empty());
+        assert_eq!(Arc::strong_count(&tasks.shared), 1);
+    }
+
+    #[test]
+    fn set_worker_count_grows_and_shrinks_the_pool() {
+        let mut tasks = Tasks::new(2, &[(TaskStage::Generation, 1)]);
+
+        let max_running_tasks = |tasks: &mut Tasks| {
+            let running = Arc::new(AtomicUsize::new(0));
+            let max_running = Arc::new(AtomicUsize::new(0));
+
+            for _ in 0..12 {
+                let running = running.clone();
+                let max_running = max_running.clone();
+                tasks.submit(TaskStage::Meshing, TaskPriority::default(), move || {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+
+            tasks.block_until_finished();
+            max_running.load(Ordering::SeqCst)
+        };
+
+        tasks.set_worker_count(4);
+        assert_eq!(tasks.total_worker_count(), 4);
+        assert_eq!(tasks.worker_limit(), 4);
+        assert!(max_running_tasks(&mut tasks) > 2);
+
+        // the surplus workers are joined, dropping their clones of the shared state
+        tasks.set_worker_count(1);
+        assert_eq!(tasks.total_worker_count(), 1);
+        assert_eq!(tasks.worker_limit(), 1);
+        assert_eq!(Arc::strong_count(&tasks.shared), 2);
+        assert_eq!(max_running_tasks(&mut tasks), 1);
+    }
+
+    #[test]
+    fn dropping_runs_pending_tasks_and_joins_workers() {
+        let mut tasks = Tasks::new(3, &[(TaskStage::Meshing, 1)]);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        for index in 0..50 {
+            let ran = ran.clone();
+            let stage = if index % 2 == 0 {
+                TaskStage::Generation
+            } else {
+                TaskStage::Meshing
+            };
+            tasks.submit(stage, TaskPriority::default(), move || {
+                std::thread::sleep(Duration::from_millis(1));
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        // each worker thread holds a clone of the shared state until it exits
+        let shared = Arc::downgrade(&tasks.shared);
+        drop(tasks);
+
+        assert_eq!(ran.load(Ordering::SeqCst), 50);
+        assert!(shared.upgrade().is_none());
+    }
+
+    #[test]
+    fn task_results_are_received_on_the_submitting_thread() {
+        let mut tasks = Tasks::new_deterministic();
+
+        let sum = tasks.submit_with_result(TaskStage::Meshing, TaskPriority::default(), || {
+            (1..=10).sum::<i32>()
+        });
+        let cancelled =
+            tasks.submit_with_result(TaskStage::Meshing, TaskPriority::default(), || 0);
+        assert_eq!(sum.try_recv(), TaskResult::Pending);
+
+        assert!(tasks.cancel_if_pending(cancelled.task_id()));
+        assert_eq!(cancelled.try_recv(), TaskResult::Cancelled);
+
+        tasks.block_until_finished();
+        assert_eq!(sum.try_recv(), TaskResult::Ready(55));
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+/// Decides how many task worker threads should be active, adding workers while there is a
+/// backlog of tasks and the frame time is within budget, and removing them when there is no
+/// backlog or the frame time is over budget, to save power when there is nothing to do.
+/// The result is intended for `Tasks::set_worker_limit`
+#[derive(Clone, Debug)]
+pub struct WorkerScaling {
+    /// Frame time above which workers are removed, as they compete with the main thread
+    pub frame_budget: Duration,
+    /// Fewest workers to keep active, e.g. the number of reserved workers
+    pub min_workers: usize,
+    /// Most workers to make active, e.g. the number of worker threads
+    pub max_workers: usize,
+    /// Number of frames to wait after changing the worker count before changing it again, so
+    /// that the frame time has time to respond
+    pub frames_between_changes: usize,
+    /// Current number of workers that should be active
+    worker_count: usize,
+    /// Exponential moving average of the frame time in seconds
+    smoothed_frame_time: f32,
+    /// Number of frames since the worker count last changed
+    frames_since_change: usize,
+}
+
+impl WorkerScaling {
+    /// Weight of the latest frame time in the moving average
+    const SMOOTHING: f32 = 0.1;
+
+    /// Fraction of the frame budget below which the frame time must be for a worker to be added
+    const HEADROOM: f32 = 0.8;
+
+    /// Starts with every worker active, so that the initial burst of chunk loading is as fast as
+    /// possible
+    pub fn new(frame_budget: Duration, min_workers: usize, max_workers: usize) -> Self {
+        Self {
+            frame_budget,
+            min_workers,
+            max_workers,
+            frames_between_changes: 30,
+            worker_count: max_workers,
+            smoothed_frame_time: 0.0,
+            frames_since_change: 0,
+        }
+    }
+
+    /// Called once per frame with the duration of the previous frame and the number of tasks
+    /// waiting to be executed. Returns the number of workers that should be active
+    pub fn update(&mut self, frame_time: Duration, pending_task_count: usize) -> usize {
+        self.smoothed_frame_time +=
+            (frame_time.as_secs_f32() - self.smoothed_frame_time) * Self::SMOOTHING;
+        self.frames_since_change += 1;
+
+        if self.frames_since_change >= self.frames_between_changes {
+            let budget = self.frame_budget.as_secs_f32();
+            let over_budget = self.smoothed_frame_time > budget;
+            let has_headroom = self.smoothed_frame_time < budget * Self::HEADROOM;
+
+            let new_worker_count = if pending_task_count == 0 || over_budget {
+                self.worker_count.saturating_sub(1)
+            } else if has_headroom {
+                self.worker_count + 1
+            } else {
+                self.worker_count
+            }
+            .clamp(self.min_workers, self.max_workers);
+
+            if new_worker_count != self.worker_count {
+                self.worker_count = new_worker_count;
+                self.frames_since_change = 0;
+            }
+        }
+
+        self.worker_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `frames` frames with the given frame time and backlog, returning the final worker count
+    fn run(scaling: &mut WorkerScaling, frames: usize, frame_ms: u64, pending: usize) -> usize {
+        (0..frames).fold(scaling.worker_count, |_, _| {
+            scaling.update(Duration::from_millis(frame_ms), pending)
+        })
+    }
+
+    #[test]
+    fn scales_with_backlog_and_headroom() {
+        let mut scaling = WorkerScaling::new(Duration::from_millis(16), 2, 8);
+        assert_eq!(scaling.worker_count, 8);
+
+        // idle: scale down to the minimum, one worker at a time
+        assert_eq!(run(&mut scaling, 30, 5, 0), 7);
+        assert_eq!(run(&mut scaling, 1000, 5, 0), 2);
+
+        // backlog with plenty of headroom: scale back up to the maximum
+        assert_eq!(run(&mut scaling, 1000, 5, 100), 8);
+
+        // backlog but over budget: workers are removed to give the main thread time
+        assert_eq!(run(&mut scaling, 1000, 30, 100), 2);
+
+        // backlog and close to the budget: the worker count holds steady
+        let mut scaling = WorkerScaling::new(Duration::from_millis(16), 2, 8);
+        assert_eq!(run(&mut scaling, 1000, 14, 100), 8);
+    }
+}
@@ -1,29 +1,63 @@
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use generational_arena::{Arena, Index};
 use glam::{IVec3, Vec3};
 use itertools::Itertools;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use self::{
-    chunk::{Chunk, CHUNK_SIZE, CHUNK_SIZE_RECIP},
+    chunk::{
+        compression::CompressedChunk, Chunk, CHUNK_SIZE, CHUNK_SIZE_I32, CHUNK_SIZE_RECIP,
+        CHUNK_SIZE_SQUARED, CHUNK_SIZE_U32,
+    },
     event::TerrainEvent,
+    generator::{GenerationParams, NoiseGenerator, WorldGenerator},
+    lighting::{
+        BlockBox, ChunkLight, EmittedLight, RelightJob, RelitBoxes, Skylight, MAX_LIGHT_DISTANCE,
+    },
     load_area::{LoadArea, LoadAreaState},
-    position_types::{ChunkPosition, GlobalBlockPosition},
+    persistence::ChunkStore,
+    position_types::{ChunkPosition, GlobalBlockPosition, LocalBlockPosition},
+    structure::{PlacementMode, Structure},
 };
 use crate::{
-    block::BlockId,
-    tasks::{TaskPriority, Tasks},
-    util::vector_map::VectorMapExt,
-    CHUNK_LOADING_PRIORITY,
+    block::{
+        behaviour::{self, BlockAccess},
+        BlockId, BLOCKS,
+    },
+    tasks::{ResultHandle, TaskId, TaskPriority, TaskResult, TaskStage, Tasks},
+    util::{face::{FaceIndex, FACE_NORMALS}, size::AsSize3, vector_map::VectorMapExt},
+    CHUNK_LOADING_PRIORITY, LIGHT_PROPAGATION_PRIORITY,
+};
+#[cfg(test)]
+use self::{
+    chunk::{border::ChunkBorder, side::ChunkSide},
+    lighting::ChunkLightSnapshot,
+};
+#[cfg(test)]
+use crate::render::{
+    self,
+    terrain::{meshing::MeshingOptions, ChunkMeshData},
 };
 
+/// Time between writing the edited chunks to the chunk store, which saves them in the background
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
 pub mod chunk;
 pub mod event;
+pub mod generator;
 pub mod lighting;
 pub mod load_area;
+pub mod persistence;
 pub mod position_types;
 
-mod temporary_generation;
+pub mod structure;
 
 /// Manages the voxel terrain, responsible for loading/unloading chunks and submitting terrain
 /// generation tasks
@@ -35,15 +69,53 @@ pub struct Terrain {
     load_areas: Arena<LoadArea>,
     /// Terrain events
     events: Vec<TerrainEvent>,
-    /// Sender for loaded chunks
-    loaded_chunk_tx: Sender<Chunk>,
+    /// Sender for loaded chunks, along with the generation they were generated for. Chunks are
+    /// compressed while they wait in the channel
+    loaded_chunk_tx: Sender<(u32, CompressedChunk)>,
     /// Receiver for loaded chunks
-    loaded_chunk_rx: Receiver<Chunk>,
+    loaded_chunk_rx: Receiver<(u32, CompressedChunk)>,
+    /// Parameters used to generate new chunks
+    generation_params: GenerationParams,
+    /// Generator of new chunks, shared with the generation tasks
+    generator: Arc<dyn WorldGenerator>,
+    /// Incremented by `regenerate`, so that chunks generated with the old parameters by tasks
+    /// that were already running can be discarded
+    generation: u32,
+    /// Generation tasks that have been submitted but whose chunks have not been received
+    generation_tasks: FxHashMap<ChunkPosition, TaskId>,
+    /// Vertical extent of the world, outside which blocks can't be placed
+    world_bounds: WorldBounds,
+    /// Progress of the mesh of each loaded chunk that has been queued for meshing, as reported by
+    /// the renderer
+    mesh_progress: FxHashMap<ChunkPosition, MeshProgress>,
+    /// Sender for mesh progress reports
+    mesh_progress_tx: Sender<(ChunkPosition, MeshProgress)>,
+    /// Receiver for mesh progress reports
+    mesh_progress_rx: Receiver<(ChunkPosition, MeshProgress)>,
+    /// Where edited chunks are saved, and where chunks are looked for before generating them
+    chunk_store: Option<ChunkStore>,
+    /// Loaded chunks that have been edited since they were last saved
+    unsaved_chunks: FxHashSet<ChunkPosition>,
+    /// When the edited chunks were last saved
+    last_save: Instant,
+    /// Boxes of blocks whose light from emitting blocks may be out of date after the edits and
+    /// chunk loads since the last relight task was started
+    stale_light: Vec<BlockBox>,
+    /// Boxes of blocks whose skylight may be out of date after the edits and chunk loads since
+    /// the last relight task was started
+    stale_skylight: Vec<BlockBox>,
+    /// Task recomputing the light of the boxes that were stale when it was started, if one is
+    /// running, and the boxes whose light from emitting blocks it recomputes
+    relight_task: Option<(ResultHandle<RelitBoxes>, Vec<BlockBox>)>,
 }
 
 impl Terrain {
-    pub fn new() -> Self {
+    /// Create an empty terrain whose chunks are generated by a `NoiseGenerator` with the given
+    /// parameters
+    pub fn new(generation_params: GenerationParams) -> Self {
         let (loaded_chunk_tx, loaded_chunk_rx) = mpsc::channel();
+        let (mesh_progress_tx, mesh_progress_rx) = mpsc::channel();
+        let world_bounds = WorldBounds::default();
 
         Self {
             chunks: Arena::new(),
@@ -51,18 +123,41 @@ impl Terrain {
             events: Vec::new(),
             loaded_chunk_tx,
             loaded_chunk_rx,
+            generation_params,
+            generator: Arc::new(NoiseGenerator::new(generation_params, world_bounds)),
+            generation: 0,
+            generation_tasks: FxHashMap::default(),
+            world_bounds,
+            mesh_progress: FxHashMap::default(),
+            mesh_progress_tx,
+            mesh_progress_rx,
+            chunk_store: None,
+            unsaved_chunks: FxHashSet::default(),
+            last_save: Instant::now(),
+            stale_light: Vec::new(),
+            stale_skylight: Vec::new(),
+            relight_task: None,
         }
     }
 
     /// Called each frame to update the terrain
     pub fn update(&mut self, tasks: &mut Tasks, camera_pos: Vec3) {
         // check for newly loaded chunks
-        while let Ok(chunk) = self.loaded_chunk_rx.try_recv() {
-            self.finished_loading_chunk(chunk);
+        while let Ok((generation, chunk)) = self.loaded_chunk_rx.try_recv() {
+            // chunks generated before the terrain was regenerated are out of date
+            if generation == self.generation {
+                self.generation_tasks.remove(&chunk.position());
+                self.finished_loading_chunk(chunk.decompress());
+            }
         }
 
-        self.check_chunks_to_unload();
+        self.receive_mesh_progress();
+        if self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.save_edited_chunks(tasks);
+        }
+        self.check_chunks_to_unload(tasks);
         self.check_chunks_to_load(tasks, camera_pos);
+        self.update_light(tasks);
 
         // mark all areas as clean
         for (_, area) in &mut self.load_areas {
@@ -83,6 +178,31 @@ impl Terrain {
             .and_then(|chunk_index| self.chunks.get(chunk_index))
     }
 
+    /// If the chunk at the given position is loaded and within the specified load area, builds its
+    /// mesh synchronously on the calling thread, for tests. Otherwise returns None.
+    /// The mesh is the same as the one built by the renderer's meshing tasks, using whichever
+    /// neighbouring chunks are loaded
+    #[cfg(test)]
+    pub fn mesh_chunk_now(
+        &self,
+        load_area_index: Index,
+        chunk_pos: &ChunkPosition,
+        options: MeshingOptions,
+    ) -> Option<ChunkMeshData> {
+        let chunk = self.get_chunk(load_area_index, chunk_pos)?;
+        let surrounding_sides = ChunkSide::get_surrounding_sides(*chunk_pos, self, load_area_index);
+        let border = ChunkBorder::get_border(*chunk_pos, self, load_area_index);
+        let light = ChunkLightSnapshot::take(*chunk_pos, self, load_area_index);
+
+        Some(render::terrain::mesh_chunk_now(
+            chunk.get_block_storage(),
+            &surrounding_sides,
+            &border,
+            light.as_ref(),
+            options,
+        ))
+    }
+
     /// If the chunk at the given position is loaded and within the specified load area, returns a
     /// mutable reference to that chunk in the chunk arena. Otherwise returns None
     pub fn get_chunk_mut(
@@ -114,52 +234,183 @@ impl Terrain {
     }
 
     /// If the global block position is inside a loaded chunk within this area, sets the block
-    /// ID at the given index to the provided ID and fire a `BlockModified` event
-    /// Otherwise returns false
+    /// ID at the given index to the provided ID and fire a `BlockModified` event, then runs the
+    /// `BlockBehaviour` callbacks of the blocks involved, which may edit further blocks.
+    /// Otherwise, or if the position is outside the world bounds, returns false
     pub fn set_block(
         &mut self,
         load_area_index: Index,
         global_block_pos: &GlobalBlockPosition,
         new_id: BlockId,
     ) -> bool {
+        if !self.world_bounds.contains(global_block_pos) {
+            return false;
+        }
+
+        let mut blocks = LoadAreaBlocks {
+            terrain: self,
+            load_area_index,
+        };
+
+        behaviour::set_block_with_callbacks(
+            &mut blocks,
+            &|block_id| BLOCKS[block_id.0 as usize].behaviour,
+            *global_block_pos,
+            new_id,
+        )
+    }
+
+    /// Same as `set_block`, but without running any block callbacks
+    fn set_block_without_callbacks(
+        &mut self,
+        load_area_index: Index,
+        global_block_pos: &GlobalBlockPosition,
+        new_id: BlockId,
+    ) -> bool {
+        if !self.world_bounds.contains(global_block_pos) {
+            return false;
+        }
+
         let (local_block_pos, chunk_pos) = global_block_pos.get_local_and_chunk_pos();
+        let sky_height_before = self.sky_height(global_block_pos.x(), global_block_pos.z());
 
         if let Some(chunk) = self.get_chunk_mut(load_area_index, &chunk_pos) {
+            let old_id = chunk.get_block(local_block_pos);
             chunk.set_block(local_block_pos, new_id);
             self.events
                 .push(TerrainEvent::BlockModified(chunk_pos, local_block_pos));
+            self.unsaved_chunks.insert(chunk_pos);
+            self.mark_light_stale(global_block_pos, old_id, new_id, sky_height_before);
             true
         } else {
             false
         }
     }
 
-    /// Raymarch through the chunks in the given load area, returning the position and normal of
-    /// the first block intersected by the ray
+    /// Like `set_block`, but refuses to place a solid block that would overlap `obstruction`, the
+    /// minimum and maximum corners of a bounding box such as the player's, so that they can't be
+    /// trapped inside it.
+    /// Returns true if the block was placed
+    pub fn place_block(
+        &mut self,
+        load_area_index: Index,
+        global_block_pos: &GlobalBlockPosition,
+        new_id: BlockId,
+        obstruction: Option<(Vec3, Vec3)>,
+    ) -> bool {
+        let block_min = global_block_pos.as_ivec3().as_vec3();
+        let block_max = block_min + Vec3::ONE;
+
+        let obstructed = obstruction.is_some_and(|(min, max)| {
+            min.cmplt(block_max).all() && max.cmpgt(block_min).all()
+        });
+
+        if obstructed && BLOCKS[new_id.0 as usize].is_collidable() {
+            return false;
+        }
+
+        self.set_block(load_area_index, global_block_pos, new_id)
+    }
+
+    /// Place a structure with its anchor at the given position, skipping any blocks that fall
+    /// outside the loaded chunks of the load area or the world bounds. Fires one `ChunkModified`
+    /// event for each chunk that was changed, so that each is remeshed once however many blocks it
+    /// received.
+    /// Returns the number of blocks placed
+    pub fn place_structure(
+        &mut self,
+        load_area_index: Index,
+        anchor_pos: &GlobalBlockPosition,
+        structure: &Structure,
+        mode: PlacementMode,
+    ) -> usize {
+        let mut modified_chunks = Vec::new();
+        let mut placed_count = 0;
+
+        for (global_block_pos, block_id) in structure.blocks_at(*anchor_pos) {
+            if !self.world_bounds.contains(&global_block_pos) {
+                continue;
+            }
+
+            let (local_block_pos, chunk_pos) = global_block_pos.get_local_and_chunk_pos();
+            let sky_height_before = self.sky_height(global_block_pos.x(), global_block_pos.z());
+
+            let Some(chunk) = self.get_chunk_mut(load_area_index, &chunk_pos) else {
+                continue;
+            };
+            let old_id = chunk.get_block(local_block_pos);
+            if !mode.should_replace(old_id) {
+                continue;
+            }
+
+            chunk.set_block(local_block_pos, block_id);
+            placed_count += 1;
+
+            self.mark_light_stale(&global_block_pos, old_id, block_id, sky_height_before);
+
+            if !modified_chunks.contains(&chunk_pos) {
+                modified_chunks.push(chunk_pos);
+            }
+        }
+
+        self.unsaved_chunks
+            .extend(modified_chunks.iter().copied());
+        self.events.extend(
+            modified_chunks
+                .into_iter()
+                .map(TerrainEvent::ChunkModified),
+        );
+
+        placed_count
+    }
+
+    /// Raymarch through the chunks in the given load area, returning the position, face and
+    /// distance of the first block intersected by the ray within `maximum_distance`, which is
+    /// measured in multiples of `ray_direction`. The ray steps from chunk
+    /// to chunk, passing through chunks that aren't loaded, and stops once it has left the world
+    /// bounds vertically and is moving away from them
     pub fn raymarch(
         &self,
         load_area_index: Index,
         ray_origin: Vec3,
         ray_direction: Vec3,
         maximum_distance: f32,
+        options: RaymarchOptions,
     ) -> Option<TerrainHit> {
-        pub const EPS: f32 = 1e-3;
-
         let dir_step = ray_direction.map(|component| if component >= 0.0 { 1.0 } else { 0.0 });
-        let dir_recip = ray_direction.recip();
+        let dir_recip = options.direction_recip(ray_direction);
 
         let mut t = 0.0;
         let mut previous_chunk_pos = None;
+        let mut current_chunk_pos = None;
+
+        for _ in 0..options.max_steps {
+            if t >= maximum_distance {
+                break;
+            }
 
-        while t < maximum_distance {
             let ray_pos = ray_origin + ray_direction * t;
 
-            let chunk_pos = ChunkPosition::from(
-                (ray_pos * CHUNK_SIZE_RECIP)
-                    .floor()
-                    .as_ivec3(),
-            );
-            if let Some(chunk) = self.get_chunk(load_area_index, &chunk_pos) {
+            let below_world = ray_pos.y < self.world_bounds.min_y as f32 && ray_direction.y <= 0.0;
+            let above_world =
+                ray_pos.y > (self.world_bounds.max_y + 1) as f32 && ray_direction.y >= 0.0;
+            if below_world || above_world {
+                break;
+            }
+
+            // a ray stepping backwards onto a chunk boundary stays in the same chunk for one more
+            // epsilon step, which mustn't count as entering it from itself
+            let chunk_pos = ChunkPosition::containing(ray_pos);
+            if current_chunk_pos != Some(chunk_pos) {
+                previous_chunk_pos = current_chunk_pos;
+                current_chunk_pos = Some(chunk_pos);
+            }
+
+            // rays pass straight through chunks without solid blocks, and skip unloaded chunks
+            let chunk = self
+                .get_chunk(load_area_index, &chunk_pos)
+                .filter(|chunk| !chunk.summary().is_empty());
+            if let Some(chunk) = chunk {
                 let ray_origin = ray_pos - chunk_pos.as_vec3() * (CHUNK_SIZE as f32);
 
                 if let Some(hit) = chunk.raymarch(
@@ -167,6 +418,7 @@ impl Terrain {
                     ray_direction,
                     previous_chunk_pos,
                     maximum_distance - t,
+                    options,
                 ) {
                     return Some(TerrainHit {
                         hit_pos: GlobalBlockPosition::from_local_and_chunk_pos(
@@ -174,6 +426,8 @@ impl Terrain {
                             chunk_pos,
                         ),
                         hit_normal: hit.hit_normal,
+                        face: hit.face,
+                        distance: t * ray_direction.length() + hit.distance,
                     });
                 }
             }
@@ -182,24 +436,68 @@ impl Terrain {
             let deltas = (dir_step - ray_pos * CHUNK_SIZE_RECIP).fract_gl()
                 * dir_recip
                 * (CHUNK_SIZE as f32);
-            t += deltas.min_element().max(EPS);
-
-            previous_chunk_pos = Some(chunk_pos);
+            t += deltas.min_element().max(options.epsilon);
         }
 
         None
     }
 
+    /// Stage of the loading and meshing pipeline that the chunk at the given position has
+    /// reached, as seen from the given load area
+    pub fn chunk_state(&self, load_area_index: Index, chunk_pos: &ChunkPosition) -> ChunkState {
+        let load_area = self
+            .load_areas
+            .get(load_area_index)
+            .expect("the load area at index `load_area_index` should exist");
+
+        if load_area.is_loading(chunk_pos) {
+            return ChunkState::Generating;
+        }
+        if !load_area.is_loaded(chunk_pos) {
+            return ChunkState::Unloaded;
+        }
+
+        match self.mesh_progress.get(chunk_pos) {
+            None | Some(MeshProgress::Discarded) => ChunkState::Generated,
+            Some(MeshProgress::Queued) => ChunkState::Meshing,
+            Some(MeshProgress::Finished) => ChunkState::Ready,
+        }
+    }
+
+    /// Number of chunks that are currently generating or meshing, e.g. for a loading indicator.
+    /// NB: chunks are only meshed once they are visible, so loaded chunks that haven't been queued
+    /// for meshing are not counted
+    pub fn pending_chunk_count(&self) -> usize {
+        let generating_count = self
+            .load_areas
+            .iter()
+            .flat_map(|(_, area)| area.loading_positions())
+            .collect::<FxHashSet<_>>()
+            .len();
+
+        let meshing_count = self
+            .mesh_progress
+            .values()
+            .filter(|progress| **progress == MeshProgress::Queued)
+            .count();
+
+        generating_count + meshing_count
+    }
+
+    /// Called by the renderer when a chunk's mesh has been queued, finished or discarded, so that
+    /// `chunk_state` can report it. Takes effect from the next call to `update`
+    pub fn report_mesh_progress(&self, chunk_pos: ChunkPosition, progress: MeshProgress) {
+        // the receiver is owned by `self`, so sending can't fail
+        let _ = self
+            .mesh_progress_tx
+            .send((chunk_pos, progress));
+    }
+
     /// The arena of loaded chunks
     pub fn chunks(&self) -> &Arena<Chunk> {
         &self.chunks
     }
 
-    /// Mutable reference to the arena of loaded chunks
-    pub fn chunks_mut(&mut self) -> &mut Arena<Chunk> {
-        &mut self.chunks
-    }
-
     /// The arena of areas around which chunks are loaded
     pub fn load_areas(&self) -> &Arena<LoadArea> {
         &self.load_areas
@@ -210,6 +508,17 @@ impl Terrain {
         &mut self.load_areas
     }
 
+    /// Request that the meshes of all loaded chunks are rebuilt, e.g. after the meshing options
+    /// change. The renderer rebuilds them in the background, keeping each old mesh until the new
+    /// one replaces it
+    pub fn remesh_all(&mut self) {
+        self.events.extend(
+            self.chunks
+                .iter()
+                .map(|(_, chunk)| TerrainEvent::ChunkRemeshRequested(chunk.position())),
+        );
+    }
+
     /// Returns an iterator over all events that have occurred since the last call to
     /// `clear_events()` in chronological order
     pub fn events(&self) -> impl Iterator<Item = &TerrainEvent> {
@@ -221,8 +530,163 @@ impl Terrain {
         self.events.clear();
     }
 
+    /// Called each frame to record the mesh progress reported since the last frame
+    fn receive_mesh_progress(&mut self) {
+        while let Ok((chunk_pos, progress)) = self.mesh_progress_rx.try_recv() {
+            // ignore reports for chunks that have been unloaded since
+            let is_loaded = self
+                .load_areas
+                .iter()
+                .any(|(_, area)| area.is_loaded(&chunk_pos));
+
+            match progress {
+                MeshProgress::Discarded => {
+                    self.mesh_progress.remove(&chunk_pos);
+                }
+                _ if is_loaded => {
+                    self.mesh_progress
+                        .insert(chunk_pos, progress);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Parameters used to generate new chunks
+    pub fn generation_params(&self) -> &GenerationParams {
+        &self.generation_params
+    }
+
+    /// Change the parameters used to generate new chunks. Chunks that are already loaded keep
+    /// their blocks until `regenerate` is called
+    pub fn set_generation_params(&mut self, params: GenerationParams) {
+        self.generation_params = params;
+        self.generator = Arc::new(NoiseGenerator::new(params, self.world_bounds));
+    }
+
+    /// Vertical extent of the world, outside which blocks can't be placed
+    pub fn world_bounds(&self) -> &WorldBounds {
+        &self.world_bounds
+    }
+
+    /// Change the vertical extent of the world. Edits are checked against the new bounds
+    /// immediately, but chunks that are already loaded keep their blocks until `regenerate` is
+    /// called
+    pub fn set_world_bounds(&mut self, world_bounds: WorldBounds) {
+        self.world_bounds = world_bounds;
+        self.generator = Arc::new(NoiseGenerator::new(self.generation_params, world_bounds));
+    }
+
+    /// Unload every chunk, so that they are generated again with the current generation
+    /// parameters. Edits that have not been saved are discarded, and chunks saved in the chunk
+    /// store are loaded from it rather than generated. Pending generation tasks are cancelled,
+    /// and the chunks of tasks that are already running are discarded when they arrive. The
+    /// chunks are queued for loading on the next call to `update`
+    pub fn regenerate(&mut self, tasks: &mut Tasks) {
+        self.unsaved_chunks.clear();
+
+        for (_, task_id) in self.generation_tasks.drain() {
+            tasks.cancel_if_pending(task_id);
+        }
+        self.generation = self.generation.wrapping_add(1);
+
+        let chunk_indices = self
+            .chunks
+            .iter()
+            .map(|(chunk_index, _)| chunk_index)
+            .collect_vec();
+        for chunk_index in chunk_indices {
+            self.unload_chunk(chunk_index);
+        }
+
+        for (_, load_area) in &mut self.load_areas {
+            for chunk_pos in load_area.loading_positions().collect_vec() {
+                load_area.mark_unloaded(&chunk_pos);
+            }
+            load_area.set_state(LoadAreaState::Dirty);
+        }
+    }
+
+    /// Returns the light of the block at the given position, or darkness if it isn't in a loaded
+    /// chunk
+    pub fn get_light(&self, global_block_pos: &GlobalBlockPosition) -> EmittedLight {
+        let (local_block_pos, chunk_pos) = global_block_pos.get_local_and_chunk_pos();
+
+        self.find_chunk(&chunk_pos)
+            .map_or(EmittedLight::DARK, |chunk| chunk.light().get(local_block_pos))
+    }
+
+    /// Returns the skylight of the block at the given position, or full skylight if it isn't in
+    /// a loaded chunk, matching how unloaded chunks are meshed
+    pub fn get_skylight(&self, global_block_pos: &GlobalBlockPosition) -> Skylight {
+        let (local_block_pos, chunk_pos) = global_block_pos.get_local_and_chunk_pos();
+
+        self.find_chunk(&chunk_pos)
+            .map_or(Skylight::FULL, |chunk| chunk.light().skylight(local_block_pos))
+    }
+
+    /// Set where edited chunks are saved and saved chunks are loaded from, or None to neither save
+    /// nor load chunks
+    pub fn set_chunk_store(&mut self, chunk_store: Option<ChunkStore>) {
+        self.chunk_store = chunk_store;
+    }
+
+    /// Save every edited chunk and wait until they are on disk. Called when exiting
+    pub fn save(&mut self, tasks: &mut Tasks) {
+        self.write_edited_chunks();
+
+        if let Some(chunk_store) = &mut self.chunk_store {
+            if let Err(e) = chunk_store.flush_now(tasks) {
+                log::error!("failed to save chunks: {e}");
+            }
+        }
+    }
+
+    /// Called every `SAVE_INTERVAL` to save the chunks edited since the last save in the
+    /// background, and to forget the saved regions that no loaded chunk belongs to
+    fn save_edited_chunks(&mut self, tasks: &mut Tasks) {
+        self.last_save = Instant::now();
+        self.write_edited_chunks();
+
+        if let Some(chunk_store) = &mut self.chunk_store {
+            chunk_store.flush(tasks);
+            chunk_store.evict_regions(
+                self.chunks
+                    .iter()
+                    .map(|(_, chunk)| chunk.position()),
+            );
+        }
+    }
+
+    /// Pass the chunks edited since they were last saved to the chunk store
+    fn write_edited_chunks(&mut self) {
+        let Some(chunk_store) = &mut self.chunk_store else {
+            self.unsaved_chunks.clear();
+            return;
+        };
+
+        for chunk_pos in self.unsaved_chunks.drain() {
+            let chunk = self
+                .load_areas
+                .iter()
+                .find_map(|(_, area)| area.get_chunk_index(&chunk_pos))
+                .and_then(|chunk_index| self.chunks.get(chunk_index));
+
+            if let Some(chunk) = chunk {
+                chunk_store.write_chunk(chunk);
+            }
+        }
+    }
+
     /// Called each frame to check for new chunks to load
     fn check_chunks_to_load(&mut self, tasks: &mut Tasks, camera_pos: Vec3) {
+        // areas that have moved may now contain chunks loaded or queued by other areas
+        for (_, area) in &mut self.load_areas {
+            if area.state().is_dirty() {
+                Self::share_chunks_with_area(area, &self.chunks, &self.generation_tasks);
+            }
+        }
+
         let load_queue = self
             .load_areas
             .iter()
@@ -243,27 +707,369 @@ impl Terrain {
         }
     }
 
-    /// Called each frame to check if any chunks should be unloaded
-    fn check_chunks_to_unload(&mut self) {
+    /// Called each frame to check if any chunks should be unloaded, or should no longer be
+    /// generated
+    fn check_chunks_to_unload(&mut self, tasks: &mut Tasks) {
         if self
             .load_areas
             .iter()
             .any(|(_, area)| area.state().is_dirty())
         {
+            // cancel the generation of chunks that have left every area, unless it has started
+            let cancelled_positions = self
+                .generation_tasks
+                .iter()
+                .filter(|(chunk_pos, _)| self.chunk_references(chunk_pos) == 0)
+                .filter(|(_, &task_id)| tasks.cancel_if_pending(task_id))
+                .map(|(&chunk_pos, _)| chunk_pos)
+                .collect_vec();
+
+            for chunk_pos in cancelled_positions {
+                self.generation_tasks.remove(&chunk_pos);
+                self.load_areas
+                    .iter_mut()
+                    .filter(|(_, load_area)| load_area.is_loading(&chunk_pos))
+                    .for_each(|(_, load_area)| load_area.mark_unloaded(&chunk_pos));
+            }
+
             let unload_queue = self
                 .chunks
                 .iter()
-                .filter(|(_, chunk)| {
-                    self.load_areas
-                        .iter()
-                        .all(|(_, area)| !area.is_within_area(&chunk.position()))
-                })
+                .filter(|(_, chunk)| self.chunk_references(&chunk.position()) == 0)
                 .map(|(chunk_index, _)| chunk_index)
                 .collect_vec();
 
+            // edited chunks are written to the chunk store as they are unloaded, so save them
+            // now rather than keeping them in memory until the next save
+            let unloaded_edited_chunk = unload_queue.iter().any(|&chunk_index| {
+                self.unsaved_chunks
+                    .contains(&self.chunks[chunk_index].position())
+            });
+
             for chunk_index in unload_queue {
                 self.unload_chunk(chunk_index);
             }
+
+            if unloaded_edited_chunk {
+                if let Some(chunk_store) = &mut self.chunk_store {
+                    chunk_store.flush(tasks);
+                }
+            }
+        }
+    }
+
+    /// Returns the loaded chunk at the given position in any load area. Light spreads between
+    /// chunks regardless of which areas they were loaded for
+    fn find_chunk(&self, chunk_pos: &ChunkPosition) -> Option<&Chunk> {
+        self.find_chunk_index(chunk_pos)
+            .and_then(|chunk_index| self.chunks.get(chunk_index))
+    }
+
+    /// Returns the index of the loaded chunk at the given position in any load area
+    fn find_chunk_index(&self, chunk_pos: &ChunkPosition) -> Option<Index> {
+        self.load_areas
+            .iter()
+            .find_map(|(_, area)| area.get_chunk_index(chunk_pos))
+    }
+
+    /// Returns the y coordinate of the highest solid block in the column at the given x and z
+    /// among the loaded chunks, or one below the bottom of the world if there is none. Blocks
+    /// above it are open to the sky
+    fn sky_height(&self, x: i32, z: i32) -> i32 {
+        let chunk_x = x.div_euclid(CHUNK_SIZE_I32);
+        let chunk_z = z.div_euclid(CHUNK_SIZE_I32);
+        let local_x = x.rem_euclid(CHUNK_SIZE_I32) as u32;
+        let local_z = z.rem_euclid(CHUNK_SIZE_I32) as u32;
+
+        self.chunk_ys_from_top()
+            .find_map(|chunk_y| {
+                let chunk = self.find_chunk(&ChunkPosition::new(chunk_x, chunk_y, chunk_z))?;
+                let y = chunk.summary().highest_solid_y(local_x, local_z)?;
+                Some(chunk_y * CHUNK_SIZE_I32 + y as i32)
+            })
+            .unwrap_or(self.world_bounds.min_y - 1)
+    }
+
+    /// Returns `sky_height` for every column of the chunks at the given x and z, ordered by z, then
+    /// x, looking up each chunk once
+    fn sky_heights_of_chunk_column(&self, chunk_x: i32, chunk_z: i32) -> Vec<i32> {
+        let mut heights = vec![None; CHUNK_SIZE_SQUARED];
+
+        for chunk_y in self.chunk_ys_from_top() {
+            let Some(chunk) = self.find_chunk(&ChunkPosition::new(chunk_x, chunk_y, chunk_z)) else {
+                continue;
+            };
+
+            for (column_index, height) in heights.iter_mut().enumerate() {
+                let (x, z) = (column_index % CHUNK_SIZE, column_index / CHUNK_SIZE);
+                *height = height.or_else(|| {
+                    let y = chunk.summary().highest_solid_y(x as u32, z as u32)?;
+                    Some(chunk_y * CHUNK_SIZE_I32 + y as i32)
+                });
+            }
+        }
+
+        heights
+            .into_iter()
+            .map(|height| height.unwrap_or(self.world_bounds.min_y - 1))
+            .collect()
+    }
+
+    /// Y coordinates of the chunks within the world bounds, from the top down
+    fn chunk_ys_from_top(&self) -> impl Iterator<Item = i32> {
+        let bottom = self.world_bounds.min_y.div_euclid(CHUNK_SIZE_I32);
+        let top = self.world_bounds.max_y.div_euclid(CHUNK_SIZE_I32);
+
+        (bottom..=top).rev()
+    }
+
+    /// Mark the light around a block that changed from `old_id` to `new_id` as out of date, given
+    /// the `sky_height` of its column before the change.
+    /// Blocks that start or stop blocking light only affect the light from emitting blocks if it
+    /// reaches them, i.e. if the block or one of its neighbours is lit. Blocks that become solid
+    /// or stop being solid also change whether the blocks below them are open to the sky
+    fn mark_light_stale(
+        &mut self,
+        global_block_pos: &GlobalBlockPosition,
+        old_id: BlockId,
+        new_id: BlockId,
+        sky_height_before: i32,
+    ) {
+        let (old_block, new_block) = (&BLOCKS[old_id.0 as usize], &BLOCKS[new_id.0 as usize]);
+        let pos = global_block_pos.as_ivec3();
+        let opacity_changed = old_block.is_opaque() != new_block.is_opaque();
+
+        let light_affected = old_block.emits_light()
+            || new_block.emits_light()
+            || opacity_changed
+                && (std::iter::once(IVec3::ZERO)
+                    .chain(FACE_NORMALS)
+                    .any(|offset| !self.get_light(&(*global_block_pos + offset)).is_dark())
+                    || self.light_is_pending((pos - 1, pos + 2)));
+        if light_affected {
+            // an edited block affects the light of blocks up to the distance light spreads
+            add_box(
+                &mut self.stale_light,
+                (pos - MAX_LIGHT_DISTANCE, pos + 1 + MAX_LIGHT_DISTANCE),
+            );
+        }
+
+        if opacity_changed || old_block.is_solid() != new_block.is_solid() {
+            // the blocks of the column between its old and new sky height were covered or
+            // uncovered
+            let sky_height_after = self.sky_height(pos.x, pos.z);
+            let lowest_y = pos.y.min(sky_height_before.min(sky_height_after) + 1);
+
+            add_box(
+                &mut self.stale_skylight,
+                (
+                    IVec3::new(pos.x, lowest_y, pos.z) - MAX_LIGHT_DISTANCE,
+                    pos + 1 + MAX_LIGHT_DISTANCE,
+                ),
+            );
+        }
+    }
+
+    /// True if the light from emitting blocks of any block in `block_box` is out of date, or is
+    /// being recomputed by the running relight task, so that the light stored in the chunks can't
+    /// be relied on there
+    fn light_is_pending(&self, (min, max): BlockBox) -> bool {
+        let running = self
+            .relight_task
+            .iter()
+            .flat_map(|(_, light_boxes)| light_boxes);
+
+        self.stale_light
+            .iter()
+            .chain(running)
+            .any(|&other_box| boxes_overlap((min, max), other_box))
+    }
+
+    /// Called each frame to store the light computed by the running relight task once it has
+    /// finished, and then to start a task recomputing the light of the boxes that have gone out
+    /// of date since. Only one relight task runs at a time, so that their light is stored in the
+    /// order the edits were made
+    fn update_light(&mut self, tasks: &mut Tasks) {
+        if let Some((task, _)) = &self.relight_task {
+            match task.try_recv() {
+                TaskResult::Pending => return,
+                TaskResult::Ready(relit) => {
+                    for ((min, max), light) in &relit.light {
+                        self.store_light(*min, *max, light, ChunkLight::set);
+                    }
+                    for ((min, max), skylight) in &relit.skylight {
+                        self.store_light(*min, *max, skylight, ChunkLight::set_skylight);
+                    }
+                }
+                TaskResult::Cancelled => log::error!("relight task panicked"),
+            }
+            self.relight_task = None;
+        }
+
+        if self.stale_light.is_empty() && self.stale_skylight.is_empty() {
+            return;
+        }
+
+        let light_boxes = self.stale_light.clone();
+        let job = self.take_relight_job();
+        let task = tasks.submit_with_result(
+            TaskStage::Generation,
+            TaskPriority {
+                class_priority: LIGHT_PROPAGATION_PRIORITY,
+                ..Default::default()
+            },
+            move || job.run(),
+        );
+        self.relight_task = Some((task, light_boxes));
+    }
+
+    /// Take the boxes whose light is out of date, along with a copy of the blocks and sky heights
+    /// around them that the light is propagated through
+    fn take_relight_job(&mut self) -> RelightJob {
+        let light_boxes = std::mem::take(&mut self.stale_light);
+
+        // there are no blocks outside the world bounds
+        let skylight_boxes = std::mem::take(&mut self.stale_skylight)
+            .into_iter()
+            .map(|(min, max)| {
+                (
+                    min.with_y(min.y.max(self.world_bounds.min_y)),
+                    max.with_y(max.y.min(self.world_bounds.max_y + 1)),
+                )
+            })
+            .filter(|(min, max)| min.y < max.y)
+            .collect_vec();
+
+        // chunks within the distance light spreads of a box, from the minimum to the maximum
+        // corner, inclusive
+        let chunk_range = |&(min, max): &BlockBox| {
+            (
+                (min - MAX_LIGHT_DISTANCE).div_euclid(IVec3::splat(CHUNK_SIZE_I32)),
+                (max - 1 + MAX_LIGHT_DISTANCE).div_euclid(IVec3::splat(CHUNK_SIZE_I32)),
+            )
+        };
+
+        let mut chunks = FxHashMap::default();
+        for (lo, hi) in light_boxes
+            .iter()
+            .chain(&skylight_boxes)
+            .map(chunk_range)
+        {
+            for (x, y, z) in itertools::iproduct!(lo.x..=hi.x, lo.y..=hi.y, lo.z..=hi.z) {
+                let chunk_pos = ChunkPosition::new(x, y, z);
+                if let Some(chunk) = self.find_chunk(&chunk_pos) {
+                    chunks
+                        .entry(chunk_pos)
+                        .or_insert_with(|| chunk.get_block_storage().clone());
+                }
+            }
+        }
+
+        let mut sky_heights = FxHashMap::default();
+        for (lo, hi) in skylight_boxes.iter().map(chunk_range) {
+            for (x, z) in itertools::iproduct!(lo.x..=hi.x, lo.z..=hi.z) {
+                sky_heights
+                    .entry((x, z))
+                    .or_insert_with(|| self.sky_heights_of_chunk_column(x, z));
+            }
+        }
+
+        RelightJob {
+            light_boxes,
+            skylight_boxes,
+            chunks,
+            sky_heights,
+        }
+    }
+
+    /// Store light computed for the box from `min` (inclusive) to `max` (exclusive), ordered by
+    /// z, then y, then x, in the loaded chunks with `set`, which returns true if the light of a
+    /// block changed. Fires a `ChunkLightChanged` event for each chunk whose mesh is affected
+    fn store_light<T: Copy>(
+        &mut self,
+        min: IVec3,
+        max: IVec3,
+        light: &[T],
+        set: impl Fn(&mut ChunkLight, LocalBlockPosition, T) -> bool,
+    ) {
+        let size = (max - min).as_size3();
+        let chunk_range = |axis: usize| {
+            min[axis].div_euclid(CHUNK_SIZE_I32)..=(max[axis] - 1).div_euclid(CHUNK_SIZE_I32)
+        };
+        let mut changed_chunks = FxHashSet::default();
+
+        for (x, y, z) in itertools::iproduct!(chunk_range(0), chunk_range(1), chunk_range(2)) {
+            let chunk_pos = ChunkPosition::new(x, y, z);
+            let Some(chunk_index) = self.find_chunk_index(&chunk_pos) else {
+                continue;
+            };
+            let chunk_light = self.chunks[chunk_index].light_mut();
+
+            // part of the box within the chunk, relative to the origin of the chunk
+            let chunk_min = chunk_pos.as_ivec3() * CHUNK_SIZE_I32;
+            let lo = min.max(chunk_min) - chunk_min;
+            let hi = max.min(chunk_min + CHUNK_SIZE_I32) - chunk_min;
+
+            for (z, y, x) in itertools::iproduct!(lo.z..hi.z, lo.y..hi.y, lo.x..hi.x) {
+                let pos = IVec3::new(x, y, z);
+                let new_light = light[size.flatten((chunk_min + pos - min).as_uvec3())];
+
+                if set(chunk_light, LocalBlockPosition::from(pos.as_uvec3()), new_light) {
+                    changed_chunks.insert(chunk_pos);
+
+                    // blocks on the edge of the chunk light the faces of the neighbouring chunks
+                    for face_normal in FACE_NORMALS {
+                        let neighbour_pos = pos + face_normal;
+                        if neighbour_pos.cmplt(IVec3::ZERO).any()
+                            || neighbour_pos.cmpge(IVec3::splat(CHUNK_SIZE_I32)).any()
+                        {
+                            changed_chunks.insert(chunk_pos + face_normal);
+                        }
+                    }
+                }
+            }
+
+            chunk_light.compact();
+        }
+
+        self.events.extend(
+            changed_chunks
+                .into_iter()
+                .map(TerrainEvent::ChunkLightChanged),
+        );
+    }
+
+    /// Number of load areas that contain the given chunk. A loaded chunk is only unloaded once no
+    /// area refers to it, so chunks shared by overlapping areas stay loaded while any of them
+    /// remains
+    fn chunk_references(&self, chunk_pos: &ChunkPosition) -> usize {
+        self.load_areas
+            .iter()
+            .filter(|(_, area)| area.contains_chunk(chunk_pos))
+            .count()
+    }
+
+    /// Rebuild the chunk states of an area that has moved from the chunks that are loaded or
+    /// loading, so that it shares the chunks loaded by other areas within its new bounds and
+    /// forgets chunks that were unloaded while they were outside its bounds
+    fn share_chunks_with_area(
+        area: &mut LoadArea,
+        chunks: &Arena<Chunk>,
+        generation_tasks: &FxHashMap<ChunkPosition, TaskId>,
+    ) {
+        area.mark_all_unloaded();
+
+        for chunk_pos in generation_tasks.keys() {
+            if area.is_within_bounds(chunk_pos) {
+                area.mark_loading(chunk_pos);
+            }
+        }
+
+        for (chunk_index, chunk) in chunks {
+            let chunk_pos = chunk.position();
+            if area.is_within_bounds(&chunk_pos) {
+                area.mark_loaded(&chunk_pos, chunk_index);
+            }
         }
     }
 
@@ -285,21 +1091,45 @@ impl Terrain {
             .filter(|(_, load_area)| load_area.is_within_bounds(&chunk_pos))
             .for_each(|(_, load_area)| load_area.mark_loading(&chunk_pos));
 
+        // chunks that were saved are read instead of generated
+        let saved_chunk = self
+            .chunk_store
+            .as_mut()
+            .and_then(|chunk_store| chunk_store.read_chunk(chunk_pos));
+
         // assign a higher priority to chunks closer to the camera
         let priority_within_class =
             Vec3::distance_squared(chunk_pos.as_vec3(), camera_pos / (CHUNK_SIZE as f32)) as i32;
 
-        // clone sender for the worker thread
+        // clone sender and generator for the worker thread
         let loaded_chunk_tx = self.loaded_chunk_tx.clone();
+        let generator = self.generator.clone();
+        let generation = self.generation;
 
-        tasks.submit(
+        let task_id = tasks.submit(
+            TaskStage::Generation,
             TaskPriority {
                 class_priority: CHUNK_LOADING_PRIORITY,
                 priority_within_class,
+                tie_breaker: chunk_pos.as_ivec3().to_array(),
             },
             move || {
-                let chunk = temporary_generation::generate_chunk(chunk_pos);
-                if let Err(e) = loaded_chunk_tx.send(chunk) {
+                let chunk = saved_chunk
+                    .and_then(|bytes| {
+                        persistence::load_chunk(chunk_pos, &bytes)
+                            .inspect_err(|e| {
+                                log::error!(
+                                    "failed to load chunk {:?}: {e}",
+                                    chunk_pos.as_ivec3()
+                                )
+                            })
+                            .ok()
+                    })
+                    .unwrap_or_else(|| {
+                        Chunk::new(chunk_pos, generator.generate_chunk(chunk_pos))
+                    });
+                let chunk = CompressedChunk::new(chunk);
+                if let Err(e) = loaded_chunk_tx.send((generation, chunk)) {
                     log::trace!(
                         "sending chunk from loading thread to main thread returned error: {}",
                         e
@@ -307,6 +1137,7 @@ impl Terrain {
                 }
             },
         );
+        self.generation_tasks.insert(chunk_pos, task_id);
     }
 
     /// Called once a chunk has finished loading and is ready to be added to the world
@@ -316,12 +1147,31 @@ impl Terrain {
         if !self
             .load_areas
             .iter()
-            .any(|(_, area)| area.is_within_area(&chunk.position()))
+            .any(|(_, area)| area.contains_chunk(&chunk.position()))
         {
             return;
         }
 
         let chunk_pos = chunk.position();
+        let chunk_min = chunk_pos.as_ivec3() * CHUNK_SIZE_I32;
+
+        // the solid blocks of the chunk cover the blocks below them that were open to the sky.
+        // Find the lowest such block before the chunk is added, while the old sky heights are
+        // still known
+        let lowest_covered_y = (!chunk.summary().is_empty())
+            .then(|| {
+                let heights_before = self.sky_heights_of_chunk_column(chunk_pos.x(), chunk_pos.z());
+
+                itertools::iproduct!(0..CHUNK_SIZE_U32, 0..CHUNK_SIZE_U32)
+                    .filter_map(|(z, x)| {
+                        let height_before = heights_before[(z * CHUNK_SIZE_U32 + x) as usize];
+                        let height = chunk_min.y + chunk.summary().highest_solid_y(x, z)? as i32;
+                        (height > height_before).then_some(height_before + 1)
+                    })
+                    .min()
+            })
+            .flatten();
+
         let chunk_index = self.chunks.insert(chunk);
 
         // inform the load areas that the chunk is loaded
@@ -332,6 +1182,38 @@ impl Terrain {
 
         self.events
             .push(TerrainEvent::ChunkLoaded(chunk_pos));
+
+        // light spreads out of the chunk from the blocks in it that emit light, and into it from
+        // the neighbouring chunks, which it can only enter through its faces
+        let has_emitters = self.chunks[chunk_index]
+            .summary()
+            .has_emitters();
+        let has_lit_neighbours = FACE_NORMALS.iter().any(|&face_normal| {
+            self.find_chunk(&(chunk_pos + face_normal))
+                .is_some_and(|chunk| !chunk.light().is_dark())
+        });
+
+        let light_is_pending = self.light_is_pending((chunk_min, chunk_min + CHUNK_SIZE_I32));
+
+        if has_emitters || has_lit_neighbours || light_is_pending {
+            add_box(
+                &mut self.stale_light,
+                (
+                    chunk_min - MAX_LIGHT_DISTANCE,
+                    chunk_min + CHUNK_SIZE_I32 + MAX_LIGHT_DISTANCE,
+                ),
+            );
+        }
+
+        // skylight spreads into and out of the chunk, and the blocks it covers are darkened
+        let lowest_y = lowest_covered_y.map_or(chunk_min.y, |y| y.min(chunk_min.y));
+        add_box(
+            &mut self.stale_skylight,
+            (
+                chunk_min.with_y(lowest_y) - MAX_LIGHT_DISTANCE,
+                chunk_min + CHUNK_SIZE_I32 + MAX_LIGHT_DISTANCE,
+            ),
+        );
     }
 
     /// Unload the chunk with the given position
@@ -345,6 +1227,14 @@ impl Terrain {
             .filter(|(_, load_area)| load_area.is_within_bounds(&chunk_pos))
             .for_each(|(_, load_area)| load_area.mark_unloaded(&chunk_pos));
 
+        self.mesh_progress.remove(&chunk_pos);
+
+        if self.unsaved_chunks.remove(&chunk_pos) {
+            if let Some(chunk_store) = &mut self.chunk_store {
+                chunk_store.write_chunk(chunk);
+            }
+        }
+
         self.events
             .push(TerrainEvent::ChunkUnloaded(
                 self.chunks[chunk_index]
@@ -355,8 +1245,1124 @@ impl Terrain {
     }
 }
 
+/// Stage of the loading and meshing pipeline that a chunk has reached
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    /// The chunk is not loaded or queued for loading
+    Unloaded,
+    /// The chunk is queued for generation or being generated
+    Generating,
+    /// The chunk is loaded but has not been queued for meshing
+    Generated,
+    /// The chunk's mesh is queued or being built
+    Meshing,
+    /// The chunk's mesh has been built and can be drawn
+    Ready,
+}
+
+/// Progress of a chunk's mesh, reported by the renderer with `Terrain::report_mesh_progress`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshProgress {
+    /// The mesh is queued or being built
+    Queued,
+    /// The mesh has been built
+    Finished,
+    /// The mesh was discarded by the renderer, e.g. because its batch was reused
+    Discarded,
+}
+
+/// Vertical extent of the world. Blocks can't be placed outside it, and generation puts a layer of
+/// unbreakable bedrock at the bottom
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldBounds {
+    /// Lowest y coordinate that a block can have, which is filled with bedrock when generated
+    pub min_y: i32,
+    /// Highest y coordinate that a block can have
+    pub max_y: i32,
+}
+
+impl WorldBounds {
+    /// True if blocks can exist at the given y coordinate
+    pub fn contains_y(&self, y: i32) -> bool {
+        (self.min_y..=self.max_y).contains(&y)
+    }
+
+    /// True if a block can exist at the given position
+    pub fn contains(&self, pos: &GlobalBlockPosition) -> bool {
+        self.contains_y(pos.as_ivec3().y)
+    }
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        Self {
+            min_y: -128,
+            max_y: 255,
+        }
+    }
+}
+
+/// Settings for `Terrain::raymarch` and `Chunk::raymarch`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaymarchOptions {
+    /// Minimum distance to advance along the ray at each step, so that the ray always makes
+    /// progress when it lies exactly on a block boundary
+    pub epsilon: f32,
+    /// Maximum number of steps taken before giving up, guarding against rays that would
+    /// otherwise advance too slowly to reach the maximum distance
+    pub max_steps: usize,
+}
+
+impl RaymarchOptions {
+    /// Reciprocal of the ray direction, with zero components mapped to positive infinity so that
+    /// the ray never steps along that axis, even for a direction of -0.0
+    pub fn direction_recip(&self, ray_direction: Vec3) -> Vec3 {
+        Vec3::select(
+            ray_direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            ray_direction.recip(),
+        )
+    }
+}
+
+impl Default for RaymarchOptions {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-3,
+            max_steps: 1024,
+        }
+    }
+}
+
 /// Returned by `Terrain::raymarch` when a block is intersected
+#[derive(Clone, Copy, Debug)]
 pub struct TerrainHit {
     pub hit_pos: GlobalBlockPosition,
     pub hit_normal: Option<IVec3>,
+    /// Face of the block that was hit, or None if the ray started inside the block
+    pub face: Option<FaceIndex>,
+    /// Distance from the origin of the ray to the point where it hit the block, in blocks
+    pub distance: f32,
+}
+
+impl TerrainHit {
+    /// Position where a block placed against the hit face would go: the neighbour of the hit
+    /// block on that face. None if the ray started inside the block it hit, in which case there
+    /// is no hit face
+    pub fn placement_pos(&self) -> Option<GlobalBlockPosition> {
+        self.hit_normal
+            .map(|hit_normal| self.hit_pos + hit_normal)
+    }
+}
+
+/// Add `new_box` to `boxes`, merged with the boxes it overlaps into the smallest box containing
+/// them all, so that the blocks where they overlap are only relit once
+fn add_box(boxes: &mut Vec<BlockBox>, new_box: BlockBox) {
+    let (mut min, mut max) = new_box;
+
+    while let Some(index) = boxes
+        .iter()
+        .position(|&other_box| boxes_overlap((min, max), other_box))
+    {
+        let (other_min, other_max) = boxes.swap_remove(index);
+        min = min.min(other_min);
+        max = max.max(other_max);
+    }
+
+    boxes.push((min, max));
+}
+
+/// True if the two boxes have any blocks in common
+fn boxes_overlap((a_min, a_max): BlockBox, (b_min, b_max): BlockBox) -> bool {
+    a_min.cmplt(b_max).all() && b_min.cmplt(a_max).all()
+}
+
+/// Blocks of the loaded chunks in one load area, edited by block callbacks
+struct LoadAreaBlocks<'a> {
+    terrain: &'a mut Terrain,
+    load_area_index: Index,
+}
+
+impl BlockAccess for LoadAreaBlocks<'_> {
+    fn get_block(&self, pos: GlobalBlockPosition) -> Option<BlockId> {
+        self.terrain
+            .get_block(self.load_area_index, &pos)
+    }
+
+    fn set_block(&mut self, pos: GlobalBlockPosition, new_id: BlockId) -> bool {
+        self.terrain
+            .set_block_without_callbacks(self.load_area_index, &pos, new_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, UVec3};
+
+    use super::*;
+    use crate::{
+        block::{
+            BLOCK_AIR, BLOCK_BEDROCK, BLOCK_DIRT, BLOCK_LAMP_ORANGE, BLOCK_LEAVES,
+            BLOCK_TALL_GRASS, BLOCK_WOOD,
+        },
+        fly_camera::FlyCamera,
+        render::{
+            frustum_culling::FrustumCullingRegions,
+            terrain::{
+                meshing::MeshingStrategy, vertex::pack_light, visibility_search::visibility_search,
+            },
+        },
+        terrain::{
+            chunk::CHUNK_SIZE_CUBED, load_area::AreaShape, position_types::LocalBlockPosition,
+        },
+        util::size::Size3,
+    };
+
+    fn terrain_with_air_chunk() -> (Terrain, Index) {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::new(-1, -1, -1),
+                Size3::splat(3),
+                AreaShape::Cuboid,
+            ));
+
+        terrain.finished_loading_chunk(Chunk::new(ChunkPosition::ZERO, vec![
+            BLOCK_AIR;
+            CHUNK_SIZE_CUBED
+        ]));
+
+        (terrain, load_area_index)
+    }
+
+    /// Recompute the light that is out of date after the edits and chunk loads so far, and wait
+    /// for it to be stored
+    fn update_light_now(terrain: &mut Terrain) {
+        let mut tasks = Tasks::new_deterministic();
+        terrain.update_light(&mut tasks);
+        tasks.block_until_finished();
+        terrain.update_light(&mut tasks);
+    }
+
+    #[test]
+    fn chunk_state_walks_from_unloaded_to_ready() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::new(-1, -1, -1),
+                Size3::splat(3),
+                AreaShape::Cuboid,
+            ));
+        let chunk_pos = ChunkPosition::ZERO;
+        let state = |terrain: &Terrain| terrain.chunk_state(load_area_index, &chunk_pos);
+
+        assert_eq!(state(&terrain), ChunkState::Unloaded);
+        assert_eq!(terrain.pending_chunk_count(), 0);
+
+        // the generation task is held, so the chunk stays queued
+        let mut tasks = Tasks::new_deterministic();
+        terrain.load_chunk(&mut tasks, chunk_pos, Vec3::ZERO);
+        assert_eq!(state(&terrain), ChunkState::Generating);
+        assert_eq!(terrain.pending_chunk_count(), 1);
+
+        terrain.finished_loading_chunk(Chunk::new(chunk_pos, vec![BLOCK_AIR; CHUNK_SIZE_CUBED]));
+        assert_eq!(state(&terrain), ChunkState::Generated);
+        assert_eq!(terrain.pending_chunk_count(), 0);
+
+        // mesh progress takes effect once it is received
+        terrain.report_mesh_progress(chunk_pos, MeshProgress::Queued);
+        assert_eq!(state(&terrain), ChunkState::Generated);
+        terrain.receive_mesh_progress();
+        assert_eq!(state(&terrain), ChunkState::Meshing);
+        assert_eq!(terrain.pending_chunk_count(), 1);
+
+        terrain.report_mesh_progress(chunk_pos, MeshProgress::Finished);
+        terrain.receive_mesh_progress();
+        assert_eq!(state(&terrain), ChunkState::Ready);
+        assert_eq!(terrain.pending_chunk_count(), 0);
+
+        // reports for unloaded chunks are ignored
+        let chunk_index = terrain.load_areas()[load_area_index]
+            .get_chunk_index(&chunk_pos)
+            .unwrap();
+        terrain.unload_chunk(chunk_index);
+        terrain.report_mesh_progress(chunk_pos, MeshProgress::Finished);
+        terrain.receive_mesh_progress();
+        assert_eq!(state(&terrain), ChunkState::Unloaded);
+    }
+
+    #[test]
+    fn axis_aligned_ray_crosses_chunks() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        blocks[LocalBlockPosition::new(8, 4, 4).get_array_index()] = BLOCK_DIRT;
+        terrain.finished_loading_chunk(Chunk::new(ChunkPosition::new(1, 0, 0), blocks));
+
+        // the ray must step a whole chunk at a time despite its zero components, and must give
+        // up rather than looping forever once it leaves the loaded chunks
+        let options = RaymarchOptions {
+            max_steps: 64,
+            ..Default::default()
+        };
+
+        let hit = terrain
+            .raymarch(
+                load_area_index,
+                Vec3::new(0.5, 4.5, 4.5),
+                Vec3::new(1.0, -0.0, 0.0),
+                100.0,
+                options,
+            )
+            .expect("ray should hit the block in the next chunk");
+        assert_eq!(hit.hit_pos, GlobalBlockPosition::new(40, 4, 4));
+        assert_eq!(hit.hit_normal, Some(IVec3::NEG_X));
+        assert_eq!(hit.face, Some(FaceIndex::NEG_X));
+        assert!((hit.distance - 39.5).abs() < 1e-3);
+
+        let miss = terrain.raymarch(
+            load_area_index,
+            Vec3::new(0.5, 10.5, 4.5),
+            Vec3::new(-0.0, 0.0, 1.0),
+            f32::INFINITY,
+            options,
+        );
+        assert!(miss.is_none());
+    }
+
+    #[test]
+    fn ray_crosses_several_chunks_and_skips_unloaded_ones() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::new(-1, -1, -1),
+                Size3::new(6, 3, 3),
+                AreaShape::Cuboid,
+            ));
+
+        // chunk 2 is left unloaded, and the block is in chunk 3
+        for x in [0, 1] {
+            terrain.finished_loading_chunk(Chunk::new(ChunkPosition::new(x, 0, 0), vec![
+                BLOCK_AIR;
+                CHUNK_SIZE_CUBED
+            ]));
+        }
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        blocks[LocalBlockPosition::new(4, 14, 9).get_array_index()] = BLOCK_DIRT;
+        terrain.finished_loading_chunk(Chunk::new(ChunkPosition::new(3, 0, 0), blocks));
+
+        // the ray enters the block through its -x face at (100, 14.05, 9.275)
+        let hit = terrain
+            .raymarch(
+                load_area_index,
+                Vec3::new(4.5, 4.5, 4.5),
+                Vec3::new(1.0, 0.1, 0.05).normalize(),
+                200.0,
+                RaymarchOptions::default(),
+            )
+            .expect("ray should hit the block three chunks away");
+        assert_eq!(hit.hit_pos, GlobalBlockPosition::new(100, 14, 9));
+        assert_eq!(hit.hit_normal, Some(IVec3::NEG_X));
+        // the ray travels 95.5 blocks along x
+        let expected_distance = 95.5 * Vec3::new(1.0, 0.1, 0.05).length();
+        assert!((hit.distance - expected_distance).abs() < 1e-2);
+
+        // the maximum distance stops the ray just short of the block
+        let miss = terrain.raymarch(
+            load_area_index,
+            Vec3::new(4.5, 4.5, 4.5),
+            Vec3::new(1.0, 0.1, 0.05).normalize(),
+            95.0,
+            RaymarchOptions::default(),
+        );
+        assert!(miss.is_none());
+    }
+
+    #[test]
+    fn ray_through_chunk_corner_hits_with_a_face_normal() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::new(-1, -1, -1),
+                Size3::splat(3),
+                AreaShape::Cuboid,
+            ));
+        for (x, y) in [(0, 0), (1, 0), (0, 1)] {
+            terrain.finished_loading_chunk(Chunk::new(ChunkPosition::new(x, y, 0), vec![
+                BLOCK_AIR;
+                CHUNK_SIZE_CUBED
+            ]));
+        }
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        blocks[LocalBlockPosition::new(0, 0, 4).get_array_index()] = BLOCK_DIRT;
+        terrain.finished_loading_chunk(Chunk::new(ChunkPosition::new(1, 1, 0), blocks));
+
+        // the ray passes exactly through the corner shared by the four chunks around the z axis,
+        // entering the block in the diagonal chunk through its edge
+        for ray_origin in [Vec3::new(28.5, 28.5, 4.5), Vec3::new(31.0, 31.0, 4.5)] {
+            let hit = terrain
+                .raymarch(
+                    load_area_index,
+                    ray_origin,
+                    Vec3::new(1.0, 1.0, 0.0).normalize(),
+                    100.0,
+                    RaymarchOptions::default(),
+                )
+                .expect("ray should hit the block across the chunk corner");
+            assert_eq!(hit.hit_pos, GlobalBlockPosition::new(32, 32, 4));
+
+            // a block placed against the hit face goes in one of the chunks next to it
+            let normal = hit.hit_normal.expect("ray started outside the block");
+            assert!([IVec3::NEG_X, IVec3::NEG_Y].contains(&normal), "{normal}");
+        }
+    }
+
+    #[test]
+    fn structure_spans_chunk_boundary() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        terrain.finished_loading_chunk(Chunk::new(ChunkPosition::new(1, 0, 0), vec![
+            BLOCK_AIR;
+            CHUNK_SIZE_CUBED
+        ]));
+
+        // the trunk is in the last column of the first chunk, so the leaves overhang the second
+        let anchor_pos = GlobalBlockPosition::new(31, 4, 4);
+        let existing_pos = GlobalBlockPosition::new(32, 6, 4);
+        terrain.set_block(load_area_index, &existing_pos, BLOCK_DIRT);
+        terrain.clear_events();
+
+        let tree = Structure::tree();
+        let placed = terrain.place_structure(
+            load_area_index,
+            &anchor_pos,
+            &tree,
+            PlacementMode::KeepExisting,
+        );
+        assert_eq!(placed, tree.blocks_at(anchor_pos).count() - 1);
+
+        assert_eq!(terrain.get_block(load_area_index, &anchor_pos), Some(BLOCK_WOOD));
+        assert_eq!(
+            terrain.get_block(load_area_index, &GlobalBlockPosition::new(32, 7, 5)),
+            Some(BLOCK_LEAVES)
+        );
+        assert_eq!(
+            terrain.get_block(load_area_index, &existing_pos),
+            Some(BLOCK_DIRT)
+        );
+
+        // each touched chunk is remeshed once. The tree also shades the blocks under it, which
+        // changes their skylight
+        let modified: Vec<_> = terrain
+            .events()
+            .filter_map(|event| match event {
+                TerrainEvent::ChunkModified(chunk_pos) => Some(*chunk_pos),
+                TerrainEvent::ChunkLightChanged(_) => None,
+                _ => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        assert_eq!(modified, [ChunkPosition::ZERO, ChunkPosition::new(1, 0, 0)]);
+
+        // copying the placed tree back out gives the same structure, apart from the dirt block
+        let copy = Structure::from_region(
+            &terrain,
+            load_area_index,
+            anchor_pos - IVec3::new(1, 0, 1),
+            tree.size(),
+        )
+        .unwrap();
+        let mut expected = tree.clone();
+        expected.set(UVec3::new(2, 2, 1), Some(BLOCK_DIRT));
+        assert_eq!(
+            copy.blocks_at(GlobalBlockPosition::new(0, 0, 0))
+                .collect_vec(),
+            expected
+                .blocks_at(GlobalBlockPosition::new(1, 0, 1))
+                .collect_vec()
+        );
+
+        // regions that aren't loaded can't be copied
+        assert!(Structure::from_region(
+            &terrain,
+            load_area_index,
+            GlobalBlockPosition::new(62, 0, 0),
+            Size3::splat(4)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn placement_pos_is_next_to_hit_face() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        let block_pos = GlobalBlockPosition::new(4, 4, 4);
+        terrain.set_block(load_area_index, &block_pos, BLOCK_DIRT);
+
+        let hit = terrain
+            .raymarch(
+                load_area_index,
+                Vec3::new(4.5, 10.5, 4.5),
+                Vec3::NEG_Y,
+                20.0,
+                RaymarchOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(hit.hit_pos, block_pos);
+        assert_eq!(hit.placement_pos(), Some(GlobalBlockPosition::new(4, 5, 4)));
+
+        // starting inside the block gives no normal, so there is nowhere to place a block
+        let hit = terrain
+            .raymarch(
+                load_area_index,
+                Vec3::new(4.5, 4.5, 4.5),
+                Vec3::NEG_Y,
+                20.0,
+                RaymarchOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(hit.hit_pos, block_pos);
+        assert_eq!(hit.hit_normal, None);
+        assert_eq!(hit.face, None);
+        assert_eq!(hit.distance, 0.0);
+        assert_eq!(hit.placement_pos(), None);
+    }
+
+    #[test]
+    fn refuses_to_place_block_inside_camera() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+
+        let camera = FlyCamera {
+            position: Vec3::new(4.5, 5.5, 4.5),
+            no_clip: false,
+            ..Default::default()
+        };
+        let camera_block_pos = GlobalBlockPosition::new(4, 5, 4);
+        let feet_block_pos = camera_block_pos - IVec3::Y;
+        let nearby_block_pos = camera_block_pos + IVec3::X;
+
+        for pos in [camera_block_pos, feet_block_pos] {
+            assert!(!terrain.place_block(
+                load_area_index,
+                &pos,
+                BLOCK_DIRT,
+                camera.collision_box()
+            ));
+            assert_eq!(terrain.get_block(load_area_index, &pos), Some(BLOCK_AIR));
+        }
+
+        assert!(terrain.place_block(
+            load_area_index,
+            &nearby_block_pos,
+            BLOCK_DIRT,
+            camera.collision_box()
+        ));
+        assert_eq!(
+            terrain.get_block(load_area_index, &nearby_block_pos),
+            Some(BLOCK_DIRT)
+        );
+    }
+
+    #[test]
+    fn places_block_inside_camera_in_no_clip_mode() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+
+        let camera = FlyCamera {
+            position: Vec3::new(4.5, 5.5, 4.5),
+            ..Default::default()
+        };
+        let camera_block_pos = GlobalBlockPosition::new(4, 5, 4);
+
+        assert!(terrain.place_block(
+            load_area_index,
+            &camera_block_pos,
+            BLOCK_DIRT,
+            camera.collision_box()
+        ));
+        assert_eq!(
+            terrain.get_block(load_area_index, &camera_block_pos),
+            Some(BLOCK_DIRT)
+        );
+    }
+
+    #[test]
+    fn plants_are_removed_with_the_block_under_them() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        let ground_pos = GlobalBlockPosition::new(4, 4, 4);
+        let plant_pos = ground_pos + IVec3::Y;
+        terrain.set_block(load_area_index, &ground_pos, BLOCK_DIRT);
+        terrain.set_block(load_area_index, &plant_pos, BLOCK_TALL_GRASS);
+
+        // edits next to the plant that leave it supported don't remove it
+        terrain.set_block(load_area_index, &(plant_pos + IVec3::X), BLOCK_DIRT);
+        terrain.set_block(load_area_index, &(ground_pos - IVec3::Y), BLOCK_DIRT);
+        assert_eq!(
+            terrain.get_block(load_area_index, &plant_pos),
+            Some(BLOCK_TALL_GRASS)
+        );
+
+        terrain.set_block(load_area_index, &ground_pos, BLOCK_AIR);
+        assert_eq!(terrain.get_block(load_area_index, &plant_pos), Some(BLOCK_AIR));
+    }
+
+    #[test]
+    fn regenerating_with_a_new_seed_changes_the_blocks() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::ZERO,
+                Size3::splat(1),
+                AreaShape::Cuboid,
+            ));
+        let mut tasks = Tasks::new_deterministic();
+
+        let mut generate = |terrain: &mut Terrain| {
+            terrain.update(&mut tasks, Vec3::ZERO);
+            tasks.block_until_finished();
+            terrain.update(&mut tasks, Vec3::ZERO);
+
+            terrain
+                .get_chunk(load_area_index, &ChunkPosition::ZERO)
+                .expect("chunk should be loaded")
+                .get_block_storage()
+                .as_block_array()
+        };
+
+        let original_blocks = generate(&mut terrain);
+        assert!(original_blocks.contains(&BLOCK_DIRT));
+
+        // regenerating with the same parameters gives the same blocks
+        terrain.regenerate(&mut Tasks::new_deterministic());
+        assert_eq!(terrain.chunks().len(), 0);
+        assert_eq!(generate(&mut terrain), original_blocks);
+
+        terrain.set_generation_params(GenerationParams {
+            seed: 1234,
+            ..*terrain.generation_params()
+        });
+        terrain.regenerate(&mut Tasks::new_deterministic());
+        assert_ne!(generate(&mut terrain), original_blocks);
+    }
+
+    #[test]
+    fn regenerating_cancels_pending_generation() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(
+                ChunkPosition::ZERO,
+                Size3::splat(1),
+                AreaShape::Cuboid,
+            ));
+        let state = |terrain: &Terrain| terrain.chunk_state(load_area_index, &ChunkPosition::ZERO);
+
+        // the generation task is held, so it is still pending when the terrain is regenerated
+        let mut tasks = Tasks::new_deterministic();
+        terrain.update(&mut tasks, Vec3::ZERO);
+        assert_eq!(state(&terrain), ChunkState::Generating);
+        assert_eq!(tasks.pending_task_count(TaskStage::Generation), 1);
+
+        terrain.regenerate(&mut tasks);
+        assert_eq!(state(&terrain), ChunkState::Unloaded);
+        assert_eq!(tasks.pending_task_count(TaskStage::Generation), 0);
+
+        // the chunk is queued again with the new parameters
+        terrain.update(&mut tasks, Vec3::ZERO);
+        assert_eq!(state(&terrain), ChunkState::Generating);
+        tasks.block_until_finished();
+        terrain.update(&mut tasks, Vec3::ZERO);
+        assert_eq!(state(&terrain), ChunkState::Generated);
+    }
+
+    #[test]
+    fn blocks_are_refused_outside_world_bounds() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        terrain.set_world_bounds(WorldBounds {
+            min_y: 4,
+            max_y: 20,
+        });
+        assert_eq!(terrain.world_bounds().max_y, 20);
+
+        let place = |terrain: &mut Terrain, y| {
+            let pos = GlobalBlockPosition::new(3, y, 3);
+            terrain.place_block(load_area_index, &pos, BLOCK_DIRT, None)
+        };
+        assert!(!place(&mut terrain, 3));
+        assert!(place(&mut terrain, 4));
+        assert!(place(&mut terrain, 20));
+        assert!(!place(&mut terrain, 21));
+        assert_eq!(
+            terrain.get_block(load_area_index, &GlobalBlockPosition::new(3, 21, 3)),
+            Some(BLOCK_AIR)
+        );
+
+        // structures are cut off at the top of the world
+        let placed = terrain.place_structure(
+            load_area_index,
+            &GlobalBlockPosition::new(10, 18, 10),
+            &Structure::tree(),
+            PlacementMode::KeepExisting,
+        );
+        let expected = Structure::tree()
+            .blocks_at(GlobalBlockPosition::new(10, 18, 10))
+            .filter(|(pos, _)| pos.as_ivec3().y <= 20)
+            .count();
+        assert_eq!(placed, expected);
+    }
+
+    #[test]
+    fn generation_places_bedrock_at_the_bottom_of_the_world() {
+        let world_bounds = WorldBounds {
+            min_y: 5,
+            max_y: 40,
+        };
+        let generator = NoiseGenerator::new(
+            GenerationParams {
+                // raise the surface well above the chunk, so that it is full below the top
+                sea_level: 1000.0,
+                ..Default::default()
+            },
+            world_bounds,
+        );
+        let blocks = generator.generate_chunk(ChunkPosition::ZERO);
+
+        for (x, z) in itertools::iproduct!(0..CHUNK_SIZE as u32, 0..CHUNK_SIZE as u32) {
+            let block = |y| blocks[LocalBlockPosition::new(x, y, z).get_array_index()];
+
+            assert!((0..5).all(|y| block(y) == BLOCK_AIR));
+            assert_eq!(block(5), BLOCK_BEDROCK);
+        }
+    }
+
+    #[test]
+    fn chunk_meshes_do_not_depend_on_the_chunk_position() {
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        blocks[LocalBlockPosition::new(0, 0, 0).get_array_index()] = BLOCK_DIRT;
+        blocks[LocalBlockPosition::new(31, 31, 31).get_array_index()] = BLOCK_WOOD;
+        blocks[LocalBlockPosition::new(5, 6, 7).get_array_index()] = BLOCK_LEAVES;
+
+        let mesh_at = |chunk_pos: ChunkPosition| {
+            let mut terrain = Terrain::new(GenerationParams::default());
+            let load_area_index = terrain
+                .load_areas_mut()
+                .insert(LoadArea::new(chunk_pos, Size3::splat(1), AreaShape::Cuboid));
+            terrain.finished_loading_chunk(Chunk::new(chunk_pos, blocks.clone()));
+
+            terrain
+                .mesh_chunk_now(load_area_index, &chunk_pos, MeshingOptions::default())
+                .expect("chunk should be loaded")
+                .vertices
+        };
+
+        let origin_vertices = mesh_at(ChunkPosition::ZERO);
+        assert!(!origin_vertices.is_empty());
+        assert!(origin_vertices.iter().all(|vertex| {
+            let position = Vec3::from(vertex.position);
+            position.cmpge(Vec3::ZERO).all() && position.cmple(Vec3::splat(CHUNK_SIZE as f32)).all()
+        }));
+
+        // including far from the origin, where baking in the world position would lose precision
+        for chunk_pos in [ChunkPosition::new(1, 0, 1), ChunkPosition::new(-31_250, 3, 62_500)] {
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&origin_vertices),
+                bytemuck::cast_slice::<_, u8>(&mesh_at(chunk_pos))
+            );
+        }
+    }
+
+    #[test]
+    fn meshing_strategy_selects_the_mesher() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        for (x, z) in itertools::iproduct!(0..4, 0..4) {
+            terrain.set_block(load_area_index, &GlobalBlockPosition::new(x, 0, z), BLOCK_DIRT);
+        }
+
+        let mesh_with = |strategy| {
+            let options = MeshingOptions {
+                strategy,
+                ..Default::default()
+            };
+            terrain
+                .mesh_chunk_now(load_area_index, &ChunkPosition::ZERO, options)
+                .expect("chunk should be loaded")
+        };
+        let culled = mesh_with(MeshingStrategy::Culled);
+        let greedy = mesh_with(MeshingStrategy::Greedy);
+
+        assert_eq!(culled.strategy, Some(MeshingStrategy::Culled));
+        assert_eq!(greedy.strategy, Some(MeshingStrategy::Greedy));
+
+        // the greedy mesher merges the faces of the slab, the culled mesher keeps one per block
+        assert_eq!(culled.vertices.len(), 4 * (2 * 16 + 4 * 4));
+        assert_eq!(greedy.vertices.len(), 4 * 6);
+    }
+
+    #[test]
+    fn remesh_all_requests_one_mesh_per_loaded_chunk() {
+        let (mut terrain, _) = terrain_with_air_chunk();
+        for chunk_pos in [ChunkPosition::new(1, 0, 0), ChunkPosition::new(0, -1, 1)] {
+            terrain.finished_loading_chunk(Chunk::new(chunk_pos, vec![
+                BLOCK_DIRT;
+                CHUNK_SIZE_CUBED
+            ]));
+        }
+        terrain.clear_events();
+
+        terrain.remesh_all();
+
+        let mut requested: Vec<_> = terrain
+            .events()
+            .map(|event| match event {
+                TerrainEvent::ChunkRemeshRequested(chunk_pos) => chunk_pos.as_ivec3().to_array(),
+                _ => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        requested.sort();
+        assert_eq!(requested, [[0, -1, 1], [0, 0, 0], [1, 0, 0]]);
+    }
+
+    #[test]
+    fn visibility_search_does_not_see_through_solid_chunks() {
+        // a row of chunks along +x, viewed from the middle of the first one
+        let visible_row = |row: [BlockId; 4]| {
+            let mut terrain = Terrain::new(GenerationParams::default());
+            let load_area_index = terrain
+                .load_areas_mut()
+                .insert(LoadArea::new(
+                    ChunkPosition::new(-1, -1, -1),
+                    Size3::new(6, 3, 3),
+                    AreaShape::Cuboid,
+                ));
+            for (x, block) in row.into_iter().enumerate() {
+                let chunk_pos = ChunkPosition::new(x as i32, 0, 0);
+                terrain.finished_loading_chunk(Chunk::new(chunk_pos, vec![
+                    block;
+                    CHUNK_SIZE_CUBED
+                ]));
+            }
+
+            let camera_pos = Vec3::splat(0.5 * CHUNK_SIZE as f32);
+            let view_proj = Mat4::perspective_rh(1.5, 1.0, 0.1, 1000.0)
+                * Mat4::look_at_rh(camera_pos, camera_pos + Vec3::X, Vec3::Y);
+            let mut frustum_culling = FrustumCullingRegions::new(Size3::ONE, Size3::splat(8));
+            frustum_culling.update(&view_proj, camera_pos);
+
+            visibility_search(&terrain, load_area_index, &frustum_culling, camera_pos)
+                .iter()
+                .map(|chunk| chunk.position().x())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(visible_row([BLOCK_AIR; 4]), [0, 1, 2, 3]);
+
+        // the solid chunk itself is drawn, but nothing behind it
+        assert_eq!(visible_row([BLOCK_AIR, BLOCK_DIRT, BLOCK_AIR, BLOCK_AIR]), [0, 1]);
+    }
+
+    #[test]
+    fn edited_chunks_are_saved_and_loaded_instead_of_generated() {
+        let directory = std::env::temp_dir().join(format!(
+            "voxels-test-{}-terrain-save",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        let edited_pos = GlobalBlockPosition::new(5, 6, 7);
+
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        terrain.set_chunk_store(Some(ChunkStore::new(&directory)));
+        terrain.set_block(load_area_index, &edited_pos, BLOCK_DIRT);
+        terrain.save(&mut Tasks::new_deterministic());
+
+        // a fresh terrain loads the chunk from disk rather than generating it
+        let mut terrain = Terrain::new(GenerationParams::default());
+        terrain.set_chunk_store(Some(ChunkStore::new(&directory)));
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(ChunkPosition::ZERO, Size3::ONE, AreaShape::Cuboid));
+        let mut tasks = Tasks::new_deterministic();
+        terrain.update(&mut tasks, Vec3::ZERO);
+        tasks.block_until_finished();
+        terrain.update(&mut tasks, Vec3::ZERO);
+
+        assert_eq!(
+            terrain.chunk_state(load_area_index, &ChunkPosition::ZERO),
+            ChunkState::Generated
+        );
+        assert_eq!(terrain.get_block(load_area_index, &edited_pos), Some(BLOCK_DIRT));
+        assert_eq!(
+            terrain.get_block(load_area_index, &GlobalBlockPosition::new(5, 6, 8)),
+            Some(BLOCK_AIR)
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn overlapping_areas_share_chunks() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let mut tasks = Tasks::new_deterministic();
+        let area = |x| {
+            LoadArea::with_radius_and_height(
+                ChunkPosition::new(x, 0, 0),
+                2,
+                1,
+                AreaShape::Cylindrical,
+            )
+        };
+        let area_a = terrain.load_areas_mut().insert(area(0));
+        let area_b = terrain.load_areas_mut().insert(area(1));
+
+        // finish generating every queued chunk with air
+        let finish_generation = |terrain: &mut Terrain, tasks: &mut Tasks| {
+            terrain.update(tasks, Vec3::ZERO);
+            for chunk_pos in terrain.generation_tasks.keys().copied().collect_vec() {
+                terrain.generation_tasks.remove(&chunk_pos);
+                terrain.finished_loading_chunk(Chunk::new(chunk_pos, vec![
+                    BLOCK_AIR;
+                    CHUNK_SIZE_CUBED
+                ]));
+            }
+        };
+        finish_generation(&mut terrain, &mut tasks);
+
+        let shared_pos = ChunkPosition::new(2, 0, 2);
+        let only_b_pos = ChunkPosition::new(4, 0, 2);
+        assert!(terrain.get_chunk(area_a, &shared_pos).is_some());
+        assert!(terrain.get_chunk(area_b, &shared_pos).is_some());
+        assert!(terrain.get_chunk(area_b, &only_b_pos).is_some());
+        let chunk_count = terrain.chunks().len();
+
+        // the shared chunk stays loaded for the area that didn't move
+        terrain.load_areas_mut()[area_b].set_pos(ChunkPosition::new(100, 0, 0));
+        finish_generation(&mut terrain, &mut tasks);
+        assert!(terrain.get_chunk(area_a, &shared_pos).is_some());
+        assert_eq!(terrain.chunk_state(area_b, &only_b_pos), ChunkState::Unloaded);
+
+        // moving back, the area shares the chunks still loaded rather than reloading them
+        terrain.load_areas_mut()[area_b].set_pos(ChunkPosition::new(1, 0, 0));
+        terrain.update(&mut tasks, Vec3::ZERO);
+        assert!(terrain.get_chunk(area_b, &shared_pos).is_some());
+        assert!(!terrain.generation_tasks.contains_key(&shared_pos));
+        assert_eq!(terrain.chunk_state(area_b, &only_b_pos), ChunkState::Generating);
+        finish_generation(&mut terrain, &mut tasks);
+        assert_eq!(terrain.chunks().len(), chunk_count);
+    }
+
+    #[test]
+    fn generation_is_cancelled_when_the_chunk_leaves_every_area() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        let mut tasks = Tasks::new_deterministic();
+        let load_area_index = terrain
+            .load_areas_mut()
+            .insert(LoadArea::new(ChunkPosition::ZERO, Size3::ONE, AreaShape::Cuboid));
+
+        terrain.update(&mut tasks, Vec3::ZERO);
+        assert!(terrain.generation_tasks.contains_key(&ChunkPosition::ZERO));
+
+        let new_pos = ChunkPosition::new(10, 0, 0);
+        terrain.load_areas_mut()[load_area_index].set_pos(new_pos);
+        terrain.update(&mut tasks, Vec3::ZERO);
+
+        assert!(!terrain.generation_tasks.contains_key(&ChunkPosition::ZERO));
+        assert!(terrain.generation_tasks.contains_key(&new_pos));
+        assert_eq!(tasks.pending_task_count(TaskStage::Generation), 1);
+        assert_eq!(terrain.pending_chunk_count(), 1);
+    }
+
+    #[test]
+    fn lamp_lights_an_enclosed_room_with_falloff() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        let light_at = |terrain: &Terrain, x, y, z| {
+            terrain.get_light(&GlobalBlockPosition::new(x, y, z))
+        };
+
+        // a hollow box of dirt from 4 to 20 on each axis, with a lamp in the middle
+        for (x, y, z) in itertools::iproduct!(4..=20, 4..=20, 4..=20) {
+            if [x, y, z].iter().any(|&c| c == 4 || c == 20) {
+                let pos = GlobalBlockPosition::new(x, y, z);
+                terrain.set_block(load_area_index, &pos, BLOCK_DIRT);
+            }
+        }
+        update_light_now(&mut terrain);
+        assert!(terrain.find_chunk(&ChunkPosition::ZERO).unwrap().light().is_dark());
+
+        let lamp_pos = GlobalBlockPosition::new(12, 12, 12);
+        terrain.clear_events();
+        terrain.set_block(load_area_index, &lamp_pos, BLOCK_LAMP_ORANGE);
+        // the light is recomputed by a task, so it isn't there until the task has finished
+        assert!(light_at(&terrain, 12, 12, 12).is_dark());
+        update_light_now(&mut terrain);
+
+        // each component drops by one per block from the lamp, up to the wall
+        for distance in 0..8 {
+            let expected = EmittedLight::from_rgb(
+                15 - distance,
+                10u16.saturating_sub(distance),
+                5u16.saturating_sub(distance),
+            );
+            assert_eq!(light_at(&terrain, 12 + distance as i32, 12, 12), expected);
+            assert_eq!(light_at(&terrain, 12, 12 - distance as i32, 12), expected);
+        }
+        // distance is counted along the axes
+        assert_eq!(light_at(&terrain, 14, 13, 12).as_rgb(), (12, 7, 2));
+        assert!(light_at(&terrain, 5, 5, 5).is_dark());
+        // neither the walls nor the outside are lit
+        assert!(light_at(&terrain, 20, 12, 12).is_dark());
+        assert!(light_at(&terrain, 21, 12, 12).is_dark());
+        assert!(terrain.events().any(|event| matches!(
+            event,
+            TerrainEvent::ChunkLightChanged(chunk_pos) if *chunk_pos == ChunkPosition::ZERO
+        )));
+
+        // the inside of the wall is lit by the block in front of it, but not by the sky
+        let mesh = terrain
+            .mesh_chunk_now(load_area_index, &ChunkPosition::ZERO, MeshingOptions::default())
+            .unwrap();
+        let wall_light = pack_light(EmittedLight::from_rgb(8, 3, 0), Skylight::DARK);
+        assert!(mesh.vertices.iter().any(|vertex| vertex.light == wall_light));
+
+        // removing the lamp leaves the room dark
+        terrain.set_block(load_area_index, &lamp_pos, BLOCK_AIR);
+        update_light_now(&mut terrain);
+        assert!(terrain.find_chunk(&ChunkPosition::ZERO).unwrap().light().is_dark());
+    }
+
+    #[test]
+    fn light_crosses_into_chunks_as_they_load() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        let red_at = |terrain: &Terrain, x| {
+            terrain
+                .get_light(&GlobalBlockPosition::new(x, 5, 5))
+                .as_rgb()
+                .0
+        };
+
+        // the neighbouring chunk isn't loaded, so the light stops at the edge of the chunk
+        terrain.set_block(load_area_index, &GlobalBlockPosition::new(30, 5, 5), BLOCK_LAMP_ORANGE);
+        update_light_now(&mut terrain);
+        assert_eq!(red_at(&terrain, 31), 14);
+        assert_eq!(red_at(&terrain, 33), 0);
+
+        let neighbour_pos = ChunkPosition::new(1, 0, 0);
+        terrain.clear_events();
+        terrain.finished_loading_chunk(Chunk::new(neighbour_pos, vec![
+            BLOCK_AIR;
+            CHUNK_SIZE_CUBED
+        ]));
+        update_light_now(&mut terrain);
+        assert_eq!(red_at(&terrain, 33), 12);
+        assert!(terrain.events().any(|event| matches!(
+            event,
+            TerrainEvent::ChunkLightChanged(chunk_pos) if *chunk_pos == neighbour_pos
+        )));
+
+        // blocking the light in the new chunk makes it go around
+        terrain.set_block(load_area_index, &GlobalBlockPosition::new(32, 5, 5), BLOCK_DIRT);
+        update_light_now(&mut terrain);
+        assert_eq!(red_at(&terrain, 32), 0);
+        assert_eq!(red_at(&terrain, 33), 10);
+    }
+
+    #[test]
+    fn edits_made_while_the_light_is_recomputed_are_relit() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        let mut tasks = Tasks::new_deterministic();
+        let red_at = |terrain: &Terrain, x| {
+            terrain
+                .get_light(&GlobalBlockPosition::new(x, 5, 5))
+                .as_rgb()
+                .0
+        };
+
+        // the wall is placed while the task lighting the room is running, so the light stored
+        // when it finishes doesn't know about the wall
+        terrain.set_block(load_area_index, &GlobalBlockPosition::new(10, 5, 5), BLOCK_LAMP_ORANGE);
+        terrain.update_light(&mut tasks);
+        terrain.set_block(load_area_index, &GlobalBlockPosition::new(11, 5, 5), BLOCK_DIRT);
+        tasks.block_until_finished();
+        terrain.update_light(&mut tasks);
+        assert_eq!(red_at(&terrain, 11), 14);
+
+        // but the wall is relit by the next task, and the light goes around it
+        tasks.block_until_finished();
+        terrain.update_light(&mut tasks);
+        assert_eq!(red_at(&terrain, 11), 0);
+        assert_eq!(red_at(&terrain, 12), 11);
+    }
+
+    #[test]
+    fn skylight_reaches_open_blocks_and_spreads_under_overhangs() {
+        let (mut terrain, load_area_index) = terrain_with_air_chunk();
+        let skylight_at = |terrain: &Terrain, x, y, z| {
+            terrain
+                .get_skylight(&GlobalBlockPosition::new(x, y, z))
+                .level()
+        };
+        let fill = |terrain: &mut Terrain, min: IVec3, max: IVec3, block_id| {
+            let mut structure = Structure::new((max - min + 1).as_size3(), UVec3::ZERO);
+            for (x, y, z) in itertools::iproduct!(min.x..=max.x, min.y..=max.y, min.z..=max.z) {
+                structure.set((IVec3::new(x, y, z) - min).as_uvec3(), Some(block_id));
+            }
+            terrain.place_structure(
+                load_area_index,
+                &GlobalBlockPosition::from(min),
+                &structure,
+                PlacementMode::KeepExisting,
+            );
+            update_light_now(terrain);
+        };
+
+        // ground below y = 24 with an air pocket buried in it
+        fill(&mut terrain, IVec3::ZERO, IVec3::new(31, 23, 31), BLOCK_DIRT);
+        terrain.set_block(load_area_index, &GlobalBlockPosition::new(16, 4, 16), BLOCK_AIR);
+        update_light_now(&mut terrain);
+        assert_eq!(skylight_at(&terrain, 5, 24, 5), 15);
+        assert_eq!(skylight_at(&terrain, 16, 4, 16), 0);
+
+        // a roof lets the light in from its edges, losing one level per block
+        fill(&mut terrain, IVec3::new(10, 28, 10), IVec3::new(20, 28, 20), BLOCK_DIRT);
+        assert_eq!(skylight_at(&terrain, 15, 24, 15), 9);
+
+        // opening the roof above a block lets the sky reach it again
+        terrain.set_block(load_area_index, &GlobalBlockPosition::new(15, 28, 15), BLOCK_AIR);
+        update_light_now(&mut terrain);
+        assert_eq!(skylight_at(&terrain, 15, 24, 15), 15);
+        assert_eq!(skylight_at(&terrain, 14, 24, 15), 14);
+    }
+
+    /// Run alone with `cargo test --release bench_peak_rss_loading_a_large_area -- --ignored`,
+    /// as the peak RSS is shared by every test running in the process
+    #[test]
+    #[ignore]
+    fn bench_peak_rss_loading_a_large_area() {
+        let mut terrain = Terrain::new(GenerationParams::default());
+        terrain
+            .load_areas_mut()
+            .insert(LoadArea::with_radius_and_height(
+                ChunkPosition::ZERO,
+                16,
+                8,
+                AreaShape::Cylindrical,
+            ));
+        let rss_before = crate::util::memory::peak_rss().unwrap();
+
+        // every generated chunk waits in the channel until the next update, as when the main
+        // thread falls behind the loading tasks
+        let mut tasks = Tasks::new_deterministic();
+        terrain.update(&mut tasks, Vec3::ZERO);
+        tasks.block_until_finished();
+        let rss_queued = crate::util::memory::peak_rss().unwrap();
+        terrain.update(&mut tasks, Vec3::ZERO);
+        let rss_loaded = crate::util::memory::peak_rss().unwrap();
+
+        let mib = |bytes: usize| (bytes - rss_before) as f64 / (1024.0 * 1024.0);
+        println!(
+            "{} chunks, peak rss increase: {:.1} MiB queued, {:.1} MiB loaded",
+            terrain.chunks().len(),
+            mib(rss_queued),
+            mib(rss_loaded),
+        );
+    }
 }
@@ -1,17 +1,26 @@
 use glam::{IVec3, Vec3};
 
-use self::{storage::ChunkBlockStorage, visibility_graph::VisibilityGraph};
-use super::position_types::{ChunkPosition, LocalBlockPosition};
+use self::{storage::ChunkBlockStorage, summary::ChunkSummary, visibility_graph::VisibilityGraph};
+use super::{
+    lighting::ChunkLight,
+    position_types::{ChunkPosition, LocalBlockPosition},
+    RaymarchOptions,
+};
 use crate::{
-    block::{BlockId, BLOCK_AIR},
+    block::{BlockId, BLOCKS},
     util::{
+        face::FaceIndex,
         size::{Size2, Size3},
         vector_map::VectorMapExt,
     },
 };
 
+pub mod border;
+pub mod compression;
+pub mod serialization;
 pub mod side;
 pub mod storage;
+pub mod summary;
 pub mod visibility_graph;
 
 pub const CHUNK_SIZE: usize = 32;
@@ -29,6 +38,8 @@ pub struct Chunk {
     pos: ChunkPosition,
     blocks: ChunkBlockStorage,
     visibility_graph: VisibilityGraph,
+    summary: ChunkSummary,
+    light: ChunkLight,
 }
 
 impl Chunk {
@@ -36,11 +47,14 @@ impl Chunk {
         // this function is called from a parallel thread so it's OK to perform intensive tasks
         // here
         let visibility_graph = VisibilityGraph::compute(&blocks);
+        let summary = ChunkSummary::compute(&blocks);
 
         Self {
             pos,
             blocks: ChunkBlockStorage::new(blocks),
             visibility_graph,
+            summary,
+            light: ChunkLight::default(),
         }
     }
 
@@ -58,7 +72,11 @@ impl Chunk {
     /// Returns the block ID at the given position.
     /// Panics if the position is out of bounds
     pub fn set_block(&mut self, pos: LocalBlockPosition, new_id: BlockId) {
-        self.blocks.set_block(pos, new_id)
+        let old_id = self.blocks.get_block(pos);
+        self.blocks.set_block(pos, new_id);
+
+        self.summary
+            .block_changed(pos, old_id, new_id, |pos| self.blocks.get_block(pos));
     }
 
     /// Returns this chunk's position
@@ -71,6 +89,21 @@ impl Chunk {
         self.visibility_graph
     }
 
+    /// Returns the summary of which blocks of this chunk are solid
+    pub fn summary(&self) -> &ChunkSummary {
+        &self.summary
+    }
+
+    /// Returns the light of the blocks in this chunk
+    pub fn light(&self) -> &ChunkLight {
+        &self.light
+    }
+
+    /// Returns the light of the blocks in this chunk, to be updated by `Terrain` as light spreads
+    pub fn light_mut(&mut self) -> &mut ChunkLight {
+        &mut self.light
+    }
+
     /// Marches through the chunk along the ray with the given origin and direction, using the DDA
     /// algorithm
     /// If a block was hit, returns the position of that block in the chunk and face index of the
@@ -81,16 +114,43 @@ impl Chunk {
         ray_direction: Vec3,
         previous_chunk_pos: Option<ChunkPosition>,
         maximum_distance: f32,
+        options: RaymarchOptions,
     ) -> Option<ChunkHit> {
-        pub const EPS: f32 = 1e-3;
+        self.raymarch_with_bounding_boxes(
+            ray_origin,
+            ray_direction,
+            previous_chunk_pos,
+            maximum_distance,
+            options,
+            |block_id| BLOCKS[block_id.0 as usize].model.bounding_box(),
+        )
+    }
 
+    /// Same as `raymarch`, but the bounding box of each block is given by `bounding_box`, which
+    /// returns the minimum and maximum corners of the box relative to the block's position.
+    /// Rays only hit a block if they intersect its bounding box, so that a ray can pass through
+    /// the empty part of a block that is not a full cube
+    fn raymarch_with_bounding_boxes(
+        &self,
+        ray_origin: Vec3,
+        ray_direction: Vec3,
+        previous_chunk_pos: Option<ChunkPosition>,
+        maximum_distance: f32,
+        options: RaymarchOptions,
+        bounding_box: impl Fn(BlockId) -> Option<(Vec3, Vec3)>,
+    ) -> Option<ChunkHit> {
         let dir_step = ray_direction.map(|component| if component >= 0.0 { 1.0 } else { 0.0 });
-        let dir_recip = ray_direction.recip();
+        let dir_recip = options.direction_recip(ray_direction);
+        let zero_axes = ray_direction.cmpeq(Vec3::ZERO);
 
         let mut t = 0.0;
         let mut previous_block_pos: Option<LocalBlockPosition> = None;
 
-        while t < maximum_distance {
+        for _ in 0..options.max_steps {
+            if t >= maximum_distance {
+                break;
+            }
+
             let ray_pos = ray_origin + ray_direction * t;
             let block_pos = ray_pos.floor().as_ivec3();
 
@@ -100,11 +160,56 @@ impl Chunk {
             }
 
             let block_pos = LocalBlockPosition::from(block_pos.as_uvec3());
-            if self.get_block(block_pos) != BLOCK_AIR {
-                // hit a block
-                return Some(ChunkHit {
-                    local_hit_pos: block_pos,
-                    hit_normal: previous_block_pos
+            let block_box = bounding_box(self.get_block(block_pos));
+
+            // intersect the ray with the bounding box of the block, relative to the point where
+            // the ray entered this block
+            let box_intersection = block_box.and_then(|(box_min, box_max)| {
+                let origin_in_block = ray_pos - block_pos.as_ivec3().as_vec3();
+
+                // the ray never crosses the box's planes along axes it doesn't move along, so it
+                // only intersects the box if it is already between them
+                let t_min = Vec3::select(
+                    zero_axes,
+                    Vec3::NEG_INFINITY,
+                    (box_min - origin_in_block) * dir_recip,
+                );
+                let t_max = Vec3::select(
+                    zero_axes,
+                    Vec3::INFINITY,
+                    (box_max - origin_in_block) * dir_recip,
+                );
+                let within_zero_axes = (!zero_axes
+                    | (origin_in_block.cmpge(box_min) & origin_in_block.cmple(box_max)))
+                .all();
+
+                let t_near = t_min.min(t_max);
+                let t_far = t_min
+                    .max(t_max)
+                    .min_element();
+
+                let t_enter = t_near.max_element();
+                (within_zero_axes
+                    && t_enter <= t_far
+                    && t_far >= 0.0
+                    && t + t_enter < maximum_distance)
+                    .then_some((t_enter, t_near))
+            });
+
+            if let Some((t_enter, t_near)) = box_intersection {
+                // hit a block; a ray starting inside the box hits it straight away
+                let distance = (t + t_enter.max(0.0)) * ray_direction.length();
+                let hit_normal = if t_enter > options.epsilon {
+                    // the ray hit a face of the box inside the block
+                    let axis = (0..3)
+                        .max_by(|&a, &b| t_near[a].total_cmp(&t_near[b]))
+                        .unwrap();
+                    let mut normal = IVec3::ZERO;
+                    normal[axis] = -ray_direction[axis].signum() as i32;
+                    Some(normal)
+                } else {
+                    // the ray hit the box where it entered the block
+                    previous_block_pos
                         .map(|previous_block_pos| {
                             previous_block_pos.as_ivec3() - block_pos.as_ivec3()
                         })
@@ -112,23 +217,224 @@ impl Chunk {
                             previous_chunk_pos.map(|previous_chunk_pos| {
                                 previous_chunk_pos.as_ivec3() - self.position().as_ivec3()
                             })
-                        }),
+                        })
+                        .map(|entry_offset| entry_face_normal(entry_offset, ray_direction))
+                };
+
+                return Some(ChunkHit {
+                    local_hit_pos: block_pos,
+                    hit_normal,
+                    face: hit_normal.and_then(FaceIndex::from_normal),
+                    distance,
                 });
             }
 
-            // advance to the next block position
+            // advance to the next block position. A ray stepping backwards onto a block boundary
+            // stays in the same block for one more epsilon step, which mustn't count as entering
+            // it from itself
             let deltas = (dir_step - ray_pos.fract_gl()) * dir_recip;
-            t += deltas.min_element().max(EPS);
+            t += deltas.min_element().max(options.epsilon);
 
-            previous_block_pos = Some(block_pos);
+            let next_block_pos = (ray_origin + ray_direction * t).floor().as_ivec3();
+            if next_block_pos != block_pos.as_ivec3() {
+                previous_block_pos = Some(block_pos);
+            }
         }
 
         None
     }
 }
 
+/// Normal of the face through which a ray entered a block from the neighbouring block or chunk
+/// at `entry_offset` from it. A ray passing exactly through an edge or corner enters from a
+/// diagonal neighbour, in which case the face is the one on the axis that the ray moves along
+/// fastest
+fn entry_face_normal(entry_offset: IVec3, ray_direction: Vec3) -> IVec3 {
+    let axis = (0..3)
+        .filter(|&axis| entry_offset[axis] != 0)
+        .max_by(|&a, &b| ray_direction[a].abs().total_cmp(&ray_direction[b].abs()))
+        .unwrap_or(0);
+
+    let mut normal = IVec3::ZERO;
+    normal[axis] = entry_offset[axis].signum();
+    normal
+}
+
 /// Returned by `Chunk::raymarch` if a block was hit
 pub struct ChunkHit {
     pub local_hit_pos: LocalBlockPosition,
     pub hit_normal: Option<IVec3>,
+    /// Face of the block that was hit, or None if the ray started inside the block
+    pub face: Option<FaceIndex>,
+    /// Distance from the origin of the ray to the point where it hit the block, in blocks
+    pub distance: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+
+    use super::*;
+    use crate::block::{BLOCK_AIR, BLOCK_DIRT};
+
+    /// Bounding boxes where dirt is a bottom slab, since there are no slab models yet
+    fn slab_bounding_box(block_id: BlockId) -> Option<(Vec3, Vec3)> {
+        match block_id {
+            BLOCK_AIR => None,
+            BLOCK_DIRT => Some((Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0))),
+            _ => Some((Vec3::ZERO, Vec3::ONE)),
+        }
+    }
+
+    /// Chunk that is empty apart from a single dirt block at `pos`
+    fn chunk_with_block(pos: LocalBlockPosition) -> Chunk {
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        blocks[pos.get_array_index()] = BLOCK_DIRT;
+        Chunk::new(ChunkPosition::from(IVec3::ZERO), blocks)
+    }
+
+    #[test]
+    fn ray_passes_over_bottom_slab() {
+        let chunk = chunk_with_block(LocalBlockPosition::from(UVec3::new(4, 4, 4)));
+
+        // horizontal ray through the empty upper half of the slab
+        let hit = chunk.raymarch_with_bounding_boxes(
+            Vec3::new(0.5, 4.75, 4.5),
+            Vec3::X,
+            None,
+            32.0,
+            RaymarchOptions::default(),
+            slab_bounding_box,
+        );
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn ray_hits_bottom_slab() {
+        let slab_pos = LocalBlockPosition::from(UVec3::new(4, 4, 4));
+        let chunk = chunk_with_block(slab_pos);
+
+        // horizontal ray through the solid lower half of the slab
+        let hit = chunk
+            .raymarch_with_bounding_boxes(
+                Vec3::new(0.5, 4.25, 4.5),
+                Vec3::X,
+                None,
+                32.0,
+                RaymarchOptions::default(),
+                slab_bounding_box,
+            )
+            .expect("ray should hit the side of the slab");
+        assert_eq!(hit.local_hit_pos, slab_pos);
+        assert_eq!(hit.hit_normal, Some(IVec3::NEG_X));
+
+        // downwards ray entering the top of the block, hitting the top of the slab inside it
+        let hit = chunk
+            .raymarch_with_bounding_boxes(
+                Vec3::new(4.5, 8.5, 4.5),
+                Vec3::NEG_Y,
+                None,
+                32.0,
+                RaymarchOptions::default(),
+                slab_bounding_box,
+            )
+            .expect("ray should hit the top of the slab");
+        assert_eq!(hit.local_hit_pos, slab_pos);
+        assert_eq!(hit.hit_normal, Some(IVec3::Y));
+    }
+
+    #[test]
+    fn ray_hits_full_block() {
+        let block_pos = LocalBlockPosition::from(UVec3::new(4, 4, 4));
+        let chunk = chunk_with_block(block_pos);
+
+        // the ray grazing the upper half of a full block should still hit it
+        let hit = chunk
+            .raymarch(
+                Vec3::new(0.5, 4.75, 4.5),
+                Vec3::X,
+                None,
+                32.0,
+                RaymarchOptions::default(),
+            )
+            .expect("ray should hit the full block");
+        assert_eq!(hit.local_hit_pos, block_pos);
+        assert_eq!(hit.hit_normal, Some(IVec3::NEG_X));
+    }
+
+    #[test]
+    fn axis_aligned_ray_along_block_boundary() {
+        let block_pos = LocalBlockPosition::from(UVec3::new(4, 4, 4));
+        let chunk = chunk_with_block(block_pos);
+
+        // the ray lies exactly on the lower faces of the block in y and z
+        for direction in [Vec3::X, Vec3::new(1.0, -0.0, -0.0)] {
+            let hit = chunk
+                .raymarch(
+                    Vec3::new(0.5, 4.0, 4.0),
+                    direction,
+                    None,
+                    32.0,
+                    RaymarchOptions::default(),
+                )
+                .expect("ray should hit the block");
+            assert_eq!(hit.local_hit_pos, block_pos);
+            assert_eq!(hit.hit_normal, Some(IVec3::NEG_X));
+        }
+
+        // a ray along the top of a slab touches it, which counts as a hit
+        let hit = chunk.raymarch_with_bounding_boxes(
+            Vec3::new(0.5, 4.5, 4.5),
+            Vec3::X,
+            None,
+            32.0,
+            RaymarchOptions::default(),
+            slab_bounding_box,
+        );
+        assert!(hit.is_some_and(|hit| hit.local_hit_pos == block_pos));
+    }
+
+    #[test]
+    fn ray_through_block_edge_hits_with_a_face_normal() {
+        let block_pos = LocalBlockPosition::from(UVec3::new(4, 4, 4));
+        let chunk = chunk_with_block(block_pos);
+
+        // the ray moves faster along y, so it hits the bottom face rather than the side
+        let hit = chunk
+            .raymarch(
+                Vec3::new(3.0, 2.0, 4.5),
+                Vec3::new(1.0, 2.0, 0.0),
+                None,
+                32.0,
+                RaymarchOptions::default(),
+            )
+            .expect("ray should hit the block through its edge");
+        assert_eq!(hit.local_hit_pos, block_pos);
+        assert_eq!(hit.hit_normal, Some(IVec3::NEG_Y));
+    }
+
+    #[test]
+    fn ray_with_negative_zero_components_does_not_stall() {
+        let block_pos = LocalBlockPosition::from(UVec3::new(4, 20, 4));
+        let chunk = chunk_with_block(block_pos);
+
+        // with few steps allowed, the ray only reaches the block if it steps a whole block at a
+        // time rather than creeping along by epsilon
+        let options = RaymarchOptions {
+            max_steps: 32,
+            ..Default::default()
+        };
+
+        let hit = chunk
+            .raymarch(
+                Vec3::new(4.5, 0.5, 4.5),
+                Vec3::new(-0.0, 1.0, -0.0),
+                None,
+                32.0,
+                options,
+            )
+            .expect("ray should hit the block");
+        assert_eq!(hit.local_hit_pos, block_pos);
+        assert_eq!(hit.hit_normal, Some(IVec3::NEG_Y));
+    }
 }
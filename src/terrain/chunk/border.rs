@@ -0,0 +1,135 @@
+use generational_arena::Index;
+use glam::{IVec3, UVec3};
+
+use super::{Chunk, CHUNK_SIZE, CHUNK_SIZE_I32};
+use crate::{
+    block::{BlockId, BLOCK_AIR},
+    terrain::{
+        position_types::{ChunkPosition, LocalBlockPosition},
+        Terrain,
+    },
+    util::size::Size3,
+};
+
+/// Size of a chunk together with its border, on each axis
+const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+
+/// Snapshot of the blocks of the surrounding chunks that touch a chunk, including along its edges
+/// and at its corners, so that the chunk can be meshed on another thread with ambient occlusion
+/// that continues across chunk boundaries.
+/// Blocks in chunks that aren't loaded are air
+#[derive(Clone, Debug)]
+pub struct ChunkBorder {
+    /// Blocks of the chunk and its border, ordered by y, then z, then x. Only the border is filled
+    /// in; the blocks inside the chunk are air
+    blocks: Box<[BlockId]>,
+}
+
+impl ChunkBorder {
+    /// Border made entirely of air, as if none of the surrounding chunks were loaded
+    pub fn air() -> Self {
+        Self {
+            blocks: vec![BLOCK_AIR; PADDED_SIZE * PADDED_SIZE * PADDED_SIZE].into_boxed_slice(),
+        }
+    }
+
+    /// Border made of the blocks returned by `get_block` for each position relative to the origin
+    /// of the chunk
+    pub fn from_fn(mut get_block: impl FnMut(IVec3) -> BlockId) -> Self {
+        let mut border = Self::air();
+
+        for pos in Self::positions() {
+            border.blocks[Self::index(pos)] = get_block(pos);
+        }
+
+        border
+    }
+
+    /// Take a snapshot of the border of the chunk at `center_pos` from the loaded chunks around it
+    pub fn get_border(
+        center_pos: ChunkPosition,
+        terrain: &Terrain,
+        load_area_index: Index,
+    ) -> Self {
+        // look up each of the surrounding chunks once rather than for every block
+        let neighbours: Vec<Option<&Chunk>> = itertools::iproduct!(-1..=1, -1..=1, -1..=1)
+            .map(|(z, y, x)| {
+                terrain.get_chunk(load_area_index, &(center_pos + ChunkPosition::new(x, y, z)))
+            })
+            .collect();
+
+        Self::from_fn(|pos| {
+            let offset = pos.div_euclid(IVec3::splat(CHUNK_SIZE_I32)) + 1;
+            let neighbour_index = (9 * offset.z + 3 * offset.y + offset.x) as usize;
+
+            neighbours[neighbour_index].map_or(BLOCK_AIR, |chunk| {
+                let local_pos = pos.rem_euclid(IVec3::splat(CHUNK_SIZE_I32)).as_uvec3();
+                chunk.get_block(LocalBlockPosition::from(local_pos))
+            })
+        })
+    }
+
+    /// Returns the block at the given position relative to the origin of the chunk, which must be
+    /// in the border
+    pub fn get(&self, pos: IVec3) -> BlockId {
+        debug_assert!(Self::is_in_border(pos));
+        self.blocks[Self::index(pos)]
+    }
+
+    /// Iterator over the blocks in the border, in the order of `positions`
+    pub fn blocks(&self) -> impl Iterator<Item = BlockId> + '_ {
+        Self::positions().map(|pos| self.blocks[Self::index(pos)])
+    }
+
+    /// Iterator over the positions in the border relative to the origin of the chunk, ordered by
+    /// y, then z, then x
+    fn positions() -> impl Iterator<Item = IVec3> {
+        let range = -1..=CHUNK_SIZE_I32;
+
+        itertools::iproduct!(range.clone(), range.clone(), range)
+            .map(|(y, z, x)| IVec3::new(x, y, z))
+            .filter(|&pos| Self::is_in_border(pos))
+    }
+
+    fn is_in_border(pos: IVec3) -> bool {
+        let in_chunk =
+            pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(CHUNK_SIZE_I32)).all();
+        let in_padded_chunk =
+            pos.cmpge(IVec3::NEG_ONE).all() && pos.cmple(IVec3::splat(CHUNK_SIZE_I32)).all();
+
+        in_padded_chunk && !in_chunk
+    }
+
+    fn index(pos: IVec3) -> usize {
+        let padded_pos = (pos + 1).as_uvec3();
+        Size3::splat(PADDED_SIZE).flatten(UVec3::new(padded_pos.x, padded_pos.z, padded_pos.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BLOCK_DIRT;
+
+    #[test]
+    fn border_surrounds_the_chunk() {
+        let border_len = PADDED_SIZE.pow(3) - CHUNK_SIZE.pow(3);
+        assert_eq!(ChunkBorder::positions().count(), border_len);
+
+        // each position in the border has its own block
+        let block_at = |pos: IVec3| BlockId((pos.x + 2 * pos.y + 3 * pos.z + 6) as u16);
+        let border = ChunkBorder::from_fn(block_at);
+        for pos in [IVec3::NEG_ONE, IVec3::new(32, 5, 7), IVec3::new(3, -1, 32)] {
+            assert_eq!(border.get(pos), block_at(pos));
+        }
+
+        let walls = ChunkBorder::from_fn(|pos| if pos.x < 0 { BLOCK_DIRT } else { BLOCK_AIR });
+        assert_eq!(
+            walls
+                .blocks()
+                .filter(|&block_id| block_id == BLOCK_DIRT)
+                .count(),
+            PADDED_SIZE * PADDED_SIZE
+        );
+    }
+}
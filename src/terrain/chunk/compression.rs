@@ -0,0 +1,173 @@
+use itertools::repeat_n;
+
+use crate::{block::BlockId, terrain::position_types::ChunkPosition};
+
+use super::{
+    storage::ChunkBlockStorage, summary::ChunkSummary, visibility_graph::VisibilityGraph, Chunk,
+    CHUNK_SIZE_CUBED,
+};
+
+/// Run-length encoded block array. Generated terrain is mostly long runs of air and solid blocks,
+/// so this is far smaller than the raw array and somewhat smaller than the palette storage
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CompressedBlocks {
+    runs: Box<[BlockRun]>,
+}
+
+/// Run of identical consecutive blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct BlockRun {
+    block_id: BlockId,
+    /// Number of blocks in the run. A chunk has 32768 blocks, so this always fits
+    length: u16,
+}
+
+impl CompressedBlocks {
+    /// Compress an array of block IDs, usually for a whole chunk ordered by y, then z, then x
+    pub fn compress(blocks: &[BlockId]) -> Self {
+        let mut runs: Vec<BlockRun> = Vec::new();
+
+        for &block_id in blocks {
+            match runs.last_mut() {
+                Some(run) if run.block_id == block_id && run.length < u16::MAX => run.length += 1,
+                _ => runs.push(BlockRun {
+                    block_id,
+                    length: 1,
+                }),
+            }
+        }
+
+        Self {
+            runs: runs.into_boxed_slice(),
+        }
+    }
+
+    /// Expand back into the array of block IDs that was compressed
+    pub fn decompress(&self) -> Vec<BlockId> {
+        let mut blocks = Vec::with_capacity(CHUNK_SIZE_CUBED);
+
+        for run in self.runs.iter() {
+            blocks.extend(repeat_n(run.block_id, run.length as usize));
+        }
+
+        blocks
+    }
+
+    /// Returns the block making up the whole array, if it is made of a single kind of block
+    pub fn uniform_block(&self) -> Option<BlockId> {
+        match *self.runs {
+            [run] => Some(run.block_id),
+            _ => None,
+        }
+    }
+
+    /// Iterate over the runs of identical blocks as pairs of block ID and run length
+    pub fn runs(&self) -> impl Iterator<Item = (BlockId, u16)> + '_ {
+        self.runs
+            .iter()
+            .map(|run| (run.block_id, run.length))
+    }
+}
+
+/// Loaded chunk whose blocks are run-length encoded while it waits in the channel from the
+/// loading tasks to the main thread. While a large area loads, thousands of chunks can queue up
+/// there. The visibility graph and summary are kept, so that only rebuilding the block storage is
+/// left to the main thread
+#[derive(Clone, Debug)]
+pub struct CompressedChunk {
+    pos: ChunkPosition,
+    blocks: CompressedBlocks,
+    visibility_graph: VisibilityGraph,
+    summary: ChunkSummary,
+}
+
+impl CompressedChunk {
+    /// Compress a chunk. Its light is not kept, as chunks are lit after they are loaded
+    pub fn new(chunk: Chunk) -> Self {
+        Self {
+            pos: chunk.pos,
+            blocks: CompressedBlocks::compress(&chunk.blocks.as_block_array()),
+            visibility_graph: chunk.visibility_graph,
+            summary: chunk.summary,
+        }
+    }
+
+    pub fn position(&self) -> ChunkPosition {
+        self.pos
+    }
+
+    /// Expand into a chunk with the compressed blocks and no light
+    pub fn decompress(self) -> Chunk {
+        // uniform chunks, such as those in the sky, don't need the array at all
+        let blocks = match self.blocks.uniform_block() {
+            Some(block_id) => ChunkBlockStorage::Uniform(block_id),
+            None => ChunkBlockStorage::new(self.blocks.decompress()),
+        };
+
+        Chunk {
+            pos: self.pos,
+            blocks,
+            visibility_graph: self.visibility_graph,
+            summary: self.summary,
+            light: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BLOCK_AIR, BLOCK_DIRT, BLOCK_GRASS, BLOCK_WOOD};
+
+    #[test]
+    fn compression_is_lossless() {
+        // solid bottom half, a grass surface, scattered wood above and a checkerboard layer
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        for (index, block_id) in blocks.iter_mut().enumerate() {
+            *block_id = match index / 1024 {
+                0..=14 => BLOCK_DIRT,
+                15 => BLOCK_GRASS,
+                20 if index % 2 == 0 => BLOCK_WOOD,
+                _ if index % 997 == 0 => BLOCK_WOOD,
+                _ => BLOCK_AIR,
+            };
+        }
+
+        let compressed = CompressedBlocks::compress(&blocks);
+        assert_eq!(compressed.decompress(), blocks);
+        assert!(
+            std::mem::size_of_val(&*compressed.runs)
+                < CHUNK_SIZE_CUBED * std::mem::size_of::<BlockId>() / 4
+        );
+
+        // a chunk of a single block is one run
+        let uniform = CompressedBlocks::compress(&[BLOCK_DIRT; CHUNK_SIZE_CUBED]);
+        assert_eq!(uniform.runs.len(), 1);
+        assert_eq!(uniform.decompress(), vec![BLOCK_DIRT; CHUNK_SIZE_CUBED]);
+        assert_eq!(uniform.uniform_block(), Some(BLOCK_DIRT));
+    }
+
+    #[test]
+    fn compressed_chunk_decompresses_to_the_same_chunk() {
+        let blocks = (0..CHUNK_SIZE_CUBED)
+            .map(|index| match index % 7 {
+                0 | 1 => BLOCK_DIRT,
+                2 => BLOCK_WOOD,
+                _ => BLOCK_AIR,
+            })
+            .collect::<Vec<_>>();
+        let pos = ChunkPosition::new(1, -2, 3);
+
+        for blocks in [blocks, vec![BLOCK_AIR; CHUNK_SIZE_CUBED]] {
+            let expected = Chunk::new(pos, blocks);
+            let chunk = CompressedChunk::new(expected.clone()).decompress();
+
+            assert_eq!(chunk.position(), pos);
+            assert_eq!(
+                chunk.get_block_storage().as_block_array(),
+                expected.get_block_storage().as_block_array()
+            );
+            assert_eq!(chunk.summary(), expected.summary());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Binary save format for the blocks of a chunk
+//!
+//! Saved chunks refer to blocks by name rather than by `BlockId`, so that a chunk saved with one
+//! block registry can be loaded after blocks are added, reordered or removed. The layout is, with
+//! all integers little-endian:
+//! - format version (`u8`)
+//! - number of palette entries (`u16`), followed by the name of each block in the palette as a
+//!   length (`u8`) and UTF-8 bytes
+//! - number of runs (`u16`), followed by each run of identical blocks as a palette index (`u16`)
+//!   and length (`u16`), ordered by y, then z, then x
+
+use itertools::repeat_n;
+use rustc_hash::FxHashMap;
+
+use super::{compression::CompressedBlocks, CHUNK_SIZE_CUBED};
+use crate::block::BlockId;
+
+/// Version written by `serialize_blocks`. Increase this when the format changes, and keep a way
+/// to read older versions in `deserialize_blocks`
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Serialize the blocks of a chunk, ordered by y, then z, then x.
+/// `block_names` gives the name of each block, indexed by `BlockId`
+pub fn serialize_blocks(blocks: &[BlockId], block_names: &[&str]) -> Vec<u8> {
+    debug_assert!(blocks.len() == CHUNK_SIZE_CUBED);
+
+    let compressed = CompressedBlocks::compress(blocks);
+
+    // palette of the blocks present in the chunk, in order of first appearance
+    let mut palette: Vec<BlockId> = Vec::new();
+    let mut palette_indices: FxHashMap<BlockId, u16> = FxHashMap::default();
+    let runs: Vec<(u16, u16)> = compressed
+        .runs()
+        .map(|(block_id, length)| {
+            let palette_index = *palette_indices
+                .entry(block_id)
+                .or_insert_with(|| {
+                    palette.push(block_id);
+                    (palette.len() - 1) as u16
+                });
+            (palette_index, length)
+        })
+        .collect();
+
+    let mut bytes = vec![FORMAT_VERSION];
+
+    bytes.extend((palette.len() as u16).to_le_bytes());
+    for block_id in palette {
+        let name = block_names[block_id.0 as usize];
+        debug_assert!(name.len() <= u8::MAX as usize);
+
+        bytes.push(name.len() as u8);
+        bytes.extend(name.as_bytes());
+    }
+
+    bytes.extend((runs.len() as u16).to_le_bytes());
+    for (palette_index, length) in runs {
+        bytes.extend(palette_index.to_le_bytes());
+        bytes.extend(length.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Deserialize the blocks of a chunk saved with `serialize_blocks`, possibly with an older
+/// version of the format or a different block registry.
+/// Saved block names are mapped to the current IDs using `block_names`, which gives the name of
+/// each block indexed by `BlockId`. Blocks whose names are no longer registered are replaced
+/// with `placeholder`
+pub fn deserialize_blocks(
+    bytes: &[u8],
+    block_names: &[&str],
+    placeholder: BlockId,
+) -> Result<Vec<BlockId>, ChunkDeserializeError> {
+    let mut reader = ByteReader { bytes };
+
+    match reader.read_u8()? {
+        1 => deserialize_blocks_v1(&mut reader, block_names, placeholder),
+        version => Err(ChunkDeserializeError::UnsupportedVersion(version)),
+    }
+}
+
+fn deserialize_blocks_v1(
+    reader: &mut ByteReader,
+    block_names: &[&str],
+    placeholder: BlockId,
+) -> Result<Vec<BlockId>, ChunkDeserializeError> {
+    let current_ids: FxHashMap<&str, BlockId> = block_names
+        .iter()
+        .enumerate()
+        .map(|(index, &name)| (name, BlockId(index as u16)))
+        .collect();
+
+    let palette_len = reader.read_u16()?;
+    let palette = (0..palette_len)
+        .map(|_| {
+            let name_len = reader.read_u8()?;
+            let name = std::str::from_utf8(reader.read_bytes(name_len as usize)?)
+                .map_err(|_| ChunkDeserializeError::InvalidBlockName)?;
+
+            Ok(current_ids
+                .get(name)
+                .copied()
+                .unwrap_or_else(|| {
+                    log::warn!("block \"{name}\" is no longer registered, replacing it");
+                    placeholder
+                }))
+        })
+        .collect::<Result<Vec<BlockId>, ChunkDeserializeError>>()?;
+
+    let run_count = reader.read_u16()?;
+    let mut blocks = Vec::with_capacity(CHUNK_SIZE_CUBED);
+    for _ in 0..run_count {
+        let palette_index = reader.read_u16()?;
+        let length = reader.read_u16()?;
+        let block_id = *palette
+            .get(palette_index as usize)
+            .ok_or(ChunkDeserializeError::InvalidPaletteIndex(palette_index))?;
+
+        blocks.extend(repeat_n(block_id, length as usize));
+    }
+
+    if blocks.len() != CHUNK_SIZE_CUBED {
+        return Err(ChunkDeserializeError::WrongBlockCount(blocks.len()));
+    }
+
+    Ok(blocks)
+}
+
+/// Reads little-endian values from the front of a byte slice
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], ChunkDeserializeError> {
+        if self.bytes.len() < count {
+            return Err(ChunkDeserializeError::UnexpectedEnd);
+        }
+
+        let (read, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(read)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ChunkDeserializeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, ChunkDeserializeError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+/// errors returned by `deserialize_blocks`
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ChunkDeserializeError {
+    #[error("unsupported format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unexpected end of data")]
+    UnexpectedEnd,
+    #[error("block name is not valid UTF-8")]
+    InvalidBlockName,
+    #[error("palette index {0} is out of range")]
+    InvalidPaletteIndex(u16),
+    #[error("expected {CHUNK_SIZE_CUBED} blocks, found {0}")]
+    WrongBlockCount(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BLOCKS;
+
+    /// A chunk with a solid bottom half of `bottom` and a layer of `surface` on top
+    fn layered_blocks(bottom: BlockId, surface: BlockId) -> Vec<BlockId> {
+        (0..CHUNK_SIZE_CUBED)
+            .map(|index| match index / 1024 {
+                0..=14 => bottom,
+                15 => surface,
+                _ => BlockId(0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_with_current_registry() {
+        let block_names = BLOCKS
+            .iter()
+            .map(|block| block.name)
+            .collect::<Vec<_>>();
+        let blocks = layered_blocks(BlockId(1), BlockId(2));
+
+        let bytes = serialize_blocks(&blocks, &block_names);
+        assert_eq!(bytes[0], FORMAT_VERSION);
+        assert_eq!(deserialize_blocks(&bytes, &block_names, BlockId(0)), Ok(blocks));
+    }
+
+    #[test]
+    fn blocks_are_remapped_by_name() {
+        let old_registry = ["air", "dirt", "grass", "wood"];
+        // wood and dirt swapped places, stone was added and grass was removed
+        let new_registry = ["air", "wood", "stone", "dirt"];
+        let placeholder = BlockId(2);
+
+        let saved = layered_blocks(BlockId(1), BlockId(2));
+        let bytes = serialize_blocks(&saved, &old_registry);
+        let loaded = deserialize_blocks(&bytes, &new_registry, placeholder).unwrap();
+
+        let dirt = BlockId(3);
+        assert_eq!(loaded, layered_blocks(dirt, placeholder));
+    }
+
+    #[test]
+    fn invalid_data_is_rejected() {
+        let block_names = ["air", "dirt"];
+        let bytes = serialize_blocks(&layered_blocks(BlockId(1), BlockId(1)), &block_names);
+
+        let mut future_version = bytes.clone();
+        future_version[0] = FORMAT_VERSION + 1;
+        assert_eq!(
+            deserialize_blocks(&future_version, &block_names, BlockId(0)),
+            Err(ChunkDeserializeError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+
+        assert_eq!(
+            deserialize_blocks(&bytes[..bytes.len() - 1], &block_names, BlockId(0)),
+            Err(ChunkDeserializeError::UnexpectedEnd)
+        );
+    }
+}
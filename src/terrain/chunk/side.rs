@@ -12,7 +12,8 @@ use crate::{
     util::face::FaceIndex,
 };
 
-/// Represents a side of a chunk, storing whether each tile is solid (false) or empty (true).
+/// Represents a side of a chunk, storing whether each tile hides the faces of adjacent blocks
+/// (false) or not (true).
 /// The tiles are indexed as follows:
 ///   CHUNK_SIZE * V + U
 /// where U goes in the direction of the first texture coordinate and V goes in the direction of
@@ -32,10 +33,9 @@ impl ChunkSide {
                 let pos_in_chunk = LocalBlockPosition::new(CHUNK_SIZE_U32 - 1, v, u);
                 let block_id = chunk.get_block(pos_in_chunk);
                 let block = &BLOCKS[block_id.0 as usize];
-                faces[index] = block
+                faces[index] = !block
                     .model
-                    .face(FaceIndex::POS_X)
-                    .is_none();
+                    .hides_adjacent_faces(FaceIndex::POS_X);
                 index += 1;
             }
         }
@@ -54,10 +54,9 @@ impl ChunkSide {
                 let pos_in_chunk = LocalBlockPosition::new(v, CHUNK_SIZE_U32 - 1, u);
                 let block_id = chunk.get_block(pos_in_chunk);
                 let block = &BLOCKS[block_id.0 as usize];
-                faces[index] = block
+                faces[index] = !block
                     .model
-                    .face(FaceIndex::POS_Y)
-                    .is_none();
+                    .hides_adjacent_faces(FaceIndex::POS_Y);
                 index += 1;
             }
         }
@@ -76,10 +75,9 @@ impl ChunkSide {
                 let pos_in_chunk = LocalBlockPosition::new(u, v, CHUNK_SIZE_U32 - 1);
                 let block_id = chunk.get_block(pos_in_chunk);
                 let block = &BLOCKS[block_id.0 as usize];
-                faces[index] = block
+                faces[index] = !block
                     .model
-                    .face(FaceIndex::POS_Z)
-                    .is_none();
+                    .hides_adjacent_faces(FaceIndex::POS_Z);
                 index += 1;
             }
         }
@@ -98,10 +96,9 @@ impl ChunkSide {
                 let pos_in_chunk = LocalBlockPosition::new(0, v, u);
                 let block_id = chunk.get_block(pos_in_chunk);
                 let block = &BLOCKS[block_id.0 as usize];
-                faces[index] = block
+                faces[index] = !block
                     .model
-                    .face(FaceIndex::NEG_X)
-                    .is_none();
+                    .hides_adjacent_faces(FaceIndex::NEG_X);
                 index += 1;
             }
         }
@@ -120,10 +117,9 @@ impl ChunkSide {
                 let pos_in_chunk = LocalBlockPosition::new(v, 0, u);
                 let block_id = chunk.get_block(pos_in_chunk);
                 let block = &BLOCKS[block_id.0 as usize];
-                faces[index] = block
+                faces[index] = !block
                     .model
-                    .face(FaceIndex::NEG_Y)
-                    .is_none();
+                    .hides_adjacent_faces(FaceIndex::NEG_Y);
                 index += 1;
             }
         }
@@ -142,10 +138,9 @@ impl ChunkSide {
                 let pos_in_chunk = LocalBlockPosition::new(u, v, 0);
                 let block_id = chunk.get_block(pos_in_chunk);
                 let block = &BLOCKS[block_id.0 as usize];
-                faces[index] = block
+                faces[index] = !block
                     .model
-                    .face(FaceIndex::NEG_Z)
-                    .is_none();
+                    .hides_adjacent_faces(FaceIndex::NEG_Z);
                 index += 1;
             }
         }
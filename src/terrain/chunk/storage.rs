@@ -59,6 +59,24 @@ impl ChunkBlockStorage {
         }
     }
 
+    /// Returns the block making up the whole chunk, if the chunk is made of a single kind of
+    /// block. This only looks at the palettes, so it can miss chunks whose layers were edited
+    /// until they happen to contain a single kind of block
+    pub fn uniform_block(&self) -> Option<BlockId> {
+        match self {
+            Self::Uniform(block_id) => Some(*block_id),
+            Self::Layered(layers) => layers
+                .iter()
+                .map(|layer| match layer.palette.block_lookup.as_slice() {
+                    [block_id] => Some(*block_id),
+                    _ => None,
+                })
+                .all_equal_value()
+                .ok()
+                .flatten(),
+        }
+    }
+
     /// Returns the block ID at the given position
     /// panics if the position is out of bounds
     pub fn get_block(&self, pos: LocalBlockPosition) -> BlockId {
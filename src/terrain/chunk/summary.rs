@@ -0,0 +1,203 @@
+use crate::{
+    block::{BlockId, BLOCKS},
+    terrain::{
+        chunk::{CHUNK_SIZE, CHUNK_SIZE_CUBED, CHUNK_SIZE_SQUARED},
+        position_types::LocalBlockPosition,
+    },
+};
+
+/// Summary of which blocks of a chunk are solid, kept up to date as the chunk is edited, so that
+/// queries such as finding the surface, skipping empty chunks for collision or building minimap
+/// heights don't need to scan every block. See `Block::is_solid`.
+/// Also counts the blocks that emit light, so that chunks without any can skip lighting
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkSummary {
+    /// Number of solid blocks in the chunk
+    solid_count: usize,
+    /// Number of blocks in the chunk that emit light
+    emitter_count: usize,
+    /// Highest solid block in each column, indexed by z, then x, or None if the column has no
+    /// solid blocks
+    highest_solid_y: [Option<u8>; CHUNK_SIZE_SQUARED],
+}
+
+impl ChunkSummary {
+    /// Compute the summary for the given block array, ordered by y, then z, then x
+    pub fn compute(blocks: &[BlockId]) -> Self {
+        debug_assert!(blocks.len() == CHUNK_SIZE_CUBED);
+
+        let mut solid_count = 0;
+        let mut emitter_count = 0;
+        let mut highest_solid_y = [None; CHUNK_SIZE_SQUARED];
+
+        // layers are visited from the bottom up, so the last solid block seen in each column is
+        // the highest
+        for (y, layer) in blocks.chunks_exact(CHUNK_SIZE_SQUARED).enumerate() {
+            for (column_index, &block_id) in layer.iter().enumerate() {
+                if is_solid(block_id) {
+                    solid_count += 1;
+                    highest_solid_y[column_index] = Some(y as u8);
+                }
+                if emits_light(block_id) {
+                    emitter_count += 1;
+                }
+            }
+        }
+
+        Self {
+            solid_count,
+            emitter_count,
+            highest_solid_y,
+        }
+    }
+
+    /// Update the summary after the block at `pos` changed from `old_id` to `new_id`.
+    /// `get_block` returns the current block at a position in the chunk, used to find the next
+    /// highest solid block when the highest block of a column is removed
+    pub fn block_changed(
+        &mut self,
+        pos: LocalBlockPosition,
+        old_id: BlockId,
+        new_id: BlockId,
+        get_block: impl Fn(LocalBlockPosition) -> BlockId,
+    ) {
+        self.emitter_count += usize::from(emits_light(new_id));
+        self.emitter_count -= usize::from(emits_light(old_id));
+
+        let (was_solid, now_solid) = (is_solid(old_id), is_solid(new_id));
+        if was_solid == now_solid {
+            return;
+        }
+
+        let pos = pos.as_uvec3();
+        let highest_solid_y = &mut self.highest_solid_y[column_index(pos.x, pos.z)];
+
+        if now_solid {
+            self.solid_count += 1;
+            *highest_solid_y = (*highest_solid_y).max(Some(pos.y as u8));
+        } else {
+            self.solid_count -= 1;
+            if *highest_solid_y == Some(pos.y as u8) {
+                *highest_solid_y = (0..pos.y)
+                    .rev()
+                    .find(|&y| is_solid(get_block(LocalBlockPosition::new(pos.x, y, pos.z))))
+                    .map(|y| y as u8);
+            }
+        }
+    }
+
+    /// True if the chunk has no solid blocks
+    pub fn is_empty(&self) -> bool {
+        self.solid_count == 0
+    }
+
+    /// True if every block in the chunk is solid
+    pub fn is_full(&self) -> bool {
+        self.solid_count == CHUNK_SIZE_CUBED
+    }
+
+    /// Number of solid blocks in the chunk
+    pub fn solid_count(&self) -> usize {
+        self.solid_count
+    }
+
+    /// True if some block in the chunk emits light
+    pub fn has_emitters(&self) -> bool {
+        self.emitter_count > 0
+    }
+
+    /// Returns the y coordinate of the highest solid block in the column at the given x and z in
+    /// the chunk, or None if the column has no solid blocks
+    pub fn highest_solid_y(&self, x: u32, z: u32) -> Option<u32> {
+        self.highest_solid_y[column_index(x, z)].map(u32::from)
+    }
+}
+
+fn column_index(x: u32, z: u32) -> usize {
+    debug_assert!((x as usize) < CHUNK_SIZE && (z as usize) < CHUNK_SIZE);
+    z as usize * CHUNK_SIZE + x as usize
+}
+
+fn is_solid(block_id: BlockId) -> bool {
+    BLOCKS[block_id.0 as usize].is_solid()
+}
+
+fn emits_light(block_id: BlockId) -> bool {
+    BLOCKS[block_id.0 as usize].emits_light()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{BLOCK_AIR, BLOCK_DIRT, BLOCK_LAMP_ORANGE},
+        terrain::{chunk::Chunk, position_types::ChunkPosition},
+    };
+
+    #[test]
+    fn uniform_chunks() {
+        let air = ChunkSummary::compute(&vec![BLOCK_AIR; CHUNK_SIZE_CUBED]);
+        assert!(air.is_empty());
+        assert!(!air.is_full());
+        assert_eq!(air.solid_count(), 0);
+        assert_eq!(air.highest_solid_y(0, 0), None);
+        assert_eq!(air.highest_solid_y(31, 31), None);
+
+        let dirt = ChunkSummary::compute(&vec![BLOCK_DIRT; CHUNK_SIZE_CUBED]);
+        assert!(!dirt.is_empty());
+        assert!(dirt.is_full());
+        assert_eq!(dirt.solid_count(), CHUNK_SIZE_CUBED);
+        assert_eq!(dirt.highest_solid_y(0, 0), Some(31));
+        assert_eq!(dirt.highest_solid_y(17, 5), Some(31));
+    }
+
+    #[test]
+    fn single_column_is_kept_up_to_date() {
+        // a column of dirt from y = 0 to 9 at x = 3, z = 7
+        let mut blocks = vec![BLOCK_AIR; CHUNK_SIZE_CUBED];
+        for y in 0..10 {
+            blocks[LocalBlockPosition::new(3, y, 7).get_array_index()] = BLOCK_DIRT;
+        }
+        let mut chunk = Chunk::new(ChunkPosition::ZERO, blocks);
+
+        let summary = chunk.summary();
+        assert!(!summary.is_empty());
+        assert!(!summary.is_full());
+        assert_eq!(summary.solid_count(), 10);
+        assert_eq!(summary.highest_solid_y(3, 7), Some(9));
+        assert_eq!(summary.highest_solid_y(7, 3), None);
+
+        // removing the top block lowers the column
+        chunk.set_block(LocalBlockPosition::new(3, 9, 7), BLOCK_AIR);
+        assert_eq!(chunk.summary().highest_solid_y(3, 7), Some(8));
+
+        // removing a block lower down leaves the top alone
+        chunk.set_block(LocalBlockPosition::new(3, 2, 7), BLOCK_AIR);
+        assert_eq!(chunk.summary().highest_solid_y(3, 7), Some(8));
+
+        // placing a floating block above the column raises it
+        chunk.set_block(LocalBlockPosition::new(3, 20, 7), BLOCK_DIRT);
+        assert_eq!(chunk.summary().highest_solid_y(3, 7), Some(20));
+
+        // replacing a solid block with another changes nothing
+        chunk.set_block(LocalBlockPosition::new(3, 20, 7), BLOCK_DIRT);
+        assert_eq!(chunk.summary().solid_count(), 9);
+
+        // swapping it for a lamp adds an emitter
+        assert!(!chunk.summary().has_emitters());
+        chunk.set_block(LocalBlockPosition::new(3, 20, 7), BLOCK_LAMP_ORANGE);
+        assert!(chunk.summary().has_emitters());
+
+        // the summary matches one computed from scratch
+        let blocks = chunk.get_block_storage().as_block_array();
+        assert_eq!(*chunk.summary(), ChunkSummary::compute(&blocks));
+
+        // emptying the chunk
+        for y in [0, 1, 3, 4, 5, 6, 7, 8, 20] {
+            chunk.set_block(LocalBlockPosition::new(3, y, 7), BLOCK_AIR);
+        }
+        assert!(chunk.summary().is_empty());
+        assert!(!chunk.summary().has_emitters());
+        assert_eq!(chunk.summary().highest_solid_y(3, 7), None);
+    }
+}
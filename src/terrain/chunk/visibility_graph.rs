@@ -46,10 +46,7 @@ impl VisibilityGraph {
                     }
 
                     // skip opaque blocks
-                    if BLOCKS[blocks[array_index].0 as usize]
-                        .model
-                        .is_opaque()
-                    {
+                    if BLOCKS[blocks[array_index].0 as usize].is_opaque() {
                         continue;
                     }
 
@@ -71,10 +68,7 @@ impl VisibilityGraph {
                                 if explored[array_index] {
                                     continue;
                                 }
-                                if BLOCKS[blocks[array_index].0 as usize]
-                                    .model
-                                    .is_opaque()
-                                {
+                                if BLOCKS[blocks[array_index].0 as usize].is_opaque() {
                                     continue;
                                 }
                                 frontier.push_back(neighbour_pos);
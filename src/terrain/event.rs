@@ -5,4 +5,12 @@ pub enum TerrainEvent {
     ChunkLoaded(ChunkPosition),
     ChunkUnloaded(ChunkPosition),
     BlockModified(ChunkPosition, LocalBlockPosition),
+    /// Any number of blocks in the chunk were modified at once, e.g. by placing a structure
+    ChunkModified(ChunkPosition),
+    /// The mesh of the chunk should be rebuilt although its blocks haven't changed, e.g. because
+    /// the meshing options changed
+    ChunkRemeshRequested(ChunkPosition),
+    /// The light of some blocks in the chunk or just outside it changed, so the chunk's mesh is
+    /// out of date although its blocks are not
+    ChunkLightChanged(ChunkPosition),
 }
@@ -0,0 +1,236 @@
+//! Generation of new chunks
+
+use bracket_noise::prelude::*;
+use glam::{IVec3, UVec3, Vec3};
+
+use super::{
+    chunk::{
+        CHUNK_SIZE, CHUNK_SIZE_CUBED, CHUNK_SIZE_I32, CHUNK_SIZE_SQUARED, CHUNK_SIZE_U32,
+    },
+    position_types::{ChunkPosition, GlobalBlockPosition, LocalBlockPosition},
+    structure::{PlacementMode, Structure},
+    WorldBounds,
+};
+use crate::{
+    block::{BlockId, BLOCKS, BLOCK_AIR, BLOCK_BEDROCK, BLOCK_DIRT, BLOCK_GRASS},
+    util::size::Size3,
+};
+
+/// One in this many grass columns has a tree growing from it
+const TREE_RARITY: u32 = 97;
+
+/// Produces the blocks of chunks that are loaded for the first time. Implementations must be
+/// deterministic, so that a chunk is generated identically every time it is loaded with the same
+/// seed. NB: this is called from worker threads
+pub trait WorldGenerator: std::fmt::Debug + Send + Sync {
+    /// Returns the blocks of the chunk at the given position, ordered by y, then z, then x
+    fn generate_chunk(&self, chunk_pos: ChunkPosition) -> Vec<BlockId>;
+}
+
+/// Parameters of the noise that shapes the terrain, passed to `Terrain::new` and changed at
+/// runtime with `Terrain::set_generation_params`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GenerationParams {
+    /// Seed of the terrain noise. The cave noise uses the next seed
+    pub seed: u64,
+    /// Number of octaves of the terrain noise
+    pub octaves: i32,
+    /// Frequency of the first octave of the terrain noise, in cycles per block
+    pub frequency: f32,
+    /// Height in blocks that the terrain noise can raise or lower the surface by
+    pub amplitude: f32,
+    /// Height that the surface is centred around
+    pub sea_level: f32,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            octaves: 7,
+            frequency: 0.003,
+            amplitude: 100.0,
+            sea_level: 0.0,
+        }
+    }
+}
+
+impl GenerationParams {
+    /// True if the terrain noise at `pos` is high enough for a block to be there
+    fn is_solid(&self, noise: &FastNoise, pos: Vec3) -> bool {
+        noise.get_noise3d(pos.x, pos.y, pos.z) * self.amplitude > pos.y - self.sea_level
+    }
+}
+
+/// Default generator, shaping the terrain with layered simplex noise. Solid blocks are grass
+/// where they have air above them and dirt below that, with caves carved out by a second noise
+/// and trees scattered on the grass. The bottom of the world is filled with bedrock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseGenerator {
+    pub params: GenerationParams,
+    pub world_bounds: WorldBounds,
+}
+
+impl NoiseGenerator {
+    pub fn new(params: GenerationParams, world_bounds: WorldBounds) -> Self {
+        Self {
+            params,
+            world_bounds,
+        }
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate_chunk(&self, chunk_pos: ChunkPosition) -> Vec<BlockId> {
+        generate_chunk(chunk_pos, &self.params, &self.world_bounds)
+    }
+}
+
+fn generate_chunk(
+    pos: ChunkPosition,
+    params: &GenerationParams,
+    world_bounds: &WorldBounds,
+) -> Vec<BlockId> {
+    let mut blocks = vec![BlockId(0); CHUNK_SIZE_CUBED];
+
+    let chunk_offset = pos.as_vec3() * (CHUNK_SIZE as f32);
+
+    let mut noise = FastNoise::seeded(params.seed);
+    noise.set_noise_type(NoiseType::SimplexFractal);
+    noise.set_fractal_octaves(params.octaves);
+    noise.set_frequency(params.frequency);
+
+    let mut cave_noise = FastNoise::seeded(params.seed.wrapping_add(1));
+    cave_noise.set_noise_type(NoiseType::SimplexFractal);
+    cave_noise.set_fractal_octaves(3);
+    cave_noise.set_frequency(0.03);
+
+    for z in 0..CHUNK_SIZE_U32 {
+        for x in 0..CHUNK_SIZE_U32 {
+            let pos_above = UVec3::new(x, CHUNK_SIZE_U32, z).as_vec3() + chunk_offset;
+            let mut solid_above = params.is_solid(&noise, pos_above);
+
+            for y in 0..CHUNK_SIZE_U32 {
+                let y = CHUNK_SIZE_U32 - 1 - y;
+                let index = Size3::splat(CHUNK_SIZE).flatten(UVec3::new(x, z, y));
+
+                let pos = UVec3::new(x, y, z).as_vec3() + chunk_offset;
+
+                if params.is_solid(&noise, pos) {
+                    let cave_noise = cave_noise.get_noise3d(pos.x, pos.y, pos.z);
+                    if cave_noise < 0.4 {
+                        if solid_above {
+                            blocks[index] = BLOCK_DIRT;
+                        } else {
+                            blocks[index] = BLOCK_GRASS;
+                        }
+                    }
+                    solid_above = true;
+                }
+            }
+        }
+    }
+
+    scatter_trees(pos, &mut blocks);
+    apply_world_bounds(pos, &mut blocks, world_bounds);
+
+    blocks
+}
+
+/// Clear the layers of the chunk outside the world bounds, and fill the bottom layer of the world
+/// with bedrock
+fn apply_world_bounds(
+    chunk_pos: ChunkPosition,
+    blocks: &mut [BlockId],
+    world_bounds: &WorldBounds,
+) {
+    let chunk_min_y = chunk_pos.as_ivec3().y * CHUNK_SIZE_I32;
+
+    for (y, layer) in blocks
+        .chunks_exact_mut(CHUNK_SIZE_SQUARED)
+        .enumerate()
+    {
+        let global_y = chunk_min_y + y as i32;
+
+        if global_y == world_bounds.min_y {
+            layer.fill(BLOCK_BEDROCK);
+        } else if !world_bounds.contains_y(global_y) {
+            layer.fill(BLOCK_AIR);
+        }
+    }
+}
+
+/// Grow trees on a pseudo-random selection of the grass blocks in the chunk. Trees are only grown
+/// where they fit entirely inside the chunk, as neighbouring chunks may be generated on other
+/// threads
+fn scatter_trees(chunk_pos: ChunkPosition, blocks: &mut [BlockId]) {
+    let tree = Structure::tree();
+    let chunk_origin = chunk_pos.as_ivec3() * CHUNK_SIZE_I32;
+    let tree_size = tree.size().as_ivec3();
+
+    for z in 1..CHUNK_SIZE_U32 - 1 {
+        for x in 1..CHUNK_SIZE_U32 - 1 {
+            let column = chunk_origin + UVec3::new(x, 0, z).as_ivec3();
+            let hash = (column.x.wrapping_mul(73856093) ^ column.z.wrapping_mul(83492791)) as u32;
+            if !hash.is_multiple_of(TREE_RARITY) {
+                continue;
+            }
+
+            // find the top grass block in the column, leaving room for the tree above it
+            let surface_y = (0..CHUNK_SIZE_U32 - tree_size.y as u32).rev().find(|&y| {
+                let block_above = blocks[LocalBlockPosition::new(x, y + 1, z).get_array_index()];
+
+                blocks[LocalBlockPosition::new(x, y, z).get_array_index()] == BLOCK_GRASS
+                    && !BLOCKS[block_above.0 as usize].is_solid()
+            });
+            let Some(surface_y) = surface_y else {
+                continue;
+            };
+
+            let anchor_pos =
+                GlobalBlockPosition::from(column + IVec3::new(0, surface_y as i32 + 1, 0));
+
+            for (global_block_pos, block_id) in tree.blocks_at(anchor_pos) {
+                let local_block_pos = LocalBlockPosition::from_global_pos(global_block_pos);
+                let block = &mut blocks[local_block_pos.get_array_index()];
+
+                if PlacementMode::KeepExisting.should_replace(*block) {
+                    *block = block_id;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generator whose surface passes through the chunks at y = 0
+    fn generator_with_seed(seed: u64) -> NoiseGenerator {
+        NoiseGenerator::new(
+            GenerationParams {
+                seed,
+                amplitude: 8.0,
+                sea_level: 16.0,
+                ..Default::default()
+            },
+            WorldBounds::default(),
+        )
+    }
+
+    #[test]
+    fn generation_is_deterministic_for_a_seed() {
+        let chunk_pos = ChunkPosition::new(3, 0, -5);
+        let blocks = generator_with_seed(42).generate_chunk(chunk_pos);
+
+        assert_eq!(blocks.len(), CHUNK_SIZE_CUBED);
+        assert_eq!(generator_with_seed(42).generate_chunk(chunk_pos), blocks);
+        assert_ne!(generator_with_seed(43).generate_chunk(chunk_pos), blocks);
+
+        // the surface crosses the chunk, so it has grass on top of dirt with air above
+        for block_id in [BLOCK_AIR, BLOCK_DIRT, BLOCK_GRASS] {
+            assert!(blocks.contains(&block_id));
+        }
+    }
+}
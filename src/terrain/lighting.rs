@@ -1,9 +1,29 @@
+use std::collections::VecDeque;
+
+use generational_arena::Index;
+use glam::IVec3;
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+
+use super::{
+    chunk::{storage::ChunkBlockStorage, Chunk, CHUNK_SIZE, CHUNK_SIZE_CUBED, CHUNK_SIZE_I32},
+    position_types::{ChunkPosition, GlobalBlockPosition, LocalBlockPosition},
+    Terrain,
+};
+use crate::{
+    block::{BlockId, BLOCKS},
+    util::size::{AsSize3, Size3},
+};
+
+/// Furthest that light spreads from the block emitting it, as its level drops by one per block
+pub const MAX_LIGHT_DISTANCE: i32 = 15;
+
 /// Emitted light values for one block packed in 16 bits
 /// Bits 0 to 4   | Red component
 /// Bits 4 to 8   | Green component
 /// Bits 8 to 12  | Blue component
 /// Bits 12 to 16 | Unused (can store skylight!)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct EmittedLight(u16);
 
 impl EmittedLight {
@@ -11,10 +31,8 @@ impl EmittedLight {
     const BORROW_GUARD: u16 = 0x2020;
     const CARRY_MASK: u16 = 0x1010;
 
-    /// Wrap a u16 storing the 3 light values in an `EmittedLight`
-    pub fn from_u16(value: u16) -> Self {
-        Self(value)
-    }
+    /// No light in any component
+    pub const DARK: Self = Self(0);
 
     /// Created a packed `EmittedLight` value from the 3 light values
     /// Values must be in 0..16
@@ -31,8 +49,14 @@ impl EmittedLight {
         self.0
     }
 
+    /// True if every component is zero
+    pub fn is_dark(&self) -> bool {
+        self.0 == 0
+    }
+
     /// Returns the individual RGB light values represented by this packed `EmittedLight` value
     /// Values are in 0..16
+    #[cfg(test)]
     pub fn as_rgb(&self) -> (u16, u16, u16) {
         ((self.0 >> 0) & 15, (self.0 >> 4) & 15, (self.0 >> 8) & 15)
     }
@@ -79,13 +103,447 @@ impl EmittedLight {
     }
 }
 
-/// Skylight value for one block
-#[derive(Clone, Copy, Debug)]
+/// Skylight value for one block, from 0 (no light from the sky) to 15 (open to the sky)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Skylight(u8);
 
+impl Skylight {
+    /// No light from the sky
+    pub const DARK: Self = Self(0);
+    /// Light of a block open to the sky
+    pub const FULL: Self = Self(15);
+
+    /// Level of the skylight, in 0..16
+    pub fn level(&self) -> u8 {
+        self.0
+    }
+
+    /// Subtract one from the level, saturating on underflow
+    pub fn decrement_and_saturate(&self) -> Self {
+        Self(self.0.saturating_sub(1))
+    }
+}
+
+/// Light of each block in a chunk from the blocks emitting light around it and from the sky.
+/// The arrays are only allocated while they vary across the chunk, as most chunks are far from
+/// any emitting blocks and are either open to the sky or buried
+#[derive(Clone, Debug, Default)]
+pub struct ChunkLight {
+    /// Light of each block, ordered like the chunk's blocks, or None if every block is dark
+    levels: Option<Box<[EmittedLight]>>,
+    /// Skylight of each block
+    skylight: SkylightLevels,
+}
+
+#[derive(Clone, Debug)]
+enum SkylightLevels {
+    /// Every block has the same skylight
+    Uniform(Skylight),
+    /// Skylight of each block, ordered like the chunk's blocks
+    Varying(Box<[Skylight]>),
+}
+
+impl Default for SkylightLevels {
+    fn default() -> Self {
+        Self::Uniform(Skylight::DARK)
+    }
+}
+
+impl ChunkLight {
+    /// Light of the block at the given position in the chunk
+    pub fn get(&self, pos: LocalBlockPosition) -> EmittedLight {
+        self.levels
+            .as_ref()
+            .map_or(EmittedLight::DARK, |levels| levels[pos.get_array_index()])
+    }
+
+    /// Set the light of the block at the given position in the chunk.
+    /// Returns true if the light changed
+    pub fn set(&mut self, pos: LocalBlockPosition, light: EmittedLight) -> bool {
+        if self.get(pos) == light {
+            return false;
+        }
+
+        let levels = self
+            .levels
+            .get_or_insert_with(|| vec![EmittedLight::DARK; CHUNK_SIZE_CUBED].into_boxed_slice());
+        levels[pos.get_array_index()] = light;
+
+        true
+    }
+
+    /// True if no block in the chunk is lit by emitting blocks
+    pub fn is_dark(&self) -> bool {
+        self.levels
+            .as_ref()
+            .is_none_or(|levels| levels.iter().all(EmittedLight::is_dark))
+    }
+
+    /// Skylight of the block at the given position in the chunk
+    pub fn skylight(&self, pos: LocalBlockPosition) -> Skylight {
+        match &self.skylight {
+            SkylightLevels::Uniform(skylight) => *skylight,
+            SkylightLevels::Varying(levels) => levels[pos.get_array_index()],
+        }
+    }
+
+    /// Set the skylight of the block at the given position in the chunk.
+    /// Returns true if the skylight changed
+    pub fn set_skylight(&mut self, pos: LocalBlockPosition, skylight: Skylight) -> bool {
+        if self.skylight(pos) == skylight {
+            return false;
+        }
+
+        if let SkylightLevels::Uniform(uniform) = self.skylight {
+            self.skylight =
+                SkylightLevels::Varying(vec![uniform; CHUNK_SIZE_CUBED].into_boxed_slice());
+        }
+        if let SkylightLevels::Varying(levels) = &mut self.skylight {
+            levels[pos.get_array_index()] = skylight;
+        }
+
+        true
+    }
+
+    /// Returns the skylight of every block in the chunk if they all have the same skylight
+    pub fn uniform_skylight(&self) -> Option<Skylight> {
+        match &self.skylight {
+            SkylightLevels::Uniform(skylight) => Some(*skylight),
+            SkylightLevels::Varying(_) => None,
+        }
+    }
+
+    /// Free the arrays that no longer vary across the chunk
+    pub fn compact(&mut self) {
+        if self.is_dark() {
+            self.levels = None;
+        }
+
+        if let SkylightLevels::Varying(levels) = &self.skylight {
+            if levels.iter().all(|&skylight| skylight == levels[0]) {
+                self.skylight = SkylightLevels::Uniform(levels[0]);
+            }
+        }
+    }
+}
+
+/// Compute the light of the blocks in the box from `min` (inclusive) to `max` (exclusive) in
+/// global block coordinates, by spreading the light of every emitting block within
+/// `MAX_LIGHT_DISTANCE` of the box outwards one block at a time through blocks that aren't
+/// opaque. `get_block` returns the block at a global position, or None if it isn't loaded, in
+/// which case it neither emits nor passes light.
+/// Returns the light of each block in the box, ordered by z, then y, then x
+pub fn propagate_light(
+    min: IVec3,
+    max: IVec3,
+    mut get_block: impl FnMut(IVec3) -> Option<BlockId>,
+) -> Vec<EmittedLight> {
+    // light reaching the box can only come from, and travel through, blocks this close to it
+    let volume_min = min - MAX_LIGHT_DISTANCE;
+    let volume_size = (max - min + 2 * MAX_LIGHT_DISTANCE).as_size3();
+
+    let mut light = vec![EmittedLight::DARK; volume_size.product()];
+    let mut passes_light = vec![false; volume_size.product()];
+    let mut queue = VecDeque::new();
+
+    for (index, pos) in box_positions(volume_size).enumerate() {
+        let Some(block_id) = get_block(volume_min + pos) else {
+            continue;
+        };
+        let block = &BLOCKS[block_id.0 as usize];
+
+        passes_light[index] = !block.is_opaque();
+        if block.emits_light() {
+            light[index] = block.emitted_light();
+            queue.push_back(pos);
+        }
+    }
+
+    while let Some(pos) = queue.pop_front() {
+        let spread_light = light[volume_size.flatten(pos.as_uvec3())].decrement_and_saturate();
+        if spread_light.is_dark() {
+            continue;
+        }
+
+        for offset in [IVec3::X, IVec3::Y, IVec3::Z, IVec3::NEG_X, IVec3::NEG_Y, IVec3::NEG_Z] {
+            let neighbour_pos = pos + offset;
+            if !volume_size.contains_ivec3(neighbour_pos) {
+                continue;
+            }
+
+            let neighbour_index = volume_size.flatten(neighbour_pos.as_uvec3());
+            let neighbour_light = light[neighbour_index];
+            let new_light = EmittedLight::max(neighbour_light, spread_light);
+            if passes_light[neighbour_index] && new_light != neighbour_light {
+                light[neighbour_index] = new_light;
+                queue.push_back(neighbour_pos);
+            }
+        }
+    }
+
+    box_positions((max - min).as_size3())
+        .map(|pos| light[volume_size.flatten((pos + MAX_LIGHT_DISTANCE).as_uvec3())])
+        .collect()
+}
+
+/// Compute the skylight of the blocks in the box from `min` (inclusive) to `max` (exclusive) in
+/// global block coordinates. Blocks above the highest solid block of their column, as returned
+/// by `sky_height` for each x and z, are open to the sky and get full skylight. From there it
+/// spreads sideways and downwards one block at a time through blocks that aren't opaque, losing a
+/// level per block, so that the space under overhangs is dimmer than the open sky.
+/// `get_block` is as for `propagate_light`.
+/// Returns the skylight of each block in the box, ordered by z, then y, then x
+pub fn propagate_skylight(
+    min: IVec3,
+    max: IVec3,
+    mut get_block: impl FnMut(IVec3) -> Option<BlockId>,
+    mut sky_height: impl FnMut(i32, i32) -> i32,
+) -> Vec<Skylight> {
+    let volume_min = min - MAX_LIGHT_DISTANCE;
+    let volume_size = (max - min + 2 * MAX_LIGHT_DISTANCE).as_size3();
+    let box_size = (max - min).as_size3();
+
+    // highest solid block of each column of the volume, ordered by z, then x
+    let heights = itertools::iproduct!(0..volume_size.z as i32, 0..volume_size.x as i32)
+        .map(|(z, x)| sky_height(volume_min.x + x, volume_min.z + z))
+        .collect_vec();
+    let height_at = |pos: IVec3| heights[pos.z as usize * volume_size.x + pos.x as usize];
+
+    // most boxes are either entirely open to the sky or too far below it for any skylight to
+    // reach them, and need no spreading
+    let box_columns = itertools::iproduct!(0..box_size.z as i32, 0..box_size.x as i32)
+        .map(|(z, x)| IVec3::new(x, 0, z) + MAX_LIGHT_DISTANCE);
+    if box_columns
+        .clone()
+        .all(|pos| height_at(pos) < min.y)
+    {
+        return vec![Skylight::FULL; box_size.product()];
+    }
+    if heights
+        .iter()
+        .all(|&height| height >= volume_min.y + volume_size.y as i32 - 1)
+    {
+        return vec![Skylight::DARK; box_size.product()];
+    }
+
+    let mut light = vec![Skylight::DARK; volume_size.product()];
+    let mut passes_light = vec![false; volume_size.product()];
+    let mut queue = VecDeque::new();
+
+    for (index, pos) in box_positions(volume_size).enumerate() {
+        let Some(block_id) = get_block(volume_min + pos) else {
+            continue;
+        };
+
+        passes_light[index] = !BLOCKS[block_id.0 as usize].is_opaque();
+        if volume_min.y + pos.y > height_at(pos) {
+            light[index] = Skylight::FULL;
+
+            // blocks open to the sky only need to spread their light if they are next to a block
+            // that isn't, i.e. they are at the bottom of their column or beside a higher one
+            let is_edge = volume_min.y + pos.y == height_at(pos) + 1
+                || [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z]
+                    .into_iter()
+                    .map(|offset| pos + offset)
+                    .filter(|&neighbour_pos| volume_size.contains_ivec3(neighbour_pos))
+                    .any(|neighbour_pos| volume_min.y + pos.y <= height_at(neighbour_pos));
+            if is_edge {
+                queue.push_back(pos);
+            }
+        }
+    }
+
+    while let Some(pos) = queue.pop_front() {
+        let spread_light = light[volume_size.flatten(pos.as_uvec3())].decrement_and_saturate();
+        if spread_light == Skylight::DARK {
+            continue;
+        }
+
+        for offset in [IVec3::X, IVec3::Y, IVec3::Z, IVec3::NEG_X, IVec3::NEG_Y, IVec3::NEG_Z] {
+            let neighbour_pos = pos + offset;
+            if !volume_size.contains_ivec3(neighbour_pos) {
+                continue;
+            }
+
+            let neighbour_index = volume_size.flatten(neighbour_pos.as_uvec3());
+            let neighbour_light = &mut light[neighbour_index];
+            if passes_light[neighbour_index] && neighbour_light.level() < spread_light.level() {
+                *neighbour_light = spread_light;
+                queue.push_back(neighbour_pos);
+            }
+        }
+    }
+
+    box_positions(box_size)
+        .map(|pos| light[volume_size.flatten((pos + MAX_LIGHT_DISTANCE).as_uvec3())])
+        .collect()
+}
+
+/// Iterator over the positions in a box of the given size, ordered by z, then y, then x
+fn box_positions(size: Size3) -> impl Iterator<Item = IVec3> {
+    let size = size.as_ivec3();
+
+    itertools::iproduct!(0..size.z, 0..size.y, 0..size.x).map(|(z, y, x)| IVec3::new(x, y, z))
+}
+
+/// Box of blocks in global block coordinates, from its minimum (inclusive) to its maximum
+/// (exclusive) corner
+pub type BlockBox = (IVec3, IVec3);
+
+/// Copy of the blocks around the boxes whose light is out of date, so that their light can be
+/// propagated on another thread
+#[derive(Debug)]
+pub struct RelightJob {
+    /// Boxes whose light from emitting blocks is recomputed
+    pub light_boxes: Vec<BlockBox>,
+    /// Boxes whose skylight is recomputed
+    pub skylight_boxes: Vec<BlockBox>,
+    /// Blocks of the loaded chunks within `MAX_LIGHT_DISTANCE` of the boxes
+    pub chunks: FxHashMap<ChunkPosition, ChunkBlockStorage>,
+    /// Highest solid block of each column within `MAX_LIGHT_DISTANCE` of the skylight boxes, for
+    /// each chunk column given by its x and z, ordered by z, then x
+    pub sky_heights: FxHashMap<(i32, i32), Vec<i32>>,
+}
+
+impl RelightJob {
+    /// Propagate the light and skylight of every box
+    pub fn run(self) -> RelitBoxes {
+        let light = self
+            .light_boxes
+            .iter()
+            .map(|&(min, max)| ((min, max), propagate_light(min, max, self.block_lookup())))
+            .collect();
+
+        let skylight = self
+            .skylight_boxes
+            .iter()
+            .map(|&(min, max)| {
+                let skylight = propagate_skylight(min, max, self.block_lookup(), |x, z| {
+                    let heights = &self.sky_heights
+                        [&(x.div_euclid(CHUNK_SIZE_I32), z.div_euclid(CHUNK_SIZE_I32))];
+                    heights[CHUNK_SIZE * z.rem_euclid(CHUNK_SIZE_I32) as usize
+                        + x.rem_euclid(CHUNK_SIZE_I32) as usize]
+                });
+                ((min, max), skylight)
+            })
+            .collect();
+
+        RelitBoxes { light, skylight }
+    }
+
+    /// Returns a function looking up the block at a global position in the copied chunks, or None
+    /// if it wasn't loaded, for the light to spread through
+    fn block_lookup(&self) -> impl FnMut(IVec3) -> Option<BlockId> + '_ {
+        // consecutive blocks are almost always in the same chunk, so remember the last one
+        let mut last_chunk: Option<(ChunkPosition, Option<&ChunkBlockStorage>)> = None;
+
+        move |pos| {
+            let (local_block_pos, chunk_pos) =
+                GlobalBlockPosition::from(pos).get_local_and_chunk_pos();
+
+            let blocks = match last_chunk {
+                Some((last_chunk_pos, blocks)) if last_chunk_pos == chunk_pos => blocks,
+                _ => {
+                    let blocks = self.chunks.get(&chunk_pos);
+                    last_chunk = Some((chunk_pos, blocks));
+                    blocks
+                }
+            };
+
+            blocks.map(|blocks| blocks.get_block(local_block_pos))
+        }
+    }
+}
+
+/// Light computed by a `RelightJob`, for each box ordered by z, then y, then x
+#[derive(Debug)]
+pub struct RelitBoxes {
+    pub light: Vec<(BlockBox, Vec<EmittedLight>)>,
+    pub skylight: Vec<(BlockBox, Vec<Skylight>)>,
+}
+
+/// Snapshot of the light of a chunk and the blocks just outside it, so that the chunk can be
+/// meshed on another thread with the light in front of each face
+#[derive(Clone, Debug)]
+pub struct ChunkLightSnapshot {
+    /// Light and skylight of the chunk and a one block border around it, ordered by z, then y,
+    /// then x
+    levels: Box<[(EmittedLight, Skylight)]>,
+}
+
+impl ChunkLightSnapshot {
+    /// Size of the chunk together with its border, on each axis
+    const PADDED_SIZE: Size3 = Size3::splat(CHUNK_SIZE + 2);
+
+    /// Light of blocks when there is no snapshot: no light from emitting blocks, and open to the
+    /// sky. Blocks in chunks that aren't loaded are lit the same way, so that faces at the edge
+    /// of the loaded terrain aren't drawn black
+    pub const UNLIT: (EmittedLight, Skylight) = (EmittedLight::DARK, Skylight::FULL);
+
+    /// Take a snapshot of the light around the chunk at `center_pos`, or None if the chunk and
+    /// its neighbours are all lit like `UNLIT`
+    pub fn take(
+        center_pos: ChunkPosition,
+        terrain: &Terrain,
+        load_area_index: Index,
+    ) -> Option<Self> {
+        let neighbours: Vec<Option<&Chunk>> = itertools::iproduct!(-1..=1, -1..=1, -1..=1)
+            .map(|(z, y, x)| {
+                terrain.get_chunk(load_area_index, &(center_pos + ChunkPosition::new(x, y, z)))
+            })
+            .collect();
+
+        if neighbours.iter().flatten().all(|chunk| {
+            chunk.light().is_dark() && chunk.light().uniform_skylight() == Some(Skylight::FULL)
+        }) {
+            return None;
+        }
+
+        let levels = box_positions(Self::PADDED_SIZE)
+            .map(|padded_pos| {
+                let pos = padded_pos - 1;
+                let offset = pos.div_euclid(IVec3::splat(CHUNK_SIZE_I32)) + 1;
+                let neighbour_index = (9 * offset.z + 3 * offset.y + offset.x) as usize;
+
+                neighbours[neighbour_index].map_or(Self::UNLIT, |chunk| {
+                    let local_pos = LocalBlockPosition::from(
+                        pos.rem_euclid(IVec3::splat(CHUNK_SIZE_I32)).as_uvec3(),
+                    );
+                    (chunk.light().get(local_pos), chunk.light().skylight(local_pos))
+                })
+            })
+            .collect();
+
+        Some(Self { levels })
+    }
+
+    /// Light and skylight of the block at the given position relative to the origin of the
+    /// chunk, which must be in the chunk or its border
+    pub fn get(&self, pos: IVec3) -> (EmittedLight, Skylight) {
+        self.levels[Self::PADDED_SIZE.flatten((pos + 1).as_uvec3())]
+    }
+
+    /// Snapshot in which every block has the same light and skylight
+    #[cfg(test)]
+    pub fn uniform(level: (EmittedLight, Skylight)) -> Self {
+        Self {
+            levels: vec![level; Self::PADDED_SIZE.product()].into(),
+        }
+    }
+
+    /// Light and skylight of the chunk and a one block border around it, ordered by z, then y,
+    /// then x
+    pub fn levels(&self) -> &[(EmittedLight, Skylight)] {
+        &self.levels
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::EmittedLight;
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::block::{BLOCK_AIR, BLOCK_DIRT, BLOCK_LAMP_ORANGE};
 
     #[test]
     fn emitted_light_from_and_as_rgb() {
@@ -129,4 +587,27 @@ mod tests {
         let x = EmittedLight::from_rgb(0, 1, 2);
         assert_eq!((0, 0, 1), x.decrement_and_saturate().as_rgb())
     }
+
+    #[test]
+    fn light_spreads_around_opaque_blocks_but_not_into_unloaded_ones() {
+        // a lamp at the origin, a wall at x = 3 that light has to go around, and nothing loaded
+        // from x = 6
+        let light = propagate_light(IVec3::new(-1, 0, 0), IVec3::new(8, 1, 1), |pos| {
+            if pos.x >= 6 {
+                None
+            } else if pos == IVec3::ZERO {
+                Some(BLOCK_LAMP_ORANGE)
+            } else if pos.x == 3 && pos.y.abs() <= 2 {
+                Some(BLOCK_DIRT)
+            } else {
+                Some(BLOCK_AIR)
+            }
+        });
+
+        let red = light
+            .iter()
+            .map(|light| light.as_rgb().0)
+            .collect_vec();
+        assert_eq!(red, [14, 15, 14, 13, 0, 5, 4, 0, 0]);
+    }
 }
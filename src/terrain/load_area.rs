@@ -38,6 +38,24 @@ impl LoadArea {
         }
     }
 
+    /// Create an area from its horizontal radius and vertical range in chunks, which can be chosen
+    /// independently, e.g. a wide but short area for flat worlds.
+    /// The area is `2 * horizontal_radius` chunks across in x and z and `vertical_range` chunks
+    /// tall. With `AreaShape::Cylindrical`, the radius applies in x and z only, while the vertical
+    /// range is a hard limit
+    pub fn with_radius_and_height(
+        pos: ChunkPosition,
+        horizontal_radius: usize,
+        vertical_range: usize,
+        shape: AreaShape,
+    ) -> Self {
+        Self::new(
+            pos,
+            Size3::new(2 * horizontal_radius, vertical_range, 2 * horizontal_radius),
+            shape,
+        )
+    }
+
     /// If the given chunk position is within the bounds of this area and the chunk is loaded,
     /// returns index of the given chunk in the chunk arena.
     /// Otherwise returns None
@@ -87,15 +105,27 @@ impl LoadArea {
             .unwrap_or(true)
     }
 
-    /// True if the chunk position is contained by the area
-    pub fn is_within_area(&self, chunk_pos: &ChunkPosition) -> bool {
+    /// Iterator over the positions of the chunks in the area that are currently loading
+    pub fn loading_positions(&self) -> impl Iterator<Item = ChunkPosition> + '_ {
+        self.chunk_states
+            .iter()
+            .filter_map(|state| match state {
+                ChunkState::Loading(pos) => Some(*pos),
+                _ => None,
+            })
+    }
+
+    /// True if the chunk position is contained by the area, according to its shape. Distances
+    /// from the center are scaled by the size of the area, so a spherical area with a different
+    /// height than width is a spheroid
+    pub fn contains_chunk(&self, chunk_pos: &ChunkPosition) -> bool {
         if !self.is_within_bounds(chunk_pos) {
             return false;
         }
 
         let v = (chunk_pos.as_vec3() - self.center_pos) * self.size_recip;
         match self.shape {
-            AreaShape::Cubic => v.abs().max_element() <= 0.5,
+            AreaShape::Cuboid => v.abs().max_element() <= 0.5,
             AreaShape::Spherical => v.length_squared() <= 0.25,
             AreaShape::Cylindrical => {
                 let len_sq_xz = v.xz().length_squared();
@@ -118,7 +148,7 @@ impl LoadArea {
 
         itertools::iproduct!(lower.x..upper.x, lower.y..upper.y, lower.z..upper.z)
             .map(|(x, y, z)| ChunkPosition::new(x, y, z))
-            .filter(|chunk_pos| self.is_within_area(&chunk_pos))
+            .filter(|chunk_pos| self.contains_chunk(&chunk_pos))
     }
 
     /// State of the area with regards to chunk loading
@@ -136,6 +166,16 @@ impl LoadArea {
         self.size
     }
 
+    /// Horizontal radius of this area in chunks
+    pub fn horizontal_radius(&self) -> usize {
+        self.size.x.min(self.size.z) / 2
+    }
+
+    /// Height of this area in chunks
+    pub fn vertical_range(&self) -> usize {
+        self.size.y
+    }
+
     /// Position of the center of this area in chunks
     pub fn center(&self) -> Vec3 {
         self.center_pos
@@ -199,6 +239,11 @@ impl LoadArea {
         self.chunk_states[array_index] = ChunkState::Unloaded;
     }
 
+    /// Called to forget the state of every chunk in the area
+    pub(super) fn mark_all_unloaded(&mut self) {
+        self.chunk_states.fill(ChunkState::Unloaded);
+    }
+
     /// If the chunk position is within the area's bounds, returns the index in `self.chunks` for
     /// that chunk
     fn get_array_index(&self, chunk_pos: &ChunkPosition) -> Option<usize> {
@@ -233,10 +278,98 @@ pub enum ChunkState {
 
 #[derive(Clone, Copy, Debug)]
 pub enum AreaShape {
-    /// Chunks are loaded in a cuboid
-    Cubic,
-    /// Chunks are loaded in a spheriod
+    /// Chunks are loaded in a box filling the area's bounds
+    Cuboid,
+    /// Chunks are loaded in a spheroid
     Spherical,
     /// Chunks are loaded in a cylinder around the y axis
     Cylindrical,
 }
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use super::*;
+
+    /// Returns the number of chunks in each (x, z) column of the area
+    fn column_heights(area: &LoadArea) -> FxHashMap<(i32, i32), usize> {
+        let mut columns = FxHashMap::default();
+        for chunk_pos in area.iter_positions() {
+            let chunk_pos = chunk_pos.as_ivec3();
+            *columns
+                .entry((chunk_pos.x, chunk_pos.z))
+                .or_default() += 1;
+        }
+        columns
+    }
+
+    #[test]
+    fn tall_narrow_and_short_wide_areas_load_different_columns() {
+        let tall_narrow = LoadArea::with_radius_and_height(
+            ChunkPosition::ZERO,
+            2,
+            16,
+            AreaShape::Cylindrical,
+        );
+        let short_wide = LoadArea::with_radius_and_height(
+            ChunkPosition::ZERO,
+            8,
+            2,
+            AreaShape::Cylindrical,
+        );
+
+        assert_eq!(tall_narrow.horizontal_radius(), 2);
+        assert_eq!(tall_narrow.vertical_range(), 16);
+        assert_eq!(short_wide.horizontal_radius(), 8);
+        assert_eq!(short_wide.vertical_range(), 2);
+
+        let tall_narrow_columns = column_heights(&tall_narrow);
+        let short_wide_columns = column_heights(&short_wide);
+
+        // every column is loaded over the full vertical range
+        assert!(tall_narrow_columns
+            .values()
+            .all(|&height| height == 16));
+        assert!(short_wide_columns
+            .values()
+            .all(|&height| height == 2));
+
+        // the cylinder only restricts the area horizontally, so the corner columns are excluded
+        assert!(!tall_narrow_columns.contains_key(&(0, 0)));
+        assert!(tall_narrow_columns.contains_key(&(2, 2)));
+        assert!(!short_wide_columns.contains_key(&(0, 0)));
+        assert!(short_wide_columns.contains_key(&(8, 8)));
+
+        // the columns of the narrow area all fall within the wide area, but not vice versa
+        assert!(tall_narrow_columns.len() < short_wide_columns.len());
+        assert!(tall_narrow_columns
+            .keys()
+            .all(|&(x, z)| short_wide_columns.contains_key(&(x + 6, z + 6))));
+    }
+
+    #[test]
+    fn chunks_on_either_side_of_the_boundary_of_each_shape() {
+        let area = |shape| LoadArea::new(ChunkPosition::ZERO, Size3::splat(8), shape);
+        let contains = |shape, x, y, z| area(shape).contains_chunk(&ChunkPosition::new(x, y, z));
+
+        // a box is bounded on each axis only
+        assert!(contains(AreaShape::Cuboid, 0, 0, 0));
+        assert!(contains(AreaShape::Cuboid, 7, 7, 7));
+        assert!(!contains(AreaShape::Cuboid, 8, 7, 7));
+        assert!(!contains(AreaShape::Cuboid, -1, 0, 0));
+
+        // a sphere is bounded by the distance on all three axes
+        assert!(contains(AreaShape::Spherical, 4, 4, 0));
+        assert!(contains(AreaShape::Spherical, 1, 4, 2));
+        assert!(!contains(AreaShape::Spherical, 1, 4, 1));
+        assert!(!contains(AreaShape::Spherical, 1, 0, 2));
+        assert!(!contains(AreaShape::Spherical, 0, 0, 0));
+
+        // a cylinder is bounded by the distance in x and z, and on the y axis
+        assert!(contains(AreaShape::Cylindrical, 1, 0, 2));
+        assert!(contains(AreaShape::Cylindrical, 1, 7, 2));
+        assert!(!contains(AreaShape::Cylindrical, 1, 7, 1));
+        assert!(!contains(AreaShape::Cylindrical, 4, 8, 4));
+    }
+}
@@ -0,0 +1,544 @@
+//! Saving chunks to disk and loading them again, so that edits survive restarts
+//!
+//! Chunks are grouped into region files of `REGION_SIZE`³ chunks, named after the position of the
+//! region, e.g. `r.0.-1.2.bin`. The layout of a region file is, with all integers little-endian:
+//! - magic bytes `REGION_MAGIC`
+//! - region format version (`u8`)
+//! - for each of the `REGION_CHUNK_COUNT` chunk slots, ordered by y, then z, then x, the offset
+//!   (`u32`) of the chunk's data from the start of the file and its length (`u32`). A length of
+//!   zero means that the chunk has not been saved
+//! - the data of each saved chunk, written by `save_chunk`. This has its own version, see
+//!   `chunk::serialization`
+
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::IVec3;
+use itertools::Itertools;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{
+    chunk::{
+        serialization::{deserialize_blocks, serialize_blocks, ChunkDeserializeError},
+        Chunk,
+    },
+    position_types::ChunkPosition,
+};
+use crate::{
+    block::{BLOCKS, BLOCK_AIR},
+    tasks::{ResultHandle, TaskPriority, TaskResult, TaskStage, Tasks},
+    CHUNK_SAVING_PRIORITY,
+};
+
+/// Number of chunks along each edge of a region
+pub const REGION_SIZE: i32 = 16;
+
+/// Number of chunks in a region
+pub const REGION_CHUNK_COUNT: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// Magic bytes at the start of every region file
+pub const REGION_MAGIC: [u8; 4] = *b"VXRG";
+
+/// Version written by `Region::to_bytes`. Increase this when the layout of region files changes,
+/// and keep a way to read older versions in `Region::from_bytes`
+pub const REGION_FORMAT_VERSION: u8 = 1;
+
+/// Size of the header of a region file, before the chunk data
+const REGION_HEADER_SIZE: usize = REGION_MAGIC.len() + 1 + REGION_CHUNK_COUNT * 8;
+
+/// Serialize the blocks of a chunk, referring to blocks by name
+pub fn save_chunk(chunk: &Chunk) -> Vec<u8> {
+    serialize_blocks(
+        &chunk.get_block_storage().as_block_array(),
+        &block_names(),
+    )
+}
+
+/// Deserialize a chunk saved with `save_chunk`. Blocks that are no longer registered are replaced
+/// with air
+pub fn load_chunk(chunk_pos: ChunkPosition, bytes: &[u8]) -> Result<Chunk, ChunkDeserializeError> {
+    let blocks = deserialize_blocks(bytes, &block_names(), BLOCK_AIR)?;
+    Ok(Chunk::new(chunk_pos, blocks))
+}
+
+/// Name of each registered block, indexed by `BlockId`
+fn block_names() -> Vec<&'static str> {
+    BLOCKS
+        .iter()
+        .map(|block| block.name)
+        .collect_vec()
+}
+
+/// Region files in a save directory. Regions are read from disk the first time one of their
+/// chunks is requested and kept in memory until `evict_regions` finds none of their chunks
+/// loaded. Chunks passed to `write_chunk` are only stored on disk by `flush` or `flush_now`
+#[derive(Debug)]
+pub struct ChunkStore {
+    directory: PathBuf,
+    /// Regions read so far, or None for region files that could not be read. Those are never
+    /// written, so that a save from a newer version is not overwritten
+    regions: FxHashMap<RegionPosition, Option<Region>>,
+    /// Regions with chunks that have not been flushed to disk
+    dirty_regions: FxHashSet<RegionPosition>,
+    /// Tasks writing regions to disk. A region is not written again until its last write has
+    /// finished, so that an older copy can't replace a newer one
+    region_writes: FxHashMap<RegionPosition, ResultHandle<Result<(), RegionError>>>,
+}
+
+impl ChunkStore {
+    /// Store region files in the given directory, which is created when they are first flushed
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            regions: FxHashMap::default(),
+            dirty_regions: FxHashSet::default(),
+            region_writes: FxHashMap::default(),
+        }
+    }
+
+    /// Returns the data of the saved chunk at the given position, to be read with `load_chunk`,
+    /// or None if it has not been saved or its region could not be read
+    pub fn read_chunk(&mut self, chunk_pos: ChunkPosition) -> Option<Arc<[u8]>> {
+        self.region(RegionPosition::containing(&chunk_pos))?
+            .chunks
+            .get(&region_slot(&chunk_pos))
+            .cloned()
+    }
+
+    /// Save the chunk, replacing any saved chunk at the same position
+    pub fn write_chunk(&mut self, chunk: &Chunk) {
+        let region_pos = RegionPosition::containing(&chunk.position());
+
+        let Some(region) = self.region(region_pos) else {
+            log::warn!(
+                "not saving chunk {:?} since its region file could not be read",
+                chunk.position().as_ivec3()
+            );
+            return;
+        };
+
+        region
+            .chunks
+            .insert(region_slot(&chunk.position()), save_chunk(chunk).into());
+        self.dirty_regions.insert(region_pos);
+    }
+
+    /// Submit tasks writing every region with unsaved chunks to disk, except those still being
+    /// written by an earlier flush, which are written by a later one
+    pub fn flush(&mut self, tasks: &mut Tasks) {
+        self.receive_region_writes();
+
+        let region_positions = self
+            .dirty_regions
+            .iter()
+            .filter(|region_pos| !self.region_writes.contains_key(region_pos))
+            .copied()
+            .collect_vec();
+
+        for region_pos in region_positions {
+            self.dirty_regions.remove(&region_pos);
+
+            let Some(Some(region)) = self.regions.get(&region_pos) else {
+                continue;
+            };
+
+            // the chunk data is shared, so copying the region is cheap
+            let region = region.clone();
+            let directory = self.directory.clone();
+
+            let result_handle = tasks.submit_with_result(
+                TaskStage::Generation,
+                TaskPriority {
+                    class_priority: CHUNK_SAVING_PRIORITY,
+                    priority_within_class: 0,
+                    tie_breaker: region_pos.0.to_array(),
+                },
+                move || region.write(&region_pos.path(&directory)),
+            );
+            self.region_writes
+                .insert(region_pos, result_handle);
+        }
+    }
+
+    /// Write every region with unsaved chunks to disk on the calling thread, after waiting for
+    /// the writes submitted by `flush`. Used when exiting, when the tasks may not run again
+    pub fn flush_now(&mut self, tasks: &mut Tasks) -> Result<(), RegionError> {
+        for (region_pos, result_handle) in self.region_writes.drain() {
+            if tasks.cancel_if_pending(result_handle.task_id()) {
+                // write the region here instead
+                self.dirty_regions.insert(region_pos);
+            } else if let Some(Err(e)) = result_handle.recv() {
+                log::error!("failed to save chunks: {e}");
+            }
+        }
+
+        for region_pos in self.dirty_regions.drain() {
+            if let Some(Some(region)) = self.regions.get(&region_pos) {
+                region.write(&region_pos.path(&self.directory))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget the regions that contain none of the loaded chunks, unless they have unsaved
+    /// chunks or are being written. They are read from disk again if one of their chunks is
+    /// loaded later
+    pub fn evict_regions(&mut self, loaded_chunks: impl IntoIterator<Item = ChunkPosition>) {
+        self.receive_region_writes();
+
+        let loaded_regions: FxHashSet<_> = loaded_chunks
+            .into_iter()
+            .map(|chunk_pos| RegionPosition::containing(&chunk_pos))
+            .collect();
+
+        self.regions.retain(|region_pos, _| {
+            loaded_regions.contains(region_pos)
+                || self.dirty_regions.contains(region_pos)
+                || self.region_writes.contains_key(region_pos)
+        });
+    }
+
+    /// Forget the region writes that have finished, logging those that failed
+    fn receive_region_writes(&mut self) {
+        self.region_writes
+            .retain(|_, result_handle| match result_handle.try_recv() {
+                TaskResult::Pending => true,
+                TaskResult::Ready(result) => {
+                    if let Err(e) = result {
+                        log::error!("failed to save chunks: {e}");
+                    }
+                    false
+                }
+                TaskResult::Cancelled => false,
+            });
+    }
+
+    /// Returns the region at the given position, reading it from disk if necessary, or None if
+    /// its file could not be read
+    fn region(&mut self, region_pos: RegionPosition) -> Option<&mut Region> {
+        let directory = &self.directory;
+
+        self.regions
+            .entry(region_pos)
+            .or_insert_with(|| {
+                let path = region_pos.path(directory);
+
+                match fs::read(&path) {
+                    Ok(bytes) => Region::from_bytes(&bytes)
+                        .inspect_err(|e| log::error!("failed to read {}: {e}", path.display()))
+                        .ok(),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Some(Region::default()),
+                    Err(e) => {
+                        log::error!("failed to read {}: {e}", path.display());
+                        None
+                    }
+                }
+            })
+            .as_mut()
+    }
+}
+
+/// Position of a region, in units of `REGION_SIZE` chunks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct RegionPosition(IVec3);
+
+impl RegionPosition {
+    fn containing(chunk_pos: &ChunkPosition) -> Self {
+        Self(chunk_pos.as_ivec3().div_euclid(IVec3::splat(REGION_SIZE)))
+    }
+
+    /// Path of the region file within the save directory
+    fn path(&self, directory: &Path) -> PathBuf {
+        directory.join(format!("r.{}.{}.{}.bin", self.0.x, self.0.y, self.0.z))
+    }
+
+}
+
+/// Index of the chunk's slot within its region, ordered by y, then z, then x
+fn region_slot(chunk_pos: &ChunkPosition) -> usize {
+    let pos = chunk_pos.as_ivec3().rem_euclid(IVec3::splat(REGION_SIZE));
+    (pos.x + REGION_SIZE * (pos.z + REGION_SIZE * pos.y)) as usize
+}
+
+/// Saved chunks of one region, indexed by `region_slot`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Region {
+    chunks: FxHashMap<usize, Arc<[u8]>>,
+}
+
+impl Region {
+    /// Write the region to the file at the given path, creating its directory if necessary
+    fn write(&self, path: &Path) -> Result<(), RegionError> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(RegionError::IoError)?;
+        }
+
+        // write to a temporary file first so that a crash can't leave a half-written region
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, self.to_bytes()).map_err(RegionError::IoError)?;
+        fs::rename(&temporary_path, path).map_err(RegionError::IoError)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(REGION_HEADER_SIZE);
+        let mut data: Vec<u8> = Vec::new();
+
+        header.extend(REGION_MAGIC);
+        header.push(REGION_FORMAT_VERSION);
+
+        for slot in 0..REGION_CHUNK_COUNT {
+            let (offset, length) = match self.chunks.get(&slot) {
+                Some(bytes) => {
+                    let offset = REGION_HEADER_SIZE + data.len();
+                    data.extend(bytes.iter());
+                    (offset, bytes.len())
+                }
+                None => (0, 0),
+            };
+
+            header.extend((offset as u32).to_le_bytes());
+            header.extend((length as u32).to_le_bytes());
+        }
+
+        header.extend(data);
+        header
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, RegionError> {
+        if bytes.len() < REGION_MAGIC.len() + 1 {
+            return Err(RegionError::UnexpectedEnd);
+        }
+        if bytes[..REGION_MAGIC.len()] != REGION_MAGIC {
+            return Err(RegionError::InvalidMagic);
+        }
+
+        match bytes[REGION_MAGIC.len()] {
+            1 => Self::from_bytes_v1(bytes),
+            version => Err(RegionError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn from_bytes_v1(bytes: &[u8]) -> Result<Self, RegionError> {
+        let table = bytes
+            .get(REGION_MAGIC.len() + 1..REGION_HEADER_SIZE)
+            .ok_or(RegionError::UnexpectedEnd)?;
+        let mut chunks = FxHashMap::default();
+
+        for (slot, entry) in table.chunks_exact(8).enumerate() {
+            let offset = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
+            let length = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]) as usize;
+
+            if length == 0 {
+                continue;
+            }
+
+            let chunk_bytes = bytes
+                .get(offset..offset + length)
+                .ok_or(RegionError::UnexpectedEnd)?;
+            chunks.insert(slot, chunk_bytes.into());
+        }
+
+        Ok(Self { chunks })
+    }
+}
+
+/// errors returned when reading or writing region files
+#[derive(Debug, thiserror::Error)]
+pub enum RegionError {
+    #[error("not a region file")]
+    InvalidMagic,
+    #[error("unsupported region format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unexpected end of data")]
+    UnexpectedEnd,
+    #[error("IO error")]
+    IoError(io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{BLOCK_DIRT, BLOCK_GLASS},
+        terrain::{chunk::CHUNK_SIZE_CUBED, position_types::LocalBlockPosition},
+    };
+
+    /// An air chunk with one dirt block and one glass block
+    fn edited_chunk(chunk_pos: ChunkPosition) -> Chunk {
+        let mut chunk = Chunk::new(chunk_pos, vec![BLOCK_AIR; CHUNK_SIZE_CUBED]);
+        chunk.set_block(LocalBlockPosition::new(1, 2, 3), BLOCK_DIRT);
+        chunk.set_block(LocalBlockPosition::new(31, 0, 0), BLOCK_GLASS);
+        chunk
+    }
+
+    fn assert_same_blocks(a: &Chunk, b: &Chunk) {
+        assert_eq!(a.position(), b.position());
+        assert_eq!(
+            a.get_block_storage().as_block_array(),
+            b.get_block_storage().as_block_array()
+        );
+    }
+
+    /// Empty directory for a test to save into
+    fn temporary_save_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "voxels-test-{}-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn chunk_round_trip() {
+        let chunk = edited_chunk(ChunkPosition::new(3, -2, 7));
+        let loaded = load_chunk(chunk.position(), &save_chunk(&chunk)).unwrap();
+
+        assert_same_blocks(&chunk, &loaded);
+    }
+
+    #[test]
+    fn chunks_map_to_distinct_slots_of_their_region() {
+        let positions = [
+            ChunkPosition::new(0, 0, 0),
+            ChunkPosition::new(15, 15, 15),
+            ChunkPosition::new(-1, 0, 0),
+            ChunkPosition::new(16, -17, 5),
+        ];
+        let regions = positions.map(|pos| RegionPosition::containing(&pos).0);
+
+        assert_eq!(regions, [
+            IVec3::ZERO,
+            IVec3::ZERO,
+            IVec3::new(-1, 0, 0),
+            IVec3::new(1, -2, 0)
+        ]);
+        assert_eq!(region_slot(&positions[0]), 0);
+        assert_eq!(region_slot(&positions[1]), REGION_CHUNK_COUNT - 1);
+        assert_eq!(region_slot(&positions[2]), 15);
+    }
+
+    #[test]
+    fn region_round_trip_and_version_check() {
+        let mut region = Region::default();
+        region.chunks.insert(0, Arc::from([1, 2, 3]));
+        region.chunks.insert(REGION_CHUNK_COUNT - 1, Arc::from([4]));
+
+        let bytes = region.to_bytes();
+        assert_eq!(bytes[..4], REGION_MAGIC);
+        assert_eq!(bytes[4], REGION_FORMAT_VERSION);
+        assert_eq!(Region::from_bytes(&bytes).unwrap(), region);
+
+        let mut future_version = bytes.clone();
+        future_version[4] = REGION_FORMAT_VERSION + 1;
+        assert!(matches!(
+            Region::from_bytes(&future_version),
+            Err(RegionError::UnsupportedVersion(version)) if version == REGION_FORMAT_VERSION + 1
+        ));
+
+        assert!(matches!(
+            Region::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(RegionError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            Region::from_bytes(b"not a region"),
+            Err(RegionError::InvalidMagic)
+        ));
+    }
+
+    #[test]
+    fn store_reads_back_flushed_chunks() {
+        let directory = temporary_save_directory("store");
+        let chunks =
+            [ChunkPosition::new(0, 0, 0), ChunkPosition::new(-20, 1, 40)].map(edited_chunk);
+
+        let mut store = ChunkStore::new(&directory);
+        for chunk in &chunks {
+            store.write_chunk(chunk);
+        }
+        let mut tasks = Tasks::new_deterministic();
+        store.flush(&mut tasks);
+        tasks.block_until_finished();
+
+        // a new store has to read the region files from disk
+        let mut store = ChunkStore::new(&directory);
+        for chunk in &chunks {
+            let bytes = store.read_chunk(chunk.position()).unwrap();
+            assert_same_blocks(chunk, &load_chunk(chunk.position(), &bytes).unwrap());
+        }
+        assert!(store.read_chunk(ChunkPosition::new(1, 0, 0)).is_none());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn unreadable_regions_are_not_overwritten() {
+        let directory = temporary_save_directory("unreadable");
+        let chunk = edited_chunk(ChunkPosition::ZERO);
+        let path = RegionPosition::containing(&chunk.position()).path(&directory);
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&path, b"VXRG\xff").unwrap();
+
+        let mut store = ChunkStore::new(&directory);
+        assert!(store.read_chunk(chunk.position()).is_none());
+        store.write_chunk(&chunk);
+        store
+            .flush_now(&mut Tasks::new_deterministic())
+            .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"VXRG\xff");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn regions_are_kept_until_saved_and_unused() {
+        let directory = temporary_save_directory("evict");
+        let chunk = edited_chunk(ChunkPosition::ZERO);
+        let region_pos = RegionPosition::containing(&chunk.position());
+
+        let mut store = ChunkStore::new(&directory);
+        let mut tasks = Tasks::new_deterministic();
+        store.write_chunk(&chunk);
+
+        // unsaved and still being written
+        store.evict_regions([]);
+        assert!(store.regions.contains_key(&region_pos));
+        store.flush(&mut tasks);
+        store.evict_regions([]);
+        assert!(store.regions.contains_key(&region_pos));
+
+        // saved, but one of its chunks is loaded
+        tasks.block_until_finished();
+        store.evict_regions([ChunkPosition::new(1, 2, 3)]);
+        assert!(store.regions.contains_key(&region_pos));
+
+        store.evict_regions([]);
+        assert!(store.regions.is_empty());
+        assert!(store.read_chunk(chunk.position()).is_some());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn flushing_now_writes_regions_whose_task_has_not_run() {
+        let directory = temporary_save_directory("flush-now");
+        let chunk = edited_chunk(ChunkPosition::ZERO);
+
+        let mut store = ChunkStore::new(&directory);
+        let mut tasks = Tasks::new_deterministic();
+        store.write_chunk(&chunk);
+        store.flush(&mut tasks);
+        store.flush_now(&mut tasks).unwrap();
+
+        let mut store = ChunkStore::new(&directory);
+        assert!(store.read_chunk(chunk.position()).is_some());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,10 +1,13 @@
-use derive_more::{Add, From, Sub};
+use std::ops::{Add, Sub};
+
+use derive_more::{Add, AddAssign, From, Sub, SubAssign};
 use glam::{IVec3, UVec3, Vec3};
 
-use super::chunk::{CHUNK_SIZE, CHUNK_SIZE_I32, CHUNK_SIZE_LOG2, CHUNK_SIZE_U32};
+use super::chunk::{CHUNK_SIZE, CHUNK_SIZE_I32, CHUNK_SIZE_LOG2, CHUNK_SIZE_RECIP, CHUNK_SIZE_U32};
+use crate::util::face::{FaceIndex, FACE_NORMALS};
 
 /// Position of a block in the world
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Add, From, Sub)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Add, AddAssign, From, Sub, SubAssign)]
 pub struct GlobalBlockPosition(IVec3);
 
 impl GlobalBlockPosition {
@@ -33,6 +36,15 @@ impl GlobalBlockPosition {
         (local_pos, chunk_pos)
     }
 
+    /// Returns the position of the adjacent block touching the face with the given index
+    pub fn neighbour(&self, face_index: FaceIndex) -> Self {
+        *self + FACE_NORMALS[face_index.as_usize()]
+    }
+
+    pub fn as_ivec3(&self) -> IVec3 {
+        self.0
+    }
+
     pub fn x(&self) -> i32 {
         self.0.x
     }
@@ -46,13 +58,27 @@ impl GlobalBlockPosition {
     }
 }
 
+impl Add<IVec3> for GlobalBlockPosition {
+    type Output = Self;
+
+    fn add(self, rhs: IVec3) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Sub<IVec3> for GlobalBlockPosition {
+    type Output = Self;
+
+    fn sub(self, rhs: IVec3) -> Self::Output {
+        Self(self.0 - rhs)
+    }
+}
+
 /// Position of a block in a chunk
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Add, From, Sub)]
 pub struct LocalBlockPosition(UVec3);
 
 impl LocalBlockPosition {
-    pub const ZERO: Self = Self(UVec3::ZERO);
-
     pub fn new(x: u32, y: u32, z: u32) -> Self {
         Self(UVec3::new(x, y, z))
     }
@@ -113,7 +139,7 @@ impl LocalBlockPosition {
 }
 
 /// Position of a chunk in the world
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Add, From, Sub)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Add, AddAssign, From, Sub, SubAssign)]
 pub struct ChunkPosition(IVec3);
 
 impl ChunkPosition {
@@ -123,6 +149,20 @@ impl ChunkPosition {
         Self(IVec3::new(x, y, z))
     }
 
+    /// Returns the position of the chunk containing the given point in world space
+    pub fn containing(world_pos: Vec3) -> Self {
+        Self(
+            (world_pos * CHUNK_SIZE_RECIP)
+                .floor()
+                .as_ivec3(),
+        )
+    }
+
+    /// Returns the position of the adjacent chunk touching the face with the given index
+    pub fn neighbour(&self, face_index: FaceIndex) -> Self {
+        *self + FACE_NORMALS[face_index.as_usize()]
+    }
+
     pub fn as_ivec3(&self) -> IVec3 {
         self.0
     }
@@ -143,3 +183,118 @@ impl ChunkPosition {
         self.0.z
     }
 }
+
+impl Add<IVec3> for ChunkPosition {
+    type Output = Self;
+
+    fn add(self, rhs: IVec3) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Sub<IVec3> for ChunkPosition {
+    type Output = Self;
+
+    fn sub(self, rhs: IVec3) -> Self::Output {
+        Self(self.0 - rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_and_chunk_pos_at_chunk_edges() {
+        let cases = [
+            (IVec3::new(0, 0, 0), UVec3::new(0, 0, 0), IVec3::new(0, 0, 0)),
+            (IVec3::new(31, 31, 31), UVec3::new(31, 31, 31), IVec3::new(0, 0, 0)),
+            (IVec3::new(32, 0, 64), UVec3::new(0, 0, 0), IVec3::new(1, 0, 2)),
+            (IVec3::new(-1, -1, -1), UVec3::new(31, 31, 31), IVec3::new(-1, -1, -1)),
+            (IVec3::new(-32, -32, -32), UVec3::new(0, 0, 0), IVec3::new(-1, -1, -1)),
+            (IVec3::new(-33, 5, -64), UVec3::new(31, 5, 0), IVec3::new(-2, 0, -2)),
+        ];
+
+        for (global, local, chunk) in cases {
+            let global_pos = GlobalBlockPosition::from(global);
+            let (local_pos, chunk_pos) = global_pos.get_local_and_chunk_pos();
+
+            assert_eq!(local_pos, LocalBlockPosition::from(local), "{:?}", global);
+            assert_eq!(chunk_pos, ChunkPosition::from(chunk), "{:?}", global);
+            assert_eq!(LocalBlockPosition::from_global_pos(global_pos), local_pos);
+            assert_eq!(
+                GlobalBlockPosition::from_local_and_chunk_pos(local_pos, chunk_pos),
+                global_pos
+            );
+        }
+    }
+
+    #[test]
+    fn containing_rounds_towards_negative_infinity() {
+        assert_eq!(
+            ChunkPosition::containing(Vec3::new(-0.5, 31.9, 32.0)),
+            ChunkPosition::new(-1, 0, 1)
+        );
+        assert_eq!(
+            ChunkPosition::containing(Vec3::new(-32.0, -32.5, 0.0)),
+            ChunkPosition::new(-1, -2, 0)
+        );
+    }
+
+    #[test]
+    fn neighbours_across_chunk_edges() {
+        let pos = GlobalBlockPosition::new(31, 0, -32);
+
+        let (local_pos, chunk_pos) = pos
+            .neighbour(FaceIndex::POS_X)
+            .get_local_and_chunk_pos();
+        assert_eq!(local_pos, LocalBlockPosition::new(0, 0, 0));
+        assert_eq!(chunk_pos, ChunkPosition::new(1, 0, -1));
+
+        let (local_pos, chunk_pos) = pos
+            .neighbour(FaceIndex::NEG_Y)
+            .get_local_and_chunk_pos();
+        assert_eq!(local_pos, LocalBlockPosition::new(31, 31, 0));
+        assert_eq!(chunk_pos, ChunkPosition::new(0, -1, -1));
+
+        let (local_pos, chunk_pos) = pos
+            .neighbour(FaceIndex::NEG_Z)
+            .get_local_and_chunk_pos();
+        assert_eq!(local_pos, LocalBlockPosition::new(31, 0, 31));
+        assert_eq!(chunk_pos, ChunkPosition::new(0, 0, -2));
+
+        assert_eq!(
+            ChunkPosition::new(0, 0, 0).neighbour(FaceIndex::NEG_X),
+            ChunkPosition::new(-1, 0, 0)
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut pos = GlobalBlockPosition::new(-1, 2, 3);
+        assert_eq!(pos + IVec3::new(1, -2, -3), GlobalBlockPosition::ZERO);
+        assert_eq!(pos - IVec3::new(-1, 2, 3), GlobalBlockPosition::ZERO);
+        assert_eq!(pos - pos, GlobalBlockPosition::ZERO);
+        assert_eq!(pos + GlobalBlockPosition::new(1, 1, 1), GlobalBlockPosition::new(0, 3, 4));
+
+        pos += GlobalBlockPosition::new(1, 0, 0);
+        pos -= GlobalBlockPosition::new(0, 2, 0);
+        assert_eq!(pos, GlobalBlockPosition::new(0, 0, 3));
+
+        let mut chunk_pos = ChunkPosition::new(-1, 0, 1);
+        assert_eq!(chunk_pos + IVec3::X, ChunkPosition::new(0, 0, 1));
+        assert_eq!(chunk_pos - IVec3::Z, ChunkPosition::new(-1, 0, 0));
+        chunk_pos -= ChunkPosition::new(-1, 0, 1);
+        assert_eq!(chunk_pos, ChunkPosition::ZERO);
+
+        let local_pos = LocalBlockPosition::new(1, 2, 3);
+        assert_eq!(
+            local_pos + LocalBlockPosition::new(1, 1, 1),
+            LocalBlockPosition::new(2, 3, 4)
+        );
+        assert_eq!(local_pos - local_pos, LocalBlockPosition::new(0, 0, 0));
+        assert_eq!(local_pos.try_add(IVec3::NEG_X), Some(LocalBlockPosition::new(0, 2, 3)));
+        assert_eq!(local_pos.try_add(IVec3::new(-2, 0, 0)), None);
+        assert_eq!(local_pos.try_add(IVec3::new(0, 0, 29)), None);
+    }
+}
@@ -0,0 +1,170 @@
+use generational_arena::Index;
+use glam::UVec3;
+
+use super::{position_types::GlobalBlockPosition, Terrain};
+use crate::{
+    block::{BlockId, BLOCKS, BLOCK_LEAVES, BLOCK_WOOD},
+    util::size::Size3,
+};
+
+/// Small 3D array of blocks that can be stamped into the terrain, such as a tree or a saved
+/// prefab. Cells may be empty, in which case placing the structure leaves the existing block there
+/// untouched
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Structure {
+    size: Size3,
+    /// Cell of the structure placed at the anchor position, e.g. the base of a tree's trunk
+    anchor: UVec3,
+    /// Blocks in the structure, indexed by `size.flatten`. None for empty cells
+    blocks: Vec<Option<BlockId>>,
+}
+
+/// How a structure treats blocks that are already in the terrain where it is placed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlacementMode {
+    /// Replace existing blocks with the structure's non-empty cells
+    Overwrite,
+    /// Only place blocks where there is currently air
+    KeepExisting,
+}
+
+impl PlacementMode {
+    /// Returns true if a block of the structure should replace the existing block
+    pub fn should_replace(self, existing: BlockId) -> bool {
+        match self {
+            Self::Overwrite => true,
+            Self::KeepExisting => !BLOCKS[existing.0 as usize].is_solid(),
+        }
+    }
+}
+
+impl Structure {
+    /// Create an empty structure of the given size, anchored at the given cell
+    pub fn new(size: Size3, anchor: UVec3) -> Self {
+        debug_assert!(size.contains_uvec3(anchor));
+
+        Self {
+            size,
+            anchor,
+            blocks: vec![None; size.product()],
+        }
+    }
+
+    /// A 3x5x3 tree, with a trunk of wood blocks topped by leaves and anchored at the base of the
+    /// trunk
+    pub fn tree() -> Self {
+        let mut tree = Self::new(Size3::new(3, 5, 3), UVec3::new(1, 0, 1));
+
+        for y in 2..5 {
+            for z in 0..3 {
+                for x in 0..3 {
+                    // the top layer is a plus shape
+                    let is_corner = x != 1 && z != 1;
+                    if y < 4 || !is_corner {
+                        tree.set(UVec3::new(x, y, z), Some(BLOCK_LEAVES));
+                    }
+                }
+            }
+        }
+
+        for y in 0..4 {
+            tree.set(UVec3::new(1, y, 1), Some(BLOCK_WOOD));
+        }
+
+        tree
+    }
+
+    /// Copy the blocks in a region of the terrain into a structure, anchored at its minimum
+    /// corner. Air becomes empty cells, so that placing the structure doesn't carve out the
+    /// terrain around it. Returns None if any of the region is not loaded
+    pub fn from_region(
+        terrain: &Terrain,
+        load_area_index: Index,
+        min: GlobalBlockPosition,
+        size: Size3,
+    ) -> Option<Self> {
+        let mut structure = Self::new(size, UVec3::ZERO);
+
+        for z in 0..size.z as u32 {
+            for y in 0..size.y as u32 {
+                for x in 0..size.x as u32 {
+                    let cell = UVec3::new(x, y, z);
+                    let block_id = terrain.get_block(load_area_index, &(min + cell.as_ivec3()))?;
+
+                    if BLOCKS[block_id.0 as usize].is_solid() {
+                        structure.set(cell, Some(block_id));
+                    }
+                }
+            }
+        }
+
+        Some(structure)
+    }
+
+    pub fn size(&self) -> Size3 {
+        self.size
+    }
+
+    /// Block in the given cell, or None if the cell is empty.
+    /// Panics if the cell is out of bounds
+    pub fn get(&self, cell: UVec3) -> Option<BlockId> {
+        assert!(self.size.contains_uvec3(cell));
+        self.blocks[self.size.flatten(cell)]
+    }
+
+    /// Set the block in the given cell, or None to make it empty.
+    /// Panics if the cell is out of bounds
+    pub fn set(&mut self, cell: UVec3, block_id: Option<BlockId>) {
+        assert!(self.size.contains_uvec3(cell));
+        self.blocks[self.size.flatten(cell)] = block_id;
+    }
+
+    /// Global positions and IDs of the non-empty blocks when the structure is placed with its
+    /// anchor at `anchor_pos`
+    pub fn blocks_at(
+        &self,
+        anchor_pos: GlobalBlockPosition,
+    ) -> impl Iterator<Item = (GlobalBlockPosition, BlockId)> + '_ {
+        let min = anchor_pos - self.anchor.as_ivec3();
+
+        itertools::iproduct!(
+            0..self.size.z as u32,
+            0..self.size.y as u32,
+            0..self.size.x as u32
+        )
+        .filter_map(move |(z, y, x)| {
+            let cell = UVec3::new(x, y, z);
+            let block_id = self.get(cell)?;
+
+            Some((min + cell.as_ivec3(), block_id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::*;
+
+    #[test]
+    fn tree_is_anchored_at_base_of_trunk() {
+        let tree = Structure::tree();
+        let anchor_pos = GlobalBlockPosition::new(10, 20, 30);
+
+        let blocks: Vec<_> = tree.blocks_at(anchor_pos).collect();
+        assert!(blocks.contains(&(anchor_pos, BLOCK_WOOD)));
+        assert!(blocks.contains(&(anchor_pos + IVec3::new(0, 3, 0), BLOCK_WOOD)));
+        assert!(blocks.contains(&(anchor_pos + IVec3::new(0, 4, 0), BLOCK_LEAVES)));
+        assert!(blocks.contains(&(anchor_pos + IVec3::new(-1, 2, -1), BLOCK_LEAVES)));
+
+        // the corners of the top layer are empty
+        assert_eq!(tree.get(UVec3::new(0, 4, 0)), None);
+        assert!(!blocks
+            .iter()
+            .any(|(pos, _)| *pos == anchor_pos + IVec3::new(-1, 4, -1)));
+
+        // 4 trunk blocks, 2 layers of 8 leaves around the trunk and a plus of 5 leaves on top
+        assert_eq!(blocks.len(), 4 + 2 * 8 + 5);
+    }
+}
@@ -1,9 +1,71 @@
 use std::time::{Duration, Instant};
 
+/// Default duration of a fixed update step, i.e. 60 fixed updates per second
+pub const DEFAULT_FIXED_DELTA: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Most fixed steps taken in one frame. Time beyond this after a long frame is dropped rather than
+/// simulated, so that the simulation can't fall further and further behind
+const MAX_FIXED_STEPS_PER_FRAME: u32 = 8;
+
+/// Number of recent frames whose durations are kept for `Time::recent_frame_times`
+pub const FRAME_TIME_HISTORY_LEN: usize = 240;
+
 #[derive(Clone, Copy, Debug)]
 pub enum TargetFrameRate {
     Limited(u32),
-    UnlimitedOrVsync,
+    /// Don't sleep between frames. The frame rate may still be limited by the present mode, see
+    /// `RenderContext::set_present_mode`
+    Unlimited,
+}
+
+/// Summary of the durations of recent frames, in seconds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameTimeStats {
+    pub min: f32,
+    pub average: f32,
+    /// 99th percentile: only one frame in a hundred took longer
+    pub p99: f32,
+    pub max: f32,
+}
+
+/// Fixed-size rolling buffer of the durations of the most recent frames in seconds
+#[derive(Debug, Clone)]
+struct FrameTimeHistory {
+    /// Each frame time is written twice, `FRAME_TIME_HISTORY_LEN` apart, so that the most recent
+    /// frame times are always contiguous and in order, starting at `start`
+    times: [f32; 2 * FRAME_TIME_HISTORY_LEN],
+    start: usize,
+    len: usize,
+}
+
+impl FrameTimeHistory {
+    fn new() -> Self {
+        Self {
+            times: [0.0; 2 * FRAME_TIME_HISTORY_LEN],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Add the duration of a frame, replacing the oldest once the buffer is full
+    fn push(&mut self, frame_time: f32) {
+        let index = if self.len < FRAME_TIME_HISTORY_LEN {
+            self.len += 1;
+            self.len - 1
+        } else {
+            let oldest = self.start;
+            self.start = (self.start + 1) % FRAME_TIME_HISTORY_LEN;
+            oldest
+        };
+
+        self.times[index] = frame_time;
+        self.times[index + FRAME_TIME_HISTORY_LEN] = frame_time;
+    }
+
+    /// Frame times from oldest to newest
+    fn as_slice(&self) -> &[f32] {
+        &self.times[self.start..self.start + self.len]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +86,20 @@ pub struct Time {
     frames_this_second: u32,
     /// Number of frames in the last second
     frames_last_second: u32,
+    /// Whether the simulation is paused, in which case `delta` is zero
+    paused: bool,
+    /// Whether to advance the simulation by one frame while paused
+    step_requested: bool,
+    /// Whether the simulation advances this frame
+    advancing: bool,
+    /// Duration of each fixed update step
+    fixed_delta: Duration,
+    /// Simulation time not yet consumed by fixed steps
+    fixed_accumulator: Duration,
+    /// Number of fixed steps to take this frame
+    fixed_steps: u32,
+    /// Wall-clock durations of recent frames, including while paused
+    frame_times: FrameTimeHistory,
 }
 
 impl Time {
@@ -37,6 +113,13 @@ impl Time {
             delta: Duration::ZERO,
             frames_this_second: 0,
             frames_last_second: 0,
+            paused: false,
+            step_requested: false,
+            advancing: true,
+            fixed_delta: DEFAULT_FIXED_DELTA,
+            fixed_accumulator: Duration::ZERO,
+            fixed_steps: 0,
+            frame_times: FrameTimeHistory::new(),
         }
     }
 
@@ -45,12 +128,40 @@ impl Time {
         // update frame index
         self.frame_index += 1;
 
-        // update delta
+        // update delta. while paused, the delta is zero unless a single step was requested
         let now = Instant::now();
-        self.delta = now - self.last_frame_instant;
+        self.record_frame_time(now - self.last_frame_instant);
+        self.advancing = !self.paused || std::mem::take(&mut self.step_requested);
+        self.delta = if self.advancing {
+            now - self.last_frame_instant
+        } else {
+            Duration::ZERO
+        };
 
         // update last frame instant
         self.last_frame_instant = now;
+
+        // a single step while paused is exactly one fixed step
+        if self.paused && self.advancing {
+            self.fixed_steps = 1;
+        } else {
+            self.accumulate_fixed_steps(self.delta);
+        }
+    }
+
+    /// Add the duration of the previous frame to the rolling frame time history
+    fn record_frame_time(&mut self, frame_time: Duration) {
+        self.frame_times
+            .push(frame_time.as_secs_f32());
+    }
+
+    /// Add the frame delta to the accumulator and take as many fixed steps as fit in it
+    fn accumulate_fixed_steps(&mut self, delta: Duration) {
+        let max_accumulated = self.fixed_delta * MAX_FIXED_STEPS_PER_FRAME;
+        self.fixed_accumulator = (self.fixed_accumulator + delta).min(max_accumulated);
+
+        self.fixed_steps = (self.fixed_accumulator.as_nanos() / self.fixed_delta.as_nanos()) as u32;
+        self.fixed_accumulator -= self.fixed_delta * self.fixed_steps;
     }
 
     /// This function is called at the end of each frame to sleep for the
@@ -64,7 +175,7 @@ impl Time {
                     std::thread::sleep(target_frame_duration - current_frame_duration);
                 }
             }
-            TargetFrameRate::UnlimitedOrVsync => (),
+            TargetFrameRate::Unlimited => (),
         }
     }
 
@@ -84,6 +195,29 @@ impl Time {
         }
     }
 
+    /// Pause or resume the simulation. While paused, `delta` is zero so that anything driven by
+    /// it stays frozen, but frames keep being rendered
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step_requested = false;
+    }
+
+    /// True if the simulation is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advance the simulation by a single frame, starting from the next call to `begin_frame`.
+    /// No-op unless paused
+    pub fn step(&mut self) {
+        self.step_requested = self.paused;
+    }
+
+    /// True if the simulation advances this frame, i.e. it is not paused or is being stepped
+    pub fn is_advancing(&self) -> bool {
+        self.advancing
+    }
+
     /// Incremented each frame
     pub fn frame_index(&self) -> usize {
         self.frame_index
@@ -104,6 +238,41 @@ impl Time {
         self.delta.as_secs_f64()
     }
 
+    /// Duration of each fixed update step
+    pub fn fixed_delta(&self) -> Duration {
+        self.fixed_delta
+    }
+
+    /// Duration of each fixed update step in seconds
+    pub fn fixed_delta_seconds(&self) -> f32 {
+        self.fixed_delta.as_secs_f32()
+    }
+
+    /// Set the duration of each fixed update step
+    pub fn set_fixed_delta(&mut self, fixed_delta: Duration) {
+        self.fixed_delta = fixed_delta.max(Duration::from_micros(100));
+        self.fixed_accumulator = Duration::ZERO;
+    }
+
+    /// Number of fixed update steps to take this frame, which may be zero when the frame rate is
+    /// higher than the fixed update rate
+    pub fn fixed_step_count(&self) -> u32 {
+        self.fixed_steps
+    }
+
+    /// Call `f` with the fixed delta in seconds once for each fixed update step this frame
+    pub fn for_each_fixed_step(&self, mut f: impl FnMut(f32)) {
+        for _ in 0..self.fixed_steps {
+            f(self.fixed_delta_seconds());
+        }
+    }
+
+    /// Fraction of a fixed step that has elapsed since the last one, for interpolating between
+    /// the states before and after the last fixed step when rendering
+    pub fn fixed_alpha(&self) -> f32 {
+        self.fixed_accumulator.as_secs_f32() / self.fixed_delta_seconds()
+    }
+
     /// The duration the program has been running
     pub fn elapsed(&self) -> Duration {
         self.last_frame_instant - self.first_frame_instant
@@ -123,4 +292,142 @@ impl Time {
     pub fn get_frames_last_second(&self) -> u32 {
         self.frames_last_second
     }
+
+    /// Durations of up to the last `FRAME_TIME_HISTORY_LEN` frames in seconds, from oldest to
+    /// newest. Unlike `delta`, these are measured while paused too
+    pub fn recent_frame_times(&self) -> &[f32] {
+        self.frame_times.as_slice()
+    }
+
+    /// Minimum, average, 99th percentile and maximum of `recent_frame_times`, or None before the
+    /// first frame
+    pub fn frame_time_percentiles(&self) -> Option<FrameTimeStats> {
+        let frame_times = self.recent_frame_times();
+        if frame_times.is_empty() {
+            return None;
+        }
+
+        let (min, max, sum) = frame_times
+            .iter()
+            .fold((f32::INFINITY, 0.0f32, 0.0), |(min, max, sum), &frame_time| {
+                (min.min(frame_time), max.max(frame_time), sum + frame_time)
+            });
+
+        // partially sort a copy, which is cheap enough to do every frame for a short history
+        let mut sorted = [0.0; FRAME_TIME_HISTORY_LEN];
+        let sorted = &mut sorted[..frame_times.len()];
+        sorted.copy_from_slice(frame_times);
+        let p99_index = (frame_times.len() * 99).div_ceil(100) - 1;
+        let (_, &mut p99, _) = sorted.select_nth_unstable_by(p99_index, f32::total_cmp);
+
+        Some(FrameTimeStats {
+            min,
+            average: sum / frame_times.len() as f32,
+            p99,
+            max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_step() {
+        let mut time = Time::new(TargetFrameRate::Unlimited);
+        let frame = |time: &mut Time| {
+            std::thread::sleep(Duration::from_millis(1));
+            time.begin_frame();
+        };
+
+        frame(&mut time);
+        assert!(time.is_advancing());
+        assert!(time.delta() > Duration::ZERO);
+
+        time.set_paused(true);
+        frame(&mut time);
+        assert!(!time.is_advancing());
+        assert_eq!(time.delta(), Duration::ZERO);
+
+        // stepping advances exactly one frame
+        time.step();
+        frame(&mut time);
+        assert!(time.is_advancing());
+        assert!(time.delta() > Duration::ZERO);
+        frame(&mut time);
+        assert_eq!(time.delta(), Duration::ZERO);
+
+        // the frame index keeps counting while paused
+        assert_eq!(time.frame_index(), 4);
+
+        time.set_paused(false);
+        time.step();
+        frame(&mut time);
+        assert!(time.delta() > Duration::ZERO);
+    }
+
+    #[test]
+    fn variable_frame_deltas_give_whole_fixed_steps() {
+        let mut time = Time::new(TargetFrameRate::Unlimited);
+        time.set_fixed_delta(Duration::from_millis(10));
+
+        let steps = [4, 7, 3, 25, 1, 10, 16]
+            .map(|frame_millis| {
+                time.accumulate_fixed_steps(Duration::from_millis(frame_millis));
+                time.fixed_step_count()
+            });
+
+        // 4, 11, 14, 39, 40, 50 and 66 ms have elapsed
+        assert_eq!(steps, [0, 1, 0, 2, 1, 1, 1]);
+        assert!((time.fixed_alpha() - 0.6).abs() < 1e-4);
+
+        let mut step_deltas = Vec::new();
+        time.for_each_fixed_step(|dt| step_deltas.push(dt));
+        assert_eq!(step_deltas, vec![0.01]);
+
+        // a long frame only catches up a limited number of steps
+        time.accumulate_fixed_steps(Duration::from_secs(1));
+        assert_eq!(time.fixed_step_count(), MAX_FIXED_STEPS_PER_FRAME);
+        assert_eq!(time.fixed_alpha(), 0.0);
+    }
+
+    #[test]
+    fn frame_time_history_keeps_the_most_recent_frames_in_order() {
+        let mut time = Time::new(TargetFrameRate::Unlimited);
+        assert!(time.recent_frame_times().is_empty());
+        assert_eq!(time.frame_time_percentiles(), None);
+
+        for frame_millis in 1..=3 {
+            time.record_frame_time(Duration::from_millis(frame_millis));
+        }
+        assert_eq!(time.recent_frame_times(), &[0.001, 0.002, 0.003]);
+
+        // once full, the oldest frames are dropped
+        for frame_millis in 4..=(FRAME_TIME_HISTORY_LEN as u64 + 10) {
+            time.record_frame_time(Duration::from_millis(frame_millis));
+        }
+        let frame_times = time.recent_frame_times();
+        assert_eq!(frame_times.len(), FRAME_TIME_HISTORY_LEN);
+        assert_eq!(frame_times[0], 0.011);
+        assert_eq!(frame_times[FRAME_TIME_HISTORY_LEN - 1], 0.25);
+        assert!(frame_times.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn frame_time_percentiles_catch_rare_stalls() {
+        let mut time = Time::new(TargetFrameRate::Unlimited);
+
+        // one frame in fifty stalls
+        for frame in 0..200 {
+            let frame_millis = if frame % 50 == 0 { 100 } else { 10 };
+            time.record_frame_time(Duration::from_millis(frame_millis));
+        }
+
+        let stats = time.frame_time_percentiles().unwrap();
+        assert_eq!(stats.min, 0.01);
+        assert_eq!(stats.max, 0.1);
+        assert_eq!(stats.p99, 0.1);
+        assert!((stats.average - (196.0 * 0.01 + 4.0 * 0.1) / 200.0).abs() < 1e-6);
+    }
 }
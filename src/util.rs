@@ -1,5 +1,6 @@
 pub mod face;
 pub mod measure_time;
+pub mod memory;
 pub mod size;
 pub mod transform;
 pub mod vector_map;
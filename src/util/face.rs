@@ -11,6 +11,15 @@ impl FaceIndex {
     pub const NEG_Y: FaceIndex = FaceIndex(4);
     pub const NEG_Z: FaceIndex = FaceIndex(5);
 
+    /// Returns the index of the face with the given normal, or None if it is not a unit vector
+    /// along one of the axes
+    pub fn from_normal(normal: IVec3) -> Option<Self> {
+        FACE_NORMALS
+            .iter()
+            .position(|&face_normal| face_normal == normal)
+            .map(Self)
+    }
+
     /// Returns the index of the opposite face, e.g. +x -> -x
     pub fn opposite(self) -> Self {
         Self((self.0 + 3) % 6)
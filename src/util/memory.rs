@@ -0,0 +1,16 @@
+/// Peak resident set size of the process in bytes, the most physical memory it has used at once,
+/// or None if it can't be read on this platform
+pub fn peak_rss() -> Option<usize> {
+    // only Linux reports it without extra dependencies, as the VmHWM line of /proc/self/status
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kibibytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+
+    Some(kibibytes * 1024)
+}
@@ -1,5 +1,7 @@
-use glam::{Mat4, Quat, Vec3};
+use glam::{Mat3, Mat4, Quat, Vec3};
 
+/// NB: with an identity rotation, forward is -Z, right is +X and up is +Y, matching the camera
+/// convention described on `Camera`
 #[derive(Clone, Copy, Debug)]
 pub struct Transform {
     pub translation: Vec3,
@@ -25,4 +27,141 @@ impl Transform {
     pub fn as_matrix(&self) -> Mat4 {
         Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
     }
+
+    /// Unit vector in the direction the transform is facing
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::NEG_Z
+    }
+
+    /// Unit vector to the right of the direction the transform is facing
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    /// Unit vector upwards relative to the direction the transform is facing
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    /// Rotate to face `target`, keeping the right vector perpendicular to `up`.
+    /// No-op if `target` is at the translation or directly along `up` from it, as the rotation is
+    /// ambiguous
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let forward = (target - self.translation).normalize_or_zero();
+        let right = forward
+            .cross(up)
+            .normalize_or_zero();
+
+        if right == Vec3::ZERO {
+            return;
+        }
+
+        let up = right.cross(forward);
+        self.rotation = Quat::from_mat3(&Mat3::from_cols(right, up, -forward));
+    }
+
+    /// Move by `offset` given in the transform's own axes, i.e. x is right, y is up and -z is
+    /// forward. Scale is not applied
+    pub fn translate_local(&mut self, offset: Vec3) {
+        self.translation += self.rotation * offset;
+    }
+
+    /// Apply `rotation` about the transform's own axes
+    pub fn rotate_local(&mut self, rotation: Quat) {
+        self.rotation = (self.rotation * rotation).normalize();
+    }
+
+    /// Interpolate between `self` at `alpha` = 0 and `other` at `alpha` = 1, linearly for the
+    /// translation and scale and spherically for the rotation, e.g. to render between the
+    /// previous and current states of a fixed update
+    pub fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, alpha),
+            scale: self.scale.lerp(other.scale, alpha),
+            rotation: self.rotation.slerp(other.rotation, alpha),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_approx_eq(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn identity_axes_follow_camera_convention() {
+        let transform = Transform::IDENTITY;
+        assert_eq!(transform.forward(), Vec3::NEG_Z);
+        assert_eq!(transform.right(), Vec3::X);
+        assert_eq!(transform.up(), Vec3::Y);
+    }
+
+    #[test]
+    fn look_at_faces_target() {
+        let mut transform = Transform::IDENTITY;
+        transform.translation = Vec3::new(1.0, 2.0, 3.0);
+
+        let target = Vec3::new(4.0, -2.0, 3.0);
+        transform.look_at(target, Vec3::Y);
+        assert_approx_eq(transform.forward(), Vec3::new(0.6, -0.8, 0.0));
+
+        // right stays horizontal, and the axes stay orthonormal
+        assert_approx_eq(transform.right(), Vec3::Z);
+        assert!(transform.right().dot(transform.forward()).abs() < 1e-5);
+        assert!(transform.up().y > 0.0);
+
+        // transforming the forward direction from the origin reaches the target
+        let distance = (target - transform.translation).length();
+        let reached = transform
+            .as_matrix()
+            .transform_point3(Vec3::NEG_Z * distance);
+        assert_approx_eq(reached, target);
+
+        // looking straight along `up` is ambiguous, so the rotation is kept
+        let rotation = transform.rotation;
+        transform.look_at(transform.translation + Vec3::Y, Vec3::Y);
+        assert_eq!(transform.rotation, rotation);
+    }
+
+    #[test]
+    fn local_movement_and_rotation_use_own_axes() {
+        let mut transform = Transform::IDENTITY;
+        transform.rotate_local(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2));
+
+        // a quarter turn to the left faces -X
+        assert_approx_eq(transform.forward(), Vec3::NEG_X);
+        assert_approx_eq(transform.right(), Vec3::NEG_Z);
+
+        transform.translate_local(Vec3::new(1.0, 2.0, -3.0));
+        assert_approx_eq(transform.translation, Vec3::new(-3.0, 2.0, -1.0));
+
+        // pitching up is about the local right axis, so it keeps facing towards -X
+        transform.rotate_local(Quat::from_rotation_x(0.5));
+        assert!(transform.forward().x < 0.0 && transform.forward().y > 0.0);
+        assert_approx_eq(transform.right(), Vec3::NEG_Z);
+    }
+
+    #[test]
+    fn interpolation_at_half_is_the_midpoint() {
+        let previous = Transform::new(Vec3::new(1.0, 2.0, 3.0), Vec3::ONE, Quat::IDENTITY);
+        let current = Transform::new(
+            Vec3::new(3.0, 2.0, -1.0),
+            Vec3::splat(3.0),
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+        );
+
+        let midpoint = previous.interpolate(&current, 0.5);
+        assert_approx_eq(midpoint.translation, Vec3::new(2.0, 2.0, 1.0));
+        assert_approx_eq(midpoint.scale, Vec3::splat(2.0));
+        assert!(midpoint
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4), 1e-5));
+
+        // the ends are the two transforms
+        assert_approx_eq(previous.interpolate(&current, 0.0).translation, previous.translation);
+        assert_approx_eq(previous.interpolate(&current, 1.0).forward(), current.forward());
+    }
 }